@@ -0,0 +1,217 @@
+//! gRPC API of a starquad index: the messages of `proto/starquad.proto`,
+//! a server which dispatches to an implementation of `StarQuad`, and a
+//! client.
+//!
+//! This is a separate crate, rather than a module of `starquad`, because
+//! tonic services are written with `async` code, which `starquad` (a 2015
+//! edition crate) cannot contain. `StarQuad` therefore has blocking methods,
+//! which the server calls on tokio's blocking thread pool.
+
+// `tonic::Status` is large, but is the error type of every gRPC method
+#![allow(clippy::result_large_err)]
+
+pub mod messages;
+
+use messages::{CountResponse, QueryRequest, RecordBatch};
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio_stream::Stream;
+use tonic::codec::{ProstCodec, Streaming};
+use tonic::codegen::{empty_body, http, Body, BoxFuture, Context, Poll, Service, StdError};
+use tonic::metadata::errors::InvalidMetadataValue;
+use tonic::metadata::MetadataMap;
+use tonic::server::{Grpc, NamedService, ServerStreamingService, UnaryService};
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Request, Response, Status};
+
+/// Fully-qualified name of the service.
+pub const SERVICE_NAME: &str = "starquad.v1.StarQuad";
+
+const QUERY_PATH: &str = "/starquad.v1.StarQuad/Query";
+const COUNT_PATH: &str = "/starquad.v1.StarQuad/Count";
+
+/// Implementation of the service. The methods may block.
+pub trait StarQuad: Send + Sync + 'static {
+    type QueryStream: Stream<Item = Result<RecordBatch, Status>> + Send + 'static;
+
+    /// Records in a region, as a stream of batches.
+    fn query(&self, request: QueryRequest) -> Result<Self::QueryStream, Status>;
+
+    /// Number of records in a region.
+    fn count(&self, request: QueryRequest) -> Result<CountResponse, Status>;
+}
+
+/// Run a method of the service on the blocking thread pool.
+async fn blocking<T, R, F>(
+    inner: Arc<T>,
+    request: Request<QueryRequest>,
+    method: F,
+) -> Result<Response<R>, Status>
+where
+    T: StarQuad,
+    R: Send + 'static,
+    F: FnOnce(&T, QueryRequest) -> Result<R, Status> + Send + 'static,
+{
+    let request = request.into_inner();
+    tokio::task::spawn_blocking(move || method(&inner, request))
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+        .map(Response::new)
+}
+
+struct QuerySvc<T>(Arc<T>);
+
+impl<T: StarQuad> ServerStreamingService<QueryRequest> for QuerySvc<T> {
+    type Response = RecordBatch;
+    type ResponseStream = T::QueryStream;
+    type Future = BoxFuture<Response<Self::ResponseStream>, Status>;
+
+    fn call(&mut self, request: Request<QueryRequest>) -> Self::Future {
+        Box::pin(blocking(self.0.clone(), request, T::query))
+    }
+}
+
+struct CountSvc<T>(Arc<T>);
+
+impl<T: StarQuad> UnaryService<QueryRequest> for CountSvc<T> {
+    type Response = CountResponse;
+    type Future = BoxFuture<Response<CountResponse>, Status>;
+
+    fn call(&mut self, request: Request<QueryRequest>) -> Self::Future {
+        Box::pin(blocking(self.0.clone(), request, T::count))
+    }
+}
+
+/// Server of the service, for use with `tonic::transport::Server`.
+pub struct StarQuadServer<T> {
+    inner: Arc<T>,
+}
+
+impl<T> StarQuadServer<T> {
+    pub fn new(inner: T) -> Self {
+        StarQuadServer {
+            inner: Arc::new(inner),
+        }
+    }
+}
+
+impl<T> Clone for StarQuadServer<T> {
+    fn clone(&self) -> Self {
+        StarQuadServer {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> NamedService for StarQuadServer<T> {
+    const NAME: &'static str = SERVICE_NAME;
+}
+
+impl<T, B> Service<http::Request<B>> for StarQuadServer<T>
+where
+    T: StarQuad,
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<tonic::body::BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let inner = self.inner.clone();
+        match request.uri().path() {
+            QUERY_PATH => Box::pin(async move {
+                let mut grpc = Grpc::new(ProstCodec::default());
+                Ok(grpc.server_streaming(QuerySvc(inner), request).await)
+            }),
+            COUNT_PATH => Box::pin(async move {
+                let mut grpc = Grpc::new(ProstCodec::default());
+                Ok(grpc.unary(CountSvc(inner), request).await)
+            }),
+            _ => Box::pin(async move {
+                let mut response = http::Response::new(empty_body());
+                let headers = response.headers_mut();
+                headers.insert(
+                    Status::GRPC_STATUS,
+                    (tonic::Code::Unimplemented as i32).into(),
+                );
+                headers.insert(
+                    http::header::CONTENT_TYPE,
+                    tonic::metadata::GRPC_CONTENT_TYPE,
+                );
+                Ok(response)
+            }),
+        }
+    }
+}
+
+/// Client of the service.
+#[derive(Clone)]
+pub struct StarQuadClient {
+    grpc: tonic::client::Grpc<Channel>,
+    /// Metadata sent with each request.
+    metadata: MetadataMap,
+}
+
+impl StarQuadClient {
+    /// Connect to a server, given its URI (eg. `"http://127.0.0.1:50051"`).
+    pub async fn connect<D>(destination: D) -> Result<Self, tonic::transport::Error>
+    where
+        D: TryInto<Endpoint>,
+        D::Error: Into<StdError>,
+    {
+        let channel = Endpoint::new(destination)?.connect().await?;
+        Ok(StarQuadClient {
+            grpc: tonic::client::Grpc::new(channel),
+            metadata: MetadataMap::new(),
+        })
+    }
+
+    /// Send a metadata entry with each request, such as the credentials
+    /// (`x-api-key` or `authorization`) of a server which requires them.
+    pub fn with_metadata(
+        mut self,
+        key: &'static str,
+        value: &str,
+    ) -> Result<Self, InvalidMetadataValue> {
+        self.metadata.insert(key, value.parse()?);
+        Ok(self)
+    }
+
+    fn request(&self, message: QueryRequest) -> Request<QueryRequest> {
+        let mut request = Request::new(message);
+        *request.metadata_mut() = self.metadata.clone();
+        request
+    }
+
+    pub async fn query(&mut self, request: QueryRequest) -> Result<Streaming<RecordBatch>, Status> {
+        self.ready().await?;
+        let path = http::uri::PathAndQuery::from_static(QUERY_PATH);
+        let response = self
+            .grpc
+            .server_streaming(self.request(request), path, ProstCodec::default())
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    pub async fn count(&mut self, request: QueryRequest) -> Result<CountResponse, Status> {
+        self.ready().await?;
+        let path = http::uri::PathAndQuery::from_static(COUNT_PATH);
+        let response = self
+            .grpc
+            .unary(self.request(request), path, ProstCodec::default())
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    async fn ready(&mut self) -> Result<(), Status> {
+        self.grpc
+            .ready()
+            .await
+            .map_err(|e| Status::unavailable(e.to_string()))
+    }
+}
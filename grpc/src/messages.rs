@@ -0,0 +1,89 @@
+//! Messages of `proto/starquad.proto`.
+
+/// Records within `radius` degrees of `(ra, dec)`.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Cone {
+    #[prost(double, tag = "1")]
+    pub ra: f64,
+    #[prost(double, tag = "2")]
+    pub dec: f64,
+    #[prost(double, tag = "3")]
+    pub radius: f64,
+}
+
+/// Records in a range of right ascension and declination, in degrees.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RaDecBox {
+    #[prost(double, tag = "1")]
+    pub ra_min: f64,
+    #[prost(double, tag = "2")]
+    pub ra_max: f64,
+    #[prost(double, tag = "3")]
+    pub dec_min: f64,
+    #[prost(double, tag = "4")]
+    pub dec_max: f64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Region {
+    #[prost(oneof = "region::Shape", tags = "1, 2")]
+    pub shape: Option<region::Shape>,
+}
+
+pub mod region {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Shape {
+        #[prost(message, tag = "1")]
+        Cone(super::Cone),
+        #[prost(message, tag = "2")]
+        Box(super::RaDecBox),
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct QueryRequest {
+    #[prost(message, optional, tag = "1")]
+    pub region: Option<Region>,
+    /// Filter expression; empty for no filter.
+    #[prost(string, tag = "2")]
+    pub filter: String,
+    /// Columns to return; empty for all columns.
+    #[prost(string, repeated, tag = "3")]
+    pub columns: Vec<String>,
+    /// Maximum number of rows in each batch; zero for the server's default.
+    #[prost(uint32, tag = "4")]
+    pub batch_size: u32,
+}
+
+/// Values of a column. Exactly one of the value lists is set, depending on
+/// the type of the column; missing values are zero or empty.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Column {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    /// Whether each value is present; empty if every value is present.
+    #[prost(bool, repeated, tag = "2")]
+    pub validity: Vec<bool>,
+    #[prost(double, repeated, tag = "3")]
+    pub doubles: Vec<f64>,
+    #[prost(int64, repeated, tag = "4")]
+    pub longs: Vec<i64>,
+    #[prost(string, repeated, tag = "5")]
+    pub strings: Vec<String>,
+    #[prost(bool, repeated, tag = "6")]
+    pub booleans: Vec<bool>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RecordBatch {
+    #[prost(uint64, tag = "1")]
+    pub num_rows: u64,
+    #[prost(message, repeated, tag = "2")]
+    pub columns: Vec<Column>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CountResponse {
+    #[prost(uint64, tag = "1")]
+    pub count: u64,
+}
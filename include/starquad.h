@@ -0,0 +1,99 @@
+/* Generated by cbindgen from src/ffi.rs; do not edit. */
+
+#ifndef STARQUAD_H
+#define STARQUAD_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * In-memory index of positions.
+ */
+typedef struct StarquadIndex StarquadIndex;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Index `len` positions, given by arrays of their `ra` and `dec` in degrees.
+ *
+ * Returns null if either array is null (unless `len` is 0).
+ *
+ * # Safety
+ *
+ * `ra` and `dec` must each point to `len` doubles.
+ */
+struct StarquadIndex *starquad_index_new(const double *ra, const double *dec, size_t len);
+
+/**
+ * Free an index. Freeing null does nothing.
+ *
+ * # Safety
+ *
+ * `index` must have been returned by `starquad_index_new`, and not already
+ * freed.
+ */
+void starquad_index_free(struct StarquadIndex *index);
+
+/**
+ * Number of positions in an index, or 0 if it is null.
+ *
+ * # Safety
+ *
+ * `index` must be null or a live index.
+ */
+size_t starquad_index_len(const struct StarquadIndex *index);
+
+/**
+ * Find the positions with `ra` between `ra_min` and `ra_max` and `dec`
+ * between `dec_min` and `dec_max`, writing up to `capacity` of their
+ * places to `out` in ascending order.
+ *
+ * Each range runs from the smaller of its bounds to the larger. Ranges of
+ * `ra` are taken modulo 360, so the smaller may be negative for a box
+ * across `ra = 0`, and `dec` is clamped to the poles.
+ *
+ * Returns the number of positions found, or 0 if `index` is null or the
+ * bounds are not numbers or lie beyond a pole.
+ *
+ * # Safety
+ *
+ * `index` must be null or a live index, and `out` must be null or point to
+ * `capacity` writable `size_t`s.
+ */
+size_t starquad_index_query_rect(const struct StarquadIndex *index,
+                                 double ra_min,
+                                 double ra_max,
+                                 double dec_min,
+                                 double dec_max,
+                                 size_t *out,
+                                 size_t capacity);
+
+/**
+ * Find the positions within `radius` degrees of `(ra, dec)` (a circle on
+ * the sky), writing up to `capacity` of their places to `out` in ascending
+ * order.
+ *
+ * Returns the number of positions found, or 0 if `index` is null.
+ *
+ * # Safety
+ *
+ * `index` must be null or a live index, and `out` must be null or point to
+ * `capacity` writable `size_t`s.
+ */
+size_t starquad_index_query_cone(const struct StarquadIndex *index,
+                                 double ra,
+                                 double dec,
+                                 double radius,
+                                 size_t *out,
+                                 size_t capacity);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* STARQUAD_H */
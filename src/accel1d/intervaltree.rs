@@ -0,0 +1,193 @@
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use geom::interval::{Interval, IntervalDomain};
+
+/// Static interval tree of items over intervals.
+///
+/// The items are sorted by the start of their intervals, and arranged as a
+/// binary search tree in a single vector, with the root of every range at
+/// its middle. Each root also records which item of its range ends last, so
+/// that queries skip the ranges which end before them.
+///
+/// ```
+/// # use starquad::accel1d::intervaltree::IntervalTree;
+/// # use starquad::geom::interval::Interval;
+/// // observations over ranges of time, in days
+/// let tree = IntervalTree::new(vec![
+///     (Interval::new(0.0, 10.0).unwrap(), "a"),
+///     (Interval::new(5.0, 2.0).unwrap(), "b"),
+///     (Interval::new(20.0, 5.0).unwrap(), "c"),
+/// ]);
+/// let mut at_6 = tree.query_point(&6.0).iter().map(|(_, n)| *n).collect::<Vec<_>>();
+/// at_6.sort();
+/// assert_eq!(at_6, vec!["a", "b"]);
+/// let during = tree.query_interval(&Interval::new(8.0, 15.0).unwrap());
+/// assert_eq!(during.len(), 2);
+/// ```
+#[derive(Clone, Debug)]
+pub struct IntervalTree<S, T> {
+    items: Vec<(Interval<S>, T)>,
+    /// Index of the item which ends last in the range rooted at each item.
+    last: Vec<usize>,
+}
+
+impl<S, T> IntervalTree<S, T>
+where
+    S: IntervalDomain,
+{
+    pub fn new(mut items: Vec<(Interval<S>, T)>) -> Self {
+        items.sort_by(|a, b| {
+            a.0.start()
+                .partial_cmp(b.0.start())
+                .unwrap_or(Ordering::Equal)
+        });
+        let mut last = (0..items.len()).collect::<Vec<_>>();
+        build(&items, &mut last, 0);
+        IntervalTree { items, last }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Items in the tree, sorted by the start of their intervals.
+    pub fn items(&self) -> &[(Interval<S>, T)] {
+        &self.items
+    }
+
+    /// Items whose intervals contain a value (a stabbing query).
+    pub fn query_point(&self, value: &S) -> Vec<&(Interval<S>, T)> {
+        let mut found = Vec::new();
+        self.visit(0, self.items.len(), value, &mut |item| {
+            if item.0.contains(value) {
+                found.push(item);
+            }
+            item.0.start() <= value
+        });
+        found
+    }
+
+    /// Items whose intervals overlap an interval.
+    pub fn query_interval(&self, interval: &Interval<S>) -> Vec<&(Interval<S>, T)> {
+        let mut found = Vec::new();
+        self.visit(0, self.items.len(), interval.start(), &mut |item| {
+            if item.0.overlaps(interval) {
+                found.push(item);
+            }
+            S::before_end(interval, item.0.start())
+        });
+        found
+    }
+
+    /// Visit the root of each range from `lo` to `hi` whose intervals do
+    /// not all end by `start`. `f` is given the root, and returns whether
+    /// to visit the items which start after it.
+    fn visit<'a, F>(&'a self, lo: usize, hi: usize, start: &S, f: &mut F)
+    where
+        F: FnMut(&'a (Interval<S>, T)) -> bool,
+    {
+        if lo == hi {
+            return;
+        }
+        let mid = (lo + hi) / 2;
+        if !S::before_end(&self.items[self.last[mid]].0, start) {
+            return;
+        }
+        self.visit(lo, mid, start, f);
+        if f(&self.items[mid]) {
+            self.visit(mid + 1, hi, start, f);
+        }
+    }
+}
+
+/// Record the item which ends last in each range of items, given the
+/// offset of the range, and return the index of that item.
+fn build<S: IntervalDomain, T>(
+    items: &[(Interval<S>, T)],
+    last: &mut [usize],
+    offset: usize,
+) -> Option<usize> {
+    if items.is_empty() {
+        return None;
+    }
+    let mid = items.len() / 2;
+    let (below, rest) = last.split_at_mut(mid);
+    let candidates = [
+        build(&items[..mid], below, offset),
+        Some(offset + mid),
+        build(&items[mid + 1..], &mut rest[1..], offset + mid + 1),
+    ];
+    let latest = candidates
+        .iter()
+        .flatten()
+        .copied()
+        .max_by(|&a, &b| items[a - offset].0.cmp_end(&items[b - offset].0))?;
+    rest[0] = latest;
+    Some(latest)
+}
+
+#[cfg(test)]
+mod test {
+    use accel1d::intervaltree::IntervalTree;
+    use alloc::vec::Vec;
+    use geom::interval::Interval;
+    use quickcheck_macros::quickcheck;
+
+    fn sorted_items<S>(query: Vec<&(Interval<S>, usize)>) -> Vec<usize> {
+        let mut items = query.iter().map(|(_, item)| *item).collect::<Vec<usize>>();
+        items.sort();
+        items
+    }
+
+    #[test]
+    fn ends_of_type() {
+        // intervals reaching the largest u8, whose ends cannot be represented
+        let tree = IntervalTree::new(vec![
+            (Interval::<u8>::new(250, 6).unwrap(), 0),
+            (Interval::new(0, 255).unwrap(), 1),
+            (Interval::new(255, 1).unwrap(), 2),
+        ]);
+        assert_eq!(sorted_items(tree.query_point(&255)), vec![0, 2]);
+        assert_eq!(sorted_items(tree.query_point(&254)), vec![0, 1]);
+        let query = Interval::new(255, 1).unwrap();
+        assert_eq!(sorted_items(tree.query_interval(&query)), vec![0, 2]);
+    }
+
+    /// Property test: stabbing and overlap queries find the same items as a
+    /// linear search.
+    #[quickcheck]
+    fn queries_match_linear_search(intervals: Vec<(i8, u8)>, value: i8, query: (i8, u8)) {
+        let interval =
+            |(start, width): (i8, u8)| Interval::new(i16::from(start), i16::from(width % 64));
+        let items = intervals
+            .into_iter()
+            .filter_map(interval)
+            .enumerate()
+            .map(|(i, interval)| (interval, i))
+            .collect::<Vec<_>>();
+        let tree = IntervalTree::new(items.clone());
+
+        let value = i16::from(value);
+        let expected = items
+            .iter()
+            .filter(|(interval, _)| interval.contains(&value))
+            .map(|(_, i)| *i)
+            .collect::<Vec<_>>();
+        assert_eq!(sorted_items(tree.query_point(&value)), expected);
+
+        if let Some(query) = interval(query) {
+            let expected = items
+                .iter()
+                .filter(|(interval, _)| {
+                    (*query.start()..query.end()).any(|v| interval.contains(&v))
+                })
+                .map(|(_, i)| *i)
+                .collect::<Vec<_>>();
+            assert_eq!(sorted_items(tree.query_interval(&query)), expected);
+        }
+    }
+}
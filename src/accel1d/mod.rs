@@ -0,0 +1,4 @@
+//! Indexes of items over extents of a line, such as observations over
+//! ranges of time or sources over ranges of magnitude.
+
+pub mod intervaltree;
@@ -0,0 +1,396 @@
+//! Bounding volume hierarchy, for items with extents rather than positions:
+//! footprints, error boxes, tiles.
+
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use geom::interval::{Interval, IntervalDomain};
+use geom::p2::P2;
+use geom::rect::Rect;
+use num::traits::float::FloatCore;
+
+/// Items with a rectangle bounding their extent.
+pub trait Bounded<S> {
+    fn bounds(&self) -> Rect<S>;
+}
+
+impl<S: Clone> Bounded<S> for Rect<S> {
+    fn bounds(&self) -> Rect<S> {
+        self.clone()
+    }
+}
+
+/// An item paired with its bounds, as points are paired with items in
+/// `Accel2D`.
+impl<S: Clone, T> Bounded<S> for (Rect<S>, T) {
+    fn bounds(&self) -> Rect<S> {
+        self.0.clone()
+    }
+}
+
+/// Half-line from `origin` in `direction`, at the points
+/// `origin + t * direction` for `t >= 0`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray<S> {
+    pub origin: P2<S>,
+    pub direction: P2<S>,
+}
+
+impl<S> Ray<S> {
+    pub fn new(origin: P2<S>, direction: P2<S>) -> Self {
+        Ray { origin, direction }
+    }
+}
+
+/// Most items in a leaf of a `Bvh`.
+const LEAF_CAPACITY: usize = 4;
+
+/// Static bounding volume hierarchy of items with bounds.
+///
+/// Each node of the tree holds the box bounding the items below it, and
+/// splits them at the median of their centres along the longer side of the
+/// box. Items are stored with their bounds, in the order of the leaves.
+///
+/// ```
+/// # use starquad::accel2d::bvh::{Bvh, Ray};
+/// # use starquad::geom::{p2::P2, rect::Rect};
+/// let tiles = (0..10)
+///     .map(|i| (Rect::new(f64::from(i) * 10.0, 0.0, 10.0, 10.0).unwrap(), i))
+///     .collect::<Vec<_>>();
+/// let bvh = Bvh::new(tiles);
+/// let found = bvh.query_rect(&Rect::new(15.0, 5.0, 10.0, 1.0).unwrap());
+/// let mut ids = found.iter().map(|(_, (_, i))| *i).collect::<Vec<_>>();
+/// ids.sort();
+/// assert_eq!(ids, vec![1, 2]);
+/// // a ray from the left, through the tiles in order
+/// let hits = bvh.query_ray(&Ray::new(P2::new(-5.0, 5.0), P2::new(1.0, 0.0)));
+/// assert_eq!(hits.len(), 10);
+/// assert_eq!(((hits[0].0).1).1, 0);
+/// assert_eq!(hits[0].1, 5.0);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Bvh<S, T> {
+    items: Vec<(Rect<S>, T)>,
+    /// Nodes in preorder, so that the left child of each node follows it.
+    nodes: Vec<Node<S>>,
+}
+
+/// Node of a `Bvh`, bounding the items from `start` to `end`.
+#[derive(Clone, Debug)]
+struct Node<S> {
+    /// Smallest start of the bounds of the items.
+    min: P2<S>,
+    /// Largest end of the bounds of the items, rounded, which may be the
+    /// last value in them rather than after it.
+    max: P2<S>,
+    start: usize,
+    end: usize,
+    /// Index of the right child, or 0 for a leaf.
+    right: usize,
+}
+
+fn interval_end<S: IntervalDomain + FloatCore>(interval: &Interval<S>) -> S {
+    *interval.start() + *interval.diameter()
+}
+
+impl<S: IntervalDomain + FloatCore> Node<S> {
+    /// Node bounding a range of items, starting at `start` in all the
+    /// items.
+    fn new<T>(items: &[(Rect<S>, T)], start: usize) -> Self {
+        let inf = S::infinity();
+        let (mut min, mut max) = (P2::new(inf, inf), P2::new(-inf, -inf));
+        for (rect, _) in items {
+            min.x = min.x.min(*rect.x());
+            min.y = min.y.min(*rect.y());
+            max.x = max.x.max(interval_end(rect.x_interval()));
+            max.y = max.y.max(interval_end(rect.y_interval()));
+        }
+        Node {
+            min,
+            max,
+            start,
+            end: start + items.len(),
+            right: 0,
+        }
+    }
+
+    fn overlaps(&self, rect: &Rect<S>) -> bool {
+        S::before_end(rect.x_interval(), &self.min.x)
+            && rect.x() <= &self.max.x
+            && S::before_end(rect.y_interval(), &self.min.y)
+            && rect.y() <= &self.max.y
+    }
+}
+
+impl<S, T> Bvh<S, T>
+where
+    S: IntervalDomain + FloatCore,
+    T: Bounded<S>,
+{
+    pub fn new(items: Vec<T>) -> Self {
+        let mut items = items
+            .into_iter()
+            .map(|item| (item.bounds(), item))
+            .collect::<Vec<_>>();
+        let mut nodes = Vec::new();
+        if !items.is_empty() {
+            build(&mut items, 0, &mut nodes);
+        }
+        Bvh { items, nodes }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Items in the tree with their bounds, in its internal order.
+    pub fn items(&self) -> &[(Rect<S>, T)] {
+        &self.items
+    }
+
+    /// Items whose bounds overlap a rectangle, with their bounds.
+    pub fn query_rect(&self, rect: &Rect<S>) -> Vec<&(Rect<S>, T)> {
+        let mut found = Vec::new();
+        self.visit(&mut |node| node.overlaps(rect), &mut |item| {
+            if item.0.x_interval().overlaps(rect.x_interval())
+                && item.0.y_interval().overlaps(rect.y_interval())
+            {
+                found.push(item);
+            }
+        });
+        found
+    }
+
+    /// Items whose bounds (including their upper edges) a ray passes
+    /// through or starts in, with the `t` at which it enters them (0 if it
+    /// starts in them), nearest first.
+    pub fn query_ray(&self, ray: &Ray<S>) -> Vec<(&(Rect<S>, T), S)> {
+        let mut found = Vec::new();
+        self.visit(
+            &mut |node| entry(ray, &node.min, &node.max).is_some(),
+            &mut |item| {
+                let rect = &item.0;
+                let (min, max) = (
+                    P2::new(*rect.x(), *rect.y()),
+                    P2::new(
+                        interval_end(rect.x_interval()),
+                        interval_end(rect.y_interval()),
+                    ),
+                );
+                if let Some(t) = entry(ray, &min, &max) {
+                    found.push((item, t));
+                }
+            },
+        );
+        found.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal));
+        found
+    }
+
+    /// Visit the items of the leaves reached through the nodes which `f`
+    /// accepts.
+    fn visit<'a, F, G>(&'a self, f: &mut F, g: &mut G)
+    where
+        F: FnMut(&Node<S>) -> bool,
+        G: FnMut(&'a (Rect<S>, T)),
+    {
+        let mut stack = Vec::new();
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(i) = stack.pop() {
+            let node = &self.nodes[i];
+            if !f(node) {
+                continue;
+            }
+            if node.right == 0 {
+                self.items[node.start..node.end].iter().for_each(&mut *g);
+            } else {
+                stack.push(node.right);
+                stack.push(i + 1);
+            }
+        }
+    }
+}
+
+/// Build the nodes for a range of items, starting at `offset` in all the
+/// items, in preorder.
+fn build<S, T>(items: &mut [(Rect<S>, T)], offset: usize, nodes: &mut Vec<Node<S>>)
+where
+    S: IntervalDomain + FloatCore,
+{
+    let index = nodes.len();
+    nodes.push(Node::new(items, offset));
+    if items.len() <= LEAF_CAPACITY {
+        return;
+    }
+    let node = &nodes[index];
+    let x_axis = node.max.x - node.min.x >= node.max.y - node.min.y;
+    let two = S::one() + S::one();
+    let centre = |rect: &Rect<S>| {
+        let interval = if x_axis {
+            rect.x_interval()
+        } else {
+            rect.y_interval()
+        };
+        *interval.start() + *interval.diameter() / two
+    };
+    let mid = items.len() / 2;
+    items.select_nth_unstable_by(mid, |a, b| {
+        centre(&a.0)
+            .partial_cmp(&centre(&b.0))
+            .unwrap_or(Ordering::Equal)
+    });
+    let (left, right) = items.split_at_mut(mid);
+    build(left, offset, nodes);
+    nodes[index].right = nodes.len();
+    build(right, offset + mid, nodes);
+}
+
+/// The `t` at which a ray enters a box from `min` to `max`, if it does.
+fn entry<S: FloatCore>(ray: &Ray<S>, min: &P2<S>, max: &P2<S>) -> Option<S> {
+    let (mut near, mut far) = (S::zero(), S::infinity());
+    let axes = [
+        (ray.origin.x, ray.direction.x, min.x, max.x),
+        (ray.origin.y, ray.direction.y, min.y, max.y),
+    ];
+    for (origin, direction, min, max) in axes {
+        if direction == S::zero() {
+            if origin < min || max < origin {
+                return None;
+            }
+            continue;
+        }
+        let (t0, t1) = ((min - origin) / direction, (max - origin) / direction);
+        near = near.max(t0.min(t1));
+        far = far.min(t0.max(t1));
+    }
+    if near <= far {
+        Some(near)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use accel2d::bvh::{entry, Bounded, Bvh, Ray};
+    use alloc::vec::Vec;
+    use geom::p2::P2;
+    use geom::rect::Rect;
+    use quickcheck_macros::quickcheck;
+
+    fn rects(boxes: Vec<(i8, i8, u8, u8)>) -> Vec<(Rect<f64>, usize)> {
+        boxes
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, (x, y, w, h))| {
+                let rect = Rect::new(
+                    f64::from(x),
+                    f64::from(y),
+                    f64::from(w % 32),
+                    f64::from(h % 32),
+                );
+                rect.map(|rect| (rect, i))
+            })
+            .collect()
+    }
+
+    fn sorted_items<B>(found: Vec<&(B, (Rect<f64>, usize))>) -> Vec<usize> {
+        let mut ids = found.iter().map(|(_, (_, i))| *i).collect::<Vec<_>>();
+        ids.sort_unstable();
+        ids
+    }
+
+    struct ErrorBox {
+        centre: P2<f64>,
+        radius: f64,
+    }
+
+    impl Bounded<f64> for ErrorBox {
+        fn bounds(&self) -> Rect<f64> {
+            let (c, r) = (self.centre, self.radius);
+            Rect::new(c.x - r, c.y - r, 2.0 * r, 2.0 * r).unwrap()
+        }
+    }
+
+    #[test]
+    fn bounded_items() {
+        let boxes = (0..100)
+            .map(|i| ErrorBox {
+                centre: P2::new(f64::from(i % 10), f64::from(i / 10)),
+                radius: 0.25,
+            })
+            .collect::<Vec<_>>();
+        let bvh = Bvh::new(boxes);
+        assert_eq!(bvh.len(), 100);
+        let found = bvh.query_rect(&Rect::new(2.5, 2.5, 2.0, 1.0).unwrap());
+        let mut centres = found
+            .iter()
+            .map(|(_, b)| (b.centre.x, b.centre.y))
+            .collect::<Vec<_>>();
+        centres.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(centres, vec![(3.0, 3.0), (4.0, 3.0)]);
+
+        // a diagonal ray passes through the boxes at (i, i)
+        let hits = bvh.query_ray(&Ray::new(P2::new(-1.0, -1.0), P2::new(1.0, 1.0)));
+        let centres = hits.iter().map(|((_, b), _)| b.centre).collect::<Vec<_>>();
+        assert_eq!(
+            centres,
+            (0..10)
+                .map(|i| P2::new(f64::from(i), f64::from(i)))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(hits[0].1, 0.75);
+        let ray = Ray::new(P2::new(0.0, 0.0), P2::new(1.0, 0.0));
+        assert!(Bvh::<f64, ErrorBox>::new(Vec::new())
+            .query_ray(&ray)
+            .is_empty());
+    }
+
+    /// Property test: overlap queries find the same items as a linear
+    /// search.
+    #[quickcheck]
+    fn query_rect_matches_linear_search(boxes: Vec<(i8, i8, u8, u8)>, query: (i8, i8, u8, u8)) {
+        let items = rects(boxes);
+        let bvh = Bvh::new(items.clone());
+        for (query, _) in rects(vec![query]) {
+            let expected = items
+                .iter()
+                .filter(|(rect, _)| {
+                    rect.x_interval().overlaps(query.x_interval())
+                        && rect.y_interval().overlaps(query.y_interval())
+                })
+                .map(|(_, i)| *i)
+                .collect::<Vec<_>>();
+            assert_eq!(sorted_items(bvh.query_rect(&query)), expected);
+        }
+    }
+
+    /// Property test: ray queries find the same items as testing each
+    /// item, nearest first.
+    #[quickcheck]
+    fn query_ray_matches_linear_search(boxes: Vec<(i8, i8, u8, u8)>, ray: (i8, i8, i8, i8)) {
+        let items = rects(boxes);
+        let bvh = Bvh::new(items.clone());
+        let ray = Ray::new(
+            P2::new(f64::from(ray.0), f64::from(ray.1)),
+            P2::new(f64::from(ray.2), f64::from(ray.3)),
+        );
+        let hits = bvh.query_ray(&ray);
+        assert!(hits.windows(2).all(|w| w[0].1 <= w[1].1));
+        let expected = items
+            .iter()
+            .filter(|(rect, _)| {
+                let min = P2::new(*rect.x(), *rect.y());
+                let max = P2::new(rect.x() + rect.width(), rect.y() + rect.height());
+                entry(&ray, &min, &max).is_some()
+            })
+            .map(|(_, i)| *i)
+            .collect::<Vec<_>>();
+        let found = sorted_items(hits.into_iter().map(|(item, _)| item).collect());
+        assert_eq!(found, expected);
+    }
+}
@@ -0,0 +1,493 @@
+//! Brute-force batch queries on a GPU, built with the `gpu` feature.
+//!
+//! A `GpuIndex` uploads the coordinates of its points once, and then answers
+//! batches of rectangle or circle queries with a compute shader which tests
+//! every point against every query. That is more work than walking a tree,
+//! but a GPU does it so quickly that, for millions of queries against tens
+//! of millions of points, it is the faster way to answer them.
+//!
+//! Coordinates are `f32` on the GPU. Rectangles exclude their upper edges
+//! exactly as `Rect::contains` does, but points within rounding of the edge
+//! of a circle may be found or missed.
+//!
+//! ```no_run
+//! # use starquad::accel2d::gpu::GpuIndex;
+//! # use starquad::geom::{p2::P2, rect::Rect};
+//! let index = GpuIndex::new(&[P2::new(1.0, 2.0), P2::new(5.0, 5.0)]).unwrap();
+//! let found = index.query_rects(&[Rect::new(0.0, 0.0, 3.0, 3.0).unwrap()]).unwrap();
+//! assert_eq!(found, vec![vec![0]]);
+//! let found = index.query_circles(&[(P2::new(5.0, 4.0), 1.5)]).unwrap();
+//! assert_eq!(found, vec![vec![1]]);
+//! ```
+
+use geom::p2::P2;
+use geom::rect::Rect;
+use std::future::Future;
+use std::pin::pin;
+use std::sync::mpsc;
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+use wgpu::{
+    BindGroupDescriptor, BindGroupEntry, Buffer, BufferDescriptor, BufferUsages,
+    CommandEncoderDescriptor, ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor,
+    Device, DeviceDescriptor, Instance, InstanceDescriptor, MapMode, PollType, Queue,
+    RequestAdapterOptions, ShaderModuleDescriptor, ShaderSource,
+};
+
+/// Errors from running queries on a GPU.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// No GPU (or software renderer) could be found.
+    #[error("no GPU adapter: {0}")]
+    Adapter(#[from] wgpu::RequestAdapterError),
+    #[error(transparent)]
+    Device(#[from] wgpu::RequestDeviceError),
+    #[error(transparent)]
+    Poll(#[from] wgpu::PollError),
+    /// Results could not be read back from the GPU.
+    #[error("failed to read results from the GPU: {0}")]
+    Map(#[from] wgpu::BufferAsyncError),
+    /// Points are numbered with `u32`s on the GPU.
+    #[error("too many points for a GPU index: {0}")]
+    TooManyPoints(usize),
+}
+
+/// Points (and queries) handled by each invocation of the shader.
+const WORKGROUP_SIZE: u32 = 256;
+
+/// Maximum number of queries tested in a single dispatch, which bounds the
+/// running time of each invocation of the shader.
+const QUERY_BATCH: usize = 4096;
+
+/// Bytes per point, and per match.
+const PAIR_SIZE: u64 = 8;
+
+const SHADER: &str = r#"
+struct Params {
+    first: u32,
+    len: u32,
+    queries: u32,
+    capacity: u32,
+    row: u32,
+    _pad0: u32,
+    _pad1: u32,
+    _pad2: u32,
+}
+
+@group(0) @binding(0) var<storage, read> points: array<vec2<f32>>;
+@group(0) @binding(1) var<storage, read> queries: array<vec4<f32>>;
+@group(0) @binding(2) var<uniform> params: Params;
+@group(0) @binding(3) var<storage, read_write> count: atomic<u32>;
+@group(0) @binding(4) var<storage, read_write> matches: array<vec2<u32>>;
+
+fn emit(query: u32, point: u32) {
+    let slot = atomicAdd(&count, 1u);
+    if (slot < params.capacity) {
+        matches[slot] = vec2<u32>(query, params.first + point);
+    }
+}
+
+// queries are (x_min, y_min, x_end, y_end), excluding the ends
+@compute @workgroup_size(256)
+fn rects(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x + id.y * params.row;
+    if (i >= params.len) {
+        return;
+    }
+    let p = points[i];
+    for (var q = 0u; q < params.queries; q++) {
+        let r = queries[q];
+        if (all(p >= r.xy) && all(p < r.zw)) {
+            emit(q, i);
+        }
+    }
+}
+
+// queries are (x, y, radius squared, unused)
+@compute @workgroup_size(256)
+fn circles(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x + id.y * params.row;
+    if (i >= params.len) {
+        return;
+    }
+    let p = points[i];
+    for (var q = 0u; q < params.queries; q++) {
+        let c = queries[q];
+        let d = p - c.xy;
+        if (dot(d, d) <= c.z) {
+            emit(q, i);
+        }
+    }
+}
+"#;
+
+/// Run a future to completion on this thread.
+///
+/// wgpu's futures are ready as soon as the native backends have answered,
+/// so this only spins when a backend is slow to respond.
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let mut context = Context::from_waker(Waker::noop());
+    loop {
+        match future.as_mut().poll(&mut context) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::yield_now(),
+        }
+    }
+}
+
+/// Little-endian bytes of a slice of `f32`s, as the GPU reads them.
+fn f32_bytes(values: impl IntoIterator<Item = f32>) -> Vec<u8> {
+    values.into_iter().flat_map(f32::to_le_bytes).collect()
+}
+
+/// Points uploaded to a single buffer, which is no larger than the device
+/// can bind.
+struct PointChunk {
+    buffer: Buffer,
+    first: u32,
+    len: u32,
+}
+
+/// Points on a GPU, which answers batches of queries by brute force.
+pub struct GpuIndex {
+    device: Device,
+    queue: Queue,
+    rects: ComputePipeline,
+    circles: ComputePipeline,
+    chunks: Vec<PointChunk>,
+    max_binding: u64,
+    max_workgroups: u32,
+    len: usize,
+}
+
+impl GpuIndex {
+    /// Upload points to the default GPU.
+    ///
+    /// The adapter is chosen by wgpu, which can be steered with its
+    /// environment variables (eg. `WGPU_BACKEND` and `WGPU_ADAPTER_NAME`).
+    pub fn new(points: &[P2<f32>]) -> Result<Self, Error> {
+        if points.len() > u32::MAX as usize {
+            return Err(Error::TooManyPoints(points.len()));
+        }
+        let instance = Instance::new(InstanceDescriptor::new_without_display_handle_from_env());
+        let adapter = block_on(instance.request_adapter(&RequestAdapterOptions::default()))?;
+        let (device, queue) = block_on(adapter.request_device(&DeviceDescriptor {
+            label: Some("starquad"),
+            required_limits: adapter.limits(),
+            ..Default::default()
+        }))?;
+        let limits = device.limits();
+        let max_binding = limits
+            .max_storage_buffer_binding_size
+            .min(limits.max_buffer_size);
+
+        let module = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("starquad queries"),
+            source: ShaderSource::Wgsl(SHADER.into()),
+        });
+        let pipeline = |entry_point| {
+            device.create_compute_pipeline(&ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: None,
+                module: &module,
+                entry_point: Some(entry_point),
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+        let (rects, circles) = (pipeline("rects"), pipeline("circles"));
+
+        let chunk_len = (max_binding / PAIR_SIZE) as usize;
+        let chunks = points
+            .chunks(chunk_len.max(1))
+            .enumerate()
+            .map(|(i, chunk)| PointChunk {
+                buffer: device.create_buffer_init(&BufferInitDescriptor {
+                    label: Some("points"),
+                    contents: &f32_bytes(chunk.iter().flat_map(|p| [p.x, p.y])),
+                    usage: BufferUsages::STORAGE,
+                }),
+                first: (i * chunk_len) as u32,
+                len: chunk.len() as u32,
+            })
+            .collect();
+        Ok(GpuIndex {
+            device,
+            queue,
+            rects,
+            circles,
+            chunks,
+            max_binding,
+            max_workgroups: limits.max_compute_workgroups_per_dimension,
+            len: points.len(),
+        })
+    }
+
+    /// Number of points in the index.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check if the index has no points.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Places (in the slice the index was built from) of the points in each
+    /// rectangle, in ascending order.
+    pub fn query_rects(&self, rects: &[Rect<f32>]) -> Result<Vec<Vec<u32>>, Error> {
+        let queries = rects.iter().flat_map(|rect| {
+            let (x, y) = (rect.x_interval(), rect.y_interval());
+            // the same sums as `Rect::contains`, so the ends are identical
+            [
+                *x.start(),
+                *y.start(),
+                *x.start() + *x.diameter(),
+                *y.start() + *y.diameter(),
+            ]
+        });
+        self.query(&self.rects, &f32_bytes(queries))
+    }
+
+    /// Places of the points within `radius` of each centre, in ascending
+    /// order.
+    pub fn query_circles(&self, circles: &[(P2<f32>, f32)]) -> Result<Vec<Vec<u32>>, Error> {
+        let queries = circles
+            .iter()
+            .flat_map(|(centre, radius)| [centre.x, centre.y, radius * radius, 0.0]);
+        self.query(&self.circles, &f32_bytes(queries))
+    }
+
+    /// Run a pipeline over the queries (16 bytes each) in batches.
+    fn query(&self, pipeline: &ComputePipeline, queries: &[u8]) -> Result<Vec<Vec<u32>>, Error> {
+        let mut results = vec![Vec::new(); queries.len() / 16];
+        for (batch, batch_queries) in queries.chunks(QUERY_BATCH * 16).enumerate() {
+            for chunk in &self.chunks {
+                for (query, place) in self.dispatch(pipeline, chunk, batch_queries)? {
+                    results[batch * QUERY_BATCH + query as usize].push(place);
+                }
+            }
+        }
+        for result in &mut results {
+            result.sort_unstable();
+        }
+        Ok(results)
+    }
+
+    /// Test a chunk of points against some queries, returning the matching
+    /// pairs of query (numbered from the first of `queries`) and point.
+    ///
+    /// Matches are first collected into a buffer of a guessed size; if they
+    /// overflow it, the dispatch is repeated with a buffer large enough, or
+    /// with the queries split in two if that would be too large to bind.
+    fn dispatch(
+        &self,
+        pipeline: &ComputePipeline,
+        chunk: &PointChunk,
+        queries: &[u8],
+    ) -> Result<Vec<(u32, u32)>, Error> {
+        let max_capacity = (self.max_binding / PAIR_SIZE) as u32;
+        let mut capacity = chunk.len.clamp(1, max_capacity);
+        loop {
+            match self.run(pipeline, chunk, queries, capacity)? {
+                Ok(matches) => return Ok(matches),
+                Err(count) if count <= max_capacity => capacity = count,
+                // a single query matches at most every point in the chunk,
+                // which always fits
+                Err(_) => {
+                    let (first, second) = queries.split_at(queries.len() / 32 * 16);
+                    let offset = (first.len() / 16) as u32;
+                    let mut matches = self.dispatch(pipeline, chunk, first)?;
+                    for (query, place) in self.dispatch(pipeline, chunk, second)? {
+                        matches.push((query + offset, place));
+                    }
+                    return Ok(matches);
+                }
+            }
+        }
+    }
+
+    /// Run one dispatch, returning the matches, or their number if there
+    /// were more than `capacity`.
+    fn run(
+        &self,
+        pipeline: &ComputePipeline,
+        chunk: &PointChunk,
+        queries: &[u8],
+        capacity: u32,
+    ) -> Result<Result<Vec<(u32, u32)>, u32>, Error> {
+        let groups = chunk.len.div_ceil(WORKGROUP_SIZE).max(1);
+        let groups_x = groups.min(self.max_workgroups);
+        let groups_y = groups.div_ceil(groups_x);
+        let params = [
+            chunk.first,
+            chunk.len,
+            (queries.len() / 16) as u32,
+            capacity,
+            groups_x * WORKGROUP_SIZE,
+            0,
+            0,
+            0,
+        ];
+        let buffer = |label, contents: &[u8], usage| {
+            self.device.create_buffer_init(&BufferInitDescriptor {
+                label: Some(label),
+                contents,
+                usage,
+            })
+        };
+        let queries = buffer("queries", queries, BufferUsages::STORAGE);
+        let params = buffer(
+            "params",
+            &params
+                .iter()
+                .flat_map(|v| v.to_le_bytes())
+                .collect::<Vec<_>>(),
+            BufferUsages::UNIFORM,
+        );
+        let count = buffer(
+            "count",
+            &[0; 4],
+            BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+        );
+        let matches = self.device.create_buffer(&BufferDescriptor {
+            label: Some("matches"),
+            size: capacity as u64 * PAIR_SIZE,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let bind_group = self.device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: chunk.buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: queries.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: params.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: count.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: matches.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor::default());
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(groups_x, groups_y, 1);
+        }
+        self.queue.submit([encoder.finish()]);
+        let word = |b: &[u8]| u32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+        let found = word(&self.read(&count, 4)?);
+        if found > capacity {
+            return Ok(Err(found));
+        }
+        let bytes = self.read(&matches, found as u64 * PAIR_SIZE)?;
+        Ok(Ok(bytes
+            .chunks_exact(PAIR_SIZE as usize)
+            .map(|pair| (word(&pair[..4]), word(&pair[4..])))
+            .collect()))
+    }
+
+    /// Copy the first `size` bytes of a buffer back from the GPU.
+    fn read(&self, buffer: &Buffer, size: u64) -> Result<Vec<u8>, Error> {
+        if size == 0 {
+            return Ok(Vec::new());
+        }
+        let staging = self.device.create_buffer(&BufferDescriptor {
+            label: Some("staging"),
+            size,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor::default());
+        encoder.copy_buffer_to_buffer(buffer, 0, &staging, 0, size);
+        self.queue.submit([encoder.finish()]);
+
+        let (sender, receiver) = mpsc::channel();
+        staging.map_async(MapMode::Read, .., move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(PollType::wait_indefinitely())?;
+        // the callback has run once the device has been waited on
+        receiver.recv().unwrap_or(Ok(()))?;
+        let bytes = staging
+            .get_mapped_range(..)
+            .map(|view| view.to_vec())
+            .unwrap_or_default();
+        staging.unmap();
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use accel2d::gpu::{Error, GpuIndex};
+    use geom::p2::P2;
+    use geom::rect::Rect;
+
+    /// Places of the points in each rectangle, found on the CPU.
+    fn cpu_query_rects(points: &[P2<f32>], rects: &[Rect<f32>]) -> Vec<Vec<u32>> {
+        rects
+            .iter()
+            .map(|rect| {
+                (0..points.len() as u32)
+                    .filter(|&i| rect.contains(&points[i as usize]))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// An index of the points, or `None` if this machine has no GPU (nor a
+    /// software renderer) for wgpu to use.
+    fn gpu_index(points: &[P2<f32>]) -> Option<GpuIndex> {
+        match GpuIndex::new(points) {
+            Ok(index) => Some(index),
+            Err(Error::Adapter(_)) => None,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    #[test]
+    fn matches_cpu() {
+        let points = (0..10_000)
+            .map(|i| P2::new((i % 100) as f32 * 0.5, (i / 100) as f32 * 0.5))
+            .collect::<Vec<_>>();
+        let index = match gpu_index(&points) {
+            Some(index) => index,
+            None => return,
+        };
+        assert_eq!(index.len(), 10_000);
+        let rects = (0..5000)
+            .map(|i| Rect::new((i % 90) as f32 * 0.5, (i / 90) as f32 * 0.5, 2.0, 1.0).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            index.query_rects(&rects).unwrap(),
+            cpu_query_rects(&points, &rects)
+        );
+        // radii between the grid spacings, so that rounding cannot matter
+        let found = index.query_circles(&[(P2::new(10.0, 10.0), 0.6)]).unwrap();
+        assert_eq!(found, vec![vec![1920, 2019, 2020, 2021, 2120]]);
+        assert_eq!(index.query_rects(&[]).unwrap(), Vec::<Vec<u32>>::new());
+    }
+}
@@ -1,9 +1,25 @@
+use alloc::vec::Vec;
+use geom::interval::IntervalDomain;
 use geom::p2::P2;
+use geom::polygon::Polygon;
 use geom::rect::Rect;
+use num::traits::float::FloatCore;
 
+pub mod bvh;
+#[cfg(feature = "gpu")]
+pub mod gpu;
+pub mod pst;
+pub mod quadtree;
+pub mod rangetree;
 #[cfg(test)]
 pub mod reference;
+#[cfg(feature = "rstar")]
+pub mod rtree;
+#[cfg(feature = "std")]
+pub mod shared;
 
+/// Spatial index of items at points in the plane, which can be queried for
+/// the items in a rectangle.
 pub trait Accel2D {
     type Scalar;
     type Item;
@@ -28,4 +44,52 @@ pub trait Accel2D {
     fn push(&mut self, item: (P2<Self::Scalar>, Self::Item));
 
     fn query_rect(&self, rect: &Rect<Self::Scalar>) -> Vec<&(P2<Self::Scalar>, Self::Item)>;
+
+    /// Number of items in a rectangle.
+    ///
+    /// By default the items are found and counted; indexes may instead count
+    /// them without finding them.
+    fn count_rect(&self, rect: &Rect<Self::Scalar>) -> usize {
+        self.query_rect(rect).len()
+    }
+
+    /// Items in each of a batch of rectangles, in the order of the
+    /// rectangles.
+    ///
+    /// By default each rectangle is queried in turn; indexes may instead
+    /// answer the whole batch in one pass.
+    #[allow(clippy::type_complexity)]
+    fn query_rects(
+        &self,
+        rects: &[Rect<Self::Scalar>],
+    ) -> Vec<Vec<&(P2<Self::Scalar>, Self::Item)>> {
+        rects.iter().map(|rect| self.query_rect(rect)).collect()
+    }
+
+    /// Items inside a polygon, as `Polygon::contains` finds them.
+    ///
+    /// By default the rectangle bounding the polygon is queried, and the
+    /// items outside the polygon are dropped.
+    fn query_polygon(&self, polygon: &Polygon<Self::Scalar>) -> Vec<&(P2<Self::Scalar>, Self::Item)>
+    where
+        Self::Scalar: IntervalDomain + FloatCore,
+    {
+        match Rect::bounding(polygon.vertices()) {
+            Some(rect) => self
+                .query_rect(&rect)
+                .into_iter()
+                .filter(|(point, _)| polygon.contains(point))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Regions of the nodes of the index, each with its depth (the root is at
+    /// depth 0), with every node before its children. This is the structure
+    /// drawn by `render`.
+    ///
+    /// By default an index has no nodes to show.
+    fn regions(&self) -> Vec<(Rect<Self::Scalar>, usize)> {
+        Vec::new()
+    }
 }
@@ -0,0 +1,279 @@
+//! Priority search tree, for "three-sided" queries: the items with `x` in a
+//! range and `y` at most some bound.
+//!
+//! With `x` a position and `y` a magnitude, these are queries like "the
+//! stars in this range of right ascension brighter than magnitude 12",
+//! which the quadtree can only answer by visiting every star in the range.
+
+use accel2d::Accel2D;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use geom::interval::IntervalDomain;
+use geom::p2::P2;
+use geom::rect::Rect;
+
+/// Static priority search tree of items at points in the plane.
+///
+/// The tree is a binary search tree on `x` and a heap on `y`: the root of
+/// every subtree is its item with the smallest `y`, and the rest are split
+/// at their median `x` between its children. Like `accel3d::kdtree::KdTree`,
+/// the items are stored in a single vector, in preorder, so the tree needs
+/// only the range of `x` of each subtree besides.
+///
+/// `query_three_sided` takes `O(log n + k)` time to find `k` items. Items
+/// can be added through `Accel2D`, but each addition rebuilds the tree, so
+/// add them all at once where possible.
+///
+/// ```
+/// # use starquad::accel2d::pst::PrioritySearchTree;
+/// # use starquad::geom::p2::P2;
+/// // (ra, magnitude) of some stars
+/// let stars = vec![
+///     (P2::new(10.0, 6.5), "a"),
+///     (P2::new(12.0, 11.0), "b"),
+///     (P2::new(14.0, 3.2), "c"),
+///     (P2::new(30.0, 1.0), "d"),
+/// ];
+/// let tree = PrioritySearchTree::new(stars);
+/// let bright = tree.query_three_sided(&10.0, &20.0, &8.0);
+/// let mut names = bright.iter().map(|(_, name)| *name).collect::<Vec<_>>();
+/// names.sort();
+/// assert_eq!(names, vec!["a", "c"]);
+/// ```
+#[derive(Clone, Debug)]
+pub struct PrioritySearchTree<S, T> {
+    items: Vec<(P2<S>, T)>,
+    /// Smallest and largest `x` of the subtree rooted at each item.
+    bounds: Vec<(S, S)>,
+}
+
+fn compare<S: PartialOrd>(a: &S, b: &S) -> Ordering {
+    a.partial_cmp(b).unwrap_or(Ordering::Equal)
+}
+
+impl<S, T> PrioritySearchTree<S, T>
+where
+    S: PartialOrd + Clone,
+{
+    pub fn new(items: Vec<(P2<S>, T)>) -> Self {
+        let mut tree = PrioritySearchTree {
+            items,
+            bounds: Vec::new(),
+        };
+        tree.rebuild();
+        tree
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Items in the tree, in its internal order.
+    pub fn items(&self) -> &[(P2<S>, T)] {
+        &self.items
+    }
+
+    /// Items with `min_x <= x <= max_x` and `y <= max_y`.
+    pub fn query_three_sided(&self, min_x: &S, max_x: &S, max_y: &S) -> Vec<&(P2<S>, T)> {
+        let mut found = Vec::new();
+        search(
+            &self.items,
+            &self.bounds,
+            &|lo: &S, hi: &S| min_x <= hi && lo <= max_x,
+            &|y: &S| y <= max_y,
+            &mut found,
+        );
+        found
+    }
+
+    fn rebuild(&mut self) {
+        self.items.sort_unstable_by(|a, b| compare(&a.0.x, &b.0.x));
+        self.bounds = self
+            .items
+            .iter()
+            .map(|(p, _)| (p.x.clone(), p.x.clone()))
+            .collect();
+        build(&mut self.items, &mut self.bounds);
+    }
+}
+
+/// Arrange items sorted by `x` into a tree in preorder, recording the
+/// bounds of each subtree.
+///
+/// The item with the smallest `y` is rotated to the front, leaving the rest
+/// sorted by `x`; the first half of them form the left subtree and the
+/// second half the right. The bounds are given as each item's own `x`.
+fn build<S: PartialOrd + Clone, T>(items: &mut [(P2<S>, T)], bounds: &mut [(S, S)]) {
+    if items.is_empty() {
+        return;
+    }
+    let (lo, hi) = (bounds[0].0.clone(), bounds[bounds.len() - 1].1.clone());
+    let top = (1..items.len()).fold(0, |top, i| {
+        if items[i].0.y < items[top].0.y {
+            i
+        } else {
+            top
+        }
+    });
+    items[..=top].rotate_right(1);
+    bounds[..=top].rotate_right(1);
+    bounds[0] = (lo, hi);
+    let mid = 1 + items.len() / 2;
+    let (left, right) = items[1..].split_at_mut(mid - 1);
+    let (left_bounds, right_bounds) = bounds[1..].split_at_mut(mid - 1);
+    build(left, left_bounds);
+    build(right, right_bounds);
+}
+
+/// Find the items of a subtree whose `x` is in a range and whose `y` is
+/// below a bound. `overlaps` says whether a range of `x` meets the range
+/// sought, and `below` whether a `y` is within the bound.
+fn search<'a, S, T, X, Y>(
+    items: &'a [(P2<S>, T)],
+    bounds: &[(S, S)],
+    overlaps: &X,
+    below: &Y,
+    found: &mut Vec<&'a (P2<S>, T)>,
+) where
+    X: Fn(&S, &S) -> bool,
+    Y: Fn(&S) -> bool,
+{
+    if items.is_empty() || !overlaps(&bounds[0].0, &bounds[0].1) || !below(&items[0].0.y) {
+        return;
+    }
+    let x = &items[0].0.x;
+    if overlaps(x, x) {
+        found.push(&items[0]);
+    }
+    let mid = 1 + items.len() / 2;
+    search(&items[1..mid], &bounds[1..mid], overlaps, below, found);
+    search(&items[mid..], &bounds[mid..], overlaps, below, found);
+}
+
+impl<S, T> Accel2D for PrioritySearchTree<S, T>
+where
+    S: IntervalDomain,
+{
+    type Scalar = S;
+    type Item = T;
+
+    fn new() -> Self {
+        PrioritySearchTree {
+            items: Vec::new(),
+            bounds: Vec::new(),
+        }
+    }
+
+    /// Add the items and rebuild the tree once.
+    fn insert(&mut self, items: Vec<(P2<S>, T)>) {
+        self.items.extend(items);
+        self.rebuild();
+    }
+
+    fn push(&mut self, item: (P2<S>, T)) {
+        self.items.push(item);
+        self.rebuild();
+    }
+
+    /// Items in a rectangle, found as the items below its upper edge and
+    /// then filtered on its lower edge, so this visits every item in the
+    /// range of `x` below the rectangle.
+    fn query_rect(&self, rect: &Rect<S>) -> Vec<&(P2<S>, T)> {
+        let (x, y) = (rect.x_interval(), rect.y_interval());
+        let mut found = Vec::new();
+        search(
+            &self.items,
+            &self.bounds,
+            &|lo: &S, hi: &S| x.start() <= hi && S::before_end(x, lo),
+            &|value: &S| S::before_end(y, value),
+            &mut found,
+        );
+        found.retain(|(point, _)| y.start() <= &point.y);
+        found
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use accel2d::pst::PrioritySearchTree;
+    use accel2d::reference::Reference;
+    use accel2d::Accel2D;
+    use alloc::vec::Vec;
+    use geom::p2::P2;
+    use geom::rect::Rect;
+    use quickcheck_macros::quickcheck;
+
+    fn sorted_items<S>(query: Vec<&(P2<S>, usize)>) -> Vec<usize> {
+        let mut items = query.iter().map(|(_, item)| *item).collect::<Vec<usize>>();
+        items.sort();
+        items
+    }
+
+    #[test]
+    fn heap_ordered() {
+        let items = (0..100)
+            .map(|i| (P2::new(i % 7, (i * 37) % 101), i))
+            .collect::<Vec<(P2<i32>, i32)>>();
+        let tree = PrioritySearchTree::new(items);
+        let min_y = tree.items().iter().map(|(p, _)| p.y).min();
+        assert_eq!(Some(tree.items()[0].0.y), min_y);
+        let found = tree.query_three_sided(&2, &3, &50);
+        assert_eq!(found.len(), 14);
+        assert!(found
+            .iter()
+            .all(|(p, _)| (2..=3).contains(&p.x) && p.y <= 50));
+        assert!(PrioritySearchTree::<i32, ()>::new(Vec::new())
+            .query_three_sided(&0, &10, &10)
+            .is_empty());
+    }
+
+    /// Property test: three-sided queries find the same items as a linear
+    /// search, including at the edges of the range.
+    #[quickcheck]
+    fn three_sided_matches_linear_search(points: Vec<(i8, i8)>, min_x: i8, width: u8, max_y: i8) {
+        let items = points
+            .into_iter()
+            .enumerate()
+            .map(|(i, (x, y))| (P2::new(i32::from(x), i32::from(y)), i))
+            .collect::<Vec<_>>();
+        let tree = PrioritySearchTree::new(items.clone());
+        let (min_x, max_x) = (i32::from(min_x), i32::from(min_x) + i32::from(width));
+        let max_y = i32::from(max_y);
+        let expected = items
+            .iter()
+            .filter(|(p, _)| min_x <= p.x && p.x <= max_x && p.y <= max_y)
+            .map(|(_, i)| *i)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sorted_items(tree.query_three_sided(&min_x, &max_x, &max_y)),
+            expected
+        );
+    }
+
+    /// Property test: the tree returns the same items in a rectangle as the
+    /// reference implementation, whether it is built at once or one item
+    /// at a time.
+    #[quickcheck]
+    fn f64_query_rect_matches_reference(points: Vec<P2<f64>>, rect: Rect<f64>) {
+        let items = points
+            .into_iter()
+            .filter(|point| point.x.is_finite() && point.y.is_finite())
+            .enumerate()
+            .map(|(i, point)| (point, i))
+            .collect::<Vec<(P2<f64>, usize)>>();
+        let reference = Reference::new_from_vec(items.clone());
+        let bulk = PrioritySearchTree::new(items.clone());
+        let mut incremental = <PrioritySearchTree<f64, usize> as Accel2D>::new();
+        for item in items {
+            incremental.push(item);
+        }
+
+        let expected = sorted_items(reference.query_rect(&rect));
+        assert_eq!(sorted_items(bulk.query_rect(&rect)), expected);
+        assert_eq!(sorted_items(incremental.query_rect(&rect)), expected);
+    }
+}
@@ -0,0 +1,2203 @@
+use accel2d::Accel2D;
+use alloc::collections::BinaryHeap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::mem;
+use geom::interval::{Interval, IntervalDomain};
+use geom::p2::P2;
+use geom::rect::Rect;
+use mst::{shorter, Boruvka, Component};
+use num::traits::float::FloatCore;
+use smallvec::SmallVec;
+
+/// Default maximum number of items stored in a leaf before it is split.
+pub const DEFAULT_LEAF_CAPACITY: usize = 16;
+
+/// Number of items a leaf of a [QuadTree](QuadTree) holds without a separate
+/// heap allocation.
+///
+/// Leaves of up to the default capacity keep their items inline in the node
+/// arena, which saves an allocation per leaf and a pointer chase per leaf
+/// scanned by a query. Larger leaves spill to the heap.
+///
+/// Every node has room for these items, branches and empty leaves included,
+/// so trees of large items should keep fewer inline, as
+/// [inline_items](inline_items) chooses.
+pub const LEAF_INLINE_ITEMS: usize = 16;
+
+/// Largest number of bytes of items which [inline_items](inline_items) keeps
+/// inline in each leaf.
+pub const LEAF_INLINE_BYTES: usize = 512;
+
+/// Number of items of type `I` to keep inline in each leaf of an
+/// [InlineQuadTree](InlineQuadTree): as many as fit in
+/// [LEAF_INLINE_BYTES](LEAF_INLINE_BYTES), up to
+/// [LEAF_INLINE_ITEMS](LEAF_INLINE_ITEMS). Items larger than that are always
+/// stored on the heap.
+pub const fn inline_items<I>() -> usize {
+    let size = mem::size_of::<I>();
+    if size == 0 || LEAF_INLINE_BYTES / size > LEAF_INLINE_ITEMS {
+        LEAF_INLINE_ITEMS
+    } else {
+        LEAF_INLINE_BYTES / size
+    }
+}
+
+/// Number of nodes in each chunk of the arena, which is the unit copied when
+/// a tree shares nodes with a snapshot and one of them is changed.
+pub const NODE_CHUNK: usize = 64;
+
+/// Default maximum depth of the tree.
+///
+/// Leaves at this depth are never split, which stops the tree from recursing
+/// without bound when many items share the same point.
+pub const DEFAULT_MAX_DEPTH: usize = 32;
+
+/// Point quadtree which keeps up to [LEAF_INLINE_ITEMS](LEAF_INLINE_ITEMS)
+/// items inline in each leaf.
+pub type QuadTree<S, T> = InlineQuadTree<S, T, LEAF_INLINE_ITEMS>;
+
+/// Point quadtree which keeps up to `INLINE` items inline in each leaf.
+///
+/// Trees of small items are usually a [QuadTree](QuadTree); trees of large
+/// ones can choose `INLINE` with [inline_items](inline_items), which keeps
+/// branches and empty leaves small:
+///
+/// ```
+/// # use starquad::accel2d::quadtree::{inline_items, InlineQuadTree};
+/// # use starquad::geom::p2::P2;
+/// type Record = [f64; 100];
+/// type RecordTree = InlineQuadTree<f64, Record, { inline_items::<(P2<f64>, Record)>() }>;
+/// ```
+///
+/// Nodes are stored in an arena (a `Vec`), with the root at index 0. Each
+/// branch divides its region into four quadrants by halving the x and y
+/// intervals of the region.
+///
+/// The region covered by the tree is fixed once it has been chosen. It can be
+/// supplied up-front using [with_bounds](QuadTree::with_bounds); otherwise it
+/// is chosen as the bounding box of the items held when the root leaf first
+/// overflows (points which are not comparable with themselves, such as
+/// `NaN`s, are ignored when choosing it). Items which are later pushed outside of the region are kept in
+/// a separate list which is scanned linearly by queries.
+///
+/// ```
+/// # use starquad::accel2d::Accel2D;
+/// # use starquad::accel2d::quadtree::QuadTree;
+/// # use starquad::geom::p2::P2;
+/// # use starquad::geom::rect::Rect;
+/// let items = (0..100).map(|i| (P2::new(i % 10, i / 10), i)).collect();
+/// let tree = QuadTree::new_from_vec(items);
+/// let query = tree.query_rect(&Rect::new(2, 3, 2, 1).unwrap());
+/// let mut found = query.iter().map(|(_, i)| *i).collect::<Vec<_>>();
+/// found.sort();
+/// assert_eq!(found, vec![32, 33]);
+/// ```
+///
+/// A [snapshot](QuadTree::snapshot) of a tree is cheap: it shares the nodes
+/// of the tree, in chunks of [NODE_CHUNK](NODE_CHUNK) nodes, and a chunk is
+/// only copied when the tree or the snapshot changes it, which is why items
+/// must be `Clone`. A server can keep answering queries from a snapshot
+/// while new items are pushed to the tree:
+///
+/// ```
+/// # use starquad::accel2d::Accel2D;
+/// # use starquad::accel2d::quadtree::QuadTree;
+/// # use starquad::geom::p2::P2;
+/// # use starquad::geom::rect::Rect;
+/// let mut tree = QuadTree::with_bounds(Rect::new(0.0, 0.0, 10.0, 10.0).unwrap());
+/// tree.push((P2::new(1.0, 1.0), "old"));
+/// let snapshot = tree.snapshot();
+/// tree.push((P2::new(2.0, 2.0), "new"));
+/// let everything = Rect::new(0.0, 0.0, 10.0, 10.0).unwrap();
+/// assert_eq!(snapshot.query_rect(&everything).len(), 1);
+/// assert_eq!(tree.query_rect(&everything).len(), 2);
+/// ```
+pub struct InlineQuadTree<S, T, const INLINE: usize> {
+    bounds: Option<Rect<S>>,
+    nodes: Vec<Arc<Vec<Node<S, T, INLINE>>>>,
+    outliers: Arc<Vec<(P2<S>, T)>>,
+    leaf_capacity: usize,
+    max_depth: usize,
+}
+
+type Items<S, T, const INLINE: usize> = SmallVec<[(P2<S>, T); INLINE]>;
+
+#[derive(Clone)]
+enum Node<S, T, const INLINE: usize> {
+    Leaf(Items<S, T, INLINE>),
+    Branch { split: P2<S>, children: [usize; 4] },
+}
+
+/// Index of the quadrant of `split` in which `point` lies.
+fn quadrant<S: PartialOrd>(split: &P2<S>, point: &P2<S>) -> usize {
+    let east = point.x >= split.x;
+    let north = point.y >= split.y;
+    (east as usize) | ((north as usize) << 1)
+}
+
+/// Bits saying which quadrants of `split` (indexed by `quadrant`) a
+/// rectangle overlaps, given that it overlaps the region being split.
+fn overlapped_quadrants<S: IntervalDomain>(split: &P2<S>, rect: &Rect<S>) -> u8 {
+    let (x, y) = (rect.x_interval(), rect.y_interval());
+    let west = x.start() < &split.x;
+    let east = x.start() >= &split.x || S::before_end(x, &split.x);
+    let south = y.start() < &split.y;
+    let north = y.start() >= &split.y || S::before_end(y, &split.y);
+    (west & south) as u8
+        | (((east & south) as u8) << 1)
+        | (((west & north) as u8) << 2)
+        | (((east & north) as u8) << 3)
+}
+
+/// Square of the smallest distance between points of two rectangles, or a
+/// lower bound of it.
+fn min_distance_2<S>(a: &Rect<S>, b: &Rect<S>) -> S
+where
+    S: IntervalDomain + FloatCore,
+{
+    let gap = |a: &Interval<S>, b: &Interval<S>| {
+        (*b.start() - a.end())
+            .max(*a.start() - b.end())
+            .max(S::zero())
+    };
+    let (dx, dy) = (
+        gap(a.x_interval(), b.x_interval()),
+        gap(a.y_interval(), b.y_interval()),
+    );
+    dx * dx + dy * dy
+}
+
+/// Square of the distance from a rectangle to a point, or a lower bound of
+/// it.
+fn point_distance_2<S>(rect: &Rect<S>, point: &P2<S>) -> S
+where
+    S: IntervalDomain + FloatCore,
+{
+    let gap = |interval: &Interval<S>, value: S| {
+        (*interval.start() - value)
+            .max(value - interval.end())
+            .max(S::zero())
+    };
+    let (dx, dy) = (
+        gap(rect.x_interval(), point.x),
+        gap(rect.y_interval(), point.y),
+    );
+    dx * dx + dy * dy
+}
+
+/// Split a rectangle into the four quadrants indexed by `quadrant`.
+fn split_rect<S: IntervalDomain>(rect: &Rect<S>) -> Option<(P2<S>, [Rect<S>; 4])> {
+    let (west, east) = rect.x_interval().split()?;
+    let (south, north) = rect.y_interval().split()?;
+    let split = P2::new(east.start().clone(), north.start().clone());
+    let quadrants = [
+        Rect::new_from_intervals(west.clone(), south.clone()),
+        Rect::new_from_intervals(east.clone(), south),
+        Rect::new_from_intervals(west, north.clone()),
+        Rect::new_from_intervals(east, north),
+    ];
+    Some((split, quadrants))
+}
+
+impl<S, T, const INLINE: usize> InlineQuadTree<S, T, INLINE>
+where
+    S: IntervalDomain,
+{
+    /// Create an empty quadtree covering a fixed region.
+    pub fn with_bounds(bounds: Rect<S>) -> Self {
+        InlineQuadTree {
+            bounds: Some(bounds),
+            nodes: vec![Arc::new(vec![Node::Leaf(SmallVec::new())])],
+            outliers: Arc::new(Vec::new()),
+            leaf_capacity: DEFAULT_LEAF_CAPACITY,
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+
+    /// Set the maximum number of items in a leaf.
+    ///
+    /// This only affects leaves which are split after it is called, so it
+    /// should be set before items are added.
+    pub fn with_leaf_capacity(mut self, leaf_capacity: usize) -> Self {
+        self.leaf_capacity = leaf_capacity.max(1);
+        self
+    }
+
+    /// Set the maximum depth of the tree.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Region covered by the tree, if it has been chosen yet.
+    pub fn bounds(&self) -> Option<&Rect<S>> {
+        self.bounds.as_ref()
+    }
+
+    /// Total number of items in the tree.
+    pub fn len(&self) -> usize {
+        let in_leaves: usize = self
+            .nodes
+            .iter()
+            .flat_map(|chunk| chunk.iter())
+            .map(|node| match node {
+                Node::Leaf(items) => items.len(),
+                Node::Branch { .. } => 0,
+            })
+            .sum();
+        in_leaves + self.outliers.len()
+    }
+
+    /// Check if the tree contains no items.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Items of the tree: those in the leaves, in the order of the arena,
+    /// and then the outliers.
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = &'a (P2<S>, T)> + 'a {
+        self.nodes
+            .iter()
+            .flat_map(|chunk| chunk.iter())
+            .flat_map(|node| match node {
+                Node::Leaf(items) => &items[..],
+                Node::Branch { .. } => &[],
+            })
+            .chain(self.outliers.iter())
+    }
+
+    /// Place (in the order of `iter`) of the first item of each leaf, by
+    /// index, and the number of items in leaves.
+    fn leaf_places(&self) -> (Vec<usize>, usize) {
+        let mut first = vec![0; self.node_count()];
+        let mut in_leaves = 0;
+        for (index, node) in self.nodes.iter().flat_map(|chunk| chunk.iter()).enumerate() {
+            if let Node::Leaf(items) = node {
+                first[index] = in_leaves;
+                in_leaves += items.len();
+            }
+        }
+        (first, in_leaves)
+    }
+
+    /// Indexes of the nodes, each before its children.
+    fn preorder(&self) -> Vec<usize> {
+        let mut preorder = Vec::with_capacity(self.node_count());
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            preorder.push(index);
+            if let Node::Branch { children, .. } = self.node(index) {
+                stack.extend(children.iter());
+            }
+        }
+        preorder
+    }
+
+    /// Region of each node, by index, if the tree has bounds.
+    fn node_rects(&self) -> Vec<Option<Rect<S>>> {
+        let mut rects = vec![None; self.node_count()];
+        let mut stack = vec![(0, self.bounds.clone())];
+        while let Some((index, rect)) = stack.pop() {
+            if let (Node::Branch { children, .. }, Some(rect)) = (self.node(index), &rect) {
+                if let Some((_, quadrants)) = split_rect(rect) {
+                    for (child, quadrant) in children.iter().zip(quadrants.iter()) {
+                        stack.push((*child, Some(quadrant.clone())));
+                    }
+                }
+            }
+            rects[index] = rect;
+        }
+        rects
+    }
+
+    fn node(&self, index: usize) -> &Node<S, T, INLINE> {
+        &self.nodes[index / NODE_CHUNK][index % NODE_CHUNK]
+    }
+
+    /// Number of nodes in the tree, which are numbered from zero (see
+    /// [DualNode::index](DualNode::index)).
+    pub fn node_count(&self) -> usize {
+        // chunks are full but for the last
+        match self.nodes.last() {
+            Some(last) => (self.nodes.len() - 1) * NODE_CHUNK + last.len(),
+            None => 0,
+        }
+    }
+
+    /// Snapshot of the tree, which shares its nodes until one of them is
+    /// changed.
+    ///
+    /// Taking a snapshot copies a pointer per chunk of nodes, rather than
+    /// the nodes themselves, and needs only a shared reference, so a reader
+    /// can take one (eg. through `SharedIndex::read`) and keep it after
+    /// releasing the tree; see the [type documentation](QuadTree).
+    pub fn snapshot(&self) -> Self {
+        InlineQuadTree {
+            bounds: self.bounds.clone(),
+            nodes: self.nodes.clone(),
+            outliers: self.outliers.clone(),
+            leaf_capacity: self.leaf_capacity,
+            max_depth: self.max_depth,
+        }
+    }
+
+    /// Depth of the deepest node, with the root at depth 1.
+    fn height(&self) -> usize {
+        let mut height = 0;
+        let mut stack = vec![(0, 1)];
+        while let Some((index, depth)) = stack.pop() {
+            height = height.max(depth);
+            if let Node::Branch { children, .. } = self.node(index) {
+                stack.extend(children.iter().map(|&child| (child, depth + 1)));
+            }
+        }
+        height
+    }
+
+    /// Append to `order` the nodes of the subtree at `index`, down to
+    /// `height` levels, in van Emde Boas order: the top half of the levels,
+    /// then each subtree hanging below them, each laid out recursively.
+    fn van_emde_boas_order(&self, index: usize, height: usize, order: &mut Vec<usize>) {
+        if height <= 1 {
+            order.push(index);
+            return;
+        }
+        let top = height / 2;
+        self.van_emde_boas_order(index, top, order);
+        let mut bottoms = vec![index];
+        for _ in 0..top {
+            bottoms = bottoms
+                .iter()
+                .flat_map(|&index| match self.node(index) {
+                    Node::Branch { children, .. } => &children[..],
+                    Node::Leaf(_) => &[],
+                })
+                .cloned()
+                .collect();
+        }
+        for bottom in bottoms {
+            self.van_emde_boas_order(bottom, height - top, order);
+        }
+    }
+}
+
+/// Changes to a tree, which copy the chunks of nodes it shares with its
+/// snapshots first.
+impl<S, T, const INLINE: usize> InlineQuadTree<S, T, INLINE>
+where
+    S: IntervalDomain,
+    T: Clone,
+{
+    fn node_mut(&mut self, index: usize) -> &mut Node<S, T, INLINE> {
+        &mut self.chunk_mut(index / NODE_CHUNK)[index % NODE_CHUNK]
+    }
+
+    fn chunk_mut(&mut self, chunk: usize) -> &mut Vec<Node<S, T, INLINE>> {
+        Arc::make_mut(&mut self.nodes[chunk])
+    }
+
+    fn outliers_mut(&mut self) -> &mut Vec<(P2<S>, T)> {
+        Arc::make_mut(&mut self.outliers)
+    }
+
+    /// Append a node to the arena, returning its index.
+    fn push_node(&mut self, node: Node<S, T, INLINE>) -> usize {
+        let index = self.node_count();
+        match self.nodes.last() {
+            Some(chunk) if chunk.len() < NODE_CHUNK => {
+                self.chunk_mut(self.nodes.len() - 1).push(node)
+            }
+            _ => self.nodes.push(Arc::new(vec![node])),
+        }
+        index
+    }
+
+    /// Reorder the nodes of the tree so that queries touch fewer cache lines.
+    ///
+    /// Nodes are otherwise stored in the order in which they were split,
+    /// which scatters the path from the root to a leaf across the arena.
+    /// Packing lays them out in the cache-oblivious van Emde Boas order, in
+    /// which each subtree of a few levels is contiguous, whatever the size of
+    /// a cache line or page. It is meant for trees which are built once and
+    /// then queried: later pushes still work, but add nodes at the end.
+    pub fn pack(&mut self) {
+        let mut order = Vec::with_capacity(self.node_count());
+        self.van_emde_boas_order(0, self.height(), &mut order);
+        let mut new_index = vec![0; self.node_count()];
+        for (new, &old) in order.iter().enumerate() {
+            new_index[old] = new;
+        }
+        let mut old_nodes = mem::take(&mut self.nodes)
+            .into_iter()
+            .flat_map(|mut chunk| mem::take(Arc::make_mut(&mut chunk)))
+            .map(Some)
+            .collect::<Vec<_>>();
+        let nodes = order
+            .iter()
+            .filter_map(|&old| old_nodes[old].take())
+            .map(|node| match node {
+                Node::Branch { split, children } => Node::Branch {
+                    split,
+                    children: [
+                        new_index[children[0]],
+                        new_index[children[1]],
+                        new_index[children[2]],
+                        new_index[children[3]],
+                    ],
+                },
+                leaf => leaf,
+            })
+            .collect::<Vec<_>>();
+        for node in nodes {
+            self.push_node(node);
+        }
+    }
+
+    /// Split the leaf at `index`, and then any of its new children which
+    /// are themselves over capacity.
+    fn subdivide(&mut self, index: usize, rect: Rect<S>, depth: usize) {
+        let mut pending = vec![(index, rect, depth)];
+        while let Some((index, rect, depth)) = pending.pop() {
+            let over_capacity = match self.node(index) {
+                Node::Leaf(items) => items.len() > self.leaf_capacity,
+                Node::Branch { .. } => false,
+            };
+            if !over_capacity || depth >= self.max_depth {
+                continue;
+            }
+            let (split, quadrants) = match split_rect(&rect) {
+                Some(split_quadrants) => split_quadrants,
+                None => continue,
+            };
+
+            let first_child = self.node_count();
+            let children = [
+                first_child,
+                first_child + 1,
+                first_child + 2,
+                first_child + 3,
+            ];
+            let mut child_items: [Items<S, T, INLINE>; 4] = Default::default();
+            let old = mem::replace(
+                self.node_mut(index),
+                Node::Branch {
+                    split: split.clone(),
+                    children,
+                },
+            );
+            if let Node::Leaf(items) = old {
+                for item in items {
+                    child_items[quadrant(&split, &item.0)].push(item);
+                }
+            }
+            for items in child_items.iter_mut() {
+                self.push_node(Node::Leaf(mem::take(items)));
+            }
+            for (child, child_rect) in children.iter().zip(quadrants.iter()) {
+                pending.push((*child, child_rect.clone(), depth + 1));
+            }
+        }
+    }
+}
+
+impl<S, T, const INLINE: usize> Accel2D for InlineQuadTree<S, T, INLINE>
+where
+    S: IntervalDomain,
+    T: Clone,
+{
+    type Scalar = S;
+    type Item = T;
+
+    fn new() -> Self {
+        InlineQuadTree {
+            bounds: None,
+            nodes: vec![Arc::new(vec![Node::Leaf(SmallVec::new())])],
+            outliers: Arc::new(Vec::new()),
+            leaf_capacity: DEFAULT_LEAF_CAPACITY,
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+
+    fn new_from_vec(items: Vec<(P2<S>, T)>) -> Self {
+        let mut quadtree = match Rect::bounding(items.iter().map(|(point, _)| point)) {
+            Some(bounds) => Self::with_bounds(bounds),
+            None => Self::new(),
+        };
+        quadtree.insert(items);
+        quadtree
+    }
+
+    fn push(&mut self, item: (P2<S>, T)) {
+        let bounds = match &self.bounds {
+            Some(bounds) => bounds.clone(),
+            None => {
+                let len = match self.node_mut(0) {
+                    Node::Leaf(items) => {
+                        items.push(item);
+                        items.len()
+                    }
+                    // The root is a leaf until bounds are set.
+                    Node::Branch { .. } => {
+                        self.outliers_mut().push(item);
+                        return;
+                    }
+                };
+                // If no bounds can be found (eg. the points span the entire
+                // range of an integer type), retry only when the number of
+                // items doubles, to keep insertion amortized linear.
+                if len > self.leaf_capacity
+                    && (len == self.leaf_capacity + 1 || len.is_power_of_two())
+                {
+                    if let Node::Leaf(items) = self.node(0) {
+                        self.bounds = Rect::bounding(items.iter().map(|(point, _)| point));
+                    }
+                    if let Some(bounds) = self.bounds.clone() {
+                        self.subdivide(0, bounds, 0);
+                    }
+                }
+                return;
+            }
+        };
+
+        if !bounds.contains(&item.0) {
+            self.outliers_mut().push(item);
+            return;
+        }
+
+        let mut index = 0;
+        let mut rect = bounds;
+        let mut depth = 0;
+        // Branches are only made from regions which can be split, so the
+        // region of each child can always be found.
+        while let Node::Branch { split, children } = self.node(index) {
+            let q = quadrant(split, &item.0);
+            if let Some((_, quadrants)) = split_rect(&rect) {
+                rect = quadrants[q].clone();
+            }
+            index = children[q];
+            depth += 1;
+        }
+        let leaf_capacity = self.leaf_capacity;
+        if let Node::Leaf(items) = self.node_mut(index) {
+            items.push(item);
+            if items.len() > leaf_capacity {
+                self.subdivide(index, rect, depth);
+            }
+        }
+    }
+
+    /// Regions of the nodes, if the tree has bounds. Outliers are in no
+    /// node.
+    fn regions(&self) -> Vec<(Rect<S>, usize)> {
+        let mut regions = Vec::with_capacity(self.node_count());
+        let mut stack = self
+            .bounds
+            .iter()
+            .map(|bounds| (0, bounds.clone(), 0))
+            .collect::<Vec<_>>();
+        while let Some((index, rect, depth)) = stack.pop() {
+            if let Node::Branch { children, .. } = self.node(index) {
+                if let Some((_, quadrants)) = split_rect(&rect) {
+                    for (child, quadrant) in children.iter().zip(quadrants.iter()).rev() {
+                        stack.push((*child, quadrant.clone(), depth + 1));
+                    }
+                }
+            }
+            regions.push((rect, depth));
+        }
+        regions
+    }
+
+    fn query_rect(&self, rect: &Rect<S>) -> Vec<&(P2<S>, T)> {
+        let mut result: Vec<&(P2<S>, T)> = self
+            .outliers
+            .iter()
+            .filter(|(point, _)| rect.contains(point))
+            .collect();
+
+        let mut stack = vec![(0, self.bounds.clone())];
+        while let Some((index, opt_node_rect)) = stack.pop() {
+            match self.node(index) {
+                Node::Leaf(items) => {
+                    for chunk in items.chunks(32) {
+                        let mut bits = rect.contains_block(chunk.iter().map(|(point, _)| point));
+                        while bits != 0 {
+                            result.push(&chunk[bits.trailing_zeros() as usize]);
+                            bits &= bits - 1;
+                        }
+                    }
+                }
+                Node::Branch { children, .. } => {
+                    // Branches always have a region which can be split.
+                    let quadrants = match opt_node_rect.as_ref().and_then(split_rect) {
+                        Some((_, quadrants)) => quadrants,
+                        None => continue,
+                    };
+                    for (child, child_rect) in children.iter().zip(quadrants.iter()) {
+                        if rect.intersect(child_rect).is_some() {
+                            stack.push((*child, Some(child_rect.clone())));
+                        }
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    /// Answer a batch of queries in a single traversal of the tree.
+    ///
+    /// Each node is visited at most once, carrying the queries which overlap
+    /// it, so a node shared by many queries is only fetched once. This is
+    /// much faster than querying each rectangle in turn when there are many
+    /// queries clustered together, as in cross-matching or density maps.
+    fn query_rects(&self, rects: &[Rect<S>]) -> Vec<Vec<&(P2<S>, T)>> {
+        let mut results: Vec<Vec<&(P2<S>, T)>> = rects
+            .iter()
+            .map(|rect| {
+                self.outliers
+                    .iter()
+                    .filter(|(point, _)| rect.contains(point))
+                    .collect()
+            })
+            .collect();
+        if rects.is_empty() {
+            return results;
+        }
+
+        // The queries overlapping each node on the stack are a range of
+        // `pending`. Ranges are pushed in stack order, so those above the
+        // range of a popped node belong to nodes already visited.
+        let mut pending = (0..rects.len())
+            .filter(|&query| match &self.bounds {
+                Some(bounds) => rects[query].intersect(bounds).is_some(),
+                None => true,
+            })
+            .collect::<Vec<usize>>();
+        let mut stack = vec![(0, 0, pending.len())];
+        while let Some((index, start, end)) = stack.pop() {
+            pending.truncate(end);
+            match self.node(index) {
+                Node::Leaf(items) => {
+                    for &query in &pending[start..end] {
+                        let result = &mut results[query];
+                        for chunk in items.chunks(32) {
+                            let mut bits =
+                                rects[query].contains_block(chunk.iter().map(|(point, _)| point));
+                            while bits != 0 {
+                                result.push(&chunk[bits.trailing_zeros() as usize]);
+                                bits &= bits - 1;
+                            }
+                        }
+                    }
+                }
+                Node::Branch { split, children } => {
+                    // Each query overlapping the branch overlaps the children
+                    // on its side of the split, so the child regions are not
+                    // needed.
+                    let masks = pending[start..end]
+                        .iter()
+                        .map(|&query| overlapped_quadrants(split, &rects[query]))
+                        .collect::<SmallVec<[u8; 64]>>();
+                    for (q, child) in children.iter().enumerate() {
+                        let child_start = pending.len();
+                        for (i, mask) in masks.iter().enumerate() {
+                            if mask & (1 << q) != 0 {
+                                let query = pending[start + i];
+                                pending.push(query);
+                            }
+                        }
+                        if pending.len() > child_start {
+                            stack.push((*child, child_start, pending.len()));
+                        }
+                    }
+                }
+            }
+        }
+        results
+    }
+}
+
+/// Node of a quadtree, as met by a [dual-tree traversal](QuadTree::dual_tree).
+pub struct DualNode<'a, S, T> {
+    index: usize,
+    rect: Option<&'a Rect<S>>,
+    items: Option<&'a [(P2<S>, T)]>,
+    children: Option<&'a [usize; 4]>,
+    first: usize,
+}
+
+impl<'a, S, T> DualNode<'a, S, T> {
+    fn new<const INLINE: usize>(
+        index: usize,
+        rect: Option<&'a Rect<S>>,
+        node: &'a Node<S, T, INLINE>,
+        first: usize,
+    ) -> Self {
+        let (items, children) = match node {
+            Node::Leaf(items) => (Some(&items[..]), None),
+            Node::Branch { children, .. } => (None, Some(children)),
+        };
+        DualNode {
+            index,
+            rect,
+            items,
+            children,
+            first,
+        }
+    }
+
+    /// Number of the node in its tree, less than its
+    /// [node_count](QuadTree::node_count), which a visitor can use to keep
+    /// state for each node. It is only the same while the tree is unchanged.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Region of the node, if the tree has bounds.
+    pub fn rect(&self) -> Option<&'a Rect<S>> {
+        self.rect
+    }
+
+    /// Items of a leaf, or `None` for a branch.
+    pub fn items(&self) -> Option<&'a [(P2<S>, T)]> {
+        self.items
+    }
+
+    /// Indexes of the children of a branch, or `None` for a leaf.
+    pub fn children(&self) -> Option<&'a [usize; 4]> {
+        self.children
+    }
+
+    /// Place, in the order of [iter](QuadTree::iter), of the first item of
+    /// a leaf; the others follow it.
+    pub fn first_place(&self) -> usize {
+        self.first
+    }
+}
+
+/// Algorithm over pairs of nodes of two quadtrees, a query tree and a
+/// reference tree (which may be the same tree), walked by
+/// [dual_tree](QuadTree::dual_tree).
+///
+/// Starting from the pair of roots, each pair which is not pruned is either
+/// a pair of leaves, whose items are compared by `base_case`, or is split
+/// into pairs of their children (keeping a leaf whole), which are walked in
+/// turn. Outliers are in no node, so a visitor must deal with them itself.
+///
+/// Counting the pairs of points within a distance of each other:
+///
+/// ```
+/// # use starquad::accel2d::Accel2D;
+/// # use starquad::accel2d::quadtree::{DualNode, DualTreeVisitor, QuadTree};
+/// # use starquad::geom::p2::P2;
+/// struct PairCount {
+///     radius_2: f64,
+///     count: usize,
+/// }
+///
+/// impl DualTreeVisitor<f64, (), ()> for PairCount {
+///     fn prune(&mut self, query: &DualNode<f64, ()>, reference: &DualNode<f64, ()>) -> bool {
+///         match (query.rect(), reference.rect()) {
+///             (Some(q), Some(r)) => {
+///                 let gap = |a: f64, a_end: f64, b: f64, b_end: f64| (b - a_end).max(a - b_end).max(0.0);
+///                 let dx = gap(*q.x(), q.x_interval().end(), *r.x(), r.x_interval().end());
+///                 let dy = gap(*q.y(), q.y_interval().end(), *r.y(), r.y_interval().end());
+///                 dx * dx + dy * dy > self.radius_2
+///             }
+///             _ => false,
+///         }
+///     }
+///
+///     fn base_case(&mut self, query: &DualNode<f64, ()>, reference: &DualNode<f64, ()>) {
+///         for (a, _) in query.items().unwrap() {
+///             for (b, _) in reference.items().unwrap() {
+///                 if a.distance_2(b) <= self.radius_2 {
+///                     self.count += 1;
+///                 }
+///             }
+///         }
+///     }
+/// }
+///
+/// let points = (0..400).map(|i| (P2::new(f64::from(i % 20), f64::from(i / 20)), ())).collect();
+/// let tree = QuadTree::new_from_vec(points);
+/// let mut pairs = PairCount { radius_2: 1.0, count: 0 };
+/// tree.dual_tree(&tree, &mut pairs);
+/// // each point with itself, and each ordered pair of neighbours on the grid
+/// assert_eq!(pairs.count, 400 + 4 * 20 * 19);
+/// ```
+pub trait DualTreeVisitor<S, T, U> {
+    /// Whether no pair of items under the two nodes needs to be compared,
+    /// so that the pair, and every pair of their descendants, is skipped.
+    fn prune(&mut self, query: &DualNode<S, T>, reference: &DualNode<S, U>) -> bool;
+
+    /// Compare the items of two leaves.
+    fn base_case(&mut self, query: &DualNode<S, T>, reference: &DualNode<S, U>);
+
+    /// Called after a query branch has been walked with a reference node,
+    /// eg. to gather the bounds found for its children. By default it does
+    /// nothing.
+    fn after_children(&mut self, _query: &DualNode<S, T>) {}
+}
+
+/// Regions and places of the nodes of two trees, for a dual-tree traversal.
+struct DualTree<'a, S, T, U, const Q: usize, const R: usize> {
+    query: &'a InlineQuadTree<S, T, Q>,
+    reference: &'a InlineQuadTree<S, U, R>,
+    query_rects: Vec<Option<Rect<S>>>,
+    reference_rects: Vec<Option<Rect<S>>>,
+    query_first: Vec<usize>,
+    reference_first: Vec<usize>,
+}
+
+impl<'a, S, T, U, const Q: usize, const R: usize> DualTree<'a, S, T, U, Q, R>
+where
+    S: IntervalDomain + FloatCore,
+{
+    fn gap(&self, q: usize, r: usize) -> S {
+        match (&self.query_rects[q], &self.reference_rects[r]) {
+            (Some(a), Some(b)) => min_distance_2(a, b),
+            _ => S::zero(),
+        }
+    }
+
+    /// Children of a reference branch, nearest to the query node `q` first,
+    /// and any with the same index as `q` before the others which touch it.
+    /// When a tree is walked against itself, this visits a node's own items
+    /// first, which for searches of the nearest items tightens its bounds
+    /// before it meets any others.
+    fn nearest_children(&self, q: usize, children: &[usize; 4]) -> [usize; 4] {
+        let mut by_gap = children.map(|child| (self.gap(q, child), child != q, child));
+        by_gap.sort_by(|a, b| {
+            a.0.partial_cmp(&b.0)
+                .unwrap_or(Ordering::Equal)
+                .then(a.1.cmp(&b.1))
+        });
+        by_gap.map(|(_, _, child)| child)
+    }
+
+    fn visit<V>(&self, visitor: &mut V, q: usize, r: usize)
+    where
+        V: DualTreeVisitor<S, T, U>,
+    {
+        let query = DualNode::new(
+            q,
+            self.query_rects[q].as_ref(),
+            self.query.node(q),
+            self.query_first[q],
+        );
+        let reference = DualNode::new(
+            r,
+            self.reference_rects[r].as_ref(),
+            self.reference.node(r),
+            self.reference_first[r],
+        );
+        if visitor.prune(&query, &reference) {
+            return;
+        }
+        match (query.children, reference.children) {
+            (None, None) => visitor.base_case(&query, &reference),
+            (None, Some(children)) => {
+                for child in self.nearest_children(q, children).iter() {
+                    self.visit(visitor, q, *child);
+                }
+            }
+            (Some(children), r_children) => {
+                for q_child in children.iter() {
+                    match r_children {
+                        Some(r_children) => {
+                            for r_child in self.nearest_children(*q_child, r_children).iter() {
+                                self.visit(visitor, *q_child, *r_child);
+                            }
+                        }
+                        None => self.visit(visitor, *q_child, r),
+                    }
+                }
+                visitor.after_children(&query);
+            }
+        }
+    }
+}
+
+impl<S, T, const INLINE: usize> InlineQuadTree<S, T, INLINE>
+where
+    S: IntervalDomain + FloatCore,
+{
+    /// Walk pairs of nodes of this tree and a reference tree with a
+    /// [visitor](DualTreeVisitor), which prunes pairs which cannot hold
+    /// pairs of items it is looking for and compares the items of pairs of
+    /// leaves.
+    ///
+    /// The children of a reference branch are visited nearest first, so that
+    /// searches for the nearest items find near ones early and prune more.
+    pub fn dual_tree<U, V, const R: usize>(
+        &self,
+        reference: &InlineQuadTree<S, U, R>,
+        visitor: &mut V,
+    ) where
+        V: DualTreeVisitor<S, T, U>,
+    {
+        DualTree {
+            query: self,
+            reference,
+            query_rects: self.node_rects(),
+            reference_rects: reference.node_rects(),
+            query_first: self.leaf_places().0,
+            reference_first: reference.leaf_places().0,
+        }
+        .visit(visitor, 0, 0);
+    }
+}
+
+impl<S, T, const INLINE: usize> InlineQuadTree<S, T, INLINE>
+where
+    S: IntervalDomain + FloatCore,
+{
+    /// Nearest other item to every item of the tree, found in a single pass.
+    ///
+    /// The result is in the order of [iter](QuadTree::iter): for each item,
+    /// the place in that order of its nearest neighbour, and the square of
+    /// the distance to it (`None` if there is no other item it can be
+    /// compared with, as for a tree of one item or a point with a `NaN`).
+    ///
+    /// Rather than searching the tree once for each item, this walks pairs
+    /// of nodes, skipping a pair when the nodes are further apart than the
+    /// nearest neighbour already found for every item in the first of them
+    /// (a dual-tree search). Outliers are compared with every item.
+    ///
+    /// ```
+    /// # use starquad::accel2d::Accel2D;
+    /// # use starquad::accel2d::quadtree::QuadTree;
+    /// # use starquad::geom::p2::P2;
+    /// let points = vec![(P2::new(0.0, 0.0), "a"), (P2::new(3.0, 0.0), "b"), (P2::new(0.0, 1.0), "c")];
+    /// let tree = QuadTree::new_from_vec(points);
+    /// let items = tree.iter().collect::<Vec<_>>();
+    /// for (item, nearest) in items.iter().zip(tree.all_nearest_neighbors()) {
+    ///     let (place, distance_2) = nearest.unwrap();
+    ///     match item.1 {
+    ///         "a" => assert_eq!((items[place].1, distance_2), ("c", 1.0)),
+    ///         "b" => assert_eq!((items[place].1, distance_2), ("a", 9.0)),
+    ///         _ => assert_eq!((items[place].1, distance_2), ("a", 1.0)),
+    ///     }
+    /// }
+    /// ```
+    pub fn all_nearest_neighbors(&self) -> Vec<Option<(usize, S)>> {
+        let places = (0..self.len()).collect::<Vec<_>>();
+        NearestNeighbors::new(self, places)
+            .search()
+            .into_iter()
+            .map(|nearest| nearest.map(|(distance_2, _, place)| (place, distance_2)))
+            .collect()
+    }
+
+    /// Item nearest to a point, and the square of its distance from it.
+    pub fn nearest_neighbor(&self, point: &P2<S>) -> Option<(&(P2<S>, T), S)> {
+        self.approx_nearest_neighbor(point, S::zero())
+    }
+
+    /// An item no more than `1 + epsilon` times as far from a point as the
+    /// nearest item, and the square of its distance from it.
+    ///
+    /// The search skips every node which is not `1 + epsilon` times nearer
+    /// than the best item found so far, so it visits fewer nodes the larger
+    /// `epsilon` is; an `epsilon` of zero finds the nearest item.
+    ///
+    /// ```
+    /// # use starquad::accel2d::Accel2D;
+    /// # use starquad::accel2d::quadtree::QuadTree;
+    /// # use starquad::geom::p2::P2;
+    /// let items = (0..100).map(|i| (P2::new((i % 10) as f64, (i / 10) as f64), i)).collect();
+    /// let tree = QuadTree::new_from_vec(items);
+    /// let point = P2::new(4.4, 5.1);
+    /// let (nearest, distance_2) = tree.nearest_neighbor(&point).unwrap();
+    /// assert_eq!(nearest.1, 54);
+    /// let (_, approx_2) = tree.approx_nearest_neighbor(&point, 0.5).unwrap();
+    /// assert!(approx_2 <= 1.5 * 1.5 * distance_2);
+    /// ```
+    pub fn approx_nearest_neighbor(&self, point: &P2<S>, epsilon: S) -> Option<(&(P2<S>, T), S)> {
+        let mut best: Option<(&(P2<S>, T), S)> = None;
+        let scale = (S::one() + epsilon) * (S::one() + epsilon);
+        for item in self.outliers.iter() {
+            let distance_2 = item.0.distance_2(point);
+            if best.map_or(!distance_2.is_nan(), |(_, d)| distance_2 < d) {
+                best = Some((item, distance_2));
+            }
+        }
+
+        let mut stack = vec![(0, self.bounds.clone(), S::zero())];
+        while let Some((index, opt_node_rect, gap)) = stack.pop() {
+            if let Some((_, best_2)) = best {
+                if gap * scale >= best_2 {
+                    continue;
+                }
+            }
+            match self.node(index) {
+                Node::Leaf(items) => {
+                    for item in items.iter() {
+                        let distance_2 = item.0.distance_2(point);
+                        if best.map_or(!distance_2.is_nan(), |(_, d)| distance_2 < d) {
+                            best = Some((item, distance_2));
+                        }
+                    }
+                }
+                Node::Branch { children, .. } => {
+                    let quadrants = match opt_node_rect.as_ref().and_then(split_rect) {
+                        Some((_, quadrants)) => quadrants,
+                        None => continue,
+                    };
+                    let mut by_gap = [0, 1, 2, 3].map(|q| {
+                        let gap = point_distance_2(&quadrants[q], point);
+                        (gap, q)
+                    });
+                    // nearest last, so that it is searched first
+                    by_gap.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+                    for (gap, q) in by_gap.iter() {
+                        stack.push((children[*q], Some(quadrants[*q].clone()), *gap));
+                    }
+                }
+            }
+        }
+        best
+    }
+}
+
+/// Borůvka's algorithm over a quadtree, whose places are in the order of
+/// [iter](QuadTree::iter). The search for each item's nearest item in
+/// another component is a dual-tree search, which skips nodes which only
+/// hold items of its own component.
+impl<S, T, const INLINE: usize> Boruvka for InlineQuadTree<S, T, INLINE>
+where
+    S: IntervalDomain + FloatCore,
+{
+    type Length = S;
+
+    fn places(&self) -> usize {
+        self.len()
+    }
+
+    fn shortest_edges(&self, component: &[usize]) -> Vec<Option<(S, usize, usize)>> {
+        NearestNeighbors::new(self, component.to_vec()).search()
+    }
+}
+
+impl<S, T, const INLINE: usize> InlineQuadTree<S, T, INLINE>
+where
+    S: IntervalDomain + FloatCore,
+{
+    /// Summaries of every node, for [level-of-detail
+    /// queries](LevelOfDetail::query_lod).
+    ///
+    /// Building them visits every item once, so a viewer builds them once
+    /// for a tree which is not changing and then queries them every frame.
+    pub fn level_of_detail(&self) -> LevelOfDetail<'_, S, T, INLINE> {
+        let mut summaries: Vec<Option<Cluster<S>>> = vec![None; self.node_count()];
+        for &index in self.preorder().iter().rev() {
+            summaries[index] = match self.node(index) {
+                Node::Leaf(items) => Cluster::of(items.iter()),
+                Node::Branch { children, .. } => children
+                    .iter()
+                    .filter_map(|child| summaries[*child].clone())
+                    .reduce(|a, b| a.join(&b)),
+            };
+        }
+        LevelOfDetail {
+            tree: self,
+            rects: self.node_rects(),
+            summaries,
+        }
+    }
+}
+
+/// Summary of the items of a node: how many there are, their mean, and the
+/// smallest and largest of their coordinates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cluster<S> {
+    pub count: usize,
+    pub centroid: P2<S>,
+    /// Corners of the bounding box of the items, which they may be on.
+    pub min: P2<S>,
+    pub max: P2<S>,
+}
+
+impl<S: FloatCore> Cluster<S> {
+    /// Summary of some items, ignoring any with a `NaN`, or `None` if there
+    /// are no others.
+    fn of<'a, T: 'a, I>(items: I) -> Option<Self>
+    where
+        I: IntoIterator<Item = &'a (P2<S>, T)>,
+        S: 'a,
+    {
+        items
+            .into_iter()
+            .filter(|(point, _)| !point.x.is_nan() && !point.y.is_nan())
+            .map(|(point, _)| Cluster {
+                count: 1,
+                centroid: *point,
+                min: *point,
+                max: *point,
+            })
+            .reduce(|a, b| a.join(&b))
+    }
+
+    /// Summary of the items of two summaries.
+    fn join(&self, other: &Cluster<S>) -> Cluster<S> {
+        let count = self.count + other.count;
+        let weight = |n: usize| S::from(n).unwrap_or_else(S::infinity);
+        let (w, w_other) = (weight(self.count), weight(other.count));
+        let total = w + w_other;
+        Cluster {
+            count,
+            centroid: P2::new(
+                (self.centroid.x * w + other.centroid.x * w_other) / total,
+                (self.centroid.y * w + other.centroid.y * w_other) / total,
+            ),
+            min: P2::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y)),
+            max: P2::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y)),
+        }
+    }
+}
+
+/// Item, or summary of items, found by a level-of-detail query.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Detail<'a, S, T> {
+    Item(&'a (P2<S>, T)),
+    Cluster(Cluster<S>),
+}
+
+/// Summaries of the nodes of a tree, from
+/// [level_of_detail](QuadTree::level_of_detail).
+pub struct LevelOfDetail<'a, S, T, const INLINE: usize = LEAF_INLINE_ITEMS> {
+    tree: &'a InlineQuadTree<S, T, INLINE>,
+    rects: Vec<Option<Rect<S>>>,
+    summaries: Vec<Option<Cluster<S>>>,
+}
+
+impl<'a, S, T, const INLINE: usize> LevelOfDetail<'a, S, T, INLINE>
+where
+    S: IntervalDomain + FloatCore,
+{
+    /// Items in a rectangle, or summaries of them, with no more than
+    /// `max_items` results however many items there are.
+    ///
+    /// Starting from the root, the node with the most items is replaced by
+    /// its children (or a leaf by its items) for as long as that leaves no
+    /// more than `max_items` results, so zooming in on a region shows it in
+    /// more detail, down to its items. A summary is of all of a node's
+    /// items, including any outside `rect` when the node crosses its edge.
+    /// Outliers in `rect` are summarized together, if they are not returned
+    /// as items.
+    ///
+    /// ```
+    /// # use starquad::accel2d::Accel2D;
+    /// # use starquad::accel2d::quadtree::{Detail, QuadTree};
+    /// # use starquad::geom::p2::P2;
+    /// # use starquad::geom::rect::Rect;
+    /// let items = (0..10_000).map(|i| (P2::new((i % 100) as f64, (i / 100) as f64), i)).collect();
+    /// let tree = QuadTree::new_from_vec(items);
+    /// let lod = tree.level_of_detail();
+    ///
+    /// let everything = lod.query_lod(&Rect::new(0.0, 0.0, 100.0, 100.0).unwrap(), 100);
+    /// assert!(everything.len() <= 100);
+    /// let count = |details: &[Detail<f64, i32>]| -> usize {
+    ///     details.iter().map(|detail| match detail {
+    ///         Detail::Item(_) => 1,
+    ///         Detail::Cluster(cluster) => cluster.count,
+    ///     }).sum()
+    /// };
+    /// assert_eq!(count(&everything), 10_000);
+    ///
+    /// let zoomed = lod.query_lod(&Rect::new(10.0, 10.0, 5.0, 5.0).unwrap(), 100);
+    /// assert_eq!(zoomed.len(), 25);
+    /// assert!(zoomed.iter().all(|detail| matches!(detail, Detail::Item(_))));
+    /// ```
+    pub fn query_lod(&self, rect: &Rect<S>, max_items: usize) -> Vec<Detail<'a, S, T>> {
+        let tree = self.tree;
+        let mut details = Vec::new();
+        let outliers = tree
+            .outliers
+            .iter()
+            .filter(|(point, _)| rect.contains(point))
+            .collect::<Vec<_>>();
+        let overlaps = |index: usize| {
+            self.summaries[index].is_some()
+                && self.rects[index]
+                    .as_ref()
+                    .is_none_or(|node_rect| rect.intersect(node_rect).is_some())
+        };
+        let mut open = BinaryHeap::new();
+        if overlaps(0) {
+            open.push((self.summaries[0].as_ref().map_or(0, |s| s.count), 0));
+        }
+        // results once every open node and the outliers are summarized
+        let mut len = open.len() + usize::from(!outliers.is_empty());
+        if max_items < len {
+            // too few for even the root and the outliers apart
+            let all = self.summaries[0]
+                .iter()
+                .cloned()
+                .chain(Cluster::of(outliers));
+            return all
+                .reduce(|a, b| a.join(&b))
+                .filter(|_| max_items > 0)
+                .map(Detail::Cluster)
+                .into_iter()
+                .collect();
+        }
+
+        while let Some((_, index)) = open.pop() {
+            match tree.node(index) {
+                Node::Branch { children, .. } => {
+                    let children = children
+                        .iter()
+                        .filter(|child| overlaps(**child))
+                        .collect::<SmallVec<[&usize; 4]>>();
+                    if len - 1 + children.len() <= max_items {
+                        len = len - 1 + children.len();
+                        for child in children {
+                            let count = self.summaries[*child].as_ref().map_or(0, |s| s.count);
+                            open.push((count, *child));
+                        }
+                        continue;
+                    }
+                }
+                Node::Leaf(items) => {
+                    let found = items
+                        .iter()
+                        .filter(|(point, _)| rect.contains(point))
+                        .collect::<Vec<_>>();
+                    if len - 1 + found.len() <= max_items {
+                        len = len - 1 + found.len();
+                        details.extend(found.into_iter().map(Detail::Item));
+                        continue;
+                    }
+                }
+            }
+            if let Some(summary) = &self.summaries[index] {
+                details.push(Detail::Cluster(summary.clone()));
+            }
+        }
+
+        if !outliers.is_empty() {
+            if len - 1 + outliers.len() <= max_items {
+                details.extend(outliers.into_iter().map(Detail::Item));
+            } else {
+                details.extend(Cluster::of(outliers).map(Detail::Cluster));
+            }
+        }
+        details
+    }
+}
+
+/// Leaf of a quadtree, as found by [leaf_at](QuadTree::leaf_at).
+///
+/// A leaf is only identified until the tree is next changed, since pushing
+/// items may split it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LeafId(usize);
+
+/// Side of a leaf, with north towards increasing `y` and east towards
+/// increasing `x`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    North,
+    South,
+    East,
+    West,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 4] = [
+        Direction::North,
+        Direction::South,
+        Direction::East,
+        Direction::West,
+    ];
+
+    /// Bit of a quadrant index (see `quadrant`) which moving in the
+    /// direction changes, and its value in the quadrants on that side.
+    fn bit(self) -> (usize, usize) {
+        match self {
+            Direction::East => (1, 1),
+            Direction::West => (1, 0),
+            Direction::North => (2, 2),
+            Direction::South => (2, 0),
+        }
+    }
+}
+
+impl<S, T, const INLINE: usize> InlineQuadTree<S, T, INLINE>
+where
+    S: IntervalDomain,
+{
+    /// Leaf whose region contains a point, or `None` if the point is outside
+    /// the bounds of the tree (or it has none).
+    pub fn leaf_at(&self, point: &P2<S>) -> Option<LeafId> {
+        if !self.bounds.as_ref()?.contains(point) {
+            return None;
+        }
+        let mut index = 0;
+        while let Node::Branch { split, children } = self.node(index) {
+            index = children[quadrant(split, point)];
+        }
+        Some(LeafId(index))
+    }
+
+    /// Items of a leaf.
+    pub fn leaf_items(&self, leaf: LeafId) -> &[(P2<S>, T)] {
+        match self.node(leaf.0) {
+            Node::Leaf(items) => items,
+            Node::Branch { .. } => &[],
+        }
+    }
+
+    /// Every leaf of the tree with its region and items, in the order of
+    /// the nodes. Leaves are smallest where items are densest, so together
+    /// they are an adaptive histogram of the items (less any outliers).
+    ///
+    /// ```
+    /// # use starquad::accel2d::Accel2D;
+    /// # use starquad::accel2d::quadtree::QuadTree;
+    /// # use starquad::geom::p2::P2;
+    /// # use starquad::geom::rect::Rect;
+    /// let mut tree = QuadTree::with_bounds(Rect::new(0, 0, 8, 8).unwrap()).with_leaf_capacity(1);
+    /// tree.insert(vec![(P2::new(1, 1), 0), (P2::new(5, 1), 1)]);
+    /// let leaves = tree.leaves();
+    /// assert_eq!(leaves.len(), 4);
+    /// assert_eq!(leaves.iter().map(|(_, items)| items.len()).sum::<usize>(), 2);
+    /// let (rect, items) = &leaves[0];
+    /// assert_eq!((rect, items[0].1), (&Rect::new(0, 0, 4, 4).unwrap(), 0));
+    /// ```
+    #[allow(clippy::type_complexity)]
+    pub fn leaves(&self) -> Vec<(Rect<S>, &[(P2<S>, T)])> {
+        self.node_rects()
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, rect)| match (self.node(index), rect) {
+                (Node::Leaf(items), Some(rect)) => Some((rect, items.as_slice())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Region of a leaf. This visits every node, so `grow_region` should be
+    /// preferred to calling it for many leaves.
+    pub fn leaf_rect(&self, leaf: LeafId) -> Option<Rect<S>> {
+        self.node_rects().swap_remove(leaf.0)
+    }
+
+    /// Leaves which share part of a leaf's side facing `direction`: one leaf
+    /// at least as large as it, or the leaves of a region as large as it
+    /// which touch that side. Leaves on the edge of the tree's bounds have
+    /// none on that side.
+    ///
+    /// ```
+    /// # use starquad::accel2d::Accel2D;
+    /// # use starquad::accel2d::quadtree::{Direction, QuadTree};
+    /// # use starquad::geom::p2::P2;
+    /// # use starquad::geom::rect::Rect;
+    /// let mut tree = QuadTree::with_bounds(Rect::new(0, 0, 8, 8).unwrap()).with_leaf_capacity(1);
+    /// // split the south-east quadrant into four
+    /// tree.insert(vec![(P2::new(1, 1), 0), (P2::new(5, 1), 1), (P2::new(7, 3), 2)]);
+    /// let west = tree.leaf_at(&P2::new(1, 1)).unwrap();
+    /// let east = tree.leaf_neighbors(west, Direction::East);
+    /// let mut found = east.iter().map(|leaf| tree.leaf_rect(*leaf).unwrap()).collect::<Vec<_>>();
+    /// found.sort_by_key(|rect| *rect.y());
+    /// assert_eq!(found, vec![Rect::new(4, 0, 2, 2).unwrap(), Rect::new(4, 2, 2, 2).unwrap()]);
+    /// assert!(tree.leaf_neighbors(west, Direction::West).is_empty());
+    /// ```
+    pub fn leaf_neighbors(&self, leaf: LeafId, direction: Direction) -> Vec<LeafId> {
+        self.adjacent_leaves(&self.parents(), leaf.0, direction)
+            .into_iter()
+            .map(LeafId)
+            .collect()
+    }
+
+    /// Leaves connected to `seed` through leaves for which `include` is true,
+    /// given the region and items of each leaf, starting with the seed (if
+    /// it is included) and then in order of how many steps from one leaf to
+    /// its neighbour they are from it.
+    ///
+    /// `include` is called once for each leaf which is reached.
+    pub fn grow_region<F>(&self, seed: LeafId, mut include: F) -> Vec<LeafId>
+    where
+        F: FnMut(&Rect<S>, &[(P2<S>, T)]) -> bool,
+    {
+        let rects = self.node_rects();
+        let parents = self.parents();
+        let mut included = |index: usize| match (self.node(index), &rects[index]) {
+            (Node::Leaf(items), Some(rect)) => include(rect, items),
+            _ => false,
+        };
+        let mut reached = vec![false; self.node_count()];
+        reached[seed.0] = true;
+        if !included(seed.0) {
+            return Vec::new();
+        }
+        let mut region = vec![seed.0];
+        let mut next = 0;
+        while let Some(&index) = region.get(next) {
+            next += 1;
+            for direction in Direction::ALL.iter() {
+                for neighbor in self.adjacent_leaves(&parents, index, *direction) {
+                    if !reached[neighbor] {
+                        reached[neighbor] = true;
+                        if included(neighbor) {
+                            region.push(neighbor);
+                        }
+                    }
+                }
+            }
+        }
+        region.into_iter().map(LeafId).collect()
+    }
+
+    /// Parent of each node, by index, with the root as its own parent.
+    fn parents(&self) -> Vec<usize> {
+        let mut parents = vec![0; self.node_count()];
+        for index in 0..self.node_count() {
+            if let Node::Branch { children, .. } = self.node(index) {
+                for child in children.iter() {
+                    parents[*child] = index;
+                }
+            }
+        }
+        parents
+    }
+
+    /// Indexes of the leaves adjacent to a node in a direction.
+    ///
+    /// This follows the tree rather than comparing regions, whose edges may
+    /// be rounded differently on either side: it climbs to the nearest
+    /// ancestor with a quadrant on the far side of the node, and then comes
+    /// down through the mirror image of the quadrants it climbed through.
+    fn adjacent_leaves(&self, parents: &[usize], index: usize, direction: Direction) -> Vec<usize> {
+        let (bit, side) = direction.bit();
+        let mut climbed = Vec::new();
+        let mut node = index;
+        let mut neighbor = loop {
+            if node == 0 {
+                return Vec::new();
+            }
+            let parent = parents[node];
+            let children = match self.node(parent) {
+                Node::Branch { children, .. } => children,
+                Node::Leaf(_) => return Vec::new(),
+            };
+            let q = children
+                .iter()
+                .position(|child| *child == node)
+                .unwrap_or(0);
+            if q & bit != side {
+                break children[q ^ bit];
+            }
+            climbed.push(q);
+            node = parent;
+        };
+        for q in climbed.iter().rev() {
+            match self.node(neighbor) {
+                Node::Branch { children, .. } => neighbor = children[q ^ bit],
+                Node::Leaf(_) => break,
+            }
+        }
+
+        // the neighbour may be a branch, whose leaves on the near side touch
+        let mut leaves = Vec::new();
+        let mut stack = vec![neighbor];
+        while let Some(index) = stack.pop() {
+            match self.node(index) {
+                Node::Leaf(_) => leaves.push(index),
+                Node::Branch { children, .. } => {
+                    stack.extend((0..4).filter(|q| q & bit != side).map(|q| children[q]))
+                }
+            }
+        }
+        leaves
+    }
+}
+
+/// Dual-tree search for the nearest item in another component to each
+/// component of the items of a tree, which finds every item's nearest
+/// neighbour when each item is a component of its own.
+struct NearestNeighbors<'a, S, T, const INLINE: usize> {
+    tree: &'a InlineQuadTree<S, T, INLINE>,
+    points: Vec<&'a P2<S>>,
+    /// Component of each place.
+    component: Vec<usize>,
+    in_leaves: usize,
+    labels: Vec<Component>,
+    /// Upper bound of the distance (squared) from any item under each node
+    /// to the nearest item found outside its component.
+    bounds: Vec<S>,
+    /// Shortest edge out of each component: its length squared, and the
+    /// places at its ends, inside and outside the component.
+    nearest: Vec<Option<(S, usize, usize)>>,
+}
+
+impl<'a, S, T, const INLINE: usize> NearestNeighbors<'a, S, T, INLINE>
+where
+    S: IntervalDomain + FloatCore,
+{
+    /// Search of a tree, with the components of its items (by place) named
+    /// by places.
+    fn new(tree: &'a InlineQuadTree<S, T, INLINE>, component: Vec<usize>) -> Self {
+        let (first, in_leaves) = tree.leaf_places();
+        let mut labels = vec![Component::Empty; tree.node_count()];
+        for &index in tree.preorder().iter().rev() {
+            labels[index] = match tree.node(index) {
+                Node::Leaf(items) => (0..items.len()).fold(Component::Empty, |label, i| {
+                    label.join(Component::One(component[first[index] + i]))
+                }),
+                Node::Branch { children, .. } => children
+                    .iter()
+                    .fold(Component::Empty, |label, child| label.join(labels[*child])),
+            };
+        }
+        NearestNeighbors {
+            tree,
+            points: tree.iter().map(|(point, _)| point).collect(),
+            in_leaves,
+            // nothing is searched for from empty nodes
+            bounds: labels
+                .iter()
+                .map(|label| match label {
+                    Component::Empty => S::zero(),
+                    _ => S::infinity(),
+                })
+                .collect(),
+            labels,
+            nearest: vec![None; component.len()],
+            component,
+        }
+    }
+
+    /// Shortest edge out of each component, by the place naming it.
+    fn search(mut self) -> Vec<Option<(S, usize, usize)>> {
+        for outlier in self.in_leaves..self.points.len() {
+            for place in 0..self.points.len() {
+                if self.component[place] != self.component[outlier] {
+                    self.offer(outlier, place);
+                    self.offer(place, outlier);
+                }
+            }
+        }
+        let tree = self.tree;
+        tree.dual_tree(tree, &mut self);
+        self.nearest
+    }
+
+    /// Offer the edge from one place to another as the shortest out of the
+    /// component of the first, breaking ties by the places at the ends so
+    /// that the edges chosen for different components never make a cycle.
+    fn offer(&mut self, place: usize, other: usize) {
+        let distance_2 = self.points[place].distance_2(self.points[other]);
+        let nearest = &mut self.nearest[self.component[place]];
+        if shorter(distance_2, place, other, nearest) {
+            *nearest = Some((distance_2, place, other));
+        }
+    }
+}
+
+/// Offers the items under the reference node as neighbours of the items
+/// under the query node.
+impl<'a, S, T, const INLINE: usize> DualTreeVisitor<S, T, T> for NearestNeighbors<'a, S, T, INLINE>
+where
+    S: IntervalDomain + FloatCore,
+{
+    fn prune(&mut self, query: &DualNode<S, T>, reference: &DualNode<S, T>) -> bool {
+        match (self.labels[query.index], self.labels[reference.index]) {
+            (Component::Empty, _) | (_, Component::Empty) => return true,
+            (Component::One(a), Component::One(b)) if a == b => return true,
+            _ => {}
+        }
+        let gap = match (query.rect, reference.rect) {
+            (Some(a), Some(b)) => min_distance_2(a, b),
+            _ => S::zero(),
+        };
+        gap > self.bounds[query.index]
+    }
+
+    fn base_case(&mut self, query: &DualNode<S, T>, reference: &DualNode<S, T>) {
+        let q_len = query.items().map_or(0, |items| items.len());
+        let r_len = reference.items().map_or(0, |items| items.len());
+        let mut bound = S::zero();
+        for place in query.first..query.first + q_len {
+            for other in reference.first..reference.first + r_len {
+                if self.component[place] != self.component[other] {
+                    self.offer(place, other);
+                }
+            }
+            let nearest = self.nearest[self.component[place]];
+            bound = bound.max(nearest.map_or(S::infinity(), |(best, _, _)| best));
+        }
+        self.bounds[query.index] = bound;
+    }
+
+    fn after_children(&mut self, query: &DualNode<S, T>) {
+        if let Some(children) = query.children() {
+            self.bounds[query.index] = children
+                .iter()
+                .fold(S::zero(), |bound, child| bound.max(self.bounds[*child]));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use accel2d::quadtree::{
+        inline_items, Detail, Direction, DualNode, DualTreeVisitor, InlineQuadTree, LeafId, Node,
+        QuadTree, LEAF_INLINE_ITEMS,
+    };
+    use accel2d::reference::Reference;
+    use accel2d::Accel2D;
+    use alloc::sync::Arc;
+    use alloc::vec::Vec;
+    use core::mem;
+    #[cfg(feature = "fs")]
+    use gaia::record::GaiaRecord;
+    use geom::interval::Interval;
+    use geom::p2::P2;
+    use geom::rect::Rect;
+    use mst::Boruvka;
+    use quickcheck_macros::quickcheck;
+
+    fn sorted_items<S>(query: Vec<&(P2<S>, usize)>) -> Vec<usize> {
+        let mut items = query.iter().map(|(_, item)| *item).collect::<Vec<usize>>();
+        items.sort();
+        items
+    }
+
+    #[test]
+    fn query_rect_grid() {
+        let items = (0..400)
+            .map(|i| (P2::new(i as i32 % 20, i as i32 / 20), i))
+            .collect::<Vec<(P2<i32>, usize)>>();
+        let tree = QuadTree::new_from_vec(items);
+        assert_eq!(tree.len(), 400);
+        let query = tree.query_rect(&Rect::new(3, 4, 2, 2).unwrap());
+        assert_eq!(sorted_items(query), vec![83, 84, 103, 104]);
+    }
+
+    #[test]
+    fn coincident_points() {
+        let mut tree = QuadTree::<f64, usize>::new().with_leaf_capacity(2);
+        for i in 0..100 {
+            tree.push((P2::new(1.0, 1.0), i));
+        }
+        tree.push((P2::new(2.0, 2.0), 100));
+        let query = tree.query_rect(&Rect::new(0.5, 0.5, 1.0, 1.0).unwrap());
+        assert_eq!(query.len(), 100);
+    }
+
+    #[test]
+    fn outliers() {
+        let bounds = Rect::new(0.0, 0.0, 1.0, 1.0).unwrap();
+        let mut tree = QuadTree::with_bounds(bounds).with_leaf_capacity(1);
+        tree.push((P2::new(0.25, 0.25), 0));
+        tree.push((P2::new(0.75, 0.75), 1));
+        tree.push((P2::new(5.0, 5.0), 2));
+        let query = tree.query_rect(&Rect::new(0.5, 0.5, 10.0, 10.0).unwrap());
+        assert_eq!(sorted_items(query), vec![1, 2]);
+    }
+
+    #[test]
+    fn pack_layout() {
+        // a complete tree of three levels, with one item in each leaf
+        let items = (0..16)
+            .map(|i| (P2::new(i % 4, i / 4), i as usize))
+            .collect::<Vec<(P2<i32>, usize)>>();
+        let mut tree = QuadTree::with_bounds(Rect::new(0, 0, 4, 4).unwrap()).with_leaf_capacity(1);
+        tree.insert(items);
+        tree.pack();
+        // each branch below the root is followed by its four leaves
+        let children = |index: usize| match tree.node(index) {
+            Node::Branch { children, .. } => children.to_vec(),
+            Node::Leaf(_) => vec![],
+        };
+        assert_eq!(children(0), vec![1, 6, 11, 16]);
+        assert_eq!(children(6), vec![7, 8, 9, 10]);
+        let query = tree.query_rect(&Rect::new(1, 1, 2, 1).unwrap());
+        assert_eq!(sorted_items(query), vec![5, 6]);
+    }
+
+    #[test]
+    fn snapshots() {
+        let mut tree = QuadTree::<i32, usize>::with_bounds(Rect::new(0, 0, 64, 64).unwrap())
+            .with_leaf_capacity(1);
+        tree.insert(
+            (0..1000)
+                .map(|i| (P2::new(i as i32 % 64, i as i32 / 64), i))
+                .collect(),
+        );
+        tree.push((P2::new(100, 100), 1000));
+        // taken through a shared reference, as a reader would
+        let reader = &tree;
+        let snapshot = reader.snapshot();
+        let chunks = tree.nodes.len();
+        assert!(chunks > 1);
+
+        // pushing to one region only copies the chunks it touches
+        tree.insert(
+            (0..100)
+                .map(|i| (P2::new(i as i32 % 4, 60 + i as i32 / 25), 2000 + i))
+                .collect(),
+        );
+        tree.push((P2::new(-1, -1), 3000));
+        let copied = tree
+            .nodes
+            .iter()
+            .zip(snapshot.nodes.iter())
+            .filter(|(a, b)| !Arc::ptr_eq(a, b))
+            .count();
+        assert!(copied > 0 && copied < chunks);
+
+        let everything = Rect::new(-10, -10, 200, 200).unwrap();
+        assert_eq!(snapshot.len(), 1001);
+        assert_eq!(
+            sorted_items(snapshot.query_rect(&everything)),
+            (0..1001).collect::<Vec<_>>()
+        );
+        assert_eq!(tree.len(), 1102);
+        let mut restored = snapshot.snapshot();
+        restored.pack();
+        assert_eq!(
+            sorted_items(restored.query_rect(&everything)),
+            (0..1001).collect::<Vec<_>>()
+        );
+        assert_eq!(snapshot.len(), 1001);
+    }
+
+    #[test]
+    fn all_nearest_neighbors_grid() {
+        // a grid with spacing 2, and one point nudged towards its neighbour
+        let mut items = (0..400)
+            .map(|i| (P2::new((i % 20) as f64 * 2.0, (i / 20) as f64 * 2.0), i))
+            .collect::<Vec<(P2<f64>, usize)>>();
+        items[21].0.x += 0.5;
+        items.push((P2::new(100.0, 0.0), 400));
+        let mut tree = QuadTree::with_bounds(Rect::new(0.0, 0.0, 40.0, 40.0).unwrap());
+        tree.insert(items);
+        let order = tree.iter().map(|(_, i)| *i).collect::<Vec<_>>();
+        let nearest = tree
+            .all_nearest_neighbors()
+            .into_iter()
+            .map(|nearest| nearest.map(|(place, distance_2)| (order[place], distance_2)))
+            .collect::<Vec<_>>();
+        let of = |i: usize| nearest[order.iter().position(|&j| j == i).unwrap()];
+        assert_eq!(of(21), Some((22, 2.25)));
+        assert_eq!(of(22), Some((21, 2.25)));
+        assert_eq!(of(0).unwrap().1, 4.0);
+        assert_eq!(of(400), Some((19, 62.0 * 62.0)));
+        assert!(QuadTree::<f64, usize>::new()
+            .all_nearest_neighbors()
+            .is_empty());
+    }
+
+    /// Property test: the nearest neighbour is as near as the nearest item
+    /// found by brute force, and an approximate one is near enough.
+    #[quickcheck]
+    fn f64_nearest_neighbor_matches_brute_force(points: Vec<P2<f64>>, point: P2<f64>) {
+        let items = points
+            .iter()
+            .enumerate()
+            .map(|(i, point)| (*point, i))
+            .collect::<Vec<(P2<f64>, usize)>>();
+        let mut tree = QuadTree::new().with_leaf_capacity(2);
+        tree.insert(items);
+        let expected = points
+            .iter()
+            .map(|other| other.distance_2(&point))
+            .filter(|distance_2| !distance_2.is_nan())
+            .fold(None, |best: Option<f64>, d| {
+                Some(best.map_or(d, |b| b.min(d)))
+            });
+        let found = tree.nearest_neighbor(&point).map(|(item, distance_2)| {
+            assert_eq!(item.0.distance_2(&point), distance_2);
+            distance_2
+        });
+        assert_eq!(found, expected);
+        let approx = tree.approx_nearest_neighbor(&point, 0.1).map(|(_, d)| d);
+        match (approx, expected) {
+            (Some(approx), Some(expected)) => assert!(approx <= 1.1 * 1.1 * expected),
+            (approx, expected) => assert_eq!(approx, expected),
+        }
+    }
+
+    /// Property test: the spanning tree connects every point, and is as
+    /// short as the one found by Prim's algorithm over every pair.
+    #[quickcheck]
+    fn f64_minimum_spanning_tree_matches_prim(points: Vec<P2<f64>>) {
+        let items = points
+            .into_iter()
+            .filter(|point| point.x.is_finite() && point.y.is_finite())
+            .map(|point| (point, ()))
+            .collect::<Vec<_>>();
+        let mut tree = QuadTree::new().with_leaf_capacity(2);
+        tree.insert(items);
+        let points = tree.iter().map(|(point, _)| *point).collect::<Vec<_>>();
+        let edges = tree.minimum_spanning_tree();
+        assert_eq!(edges.len(), points.len().saturating_sub(1));
+        for (a, b, distance_2) in edges.iter() {
+            assert_eq!(points[*a].distance_2(&points[*b]), *distance_2);
+        }
+
+        let mut reached = vec![false; points.len()];
+        let mut nearest = vec![f64::INFINITY; points.len()];
+        let mut expected = 0.0;
+        if !points.is_empty() {
+            nearest[0] = 0.0;
+        }
+        for _ in 0..points.len() {
+            let next = (0..points.len())
+                .filter(|&i| !reached[i])
+                .min_by(|&a, &b| nearest[a].partial_cmp(&nearest[b]).unwrap())
+                .unwrap();
+            reached[next] = true;
+            expected += nearest[next];
+            for i in 0..points.len() {
+                nearest[i] = nearest[i].min(points[next].distance_2(&points[i]));
+            }
+        }
+        let total = edges.iter().map(|edge| edge.2).sum::<f64>();
+        assert!((total - expected).abs() <= 1e-9 * expected.abs().max(1.0));
+    }
+
+    /// Property test: the nearest neighbours found in a single pass are as
+    /// near as those found by comparing every pair of items.
+    #[quickcheck]
+    fn f64_all_nearest_neighbors_match_brute_force(points: Vec<P2<f64>>) {
+        let items = points
+            .into_iter()
+            .enumerate()
+            .map(|(i, point)| (point, i))
+            .collect::<Vec<(P2<f64>, usize)>>();
+        let mut tree = QuadTree::new().with_leaf_capacity(2);
+        tree.insert(items);
+        let points = tree.iter().map(|(point, _)| *point).collect::<Vec<_>>();
+        let nearest = tree.all_nearest_neighbors();
+        assert_eq!(nearest.len(), points.len());
+        for (i, point) in points.iter().enumerate() {
+            let expected = (0..points.len())
+                .filter(|&j| j != i)
+                .map(|j| point.distance_2(&points[j]))
+                .filter(|distance_2| !distance_2.is_nan())
+                .fold(None, |best: Option<f64>, d| {
+                    Some(best.map_or(d, |b| b.min(d)))
+                });
+            let found = nearest[i].map(|(j, distance_2)| {
+                assert_eq!(point.distance_2(&points[j]), distance_2);
+                distance_2
+            });
+            assert_eq!(found, expected);
+        }
+    }
+
+    /// Property test: the quadtree returns the same items as the reference
+    /// implementation, whether it is built incrementally or in bulk.
+    #[quickcheck]
+    fn f64_query_rect_matches_reference(points: Vec<P2<f64>>, rect: Rect<f64>) {
+        let items = points
+            .into_iter()
+            .enumerate()
+            .map(|(i, point)| (point, i))
+            .collect::<Vec<(P2<f64>, usize)>>();
+        let reference = Reference::new_from_vec(items.clone());
+        let bulk = QuadTree::new_from_vec(items.clone());
+        let mut incremental = QuadTree::new().with_leaf_capacity(2);
+        incremental.insert(items);
+
+        let expected = sorted_items(reference.query_rect(&rect));
+        assert_eq!(sorted_items(bulk.query_rect(&rect)), expected);
+        assert_eq!(sorted_items(incremental.query_rect(&rect)), expected);
+        incremental.pack();
+        assert_eq!(sorted_items(incremental.query_rect(&rect)), expected);
+    }
+
+    #[test]
+    fn query_rects_grid() {
+        let items = (0..400)
+            .map(|i| (P2::new(i as i32 % 20, i as i32 / 20), i))
+            .collect::<Vec<(P2<i32>, usize)>>();
+        let tree = QuadTree::new_from_vec(items);
+        let rects = [
+            Rect::new(3, 4, 2, 2).unwrap(),
+            Rect::new(4, 4, 1, 1).unwrap(),
+            Rect::new(30, 30, 5, 5).unwrap(),
+        ];
+        let found = tree
+            .query_rects(&rects)
+            .into_iter()
+            .map(sorted_items)
+            .collect::<Vec<_>>();
+        assert_eq!(found, vec![vec![83, 84, 103, 104], vec![84], vec![]]);
+        assert!(tree.query_rects(&[]).is_empty());
+    }
+
+    /// Property test: a batch of queries finds the same items as querying
+    /// each rectangle in turn.
+    #[quickcheck]
+    fn f64_query_rects_matches_query_rect(points: Vec<P2<f64>>, rects: Vec<Rect<f64>>) {
+        let items = points
+            .into_iter()
+            .enumerate()
+            .map(|(i, point)| (point, i))
+            .collect::<Vec<(P2<f64>, usize)>>();
+        let mut tree = QuadTree::new().with_leaf_capacity(2);
+        tree.insert(items);
+
+        let batch = tree.query_rects(&rects);
+        assert_eq!(batch.len(), rects.len());
+        for (found, rect) in batch.into_iter().zip(rects.iter()) {
+            assert_eq!(sorted_items(found), sorted_items(tree.query_rect(rect)));
+        }
+    }
+
+    #[quickcheck]
+    fn i8_query_rect_matches_reference(points: Vec<P2<i8>>, rect: Rect<i8>) {
+        let items = points
+            .into_iter()
+            .enumerate()
+            .map(|(i, point)| (point, i))
+            .collect::<Vec<(P2<i8>, usize)>>();
+        let reference = Reference::new_from_vec(items.clone());
+        let mut tree = QuadTree::new().with_leaf_capacity(1);
+        tree.insert(items);
+        assert_eq!(
+            sorted_items(tree.query_rect(&rect)),
+            sorted_items(reference.query_rect(&rect))
+        );
+    }
+
+    /// Property test: a level-of-detail query returns no more than it is
+    /// asked for, accounts for every item in the rectangle, and returns
+    /// them all as items when there is room for every item of the tree.
+    #[quickcheck]
+    fn f64_query_lod_accounts_for_items(
+        points: Vec<P2<f64>>,
+        outliers: Vec<P2<f64>>,
+        rect: Rect<f64>,
+        max_items: u8,
+    ) {
+        let max_items = usize::from(max_items);
+        let mut tree =
+            QuadTree::with_bounds(Rect::new(-1e3, -1e3, 2e3, 2e3).unwrap()).with_leaf_capacity(4);
+        let items = points
+            .into_iter()
+            .map(|point| P2::new(point.x % 1e3, point.y % 1e3))
+            .chain(outliers)
+            .enumerate()
+            .map(|(i, point)| (point, i))
+            .collect::<Vec<_>>();
+        tree.insert(items);
+        let expected = sorted_items(tree.query_rect(&rect));
+
+        let lod = tree.level_of_detail();
+        let details = lod.query_lod(&rect, max_items);
+        assert!(details.len() <= max_items);
+        let found = details
+            .iter()
+            .filter_map(|detail| match detail {
+                Detail::Item(item) => Some(*item),
+                Detail::Cluster(_) => None,
+            })
+            .collect::<Vec<_>>();
+        let clustered = details
+            .iter()
+            .map(|detail| match detail {
+                Detail::Item(_) => 0,
+                Detail::Cluster(cluster) => cluster.count,
+            })
+            .sum::<usize>();
+        let found = sorted_items(found);
+        assert!(found
+            .iter()
+            .all(|item| expected.binary_search(item).is_ok()));
+        assert!(found.windows(2).all(|pair| pair[0] != pair[1]));
+        assert!(max_items == 0 || found.len() + clustered >= expected.len());
+        if max_items >= tree.len() {
+            assert_eq!(found, expected);
+        }
+    }
+
+    /// Pairs of items of two trees no further apart than a distance.
+    struct Join {
+        radius_2: f64,
+        pairs: Vec<(usize, usize)>,
+    }
+
+    impl DualTreeVisitor<f64, usize, usize> for Join {
+        fn prune(
+            &mut self,
+            query: &DualNode<f64, usize>,
+            reference: &DualNode<f64, usize>,
+        ) -> bool {
+            match (query.rect(), reference.rect()) {
+                (Some(q), Some(r)) => super::min_distance_2(q, r) > self.radius_2,
+                _ => false,
+            }
+        }
+
+        fn base_case(&mut self, query: &DualNode<f64, usize>, reference: &DualNode<f64, usize>) {
+            for (a, i) in query.items().unwrap() {
+                for (b, j) in reference.items().unwrap() {
+                    if a.distance_2(b) <= self.radius_2 {
+                        self.pairs.push((*i, *j));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Property test: a dual-tree join of two trees finds the same pairs as
+    /// comparing every pair of items.
+    #[quickcheck]
+    fn f64_dual_tree_join_matches_brute_force(
+        left: Vec<(u8, u8)>,
+        right: Vec<(u8, u8)>,
+        radius: u8,
+    ) {
+        let items = |points: Vec<(u8, u8)>| {
+            points
+                .into_iter()
+                .enumerate()
+                .map(|(i, (x, y))| (P2::new(f64::from(x), f64::from(y)), i))
+                .collect::<Vec<_>>()
+        };
+        let (left, right) = (items(left), items(right));
+        let radius_2 = f64::from(radius % 32).powi(2);
+        let mut query =
+            QuadTree::with_bounds(Rect::new(0.0, 0.0, 256.0, 256.0).unwrap()).with_leaf_capacity(2);
+        query.insert(left.clone());
+        let reference = QuadTree::new_from_vec(right.clone());
+        let mut join = Join {
+            radius_2,
+            pairs: Vec::new(),
+        };
+        query.dual_tree(&reference, &mut join);
+        join.pairs.sort();
+        let mut expected = Vec::new();
+        for (a, i) in left.iter() {
+            for (b, j) in right.iter() {
+                if a.distance_2(b) <= radius_2 {
+                    expected.push((*i, *j));
+                }
+            }
+        }
+        expected.sort();
+        assert_eq!(join.pairs, expected);
+    }
+
+    #[test]
+    fn large_items_are_not_inline() {
+        type Record = [u8; 1024];
+        const INLINE: usize = inline_items::<(P2<f64>, Record)>();
+        assert_eq!(INLINE, 0);
+        assert_eq!(inline_items::<(P2<f64>, usize)>(), LEAF_INLINE_ITEMS);
+        assert_eq!(inline_items::<()>(), LEAF_INLINE_ITEMS);
+        assert!(mem::size_of::<Node<f64, Record, INLINE>>() <= 64);
+
+        let mut tree = InlineQuadTree::<f64, Record, INLINE>::with_bounds(
+            Rect::new(0.0, 0.0, 4.0, 4.0).unwrap(),
+        );
+        for i in 0..40 {
+            tree.push((P2::new(i as f64 / 10.0, 1.0), [i; 1024]));
+        }
+        let found = tree.query_rect(&Rect::new(0.0, 0.0, 1.0, 2.0).unwrap());
+        assert_eq!(found.len(), 10);
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn gaia_index_nodes_are_small() {
+        const INLINE: usize = inline_items::<(P2<f64>, GaiaRecord)>();
+        assert_eq!(INLINE, 0);
+        assert!(mem::size_of::<Node<f64, GaiaRecord, INLINE>>() <= 64);
+        assert!(mem::size_of::<GaiaRecord>() > 64);
+    }
+
+    fn byte_tree(points: Vec<(u8, u8)>) -> QuadTree<i32, usize> {
+        let mut tree =
+            QuadTree::with_bounds(Rect::new(0, 0, 256, 256).unwrap()).with_leaf_capacity(1);
+        tree.insert(
+            points
+                .into_iter()
+                .enumerate()
+                .map(|(i, (x, y))| (P2::new(i32::from(x), i32::from(y)), i))
+                .collect(),
+        );
+        tree
+    }
+
+    /// Property test: the neighbours of a leaf in each direction are the
+    /// leaves whose regions share part of its side.
+    #[quickcheck]
+    fn i32_leaf_neighbors_share_sides(points: Vec<(u8, u8)>) {
+        let tree = byte_tree(points);
+        let rects = tree.node_rects();
+        let leaves = (0..tree.node_count())
+            .filter(|index| matches!(tree.node(*index), Node::Leaf(_)))
+            .collect::<Vec<_>>();
+        let overlap =
+            |a: &Interval<i32>, b: &Interval<i32>| a.start() < &b.end() && b.start() < &a.end();
+        let touches = |a: &Rect<i32>, b: &Rect<i32>, direction| match direction {
+            Direction::East => {
+                *b.x() == a.x_interval().end() && overlap(a.y_interval(), b.y_interval())
+            }
+            Direction::West => {
+                *a.x() == b.x_interval().end() && overlap(a.y_interval(), b.y_interval())
+            }
+            Direction::North => {
+                *b.y() == a.y_interval().end() && overlap(a.x_interval(), b.x_interval())
+            }
+            Direction::South => {
+                *a.y() == b.y_interval().end() && overlap(a.x_interval(), b.x_interval())
+            }
+        };
+        for leaf in leaves.iter() {
+            let rect = rects[*leaf].as_ref().unwrap();
+            for direction in Direction::ALL.iter() {
+                let mut found = tree
+                    .leaf_neighbors(LeafId(*leaf), *direction)
+                    .into_iter()
+                    .map(|neighbor| neighbor.0)
+                    .collect::<Vec<_>>();
+                found.sort();
+                let expected = leaves
+                    .iter()
+                    .filter(|other| touches(rect, rects[**other].as_ref().unwrap(), *direction))
+                    .copied()
+                    .collect::<Vec<_>>();
+                assert_eq!(found, expected, "{:?} {:?}", rect, direction);
+            }
+        }
+    }
+
+    /// Property test: a region grown through empty leaves reaches every
+    /// empty leaf next to it, and one grown through every leaf reaches them
+    /// all.
+    #[quickcheck]
+    fn i32_grow_region_is_closed(points: Vec<(u8, u8)>, seed: (u8, u8)) {
+        let tree = byte_tree(points);
+        let seed = tree
+            .leaf_at(&P2::new(i32::from(seed.0), i32::from(seed.1)))
+            .unwrap();
+        let everything = tree.grow_region(seed, |_, _| true);
+        assert_eq!(everything[0], seed);
+        let leaves = (0..tree.node_count())
+            .filter(|index| matches!(tree.node(*index), Node::Leaf(_)))
+            .count();
+        assert_eq!(everything.len(), leaves);
+
+        let empty = tree.grow_region(seed, |_, items| items.is_empty());
+        assert_eq!(empty.is_empty(), !tree.leaf_items(seed).is_empty());
+        for leaf in empty.iter() {
+            assert!(tree.leaf_items(*leaf).is_empty());
+            for direction in Direction::ALL.iter() {
+                for neighbor in tree.leaf_neighbors(*leaf, *direction) {
+                    assert!(!tree.leaf_items(neighbor).is_empty() || empty.contains(&neighbor));
+                }
+            }
+        }
+    }
+}
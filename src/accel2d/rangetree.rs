@@ -0,0 +1,307 @@
+//! Range tree with fractional cascading, for counting the items in
+//! rectangles.
+//!
+//! Completeness maps and selection functions count the sources in millions
+//! of cells without needing the sources themselves; a `RangeTree` counts
+//! the items in a rectangle in `O(log n)` time, however many there are.
+
+use accel2d::Accel2D;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use geom::interval::{Interval, IntervalDomain};
+use geom::p2::P2;
+use geom::rect::Rect;
+
+/// Static 2D range tree of items at points in the plane.
+///
+/// The items are sorted by `x`, and each node of a balanced binary tree over
+/// them holds its items sorted by `y`. The nodes at each depth are stored
+/// together, and each position at a depth records how many of the items
+/// before it in its node go to the node's left child, so a query finds its
+/// place in the children of a node without searching them (fractional
+/// cascading). The tree takes `O(n log n)` space.
+///
+/// Items can be added through `Accel2D`, but each addition rebuilds the
+/// tree, so add them all at once where possible.
+///
+/// ```
+/// # use starquad::accel2d::rangetree::RangeTree;
+/// # use starquad::accel2d::Accel2D;
+/// # use starquad::geom::{p2::P2, rect::Rect};
+/// let items = (0..100).map(|i| (P2::new(i % 10, i / 10), i)).collect();
+/// let tree = RangeTree::new(items);
+/// let rect = Rect::new(2, 3, 4, 5).unwrap();
+/// assert_eq!(tree.count_rect(&rect), 20);
+/// assert_eq!(tree.query_rect(&rect).len(), 20);
+/// ```
+#[derive(Clone, Debug)]
+pub struct RangeTree<S, T> {
+    /// Items, sorted by `x`.
+    items: Vec<(P2<S>, T)>,
+    /// `y` of the items, sorted.
+    ys: Vec<S>,
+    levels: Vec<Level>,
+}
+
+/// Nodes of the tree at one depth.
+#[derive(Clone, Debug)]
+struct Level {
+    /// Indexes in `items` of the items of each node, sorted by `y`.
+    ranks: Vec<usize>,
+    /// Number of the items before each position at this depth which go to
+    /// the left child of their node, with a final total.
+    left: Vec<usize>,
+}
+
+fn compare<S: PartialOrd>(a: &S, b: &S) -> Ordering {
+    a.partial_cmp(b).unwrap_or(Ordering::Equal)
+}
+
+/// Range of the indexes of values, sorted by a key, whose keys are in an
+/// interval.
+fn positions<S, V, K>(values: &[V], key: K, interval: &Interval<S>) -> (usize, usize)
+where
+    S: IntervalDomain,
+    K: Fn(&V) -> &S,
+{
+    (
+        values.partition_point(|v| key(v) < interval.start()),
+        values.partition_point(|v| S::before_end(interval, key(v))),
+    )
+}
+
+impl<S, T> RangeTree<S, T>
+where
+    S: PartialOrd + Clone,
+{
+    pub fn new(items: Vec<(P2<S>, T)>) -> Self {
+        let mut tree = RangeTree {
+            items,
+            ys: Vec::new(),
+            levels: Vec::new(),
+        };
+        tree.rebuild();
+        tree
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Items in the tree, sorted by `x`.
+    pub fn items(&self) -> &[(P2<S>, T)] {
+        &self.items
+    }
+
+    fn rebuild(&mut self) {
+        let n = self.items.len();
+        self.items.sort_by(|a, b| compare(&a.0.x, &b.0.x));
+        let mut ranks = (0..n).collect::<Vec<_>>();
+        ranks.sort_by(|&a, &b| compare(&self.items[a].0.y, &self.items[b].0.y));
+        self.ys = ranks.iter().map(|&i| self.items[i].0.y.clone()).collect();
+
+        // split the nodes at each depth, keeping their items in order of y
+        self.levels = Vec::new();
+        let mut nodes = vec![(0, n)];
+        while nodes.iter().any(|(lo, hi)| hi - lo > 1) {
+            let mut left = vec![0; n + 1];
+            let mut next = ranks.clone();
+            let mut children = Vec::with_capacity(nodes.len() * 2);
+            for &(lo, hi) in &nodes {
+                if hi - lo <= 1 {
+                    children.push((lo, hi));
+                    continue;
+                }
+                let mid = (lo + hi) / 2;
+                let (mut l, mut r) = (lo, mid);
+                for i in lo..hi {
+                    if ranks[i] < mid {
+                        next[l] = ranks[i];
+                        l += 1;
+                        left[i + 1] = 1;
+                    } else {
+                        next[r] = ranks[i];
+                        r += 1;
+                    }
+                }
+                children.push((lo, mid));
+                children.push((mid, hi));
+            }
+            for i in 0..n {
+                left[i + 1] += left[i];
+            }
+            self.levels.push(Level { ranks, left });
+            ranks = next;
+            nodes = children;
+        }
+        self.levels.push(Level {
+            ranks,
+            left: Vec::new(),
+        });
+    }
+}
+
+impl<S, T> RangeTree<S, T>
+where
+    S: IntervalDomain,
+{
+    /// Visit the nodes covering the items in a rectangle. `f` is given the
+    /// depth of each node and the positions at that depth of its items in
+    /// the rectangle.
+    fn cover<F: FnMut(usize, usize, usize)>(&self, rect: &Rect<S>, f: &mut F) {
+        let (start, end) = positions(&self.items, |(p, _)| &p.x, rect.x_interval());
+        let (p0, p1) = positions(&self.ys, |y| y, rect.y_interval());
+        let query = Query {
+            levels: &self.levels,
+            start,
+            end,
+        };
+        query.cover(0, 0, self.items.len(), p0, p1, f);
+    }
+}
+
+/// Range of items, by index in `items`, sought in a tree.
+struct Query<'a> {
+    levels: &'a [Level],
+    start: usize,
+    end: usize,
+}
+
+impl<'a> Query<'a> {
+    /// Visit the nodes covering the range within the node of items `lo` to
+    /// `hi` at a depth, whose items from positions `p0` to `p1` (from the
+    /// start of the node) are in the range of `y`.
+    fn cover<F>(&self, depth: usize, lo: usize, hi: usize, p0: usize, p1: usize, f: &mut F)
+    where
+        F: FnMut(usize, usize, usize),
+    {
+        if self.end <= lo || hi <= self.start || p0 == p1 {
+            return;
+        }
+        if self.start <= lo && hi <= self.end {
+            f(depth, lo + p0, lo + p1);
+            return;
+        }
+        let left = &self.levels[depth].left;
+        let (l0, l1) = (left[lo + p0] - left[lo], left[lo + p1] - left[lo]);
+        let mid = (lo + hi) / 2;
+        self.cover(depth + 1, lo, mid, l0, l1, f);
+        self.cover(depth + 1, mid, hi, p0 - l0, p1 - l1, f);
+    }
+}
+
+impl<S, T> Accel2D for RangeTree<S, T>
+where
+    S: IntervalDomain,
+{
+    type Scalar = S;
+    type Item = T;
+
+    fn new() -> Self {
+        RangeTree {
+            items: Vec::new(),
+            ys: Vec::new(),
+            levels: vec![Level {
+                ranks: Vec::new(),
+                left: Vec::new(),
+            }],
+        }
+    }
+
+    /// Add the items and rebuild the tree once.
+    fn insert(&mut self, items: Vec<(P2<S>, T)>) {
+        self.items.extend(items);
+        self.rebuild();
+    }
+
+    fn push(&mut self, item: (P2<S>, T)) {
+        self.items.push(item);
+        self.rebuild();
+    }
+
+    fn query_rect(&self, rect: &Rect<S>) -> Vec<&(P2<S>, T)> {
+        let mut found = Vec::new();
+        self.cover(rect, &mut |depth, from, to| {
+            let ranks = &self.levels[depth].ranks[from..to];
+            found.extend(ranks.iter().map(|&i| &self.items[i]));
+        });
+        found
+    }
+
+    /// Count the items without finding them, in `O(log n)` time.
+    fn count_rect(&self, rect: &Rect<S>) -> usize {
+        let mut count = 0;
+        self.cover(rect, &mut |_, from, to| count += to - from);
+        count
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use accel2d::rangetree::RangeTree;
+    use accel2d::reference::Reference;
+    use accel2d::Accel2D;
+    use alloc::vec::Vec;
+    use geom::p2::P2;
+    use geom::rect::Rect;
+    use quickcheck_macros::quickcheck;
+
+    fn sorted_items<S>(query: Vec<&(P2<S>, usize)>) -> Vec<usize> {
+        let mut items = query.iter().map(|(_, item)| *item).collect::<Vec<usize>>();
+        items.sort();
+        items
+    }
+
+    #[test]
+    fn edges() {
+        let empty = <RangeTree<u8, ()> as Accel2D>::new();
+        assert_eq!(empty.count_rect(&Rect::new(0, 0, 10, 10).unwrap()), 0);
+        let items = (0..=255).map(|i| (P2::new(i, 255 - i), ())).collect();
+        let tree = RangeTree::new(items);
+        // the end of the rectangle, 256, cannot be represented as a u8
+        assert_eq!(tree.count_rect(&Rect::new(250, 0, 6, 10).unwrap()), 6);
+        assert_eq!(tree.count_rect(&Rect::new(0, 0, 10, 246).unwrap()), 0);
+        assert_eq!(tree.count_rect(&Rect::new(0, 0, 10, 247).unwrap()), 1);
+    }
+
+    /// Property test: the tree finds and counts the same items in a
+    /// rectangle as the reference implementation, with many points sharing
+    /// coordinates.
+    #[quickcheck]
+    fn query_rect_matches_reference(points: Vec<(i8, i8)>, rect: Rect<i8>) {
+        let items = points
+            .into_iter()
+            .enumerate()
+            .map(|(i, (x, y))| (P2::new(x / 8, y / 8), i))
+            .collect::<Vec<_>>();
+        let reference = Reference::new_from_vec(items.clone());
+        let tree = RangeTree::new(items);
+        let expected = sorted_items(reference.query_rect(&rect));
+        assert_eq!(tree.count_rect(&rect), expected.len());
+        assert_eq!(sorted_items(tree.query_rect(&rect)), expected);
+    }
+
+    /// Property test: as `query_rect_matches_reference`, with `f64`s and
+    /// the tree built one item at a time.
+    #[quickcheck]
+    fn f64_query_rect_matches_reference(points: Vec<P2<f64>>, rect: Rect<f64>) {
+        let items = points
+            .into_iter()
+            .filter(|point| point.x.is_finite() && point.y.is_finite())
+            .enumerate()
+            .map(|(i, point)| (point, i))
+            .collect::<Vec<(P2<f64>, usize)>>();
+        let reference = Reference::new_from_vec(items.clone());
+        let mut tree = <RangeTree<f64, usize> as Accel2D>::new();
+        for item in items {
+            tree.push(item);
+        }
+        let expected = sorted_items(reference.query_rect(&rect));
+        assert_eq!(tree.count_rect(&rect), expected.len());
+        assert_eq!(sorted_items(tree.query_rect(&rect)), expected);
+    }
+}
@@ -1,4 +1,5 @@
 use accel2d::Accel2D;
+use alloc::vec::Vec;
 use geom::interval::IntervalDomain;
 use geom::p2::P2;
 use geom::rect::Rect;
@@ -42,6 +43,8 @@ where
 mod test {
     use accel2d::reference::Reference;
     use accel2d::Accel2D;
+    use alloc::string::String;
+    use alloc::vec::Vec;
     use geom::p2::P2;
     use geom::rect::Rect;
 
@@ -0,0 +1,286 @@
+//! Adapters between starquad geometry and the [rstar](https://docs.rs/rstar)
+//! crate, built with the `rstar` feature.
+//!
+//! `P2` is an rstar `Point` and `Rect` an `RTreeObject`, so either can be
+//! stored in an rstar `RTree`, and `RTreeIndex` wraps an `RTree` as an
+//! `Accel2D`, so that it can be swapped for a `QuadTree` (eg. to compare them
+//! on a workload, or to move code from rstar one index at a time).
+
+use accel2d::Accel2D;
+use alloc::vec::Vec;
+use geom::interval::IntervalDomain;
+use geom::p2::P2;
+use geom::rect::Rect;
+use rstar::{Point, PointDistance, RTree, RTreeNode, RTreeNum, RTreeObject, AABB};
+
+impl<S> Point for P2<S>
+where
+    S: RTreeNum,
+{
+    type Scalar = S;
+    const DIMENSIONS: usize = 2;
+
+    fn generate(mut generator: impl FnMut(usize) -> S) -> Self {
+        P2::new(generator(0), generator(1))
+    }
+
+    fn nth(&self, index: usize) -> S {
+        if index == 0 {
+            self.x
+        } else {
+            self.y
+        }
+    }
+
+    fn nth_mut(&mut self, index: usize) -> &mut S {
+        if index == 0 {
+            &mut self.x
+        } else {
+            &mut self.y
+        }
+    }
+}
+
+/// The envelope of a rectangle includes its upper edges, which the rectangle
+/// itself does not.
+impl<S> From<&Rect<S>> for AABB<P2<S>>
+where
+    S: IntervalDomain + RTreeNum,
+{
+    fn from(rect: &Rect<S>) -> Self {
+        AABB::from_corners(
+            P2::new(*rect.x(), *rect.y()),
+            P2::new(rect.x_interval().end(), rect.y_interval().end()),
+        )
+    }
+}
+
+impl<S> RTreeObject for Rect<S>
+where
+    S: IntervalDomain + RTreeNum,
+{
+    type Envelope = AABB<P2<S>>;
+
+    fn envelope(&self) -> Self::Envelope {
+        AABB::from(self)
+    }
+}
+
+/// Item stored in the `RTree` of an `RTreeIndex`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RTreeItem<S, T>(pub (P2<S>, T));
+
+impl<S, T> RTreeObject for RTreeItem<S, T>
+where
+    S: RTreeNum,
+{
+    type Envelope = AABB<P2<S>>;
+
+    fn envelope(&self) -> Self::Envelope {
+        AABB::from_point((self.0).0)
+    }
+}
+
+impl<S, T> PointDistance for RTreeItem<S, T>
+where
+    S: RTreeNum,
+{
+    fn distance_2(&self, point: &P2<S>) -> S {
+        (self.0).0.distance_2(point)
+    }
+}
+
+/// `Accel2D` backed by an rstar `RTree`.
+///
+/// ```
+/// # use starquad::accel2d::rtree::RTreeIndex;
+/// # use starquad::accel2d::Accel2D;
+/// # use starquad::geom::{p2::P2, rect::Rect};
+/// let index = RTreeIndex::new_from_vec(vec![(P2::new(1.0, 2.0), "a"), (P2::new(5.0, 5.0), "b")]);
+/// let found = index.query_rect(&Rect::new(0.0, 0.0, 3.0, 3.0).unwrap());
+/// assert_eq!(found, vec![&(P2::new(1.0, 2.0), "a")]);
+/// // the tree itself answers rstar's own queries
+/// let nearest = index.tree().nearest_neighbor(&P2::new(4.0, 4.0)).unwrap();
+/// assert_eq!((nearest.0).1, "b");
+/// ```
+pub struct RTreeIndex<S, T>
+where
+    S: RTreeNum,
+{
+    tree: RTree<RTreeItem<S, T>>,
+}
+
+impl<S, T> RTreeIndex<S, T>
+where
+    S: RTreeNum,
+{
+    pub fn tree(&self) -> &RTree<RTreeItem<S, T>> {
+        &self.tree
+    }
+
+    pub fn into_tree(self) -> RTree<RTreeItem<S, T>> {
+        self.tree
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.size()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.size() == 0
+    }
+}
+
+impl<S, T> From<RTree<RTreeItem<S, T>>> for RTreeIndex<S, T>
+where
+    S: RTreeNum,
+{
+    fn from(tree: RTree<RTreeItem<S, T>>) -> Self {
+        RTreeIndex { tree }
+    }
+}
+
+impl<S, T> Accel2D for RTreeIndex<S, T>
+where
+    S: IntervalDomain + RTreeNum,
+{
+    type Scalar = S;
+    type Item = T;
+
+    fn new() -> Self {
+        RTreeIndex { tree: RTree::new() }
+    }
+
+    /// Bulk load the items if the index is empty, which builds a better tree
+    /// than inserting them one at a time.
+    fn insert(&mut self, items: Vec<(P2<S>, T)>) {
+        if self.tree.size() == 0 {
+            self.tree = RTree::bulk_load(items.into_iter().map(RTreeItem).collect());
+        } else {
+            for item in items {
+                self.push(item);
+            }
+        }
+    }
+
+    fn push(&mut self, item: (P2<S>, T)) {
+        self.tree.insert(RTreeItem(item));
+    }
+
+    fn query_rect(&self, rect: &Rect<S>) -> Vec<&(P2<S>, T)> {
+        let envelope = AABB::from(rect);
+        self.tree
+            .locate_in_envelope(&envelope)
+            .map(|item| &item.0)
+            .filter(|(point, _)| rect.contains(point))
+            .collect()
+    }
+
+    /// Envelopes of the parent nodes of the tree, enlarged to include their
+    /// upper edges. An empty tree has no regions.
+    fn regions(&self) -> Vec<(Rect<S>, usize)> {
+        let mut regions = Vec::new();
+        let mut stack = vec![(self.tree.root(), 0)];
+        while let Some((node, depth)) = stack.pop() {
+            if node.children().is_empty() {
+                continue;
+            }
+            let envelope = node.envelope();
+            if let Some(rect) = Rect::bounding(&[envelope.lower(), envelope.upper()]) {
+                regions.push((rect, depth));
+            }
+            for child in node.children().iter().rev() {
+                if let RTreeNode::Parent(parent) = child {
+                    stack.push((parent, depth + 1));
+                }
+            }
+        }
+        regions
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use accel2d::reference::Reference;
+    use accel2d::rtree::{RTreeIndex, RTreeItem};
+    use accel2d::Accel2D;
+    use geom::p2::P2;
+    use geom::rect::Rect;
+    use quickcheck_macros::quickcheck;
+    use rstar::{RTree, AABB};
+
+    fn sorted_items<S>(query: Vec<&(P2<S>, usize)>) -> Vec<usize> {
+        let mut items = query.iter().map(|(_, item)| *item).collect::<Vec<usize>>();
+        items.sort();
+        items
+    }
+
+    #[test]
+    fn rects_in_rtree() {
+        let tree = RTree::bulk_load(vec![
+            Rect::new(0, 0, 2, 2).unwrap(),
+            Rect::new(5, 5, 1, 3).unwrap(),
+        ]);
+        let found = tree
+            .locate_in_envelope_intersecting(&AABB::from_corners(P2::new(1, 1), P2::new(3, 3)))
+            .collect::<Vec<_>>();
+        assert_eq!(found, vec![&Rect::new(0, 0, 2, 2).unwrap()]);
+    }
+
+    #[test]
+    fn upper_edges_excluded() {
+        let items = (0..400)
+            .map(|i| (P2::new(i as i32 % 20, i as i32 / 20), i))
+            .collect::<Vec<(P2<i32>, usize)>>();
+        let index = RTreeIndex::new_from_vec(items);
+        let query = index.query_rect(&Rect::new(3, 4, 2, 2).unwrap());
+        assert_eq!(sorted_items(query), vec![83, 84, 103, 104]);
+    }
+
+    #[test]
+    fn wraps_existing_tree() {
+        let tree = RTree::bulk_load(vec![RTreeItem((P2::new(1.0, 1.0), 0))]);
+        let mut index = RTreeIndex::from(tree);
+        index.push((P2::new(2.0, 2.0), 1));
+        assert_eq!(index.len(), 2);
+        let query = index.query_rect(&Rect::new(0.0, 0.0, 1.5, 1.5).unwrap());
+        assert_eq!(sorted_items(query), vec![0]);
+    }
+
+    #[test]
+    fn regions_cover_items() {
+        assert!(RTreeIndex::<f64, usize>::new().regions().is_empty());
+        let items = (0..1000)
+            .map(|i| (P2::new(f64::from(i % 40), f64::from(i / 40)), i))
+            .collect::<Vec<_>>();
+        let index = RTreeIndex::new_from_vec(items.clone());
+        let regions = index.regions();
+        assert_eq!(regions[0].1, 0);
+        assert!(regions.iter().any(|(_, depth)| *depth > 0));
+        for (point, _) in items.iter() {
+            assert!(regions[0].0.contains(point));
+        }
+    }
+
+    /// Property test: the r-tree returns the same items as the reference
+    /// implementation, whether it is bulk loaded or built incrementally.
+    #[quickcheck]
+    fn f64_query_rect_matches_reference(points: Vec<P2<f64>>, rect: Rect<f64>) {
+        let items = points
+            .into_iter()
+            .filter(|point| point.x.is_finite() && point.y.is_finite())
+            .enumerate()
+            .map(|(i, point)| (point, i))
+            .collect::<Vec<(P2<f64>, usize)>>();
+        let reference = Reference::new_from_vec(items.clone());
+        let bulk = RTreeIndex::new_from_vec(items.clone());
+        let mut incremental = RTreeIndex::new();
+        for item in items {
+            incremental.push(item);
+        }
+
+        let expected = sorted_items(reference.query_rect(&rect));
+        assert_eq!(sorted_items(bulk.query_rect(&rect)), expected);
+        assert_eq!(sorted_items(incremental.query_rect(&rect)), expected);
+    }
+}
@@ -0,0 +1,234 @@
+//! Spatial indexes shared between threads.
+//!
+//! The indexes in `accel2d` are plain data: queries take `&self` and
+//! additions take `&mut self`, so none of them lock. `SharedIndex` puts one
+//! behind a read-write lock, so that any number of threads can query it
+//! while writes are applied whole, one batch at a time.
+
+use accel2d::Accel2D;
+use geom::p2::P2;
+use geom::rect::Rect;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{PoisonError, RwLock, RwLockReadGuard};
+
+/// Index shared between threads, with concurrent queries and serialized
+/// batched writes.
+///
+/// Each write (`insert` or `update`) holds the lock for writing while it
+/// runs, and then advances the index's epoch by one, so a reader sees
+/// either none or all of a batch, and the epoch it reads with names the
+/// batches it sees. Readers never block one another, but wait for a write
+/// in progress, and writers wait for the readers before them: add items in
+/// large batches rather than one at a time, which also lets the static
+/// indexes rebuild once per batch.
+///
+/// A `SharedIndex<A>` is `Send` when `A` is, and `Sync` when `A` is `Send`
+/// and `Sync`. The backends are `Send` and `Sync` exactly when their
+/// scalars and items are:
+///
+/// - `QuadTree` shares the nodes of its snapshots through `Arc`s, and only
+///   copies them from behind a `&mut`;
+/// - `RangeTree`, `PrioritySearchTree` and `Bvh` own their items in
+///   vectors;
+/// - `RTreeIndex` (with the `rstar` feature) owns an rstar `RTree`.
+///
+/// ```
+/// # use starquad::accel2d::quadtree::QuadTree;
+/// # use starquad::accel2d::shared::SharedIndex;
+/// # use starquad::accel2d::Accel2D;
+/// # use starquad::geom::{p2::P2, rect::Rect};
+/// # use std::sync::Arc;
+/// # use std::thread;
+/// let index = Arc::new(SharedIndex::new(QuadTree::new()));
+/// let writer = {
+///     let index = index.clone();
+///     thread::spawn(move || {
+///         for batch in 0..10 {
+///             index.insert((0..100).map(|i| (P2::new(i, batch), i)).collect());
+///         }
+///     })
+/// };
+/// let everything = Rect::new(0, 0, 100, 10).unwrap();
+/// // a query sees whole batches, and the epoch counts them
+/// let index = index.read();
+/// assert_eq!(index.count_rect(&everything) as u64, 100 * index.epoch());
+/// # drop(index);
+/// # writer.join().unwrap();
+/// ```
+///
+/// A write which panics may leave part of its batch in the index, which
+/// later reads see, but is not counted in the epoch.
+#[derive(Debug, Default)]
+pub struct SharedIndex<A> {
+    index: RwLock<A>,
+    epoch: AtomicU64,
+}
+
+/// Index read at an epoch, which holds the lock for reading until it is
+/// dropped.
+#[derive(Debug)]
+pub struct ReadGuard<'a, A> {
+    index: RwLockReadGuard<'a, A>,
+    epoch: u64,
+}
+
+impl<'a, A> ReadGuard<'a, A> {
+    /// Number of writes seen by this reader.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+}
+
+impl<'a, A> Deref for ReadGuard<'a, A> {
+    type Target = A;
+
+    fn deref(&self) -> &A {
+        &self.index
+    }
+}
+
+impl<A> SharedIndex<A> {
+    /// Share an index, at epoch 0.
+    pub fn new(index: A) -> Self {
+        SharedIndex {
+            index: RwLock::new(index),
+            epoch: AtomicU64::new(0),
+        }
+    }
+
+    /// Number of writes applied so far.
+    pub fn epoch(&self) -> u64 {
+        self.epoch.load(Ordering::Acquire)
+    }
+
+    /// Read the index, waiting for any write in progress.
+    pub fn read(&self) -> ReadGuard<'_, A> {
+        let index = self.index.read().unwrap_or_else(PoisonError::into_inner);
+        // the epoch only changes while the lock is held for writing
+        let epoch = self.epoch.load(Ordering::Acquire);
+        ReadGuard { index, epoch }
+    }
+
+    /// Change the index in a single write, and return the epoch which
+    /// includes it.
+    pub fn update<F, R>(&self, f: F) -> (R, u64)
+    where
+        F: FnOnce(&mut A) -> R,
+    {
+        let mut index = self.index.write().unwrap_or_else(PoisonError::into_inner);
+        let result = f(&mut index);
+        let epoch = self.epoch.fetch_add(1, Ordering::AcqRel) + 1;
+        (result, epoch)
+    }
+
+    /// The index, once no other thread shares it.
+    pub fn into_inner(self) -> A {
+        self.index
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<A> SharedIndex<A>
+where
+    A: Accel2D,
+{
+    /// Add a batch of items in a single write, and return the epoch which
+    /// includes them.
+    pub fn insert(&self, items: Vec<(P2<A::Scalar>, A::Item)>) -> u64 {
+        self.update(|index| index.insert(items)).1
+    }
+
+    /// Copies of the items in a rectangle, found without holding the lock
+    /// beyond the query.
+    pub fn query_rect(&self, rect: &Rect<A::Scalar>) -> Vec<(P2<A::Scalar>, A::Item)>
+    where
+        A::Scalar: Clone,
+        A::Item: Clone,
+    {
+        self.read().query_rect(rect).into_iter().cloned().collect()
+    }
+
+    /// Number of items in a rectangle.
+    pub fn count_rect(&self, rect: &Rect<A::Scalar>) -> usize {
+        self.read().count_rect(rect)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use accel2d::bvh::Bvh;
+    use accel2d::pst::PrioritySearchTree;
+    use accel2d::quadtree::QuadTree;
+    use accel2d::rangetree::RangeTree;
+    use accel2d::shared::SharedIndex;
+    use accel2d::Accel2D;
+    use geom::p2::P2;
+    use geom::rect::Rect;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn backends_are_send_and_sync() {
+        send_sync::<SharedIndex<QuadTree<f64, u64>>>();
+        send_sync::<SharedIndex<RangeTree<f64, u64>>>();
+        send_sync::<SharedIndex<PrioritySearchTree<f64, u64>>>();
+        send_sync::<SharedIndex<Bvh<f64, u64>>>();
+        #[cfg(feature = "rstar")]
+        send_sync::<SharedIndex<::accel2d::rtree::RTreeIndex<f64, u64>>>();
+    }
+
+    #[test]
+    fn readers_see_whole_batches() {
+        const BATCH: u64 = 50;
+        const BATCHES: u64 = 40;
+        let index = Arc::new(SharedIndex::new(<RangeTree<u64, u64> as Accel2D>::new()));
+        let everything = Rect::new(0, 0, BATCH, BATCHES).unwrap();
+        let readers = (0..4)
+            .map(|_| {
+                let index = index.clone();
+                let everything = everything.clone();
+                thread::spawn(move || {
+                    let mut last = 0;
+                    while last < BATCHES {
+                        let read = index.read();
+                        assert!(read.epoch() >= last);
+                        assert_eq!(read.count_rect(&everything) as u64, read.epoch() * BATCH);
+                        last = read.epoch();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for batch in 0..BATCHES {
+            let items = (0..BATCH).map(|i| (P2::new(i, batch), i)).collect();
+            assert_eq!(index.insert(items), batch + 1);
+        }
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        assert_eq!(index.epoch(), BATCHES);
+        assert_eq!(index.query_rect(&Rect::new(0, 3, 2, 1).unwrap()).len(), 2);
+        let (len, epoch) = index.update(|tree| tree.len());
+        assert_eq!((len, epoch), ((BATCH * BATCHES) as usize, BATCHES + 1));
+    }
+
+    #[test]
+    fn recovers_from_panicking_write() {
+        let index = Arc::new(SharedIndex::new(QuadTree::<f64, u64>::new()));
+        let writer = index.clone();
+        let write = thread::spawn(move || {
+            writer.update(|tree| {
+                tree.push((P2::new(1.0, 1.0), 1));
+                panic!("in a write")
+            })
+        });
+        assert!(write.join().is_err());
+        let read = index.read();
+        assert_eq!((read.len(), read.epoch()), (1, 0));
+        drop(read);
+        assert_eq!(index.insert(vec![(P2::new(2.0, 2.0), 2)]), 1);
+    }
+}
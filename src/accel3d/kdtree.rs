@@ -0,0 +1,586 @@
+#[cfg(feature = "std")]
+use accel3d::metric::Metric;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::ops::Range;
+use geom::p3::P3;
+use mst::{shorter, Boruvka, Component};
+use num::Num;
+
+/// Number of items which [KdTree::push](KdTree::push) buffers before it
+/// merges them into the tree.
+pub const KD_BUFFER: usize = 32;
+
+/// Mostly static k-d tree of items at points in space.
+///
+/// The items are stored in a single vector, arranged so that the median (on
+/// an axis which cycles through `x`, `y` and `z` with depth) of every range
+/// is at its middle, with the smaller items before it and the larger ones
+/// after. The tree needs no other storage besides the lengths of these
+/// ranges.
+///
+/// Items can be added by [push](KdTree::push), by the logarithmic method:
+/// they are kept in a buffer of up to [KD_BUFFER](KD_BUFFER) items, which
+/// queries scan, and a full buffer is merged with the smaller ranges before
+/// it into one, so there are never more than about `log2(n)` ranges and
+/// each item is rebuilt only `O(log n)` times.
+///
+/// ```
+/// # use starquad::accel3d::kdtree::KdTree;
+/// # use starquad::geom::p3::P3;
+/// let tree = KdTree::new((0..10).map(|i| (P3::new(i, 0, 0), i)).collect());
+/// let found = tree.query_box(&P3::new(2, -1, -1), &P3::new(4, 1, 1));
+/// let mut values = found.iter().map(|(_, i)| *i).collect::<Vec<_>>();
+/// values.sort();
+/// assert_eq!(values, vec![2, 3, 4]);
+/// assert_eq!(tree.nearest_neighbor(&P3::new(7, 1, 0)).unwrap().0 .1, 7);
+/// ```
+///
+/// ```
+/// # use starquad::accel3d::kdtree::KdTree;
+/// # use starquad::geom::p3::P3;
+/// let mut tree = KdTree::new(Vec::new());
+/// for i in 0..100 {
+///     tree.push((P3::new(i, i, 0), i));
+/// }
+/// assert_eq!(tree.query_radius(&P3::new(50, 50, 0), 2).len(), 3);
+/// ```
+#[derive(Clone, Debug)]
+pub struct KdTree<S, T> {
+    items: Vec<(P3<S>, T)>,
+    /// Lengths of the ranges built into trees, from the start of `items`,
+    /// longest first; the rest of the items are the buffer.
+    runs: Vec<usize>,
+}
+
+fn compare<S: PartialOrd + Copy>(a: &P3<S>, b: &P3<S>, axis: usize) -> Ordering {
+    a.axis(axis)
+        .partial_cmp(&b.axis(axis))
+        .unwrap_or(Ordering::Equal)
+}
+
+impl<S, T> KdTree<S, T>
+where
+    S: Num + PartialOrd + Copy,
+{
+    pub fn new(mut items: Vec<(P3<S>, T)>) -> Self {
+        build(&mut items, 0);
+        let runs = if items.is_empty() {
+            Vec::new()
+        } else {
+            vec![items.len()]
+        };
+        KdTree { items, runs }
+    }
+
+    /// Add an item, merging the buffer into the tree once it is full.
+    pub fn push(&mut self, item: (P3<S>, T)) {
+        self.items.push(item);
+        let mut merged = self.items.len() - self.runs.iter().sum::<usize>();
+        if merged < KD_BUFFER {
+            return;
+        }
+        while let Some(&run) = self.runs.last() {
+            if run > merged {
+                break;
+            }
+            merged += run;
+            self.runs.pop();
+        }
+        let start = self.items.len() - merged;
+        build(&mut self.items[start..], 0);
+        self.runs.push(merged);
+    }
+
+    /// Ranges of the items which are trees: each built range, then each
+    /// buffered item on its own.
+    fn tree_ranges(&self) -> impl Iterator<Item = Range<usize>> + '_ {
+        let mut start = 0;
+        let built = self.runs.iter().map(move |run| {
+            start += run;
+            start - run..start
+        });
+        let buffered = self.runs.iter().sum::<usize>();
+        built.chain((buffered..self.items.len()).map(|place| place..place + 1))
+    }
+
+    /// The trees which hold the items.
+    fn trees(&self) -> impl Iterator<Item = &[(P3<S>, T)]> {
+        self.tree_ranges().map(move |range| &self.items[range])
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Items in the tree, in its internal order.
+    pub fn items(&self) -> &[(P3<S>, T)] {
+        &self.items
+    }
+
+    /// Items in the box from `min` to `max`, including its faces.
+    pub fn query_box(&self, min: &P3<S>, max: &P3<S>) -> Vec<&(P3<S>, T)> {
+        let mut found = Vec::new();
+        for tree in self.trees() {
+            visit(tree, 0, &mut |items, axis| {
+                let mid = items.len() / 2;
+                let (point, _) = &items[mid];
+                let inside =
+                    (0..3).all(|a| min.axis(a) <= point.axis(a) && point.axis(a) <= max.axis(a));
+                if inside {
+                    found.push(&items[mid]);
+                }
+                (
+                    min.axis(axis) <= point.axis(axis),
+                    point.axis(axis) <= max.axis(axis),
+                )
+            });
+        }
+        found
+    }
+
+    /// Items within `radius` of a point, including those at exactly
+    /// `radius`.
+    pub fn query_radius(&self, center: &P3<S>, radius: S) -> Vec<&(P3<S>, T)> {
+        let radius_2 = radius * radius;
+        let mut found = Vec::new();
+        for tree in self.trees() {
+            visit(tree, 0, &mut |items, axis| {
+                let mid = items.len() / 2;
+                let (point, _) = &items[mid];
+                if point.distance_2(center) <= radius_2 {
+                    found.push(&items[mid]);
+                }
+                let (c, p) = (center.axis(axis), point.axis(axis));
+                let below = c <= p || (c - p) * (c - p) <= radius_2;
+                let above = p <= c || (p - c) * (p - c) <= radius_2;
+                (below, above)
+            });
+        }
+        found
+    }
+
+    /// Nearest item to a point, and the square of its distance.
+    pub fn nearest_neighbor(&self, point: &P3<S>) -> Option<(&(P3<S>, T), S)> {
+        let mut best: Option<(&(P3<S>, T), S)> = None;
+        for tree in self.trees() {
+            nearest(tree, 0, point, &mut best);
+        }
+        best
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> KdTree<f64, T> {
+    /// The `k` items nearest to a point by a metric, nearest first, with
+    /// their distances.
+    ///
+    /// ```
+    /// # use starquad::accel3d::kdtree::KdTree;
+    /// # use starquad::accel3d::metric::GreatCircle;
+    /// # use starquad::sky::position::SkyPosition;
+    /// let stars = [(10.0, 20.0), (10.5, 20.0), (190.0, -20.0)];
+    /// let tree = KdTree::new(
+    ///     stars.iter().map(|&(ra, dec)| (SkyPosition::new(ra, dec).unit_vector(), ra)).collect(),
+    /// );
+    /// let near = tree.knn_by(&SkyPosition::new(10.1, 20.0).unit_vector(), 2, &GreatCircle);
+    /// assert_eq!(near.iter().map(|(item, _)| item.1).collect::<Vec<_>>(), vec![10.0, 10.5]);
+    /// assert!((near[0].1.to_degrees() - 0.1 * 20f64.to_radians().cos()).abs() < 1e-6);
+    /// ```
+    pub fn knn_by<M: Metric<T>>(
+        &self,
+        point: &P3<f64>,
+        k: usize,
+        metric: &M,
+    ) -> Vec<(&(P3<f64>, T), f64)> {
+        let mut best = Vec::with_capacity(k + 1);
+        if k > 0 {
+            for tree in self.trees() {
+                knn(tree, 0, point, k, metric, &mut best);
+            }
+        }
+        best
+    }
+
+    /// Nearest item to a point by a metric, and its distance.
+    pub fn nearest_by<M: Metric<T>>(
+        &self,
+        point: &P3<f64>,
+        metric: &M,
+    ) -> Option<(&(P3<f64>, T), f64)> {
+        self.knn_by(point, 1, metric).pop()
+    }
+
+    /// Items within a distance of a point by a metric, including those at
+    /// exactly that distance.
+    pub fn query_radius_by<M: Metric<T>>(
+        &self,
+        center: &P3<f64>,
+        radius: f64,
+        metric: &M,
+    ) -> Vec<&(P3<f64>, T)> {
+        let mut found = Vec::new();
+        for tree in self.trees() {
+            visit(tree, 0, &mut |items, axis| {
+                let mid = items.len() / 2;
+                if metric.distance(center, &items[mid]) <= radius {
+                    found.push(&items[mid]);
+                }
+                let (c, p) = (center.axis(axis), items[mid].0.axis(axis));
+                let below = c <= p || metric.bound(c - p) <= radius;
+                let above = p <= c || metric.bound(p - c) <= radius;
+                (below, above)
+            });
+        }
+        found
+    }
+}
+
+/// Borůvka's algorithm over a k-d tree, whose places are in the order of
+/// [items](KdTree::items). The search for each item's nearest item in
+/// another component skips subtrees which only hold items of its own
+/// component.
+impl<S, T> Boruvka for KdTree<S, T>
+where
+    S: Num + PartialOrd + Copy,
+{
+    type Length = S;
+
+    fn places(&self) -> usize {
+        self.len()
+    }
+
+    fn shortest_edges(&self, component: &[usize]) -> Vec<Option<(S, usize, usize)>> {
+        let mut labels = vec![Component::Empty; self.items.len()];
+        for range in self.tree_ranges() {
+            label_components(range, component, &mut labels);
+        }
+        let mut nearest = vec![None; component.len()];
+        for place in 0..self.items.len() {
+            for range in self.tree_ranges() {
+                let best = &mut nearest[component[place]];
+                nearest_outside(&self.items, range, 0, place, component, &labels, best);
+            }
+        }
+        nearest
+    }
+}
+
+/// Label the subtree of every item of the tree in `range` (at the item's
+/// place) with the components of the items in it.
+fn label_components(
+    range: Range<usize>,
+    component: &[usize],
+    labels: &mut [Component],
+) -> Component {
+    if range.is_empty() {
+        return Component::Empty;
+    }
+    let mid = range.start + range.len() / 2;
+    let label = Component::One(component[mid])
+        .join(label_components(range.start..mid, component, labels))
+        .join(label_components(mid + 1..range.end, component, labels));
+    labels[mid] = label;
+    label
+}
+
+/// Search the tree in `range` for a shorter edge than `best` from `place` to
+/// an item of another component.
+fn nearest_outside<S, T>(
+    items: &[(P3<S>, T)],
+    range: Range<usize>,
+    axis: usize,
+    place: usize,
+    component: &[usize],
+    labels: &[Component],
+    best: &mut Option<(S, usize, usize)>,
+) where
+    S: Num + PartialOrd + Copy,
+{
+    if range.is_empty() {
+        return;
+    }
+    let mid = range.start + range.len() / 2;
+    if labels[mid] == Component::One(component[place]) {
+        return;
+    }
+    let (point, item) = (&items[place].0, &items[mid].0);
+    if component[mid] != component[place] {
+        let distance_2 = item.distance_2(point);
+        if shorter(distance_2, place, mid, best) {
+            *best = Some((distance_2, place, mid));
+        }
+    }
+    let (p, m) = (point.axis(axis), item.axis(axis));
+    let (below, above) = (range.start..mid, mid + 1..range.end);
+    let (near, far) = if p < m {
+        (below, above)
+    } else {
+        (above, below)
+    };
+    let next = (axis + 1) % 3;
+    nearest_outside(items, near, next, place, component, labels, best);
+    // a far item as near as the best may still win the tie
+    let gap = if p < m { m - p } else { p - m };
+    if best.is_none_or(|(d, _, _)| gap * gap <= d) {
+        nearest_outside(items, far, next, place, component, labels, best);
+    }
+}
+
+/// Visit the middle item of each range which `f` leads to. `f` is given
+/// a range and its axis, and returns whether to visit the items below
+/// and above the middle one.
+fn visit<'a, S, T, F>(items: &'a [(P3<S>, T)], axis: usize, f: &mut F)
+where
+    F: FnMut(&'a [(P3<S>, T)], usize) -> (bool, bool),
+{
+    if items.is_empty() {
+        return;
+    }
+    let mid = items.len() / 2;
+    let (below, above) = f(items, axis);
+    if below {
+        visit(&items[..mid], (axis + 1) % 3, f);
+    }
+    if above {
+        visit(&items[mid + 1..], (axis + 1) % 3, f);
+    }
+}
+
+fn build<S: PartialOrd + Copy, T>(items: &mut [(P3<S>, T)], axis: usize) {
+    if items.len() <= 1 {
+        return;
+    }
+    let mid = items.len() / 2;
+    items.select_nth_unstable_by(mid, |a, b| compare(&a.0, &b.0, axis));
+    let (below, rest) = items.split_at_mut(mid);
+    build(below, (axis + 1) % 3);
+    build(&mut rest[1..], (axis + 1) % 3);
+}
+
+fn nearest<'a, S, T>(
+    items: &'a [(P3<S>, T)],
+    axis: usize,
+    point: &P3<S>,
+    best: &mut Option<(&'a (P3<S>, T), S)>,
+) where
+    S: Num + PartialOrd + Copy,
+{
+    if items.is_empty() {
+        return;
+    }
+    let mid = items.len() / 2;
+    let item = &items[mid];
+    let distance_2 = item.0.distance_2(point);
+    if best.is_none_or(|(_, d)| distance_2 < d) {
+        *best = Some((item, distance_2));
+    }
+    let (p, m) = (point.axis(axis), item.0.axis(axis));
+    let (near, far) = if p < m {
+        (&items[..mid], &items[mid + 1..])
+    } else {
+        (&items[mid + 1..], &items[..mid])
+    };
+    nearest(near, (axis + 1) % 3, point, best);
+    let gap = if p < m { m - p } else { p - m };
+    if best.is_none_or(|(_, d)| gap * gap < d) {
+        nearest(far, (axis + 1) % 3, point, best);
+    }
+}
+
+/// Search for the `k` nearest items by a metric, keeping those found so far
+/// in `best`, nearest first.
+#[cfg(feature = "std")]
+fn knn<'a, T, M: Metric<T>>(
+    items: &'a [(P3<f64>, T)],
+    axis: usize,
+    point: &P3<f64>,
+    k: usize,
+    metric: &M,
+    best: &mut Vec<(&'a (P3<f64>, T), f64)>,
+) {
+    if items.is_empty() {
+        return;
+    }
+    let mid = items.len() / 2;
+    let item = &items[mid];
+    let distance = metric.distance(point, item);
+    if best.len() < k || best.last().is_some_and(|(_, d)| distance < *d) {
+        let place = best.partition_point(|(_, d)| *d <= distance);
+        best.insert(place, (item, distance));
+        best.truncate(k);
+    }
+    let (p, m) = (point.axis(axis), item.0.axis(axis));
+    let (near, far) = if p < m {
+        (&items[..mid], &items[mid + 1..])
+    } else {
+        (&items[mid + 1..], &items[..mid])
+    };
+    knn(near, (axis + 1) % 3, point, k, metric, best);
+    let bound = metric.bound((p - m).abs());
+    if best.len() < k || best.last().is_some_and(|(_, d)| bound < *d) {
+        knn(far, (axis + 1) % 3, point, k, metric, best);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use accel3d::kdtree::{KdTree, KD_BUFFER};
+    use alloc::vec::Vec;
+    use geom::p3::P3;
+    use mst::Boruvka;
+    use quickcheck_macros::quickcheck;
+
+    fn points(coords: Vec<(i8, i8, i8)>) -> Vec<(P3<i32>, usize)> {
+        coords
+            .into_iter()
+            .enumerate()
+            .map(|(i, (x, y, z))| (P3::new(i32::from(x), i32::from(y), i32::from(z)), i))
+            .collect()
+    }
+
+    /// Property test: box and radius queries find the same items as a
+    /// linear search.
+    #[quickcheck]
+    fn queries_match_linear_search(coords: Vec<(i8, i8, i8)>, corner: (i8, i8, i8), size: u8) {
+        let items = points(coords);
+        let tree = KdTree::new(items.clone());
+        let min = P3::new(
+            i32::from(corner.0),
+            i32::from(corner.1),
+            i32::from(corner.2),
+        );
+        let max = P3::new(min.x + i32::from(size), min.y + 30, min.z + i32::from(size));
+        let sorted = |found: Vec<&(P3<i32>, usize)>| {
+            let mut ids = found.iter().map(|(_, i)| *i).collect::<Vec<_>>();
+            ids.sort_unstable();
+            ids
+        };
+
+        let expected = items
+            .iter()
+            .filter(|(p, _)| {
+                min.x <= p.x
+                    && p.x <= max.x
+                    && min.y <= p.y
+                    && p.y <= max.y
+                    && min.z <= p.z
+                    && p.z <= max.z
+            })
+            .map(|(_, i)| *i)
+            .collect::<Vec<_>>();
+        assert_eq!(sorted(tree.query_box(&min, &max)), expected);
+
+        let radius = i32::from(size);
+        let expected = items
+            .iter()
+            .filter(|(p, _)| p.distance_2(&min) <= radius * radius)
+            .map(|(_, i)| *i)
+            .collect::<Vec<_>>();
+        assert_eq!(sorted(tree.query_radius(&min, radius)), expected);
+
+        let nearest = items.iter().map(|(p, _)| p.distance_2(&min)).min();
+        assert_eq!(tree.nearest_neighbor(&min).map(|(_, d)| d), nearest);
+    }
+
+    /// Property test: a tree built partly by pushes finds the same items as
+    /// one built at once.
+    #[quickcheck]
+    fn pushed_items_match_built_tree(
+        coords: Vec<(i8, i8, i8)>,
+        split: usize,
+        center: (i8, i8, i8),
+    ) {
+        let items = points(coords);
+        let split = split % (items.len() + 1);
+        let mut pushed = KdTree::new(items[..split].to_vec());
+        for item in &items[split..] {
+            pushed.push(*item);
+        }
+        let built = KdTree::new(items.clone());
+        let center = P3::new(
+            i32::from(center.0),
+            i32::from(center.1),
+            i32::from(center.2),
+        );
+        let sorted = |found: Vec<&(P3<i32>, usize)>| {
+            let mut ids = found.iter().map(|(_, i)| *i).collect::<Vec<_>>();
+            ids.sort_unstable();
+            ids
+        };
+
+        assert_eq!(pushed.len(), items.len());
+        assert_eq!(
+            sorted(pushed.query_radius(&center, 50)),
+            sorted(built.query_radius(&center, 50))
+        );
+        let corner = P3::new(center.x + 40, center.y + 40, center.z + 40);
+        assert_eq!(
+            sorted(pushed.query_box(&center, &corner)),
+            sorted(built.query_box(&center, &corner))
+        );
+        assert_eq!(
+            pushed.nearest_neighbor(&center).map(|(_, d)| d),
+            built.nearest_neighbor(&center).map(|(_, d)| d)
+        );
+    }
+
+    /// Property test: the spanning tree of a tree built partly by pushes
+    /// connects every point, and is as short as the one found by Prim's
+    /// algorithm over every pair.
+    #[quickcheck]
+    fn minimum_spanning_tree_matches_prim(coords: Vec<(i8, i8, i8)>, split: usize) {
+        let items = points(coords);
+        let split = split % (items.len() + 1);
+        let mut tree = KdTree::new(items[..split].to_vec());
+        for item in &items[split..] {
+            tree.push(*item);
+        }
+        let points = tree
+            .items()
+            .iter()
+            .map(|(point, _)| *point)
+            .collect::<Vec<_>>();
+        let edges = tree.minimum_spanning_tree();
+        assert_eq!(edges.len(), points.len().saturating_sub(1));
+        for (a, b, distance_2) in edges.iter() {
+            assert_eq!(points[*a].distance_2(&points[*b]), *distance_2);
+        }
+
+        let mut reached = vec![false; points.len()];
+        let mut nearest = vec![i32::MAX; points.len()];
+        let mut expected = 0;
+        if !points.is_empty() {
+            nearest[0] = 0;
+        }
+        for _ in 0..points.len() {
+            let next = (0..points.len())
+                .filter(|&i| !reached[i])
+                .min_by_key(|&i| nearest[i])
+                .unwrap();
+            reached[next] = true;
+            expected += nearest[next];
+            for i in 0..points.len() {
+                nearest[i] = nearest[i].min(points[next].distance_2(&points[i]));
+            }
+        }
+        assert_eq!(edges.iter().map(|edge| edge.2).sum::<i32>(), expected);
+    }
+
+    #[test]
+    fn pushes_keep_few_runs() {
+        let mut tree = KdTree::new(Vec::new());
+        for i in 0..10_000 {
+            tree.push((P3::new(i, -i, i % 7), i));
+            assert!(tree.runs.len() <= 10);
+            assert!(tree.len() - tree.runs.iter().sum::<usize>() < KD_BUFFER);
+        }
+        assert!(tree.runs.windows(2).all(|w| w[0] > w[1]));
+        let found = tree.query_box(&P3::new(100, -200, 0), &P3::new(200, -100, 6));
+        assert_eq!(found.len(), 101);
+    }
+}
@@ -0,0 +1,259 @@
+//! Distances for nearest-neighbour and radius queries of a `KdTree` (see
+//! `KdTree::knn_by`), for matching in the metric suited to the data rather
+//! than by raw coordinate distance.
+//!
+//! Metrics take square roots and inverse sines, so need the `std` feature.
+
+use accel3d::kdtree::KdTree;
+use geom::p3::P3;
+
+/// Distance from a query point to the items of a `KdTree`.
+///
+/// The distance may depend on the item as well as its point, as for
+/// `Mahalanobis`, so long as `bound` holds for every item.
+pub trait Metric<T> {
+    /// Distance from a point to an item at a point.
+    fn distance(&self, query: &P3<f64>, item: &(P3<f64>, T)) -> f64;
+
+    /// Lower bound of the distance from a point to any item whose point is
+    /// at least `gap` from it along an axis. The tree skips the items
+    /// beyond a splitting plane when this exceeds the distance sought.
+    fn bound(&self, gap: f64) -> f64;
+}
+
+/// Straight-line distance.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Euclidean;
+
+impl<T> Metric<T> for Euclidean {
+    fn distance(&self, query: &P3<f64>, item: &(P3<f64>, T)) -> f64 {
+        query.distance_2(&item.0).sqrt()
+    }
+
+    fn bound(&self, gap: f64) -> f64 {
+        gap
+    }
+}
+
+/// Angle, in radians, between points on the unit sphere (see
+/// `SkyPosition::unit_vector`).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GreatCircle;
+
+/// Angle subtended by a chord of the unit sphere.
+fn chord_angle(chord: f64) -> f64 {
+    2.0 * (chord / 2.0).min(1.0).asin()
+}
+
+impl<T> Metric<T> for GreatCircle {
+    fn distance(&self, query: &P3<f64>, item: &(P3<f64>, T)) -> f64 {
+        chord_angle(query.distance_2(&item.0).sqrt())
+    }
+
+    fn bound(&self, gap: f64) -> f64 {
+        // a chord is at least as long as its extent along any axis
+        chord_angle(gap)
+    }
+}
+
+/// Items with the covariance of the uncertainty of their positions.
+pub trait Covariance {
+    fn covariance(&self) -> [[f64; 3]; 3];
+}
+
+fn trace(m: &[[f64; 3]; 3]) -> f64 {
+    m[0][0] + m[1][1] + m[2][2]
+}
+
+/// Number of standard deviations between a query point and an item, given
+/// the covariances of both (the Mahalanobis distance of their difference).
+///
+/// Covariances must be positive definite; an item whose covariance, summed
+/// with the query's, cannot be inverted is infinitely far away.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mahalanobis {
+    covariance: [[f64; 3]; 3],
+    max_variance: f64,
+}
+
+impl Mahalanobis {
+    /// Distances from a query point with a covariance to the items of a
+    /// tree. Make a new one after pushing items to the tree, as they may
+    /// have larger covariances than the bound it keeps.
+    pub fn new<T: Covariance>(covariance: [[f64; 3]; 3], tree: &KdTree<f64, T>) -> Self {
+        // the trace of a covariance bounds its variance in any direction
+        let largest = tree
+            .items()
+            .iter()
+            .map(|(_, item)| trace(&item.covariance()))
+            .fold(0.0, f64::max);
+        Mahalanobis {
+            covariance,
+            max_variance: trace(&covariance) + largest,
+        }
+    }
+}
+
+/// Inverse of a symmetric 3×3 matrix, if it is not singular.
+fn inverse(m: &[[f64; 3]; 3]) -> Option<[[f64; 3]; 3]> {
+    let cofactor = |r: usize, c: usize| {
+        let (r0, r1) = ((r + 1) % 3, (r + 2) % 3);
+        let (c0, c1) = ((c + 1) % 3, (c + 2) % 3);
+        m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+    };
+    let det = (0..3).map(|c| m[0][c] * cofactor(0, c)).sum::<f64>();
+    if det == 0.0 || !det.is_finite() {
+        return None;
+    }
+    let mut inverse = [[0.0; 3]; 3];
+    for (r, row) in inverse.iter_mut().enumerate() {
+        for (c, value) in row.iter_mut().enumerate() {
+            *value = cofactor(c, r) / det;
+        }
+    }
+    Some(inverse)
+}
+
+impl<T: Covariance> Metric<T> for Mahalanobis {
+    fn distance(&self, query: &P3<f64>, item: &(P3<f64>, T)) -> f64 {
+        let mut sum = item.1.covariance();
+        for (row, query_row) in sum.iter_mut().zip(&self.covariance) {
+            for (value, q) in row.iter_mut().zip(query_row) {
+                *value += q;
+            }
+        }
+        let inverse = match inverse(&sum) {
+            Some(inverse) => inverse,
+            None => return f64::INFINITY,
+        };
+        let d = [item.0.x - query.x, item.0.y - query.y, item.0.z - query.z];
+        let mut d_2 = 0.0;
+        for (r, row) in inverse.iter().enumerate() {
+            for (c, value) in row.iter().enumerate() {
+                d_2 += d[r] * value * d[c];
+            }
+        }
+        d_2.max(0.0).sqrt()
+    }
+
+    fn bound(&self, gap: f64) -> f64 {
+        gap / self.max_variance.sqrt()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use accel3d::kdtree::KdTree;
+    use accel3d::metric::{Covariance, Euclidean, GreatCircle, Mahalanobis, Metric};
+    use geom::p3::P3;
+    use quickcheck_macros::quickcheck;
+    use sky::position::SkyPosition;
+
+    fn points(coords: Vec<(i8, i8, i8)>) -> Vec<(P3<f64>, usize)> {
+        coords
+            .into_iter()
+            .enumerate()
+            .map(|(i, (x, y, z))| (P3::new(f64::from(x), f64::from(y), f64::from(z)), i))
+            .collect()
+    }
+
+    /// Property test: k-nearest and radius queries by a metric find the
+    /// same distances as a linear search.
+    #[quickcheck]
+    fn euclidean_matches_linear_search(coords: Vec<(i8, i8, i8)>, center: (i8, i8, i8), k: u8) {
+        let items = points(coords);
+        let tree = KdTree::new(items.clone());
+        let center = P3::new(
+            f64::from(center.0),
+            f64::from(center.1),
+            f64::from(center.2),
+        );
+        let mut distances = items
+            .iter()
+            .map(|item| Euclidean.distance(&center, item))
+            .collect::<Vec<_>>();
+        distances.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let k = usize::from(k % 16);
+        let found = tree.knn_by(&center, k, &Euclidean);
+        let found = found.iter().map(|(_, d)| *d).collect::<Vec<_>>();
+        assert_eq!(found, distances[..k.min(distances.len())]);
+        assert_eq!(
+            tree.nearest_by(&center, &Euclidean).map(|(_, d)| d),
+            distances.first().copied()
+        );
+
+        let radius = 40.0;
+        let within = distances.iter().filter(|d| **d <= radius).count();
+        assert_eq!(
+            tree.query_radius_by(&center, radius, &Euclidean).len(),
+            within
+        );
+    }
+
+    #[test]
+    fn great_circle() {
+        let stars = (0..200)
+            .map(|i| {
+                SkyPosition::new(
+                    f64::from(i) * 7.3 % 360.0,
+                    f64::from(i) * 1.7 % 180.0 - 90.0,
+                )
+            })
+            .collect::<Vec<_>>();
+        let tree = KdTree::new(stars.iter().map(|s| (s.unit_vector(), *s)).collect());
+        let target = SkyPosition::new(100.0, -30.0);
+        let mut separations = stars
+            .iter()
+            .map(|s| s.separation(&target))
+            .collect::<Vec<_>>();
+        separations.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let found = tree.knn_by(&target.unit_vector(), 5, &GreatCircle);
+        for ((item, angle), separation) in found.iter().zip(&separations) {
+            assert!((angle.to_degrees() - separation).abs() < 1e-9);
+            assert!((item.1.separation(&target) - separation).abs() < 1e-9);
+        }
+
+        let within = separations.iter().filter(|s| **s <= 20.0).count();
+        let found = tree.query_radius_by(&target.unit_vector(), 20f64.to_radians(), &GreatCircle);
+        assert_eq!(found.len(), within);
+    }
+
+    struct Uncertain([[f64; 3]; 3]);
+
+    impl Covariance for Uncertain {
+        fn covariance(&self) -> [[f64; 3]; 3] {
+            self.0
+        }
+    }
+
+    #[test]
+    fn mahalanobis() {
+        let round = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+        // uncertain along x
+        let long = [[100.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+        let tree = KdTree::new(vec![
+            (P3::new(0.0, 3.0, 0.0), Uncertain(round)),
+            (P3::new(5.0, 0.0, 0.0), Uncertain(long)),
+            (P3::new(0.0, 0.0, 0.0), Uncertain([[0.0; 3]; 3])),
+        ]);
+        let origin = P3::new(0.0, 0.0, 0.0);
+        // the elongated item is further away, but more likely the match
+        let metric = Mahalanobis::new(round, &tree);
+        let found = tree.knn_by(&origin, 3, &metric);
+        assert_eq!(found[0].0 .0, P3::new(0.0, 0.0, 0.0));
+        assert_eq!(found[1].0 .0, P3::new(5.0, 0.0, 0.0));
+        assert!((found[1].1 - 5.0 / 101f64.sqrt()).abs() < 1e-12);
+        assert!((found[2].1 - 3.0 / 2f64.sqrt()).abs() < 1e-12);
+        assert_eq!(
+            tree.knn_by(&origin, 2, &Euclidean)[1].0 .0,
+            P3::new(0.0, 3.0, 0.0)
+        );
+
+        // without the query's covariance, the item at the origin is singular
+        let metric = Mahalanobis::new([[0.0; 3]; 3], &tree);
+        let found = tree.knn_by(&origin, 3, &metric);
+        assert_eq!(found[2].1, f64::INFINITY);
+    }
+}
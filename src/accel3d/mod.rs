@@ -0,0 +1,6 @@
+//! Spatial indexes of items at points in space, such as stars at their
+//! Galactic Cartesian positions.
+
+pub mod kdtree;
+#[cfg(feature = "std")]
+pub mod metric;
@@ -0,0 +1,225 @@
+//! `quickcheck` generators for starquad types, so that code using them can
+//! be property-tested downstream without writing its own.
+//!
+//! Points, intervals and rectangles take their coordinates from the
+//! generators of their scalars. Sky positions, regions and Gaia records are
+//! generated on the sky: right ascensions in `[0, 360)` and declinations in
+//! `[-90, 90]` degrees.
+
+#[cfg(feature = "fs")]
+use csv::StringRecord;
+#[cfg(feature = "fs")]
+use gaia::record::GaiaRecord;
+#[cfg(feature = "fs")]
+use gaia::schema::{ColumnType, COLUMNS};
+use geom::interval::{Interval, IntervalDomain};
+use geom::p2::P2;
+use geom::rect::Rect;
+use quickcheck::{Arbitrary, Gen};
+#[cfg(feature = "std")]
+use sky::position::SkyPosition;
+#[cfg(feature = "std")]
+use sky::region::Region;
+
+impl<S> Arbitrary for P2<S>
+where
+    S: Arbitrary,
+{
+    fn arbitrary<G>(g: &mut G) -> Self
+    where
+        G: Gen,
+    {
+        let x = S::arbitrary(g);
+        let y = S::arbitrary(g);
+        P2::new(x, y)
+    }
+}
+
+macro_rules! create_arbitrary_int_interval {
+    ($t:ty) => {
+        impl Arbitrary for Interval<$t> {
+            fn arbitrary<G>(g: &mut G) -> Self
+            where
+                G: Gen,
+            {
+                let mut start: $t;
+                let mut end: $t;
+                let diameter: $t;
+                loop {
+                    start = <$t>::arbitrary(g);
+                    end = <$t>::arbitrary(g);
+                    let opt_diameter = end
+                        .checked_sub(start)
+                        .and_then(|pdiam| pdiam.checked_add(1));
+                    if let Some(d) = opt_diameter {
+                        diameter = d;
+                        break;
+                    }
+                }
+                Interval::new(start, diameter).expect("Arbitrary int interval")
+            }
+        }
+    };
+}
+
+macro_rules! create_arbitrary_float_interval {
+    ($t:ty) => {
+        impl Arbitrary for Interval<$t> {
+            fn arbitrary<G>(g: &mut G) -> Self
+            where
+                G: Gen,
+            {
+                let start = <$t>::arbitrary(g);
+                let end = <$t>::arbitrary(g);
+                let diameter = end - start;
+                Interval::new(start, diameter).expect("Arbitrary float interval")
+            }
+        }
+    };
+}
+
+create_arbitrary_int_interval!(i8);
+create_arbitrary_int_interval!(i16);
+create_arbitrary_int_interval!(i32);
+create_arbitrary_int_interval!(i64);
+create_arbitrary_int_interval!(i128);
+
+create_arbitrary_int_interval!(u8);
+create_arbitrary_int_interval!(u16);
+create_arbitrary_int_interval!(u32);
+create_arbitrary_int_interval!(u64);
+create_arbitrary_int_interval!(u128);
+
+create_arbitrary_float_interval!(f32);
+create_arbitrary_float_interval!(f64);
+
+impl<S> Arbitrary for Rect<S>
+where
+    Interval<S>: Arbitrary,
+    S: Clone + IntervalDomain,
+{
+    fn arbitrary<G>(g: &mut G) -> Self
+    where
+        G: Gen,
+    {
+        let x_interval = Interval::<S>::arbitrary(g);
+        let y_interval = Interval::<S>::arbitrary(g);
+        Rect::new_from_intervals(x_interval, y_interval)
+    }
+}
+
+/// Value drawn uniformly from `[min, max)`.
+///
+/// The generators of `f64` only reach the size of the `Gen`, so angles are
+/// drawn from its random numbers instead.
+#[cfg(feature = "std")]
+fn uniform<G: Gen>(g: &mut G, min: f64, max: f64) -> f64 {
+    let unit = (g.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+    min + (max - min) * unit
+}
+
+#[cfg(feature = "std")]
+impl Arbitrary for SkyPosition {
+    fn arbitrary<G>(g: &mut G) -> Self
+    where
+        G: Gen,
+    {
+        let ra = uniform(g, 0.0, 360.0);
+        let dec = uniform(g, -90.0, 90.0);
+        SkyPosition::new(ra, dec)
+    }
+}
+
+/// Regions of either kind: rectangles which do not wrap around `ra = 0`,
+/// and cones of up to 10 degrees radius.
+#[cfg(feature = "std")]
+impl Arbitrary for Region {
+    fn arbitrary<G>(g: &mut G) -> Self
+    where
+        G: Gen,
+    {
+        if bool::arbitrary(g) {
+            let position = SkyPosition::arbitrary(g);
+            let width = uniform(g, 0.0, 360.0 - position.ra);
+            let height = uniform(g, 0.0, 90.0 - position.dec);
+            let rect = Rect::new(position.ra, position.dec, width, height);
+            Region::Rect(rect.expect("Arbitrary region"))
+        } else {
+            let radius = uniform(g, 0.0, 10.0);
+            Region::cone(SkyPosition::arbitrary(g), radius)
+        }
+    }
+}
+
+/// Records with a position on the sky, each empty column empty half of the
+/// time, and the other columns taken from the generators of their types.
+/// The text columns hold their usual values.
+#[cfg(feature = "fs")]
+impl Arbitrary for GaiaRecord {
+    fn arbitrary<G>(g: &mut G) -> Self
+    where
+        G: Gen,
+    {
+        let fields = COLUMNS
+            .iter()
+            .map(|column| {
+                if column.nullable && bool::arbitrary(g) {
+                    return String::new();
+                }
+                match column.column_type {
+                    ColumnType::Boolean => bool::arbitrary(g).to_string(),
+                    ColumnType::UnsignedByte => u8::arbitrary(g).to_string(),
+                    ColumnType::Long => u64::arbitrary(g).to_string(),
+                    ColumnType::Double => f64::arbitrary(g).to_string(),
+                    ColumnType::Text => String::new(),
+                }
+            })
+            .collect::<StringRecord>();
+        let mut record = fields
+            .deserialize::<GaiaRecord>(None)
+            .expect("Arbitrary Gaia record");
+        let position = SkyPosition::arbitrary(g);
+        record.ra = position.ra;
+        record.dec = position.dec;
+        record.designation = format!("Gaia DR2 {}", record.source_id);
+        record.ref_epoch = String::from("2015.5");
+        record.phot_variable_flag = String::from("NOT_AVAILABLE");
+        record
+    }
+}
+
+#[cfg(all(test, feature = "fs"))]
+mod test {
+    use gaia::record::GaiaRecord;
+    use gaia::schema::string_record;
+    use quickcheck_macros::quickcheck;
+    use sky::region::Region;
+
+    /// Property test: generated records survive a round trip through their
+    /// CSV fields.
+    #[quickcheck]
+    fn record_csv_round_trip(record: GaiaRecord) {
+        let fields = string_record(&record);
+        assert_eq!(fields.deserialize::<GaiaRecord>(None).unwrap(), record);
+        assert!((0.0..360.0).contains(&record.ra));
+        assert!((-90.0..=90.0).contains(&record.dec));
+    }
+
+    /// Property test: generated regions lie on the sky.
+    #[quickcheck]
+    fn region_on_sky(region: Region) {
+        match region {
+            Region::Rect(rect) => {
+                assert!(*rect.x() >= 0.0 && *rect.x() + *rect.width() <= 360.0);
+                assert!(*rect.y() >= -90.0 && *rect.y() + *rect.height() <= 90.0);
+            }
+            Region::Cone { center, radius } => {
+                assert!((0.0..=10.0).contains(&radius));
+                assert!(Region::cone(center, radius).contains(&center));
+            }
+            Region::Rects(_) | Region::Polygon(_) => {
+                panic!("only rectangles and cones are generated")
+            }
+        }
+    }
+}
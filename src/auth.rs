@@ -0,0 +1,379 @@
+//! Authentication and rate limiting of the query servers.
+//!
+//! The HTTP and gRPC servers check each request with an `Auth`, if they are
+//! given one. Its `Authenticator` names the client presenting the
+//! credentials of the request, or rejects them, and its `RateLimit` then
+//! limits the requests of each client. `ApiKeys` (an `x-api-key` header)
+//! and `BearerTokens` (an `Authorization: Bearer` header) are provided;
+//! other schemes implement `Authenticator`.
+//!
+//! Key and token files hold a client on each line, as its name and secret
+//! separated by whitespace. Empty lines and lines starting with `#` are
+//! ignored.
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::BuildHasher;
+use std::io;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Header (or gRPC metadata key) of an API key.
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Header (or gRPC metadata key) of a bearer token.
+pub const AUTHORIZATION_HEADER: &str = "authorization";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("line {line}: expected a name and a secret")]
+    Syntax { line: usize },
+    #[error("line {line}: secret already given")]
+    DuplicateSecret { line: usize },
+    /// The secret of a client added in code is already given to a client.
+    #[error("secret of {0} already given")]
+    SecretInUse(String),
+    /// A rate limit which is not a positive number.
+    #[error("rate limit must be positive, not {0}")]
+    InvalidRate(f64),
+}
+
+/// Reason a request is refused.
+#[derive(Clone, Copy, Debug, PartialEq, thiserror::Error)]
+pub enum Denied {
+    #[error("missing credentials")]
+    Missing,
+    #[error("invalid credentials")]
+    Invalid,
+    /// The client has made too many requests, and may retry after a time.
+    #[error("rate limit exceeded; retry in {:.1}s", .0.as_secs_f64())]
+    RateLimited(Duration),
+}
+
+/// Credentials of a request: the values of the headers which may hold them.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Credentials<'a> {
+    /// Value of the `Authorization` header.
+    pub authorization: Option<&'a str>,
+    /// Value of the `x-api-key` header.
+    pub api_key: Option<&'a str>,
+}
+
+/// Check of the credentials of requests.
+pub trait Authenticator: Send + Sync {
+    /// Name of the client presenting credentials.
+    fn authenticate(&self, credentials: &Credentials) -> Result<String, Denied>;
+}
+
+/// Names of clients, by their secrets.
+///
+/// Clients are found by a hash of the secret presented, keyed afresh in
+/// each process, and the secret is then compared with theirs in constant
+/// time, so the time taken by a check tells nothing of the secrets held.
+#[derive(Clone, Debug, Default)]
+struct Secrets {
+    key: RandomState,
+    clients: HashMap<u64, Client>,
+}
+
+#[derive(Clone, Debug)]
+struct Client {
+    name: String,
+    secret: String,
+}
+
+/// Whether two byte strings are equal, in a time which depends only on
+/// their lengths.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+impl Secrets {
+    fn parse(text: &str) -> Result<Self, Error> {
+        let mut secrets = Secrets::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let (name, secret) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(name), Some(secret), None) => (name, secret),
+                _ => return Err(Error::Syntax { line: i + 1 }),
+            };
+            if !secrets.insert(name, secret) {
+                return Err(Error::DuplicateSecret { line: i + 1 });
+            }
+        }
+        Ok(secrets)
+    }
+
+    /// Add a client, unless its secret is already given.
+    fn insert(&mut self, name: &str, secret: &str) -> bool {
+        let hash = self.key.hash_one(secret);
+        if self.clients.contains_key(&hash) {
+            return false;
+        }
+        let client = Client {
+            name: String::from(name),
+            secret: String::from(secret),
+        };
+        self.clients.insert(hash, client);
+        true
+    }
+
+    fn client(&self, secret: Option<&str>) -> Result<String, Denied> {
+        let secret = secret.ok_or(Denied::Missing)?;
+        self.clients
+            .get(&self.key.hash_one(secret))
+            .filter(|client| constant_time_eq(client.secret.as_bytes(), secret.as_bytes()))
+            .map(|client| client.name.clone())
+            .ok_or(Denied::Invalid)
+    }
+}
+
+/// Clients identified by a key in the `x-api-key` header.
+#[derive(Clone, Debug, Default)]
+pub struct ApiKeys(Secrets);
+
+impl ApiKeys {
+    /// No clients, to be added with `with_key` when configuring a server in
+    /// code; servers usually `read` their clients from a file.
+    pub fn new() -> Self {
+        ApiKeys::default()
+    }
+
+    /// Read the clients and their keys from a file.
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        Ok(ApiKeys(Secrets::parse(&fs::read_to_string(path)?)?))
+    }
+
+    /// Add a client, whose key must not be that of another.
+    pub fn with_key(mut self, name: &str, key: &str) -> Result<Self, Error> {
+        if !self.0.insert(name, key) {
+            return Err(Error::SecretInUse(String::from(name)));
+        }
+        Ok(self)
+    }
+}
+
+impl Authenticator for ApiKeys {
+    fn authenticate(&self, credentials: &Credentials) -> Result<String, Denied> {
+        self.0.client(credentials.api_key)
+    }
+}
+
+/// Clients identified by a token in an `Authorization: Bearer TOKEN` header.
+#[derive(Clone, Debug, Default)]
+pub struct BearerTokens(Secrets);
+
+impl BearerTokens {
+    /// No clients, to be added with `with_token` when configuring a server
+    /// in code; servers usually `read` their clients from a file.
+    pub fn new() -> Self {
+        BearerTokens::default()
+    }
+
+    /// Read the clients and their tokens from a file.
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        Ok(BearerTokens(Secrets::parse(&fs::read_to_string(path)?)?))
+    }
+
+    /// Add a client, whose token must not be that of another.
+    pub fn with_token(mut self, name: &str, token: &str) -> Result<Self, Error> {
+        if !self.0.insert(name, token) {
+            return Err(Error::SecretInUse(String::from(name)));
+        }
+        Ok(self)
+    }
+}
+
+impl Authenticator for BearerTokens {
+    fn authenticate(&self, credentials: &Credentials) -> Result<String, Denied> {
+        let token = credentials.authorization.map(|value| {
+            let value = value.trim();
+            match value.split_once(' ') {
+                Some((scheme, token)) if scheme.eq_ignore_ascii_case("bearer") => token.trim(),
+                _ => "",
+            }
+        });
+        self.0.client(token)
+    }
+}
+
+/// Requests a client may still make, which refill at the rate of a
+/// `RateLimit`.
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Limit on the rate of requests of each client, as a token bucket: a
+/// client may make a burst of requests, and then requests at the rate.
+#[derive(Debug)]
+pub struct RateLimit {
+    rate: f64,
+    burst: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimit {
+    /// At most `rate` requests a second from each client, in bursts of at
+    /// most `burst` requests. `rate` must be positive and finite.
+    pub fn new(rate: f64, burst: u32) -> Result<Self, Error> {
+        if !(rate.is_finite() && rate > 0.0) {
+            return Err(Error::InvalidRate(rate));
+        }
+        Ok(RateLimit {
+            rate,
+            burst: f64::from(burst.max(1)),
+            buckets: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Count a request of a client, if it is within the limit.
+    pub fn check(&self, client: &str) -> Result<(), Denied> {
+        self.check_at(client, Instant::now())
+    }
+
+    fn check_at(&self, client: &str, now: Instant) -> Result<(), Denied> {
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        let bucket = buckets
+            .entry(String::from(client))
+            .or_insert_with(|| Bucket {
+                tokens: self.burst,
+                updated: now,
+            });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let wait = (1.0 - bucket.tokens) / self.rate;
+            Err(Denied::RateLimited(Duration::from_secs_f64(wait)))
+        }
+    }
+}
+
+/// Authentication, and optionally rate limiting, of the requests of a
+/// server.
+///
+/// ```
+/// # use starquad::auth::{ApiKeys, Auth, Credentials, Denied, RateLimit};
+/// let auth = Auth::new(ApiKeys::new().with_key("alice", "s3cret").unwrap())
+///     .with_rate_limit(RateLimit::new(10.0, 20).unwrap());
+/// let credentials = Credentials {
+///     api_key: Some("s3cret"),
+///     ..Credentials::default()
+/// };
+/// assert_eq!(auth.check(&credentials), Ok(String::from("alice")));
+/// assert_eq!(auth.check(&Credentials::default()), Err(Denied::Missing));
+/// ```
+pub struct Auth {
+    authenticator: Box<dyn Authenticator>,
+    rate_limit: Option<RateLimit>,
+}
+
+impl Auth {
+    pub fn new<A: Authenticator + 'static>(authenticator: A) -> Self {
+        Auth {
+            authenticator: Box::new(authenticator),
+            rate_limit: None,
+        }
+    }
+
+    pub fn with_rate_limit(mut self, rate_limit: RateLimit) -> Self {
+        self.rate_limit = Some(rate_limit);
+        self
+    }
+
+    /// Name of the client making a request, if it may make it.
+    pub fn check(&self, credentials: &Credentials) -> Result<String, Denied> {
+        let result = self
+            .authenticator
+            .authenticate(credentials)
+            .and_then(|client| match &self.rate_limit {
+                Some(rate_limit) => rate_limit.check(&client).map(|()| client),
+                None => Ok(client),
+            });
+        if let Err(denied) = &result {
+            tracing::info!(target: "starquad::audit", reason = %denied, "request denied");
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use auth::{Authenticator, BearerTokens, Credentials, Denied, Error, RateLimit, Secrets};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn secrets() {
+        let secrets = Secrets::parse("# clients\nalice  k1\n\nbob\tk2\n").unwrap();
+        assert_eq!(secrets.client(Some("k2")), Ok(String::from("bob")));
+        assert_eq!(secrets.client(Some("k3")), Err(Denied::Invalid));
+        assert_eq!(secrets.client(None), Err(Denied::Missing));
+        assert!(matches!(
+            Secrets::parse("alice k1\nbob\n"),
+            Err(Error::Syntax { line: 2 })
+        ));
+        assert!(matches!(
+            Secrets::parse("alice k1\nbob k1\n"),
+            Err(Error::DuplicateSecret { line: 2 })
+        ));
+    }
+
+    #[test]
+    fn bearer_tokens() {
+        let tokens = BearerTokens::new().with_token("alice", "t1").unwrap();
+        assert!(matches!(
+            tokens.clone().with_token("bob", "t1"),
+            Err(Error::SecretInUse(name)) if name == "bob"
+        ));
+        let check = |authorization| {
+            tokens.authenticate(&Credentials {
+                authorization,
+                api_key: Some("t1"),
+            })
+        };
+        assert_eq!(check(Some("Bearer t1")), Ok(String::from("alice")));
+        assert_eq!(check(Some("bearer  t1 ")), Ok(String::from("alice")));
+        assert_eq!(check(Some("Basic t1")), Err(Denied::Invalid));
+        assert_eq!(check(Some("t1")), Err(Denied::Invalid));
+        assert_eq!(check(None), Err(Denied::Missing));
+    }
+
+    #[test]
+    fn rate_limit() {
+        assert!(RateLimit::new(0.0, 3).is_err());
+        assert!(RateLimit::new(f64::NAN, 3).is_err());
+        assert!(RateLimit::new(f64::INFINITY, 3).is_err());
+        let limit = RateLimit::new(2.0, 3).unwrap();
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert_eq!(limit.check_at("alice", start), Ok(()));
+        }
+        assert_eq!(
+            limit.check_at("alice", start),
+            Err(Denied::RateLimited(Duration::from_millis(500)))
+        );
+        // each client has its own limit, which refills over time
+        assert_eq!(limit.check_at("bob", start), Ok(()));
+        let later = start + Duration::from_millis(500);
+        assert_eq!(limit.check_at("alice", later), Ok(()));
+        assert!(limit.check_at("alice", later).is_err());
+        let much_later = start + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert_eq!(limit.check_at("alice", much_later), Ok(()));
+        }
+        assert!(limit.check_at("alice", much_later).is_err());
+    }
+}
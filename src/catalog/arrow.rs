@@ -0,0 +1,345 @@
+use arrow_array::{
+    Array, ArrayRef, BooleanArray, Float64Array, Int64Array, RecordBatch, StringArray, UInt64Array,
+    UInt8Array,
+};
+use arrow_cast::cast;
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef};
+use crossmatch::join::JoinTable;
+use csv::StringRecord;
+use gaia::projection::Projection;
+use gaia::record::GaiaRecord;
+use gaia::schema::{self, Column, ColumnType, COLUMNS};
+use sky::position::SkySource;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Default number of rows in each record batch produced from a stream.
+pub const DEFAULT_BATCH_SIZE: usize = 65536;
+
+/// Errors from converting to and from Arrow record batches.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Arrow(#[from] ArrowError),
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    /// A value could not be converted to the type of its column.
+    #[error("invalid value for column {column}: {value:?}")]
+    InvalidValue { column: String, value: String },
+}
+
+fn data_type(column_type: ColumnType) -> DataType {
+    match column_type {
+        ColumnType::Boolean => DataType::Boolean,
+        ColumnType::UnsignedByte => DataType::UInt8,
+        ColumnType::Long => DataType::Int64,
+        ColumnType::Double => DataType::Float64,
+        ColumnType::Text => DataType::Utf8,
+    }
+}
+
+/// Arrow schema of the Gaia columns.
+///
+/// Integer identifiers are stored as `Int64` rather than `UInt64`, as this is
+/// what the Gaia archive and most other tools use.
+pub fn gaia_schema() -> SchemaRef {
+    Arc::new(Schema::new(
+        COLUMNS
+            .iter()
+            .map(|c| Field::new(c.name, data_type(c.column_type), c.nullable))
+            .collect::<Vec<_>>(),
+    ))
+}
+
+/// Build the array of a column from the text fields of records.
+fn column_array(
+    column: &Column,
+    index: usize,
+    records: &[StringRecord],
+) -> Result<ArrayRef, Error> {
+    fn parse<T: FromStr>(
+        column: &Column,
+        index: usize,
+        records: &[StringRecord],
+    ) -> Result<Vec<Option<T>>, Error> {
+        records
+            .iter()
+            .map(|record| match record.get(index).unwrap_or("") {
+                "" => Ok(None),
+                value => value.parse().map(Some).map_err(|_| Error::InvalidValue {
+                    column: String::from(column.name),
+                    value: String::from(value),
+                }),
+            })
+            .collect()
+    }
+    Ok(match column.column_type {
+        ColumnType::Boolean => Arc::new(BooleanArray::from(parse::<bool>(column, index, records)?)),
+        ColumnType::UnsignedByte => {
+            Arc::new(UInt8Array::from(parse::<u8>(column, index, records)?))
+        }
+        ColumnType::Long => Arc::new(Int64Array::from(parse::<i64>(column, index, records)?)),
+        ColumnType::Double => Arc::new(Float64Array::from(parse::<f64>(column, index, records)?)),
+        ColumnType::Text => Arc::new(StringArray::from(
+            records
+                .iter()
+                .map(|record| record.get(index))
+                .collect::<Vec<_>>(),
+        )),
+    })
+}
+
+/// Convert Gaia records to a record batch with the schema of `gaia_schema`.
+pub fn gaia_record_batch(records: &[GaiaRecord]) -> Result<RecordBatch, Error> {
+    let fields = schema::string_records(records)?;
+    let columns = COLUMNS
+        .iter()
+        .enumerate()
+        .map(|(i, column)| column_array(column, i, &fields))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(RecordBatch::try_new(gaia_schema(), columns)?)
+}
+
+/// Source of the text of a column of Gaia records read from a batch.
+enum ColumnText {
+    Array(StringArray),
+    Cleared(&'static str),
+}
+
+/// Convert a record batch to Gaia records.
+///
+/// Columns are matched to `GaiaRecord` fields by name, and other columns are
+/// ignored. Columns may have any type that Arrow can cast to text (for
+/// example, `ref_epoch` may be a double).
+pub fn gaia_records(batch: &RecordBatch) -> Result<Vec<GaiaRecord>, Error> {
+    projected_gaia_records(batch, &Projection::all())
+}
+
+/// Convert the columns of a record batch selected by a projection to Gaia
+/// records, with the other columns cleared (as by
+/// `Projection::clear_others`), whether or not the batch holds them.
+pub fn projected_gaia_records(
+    batch: &RecordBatch,
+    projection: &Projection,
+) -> Result<Vec<GaiaRecord>, Error> {
+    let columns = COLUMNS
+        .iter()
+        .enumerate()
+        .map(|(i, column)| match batch.column_by_name(column.name) {
+            _ if !projection.columns().contains(&i) => Ok(ColumnText::Cleared(column.cleared())),
+            Some(array) => {
+                let text = cast(array, &DataType::Utf8)?;
+                let text = text
+                    .as_any()
+                    .downcast_ref::<StringArray>()
+                    .cloned()
+                    .ok_or_else(|| {
+                        ArrowError::CastError(format!("{} is not cast to text", column.name))
+                    })?;
+                Ok(ColumnText::Array(text))
+            }
+            None => Ok(ColumnText::Cleared("")),
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    let headers = schema::headers();
+    (0..batch.num_rows())
+        .map(|row| {
+            let record = columns
+                .iter()
+                .map(|column| match column {
+                    ColumnText::Array(array) if array.is_valid(row) => array.value(row),
+                    ColumnText::Array(_) => "",
+                    ColumnText::Cleared(text) => text,
+                })
+                .collect::<StringRecord>();
+            record.deserialize(Some(&headers)).map_err(Error::from)
+        })
+        .collect()
+}
+
+/// Convert sources to a record batch with columns `id`, `ra`, `dec`,
+/// `epoch`, `mag` and `error`.
+pub fn sources_record_batch<I>(sources: I) -> Result<RecordBatch, Error>
+where
+    I: IntoIterator,
+    I::Item: SkySource,
+{
+    let mut id = Vec::new();
+    let mut ra = Vec::new();
+    let mut dec = Vec::new();
+    let mut epoch = Vec::new();
+    let mut mag = Vec::new();
+    let mut error = Vec::new();
+    for source in sources {
+        let position = source.position();
+        id.push(source.id());
+        ra.push(position.ra);
+        dec.push(position.dec);
+        epoch.push(source.epoch());
+        mag.push(source.magnitude());
+        error.push(source.position_error());
+    }
+    let schema = Schema::new(vec![
+        Field::new("id", DataType::Utf8, true),
+        Field::new("ra", DataType::Float64, false),
+        Field::new("dec", DataType::Float64, false),
+        Field::new("epoch", DataType::Float64, true),
+        Field::new("mag", DataType::Float64, true),
+        Field::new("error", DataType::Float64, true),
+    ]);
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from(id)),
+        Arc::new(Float64Array::from(ra)),
+        Arc::new(Float64Array::from(dec)),
+        Arc::new(Float64Array::from(epoch)),
+        Arc::new(Float64Array::from(mag)),
+        Arc::new(Float64Array::from(error)),
+    ];
+    Ok(RecordBatch::try_new(Arc::new(schema), columns)?)
+}
+
+/// Convert the rows of a join table to a record batch with columns `left`,
+/// `right`, `separation` (in arcseconds), `score`, `candidates` and
+/// `ambiguous`.
+pub fn join_record_batch(table: &JoinTable) -> Result<RecordBatch, Error> {
+    let rows = table.rows();
+    let schema = Schema::new(vec![
+        Field::new("left", DataType::UInt64, false),
+        Field::new("right", DataType::UInt64, false),
+        Field::new("separation", DataType::Float64, false),
+        Field::new("score", DataType::Float64, false),
+        Field::new("candidates", DataType::UInt64, false),
+        Field::new("ambiguous", DataType::Boolean, false),
+    ]);
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(UInt64Array::from_iter_values(
+            rows.iter().map(|m| m.left as u64),
+        )),
+        Arc::new(UInt64Array::from_iter_values(
+            rows.iter().map(|m| m.right as u64),
+        )),
+        Arc::new(Float64Array::from_iter_values(
+            rows.iter().map(|m| m.separation),
+        )),
+        Arc::new(Float64Array::from_iter_values(rows.iter().map(|m| m.score))),
+        Arc::new(UInt64Array::from_iter_values(
+            rows.iter().map(|m| m.candidates as u64),
+        )),
+        Arc::new(BooleanArray::from(
+            rows.iter().map(|m| m.ambiguous).collect::<Vec<_>>(),
+        )),
+    ];
+    Ok(RecordBatch::try_new(Arc::new(schema), columns)?)
+}
+
+/// Adapter which groups a stream of Gaia records into record batches.
+pub struct RecordBatches<I> {
+    records: I,
+    batch_size: usize,
+}
+
+impl<I> RecordBatches<I>
+where
+    I: Iterator<Item = GaiaRecord>,
+{
+    pub fn new(records: I) -> Self {
+        RecordBatches {
+            records,
+            batch_size: DEFAULT_BATCH_SIZE,
+        }
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+}
+
+impl<I> Iterator for RecordBatches<I>
+where
+    I: Iterator<Item = GaiaRecord>,
+{
+    type Item = Result<RecordBatch, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let records = self
+            .records
+            .by_ref()
+            .take(self.batch_size)
+            .collect::<Vec<_>>();
+        if records.is_empty() {
+            None
+        } else {
+            Some(gaia_record_batch(&records))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use arrow_array::{Array, Float64Array, StringArray};
+    use catalog::arrow::{gaia_records, join_record_batch, sources_record_batch, RecordBatches};
+    use crossmatch::join::{JoinTable, Match};
+    use gaia::record::test::sample_record;
+    use gaia::record::GaiaRecord;
+    use gaia::schema::COLUMNS;
+    use sky::position::SkyPosition;
+
+    #[test]
+    fn gaia_round_trip() {
+        let records = (0..10)
+            .map(|i| {
+                let mut record = sample_record();
+                record.source_id = i;
+                record.pmra = if i % 2 == 0 { Some(i as f64) } else { None };
+                record
+            })
+            .collect::<Vec<GaiaRecord>>();
+        let batches = RecordBatches::new(records.clone().into_iter())
+            .with_batch_size(4)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            batches.iter().map(|b| b.num_rows()).collect::<Vec<_>>(),
+            vec![4, 4, 2]
+        );
+        assert_eq!(batches[0].num_columns(), COLUMNS.len());
+        let read = batches
+            .iter()
+            .flat_map(|b| gaia_records(b).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(read, records);
+    }
+
+    #[test]
+    fn sources() {
+        let batch =
+            sources_record_batch(vec![SkyPosition::new(1.0, 2.0), SkyPosition::new(3.0, 4.0)])
+                .unwrap();
+        let dec = batch
+            .column_by_name("dec")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(dec.values().to_vec(), vec![2.0, 4.0]);
+        let id = batch.column_by_name("id").unwrap();
+        assert!(id.as_any().downcast_ref::<StringArray>().is_some());
+        assert_eq!(id.null_count(), 2);
+    }
+
+    #[test]
+    fn join() {
+        let table = JoinTable::from_matches(vec![Match {
+            left: 3,
+            right: 7,
+            separation: 0.5,
+            score: 20.0,
+            candidates: 2,
+            ambiguous: true,
+        }]);
+        let batch = join_record_batch(&table).unwrap();
+        assert_eq!(batch.num_rows(), 1);
+        assert_eq!(batch.schema().field(2).name(), "separation");
+    }
+}
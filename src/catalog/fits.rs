@@ -0,0 +1,537 @@
+use catalog::votable::{Datatype, Field, Value, VoTable};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io;
+use std::io::Read;
+
+/// Size of a FITS block; headers and data units are padded to a multiple of
+/// this.
+const BLOCK_SIZE: usize = 2880;
+
+/// Size of a header card.
+const CARD_SIZE: usize = 80;
+
+/// Errors from reading a FITS file.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// The file is not a FITS file, or contains an unsupported table.
+    #[error("{0}")]
+    Format(String),
+}
+
+fn format_error<T>(message: String) -> Result<T, Error> {
+    Err(Error::Format(message))
+}
+
+/// Keywords of a header data unit.
+struct Header {
+    values: HashMap<String, String>,
+}
+
+impl Header {
+    /// Read a header, up to and including the block containing its `END`
+    /// card. Returns `None` at the end of the file.
+    fn read<R: Read>(reader: &mut R) -> Result<Option<Header>, Error> {
+        let mut values = HashMap::new();
+        let mut block = [0u8; BLOCK_SIZE];
+        let mut first = true;
+        loop {
+            if first {
+                // a clean end of file is only allowed before a header
+                let n = read_full(reader, &mut block)?;
+                if n == 0 {
+                    return Ok(None);
+                } else if n < BLOCK_SIZE {
+                    return format_error(String::from("truncated header"));
+                }
+                first = false;
+            } else {
+                reader.read_exact(&mut block)?;
+            }
+            for card in block.chunks(CARD_SIZE) {
+                let card = String::from_utf8_lossy(card);
+                let keyword = card[..8].trim_end();
+                if keyword == "END" {
+                    return Ok(Some(Header { values }));
+                }
+                if &card[8..10] == "= " {
+                    values.insert(String::from(keyword), parse_value(&card[10..]));
+                }
+            }
+        }
+    }
+
+    fn get(&self, keyword: &str) -> Option<&str> {
+        self.values.get(keyword).map(String::as_str)
+    }
+
+    fn integer(&self, keyword: &str) -> Result<Option<i64>, Error> {
+        match self.get(keyword) {
+            None => Ok(None),
+            Some(value) => match value.parse() {
+                Ok(value) => Ok(Some(value)),
+                Err(_) => format_error(format!("invalid integer {:?} for {}", value, keyword)),
+            },
+        }
+    }
+
+    fn required(&self, keyword: &str) -> Result<i64, Error> {
+        match self.integer(keyword)? {
+            Some(value) => Ok(value),
+            None => format_error(format!("missing keyword {}", keyword)),
+        }
+    }
+
+    fn float(&self, keyword: &str) -> Result<Option<f64>, Error> {
+        match self.get(keyword) {
+            None => Ok(None),
+            Some(value) => match value.replace('D', "E").parse() {
+                Ok(value) => Ok(Some(value)),
+                Err(_) => format_error(format!("invalid number {:?} for {}", value, keyword)),
+            },
+        }
+    }
+
+    /// Size in bytes of the data unit following this header, excluding
+    /// padding.
+    fn data_size(&self) -> Result<usize, Error> {
+        let naxis = self.required("NAXIS")?;
+        if naxis == 0 {
+            return Ok(0);
+        }
+        let bitpix = self.required("BITPIX")?;
+        let mut size = 1;
+        for i in 1..=naxis {
+            size *= self.required(&format!("NAXIS{}", i))?;
+        }
+        let pcount = self.integer("PCOUNT")?.unwrap_or(0);
+        let gcount = self.integer("GCOUNT")?.unwrap_or(1);
+        Ok((bitpix.abs() / 8 * gcount * (pcount + size)) as usize)
+    }
+}
+
+/// Read until `buf` is full or the end of the file is reached.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match reader.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(m) => n += m,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(n)
+}
+
+/// Parse the value field of a header card, removing quotes from strings and
+/// any trailing comment.
+fn parse_value(field: &str) -> String {
+    let field = field.trim_start();
+    if let Some(quoted) = field.strip_prefix('\'') {
+        let mut value = String::new();
+        let mut chars = quoted.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c == '\'' {
+                if chars.peek() == Some(&'\'') {
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            value.push(c);
+        }
+        String::from(value.trim_end())
+    } else {
+        String::from(field.split('/').next().unwrap_or("").trim())
+    }
+}
+
+fn skip<R: Read>(reader: &mut R, n: usize) -> Result<(), Error> {
+    let skipped = io::copy(&mut reader.take(n as u64), &mut io::sink())?;
+    if skipped < n as u64 {
+        return format_error(String::from("truncated data unit"));
+    }
+    Ok(())
+}
+
+fn padding(size: usize) -> usize {
+    (BLOCK_SIZE - size % BLOCK_SIZE) % BLOCK_SIZE
+}
+
+/// Layout of a column of a binary table.
+#[derive(Clone, Debug)]
+struct Column {
+    code: char,
+    repeat: usize,
+    offset: usize,
+    scale: f64,
+    zero: f64,
+    null: Option<i64>,
+}
+
+impl Column {
+    fn element_size(&self) -> usize {
+        match self.code {
+            'L' | 'B' | 'A' => 1,
+            'I' => 2,
+            'J' | 'E' => 4,
+            'K' | 'D' => 8,
+            _ => 0,
+        }
+    }
+
+    fn is_scaled(&self) -> bool {
+        self.scale != 1.0 || self.zero.fract() != 0.0
+    }
+
+    /// VOTable datatype of the column's (scaled) values.
+    fn datatype(&self) -> Datatype {
+        let offset = self.zero != 0.0;
+        match self.code {
+            'L' => Datatype::Boolean,
+            'A' => Datatype::Char,
+            'E' => Datatype::Float,
+            'D' => Datatype::Double,
+            _ if self.is_scaled() => Datatype::Double,
+            'B' if offset => Datatype::Short,
+            'B' => Datatype::UnsignedByte,
+            'I' if offset => Datatype::Int,
+            'I' => Datatype::Short,
+            'J' if offset => Datatype::Long,
+            'J' => Datatype::Int,
+            _ => Datatype::Long,
+        }
+    }
+
+    fn decode(&self, row: &[u8]) -> Value {
+        let data = &row[self.offset..self.offset + self.repeat * self.element_size()];
+        if self.code == 'A' {
+            let end = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+            let text = String::from_utf8_lossy(&data[..end]).trim_end().to_string();
+            return if text.is_empty() {
+                Value::Null
+            } else {
+                Value::Text(text)
+            };
+        }
+        let mut elements = data
+            .chunks(self.element_size())
+            .map(|c| self.decode_element(c))
+            .collect::<Vec<_>>();
+        match elements.len() {
+            0 => Value::Null,
+            1 => elements.remove(0),
+            _ => Value::Text(
+                elements
+                    .iter()
+                    .map(|e| match e {
+                        Value::Null => String::from("NaN"),
+                        Value::Boolean(b) => String::from(if *b { "T" } else { "F" }),
+                        other => other.to_text(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ),
+        }
+    }
+
+    fn decode_element(&self, c: &[u8]) -> Value {
+        let float = |f: f64| {
+            if f.is_nan() {
+                Value::Null
+            } else {
+                Value::Float(f)
+            }
+        };
+        let raw = match self.code {
+            'L' => {
+                return match c[0] {
+                    b'T' => Value::Boolean(true),
+                    b'F' => Value::Boolean(false),
+                    _ => Value::Null,
+                };
+            }
+            'E' => return float(f64::from(f32::from_be_bytes([c[0], c[1], c[2], c[3]]))),
+            'D' => {
+                return float(f64::from_be_bytes([
+                    c[0], c[1], c[2], c[3], c[4], c[5], c[6], c[7],
+                ]));
+            }
+            'B' => i64::from(c[0]),
+            'I' => i64::from(i16::from_be_bytes([c[0], c[1]])),
+            'J' => i64::from(i32::from_be_bytes([c[0], c[1], c[2], c[3]])),
+            _ => i64::from_be_bytes([c[0], c[1], c[2], c[3], c[4], c[5], c[6], c[7]]),
+        };
+        if self.null == Some(raw) {
+            Value::Null
+        } else if self.is_scaled() {
+            Value::Float(raw as f64 * self.scale + self.zero)
+        } else {
+            let value = i128::from(raw) + self.zero as i128;
+            i64::try_from(value).map_or(Value::Float(value as f64), Value::Integer)
+        }
+    }
+}
+
+/// Parse a `TFORMn` value into a repeat count and type code.
+fn parse_tform(tform: &str) -> Result<(usize, char), Error> {
+    let tform = tform.trim();
+    let digits = tform.chars().take_while(char::is_ascii_digit).count();
+    let repeat = if digits == 0 {
+        1
+    } else {
+        tform[..digits].parse().unwrap_or(1)
+    };
+    match tform[digits..].chars().next() {
+        Some(code @ ('L' | 'B' | 'I' | 'J' | 'K' | 'A' | 'E' | 'D')) => Ok((repeat, code)),
+        _ => format_error(format!("unsupported column format {:?}", tform)),
+    }
+}
+
+/// Streaming reader of the rows of a FITS binary table (`BINTABLE`
+/// extension).
+///
+/// Column types are mapped to the equivalent VOTable datatypes, so that rows
+/// can be collected into a [VoTable](::catalog::votable::VoTable) and
+/// converted to Gaia records or generic sources from there. Integer columns
+/// are scaled by `TSCALn` and `TZEROn`, and values equal to `TNULLn`, NaN
+/// floats and undefined logicals are read as nulls. Variable-length array,
+/// bit and complex columns are not supported.
+pub struct BinTableReader<R> {
+    reader: R,
+    name: Option<String>,
+    fields: Vec<Field>,
+    columns: Vec<Column>,
+    row: Vec<u8>,
+    rows_remaining: usize,
+}
+
+impl<R: Read> BinTableReader<R> {
+    /// Create a reader of the first binary table extension of a file.
+    pub fn new(mut reader: R) -> Result<Self, Error> {
+        let primary = match Header::read(&mut reader)? {
+            Some(header) if header.get("SIMPLE") == Some("T") => header,
+            _ => return format_error(String::from("not a FITS file")),
+        };
+        let size = primary.data_size()?;
+        skip(&mut reader, size + padding(size))?;
+
+        loop {
+            let header = match Header::read(&mut reader)? {
+                Some(header) => header,
+                None => return format_error(String::from("no BINTABLE extension found")),
+            };
+            if header.get("XTENSION") == Some("BINTABLE") {
+                return BinTableReader::from_header(reader, &header);
+            }
+            let size = header.data_size()?;
+            skip(&mut reader, size + padding(size))?;
+        }
+    }
+
+    fn from_header(reader: R, header: &Header) -> Result<Self, Error> {
+        let row_size = header.required("NAXIS1")? as usize;
+        let rows = header.required("NAXIS2")? as usize;
+        let tfields = header.required("TFIELDS")?;
+
+        let mut fields = Vec::new();
+        let mut columns = Vec::new();
+        let mut offset = 0;
+        for i in 1..=tfields {
+            let keyword = |name: &str| format!("{}{}", name, i);
+            let tform = header.get(&keyword("TFORM")).unwrap_or("");
+            let (repeat, code) = parse_tform(tform)?;
+            let column = Column {
+                code,
+                repeat,
+                offset,
+                scale: header.float(&keyword("TSCAL"))?.unwrap_or(1.0),
+                zero: header.float(&keyword("TZERO"))?.unwrap_or(0.0),
+                null: header.integer(&keyword("TNULL"))?,
+            };
+            offset += repeat * column.element_size();
+
+            let name = header
+                .get(&keyword("TTYPE"))
+                .map_or_else(|| keyword("col"), String::from);
+            let mut field = Field::new(&name, column.datatype());
+            field.arraysize = if repeat == 1 {
+                None
+            } else {
+                Some(repeat.to_string())
+            };
+            field.unit = header
+                .get(&keyword("TUNIT"))
+                .filter(|u| !u.is_empty())
+                .map(String::from);
+            fields.push(field);
+            columns.push(column);
+        }
+        if offset > row_size {
+            return format_error(format!(
+                "columns need {} bytes but rows are {} bytes",
+                offset, row_size
+            ));
+        }
+
+        Ok(BinTableReader {
+            reader,
+            name: header.get("EXTNAME").map(String::from),
+            fields,
+            columns,
+            row: vec![0; row_size],
+            rows_remaining: rows,
+        })
+    }
+
+    /// Name of the table, from its `EXTNAME` keyword.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+
+    /// Read all remaining rows into a table.
+    pub fn read_table(self) -> Result<VoTable, Error> {
+        let name = self.name.clone();
+        let fields = self.fields.clone();
+        let rows = self.collect::<Result<Vec<_>, _>>()?;
+        Ok(VoTable { name, fields, rows })
+    }
+}
+
+impl<R: Read> Iterator for BinTableReader<R> {
+    type Item = Result<Vec<Value>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rows_remaining == 0 {
+            return None;
+        }
+        self.rows_remaining -= 1;
+        if let Err(e) = self.reader.read_exact(&mut self.row) {
+            self.rows_remaining = 0;
+            return Some(Err(Error::from(e)));
+        }
+        Some(Ok(self
+            .columns
+            .iter()
+            .map(|c| c.decode(&self.row))
+            .collect()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use catalog::fits::{BinTableReader, BLOCK_SIZE};
+    use catalog::generic::ColumnMapping;
+    use catalog::votable::{Datatype, Value};
+    use sky::position::{SkyPosition, SkySource};
+
+    fn header(cards: &[&str]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for card in cards.iter().chain(["END"].iter()) {
+            bytes.extend_from_slice(format!("{:80}", card).as_bytes());
+        }
+        bytes.resize(bytes.len().div_ceil(BLOCK_SIZE) * BLOCK_SIZE, b' ');
+        bytes
+    }
+
+    fn row(source_id: i64, ra: f64, dec: f64, mag: f32, name: &str, n: i16) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&source_id.to_be_bytes());
+        bytes.extend_from_slice(&ra.to_be_bytes());
+        bytes.extend_from_slice(&dec.to_be_bytes());
+        bytes.extend_from_slice(&mag.to_be_bytes());
+        bytes.extend_from_slice(format!("{:4}", name).as_bytes());
+        bytes.extend_from_slice(&n.to_be_bytes());
+        bytes
+    }
+
+    fn sample_file() -> Vec<u8> {
+        let mut file = header(&[
+            "SIMPLE  =                    T",
+            "BITPIX  =                    8",
+            "NAXIS   =                    0",
+        ]);
+        file.extend(header(&[
+            "XTENSION= 'BINTABLE'",
+            "BITPIX  =                    8",
+            "NAXIS   =                    2",
+            "NAXIS1  =                   34",
+            "NAXIS2  =                    2",
+            "PCOUNT  =                    0",
+            "GCOUNT  =                    1",
+            "TFIELDS =                    6",
+            "EXTNAME = 'sources '           / table name",
+            "TTYPE1  = 'source_id'",
+            "TFORM1  = 'K       '",
+            "TTYPE2  = 'ra      '",
+            "TFORM2  = 'D       '",
+            "TUNIT2  = 'deg     '",
+            "TTYPE3  = 'dec     '",
+            "TFORM3  = 'D       '",
+            "TTYPE4  = 'phot_g_mean_mag'",
+            "TFORM4  = 'E       '",
+            "TTYPE5  = 'name    '",
+            "TFORM5  = '4A      '",
+            "TTYPE6  = 'n       '",
+            "TFORM6  = 'I       '",
+            "TNULL6  =                   -1",
+            "TZERO6  =                32768",
+        ]));
+        let mut data = row(42, 10.5, -20.25, 15.5, "ab", -32768);
+        data.extend(row(43, 11.0, -21.0, f32::NAN, "", -1));
+        data.resize(BLOCK_SIZE, 0);
+        file.extend(data);
+        file
+    }
+
+    #[test]
+    fn read_rows() {
+        let file = sample_file();
+        let reader = BinTableReader::new(file.as_slice()).unwrap();
+        assert_eq!(reader.name(), Some("sources"));
+        assert_eq!(reader.fields()[1].unit, Some(String::from("deg")));
+        assert_eq!(reader.fields()[5].datatype, Datatype::Int);
+        let rows = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(
+            rows[0],
+            vec![
+                Value::Integer(42),
+                Value::Float(10.5),
+                Value::Float(-20.25),
+                Value::Float(15.5),
+                Value::Text(String::from("ab")),
+                Value::Integer(0),
+            ]
+        );
+        assert_eq!(rows[1][3], Value::Null);
+        assert_eq!(rows[1][4], Value::Null);
+        assert_eq!(rows[1][5], Value::Null);
+    }
+
+    #[test]
+    fn sources() {
+        let table = BinTableReader::new(sample_file().as_slice())
+            .unwrap()
+            .read_table()
+            .unwrap();
+        let mapping: ColumnMapping = "id=source_id,ra=ra,dec=dec,mag=phot_g_mean_mag"
+            .parse()
+            .unwrap();
+        let sources = table.sources(&mapping).unwrap();
+        assert_eq!(sources[1].id(), Some(String::from("43")));
+        assert_eq!(sources[1].position(), SkyPosition::new(11.0, -21.0));
+        assert_eq!(sources[1].magnitude(), None);
+    }
+
+    #[test]
+    fn not_fits() {
+        assert!(BinTableReader::new(&b"ra,dec\n1,2\n"[..]).is_err());
+    }
+}
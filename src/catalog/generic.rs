@@ -0,0 +1,308 @@
+use csv::{Reader, ReaderBuilder, StringRecord, StringRecordsIntoIter, Trim};
+use sky::position::{SkyPosition, SkySource};
+use std::io::Read;
+use std::str::FromStr;
+
+/// Mapping from the roles of catalog columns to column names.
+///
+/// Only `ra` and `dec` (both in degrees) are required. `error` is a one-sigma
+/// positional uncertainty in milliarcseconds, and `epoch` a Julian year.
+///
+/// A mapping can be parsed from a comma-separated list of `role=column`
+/// pairs:
+///
+/// ```
+/// # use starquad::catalog::generic::ColumnMapping;
+/// let mapping: ColumnMapping = "ra=RAJ2000,dec=DEJ2000,mag=Vmag".parse().unwrap();
+/// assert_eq!(mapping.ra, "RAJ2000");
+/// assert_eq!(mapping.mag, Some(String::from("Vmag")));
+/// assert_eq!(mapping.id, None);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColumnMapping {
+    pub ra: String,
+    pub dec: String,
+    pub id: Option<String>,
+    pub epoch: Option<String>,
+    pub mag: Option<String>,
+    pub error: Option<String>,
+}
+
+impl ColumnMapping {
+    pub fn new(ra: &str, dec: &str) -> Self {
+        ColumnMapping {
+            ra: String::from(ra),
+            dec: String::from(dec),
+            id: None,
+            epoch: None,
+            mag: None,
+            error: None,
+        }
+    }
+}
+
+impl FromStr for ColumnMapping {
+    type Err = Error;
+
+    fn from_str(spec: &str) -> Result<Self, Error> {
+        let mut mapping = ColumnMapping::new("", "");
+        for pair in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let mut parts = pair.splitn(2, '=');
+            let role = parts.next().unwrap_or("").trim();
+            let column = match parts.next() {
+                Some(column) => String::from(column.trim()),
+                None => return Err(Error::InvalidMapping(String::from(pair))),
+            };
+            match role {
+                "ra" => mapping.ra = column,
+                "dec" => mapping.dec = column,
+                "id" => mapping.id = Some(column),
+                "epoch" => mapping.epoch = Some(column),
+                "mag" => mapping.mag = Some(column),
+                "error" => mapping.error = Some(column),
+                _ => return Err(Error::InvalidMapping(String::from(pair))),
+            }
+        }
+        if mapping.ra.is_empty() || mapping.dec.is_empty() {
+            return Err(Error::InvalidMapping(String::from(spec)));
+        }
+        Ok(mapping)
+    }
+}
+
+/// A source read from a generic catalog.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GenericSource {
+    pub id: Option<String>,
+    pub position: SkyPosition,
+    pub epoch: Option<f64>,
+    pub mag: Option<f64>,
+    pub error: Option<f64>,
+}
+
+impl SkySource for GenericSource {
+    fn position(&self) -> SkyPosition {
+        self.position
+    }
+
+    fn position_error(&self) -> Option<f64> {
+        self.error
+    }
+
+    fn epoch(&self) -> Option<f64> {
+        self.epoch
+    }
+
+    fn id(&self) -> Option<String> {
+        self.id.clone()
+    }
+
+    fn magnitude(&self) -> Option<f64> {
+        self.mag
+    }
+}
+
+/// Errors from reading a generic catalog.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// A column mapping specification could not be parsed.
+    #[error("invalid column mapping: {0}")]
+    InvalidMapping(String),
+    /// A mapped column is missing from the header.
+    #[error("column not found: {0}")]
+    MissingColumn(String),
+    /// A value could not be parsed as a number.
+    #[error("line {line}: invalid value for column {column}: {value:?}")]
+    InvalidValue {
+        line: u64,
+        column: String,
+        value: String,
+    },
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+}
+
+/// Parser of sources from records with a known header.
+///
+/// This resolves a `ColumnMapping` against a header once, so that it can be
+/// used for CSV files as well as other tabular formats.
+pub struct SourceParser {
+    ra: (String, usize),
+    dec: (String, usize),
+    id: Option<usize>,
+    epoch: Option<(String, usize)>,
+    mag: Option<(String, usize)>,
+    error: Option<(String, usize)>,
+}
+
+impl SourceParser {
+    pub fn new(headers: &StringRecord, mapping: &ColumnMapping) -> Result<Self, Error> {
+        let find = |name: &String| {
+            headers
+                .iter()
+                .position(|h| h == name)
+                .map(|i| (name.clone(), i))
+                .ok_or_else(|| Error::MissingColumn(name.clone()))
+        };
+        let find_opt = |name: &Option<String>| name.as_ref().map(find).transpose();
+        Ok(SourceParser {
+            ra: find(&mapping.ra)?,
+            dec: find(&mapping.dec)?,
+            id: find_opt(&mapping.id)?.map(|(_, i)| i),
+            epoch: find_opt(&mapping.epoch)?,
+            mag: find_opt(&mapping.mag)?,
+            error: find_opt(&mapping.error)?,
+        })
+    }
+
+    /// Parse a single record. Empty fields are treated as missing values.
+    pub fn parse(&self, record: &StringRecord) -> Result<GenericSource, Error> {
+        let line = record.position().map_or(0, |p| p.line());
+        let field = |i: usize| record.get(i).unwrap_or("");
+        let number = |(name, i): &(String, usize)| {
+            let value = field(*i);
+            if value.is_empty() {
+                Ok(None)
+            } else {
+                value.parse().map(Some).map_err(|_| Error::InvalidValue {
+                    line,
+                    column: name.clone(),
+                    value: String::from(value),
+                })
+            }
+        };
+        let required = |column: &(String, usize)| {
+            number(column)?.ok_or_else(|| Error::InvalidValue {
+                line,
+                column: column.0.clone(),
+                value: String::new(),
+            })
+        };
+        let optional = |column: &Option<(String, usize)>| match column {
+            Some(column) => number(column),
+            None => Ok(None),
+        };
+        Ok(GenericSource {
+            id: self.id.map(|i| String::from(field(i))),
+            position: SkyPosition::new(required(&self.ra)?, required(&self.dec)?),
+            epoch: optional(&self.epoch)?,
+            mag: optional(&self.mag)?,
+            error: optional(&self.error)?,
+        })
+    }
+}
+
+/// Reader of sources from a CSV file with an arbitrary header.
+pub struct GenericReader<R> {
+    records: StringRecordsIntoIter<R>,
+    parser: SourceParser,
+}
+
+impl<R: Read> GenericReader<R> {
+    /// Create a reader for comma-separated values.
+    pub fn new(reader: R, mapping: &ColumnMapping) -> Result<Self, Error> {
+        GenericReader::with_delimiter(reader, mapping, b',')
+    }
+
+    pub fn with_delimiter(
+        reader: R,
+        mapping: &ColumnMapping,
+        delimiter: u8,
+    ) -> Result<Self, Error> {
+        let csv_reader = ReaderBuilder::new()
+            .delimiter(delimiter)
+            .has_headers(true)
+            .trim(Trim::All)
+            .from_reader(reader);
+        GenericReader::from_csv(csv_reader, mapping)
+    }
+
+    /// Create a reader from a configured CSV reader, which must have headers.
+    pub fn from_csv(mut reader: Reader<R>, mapping: &ColumnMapping) -> Result<Self, Error> {
+        let parser = SourceParser::new(reader.headers()?, mapping)?;
+        Ok(GenericReader {
+            records: reader.into_records(),
+            parser,
+        })
+    }
+
+    /// Convert the reader into an iterator of boxed `SkySource` trait
+    /// objects, for use alongside sources from other catalogs.
+    pub fn boxed(self) -> impl Iterator<Item = Result<Box<dyn SkySource>, Error>>
+    where
+        R: 'static,
+    {
+        self.map(|source| source.map(|s| Box::new(s) as Box<dyn SkySource>))
+    }
+}
+
+impl<R: Read> Iterator for GenericReader<R> {
+    type Item = Result<GenericSource, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.records.next().map(|record| match record {
+            Ok(record) => self.parser.parse(&record),
+            Err(e) => Err(Error::from(e)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use catalog::generic::{ColumnMapping, Error, GenericReader};
+    use crossmatch::matcher::CrossMatch;
+    use sky::position::{SkyPosition, SkySource};
+
+    const CATALOG: &str = "\
+name,RAJ2000,DEJ2000,Vmag,obs_epoch
+alpha, 10.0, 20.0, 5.5, 2000.0
+beta, 30.0, -40.0, , 2010.0
+";
+
+    #[test]
+    fn read() {
+        let mapping: ColumnMapping = "id=name, ra=RAJ2000, dec=DEJ2000, mag=Vmag, epoch=obs_epoch"
+            .parse()
+            .unwrap();
+        let sources = GenericReader::new(CATALOG.as_bytes(), &mapping)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(sources.len(), 2);
+        assert_eq!(sources[0].id(), Some(String::from("alpha")));
+        assert_eq!(sources[0].position(), SkyPosition::new(10.0, 20.0));
+        assert_eq!(sources[0].magnitude(), Some(5.5));
+        assert_eq!(sources[1].magnitude(), None);
+        assert_eq!(sources[1].epoch(), Some(2010.0));
+    }
+
+    #[test]
+    fn missing_column() {
+        let mapping = ColumnMapping::new("ra", "DEJ2000");
+        match GenericReader::new(CATALOG.as_bytes(), &mapping) {
+            Err(Error::MissingColumn(column)) => assert_eq!(column, "ra"),
+            _ => panic!("expected a missing column"),
+        }
+    }
+
+    #[test]
+    fn invalid_mapping() {
+        assert!("ra=a,dec".parse::<ColumnMapping>().is_err());
+        assert!("ra=a,flux=b,dec=c".parse::<ColumnMapping>().is_err());
+        assert!("ra=a".parse::<ColumnMapping>().is_err());
+    }
+
+    #[test]
+    fn crossmatch_trait_objects() {
+        let mapping = ColumnMapping::new("RAJ2000", "DEJ2000");
+        let sources = GenericReader::new(CATALOG.as_bytes(), &mapping)
+            .unwrap()
+            .boxed()
+            .collect::<Result<Vec<Box<dyn SkySource>>, _>>()
+            .unwrap();
+        let left = vec![SkyPosition::new(30.0, -40.0001)];
+        let table = CrossMatch::new(1.0).match_catalogs(&left, &sources);
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.rows()[0].right, 1);
+    }
+}
@@ -0,0 +1,9 @@
+#[cfg(feature = "arrow")]
+pub mod arrow;
+pub mod fits;
+pub mod generic;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod votable;
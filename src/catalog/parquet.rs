@@ -0,0 +1,422 @@
+use arrow_schema::ArrowError;
+use catalog::arrow::{
+    self, gaia_record_batch, gaia_schema, projected_gaia_records, DEFAULT_BATCH_SIZE,
+};
+use gaia::projection::Projection;
+use gaia::record::GaiaRecord;
+use geom::p2::P2;
+use geom::rect::Rect;
+use parquet::arrow::arrow_reader::{ParquetRecordBatchReader, ParquetRecordBatchReaderBuilder};
+use parquet::arrow::{ArrowWriter, ProjectionMask};
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::errors::ParquetError;
+use parquet::file::metadata::RowGroupMetaData;
+use parquet::file::properties::WriterProperties;
+use parquet::file::reader::ChunkReader;
+use parquet::file::statistics::Statistics;
+use sky::healpix::{self, MAX_ORDER};
+use sky::position::SkySource;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io;
+use std::io::Write;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+/// Errors from reading or writing Parquet files.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Parquet(#[from] ParquetError),
+    #[error(transparent)]
+    Arrow(#[from] arrow::Error),
+}
+
+impl From<ArrowError> for Error {
+    fn from(e: ArrowError) -> Self {
+        Error::Arrow(arrow::Error::from(e))
+    }
+}
+
+/// Filter applied while reading Gaia records from Parquet.
+///
+/// Row groups whose column statistics show that they cannot contain a
+/// matching record are skipped without being decoded; the remaining records
+/// are then filtered exactly.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RowFilter {
+    rect: Option<Rect<f64>>,
+    source_ids: Option<RangeInclusive<u64>>,
+}
+
+impl RowFilter {
+    pub fn new() -> Self {
+        RowFilter::default()
+    }
+
+    /// Only read records whose `(ra, dec)` lies within `rect`.
+    pub fn with_rect(mut self, rect: Rect<f64>) -> Self {
+        self.rect = Some(rect);
+        self
+    }
+
+    /// Only read records whose `source_id` lies within `source_ids`.
+    pub fn with_source_ids(mut self, source_ids: RangeInclusive<u64>) -> Self {
+        self.source_ids = Some(source_ids);
+        self
+    }
+
+    pub fn matches(&self, record: &GaiaRecord) -> bool {
+        self.rect
+            .as_ref()
+            .is_none_or(|rect| rect.contains(&P2::new(record.ra, record.dec)))
+            && self
+                .source_ids
+                .as_ref()
+                .is_none_or(|ids| ids.contains(&record.source_id))
+    }
+
+    /// Check if a row group may contain matching records, according to its
+    /// statistics.
+    fn may_match(&self, row_group: &RowGroupMetaData) -> bool {
+        let overlaps = |name: &str, min: f64, max: f64| {
+            row_group
+                .columns()
+                .iter()
+                .find(|c| c.column_descr().name() == name)
+                .and_then(|c| c.statistics())
+                .and_then(statistics_range)
+                .is_none_or(|(lo, hi)| lo <= max && hi >= min)
+        };
+        let rect_overlaps = self.rect.as_ref().is_none_or(|rect| {
+            overlaps("ra", *rect.x(), rect.x_interval().end())
+                && overlaps("dec", *rect.y(), rect.y_interval().end())
+        });
+        let ids_overlap = self
+            .source_ids
+            .as_ref()
+            .is_none_or(|ids| overlaps("source_id", *ids.start() as f64, *ids.end() as f64));
+        rect_overlaps && ids_overlap
+    }
+}
+
+/// Minimum and maximum of a numeric column chunk.
+fn statistics_range(statistics: &Statistics) -> Option<(f64, f64)> {
+    match statistics {
+        Statistics::Int32(s) => Some((f64::from(*s.min_opt()?), f64::from(*s.max_opt()?))),
+        Statistics::Int64(s) => Some((*s.min_opt()? as f64, *s.max_opt()? as f64)),
+        Statistics::Float(s) => Some((f64::from(*s.min_opt()?), f64::from(*s.max_opt()?))),
+        Statistics::Double(s) => Some((*s.min_opt()?, *s.max_opt()?)),
+        _ => None,
+    }
+}
+
+/// Reader of Gaia records from a Parquet file.
+///
+/// Columns are matched to `GaiaRecord` fields by name (as in
+/// [gaia_records](::catalog::arrow::gaia_records)), and only those columns
+/// are decoded.
+pub struct ParquetReader {
+    batches: ParquetRecordBatchReader,
+    filter: RowFilter,
+    projection: Projection,
+    buffer: std::vec::IntoIter<GaiaRecord>,
+}
+
+impl ParquetReader {
+    pub fn new<R: ChunkReader + 'static>(reader: R) -> Result<Self, Error> {
+        ParquetReader::with_filter(reader, RowFilter::new())
+    }
+
+    pub fn with_filter<R: ChunkReader + 'static>(
+        reader: R,
+        filter: RowFilter,
+    ) -> Result<Self, Error> {
+        ParquetReader::with_projection(reader, filter, Projection::all())
+    }
+
+    /// Read only the columns of a projection, and those the filter needs,
+    /// leaving the other columns of the records cleared (as by
+    /// `Projection::clear_others`).
+    pub fn with_projection<R: ChunkReader + 'static>(
+        reader: R,
+        filter: RowFilter,
+        mut projection: Projection,
+    ) -> Result<Self, Error> {
+        if filter.rect.is_some() {
+            projection = projection.with_position();
+        }
+        if filter.source_ids.is_some() {
+            projection = projection.with_source_id();
+        }
+        let builder = ParquetRecordBatchReaderBuilder::try_new(reader)?;
+        let roots = builder
+            .schema()
+            .fields()
+            .iter()
+            .enumerate()
+            .filter(|(_, field)| projection.contains(field.name()))
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        let mask = ProjectionMask::roots(builder.parquet_schema(), roots);
+        let row_groups = builder
+            .metadata()
+            .row_groups()
+            .iter()
+            .enumerate()
+            .filter(|(_, row_group)| filter.may_match(row_group))
+            .map(|(i, _)| i)
+            .collect();
+        let batches = builder
+            .with_projection(mask)
+            .with_row_groups(row_groups)
+            .with_batch_size(DEFAULT_BATCH_SIZE)
+            .build()?;
+        Ok(ParquetReader {
+            batches,
+            filter,
+            projection,
+            buffer: Vec::new().into_iter(),
+        })
+    }
+}
+
+impl Iterator for ParquetReader {
+    type Item = Result<GaiaRecord, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(record) = self.buffer.next() {
+                return Some(Ok(record));
+            }
+            let batch = match self.batches.next()? {
+                Ok(batch) => batch,
+                Err(e) => return Some(Err(Error::from(e))),
+            };
+            match projected_gaia_records(&batch, &self.projection) {
+                Ok(mut records) => {
+                    records.retain(|r| self.filter.matches(r));
+                    self.buffer = records.into_iter();
+                }
+                Err(e) => return Some(Err(Error::from(e))),
+            }
+        }
+    }
+}
+
+/// Writer of Gaia records to a Parquet file, with all of the Gaia columns.
+pub struct ParquetWriter<W: Write + Send> {
+    writer: ArrowWriter<W>,
+}
+
+impl<W: Write + Send> ParquetWriter<W> {
+    pub fn new(writer: W) -> Result<Self, Error> {
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::ZSTD(ZstdLevel::default()))
+            .build();
+        Ok(ParquetWriter {
+            writer: ArrowWriter::try_new(writer, gaia_schema(), Some(properties))?,
+        })
+    }
+
+    pub fn write(&mut self, records: &[GaiaRecord]) -> Result<(), Error> {
+        for chunk in records.chunks(DEFAULT_BATCH_SIZE) {
+            let batch = gaia_record_batch(chunk)?;
+            self.writer.write(&batch)?;
+        }
+        Ok(())
+    }
+
+    /// Write any buffered records and the file footer.
+    pub fn close(self) -> Result<(), Error> {
+        self.writer.close()?;
+        Ok(())
+    }
+}
+
+/// Writer of Gaia records to a directory of Parquet files, partitioned by
+/// the HEALPix pixel (in the NESTED scheme) containing each record.
+///
+/// The files are laid out using Hive-style partitioning, as
+/// `healpix_<order>=<pixel>/part-0.parquet`, so that the pixel is available as
+/// a column to query engines such as DuckDB, and filters on it only read the
+/// matching files:
+///
+/// ```sql
+/// SELECT source_id, ra, dec
+/// FROM read_parquet('gaia/*/*.parquet', hive_partitioning = true)
+/// WHERE healpix_5 IN (1234, 1235);
+/// ```
+///
+/// One file is kept open for each pixel written, so low orders (which have
+/// `12 * 4^order` pixels) should be used.
+pub struct PartitionedWriter {
+    directory: PathBuf,
+    order: u8,
+    writers: BTreeMap<u64, ParquetWriter<File>>,
+}
+
+impl PartitionedWriter {
+    pub fn new<P: AsRef<Path>>(directory: P, order: u8) -> Result<Self, Error> {
+        if order > MAX_ORDER {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("HEALPix order {} is too large", order),
+            )));
+        }
+        fs::create_dir_all(&directory)?;
+        Ok(PartitionedWriter {
+            directory: directory.as_ref().to_path_buf(),
+            order,
+            writers: BTreeMap::new(),
+        })
+    }
+
+    /// Path of the file holding the records in a pixel.
+    pub fn partition_path(&self, pixel: u64) -> PathBuf {
+        self.directory
+            .join(format!("healpix_{}={}", self.order, pixel))
+            .join("part-0.parquet")
+    }
+
+    pub fn write(&mut self, records: &[GaiaRecord]) -> Result<(), Error> {
+        let mut partitions: BTreeMap<u64, Vec<GaiaRecord>> = BTreeMap::new();
+        for record in records {
+            let pixel = healpix::pixel(self.order, &record.position());
+            partitions.entry(pixel).or_default().push(record.clone());
+        }
+        for (pixel, records) in partitions {
+            if !self.writers.contains_key(&pixel) {
+                let path = self.partition_path(pixel);
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                let writer = ParquetWriter::new(File::create(path)?)?;
+                self.writers.insert(pixel, writer);
+            }
+            if let Some(writer) = self.writers.get_mut(&pixel) {
+                writer.write(&records)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Close all of the files, returning the pixels which were written.
+    pub fn close(self) -> Result<Vec<u64>, Error> {
+        let mut pixels = Vec::new();
+        for (pixel, writer) in self.writers {
+            writer.close()?;
+            pixels.push(pixel);
+        }
+        Ok(pixels)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use catalog::parquet::{ParquetReader, ParquetWriter, PartitionedWriter, RowFilter};
+    use gaia::projection::Projection;
+    use gaia::record::test::sample_record;
+    use gaia::record::GaiaRecord;
+    use geom::rect::Rect;
+    use sky::healpix;
+    use sky::position::SkySource;
+    use std::fs::{self, File};
+
+    fn records() -> Vec<GaiaRecord> {
+        (0..100)
+            .map(|i| {
+                let mut record = sample_record();
+                record.source_id = i;
+                record.ra = i as f64;
+                record.dec = -45.0 + i as f64 / 10.0;
+                record.parallax = if i % 2 == 0 { Some(0.5) } else { None };
+                record.duplicated_source = i % 3 == 0;
+                record.designation = format!("Gaia DR2 {}", i);
+                record
+            })
+            .collect()
+    }
+
+    fn write_file(name: &str, records: &[GaiaRecord]) -> File {
+        let path = std::env::temp_dir().join(name);
+        let mut writer = ParquetWriter::new(File::create(&path).unwrap()).unwrap();
+        writer.write(records).unwrap();
+        writer.close().unwrap();
+        let file = File::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        file
+    }
+
+    #[test]
+    fn round_trip() {
+        let records = records();
+        let file = write_file("starquad-parquet-round-trip.parquet", &records);
+        let read = ParquetReader::new(file)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(read, records);
+    }
+
+    #[test]
+    fn filter() {
+        let file = write_file("starquad-parquet-filter.parquet", &records());
+        let filter = RowFilter::new()
+            .with_rect(Rect::new(10.0, -90.0, 20.0, 180.0).unwrap())
+            .with_source_ids(15..=50);
+        let ids = ParquetReader::with_filter(file, filter)
+            .unwrap()
+            .map(|r| r.unwrap().source_id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, (15..30).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn projection() {
+        let records = records();
+        let file = write_file("starquad-parquet-projection.parquet", &records);
+        let projection = Projection::new(&["parallax", "designation"]).unwrap();
+        let filter = RowFilter::new().with_source_ids(15..=50);
+        let read = ParquetReader::with_projection(file, filter, projection.clone())
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        // the filter's column is read too
+        let projection = projection.with_column("source_id").unwrap();
+        let expected = records[15..=50]
+            .iter()
+            .map(|r| projection.clear_others(r).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(read, expected);
+        assert!(read.iter().all(|r| r.ra == 0.0 && r.pmra.is_none()));
+    }
+
+    #[test]
+    fn partitioned() {
+        let directory = std::env::temp_dir().join("starquad-parquet-partitioned");
+        let _ = fs::remove_dir_all(&directory);
+        let records = records();
+        let mut writer = PartitionedWriter::new(&directory, 2).unwrap();
+        writer.write(&records[..50]).unwrap();
+        writer.write(&records[50..]).unwrap();
+        let pixels = writer.close().unwrap();
+        assert!(pixels.len() > 1);
+
+        let writer = PartitionedWriter::new(&directory, 2).unwrap();
+        let mut read = Vec::new();
+        for pixel in pixels {
+            let file = File::open(writer.partition_path(pixel)).unwrap();
+            for record in ParquetReader::new(file).unwrap() {
+                let record = record.unwrap();
+                assert_eq!(healpix::pixel(2, &record.position()), pixel);
+                read.push(record);
+            }
+        }
+        fs::remove_dir_all(&directory).unwrap();
+        read.sort_by_key(|r| r.source_id);
+        assert_eq!(read, records);
+    }
+}
@@ -0,0 +1,204 @@
+use accel2d::Accel2D;
+use gaia::record::GaiaRecord;
+use gaia::schema::{self, Column, ColumnType};
+use geom::p2::P2;
+use rusqlite::types::Value;
+use rusqlite::{params_from_iter, Connection};
+
+/// Name of the table holding the selected columns.
+pub const TABLE: &str = "gaia_source";
+
+/// Name of the R*Tree virtual table indexing `TABLE` by `(ra, dec)`.
+pub const RTREE_TABLE: &str = "gaia_source_rtree";
+
+/// Errors from writing or reading a SQLite database.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    /// A column name is not a Gaia column.
+    #[error("unknown column: {0}")]
+    UnknownColumn(String),
+    /// A value could not be converted to the type of its column.
+    #[error("invalid value for column {column}: {value:?}")]
+    InvalidValue { column: String, value: String },
+}
+
+fn sql_type(column_type: ColumnType) -> &'static str {
+    match column_type {
+        ColumnType::Boolean | ColumnType::UnsignedByte | ColumnType::Long => "INTEGER",
+        ColumnType::Double => "REAL",
+        ColumnType::Text => "TEXT",
+    }
+}
+
+/// Convert the text form of a value to a SQLite value.
+fn sql_value(column: &Column, text: &str) -> Result<Value, Error> {
+    let invalid = || Error::InvalidValue {
+        column: String::from(column.name),
+        value: String::from(text),
+    };
+    if text.is_empty() && column.column_type != ColumnType::Text {
+        return Ok(Value::Null);
+    }
+    Ok(match column.column_type {
+        ColumnType::Boolean => Value::Integer(text.parse::<bool>().map_err(|_| invalid())? as i64),
+        ColumnType::UnsignedByte | ColumnType::Long => {
+            Value::Integer(text.parse().map_err(|_| invalid())?)
+        }
+        ColumnType::Double => Value::Real(text.parse().map_err(|_| invalid())?),
+        ColumnType::Text => Value::Text(String::from(text)),
+    })
+}
+
+/// Writer of selected Gaia columns to a SQLite database.
+///
+/// Records are stored in the table `gaia_source`, whose `id` column is the
+/// row id of an R*Tree virtual table `gaia_source_rtree` with the bounds
+/// `min_ra`, `max_ra`, `min_dec` and `max_dec`. A cone or box search can then
+/// be done in SQL from any language, for example:
+///
+/// ```sql
+/// SELECT s.* FROM gaia_source s JOIN gaia_source_rtree r ON s.id = r.id
+/// WHERE r.max_ra >= 10 AND r.min_ra <= 11 AND r.max_dec >= -5 AND r.min_dec <= -4;
+/// ```
+///
+/// The R*Tree holds single-precision bounds, so its results must be refined
+/// using the `ra` and `dec` columns when exact boundaries matter.
+pub struct SqliteWriter<'a> {
+    connection: &'a mut Connection,
+    columns: Vec<(usize, &'static Column)>,
+}
+
+impl<'a> SqliteWriter<'a> {
+    /// Create the tables for the named columns. The `ra` and `dec` columns
+    /// are always included.
+    pub fn new(connection: &'a mut Connection, names: &[&str]) -> Result<Self, Error> {
+        let mut columns = Vec::new();
+        for &name in names.iter().chain(["ra", "dec"].iter()) {
+            let index = schema::COLUMNS
+                .iter()
+                .position(|c| c.name == name)
+                .ok_or_else(|| Error::UnknownColumn(String::from(name)))?;
+            if !columns.iter().any(|&(i, _)| i == index) {
+                columns.push((index, &schema::COLUMNS[index]));
+            }
+        }
+        let definitions = columns
+            .iter()
+            .map(|(_, c)| format!("{} {}", c.name, sql_type(c.column_type)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        connection.execute_batch(&format!(
+            "CREATE TABLE {} (id INTEGER PRIMARY KEY, {});
+             CREATE VIRTUAL TABLE {} USING rtree(id, min_ra, max_ra, min_dec, max_dec);",
+            TABLE, definitions, RTREE_TABLE
+        ))?;
+        Ok(SqliteWriter {
+            connection,
+            columns,
+        })
+    }
+
+    /// Append records in a single transaction.
+    pub fn write(&mut self, records: &[GaiaRecord]) -> Result<(), Error> {
+        let names = self.columns.iter().map(|(_, c)| c.name).collect::<Vec<_>>();
+        let placeholders = vec!["?"; names.len()].join(", ");
+        let transaction = self.connection.transaction()?;
+        {
+            let mut insert = transaction.prepare(&format!(
+                "INSERT INTO {} ({}) VALUES ({})",
+                TABLE,
+                names.join(", "),
+                placeholders
+            ))?;
+            let mut insert_rtree = transaction.prepare(&format!(
+                "INSERT INTO {} VALUES (?, ?, ?, ?, ?)",
+                RTREE_TABLE
+            ))?;
+            for (record, fields) in records.iter().zip(schema::string_records(records)?) {
+                let values = self
+                    .columns
+                    .iter()
+                    .map(|&(i, column)| sql_value(column, fields.get(i).unwrap_or("")))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let id = insert.insert(params_from_iter(values))?;
+                insert_rtree.execute(rusqlite::params![
+                    id, record.ra, record.ra, record.dec, record.dec
+                ])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+}
+
+/// Rebuild a spatial index from a database written by `SqliteWriter`. The
+/// items of the index are the `id`s of the rows of `gaia_source`, and their
+/// points are the full-precision `(ra, dec)` of each row.
+pub fn read_index<A>(connection: &Connection) -> Result<A, Error>
+where
+    A: Accel2D<Scalar = f64, Item = i64>,
+{
+    let mut statement = connection.prepare(&format!("SELECT id, ra, dec FROM {}", TABLE))?;
+    let items = statement
+        .query_map([], |row| {
+            Ok((
+                P2::new(row.get::<_, f64>(1)?, row.get::<_, f64>(2)?),
+                row.get(0)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(A::new_from_vec(items))
+}
+
+#[cfg(test)]
+mod test {
+    use accel2d::Accel2D;
+    use catalog::sqlite::{read_index, SqliteWriter};
+    use gaia::record::test::sample_record;
+    use geom::rect::Rect;
+    use rusqlite::Connection;
+    use sky::index::SkyIndex;
+
+    #[test]
+    fn write_and_read() {
+        let records = (0..50)
+            .map(|i| {
+                let mut record = sample_record();
+                record.source_id = 1000 + i;
+                record.ra = i as f64;
+                record.dec = (i as f64) / 2.0 - 10.0;
+                record.parallax = if i % 2 == 0 { Some(1.0) } else { None };
+                record
+            })
+            .collect::<Vec<_>>();
+        let mut connection = Connection::open_in_memory().unwrap();
+        let mut writer = SqliteWriter::new(&mut connection, &["source_id", "parallax"]).unwrap();
+        writer.write(&records).unwrap();
+
+        let ids = connection
+            .prepare(
+                "SELECT s.source_id FROM gaia_source s JOIN gaia_source_rtree r ON s.id = r.id
+                 WHERE r.max_ra >= 9.5 AND r.min_ra <= 12.5 AND s.parallax IS NOT NULL
+                 ORDER BY s.source_id",
+            )
+            .unwrap()
+            .query_map([], |row| row.get::<_, i64>(0))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(ids, vec![1010, 1012]);
+
+        let index: SkyIndex<i64> = read_index(&connection).unwrap();
+        let mut rows = index
+            .query_rect(&Rect::new(20.0, -90.0, 2.0, 180.0).unwrap())
+            .iter()
+            .map(|(_, id)| *id)
+            .collect::<Vec<_>>();
+        rows.sort();
+        assert_eq!(rows, vec![21, 22]);
+    }
+}
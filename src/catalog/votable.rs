@@ -0,0 +1,835 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use catalog::generic::{self, ColumnMapping, GenericSource, SourceParser};
+use csv::StringRecord;
+use gaia::record::GaiaRecord;
+use gaia::schema::{self, ColumnType, COLUMNS};
+use quick_xml::escape::escape;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use sky::position::SkySource;
+use std::io;
+use std::io::{BufRead, Write};
+
+/// Primitive datatype of a VOTable field.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Datatype {
+    Boolean,
+    UnsignedByte,
+    Short,
+    Int,
+    Long,
+    Char,
+    UnicodeChar,
+    Float,
+    Double,
+}
+
+impl Datatype {
+    pub fn parse(name: &str) -> Option<Datatype> {
+        match name {
+            "boolean" => Some(Datatype::Boolean),
+            "unsignedByte" => Some(Datatype::UnsignedByte),
+            "short" => Some(Datatype::Short),
+            "int" => Some(Datatype::Int),
+            "long" => Some(Datatype::Long),
+            "char" => Some(Datatype::Char),
+            "unicodeChar" => Some(Datatype::UnicodeChar),
+            "float" => Some(Datatype::Float),
+            "double" => Some(Datatype::Double),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Datatype::Boolean => "boolean",
+            Datatype::UnsignedByte => "unsignedByte",
+            Datatype::Short => "short",
+            Datatype::Int => "int",
+            Datatype::Long => "long",
+            Datatype::Char => "char",
+            Datatype::UnicodeChar => "unicodeChar",
+            Datatype::Float => "float",
+            Datatype::Double => "double",
+        }
+    }
+
+    /// Size in bytes of a single element in the binary serializations.
+    fn size(&self) -> usize {
+        match self {
+            Datatype::Boolean | Datatype::UnsignedByte | Datatype::Char => 1,
+            Datatype::Short | Datatype::UnicodeChar => 2,
+            Datatype::Int | Datatype::Float => 4,
+            Datatype::Long | Datatype::Double => 8,
+        }
+    }
+
+    fn is_text(&self) -> bool {
+        *self == Datatype::Char || *self == Datatype::UnicodeChar
+    }
+}
+
+impl From<ColumnType> for Datatype {
+    fn from(column_type: ColumnType) -> Self {
+        match column_type {
+            ColumnType::Boolean => Datatype::Boolean,
+            ColumnType::UnsignedByte => Datatype::UnsignedByte,
+            ColumnType::Long => Datatype::Long,
+            ColumnType::Double => Datatype::Double,
+            ColumnType::Text => Datatype::Char,
+        }
+    }
+}
+
+/// Description of a column of a VOTable.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Field {
+    pub name: String,
+    pub datatype: Datatype,
+    pub arraysize: Option<String>,
+    pub unit: Option<String>,
+    pub ucd: Option<String>,
+    /// Value which represents null, from the field's `VALUES` element.
+    pub null: Option<String>,
+}
+
+impl Field {
+    pub fn new(name: &str, datatype: Datatype) -> Self {
+        let arraysize = if datatype.is_text() {
+            Some(String::from("*"))
+        } else {
+            None
+        };
+        Field {
+            name: String::from(name),
+            datatype,
+            arraysize,
+            unit: None,
+            ucd: None,
+            null: None,
+        }
+    }
+
+    pub fn with_unit(mut self, unit: &str) -> Self {
+        self.unit = Some(String::from(unit));
+        self
+    }
+
+    pub fn with_ucd(mut self, ucd: &str) -> Self {
+        self.ucd = Some(String::from(ucd));
+        self
+    }
+
+    /// Number of elements in a binary cell, or `None` for variable-length
+    /// arrays (which are prefixed by their length).
+    fn fixed_count(&self) -> Result<Option<usize>, Error> {
+        match &self.arraysize {
+            None => Ok(Some(1)),
+            Some(size) if size.ends_with('*') => Ok(None),
+            Some(size) => size
+                .split('x')
+                .map(|n| n.parse::<usize>())
+                .product::<Result<usize, _>>()
+                .map(Some)
+                .map_err(|_| Error::Format(format!("invalid arraysize {:?}", size))),
+        }
+    }
+
+    fn is_scalar(&self) -> bool {
+        self.arraysize.as_ref().is_none_or(|size| size == "1")
+    }
+}
+
+/// Value of a single VOTable cell.
+///
+/// Numeric arrays are represented as `Text` in their TABLEDATA form (values
+/// separated by spaces).
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Boolean(bool),
+    Integer(i64),
+    Float(f64),
+    Text(String),
+}
+
+impl Value {
+    /// Text form of the value, as used in TABLEDATA (and CSV) cells. Nulls
+    /// are represented by empty strings.
+    pub fn to_text(&self) -> String {
+        match self {
+            Value::Null => String::new(),
+            Value::Boolean(b) => String::from(if *b { "true" } else { "false" }),
+            Value::Integer(i) => i.to_string(),
+            Value::Float(f) => f.to_string(),
+            Value::Text(s) => s.clone(),
+        }
+    }
+
+    /// Parse the text form of a value.
+    fn parse(field: &Field, text: &str) -> Result<Value, Error> {
+        let text = text.trim();
+        if text.is_empty() || field.null.as_ref().is_some_and(|null| null == text) {
+            return Ok(Value::Null);
+        }
+        if field.datatype.is_text() || !field.is_scalar() {
+            return Ok(Value::Text(String::from(text)));
+        }
+        let invalid = || {
+            Error::Format(format!(
+                "invalid {} value for field {}: {:?}",
+                field.datatype.name(),
+                field.name,
+                text
+            ))
+        };
+        match field.datatype {
+            Datatype::Boolean => match text {
+                "T" | "t" | "true" | "1" => Ok(Value::Boolean(true)),
+                "F" | "f" | "false" | "0" => Ok(Value::Boolean(false)),
+                "?" => Ok(Value::Null),
+                _ => Err(invalid()),
+            },
+            Datatype::Float | Datatype::Double => {
+                let value = text.parse::<f64>().map_err(|_| invalid())?;
+                Ok(if value.is_nan() {
+                    Value::Null
+                } else {
+                    Value::Float(value)
+                })
+            }
+            _ => {
+                let parsed = if let Some(hex) = text.strip_prefix("0x") {
+                    i64::from_str_radix(hex, 16)
+                } else {
+                    text.parse::<i64>()
+                };
+                parsed.map(Value::Integer).map_err(|_| invalid())
+            }
+        }
+    }
+}
+
+/// Errors from reading a VOTable.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Xml(#[from] quick_xml::Error),
+    #[error(transparent)]
+    Base64(#[from] base64::DecodeError),
+    /// The document is not a VOTable that can be read.
+    #[error("{0}")]
+    Format(String),
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    #[error(transparent)]
+    Generic(#[from] generic::Error),
+}
+
+impl From<quick_xml::events::attributes::AttrError> for Error {
+    fn from(e: quick_xml::events::attributes::AttrError) -> Self {
+        Error::Xml(quick_xml::Error::from(e))
+    }
+}
+
+/// A table read from, or to be written to, a VOTable document.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VoTable {
+    pub name: Option<String>,
+    pub fields: Vec<Field>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+/// Serialization of the DATA element being read.
+#[derive(Clone, Copy, PartialEq)]
+enum Serialization {
+    TableData,
+    Binary,
+    Binary2,
+}
+
+fn attribute(element: &BytesStart, name: &str) -> Result<Option<String>, Error> {
+    match element.try_get_attribute(name)? {
+        Some(attr) => Ok(Some(attr.unescape_value()?.into_owned())),
+        None => Ok(None),
+    }
+}
+
+impl VoTable {
+    /// Read the first TABLE of a VOTable document.
+    ///
+    /// TABLEDATA, BINARY and BINARY2 serializations are supported; streams
+    /// must be embedded in the document with base64 encoding.
+    pub fn read<R: BufRead>(reader: R) -> Result<VoTable, Error> {
+        let mut reader = Reader::from_reader(reader);
+        let mut buf = Vec::new();
+        let mut table = VoTable::default();
+        let mut in_table = false;
+        let mut serialization = None;
+        let mut row: Option<Vec<Value>> = None;
+        let mut text: Option<String> = None;
+
+        loop {
+            let event = reader.read_event_into(&mut buf)?;
+            match &event {
+                Event::Start(e) | Event::Empty(e) => {
+                    let empty = matches!(event, Event::Empty(_));
+                    match e.local_name().as_ref() {
+                        b"TABLE" => {
+                            in_table = true;
+                            table.name = attribute(e, "name")?;
+                        }
+                        b"FIELD" if in_table => {
+                            let name = attribute(e, "name")?.unwrap_or_default();
+                            let datatype_name = attribute(e, "datatype")?.unwrap_or_default();
+                            let datatype = Datatype::parse(&datatype_name).ok_or_else(|| {
+                                Error::Format(format!(
+                                    "unsupported datatype {:?} for field {}",
+                                    datatype_name, name
+                                ))
+                            })?;
+                            table.fields.push(Field {
+                                name,
+                                datatype,
+                                arraysize: attribute(e, "arraysize")?,
+                                unit: attribute(e, "unit")?,
+                                ucd: attribute(e, "ucd")?,
+                                null: None,
+                            });
+                        }
+                        b"VALUES" if in_table => {
+                            if let (Some(field), Some(null)) =
+                                (table.fields.last_mut(), attribute(e, "null")?)
+                            {
+                                field.null = Some(null);
+                            }
+                        }
+                        b"TABLEDATA" if in_table => serialization = Some(Serialization::TableData),
+                        b"BINARY" if in_table => serialization = Some(Serialization::Binary),
+                        b"BINARY2" if in_table => serialization = Some(Serialization::Binary2),
+                        b"STREAM" if in_table => {
+                            if attribute(e, "href")?.is_some() {
+                                return Err(Error::Format(String::from(
+                                    "external streams are not supported",
+                                )));
+                            }
+                            text = Some(String::new());
+                        }
+                        b"TR" if serialization == Some(Serialization::TableData) => {
+                            row = Some(Vec::new());
+                        }
+                        b"TD" => {
+                            if empty {
+                                if let Some(row) = row.as_mut() {
+                                    row.push(Value::Null);
+                                }
+                            } else {
+                                text = Some(String::new());
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                Event::Text(t) => {
+                    if let Some(text) = text.as_mut() {
+                        text.push_str(&t.unescape()?);
+                    }
+                }
+                Event::CData(t) => {
+                    if let Some(text) = text.as_mut() {
+                        text.push_str(&String::from_utf8_lossy(t));
+                    }
+                }
+                Event::End(e) => match e.local_name().as_ref() {
+                    b"TD" => {
+                        if let (Some(row), Some(cell)) = (row.as_mut(), text.take()) {
+                            let field = table.fields.get(row.len()).ok_or_else(|| {
+                                Error::Format(String::from("more cells than fields"))
+                            })?;
+                            row.push(Value::parse(field, &cell)?);
+                        }
+                    }
+                    b"TR" => {
+                        if let Some(mut row) = row.take() {
+                            row.resize(table.fields.len(), Value::Null);
+                            table.rows.push(row);
+                        }
+                    }
+                    b"STREAM" => {
+                        let encoded = text.take().unwrap_or_default();
+                        let encoded = encoded
+                            .chars()
+                            .filter(|c| !c.is_whitespace())
+                            .collect::<String>();
+                        let bytes = STANDARD.decode(encoded)?;
+                        let with_nulls = serialization == Some(Serialization::Binary2);
+                        table.rows = read_binary(&bytes, &table.fields, with_nulls)?;
+                    }
+                    b"TABLE" => break,
+                    _ => {}
+                },
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+        if !in_table {
+            return Err(Error::Format(String::from("no TABLE element found")));
+        }
+        Ok(table)
+    }
+
+    /// Write the table as a VOTable document using TABLEDATA serialization.
+    pub fn write<W: Write>(&self, writer: W) -> io::Result<()> {
+        self.write_document(writer, None)
+    }
+
+    /// Write the table as a VOTable document, followed by an `INFO` element
+    /// named `QUERY_STATUS` whose value is `OVERFLOW`, reporting that the
+    /// query which found its rows had more.
+    pub fn write_overflow<W: Write>(&self, writer: W, message: &str) -> io::Result<()> {
+        self.write_document(writer, Some(message))
+    }
+
+    fn write_document<W: Write>(&self, mut writer: W, overflow: Option<&str>) -> io::Result<()> {
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            writer,
+            r#"<VOTABLE version="1.4" xmlns="http://www.ivoa.net/xml/VOTable/v1.3">"#
+        )?;
+        writeln!(writer, r#"<RESOURCE type="results">"#)?;
+        match &self.name {
+            Some(name) => writeln!(writer, r#"<TABLE name="{}">"#, escape(name.as_str()))?,
+            None => writeln!(writer, "<TABLE>")?,
+        }
+        for field in &self.fields {
+            write!(
+                writer,
+                r#"<FIELD name="{}" datatype="{}""#,
+                escape(field.name.as_str()),
+                field.datatype.name()
+            )?;
+            for (attr, value) in [
+                ("arraysize", &field.arraysize),
+                ("unit", &field.unit),
+                ("ucd", &field.ucd),
+            ] {
+                if let Some(value) = value {
+                    write!(writer, r#" {}="{}""#, attr, escape(value.as_str()))?;
+                }
+            }
+            writeln!(writer, "/>")?;
+        }
+        writeln!(writer, "<DATA><TABLEDATA>")?;
+        for row in &self.rows {
+            write!(writer, "<TR>")?;
+            for value in row {
+                match value {
+                    Value::Null => write!(writer, "<TD/>")?,
+                    Value::Boolean(b) => write!(writer, "<TD>{}</TD>", if *b { "T" } else { "F" })?,
+                    other => write!(writer, "<TD>{}</TD>", escape(other.to_text().as_str()))?,
+                }
+            }
+            writeln!(writer, "</TR>")?;
+        }
+        writeln!(writer, "</TABLEDATA></DATA>")?;
+        writeln!(writer, "</TABLE>")?;
+        if let Some(message) = overflow {
+            writeln!(
+                writer,
+                r#"<INFO name="QUERY_STATUS" value="OVERFLOW">{}</INFO>"#,
+                escape(message)
+            )?;
+        }
+        writeln!(writer, "</RESOURCE>")?;
+        writeln!(writer, "</VOTABLE>")?;
+        Ok(())
+    }
+
+    /// Names of the table's fields, as a header record.
+    fn headers(&self) -> StringRecord {
+        self.fields.iter().map(|f| f.name.as_str()).collect()
+    }
+
+    fn string_records(&self) -> impl Iterator<Item = StringRecord> + '_ {
+        self.rows
+            .iter()
+            .map(|row| row.iter().map(Value::to_text).collect::<StringRecord>())
+    }
+
+    /// Convert the rows of the table into Gaia records, matching fields to
+    /// record fields by name.
+    pub fn gaia_records(&self) -> Result<Vec<GaiaRecord>, Error> {
+        let headers = self.headers();
+        self.string_records()
+            .map(|record| record.deserialize(Some(&headers)).map_err(Error::from))
+            .collect()
+    }
+
+    /// Convert the rows of the table into generic sources.
+    pub fn sources(&self, mapping: &ColumnMapping) -> Result<Vec<GenericSource>, Error> {
+        let parser = SourceParser::new(&self.headers(), mapping)?;
+        self.string_records()
+            .map(|record| parser.parse(&record).map_err(Error::from))
+            .collect()
+    }
+
+    /// Create a table of Gaia records, with all of the Gaia columns and their
+    /// units. The `source_id`, `ra` and `dec` fields are marked as the main
+    /// identifier and position.
+    pub fn from_gaia_records(records: &[GaiaRecord]) -> Result<VoTable, Error> {
+        let fields = COLUMNS
+            .iter()
+            .map(|column| {
+                let mut field = Field::new(column.name, Datatype::from(column.column_type));
+                if let Some(unit) = schema::unit(column.name) {
+                    field = field.with_unit(unit.symbol());
+                }
+                match column.name {
+                    "source_id" => field.with_ucd("meta.id;meta.main"),
+                    "ra" => field.with_ucd("pos.eq.ra;meta.main"),
+                    "dec" => field.with_ucd("pos.eq.dec;meta.main"),
+                    _ => field,
+                }
+            })
+            .collect::<Vec<_>>();
+        let rows = schema::string_records(records)?
+            .iter()
+            .map(|record| {
+                fields
+                    .iter()
+                    .zip(record.iter())
+                    .map(|(field, text)| Value::parse(field, text))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(VoTable {
+            name: Some(String::from("gaia_source")),
+            fields,
+            rows,
+        })
+    }
+
+    /// Keep only the fields (and their cells) for which `keep` is true.
+    pub fn retain_fields<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&Field) -> bool,
+    {
+        let kept = self.fields.iter().map(&mut keep).collect::<Vec<_>>();
+        let mut flags = kept.iter();
+        self.fields.retain(|_| *flags.next().unwrap_or(&false));
+        for row in &mut self.rows {
+            let mut flags = kept.iter();
+            row.retain(|_| *flags.next().unwrap_or(&false));
+        }
+    }
+
+    /// Create a table of the identifiers, positions and magnitudes of a
+    /// collection of sources.
+    pub fn from_sources<I>(sources: I) -> VoTable
+    where
+        I: IntoIterator,
+        I::Item: SkySource,
+    {
+        let fields = vec![
+            Field::new("id", Datatype::Char).with_ucd("meta.id;meta.main"),
+            Field::new("ra", Datatype::Double)
+                .with_unit("deg")
+                .with_ucd("pos.eq.ra;meta.main"),
+            Field::new("dec", Datatype::Double)
+                .with_unit("deg")
+                .with_ucd("pos.eq.dec;meta.main"),
+            Field::new("mag", Datatype::Double)
+                .with_unit("mag")
+                .with_ucd("phot.mag"),
+        ];
+        let optional_float = |value: Option<f64>| value.map_or(Value::Null, Value::Float);
+        let rows = sources
+            .into_iter()
+            .map(|source| {
+                let position = source.position();
+                vec![
+                    source.id().map_or(Value::Null, Value::Text),
+                    Value::Float(position.ra),
+                    Value::Float(position.dec),
+                    optional_float(source.magnitude()),
+                ]
+            })
+            .collect();
+        VoTable {
+            name: None,
+            fields,
+            rows,
+        }
+    }
+}
+
+/// Write a VOTable document reporting that a query failed, with an `INFO`
+/// element named `QUERY_STATUS` whose value is `ERROR`.
+pub fn write_error<W: Write>(mut writer: W, message: &str) -> io::Result<()> {
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        writer,
+        r#"<VOTABLE version="1.4" xmlns="http://www.ivoa.net/xml/VOTable/v1.3">"#
+    )?;
+    writeln!(writer, r#"<RESOURCE type="results">"#)?;
+    writeln!(
+        writer,
+        r#"<INFO name="QUERY_STATUS" value="ERROR">{}</INFO>"#,
+        escape(message)
+    )?;
+    writeln!(writer, "</RESOURCE>")?;
+    writeln!(writer, "</VOTABLE>")?;
+    Ok(())
+}
+
+/// Decode the rows of a BINARY or BINARY2 stream.
+fn read_binary(bytes: &[u8], fields: &[Field], with_nulls: bool) -> Result<Vec<Vec<Value>>, Error> {
+    let truncated = || Error::Format(String::from("truncated binary stream"));
+    let take = |pos: &mut usize, n: usize| -> Result<&[u8], Error> {
+        let slice = bytes.get(*pos..*pos + n).ok_or_else(truncated)?;
+        *pos += n;
+        Ok(slice)
+    };
+    let counts = fields
+        .iter()
+        .map(Field::fixed_count)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut rows = Vec::new();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let null_flags = if with_nulls {
+            take(&mut pos, fields.len().div_ceil(8))?.to_vec()
+        } else {
+            Vec::new()
+        };
+        let mut row = Vec::with_capacity(fields.len());
+        for (i, (field, count)) in fields.iter().zip(counts.iter()).enumerate() {
+            let count = match count {
+                Some(count) => *count,
+                None => {
+                    let len = take(&mut pos, 4)?;
+                    u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize
+                }
+            };
+            let data = take(&mut pos, count * field.datatype.size())?;
+            let is_null = with_nulls && null_flags[i / 8] & (0x80 >> (i % 8)) != 0;
+            row.push(if is_null {
+                Value::Null
+            } else {
+                decode_cell(field, data, count)?
+            });
+        }
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+/// Decode the binary representation of a single cell.
+fn decode_cell(field: &Field, data: &[u8], count: usize) -> Result<Value, Error> {
+    let text = match field.datatype {
+        Datatype::Char => {
+            let end = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+            String::from_utf8_lossy(&data[..end]).trim_end().to_string()
+        }
+        Datatype::UnicodeChar => {
+            let units = data
+                .chunks(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                .take_while(|u| *u != 0)
+                .collect::<Vec<_>>();
+            String::from_utf16_lossy(&units).trim_end().to_string()
+        }
+        _ => {
+            let size = field.datatype.size();
+            let elements = data
+                .chunks(size)
+                .map(|c| decode_element(field.datatype, c))
+                .collect::<Vec<_>>();
+            if count == 1 {
+                return Ok(match elements.into_iter().next() {
+                    Some(Value::Integer(i)) if field.null.as_ref() == Some(&i.to_string()) => {
+                        Value::Null
+                    }
+                    Some(value) => value,
+                    None => Value::Null,
+                });
+            }
+            elements
+                .iter()
+                .map(|e| match e {
+                    Value::Null => String::from("NaN"),
+                    Value::Boolean(b) => String::from(if *b { "T" } else { "F" }),
+                    other => other.to_text(),
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        }
+    };
+    Ok(if text.is_empty() {
+        Value::Null
+    } else {
+        Value::Text(text)
+    })
+}
+
+fn decode_element(datatype: Datatype, c: &[u8]) -> Value {
+    let float = |f: f64| {
+        if f.is_nan() {
+            Value::Null
+        } else {
+            Value::Float(f)
+        }
+    };
+    match datatype {
+        Datatype::Boolean => match c[0] {
+            b'T' | b't' | b'1' => Value::Boolean(true),
+            b'F' | b'f' | b'0' => Value::Boolean(false),
+            _ => Value::Null,
+        },
+        Datatype::UnsignedByte => Value::Integer(i64::from(c[0])),
+        Datatype::Short => Value::Integer(i64::from(i16::from_be_bytes([c[0], c[1]]))),
+        Datatype::Int => Value::Integer(i64::from(i32::from_be_bytes([c[0], c[1], c[2], c[3]]))),
+        Datatype::Long => Value::Integer(i64::from_be_bytes([
+            c[0], c[1], c[2], c[3], c[4], c[5], c[6], c[7],
+        ])),
+        Datatype::Float => float(f64::from(f32::from_be_bytes([c[0], c[1], c[2], c[3]]))),
+        Datatype::Double => float(f64::from_be_bytes([
+            c[0], c[1], c[2], c[3], c[4], c[5], c[6], c[7],
+        ])),
+        Datatype::Char | Datatype::UnicodeChar => Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use catalog::generic::ColumnMapping;
+    use catalog::votable::{Value, VoTable};
+    use gaia::record::test::sample_record;
+    use sky::position::{SkyPosition, SkySource};
+
+    const TABLEDATA: &str = r#"<?xml version="1.0"?>
+<VOTABLE version="1.3" xmlns="http://www.ivoa.net/xml/VOTable/v1.3">
+<RESOURCE><TABLE name="results">
+  <FIELD name="source_id" datatype="long"><VALUES null="-1"/></FIELD>
+  <FIELD name="ra" datatype="double" unit="deg"/>
+  <FIELD name="dec" datatype="double" unit="deg"/>
+  <FIELD name="phot_g_mean_mag" datatype="float"/>
+  <FIELD name="flag" datatype="boolean"/>
+  <FIELD name="name" datatype="char" arraysize="*"/>
+  <DATA><TABLEDATA>
+    <TR><TD>42</TD><TD>10.5</TD><TD>-20.25</TD><TD>15.5</TD><TD>T</TD><TD>a &amp; b</TD></TR>
+    <TR><TD>-1</TD><TD>11</TD><TD>-21</TD><TD/><TD>F</TD><TD></TD></TR>
+  </TABLEDATA></DATA>
+</TABLE></RESOURCE>
+</VOTABLE>"#;
+
+    #[test]
+    fn read_tabledata() {
+        let table = VoTable::read(TABLEDATA.as_bytes()).unwrap();
+        assert_eq!(table.name, Some(String::from("results")));
+        assert_eq!(table.fields.len(), 6);
+        assert_eq!(table.fields[1].unit, Some(String::from("deg")));
+        assert_eq!(
+            table.rows[0],
+            vec![
+                Value::Integer(42),
+                Value::Float(10.5),
+                Value::Float(-20.25),
+                Value::Float(15.5),
+                Value::Boolean(true),
+                Value::Text(String::from("a & b")),
+            ]
+        );
+        assert_eq!(table.rows[1][0], Value::Null);
+        assert_eq!(table.rows[1][3], Value::Null);
+        assert_eq!(table.rows[1][5], Value::Null);
+    }
+
+    #[test]
+    fn read_binary2() {
+        let mut bytes = Vec::new();
+        // row 1: no nulls
+        bytes.push(0u8);
+        bytes.extend_from_slice(&7i32.to_be_bytes());
+        bytes.extend_from_slice(&1.5f64.to_be_bytes());
+        bytes.extend_from_slice(&3u32.to_be_bytes());
+        bytes.extend_from_slice(b"abc");
+        // row 2: second field null
+        bytes.push(0x40u8);
+        bytes.extend_from_slice(&8i32.to_be_bytes());
+        bytes.extend_from_slice(&0f64.to_be_bytes());
+        bytes.extend_from_slice(&0u32.to_be_bytes());
+        let document = format!(
+            r#"<VOTABLE><RESOURCE><TABLE>
+<FIELD name="n" datatype="int"/>
+<FIELD name="x" datatype="double"/>
+<FIELD name="s" datatype="char" arraysize="*"/>
+<DATA><BINARY2><STREAM encoding="base64">{}</STREAM></BINARY2></DATA>
+</TABLE></RESOURCE></VOTABLE>"#,
+            STANDARD.encode(&bytes)
+        );
+        let table = VoTable::read(document.as_bytes()).unwrap();
+        assert_eq!(
+            table.rows,
+            vec![
+                vec![
+                    Value::Integer(7),
+                    Value::Float(1.5),
+                    Value::Text(String::from("abc"))
+                ],
+                vec![Value::Integer(8), Value::Null, Value::Null],
+            ]
+        );
+    }
+
+    #[test]
+    fn sources() {
+        let table = VoTable::read(TABLEDATA.as_bytes()).unwrap();
+        let mapping: ColumnMapping = "id=source_id,ra=ra,dec=dec,mag=phot_g_mean_mag"
+            .parse()
+            .unwrap();
+        let sources = table.sources(&mapping).unwrap();
+        assert_eq!(sources[0].id(), Some(String::from("42")));
+        assert_eq!(sources[0].position(), SkyPosition::new(10.5, -20.25));
+        assert_eq!(sources[1].magnitude(), None);
+    }
+
+    #[test]
+    fn gaia_round_trip() {
+        let mut a = sample_record();
+        a.source_id = 5;
+        a.ra = 123.456;
+        a.parallax = Some(1.25);
+        a.duplicated_source = true;
+        a.phot_variable_flag = String::from("NOT_AVAILABLE");
+        let records = vec![a, sample_record()];
+
+        let mut document = Vec::new();
+        VoTable::from_gaia_records(&records)
+            .unwrap()
+            .write(&mut document)
+            .unwrap();
+        let table = VoTable::read(document.as_slice()).unwrap();
+        assert_eq!(table.gaia_records().unwrap(), records);
+        let pmra = table.fields.iter().find(|f| f.name == "pmra").unwrap();
+        assert_eq!(pmra.unit.as_deref(), Some("mas.yr**-1"));
+    }
+
+    #[test]
+    fn sources_round_trip() {
+        let sources = vec![SkyPosition::new(1.0, 2.0), SkyPosition::new(3.0, 4.0)];
+        let mut document = Vec::new();
+        VoTable::from_sources(&sources)
+            .write(&mut document)
+            .unwrap();
+        let table = VoTable::read(document.as_slice()).unwrap();
+        let read = table.sources(&ColumnMapping::new("ra", "dec")).unwrap();
+        let positions = read.iter().map(|s| s.position()).collect::<Vec<_>>();
+        assert_eq!(positions, sources);
+    }
+}
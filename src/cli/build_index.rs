@@ -0,0 +1,198 @@
+use clap::Args;
+use cli::progress::Bar;
+use cli::{expand_inputs, interrupt_token, read_tracked, Position, Result};
+use starquad::gaia::filter::Filter;
+use starquad::gaia::projection::Projection;
+use starquad::progress::Tracker;
+use starquad::store::builder::{StoreBuilder, DEFAULT_ORDER};
+use starquad::store::manifest::{Checkpoint, Manifest, ShardFormat, FORMAT_VERSION};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Astrometric quality cuts of Lindegren et al. (2018), section 4.
+const QUALITY_CUTS: &str =
+    "parallax_over_error > 10 && visibility_periods_used > 8 && astrometric_excess_noise < 1";
+
+#[derive(Args)]
+pub struct BuildIndexArgs {
+    /// Gaia CSV files (optionally gzipped), or directories of them.
+    #[arg(required_unless_present = "resume")]
+    inputs: Vec<PathBuf>,
+    /// Directory of the index.
+    #[arg(short, long)]
+    output: PathBuf,
+    /// HEALPix order of the index shards.
+    #[arg(long, visible_alias = "level", default_value_t = DEFAULT_ORDER)]
+    order: u8,
+    /// Shard file format: bincode, csv, parquet or zstd (if enabled).
+    #[arg(long, default_value = "bincode")]
+    format: ShardFormat,
+    /// Comma-separated columns to store (default: all). Other columns are
+    /// cleared.
+    #[arg(long, value_delimiter = ',')]
+    columns: Vec<String>,
+    /// Only index records matching a filter expression. May be repeated.
+    #[arg(long)]
+    filter: Vec<Filter>,
+    /// Only index records passing the astrometric quality cuts of
+    /// Lindegren et al. (2018).
+    #[arg(long)]
+    quality: bool,
+    /// Also write an index of the records by source_id, for `lookup`.
+    #[arg(long)]
+    source_index: bool,
+    /// Comma-separated numeric columns of which each shard records the
+    /// range, for filtered queries to skip shards [default:
+    /// phot_g_mean_mag,parallax].
+    #[arg(long, value_delimiter = ',')]
+    zone_maps: Option<Vec<String>>,
+    /// Record no column ranges in the shards.
+    #[arg(long, conflicts_with = "zone_maps")]
+    no_zone_maps: bool,
+    /// Also write a Bloom filter of the sources of each shard, for lookups
+    /// without a source index and queries filtered on source_id.
+    #[arg(long)]
+    bloom_filters: bool,
+    /// Version of the index format to write, for older readers.
+    #[arg(long, default_value_t = FORMAT_VERSION)]
+    format_version: u32,
+    /// Continue a build of the output index which was interrupted, reading
+    /// the rest of its inputs. The filters must be the same as before;
+    /// the other options are taken from the index.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "inputs", "order", "format", "columns", "source_index", "zone_maps",
+            "no_zone_maps", "bloom_filters", "format_version"
+        ]
+    )]
+    resume: bool,
+}
+
+/// Summary of an index build.
+struct BuildReport {
+    files: usize,
+    read: u64,
+    rejected: u64,
+    elapsed: Duration,
+    bytes: u64,
+}
+
+impl BuildReport {
+    fn print(&self, manifest: &Manifest) {
+        let mut sizes = manifest
+            .shards
+            .iter()
+            .map(|s| s.records)
+            .collect::<Vec<_>>();
+        sizes.sort_unstable();
+        let indexed = manifest.records();
+        println!("input files:      {}", self.files);
+        println!("records read:     {}", self.read);
+        println!("rejected by cuts: {}", self.rejected);
+        println!("records indexed:  {}", indexed);
+        println!(
+            "shards:           {} (HEALPix order {}, {:?})",
+            sizes.len(),
+            manifest.order,
+            manifest.format
+        );
+        if !sizes.is_empty() {
+            println!(
+                "shard records:    min {}, median {}, max {}",
+                sizes[0],
+                sizes[sizes.len() / 2],
+                sizes[sizes.len() - 1]
+            );
+        }
+        println!(
+            "size on disk:     {:.1} MiB",
+            self.bytes as f64 / (1024.0 * 1024.0)
+        );
+        println!("elapsed:          {:.1} s", self.elapsed.as_secs_f64());
+    }
+}
+
+fn directory_size(dir: &Path) -> Result<u64> {
+    let mut bytes = 0;
+    for entry in fs::read_dir(dir)? {
+        bytes += entry?.metadata()?.len();
+    }
+    Ok(bytes)
+}
+
+pub fn run(args: BuildIndexArgs) -> Result<()> {
+    let start = Instant::now();
+    let mut filters = args.filter;
+    if args.quality {
+        filters.push(QUALITY_CUTS.parse()?);
+    }
+    let (mut builder, inputs, from) = if args.resume {
+        let (builder, checkpoint) = StoreBuilder::resume(&args.output)?;
+        let from = Position {
+            files: checkpoint.files,
+            records: checkpoint.records,
+        };
+        (builder, checkpoint.inputs, from)
+    } else {
+        let mut builder = StoreBuilder::new(&args.output)?
+            .with_version(args.format_version)?
+            .with_order(args.order)
+            .with_format(args.format);
+        if !args.columns.is_empty() {
+            builder = builder.with_columns(Projection::new(&args.columns)?);
+        }
+        if args.source_index {
+            builder = builder.with_source_index()?;
+        }
+        if let Some(columns) = &args.zone_maps {
+            builder =
+                builder.with_zone_maps(&columns.iter().map(String::as_str).collect::<Vec<_>>())?;
+        } else if args.no_zone_maps {
+            builder = builder.with_zone_maps(&[])?;
+        }
+        if args.bloom_filters {
+            builder = builder.with_bloom_filters();
+        }
+        (builder, expand_inputs(&args.inputs)?, Position::default())
+    };
+    let mut bar = Bar::new();
+    let mut tracker = Tracker::new(&mut bar)
+        .with_files(&inputs)?
+        .with_cancel(interrupt_token()?);
+    let (mut read, mut rejected) = (0, 0);
+    let stopped = read_tracked(&inputs, from, &mut tracker, |record| {
+        read += 1;
+        if filters.iter().all(|f| f.matches(&record)) {
+            builder.push(&record)?;
+        } else {
+            rejected += 1;
+        }
+        Ok(())
+    })?;
+    tracker.finish();
+    if let Some(position) = stopped {
+        let manifest = builder.finish_with_checkpoint(Checkpoint {
+            inputs,
+            files: position.files,
+            records: position.records,
+        })?;
+        return Err(format!(
+            "cancelled after indexing {} records; continue with `build-index --resume -o {}`",
+            manifest.records(),
+            args.output.display()
+        )
+        .into());
+    }
+    let manifest = builder.finish()?;
+    let report = BuildReport {
+        files: inputs.len(),
+        read,
+        rejected,
+        elapsed: start.elapsed(),
+        bytes: directory_size(&args.output)?,
+    };
+    report.print(&manifest);
+    Ok(())
+}
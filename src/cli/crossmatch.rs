@@ -0,0 +1,256 @@
+use clap::Args;
+use cli::progress::Bar;
+use cli::{create_output, interrupt_token, read_tracked, Position, Result};
+use csv::{ReaderBuilder, Trim};
+use flate2::read::GzDecoder;
+use serde::Serialize;
+use starquad::catalog::generic::{ColumnMapping, GenericReader};
+use starquad::crossmatch::matcher::{CrossMatch, MatchMode};
+use starquad::crossmatch::parallel::{
+    match_parallel_with_progress, match_self_parallel_with_progress, shard_order,
+};
+use starquad::crossmatch::quality::MagnitudePrior;
+use starquad::output::Format;
+use starquad::progress::Tracker;
+use starquad::sky::position::SkySource;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+#[derive(Args)]
+pub struct CrossmatchArgs {
+    /// Gaia CSV file whose sources are matched.
+    left: PathBuf,
+    /// Gaia CSV file searched for matches, or any CSV file if `--columns` is
+    /// given.
+    #[arg(required_unless_present = "self_match", conflicts_with = "self_match")]
+    right: Option<PathBuf>,
+    /// Match the sources of LEFT with each other, to find duplicates and
+    /// close pairs. A pair is written once if each source matches the other.
+    #[arg(long = "self", conflicts_with = "columns")]
+    self_match: bool,
+    /// Read RIGHT as a CSV catalog with these columns, given as a list of
+    /// `role=column` pairs with the roles ra, dec, id, epoch, mag and error
+    /// (eg. `ra=RAJ2000,dec=DEJ2000,id=Name`).
+    #[arg(long, value_name = "MAPPING")]
+    columns: Option<ColumnMapping>,
+    /// Field delimiter of RIGHT when `--columns` is given.
+    #[arg(long, default_value_t = ',')]
+    delimiter: char,
+    /// Maximum match radius, in arcseconds.
+    #[arg(long, default_value_t = 1.0)]
+    radius: f64,
+    /// Also require matches to be within this many times the combined
+    /// positional uncertainty of the two sources.
+    #[arg(long)]
+    n_sigma: Option<f64>,
+    /// Propagate sources with proper motions to this epoch (a Julian year,
+    /// eg. the epoch of observation of RIGHT) before matching.
+    #[arg(long)]
+    epoch: Option<f64>,
+    /// Keep every match within the radius, rather than only the nearest.
+    #[arg(long, conflicts_with = "mutual")]
+    all: bool,
+    /// Keep only pairs of sources which are each other's nearest match.
+    #[arg(long)]
+    mutual: bool,
+    /// Weight the scores of matches by a prior that the magnitude of the
+    /// RIGHT source is OFFSET + SLOPE * G (SLOPE defaults to 1), with a
+    /// scatter of SIGMA magnitudes.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_name = "OFFSET,SIGMA[,SLOPE]",
+        allow_hyphen_values = true
+    )]
+    mag_prior: Vec<f64>,
+    /// Number of threads matching (default: one for each core).
+    #[arg(long)]
+    threads: Option<usize>,
+    /// Output format: csv, jsonl or bincode.
+    #[arg(long, default_value = "csv")]
+    format: Format,
+    /// Output file (default: stdout).
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+const CANCELLED: &str = "cancelled before matching";
+
+/// A row of the output, with the catalog positions of both sources.
+///
+/// Sources of the right catalog without an `id` column are identified by
+/// their row number, counting from zero.
+#[derive(Serialize)]
+struct Row {
+    left_id: String,
+    left_ra: f64,
+    left_dec: f64,
+    right_id: String,
+    right_ra: f64,
+    right_dec: f64,
+    /// Separation at the matching epoch, in arcseconds.
+    separation: f64,
+    /// Likelihood ratio of the match.
+    score: f64,
+    /// Sources of the right catalog accepted as matches of the left source.
+    candidates: usize,
+    /// Whether another candidate scores nearly as well.
+    ambiguous: bool,
+}
+
+/// A source of the right catalog, which threads share.
+type RightSource = Box<dyn SkySource + Send + Sync>;
+
+/// Read the right catalog, as Gaia records or with a column mapping.
+fn read_right(
+    path: &Path,
+    mapping: Option<&ColumnMapping>,
+    delimiter: char,
+    tracker: &mut Tracker,
+) -> Result<Vec<RightSource>> {
+    match mapping {
+        Some(mapping) => {
+            if !delimiter.is_ascii() {
+                return Err("the delimiter must be an ASCII character".into());
+            }
+            let file = File::open(path)?;
+            let file: Box<dyn Read> = if path.extension().is_some_and(|e| e == "gz") {
+                Box::new(GzDecoder::new(file))
+            } else {
+                Box::new(file)
+            };
+            let csv = ReaderBuilder::new()
+                .delimiter(delimiter as u8)
+                .trim(Trim::All)
+                .from_reader(file);
+            let sources = GenericReader::from_csv(csv, mapping)?
+                .map(|source| source.map(|s| Box::new(s) as RightSource))
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            tracker.file_done();
+            Ok(sources)
+        }
+        None => {
+            let mut sources = Vec::new();
+            let stopped = read_tracked(
+                &[path.to_path_buf()],
+                Position::default(),
+                tracker,
+                |record| {
+                    sources.push(Box::new(record) as RightSource);
+                    Ok(())
+                },
+            )?;
+            match stopped {
+                Some(_) => Err(CANCELLED.into()),
+                None => Ok(sources),
+            }
+        }
+    }
+}
+
+pub fn run(args: CrossmatchArgs) -> Result<()> {
+    let magnitude_prior = match *args.mag_prior.as_slice() {
+        [] => None,
+        [offset, sigma] if sigma > 0.0 => Some(MagnitudePrior::new(offset, sigma)),
+        [offset, sigma, slope] if sigma > 0.0 => {
+            Some(MagnitudePrior::new(offset, sigma).with_slope(slope))
+        }
+        _ => return Err("--mag-prior takes OFFSET,SIGMA[,SLOPE] with a positive SIGMA".into()),
+    };
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()?;
+    }
+    let mut bar = Bar::new();
+    let files = [Some(&args.left), args.right.as_ref()];
+    let files = files.iter().flatten().collect::<Vec<_>>();
+    let mut tracker = Tracker::new(&mut bar)
+        .with_files(&files)?
+        .with_cancel(interrupt_token()?);
+    let mut left = Vec::new();
+    let stopped = read_tracked(
+        std::slice::from_ref(&args.left),
+        Position::default(),
+        &mut tracker,
+        |record| {
+            left.push(record);
+            Ok(())
+        },
+    )?;
+    if stopped.is_some() {
+        return Err(CANCELLED.into());
+    }
+    let right = match &args.right {
+        Some(path) => read_right(path, args.columns.as_ref(), args.delimiter, &mut tracker)?,
+        None => Vec::new(),
+    };
+    let mode = if args.all {
+        MatchMode::All
+    } else if args.mutual {
+        MatchMode::Mutual
+    } else {
+        MatchMode::Best
+    };
+    let mut crossmatch = CrossMatch::new(args.radius).with_mode(mode);
+    if let Some(n_sigma) = args.n_sigma {
+        crossmatch = crossmatch.with_n_sigma(n_sigma);
+    }
+    if let Some(epoch) = args.epoch {
+        crossmatch = crossmatch.with_epoch(epoch);
+    }
+    if let Some(prior) = magnitude_prior {
+        crossmatch = crossmatch.with_magnitude_prior(prior);
+    }
+    let order = shard_order(args.radius);
+    let table = if args.self_match {
+        match_self_parallel_with_progress(&crossmatch, order, &left, &mut tracker)
+    } else {
+        match_parallel_with_progress(&crossmatch, order, &left, &right, &mut tracker)
+    };
+    // matches are only written if some of the left catalog was matched
+    let cancelled = tracker.cancelled();
+    let matched = match tracker.status().stage.as_str() {
+        "matching" => tracker.status().records,
+        _ => 0,
+    };
+    tracker.finish();
+    if cancelled && matched == 0 {
+        return Err(CANCELLED.into());
+    }
+    let mut sink = args
+        .format
+        .sink::<_, Row>(create_output(args.output.as_deref())?)?;
+    for m in table.rows() {
+        let l = &left[m.left];
+        let r: &dyn SkySource = if args.self_match {
+            &left[m.right]
+        } else {
+            &right[m.right]
+        };
+        sink.write(&Row {
+            left_id: l.source_id.to_string(),
+            left_ra: l.ra,
+            left_dec: l.dec,
+            right_id: r.id().unwrap_or_else(|| m.right.to_string()),
+            right_ra: r.position().ra,
+            right_dec: r.position().dec,
+            separation: m.separation,
+            score: m.score,
+            candidates: m.candidates,
+            ambiguous: m.ambiguous,
+        })?;
+    }
+    sink.finish()?;
+    eprintln!("{} matches", table.len());
+    if cancelled {
+        return Err(format!(
+            "cancelled after matching {} of {} sources",
+            matched,
+            left.len()
+        )
+        .into());
+    }
+    Ok(())
+}
@@ -0,0 +1,266 @@
+use clap::{Args, Subcommand};
+use cli::{create_output, Range, Result};
+use serde::Serialize;
+use starquad::gaia::filter::Filter;
+use starquad::gaia::record::GaiaRecord;
+use starquad::geom::rect::Rect;
+use starquad::output::geojson::{Feature, FeatureCollection};
+use starquad::output::image::{self, Colormap, Render, Stretch};
+use starquad::output::Format;
+use starquad::sky::density::{AdaptiveMap, HealpixMap, Image, TangentGrid};
+use starquad::sky::position::{SkyPosition, SkySource};
+use starquad::sky::region::Region;
+use starquad::store::{Query, Store};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+#[derive(Args)]
+pub struct DensmapArgs {
+    #[command(subcommand)]
+    map: MapArgs,
+}
+
+#[derive(Subcommand)]
+enum MapArgs {
+    /// Count sources in HEALPix pixels, and draw an all-sky image.
+    Healpix {
+        /// HEALPix order of the map.
+        #[arg(long, default_value_t = 6)]
+        order: u8,
+        /// Width of the image, in pixels.
+        #[arg(long, default_value_t = 1024)]
+        width: usize,
+        #[command(flatten)]
+        options: MapOptions,
+    },
+    /// Count sources in a grid on the tangent plane at a position.
+    Tangent {
+        /// Right ascension of the center, in degrees.
+        #[arg(long)]
+        ra: f64,
+        /// Declination of the center, in degrees.
+        #[arg(long, allow_hyphen_values = true)]
+        dec: f64,
+        /// Size of a pixel, in arcseconds.
+        #[arg(long, default_value_t = 60.0)]
+        scale: f64,
+        /// Width of the grid, in pixels.
+        #[arg(long, default_value_t = 512)]
+        width: usize,
+        /// Height of the grid, in pixels.
+        #[arg(long, default_value_t = 512)]
+        height: usize,
+        #[command(flatten)]
+        options: MapOptions,
+    },
+    /// Count sources in the tiles of an adaptive map of a box, which are
+    /// split where sources are dense, and write the tiles.
+    Tiles {
+        /// Directory of an index written by `build-index`.
+        index: PathBuf,
+        /// Range of right ascension, within 0 to 360 degrees.
+        #[arg(long, default_value = "0,360")]
+        ra_range: Range,
+        /// Range of declination, in degrees.
+        #[arg(long, allow_hyphen_values = true, default_value = "-90,90")]
+        dec_range: Range,
+        /// Most sources in a tile before it is split into four.
+        #[arg(long, default_value_t = 1000)]
+        leaf_capacity: usize,
+        /// Only count records matching a filter expression.
+        #[arg(long)]
+        filter: Option<Filter>,
+        /// Output format: csv or jsonl.
+        #[arg(long, default_value = "csv")]
+        format: Format,
+        /// Write the tiles as a GeoJSON feature collection instead.
+        #[arg(long, conflicts_with = "format")]
+        geojson: bool,
+        /// Output file (default: stdout).
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Args)]
+struct MapOptions {
+    /// Directory of an index written by `build-index`.
+    index: PathBuf,
+    /// Only count records matching a filter expression.
+    #[arg(long)]
+    filter: Option<Filter>,
+    /// Output PNG image.
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Brightness stretch: linear, sqrt, log or asinh.
+    #[arg(long, default_value = "log")]
+    stretch: Stretch,
+    /// Colour map: gray, viridis or inferno.
+    #[arg(long, default_value = "inferno")]
+    colormap: Colormap,
+    /// Count shown at the top of the colour map (default: the largest).
+    #[arg(long)]
+    max: Option<f64>,
+    /// Also write the counts, as CSV or FITS (by extension: .fits, .fit or
+    /// .fts for FITS).
+    #[arg(long)]
+    raw: Option<PathBuf>,
+}
+
+/// Count of sources in a HEALPix pixel.
+#[derive(Serialize)]
+struct PixelCount {
+    pixel: u64,
+    count: u64,
+}
+
+fn is_fits(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e == "fits" || e == "fit" || e == "fts")
+}
+
+/// Call `add` with the positions of the records of a query which match a
+/// filter.
+fn positions<F>(query: Query, filter: Option<&Filter>, mut add: F) -> Result<()>
+where
+    F: FnMut(&SkyPosition),
+{
+    for record in query {
+        let record: GaiaRecord = record?;
+        if filter.is_none_or(|f| f.matches(&record)) {
+            add(&record.position());
+        }
+    }
+    Ok(())
+}
+
+fn write_png(options: &MapOptions, image: &Image) -> Result<()> {
+    let mut render = Render::new(options.stretch, options.colormap).with_min(0.0);
+    if let Some(max) = options.max {
+        render = render.with_max(max);
+    }
+    render.write_png(image, BufWriter::new(File::create(&options.output)?))?;
+    Ok(())
+}
+
+pub fn run(args: DensmapArgs) -> Result<()> {
+    match args.map {
+        MapArgs::Healpix {
+            order,
+            width,
+            options,
+        } => {
+            if order > 10 {
+                return Err("the HEALPix order must be at most 10".into());
+            }
+            let store = Store::open(&options.index)?;
+            let mut map = HealpixMap::new(order);
+            positions(store.scan(), options.filter.as_ref(), |p| map.add(p))?;
+            write_png(&options, &map.image(width))?;
+            if let Some(raw) = &options.raw {
+                if is_fits(raw) {
+                    let counts = map.counts().iter().map(|&c| c as f64).collect::<Vec<_>>();
+                    image::write_fits(
+                        BufWriter::new(File::create(raw)?),
+                        &[counts.len()],
+                        &counts,
+                        &[
+                            ("PIXTYPE", String::from("'HEALPIX '")),
+                            ("ORDERING", String::from("'NESTED  '")),
+                            ("NSIDE", (1u64 << order).to_string()),
+                            ("BUNIT", String::from("'count   '")),
+                        ],
+                    )?;
+                } else {
+                    let mut sink = Format::Csv.sink::<_, PixelCount>(create_output(Some(raw))?)?;
+                    for (pixel, &count) in map.counts().iter().enumerate() {
+                        sink.write(&PixelCount {
+                            pixel: pixel as u64,
+                            count,
+                        })?;
+                    }
+                    sink.finish()?;
+                }
+            }
+            eprintln!("{} sources", map.total());
+        }
+        MapArgs::Tangent {
+            ra,
+            dec,
+            scale,
+            width,
+            height,
+            options,
+        } => {
+            let center = SkyPosition::new(ra, dec);
+            let mut grid = TangentGrid::new(center, scale / 3600.0, width, height);
+            let store = Store::open(&options.index)?;
+            let query = store.query(&Region::cone(center, grid.radius()));
+            positions(query, options.filter.as_ref(), |p| grid.add(p))?;
+            write_png(&options, grid.image())?;
+            if let Some(raw) = &options.raw {
+                if is_fits(raw) {
+                    // a TAN world coordinate system, with the reference
+                    // pixel at the center of the image
+                    image::write_fits_image(
+                        grid.image(),
+                        BufWriter::new(File::create(raw)?),
+                        &[
+                            ("CTYPE1", String::from("'RA---TAN'")),
+                            ("CTYPE2", String::from("'DEC--TAN'")),
+                            ("CRVAL1", ra.to_string()),
+                            ("CRVAL2", dec.to_string()),
+                            ("CRPIX1", (width as f64 / 2.0 + 0.5).to_string()),
+                            ("CRPIX2", (height as f64 / 2.0 + 0.5).to_string()),
+                            ("CDELT1", (-scale / 3600.0).to_string()),
+                            ("CDELT2", (scale / 3600.0).to_string()),
+                            ("BUNIT", String::from("'count   '")),
+                        ],
+                    )?;
+                } else {
+                    image::write_csv(grid.image(), create_output(Some(raw))?)?;
+                }
+            }
+            eprintln!("{} sources", grid.total());
+        }
+        MapArgs::Tiles {
+            index,
+            ra_range: ra,
+            dec_range: dec,
+            leaf_capacity,
+            filter,
+            format,
+            geojson,
+            output,
+        } => {
+            if ra.min < 0.0 || ra.max > 360.0 || dec.min < -90.0 || dec.max > 90.0 {
+                return Err("the box must lie within ra 0,360 and dec -90,90".into());
+            }
+            // tiles hold their lower edges, so the box does not hold its
+            // upper ones, but its tiles' edges are round numbers
+            let bounds = Rect::new(ra.min, dec.min, ra.max - ra.min, dec.max - dec.min)
+                .filter(|_| ra.min < ra.max && dec.min < dec.max)
+                .ok_or("the box must have finite, increasing ranges")?;
+            let mut map = AdaptiveMap::new(bounds.clone(), leaf_capacity);
+            let store = Store::open(&index)?;
+            positions(store.query(&Region::Rect(bounds)), filter.as_ref(), |p| {
+                map.add(p)
+            })?;
+            let tiles = map.tiles();
+            let output = create_output(output.as_deref())?;
+            if geojson {
+                let features = tiles.iter().map(Feature::from).collect();
+                FeatureCollection { features }.write(output)?;
+            } else {
+                let mut sink = format.sink(output)?;
+                for tile in &tiles {
+                    sink.write(tile)?;
+                }
+                sink.finish()?;
+            }
+            eprintln!("{} sources in {} tiles", map.total(), tiles.len());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,73 @@
+use clap::Args;
+use cli::{create_output, expand_inputs, read_all, Result};
+use starquad::crossmatch::matcher::CrossMatch;
+use starquad::gaia::diff::ReleaseDiff;
+use starquad::gaia::sort::{ExternalSort, SortKey};
+use std::env;
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct DiffArgs {
+    /// Gaia CSV file, or directory of them, of the old release.
+    old: PathBuf,
+    /// Gaia CSV file, or directory of them, of the new release.
+    new: PathBuf,
+    /// Pair sources by position, within this radius in arcseconds, rather
+    /// than by source_id. Both releases are read into memory.
+    #[arg(long, value_name = "RADIUS")]
+    crossmatch: Option<f64>,
+    /// The releases are already sorted by source_id (as Gaia's files are,
+    /// read in order of name), so need not be sorted on disk first.
+    #[arg(long, conflicts_with = "crossmatch")]
+    sorted: bool,
+    /// Directory for sorted runs (default: the system temporary
+    /// directory).
+    #[arg(long)]
+    temp_dir: Option<PathBuf>,
+    /// Output CSV file of the differences of each column (default: stdout).
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+/// Compare two releases of the catalog, column by column.
+pub fn run(args: DiffArgs) -> Result<()> {
+    let old_inputs = expand_inputs(std::slice::from_ref(&args.old))?;
+    let new_inputs = expand_inputs(std::slice::from_ref(&args.new))?;
+    // stop at the first error of either release
+    let (mut old_error, mut new_error) = (None, None);
+    let old = read_all(&old_inputs).map_while(|r| r.map_err(|e| old_error = Some(e)).ok());
+    let new = read_all(&new_inputs).map_while(|r| r.map_err(|e| new_error = Some(e)).ok());
+    let (mut old_merge_error, mut new_merge_error) = (None, None);
+    let diff = match args.crossmatch {
+        Some(radius) => {
+            let (old, new) = (old.collect::<Vec<_>>(), new.collect::<Vec<_>>());
+            ReleaseDiff::by_crossmatch(&old, &new, &CrossMatch::new(radius))
+        }
+        None if args.sorted => ReleaseDiff::by_source_id(old, new)?,
+        None => {
+            let dir = args.temp_dir.unwrap_or_else(env::temp_dir);
+            let sort = |name: &str| {
+                let runs = format!("starquad-diff-{}-{}", name, std::process::id());
+                ExternalSort::new(SortKey::SourceId, dir.join(runs))
+            };
+            let old = sort("old").sort(old)?;
+            let new = sort("new").sort(new)?;
+            ReleaseDiff::by_source_id(
+                old.map_while(|r| r.map_err(|e| old_merge_error = Some(e)).ok()),
+                new.map_while(|r| r.map_err(|e| new_merge_error = Some(e)).ok()),
+            )?
+        }
+    };
+    if let Some(error) = old_error.or(new_error) {
+        return Err(error);
+    }
+    if let Some(error) = old_merge_error.or(new_merge_error) {
+        return Err(error.into());
+    }
+    eprintln!(
+        "{} sources in both releases, {} only in the old, {} only in the new",
+        diff.matched, diff.old_only, diff.new_only
+    );
+    diff.write_csv(create_output(args.output.as_deref())?)?;
+    Ok(())
+}
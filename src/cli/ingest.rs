@@ -0,0 +1,56 @@
+use clap::Args;
+use cli::progress::Bar;
+use cli::{create_output, expand_inputs, interrupt_token, read_tracked, Position, Result};
+use starquad::gaia::filter::Filter;
+use starquad::gaia::record::GaiaRecord;
+use starquad::output::Format;
+use starquad::progress::Tracker;
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct IngestArgs {
+    /// Gaia CSV files (optionally gzipped), or directories of them.
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+    /// Output format: csv, jsonl or bincode.
+    #[arg(long, default_value = "bincode")]
+    format: Format,
+    /// Only keep records matching a filter expression.
+    #[arg(long)]
+    filter: Option<Filter>,
+    /// Output file (default: stdout).
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+pub fn run(args: IngestArgs) -> Result<()> {
+    let mut sink = args
+        .format
+        .sink::<_, GaiaRecord>(create_output(args.output.as_deref())?)?;
+    let inputs = expand_inputs(&args.inputs)?;
+    let mut bar = Bar::new();
+    let mut tracker = Tracker::new(&mut bar)
+        .with_files(&inputs)?
+        .with_cancel(interrupt_token()?);
+    let mut records = 0;
+    let stopped = read_tracked(&inputs, Position::default(), &mut tracker, |record| {
+        if args.filter.as_ref().is_none_or(|f| f.matches(&record)) {
+            sink.write(&record)?;
+            records += 1;
+        }
+        Ok(())
+    })?;
+    tracker.finish();
+    sink.finish()?;
+    eprintln!("wrote {} records", records);
+    match stopped {
+        // the output is complete up to the record where reading stopped
+        Some(position) => Err(format!(
+            "cancelled after record {} of {}",
+            position.records,
+            inputs[position.files].display()
+        )
+        .into()),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,116 @@
+use clap::Args;
+use cli::Result;
+use starquad::sky::healpix;
+use starquad::store::file::StarFile;
+use starquad::store::Store;
+use std::path::{Path, PathBuf};
+
+#[derive(Args)]
+pub struct InspectArgs {
+    /// Directory of an index written by `build-index`, or a file written by
+    /// `pack`.
+    index: PathBuf,
+    /// Number of the largest shards to list.
+    #[arg(long, default_value_t = 10)]
+    largest: usize,
+}
+
+pub fn run(args: InspectArgs) -> Result<()> {
+    if args.index.is_file() {
+        return inspect_file(&args.index);
+    }
+    let store = Store::open(&args.index)?;
+    let manifest = store.manifest();
+    let pixels = healpix::n_pixels(manifest.order);
+    let mut shards = manifest.shards.iter().collect::<Vec<_>>();
+    shards.sort_by_key(|s| s.records);
+    let records = manifest.records();
+    println!("index:        {}", args.index.display());
+    println!("format:       {:?}", manifest.format);
+    if manifest.requires.is_empty() {
+        println!("version:      {}", manifest.version);
+    } else {
+        println!(
+            "version:      {} (requires {})",
+            manifest.version,
+            manifest.requires.join(", ")
+        );
+    }
+    println!(
+        "depth:        HEALPix order {} ({} pixels)",
+        manifest.order, pixels
+    );
+    println!(
+        "shards:       {} ({:.1}% of pixels are empty)",
+        shards.len(),
+        100.0 * (pixels - shards.len() as u64) as f64 / pixels as f64
+    );
+    println!("records:      {}", records);
+    if !manifest.zone_maps.is_empty() {
+        println!("zone maps:    {}", manifest.zone_maps.join(", "));
+    }
+    if manifest.bloom_filters {
+        println!("bloom:        a filter of the sources of each shard");
+    }
+    if !store.pending().is_empty() {
+        println!(
+            "pending:      {} sources changed in the log",
+            store.pending().len()
+        );
+    }
+    if shards.is_empty() {
+        return Ok(());
+    }
+    println!(
+        "shard sizes:  min {}, median {}, mean {:.1}, max {}",
+        shards[0].records,
+        shards[shards.len() / 2].records,
+        records as f64 / shards.len() as f64,
+        shards[shards.len() - 1].records
+    );
+    // histogram of shard sizes in powers of two
+    let mut histogram = Vec::new();
+    for shard in &shards {
+        let bin = (64 - shard.records.leading_zeros()) as usize;
+        if histogram.len() <= bin {
+            histogram.resize(bin + 1, 0);
+        }
+        histogram[bin] += 1;
+    }
+    println!("shard size histogram:");
+    for (bin, count) in histogram.iter().enumerate().filter(|(_, &n)| n > 0) {
+        let (low, high) = if bin == 0 {
+            (0, 0)
+        } else {
+            (1u64 << (bin - 1), (1u64 << bin) - 1)
+        };
+        println!("  {:>10} - {:<10} {}", low, high, count);
+    }
+    println!("largest shards:");
+    for shard in shards.iter().rev().take(args.largest) {
+        println!(
+            "  pixel {:>8}: {:>10} records, ra [{:.3}, {:.3}], dec [{:.3}, {:.3}]",
+            shard.pixel, shard.records, shard.min_ra, shard.max_ra, shard.min_dec, shard.max_dec
+        );
+    }
+    Ok(())
+}
+
+fn inspect_file(path: &Path) -> Result<()> {
+    let file = StarFile::open(path)?;
+    let metadata = file.metadata();
+    println!("file:         {}", path.display());
+    println!("version:      {}", metadata.version);
+    if let Some(description) = &metadata.description {
+        println!("description:  {}", description);
+    }
+    println!(
+        "depth:        HEALPix order {} ({} pixels)",
+        metadata.order,
+        healpix::n_pixels(metadata.order)
+    );
+    println!("cells:        {}", file.cells().len());
+    println!("records:      {}", metadata.rows);
+    println!("columns:      {}", metadata.columns().join(", "));
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use clap::Args;
+use cli::{create_output, Result};
+use starquad::gaia::projection::Projection;
+use starquad::output::Format;
+use starquad::store::Store;
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct LookupArgs {
+    /// Directory of an index written by `build-index`.
+    index: PathBuf,
+    /// Source IDs to look up.
+    #[arg(required = true)]
+    source_ids: Vec<u64>,
+    /// Comma-separated columns to output (default: all).
+    #[arg(long, value_delimiter = ',')]
+    columns: Vec<String>,
+    /// Output format: csv, jsonl, bincode or arrow (an Arrow IPC stream, if
+    /// enabled).
+    #[arg(long, default_value = "csv")]
+    format: Format,
+    /// Output file (default: stdout).
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+/// Write the records of the sources which are in the index, reporting those
+/// which are not.
+pub fn run(args: LookupArgs) -> Result<()> {
+    let projection = if args.columns.is_empty() {
+        Projection::all()
+    } else {
+        Projection::new(&args.columns)?
+    };
+    let store = Store::open(&args.index)?;
+    let mut sink = args
+        .format
+        .projected_sink(create_output(args.output.as_deref())?, projection);
+    let mut missing = 0;
+    for source_id in args.source_ids {
+        match store.lookup(source_id)? {
+            Some(record) => sink.write(&record)?,
+            None => {
+                eprintln!("source {} not found", source_id);
+                missing += 1;
+            }
+        }
+    }
+    sink.finish()?;
+    if missing > 0 {
+        return Err(format!("{} sources not found", missing).into());
+    }
+    Ok(())
+}
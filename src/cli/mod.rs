@@ -0,0 +1,362 @@
+#[cfg(any(feature = "server", feature = "grpc"))]
+use clap::Args;
+use clap::{ArgAction, Parser, Subcommand};
+#[cfg(any(feature = "server", feature = "grpc"))]
+use starquad::auth::{ApiKeys, Auth, BearerTokens, RateLimit};
+use starquad::gaia::reader;
+use starquad::gaia::record::GaiaRecord;
+use starquad::progress::{CancelToken, Tracker};
+use starquad::store::results::ResultCache;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io;
+use std::io::Write;
+use std::num::ParseFloatError;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tracing::Level;
+use tracing_subscriber::EnvFilter;
+
+mod build_index;
+mod crossmatch;
+mod densmap;
+mod diff;
+mod ingest;
+mod inspect;
+mod lookup;
+mod neighbours;
+mod pack;
+mod progress;
+mod query;
+mod repl;
+mod rv_subsample;
+mod sample;
+#[cfg(feature = "server")]
+mod serve;
+#[cfg(feature = "grpc")]
+mod serve_grpc;
+mod sort;
+mod stats;
+mod update;
+mod validate;
+
+#[derive(Parser)]
+#[command(name = "starquad", version, about = "Tools for the Gaia catalog")]
+pub struct Cli {
+    #[command(subcommand)]
+    command: Command,
+    /// Log more detail to standard error: -v for timings of each file and
+    /// query, -vv for each shard, -vvv for everything.
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    verbose: u8,
+    /// Only log errors.
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Write logs as JSON lines.
+    #[arg(long, global = true)]
+    log_json: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Convert Gaia CSV files to another format.
+    Ingest(ingest::IngestArgs),
+    /// Build an on-disk index of Gaia CSV files.
+    BuildIndex(build_index::BuildIndexArgs),
+    /// Find the records of an index, or of a packed file, in a region.
+    Query(query::QueryArgs),
+    /// Print the schema and column statistics of Gaia CSV files and indexes.
+    Stats(stats::StatsArgs),
+    /// Print the structure of an index or a packed file.
+    Inspect(inspect::InspectArgs),
+    /// Fetch the records of sources from an index by source_id.
+    Lookup(lookup::LookupArgs),
+    /// Cross-match two Gaia CSV files.
+    Crossmatch(crossmatch::CrossmatchArgs),
+    /// Pack the records of Gaia CSV files or indexes into a single file,
+    /// which can be queried without an index.
+    Pack(pack::PackArgs),
+    /// Query an index interactively, keeping its shards in memory.
+    Repl(repl::ReplArgs),
+    /// Draw a map of the density of sources in an index.
+    Densmap(densmap::DensmapArgs),
+    /// Compare the separations of the sources of a region from their
+    /// nearest neighbours with those of a random field.
+    Neighbours(neighbours::NeighboursArgs),
+    /// Compare two releases of the catalog, reporting the differences of
+    /// each column for the sources they share.
+    Diff(diff::DiffArgs),
+    /// Serve queries of an index over HTTP.
+    #[cfg(feature = "server")]
+    Serve(serve::ServeArgs),
+    /// Serve queries of an index over gRPC.
+    #[cfg(feature = "grpc")]
+    ServeGrpc(serve_grpc::ServeGrpcArgs),
+    /// Write the Galactic phase-space coordinates of the sources with radial
+    /// velocities, optionally selected by position and speed.
+    RvSubsample(rv_subsample::RvSubsampleArgs),
+    /// Write a random subsample of Gaia CSV files.
+    Sample(sample::SampleArgs),
+    /// Sort Gaia CSV files by source_id or sky position, using temporary
+    /// files for inputs too large to sort in memory.
+    Sort(sort::SortArgs),
+    /// Add, replace or remove records of an index through its log of
+    /// changes, and fold the log into the index.
+    Update(update::UpdateArgs),
+    /// Check downloaded Gaia CSV files against their checksums and schema.
+    Validate(validate::ValidateArgs),
+}
+
+impl Cli {
+    /// Send logs to standard error, at the level given by the `RUST_LOG`
+    /// environment variable if set, and otherwise by `-v` and `-q`.
+    fn init_logging(&self) {
+        let level = if self.quiet {
+            Level::ERROR
+        } else {
+            match self.verbose {
+                0 => Level::WARN,
+                1 => Level::INFO,
+                2 => Level::DEBUG,
+                _ => Level::TRACE,
+            }
+        };
+        let filter = EnvFilter::builder()
+            .with_default_directive(level.into())
+            .from_env_lossy();
+        let logs = tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_writer(io::stderr);
+        if self.log_json {
+            logs.json().init();
+        } else {
+            logs.init();
+        }
+    }
+
+    pub fn run(self) -> Result<()> {
+        self.init_logging();
+        match self.command {
+            Command::Ingest(args) => ingest::run(args),
+            Command::BuildIndex(args) => build_index::run(args),
+            Command::Query(args) => query::run(args),
+            Command::Stats(args) => stats::run(args),
+            Command::Inspect(args) => inspect::run(args),
+            Command::Lookup(args) => lookup::run(args),
+            Command::Crossmatch(args) => crossmatch::run(args),
+            Command::Pack(args) => pack::run(args),
+            Command::Densmap(args) => densmap::run(args),
+            Command::Neighbours(args) => neighbours::run(args),
+            Command::Diff(args) => diff::run(args),
+            Command::Repl(args) => repl::run(args),
+            Command::RvSubsample(args) => rv_subsample::run(args),
+            Command::Sample(args) => sample::run(args),
+            #[cfg(feature = "server")]
+            Command::Serve(args) => serve::run(args),
+            #[cfg(feature = "grpc")]
+            Command::ServeGrpc(args) => serve_grpc::run(args),
+            Command::Sort(args) => sort::run(args),
+            Command::Update(args) => update::run(args),
+            Command::Validate(args) => validate::run(args),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+/// Closed range of values given on the command line as `min,max`.
+#[derive(Clone, Copy, Debug)]
+pub struct Range {
+    pub min: f64,
+    pub max: f64,
+}
+
+impl FromStr for Range {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<Self, String> {
+        let (min, max) = text
+            .split_once(',')
+            .ok_or_else(|| String::from("expected MIN,MAX"))?;
+        let parse = |s: &str| s.trim().parse().map_err(|e: ParseFloatError| e.to_string());
+        let range = Range {
+            min: parse(min)?,
+            max: parse(max)?,
+        };
+        if range.min <= range.max {
+            Ok(range)
+        } else {
+            Err(String::from("MIN is greater than MAX"))
+        }
+    }
+}
+
+/// Records of all input files, in order.
+pub fn read_all(inputs: &[PathBuf]) -> impl Iterator<Item = Result<GaiaRecord>> + '_ {
+    inputs.iter().flat_map(|path| {
+        let records: Box<dyn Iterator<Item = Result<GaiaRecord>>> = match reader::open(path) {
+            Ok(records) => Box::new(records.map(|r| r.map_err(Box::from))),
+            Err(e) => Box::new(Some(Err(Box::from(e))).into_iter()),
+        };
+        records
+    })
+}
+
+/// Position in a list of input files: the number of files read completely,
+/// and the number of records read of the next file.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Position {
+    pub files: usize,
+    pub records: u64,
+}
+
+/// Pass the records of all input files, in order and starting from a
+/// position, to a function, counting the bytes, records and files read with
+/// a tracker.
+///
+/// If the tracker is cancelled, reading stops after the current record and
+/// the position from which to continue is returned.
+pub fn read_tracked<F>(
+    inputs: &[PathBuf],
+    from: Position,
+    tracker: &mut Tracker,
+    mut f: F,
+) -> Result<Option<Position>>
+where
+    F: FnMut(GaiaRecord) -> Result<()>,
+{
+    tracker.start_stage("reading", None);
+    for (file, path) in inputs.iter().enumerate().skip(from.files) {
+        let _span = tracing::info_span!("read", path = %path.display()).entered();
+        let (start, bytes) = (Instant::now(), tracker.bytes().get());
+        let skip = if file == from.files { from.records } else { 0 };
+        let mut records = 0u64;
+        for record in reader::open_counted(path, tracker.bytes())? {
+            let record = record?;
+            records += 1;
+            if records <= skip {
+                continue;
+            }
+            f(record)?;
+            tracker.record();
+            if tracker.cancelled() {
+                tracing::info!(records, "stopped");
+                return Ok(Some(Position {
+                    files: file,
+                    records,
+                }));
+            }
+        }
+        tracker.file_done();
+        tracing::info!(
+            records,
+            bytes = tracker.bytes().get() - bytes,
+            elapsed_ms = start.elapsed().as_secs_f64() * 1000.0,
+            "file read"
+        );
+    }
+    Ok(None)
+}
+
+/// Token which is cancelled by the first Ctrl-C, so that the current command
+/// stops at its next safe point. A second Ctrl-C exits immediately.
+pub fn interrupt_token() -> Result<CancelToken> {
+    let token = CancelToken::new();
+    let handler = token.clone();
+    ctrlc::set_handler(move || {
+        if handler.is_cancelled() {
+            process::exit(130);
+        }
+        eprintln!("stopping (press Ctrl-C again to abort)");
+        handler.cancel();
+    })?;
+    Ok(token)
+}
+
+/// Replace directories with the Gaia CSV files (`*.csv` and `*.csv.gz`) they
+/// contain, in order of name.
+pub fn expand_inputs(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut inputs = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let mut files = fs::read_dir(path)?
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<io::Result<Vec<_>>>()?;
+            files.retain(|f| {
+                let name = f.to_string_lossy();
+                name.ends_with(".csv") || name.ends_with(".csv.gz")
+            });
+            files.sort();
+            inputs.extend(files);
+        } else {
+            inputs.push(path.clone());
+        }
+    }
+    Ok(inputs)
+}
+
+/// Cache of the results of queries of up to `records` records in all, and
+/// kept for at most `ttl` seconds, if `records` is given.
+pub fn result_cache(records: Option<u64>, ttl: Option<f64>) -> Result<Option<ResultCache>> {
+    let cache = match records {
+        Some(records) => ResultCache::new(records),
+        None => return Ok(None),
+    };
+    Ok(Some(match ttl {
+        Some(ttl) => cache.with_ttl(Duration::try_from_secs_f64(ttl)?),
+        None => cache,
+    }))
+}
+
+/// Open an output file, or standard output if there is no path.
+pub fn create_output(path: Option<&Path>) -> Result<Box<dyn Write>> {
+    Ok(match path {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout()),
+    })
+}
+
+/// Credentials a server requires of its clients.
+#[cfg(any(feature = "server", feature = "grpc"))]
+#[derive(Args)]
+pub struct AuthArgs {
+    /// Require an `x-api-key` header holding one of the keys of a file of
+    /// `NAME KEY` lines.
+    #[arg(long, conflicts_with = "bearer_tokens")]
+    api_keys: Option<PathBuf>,
+    /// Require an `Authorization: Bearer` header holding one of the tokens
+    /// of a file of `NAME TOKEN` lines.
+    #[arg(long)]
+    bearer_tokens: Option<PathBuf>,
+    /// Allow each client at most this many requests a second, on average.
+    #[arg(long)]
+    rate_limit: Option<f64>,
+    /// Allow each client bursts of at most this many requests (default: the
+    /// rate limit, rounded up).
+    #[arg(long, requires = "rate_limit")]
+    burst: Option<u32>,
+}
+
+#[cfg(any(feature = "server", feature = "grpc"))]
+impl AuthArgs {
+    /// Authentication of requests, if it is required.
+    pub fn auth(&self) -> Result<Option<Auth>> {
+        let auth = match (&self.api_keys, &self.bearer_tokens) {
+            (Some(path), _) => Auth::new(ApiKeys::read(path)?),
+            (None, Some(path)) => Auth::new(BearerTokens::read(path)?),
+            (None, None) if self.rate_limit.is_some() => {
+                return Err("--rate-limit needs --api-keys or --bearer-tokens".into())
+            }
+            (None, None) => return Ok(None),
+        };
+        Ok(Some(match self.rate_limit {
+            Some(rate) => {
+                let burst = self.burst.unwrap_or(rate.ceil() as u32);
+                auth.with_rate_limit(RateLimit::new(rate, burst)?)
+            }
+            None => auth,
+        }))
+    }
+}
@@ -0,0 +1,74 @@
+use clap::Args;
+use cli::{create_output, Range, Result};
+use starquad::gaia::filter::Filter;
+use starquad::output::Format;
+use starquad::sky::neighbours::NeighbourStats;
+use starquad::sky::position::SkySource;
+use starquad::sky::region::Region;
+use starquad::store::Store;
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct NeighboursArgs {
+    /// Directory of an index written by `build-index`.
+    index: PathBuf,
+    /// Range of right ascension, in degrees.
+    #[arg(long, allow_hyphen_values = true)]
+    ra_range: Range,
+    /// Range of declination, in degrees.
+    #[arg(long, allow_hyphen_values = true)]
+    dec_range: Range,
+    /// Only count records matching a filter expression.
+    #[arg(long)]
+    filter: Option<Filter>,
+    /// Width of the bins of the histogram, in arcseconds (default: a
+    /// quarter of the mean separation expected of a random field).
+    #[arg(long)]
+    bin_width: Option<f64>,
+    /// Output format of the histogram: csv or jsonl.
+    #[arg(long, default_value = "csv")]
+    format: Format,
+    /// Output file of the histogram (default: stdout).
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+pub fn run(args: NeighboursArgs) -> Result<()> {
+    if args.bin_width.is_some_and(|w| w <= 0.0 || !w.is_finite()) {
+        return Err("the bin width must be positive".into());
+    }
+    let (ra, dec) = (args.ra_range, args.dec_range);
+    let region = Region::sky_box(ra.min, ra.max, dec.min, dec.max)?;
+    let mut positions = Vec::new();
+    for record in Store::open(&args.index)?.query(&region) {
+        let record = record?;
+        if args.filter.as_ref().is_none_or(|f| f.matches(&record)) {
+            positions.push(record.position());
+        }
+    }
+    let stats = NeighbourStats::new(&positions, &region, args.bin_width)
+        .ok_or("the region holds fewer than two sources")?;
+    let mut sink = args.format.sink(create_output(args.output.as_deref())?)?;
+    for bin in &stats.histogram {
+        sink.write(bin)?;
+    }
+    sink.finish()?;
+    eprintln!("sources:      {}", stats.sources);
+    eprintln!(
+        "density:      {:.6} per square arcminute",
+        stats.density * 3600.0
+    );
+    eprintln!(
+        "separation:   min {:.3}\"  median {:.3}\"  mean {:.3}\"  max {:.3}\"",
+        stats.min, stats.median, stats.mean, stats.max
+    );
+    eprintln!(
+        "random field: median {:.3}\"  mean {:.3}\"",
+        stats.expected_median, stats.expected_mean
+    );
+    eprintln!(
+        "Clark-Evans:  {:.3} (below 1 if clustered, above 1 if regular)",
+        stats.clark_evans()
+    );
+    Ok(())
+}
@@ -0,0 +1,90 @@
+use clap::Args;
+use cli::{expand_inputs, read_all, Range, Result};
+use starquad::gaia::filter::Filter;
+use starquad::gaia::projection::Projection;
+use starquad::gaia::record::GaiaRecord;
+use starquad::sky::position::SkyPosition;
+use starquad::sky::region::Region;
+use starquad::store::builder::DEFAULT_ORDER;
+use starquad::store::file::StarFileBuilder;
+use starquad::store::manifest::MANIFEST_FILE;
+use starquad::store::Store;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct PackArgs {
+    /// Gaia CSV files (optionally gzipped), directories of them, or
+    /// directories of indexes written by `build-index`.
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+    /// Output file.
+    #[arg(short, long)]
+    output: PathBuf,
+    /// HEALPix order of the index of the file.
+    #[arg(long, default_value_t = DEFAULT_ORDER)]
+    order: u8,
+    /// Comma-separated columns to store (default: all). Other columns are
+    /// cleared.
+    #[arg(long, value_delimiter = ',')]
+    columns: Vec<String>,
+    /// Only pack records in a range of right ascension, in degrees.
+    #[arg(long, allow_hyphen_values = true)]
+    ra_range: Option<Range>,
+    /// Only pack records in a range of declination, in degrees.
+    #[arg(long, allow_hyphen_values = true)]
+    dec_range: Option<Range>,
+    /// Only pack records matching a filter expression. May be repeated.
+    #[arg(long)]
+    filter: Vec<Filter>,
+    /// Description of the file, shown by `inspect`.
+    #[arg(long)]
+    description: Option<String>,
+}
+
+pub fn run(args: PackArgs) -> Result<()> {
+    let ra = args.ra_range.unwrap_or(Range {
+        min: 0.0,
+        max: 360.0,
+    });
+    let dec = args.dec_range.unwrap_or(Range {
+        min: -90.0,
+        max: 90.0,
+    });
+    let region = Region::sky_box(ra.min, ra.max, dec.min, dec.max)?;
+    let mut builder = StarFileBuilder::new(&args.output).with_order(args.order);
+    if !args.columns.is_empty() {
+        builder = builder.with_columns(Projection::new(&args.columns)?);
+    }
+    if let Some(description) = &args.description {
+        builder = builder.with_description(description);
+    }
+    let mut push = |record: GaiaRecord| -> Result<()> {
+        if args.filter.iter().all(|f| f.matches(&record)) {
+            builder.push(&record)?;
+        }
+        Ok(())
+    };
+    let (indexes, files): (Vec<_>, Vec<_>) = args
+        .inputs
+        .iter()
+        .cloned()
+        .partition(|path| path.join(MANIFEST_FILE).is_file());
+    for dir in &indexes {
+        for record in Store::open(dir)?.query(&region) {
+            push(record?)?;
+        }
+    }
+    for record in read_all(&expand_inputs(&files)?) {
+        let record = record?;
+        if region.contains(&SkyPosition::new(record.ra, record.dec)) {
+            push(record)?;
+        }
+    }
+    let metadata = builder.finish()?;
+    let bytes = fs::metadata(&args.output)?.len();
+    println!("records:  {}", metadata.rows);
+    println!("columns:  {}", metadata.columns().len());
+    println!("size:     {:.1} MiB", bytes as f64 / (1024.0 * 1024.0));
+    Ok(())
+}
@@ -0,0 +1,61 @@
+use indicatif::{HumanBytes, HumanDuration, ProgressBar, ProgressStyle};
+use starquad::progress::{Progress, Status};
+
+/// Resolution of the bar, which shows `Status::fraction`.
+const STEPS: u64 = 1000;
+
+/// Progress bar on standard error, which is hidden when standard error is not
+/// a terminal.
+pub struct Bar {
+    bar: ProgressBar,
+}
+
+impl Bar {
+    pub fn new() -> Self {
+        Bar {
+            bar: ProgressBar::new_spinner(),
+        }
+    }
+
+    fn message(status: &Status) -> String {
+        let mut message = format!("{}: {} records", status.stage, status.records);
+        if let Some(total) = status.total_files {
+            message += &format!(", {}/{} files", status.files, total);
+        }
+        if status.bytes > 0 {
+            message += &format!(", {}", HumanBytes(status.bytes));
+        }
+        if let Some(eta) = status.eta() {
+            message += &format!(", eta {}", HumanDuration(eta));
+        }
+        message
+    }
+}
+
+impl Progress for Bar {
+    fn stage(&mut self, status: &Status) {
+        let style = if status.fraction().is_some() {
+            self.bar.set_length(STEPS);
+            ProgressStyle::with_template("[{elapsed_precise}] {bar:30} {percent:>3}% {msg}")
+        } else {
+            ProgressStyle::with_template("[{elapsed_precise}] {spinner} {msg}")
+        };
+        self.bar
+            .set_style(style.unwrap_or_else(|_| ProgressStyle::default_spinner()));
+        self.update(status);
+    }
+
+    fn update(&mut self, status: &Status) {
+        if let Some(fraction) = status.fraction() {
+            self.bar
+                .set_position((fraction.min(1.0) * STEPS as f64) as u64);
+        } else {
+            self.bar.tick();
+        }
+        self.bar.set_message(Bar::message(status));
+    }
+
+    fn finish(&mut self, _status: &Status) {
+        self.bar.finish_and_clear();
+    }
+}
@@ -0,0 +1,175 @@
+use clap::{Args, Subcommand};
+use cli::{create_output, Range, Result};
+use starquad::gaia::filter::Filter;
+use starquad::gaia::projection::Projection;
+use starquad::output::geojson::{Feature, FeatureCollection, Geometry};
+use starquad::output::Format;
+use starquad::sky::position::SkyPosition;
+use starquad::sky::region::Region;
+use starquad::store::file::StarFile;
+use starquad::store::{Cursor, Store};
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct QueryArgs {
+    #[command(subcommand)]
+    region: RegionArgs,
+}
+
+#[derive(Subcommand)]
+enum RegionArgs {
+    /// Find the records within a radius of a position.
+    Cone {
+        /// Right ascension of the center, in degrees.
+        #[arg(long)]
+        ra: f64,
+        /// Declination of the center, in degrees.
+        #[arg(long, allow_hyphen_values = true)]
+        dec: f64,
+        /// Radius, in degrees.
+        #[arg(long)]
+        radius: f64,
+        #[command(flatten)]
+        options: QueryOptions,
+    },
+    /// Find the records in a range of right ascension and declination.
+    Box {
+        /// Range of right ascension, in degrees.
+        #[arg(long, allow_hyphen_values = true, default_value = "0,360")]
+        ra_range: Range,
+        /// Range of declination, in degrees.
+        #[arg(long, allow_hyphen_values = true, default_value = "-90,90")]
+        dec_range: Range,
+        #[command(flatten)]
+        options: QueryOptions,
+    },
+}
+
+#[derive(Args)]
+struct QueryOptions {
+    /// Directory of an index written by `build-index`, or a file written by
+    /// `pack`.
+    index: PathBuf,
+    /// Propagate positions to this epoch (a Julian year, eg. the epoch of
+    /// observation of another catalog) before testing them against the
+    /// region.
+    #[arg(long)]
+    epoch: Option<f64>,
+    /// Only return records matching a filter expression.
+    #[arg(long)]
+    filter: Option<Filter>,
+    /// Comma-separated columns to output (default: all).
+    #[arg(long, value_delimiter = ',')]
+    columns: Vec<String>,
+    /// Output format: csv, jsonl, bincode or arrow (an Arrow IPC stream, if
+    /// enabled).
+    #[arg(long, default_value = "csv")]
+    format: Format,
+    /// Only output this many records, and print the cursor of the next
+    /// page to stderr if there may be more.
+    #[arg(long)]
+    limit: Option<usize>,
+    /// Continue from the cursor printed by the same query with `--limit`.
+    #[arg(long, default_value = "start")]
+    cursor: Cursor,
+    /// Output file (default: stdout).
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Also write the region of the query to a GeoJSON file.
+    #[arg(long)]
+    footprint: Option<PathBuf>,
+}
+
+pub fn run(args: QueryArgs) -> Result<()> {
+    let (region, options) = match args.region {
+        RegionArgs::Cone {
+            ra,
+            dec,
+            radius,
+            options,
+        } => (Region::cone(SkyPosition::new(ra, dec), radius), options),
+        RegionArgs::Box {
+            ra_range,
+            dec_range,
+            options,
+        } => (
+            Region::sky_box(ra_range.min, ra_range.max, dec_range.min, dec_range.max)?,
+            options,
+        ),
+    };
+    if options.index.is_file() {
+        query_file(&region, &options)?;
+    } else {
+        query_store(&region, &options)?;
+    }
+    // written once the query has succeeded, so that a failed query leaves
+    // no footprint behind
+    if let Some(path) = &options.footprint {
+        let feature = Feature::new(Geometry::from(&region)).with_property("area", region.area());
+        let features = vec![feature];
+        FeatureCollection { features }.write(create_output(Some(path))?)?;
+    }
+    Ok(())
+}
+
+/// Query a directory written by `build-index`.
+fn query_store(region: &Region, options: &QueryOptions) -> Result<()> {
+    let projection = if options.columns.is_empty() {
+        Projection::all()
+    } else {
+        Projection::new(&options.columns)?
+    };
+    let store = Store::open(&options.index)?;
+    let query = match options.epoch {
+        Some(epoch) => store.query_at(region, epoch),
+        None => store.query(region),
+    };
+    let mut query = query.select(&projection);
+    let mut sink = options
+        .format
+        .projected_sink(create_output(options.output.as_deref())?, projection);
+    if let Some(filter) = &options.filter {
+        query = query.matching(filter);
+    }
+    let mut query = query.after(options.cursor);
+    for record in query.by_ref().take(options.limit.unwrap_or(usize::MAX)) {
+        sink.write(&record?)?;
+    }
+    sink.finish()?;
+    if options.limit.is_some() {
+        let cursor = query.cursor();
+        if query.next().is_some() {
+            eprintln!("next cursor: {}", cursor);
+        }
+    }
+    Ok(())
+}
+
+/// Query a file written by `pack`, which holds only the columns it was
+/// packed with (and outputs them by default) and has no cursors.
+fn query_file(region: &Region, options: &QueryOptions) -> Result<()> {
+    if options.epoch.is_some() || options.cursor != Cursor::Start {
+        return Err("--epoch and --cursor need an index".into());
+    }
+    let file = StarFile::open(&options.index)?;
+    let projection = if options.columns.is_empty() {
+        Projection::new(&file.metadata().columns())?
+    } else {
+        Projection::new(&options.columns)?
+    };
+    if let Some(name) = projection.names().iter().find(|c| !file.contains(c)) {
+        return Err(format!("column {} is not stored in the file", name).into());
+    }
+    let mut query = file.query(region);
+    if let Some(filter) = &options.filter {
+        query = query.matching(filter)?;
+    }
+    let mut sink = options
+        .format
+        .projected_sink(create_output(options.output.as_deref())?, projection);
+    for record in query.take(options.limit.unwrap_or(usize::MAX)) {
+        sink.write(&record?)?;
+    }
+    sink.finish()?;
+    Ok(())
+}
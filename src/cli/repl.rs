@@ -0,0 +1,323 @@
+use clap::Args;
+use cli::{result_cache, Range, Result};
+use starquad::gaia::filter::Filter;
+use starquad::gaia::projection::Projection;
+use starquad::gaia::record::GaiaRecord;
+use starquad::output::Format;
+use starquad::sky::position::SkyPosition;
+use starquad::sky::region::Region;
+use starquad::store::cache::{CachedStore, DEFAULT_CAPACITY};
+use starquad::store::results::{QueryKey, QueryResult, ResultCache};
+use starquad::store::{self, Store};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+
+#[derive(Args)]
+pub struct ReplArgs {
+    /// Directory of an index written by `build-index`.
+    index: PathBuf,
+    /// Maximum number of records to keep in memory between queries.
+    #[arg(long, default_value_t = DEFAULT_CAPACITY)]
+    cache: u64,
+    /// Keep the results of recent queries, of up to this many records in
+    /// all, to answer repeated queries without scanning shards.
+    #[arg(long)]
+    result_cache: Option<u64>,
+    /// Drop cached results after this many seconds.
+    #[arg(long, requires = "result_cache")]
+    result_ttl: Option<f64>,
+}
+
+const HELP: &str = "\
+commands:
+  cone RA DEC RADIUS [> FILE]           records within RADIUS degrees of (RA, DEC)
+  box RAMIN,RAMAX DECMIN,DECMAX [> FILE]  records in a range of ra and dec
+  count cone ... | count box ...        number of matching records
+  explain cone ... | explain box ...    shards read by a query
+  filter [EXPR]                         set or (with no EXPR) clear the filter
+  columns [A,B,...]                     set or (with no list) clear the columns
+  format csv|jsonl                      set the output format
+  show                                  print the current settings
+  cache [clear]                         print or clear the shard and result caches
+  help                                  print this message
+  quit                                  exit
+
+Query output goes to standard output, or to FILE with `> FILE`, or is
+appended to FILE with `>> FILE`.";
+
+/// Settings which apply to every query of a session.
+struct Session {
+    store: CachedStore,
+    results: Option<ResultCache>,
+    filter: Option<(String, Filter)>,
+    columns: Projection,
+    format: Format,
+}
+
+/// Destination of the records of a query.
+enum Output {
+    Stdout,
+    Create(PathBuf),
+    Append(PathBuf),
+}
+
+/// Split `> FILE` or `>> FILE` from the end of a command.
+fn split_output(line: &str) -> (&str, Output) {
+    match line.find('>') {
+        Some(i) => {
+            let (append, path) = match line[i + 1..].strip_prefix('>') {
+                Some(path) => (true, path),
+                None => (false, &line[i + 1..]),
+            };
+            let path = PathBuf::from(path.trim());
+            let output = if append {
+                Output::Append(path)
+            } else {
+                Output::Create(path)
+            };
+            (&line[..i], output)
+        }
+        None => (line, Output::Stdout),
+    }
+}
+
+fn parse_region(words: &[&str]) -> std::result::Result<Region, String> {
+    let number = |s: &str| s.parse::<f64>().map_err(|e| format!("{}: {}", s, e));
+    match words {
+        ["cone", ra, dec, radius] => Ok(Region::cone(
+            SkyPosition::new(number(ra)?, number(dec)?),
+            number(radius)?,
+        )),
+        ["box", ra, dec] => {
+            let (ra, dec) = (ra.parse::<Range>()?, dec.parse::<Range>()?);
+            Region::sky_box(ra.min, ra.max, dec.min, dec.max).map_err(|e| e.to_string())
+        }
+        ["cone", ..] => Err(String::from("usage: cone RA DEC RADIUS")),
+        ["box", ..] => Err(String::from("usage: box RAMIN,RAMAX DECMIN,DECMAX")),
+        _ => Err(String::from("expected a cone or box query")),
+    }
+}
+
+impl Session {
+    /// Run one command, returning `false` when the session should end.
+    fn execute(&mut self, line: &str) -> Result<bool> {
+        let line = line.trim();
+        let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        match command {
+            "" => {}
+            "quit" | "exit" => return Ok(false),
+            "help" => println!("{}", HELP),
+            "filter" if rest.is_empty() => self.filter = None,
+            "filter" => self.filter = Some((String::from(rest), rest.parse()?)),
+            "columns" if rest.is_empty() => self.columns = Projection::all(),
+            "columns" => {
+                self.columns = Projection::new(&rest.split(',').map(str::trim).collect::<Vec<_>>())?
+            }
+            "format" => self.format = rest.parse()?,
+            "show" => self.show(),
+            "cache" if rest == "clear" => {
+                self.store.clear();
+                if let Some(results) = &mut self.results {
+                    results.clear();
+                }
+            }
+            "cache" => {
+                println!(
+                    "{} shards, {} records in memory",
+                    self.store.cached_shards(),
+                    self.store.cached_records()
+                );
+                if let Some(results) = &self.results {
+                    println!(
+                        "{} results, {} records in memory ({} hits, {} misses)",
+                        results.len(),
+                        results.cached_records(),
+                        results.hits(),
+                        results.misses()
+                    );
+                }
+            }
+            "count" => {
+                let region = parse_region(&rest.split_whitespace().collect::<Vec<_>>())?;
+                let mut count = 0;
+                self.for_each_match(&region, |_| {
+                    count += 1;
+                    Ok(())
+                })?;
+                println!("{}", count);
+            }
+            "explain" => {
+                let region = parse_region(&rest.split_whitespace().collect::<Vec<_>>())?;
+                self.explain(&region);
+            }
+            "cone" | "box" => {
+                let (query, output) = split_output(line);
+                let region = parse_region(&query.split_whitespace().collect::<Vec<_>>())?;
+                self.query(&region, output)?;
+            }
+            _ => return Err(format!("unknown command: {} (try `help`)", command).into()),
+        }
+        Ok(true)
+    }
+
+    fn show(&self) {
+        let manifest = self.store.store().manifest();
+        println!(
+            "index:   {} records in {} shards",
+            manifest.records(),
+            manifest.shards.len()
+        );
+        match &self.filter {
+            Some((text, _)) => println!("filter:  {}", text),
+            None => println!("filter:  none"),
+        }
+        if self.columns == Projection::all() {
+            println!("columns: all");
+        } else {
+            println!("columns: {}", self.columns.names().join(","));
+        }
+        println!("format:  {:?}", self.format);
+    }
+
+    fn explain(&self, region: &Region) {
+        let plans = self.store.explain(region);
+        let records = plans.iter().map(|p| p.shard.records).sum::<u64>();
+        let cached = plans.iter().filter(|p| p.cached).count();
+        println!(
+            "{} shards ({} cached), {} records to scan",
+            plans.len(),
+            cached,
+            records
+        );
+        for plan in &plans {
+            let shard = &plan.shard;
+            println!(
+                "  pixel {:>8}: {:>10} records, ra [{:.3}, {:.3}], dec [{:.3}, {:.3}]{}",
+                shard.pixel,
+                shard.records,
+                shard.min_ra,
+                shard.max_ra,
+                shard.min_dec,
+                shard.max_dec,
+                if plan.cached { ", cached" } else { "" }
+            );
+        }
+        if let Some((text, filter)) = &self.filter {
+            println!("filter {} on columns {}", text, filter.columns().join(","));
+        }
+    }
+
+    /// Records in a region which match the filter, read as they are
+    /// needed.
+    fn matching<'a>(
+        &'a mut self,
+        region: &Region,
+    ) -> impl Iterator<Item = std::result::Result<GaiaRecord, store::Error>> + 'a {
+        let filter = self.filter.as_ref().map(|(_, f)| f);
+        self.store.stream(region).filter(move |r| {
+            r.as_ref()
+                .map_or(true, |r| filter.is_none_or(|f| f.matches(r)))
+        })
+    }
+
+    /// Pass the records in a region which match the filter to `f`, from
+    /// the result cache if it holds them, and otherwise keeping them there
+    /// if they fit.
+    fn for_each_match<F>(&mut self, region: &Region, mut f: F) -> Result<()>
+    where
+        F: FnMut(&GaiaRecord) -> Result<()>,
+    {
+        // the records hold every column, whichever are output
+        let filter = self.filter.as_ref().map(|(_, f)| f);
+        let key = QueryKey::new(region, filter, &Projection::all());
+        let mut kept = match &mut self.results {
+            Some(results) => match results.get(&key) {
+                Some(result) => return result.records.iter().try_for_each(f),
+                None => Some((Vec::new(), results.max_records())),
+            },
+            None => None,
+        };
+        for record in self.matching(region) {
+            let record = record?;
+            f(&record)?;
+            kept = kept.filter(|(records, max)| (records.len() as u64) < *max);
+            if let Some((records, _)) = &mut kept {
+                records.push(record);
+            }
+        }
+        if let (Some(results), Some((records, _))) = (&mut self.results, kept) {
+            let result = QueryResult {
+                records,
+                truncated: None,
+            };
+            results.insert(key, Arc::new(result));
+        }
+        Ok(())
+    }
+
+    fn query(&mut self, region: &Region, output: Output) -> Result<()> {
+        let start = Instant::now();
+        let format = self.format;
+        let columns = self.columns.clone();
+        let writer: Box<dyn Write> = match &output {
+            Output::Stdout => Box::new(io::stdout()),
+            Output::Create(path) => Box::new(File::create(path)?),
+            Output::Append(path) => {
+                Box::new(OpenOptions::new().append(true).create(true).open(path)?)
+            }
+        };
+        let mut sink = format.projected_sink(writer, columns);
+        let mut written = 0;
+        self.for_each_match(region, |record| {
+            sink.write(record)?;
+            written += 1;
+            Ok(())
+        })?;
+        sink.finish()?;
+        eprintln!(
+            "{} records in {:.3} s",
+            written,
+            start.elapsed().as_secs_f64()
+        );
+        Ok(())
+    }
+}
+
+pub fn run(args: ReplArgs) -> Result<()> {
+    let store = Store::open(&args.index)?;
+    let results = result_cache(args.result_cache, args.result_ttl)?;
+    let mut session = Session {
+        store: CachedStore::new(store).with_capacity(args.cache),
+        results,
+        filter: None,
+        columns: Projection::all(),
+        format: Format::Csv,
+    };
+    let interactive = io::stdin().is_terminal();
+    if interactive {
+        eprintln!("index {} (type `help` for commands)", args.index.display());
+    }
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        if interactive {
+            eprint!("starquad> ");
+            io::stderr().flush()?;
+        }
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => break,
+        };
+        match session.execute(&line) {
+            Ok(true) => {}
+            Ok(false) => break,
+            // a bad command should not end an interactive session
+            Err(e) if interactive => eprintln!("error: {}", e),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
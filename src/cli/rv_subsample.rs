@@ -0,0 +1,55 @@
+use clap::Args;
+use cli::{create_output, expand_inputs, read_all, Range, Result};
+use starquad::gaia::kinematics::RadialVelocitySubsample;
+use starquad::geom::p3::P3;
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct RvSubsampleArgs {
+    /// Gaia CSV files (optionally gzipped), or directories of them.
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+    /// Only keep sources within `--radius` parsecs of this Galactic
+    /// Cartesian position, given as `X,Y,Z` in parsecs.
+    #[arg(long, value_delimiter = ',', num_args = 3, requires = "radius")]
+    center: Option<Vec<f64>>,
+    /// Radius around `--center`, in parsecs.
+    #[arg(long, requires = "center")]
+    radius: Option<f64>,
+    /// Only keep sources with a heliocentric speed in this range, as
+    /// `MIN,MAX` in km/s.
+    #[arg(long)]
+    speed: Option<Range>,
+    /// Output CSV file (default: stdout).
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+/// Write the phase-space coordinates of the radial-velocity subsample, after
+/// indexing it by position.
+pub fn run(args: RvSubsampleArgs) -> Result<()> {
+    let mut error = None;
+    let inputs = expand_inputs(&args.inputs)?;
+    let records = read_all(&inputs).map_while(|r| r.map_err(|e| error = Some(e)).ok());
+    let index = RadialVelocitySubsample::new(records).index();
+    if let Some(error) = error {
+        return Err(error);
+    }
+    tracing::info!(sources = index.len(), "indexed");
+    let mut selected = match (&args.center, args.radius) {
+        (Some(center), Some(radius)) => {
+            index.query_radius(&P3::new(center[0], center[1], center[2]), radius)
+        }
+        _ => index.items().iter().collect(),
+    };
+    if let Some(speed) = args.speed {
+        selected.retain(|(_, p)| speed.min <= p.speed() && p.speed() <= speed.max);
+    }
+    selected.sort_unstable_by_key(|(_, p)| p.source_id);
+    let mut writer = csv::Writer::from_writer(create_output(args.output.as_deref())?);
+    for (_, phase_space) in selected {
+        writer.serialize(phase_space)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
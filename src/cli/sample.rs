@@ -0,0 +1,83 @@
+use clap::Args;
+use cli::{expand_inputs, read_all, Result};
+use starquad::gaia::filter::Filter;
+use starquad::gaia::record::GaiaRecord;
+use starquad::gaia::sample::{reservoir, Bernoulli};
+use starquad::gaia::writer::{self, GaiaWriter};
+use std::fs::File;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct SampleArgs {
+    /// Gaia CSV files (optionally gzipped), or directories of them.
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+    /// Keep each record with this probability.
+    #[arg(long, conflicts_with = "size", required_unless_present = "size")]
+    fraction: Option<f64>,
+    /// Keep exactly this many records, chosen uniformly.
+    #[arg(long)]
+    size: Option<usize>,
+    /// Only sample records matching a filter expression, such as
+    /// `parallax_over_error > 10 && bp_rp != null`.
+    #[arg(long)]
+    filter: Option<Filter>,
+    /// Seed of the random number generator.
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    /// Output file, gzipped if its name ends in `.gz` (default: stdout).
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+/// Write records as Gaia CSV, gzipped if the output file name ends in `.gz`.
+pub fn write_records<I>(output: Option<&PathBuf>, records: I) -> Result<()>
+where
+    I: IntoIterator<Item = GaiaRecord>,
+{
+    match output {
+        Some(path) if path.extension().is_some_and(|e| e == "gz") => {
+            let mut writer = writer::create_gz(path)?;
+            for record in records {
+                writer.write(&record)?;
+            }
+            writer.into_inner()?.finish()?;
+        }
+        Some(path) => {
+            let mut writer = GaiaWriter::new(File::create(path)?)?;
+            for record in records {
+                writer.write(&record)?;
+            }
+            writer.into_inner()?;
+        }
+        None => {
+            let mut writer = GaiaWriter::new(io::stdout())?;
+            for record in records {
+                writer.write(&record)?;
+            }
+            writer.into_inner()?;
+        }
+    }
+    Ok(())
+}
+
+pub fn run(args: SampleArgs) -> Result<()> {
+    // stop at the first error, rather than letting sampling skip it
+    let mut error = None;
+    let inputs = expand_inputs(&args.inputs)?;
+    let records = read_all(&inputs)
+        .map_while(|r| r.map_err(|e| error = Some(e)).ok())
+        .filter(|r| args.filter.as_ref().is_none_or(|f| f.matches(r)));
+    match (args.fraction, args.size) {
+        (_, Some(size)) => {
+            write_records(args.output.as_ref(), reservoir(records, size, args.seed))?
+        }
+        (Some(fraction), None) => write_records(
+            args.output.as_ref(),
+            Bernoulli::new(records, fraction, args.seed),
+        )?,
+        (None, None) => return Err("one of --fraction and --size is needed".into()),
+    }
+    error.map_or(Ok(()), Err)
+}
@@ -0,0 +1,59 @@
+use clap::Args;
+use cli::{result_cache, AuthArgs, Result};
+use starquad::server;
+use starquad::store::limits::QueryLimits;
+use starquad::store::Store;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Args)]
+pub struct ServeArgs {
+    /// Directory of an index written by `build-index`.
+    index: PathBuf,
+    /// Address to listen on.
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    address: String,
+    /// Stop each query after this many records.
+    #[arg(long)]
+    max_results: Option<u64>,
+    /// Stop each query after reading this many shards.
+    #[arg(long)]
+    max_shards: Option<usize>,
+    /// Stop each query after this many seconds.
+    #[arg(long)]
+    timeout: Option<f64>,
+    /// Keep the results of recent queries, of up to this many records in
+    /// all, to answer repeated queries from memory.
+    #[arg(long)]
+    result_cache: Option<u64>,
+    /// Drop cached results after this many seconds.
+    #[arg(long, requires = "result_cache")]
+    result_ttl: Option<f64>,
+    #[command(flatten)]
+    auth: AuthArgs,
+}
+
+pub fn run(args: ServeArgs) -> Result<()> {
+    let mut limits = QueryLimits::new();
+    if let Some(max_results) = args.max_results {
+        limits = limits.with_max_results(max_results);
+    }
+    if let Some(max_shards) = args.max_shards {
+        limits = limits.with_max_shards(max_shards);
+    }
+    if let Some(timeout) = args.timeout {
+        limits = limits.with_timeout(Duration::try_from_secs_f64(timeout)?);
+    }
+    let results = result_cache(args.result_cache, args.result_ttl)?;
+    let auth = args.auth.auth()?;
+    let store = Store::open(&args.index)?;
+    let listener = TcpListener::bind(&args.address)?;
+    eprintln!(
+        "serving {} records on http://{}",
+        store.manifest().records(),
+        listener.local_addr()?
+    );
+    server::serve(store, limits, results, auth, listener)?;
+    Ok(())
+}
@@ -0,0 +1,30 @@
+use clap::Args;
+use cli::{AuthArgs, Result};
+use starquad::grpc;
+use starquad::store::Store;
+use std::net::TcpListener;
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct ServeGrpcArgs {
+    /// Directory of an index written by `build-index`.
+    index: PathBuf,
+    /// Address to listen on.
+    #[arg(long, default_value = "127.0.0.1:50051")]
+    address: String,
+    #[command(flatten)]
+    auth: AuthArgs,
+}
+
+pub fn run(args: ServeGrpcArgs) -> Result<()> {
+    let auth = args.auth.auth()?;
+    let store = Store::open(&args.index)?;
+    let listener = TcpListener::bind(&args.address)?;
+    eprintln!(
+        "serving {} records over gRPC on {}",
+        store.manifest().records(),
+        listener.local_addr()?
+    );
+    grpc::serve(store, auth, listener)?;
+    Ok(())
+}
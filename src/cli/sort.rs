@@ -0,0 +1,48 @@
+use clap::Args;
+use cli::sample::write_records;
+use cli::{expand_inputs, read_all, Result};
+use starquad::gaia::sort::{ExternalSort, SortKey};
+use std::env;
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct SortArgs {
+    /// Gaia CSV files (optionally gzipped), or directories of them.
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+    /// Sort key: `source-id`, `healpix` or `morton`.
+    #[arg(long, default_value = "source-id")]
+    key: SortKey,
+    /// Number of records sorted in memory at a time.
+    #[arg(long, default_value_t = 1_000_000)]
+    run_size: usize,
+    /// Directory for the sorted runs (default: the system temporary
+    /// directory).
+    #[arg(long)]
+    temp_dir: Option<PathBuf>,
+    /// Output file, gzipped if its name ends in `.gz` (default: stdout).
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+/// Write the records of the inputs, sorted, using sorted runs on disk.
+pub fn run(args: SortArgs) -> Result<()> {
+    let mut error = None;
+    let inputs = expand_inputs(&args.inputs)?;
+    let records = read_all(&inputs).map_while(|r| r.map_err(|e| error = Some(e)).ok());
+    let dir = args
+        .temp_dir
+        .unwrap_or_else(|| env::temp_dir().join(format!("starquad-sort-{}", std::process::id())));
+    let sorted = ExternalSort::new(args.key, &dir)
+        .with_run_size(args.run_size)
+        .sort(records)?;
+    if let Some(error) = error {
+        return Err(error);
+    }
+    let mut merge_error = None;
+    write_records(
+        args.output.as_ref(),
+        sorted.map_while(|r| r.map_err(|e| merge_error = Some(e)).ok()),
+    )?;
+    merge_error.map_or(Ok(()), |e| Err(e.into()))
+}
@@ -0,0 +1,100 @@
+use clap::Args;
+use cli::Result;
+use csv::StringRecord;
+use starquad::gaia::reader;
+use starquad::gaia::schema;
+use starquad::gaia::stats::CatalogStats;
+use starquad::store::Store;
+use std::path::{Path, PathBuf};
+
+#[derive(Args)]
+pub struct StatsArgs {
+    /// Gaia CSV files, or index directories.
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+}
+
+fn print_stats(stats: &CatalogStats) {
+    let format = |value: Option<f64>| value.map_or(String::new(), |v| format!("{:.6}", v));
+    println!(
+        "  {:<36} {:>12} {:>12} {:>16} {:>16} {:>16}",
+        "column", "values", "nulls", "min", "max", "mean"
+    );
+    for column in stats.columns() {
+        println!(
+            "  {:<36} {:>12} {:>12} {:>16} {:>16} {:>16}",
+            column.name,
+            column.count,
+            column.nulls,
+            format(column.min),
+            format(column.max),
+            format(column.mean())
+        );
+    }
+}
+
+/// Print the schema, row count and column statistics of a Gaia CSV file.
+fn file_stats(path: &Path) -> Result<()> {
+    let mut reader = reader::open_csv(path)?;
+    let headers = reader.headers()?.clone();
+    let expected = schema::headers();
+    let missing = expected
+        .iter()
+        .filter(|name| !headers.iter().any(|h| h == *name))
+        .collect::<Vec<_>>();
+    let extra = headers
+        .iter()
+        .filter(|name| !expected.iter().any(|e| e == *name))
+        .collect::<Vec<_>>();
+    println!("{}:", path.display());
+    println!("  columns: {}", headers.len());
+    if !missing.is_empty() {
+        println!("  missing Gaia columns: {}", missing.join(", "));
+    }
+    if !extra.is_empty() {
+        println!("  other columns: {}", extra.join(", "));
+    }
+    // column statistics need the columns in the order of the Gaia schema
+    let mut stats = if headers == expected {
+        Some(CatalogStats::new())
+    } else {
+        None
+    };
+    let mut rows = 0;
+    let mut record = StringRecord::new();
+    while reader.read_record(&mut record)? {
+        rows += 1;
+        if let Some(stats) = stats.as_mut() {
+            stats.add_fields(&record);
+        }
+    }
+    println!("  rows: {}", rows);
+    if let Some(stats) = stats {
+        print_stats(&stats);
+    }
+    Ok(())
+}
+
+fn index_stats(path: &Path) -> Result<()> {
+    let store = Store::open(path)?;
+    let manifest = store.manifest();
+    println!("{}:", path.display());
+    println!("  rows: {}", manifest.records());
+    match &manifest.columns {
+        Some(columns) => println!("  columns: {}", columns.join(", ")),
+        None => println!("  columns: all"),
+    }
+    print_stats(&manifest.stats);
+    Ok(())
+}
+
+pub fn run(args: StatsArgs) -> Result<()> {
+    for path in &args.paths {
+        if path.is_dir() {
+            index_stats(path)?;
+        } else {
+            file_stats(path)?;
+        }
+    }
+    Ok(())
+}
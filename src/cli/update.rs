@@ -0,0 +1,62 @@
+use clap::Args;
+use cli::{expand_inputs, read_all, Result};
+use starquad::store::wal::Change;
+use starquad::store::Store;
+use std::mem;
+use std::path::PathBuf;
+
+/// Number of changes appended to the log with each sync.
+const BATCH: usize = 10_000;
+
+#[derive(Args)]
+pub struct UpdateArgs {
+    /// Directory of an index written by `build-index`.
+    index: PathBuf,
+    /// Gaia CSV files (optionally gzipped), or directories of them, whose
+    /// records are added to the index, replacing any of the same sources.
+    inputs: Vec<PathBuf>,
+    /// Comma-separated source IDs to remove from the index.
+    #[arg(long, value_delimiter = ',')]
+    remove: Vec<u64>,
+    /// Then rewrite the shards holding the changes, and empty the log.
+    #[arg(long)]
+    compact: bool,
+    /// Finally rewrite the index in a version of the format, for older
+    /// readers.
+    #[arg(long)]
+    format_version: Option<u32>,
+}
+
+/// Log changes to an index, and compact it or change its version if
+/// asked.
+pub fn run(args: UpdateArgs) -> Result<()> {
+    let mut store = Store::open(&args.index)?;
+    let mut batch = Vec::with_capacity(BATCH);
+    let mut pushed = 0;
+    for record in read_all(&expand_inputs(&args.inputs)?) {
+        batch.push(Change::Push(Box::new(record?)));
+        pushed += 1;
+        if batch.len() == BATCH {
+            store.apply(mem::take(&mut batch))?;
+        }
+    }
+    batch.extend(args.remove.iter().map(|&id| Change::Remove(id)));
+    if !batch.is_empty() {
+        store.apply(batch)?;
+    }
+    println!("records pushed:  {}", pushed);
+    println!("sources removed: {}", args.remove.len());
+    if args.compact {
+        let changed = store.pending().len();
+        let manifest = store.compact()?;
+        println!("compacted:       {} sources changed", changed);
+        println!("records indexed: {}", manifest.records());
+    } else {
+        println!("pending:         {} sources changed", store.pending().len());
+    }
+    if let Some(version) = args.format_version {
+        store.set_version(version)?;
+        println!("format version:  {}", version);
+    }
+    Ok(())
+}
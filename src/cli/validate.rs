@@ -0,0 +1,69 @@
+use clap::Args;
+use cli::{create_output, expand_inputs, Result};
+use starquad::gaia::validate::{self, FileReport};
+use starquad::output::jsonl_sink::JsonlSink;
+use starquad::output::RecordSink;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Args)]
+pub struct ValidateArgs {
+    /// Gaia CSV files, or directories of them.
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+    /// Checksum file to use instead of the `MD5SUM.txt` in the directory of
+    /// each file.
+    #[arg(long)]
+    md5sums: Option<PathBuf>,
+    /// Output file for the report (JSON Lines, one object per file).
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+/// Expected checksum of a file, looking up `MD5SUM.txt` files by directory.
+fn expected_md5(
+    path: &Path,
+    sums: &mut HashMap<PathBuf, HashMap<String, String>>,
+) -> Result<Option<String>> {
+    let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+    if !sums.contains_key(&dir) {
+        let dir_sums = validate::read_md5sums(if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            &dir
+        })?;
+        sums.insert(dir.clone(), dir_sums);
+    }
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned());
+    Ok(name.and_then(|n| sums[&dir].get(&n).cloned()))
+}
+
+pub fn run(args: ValidateArgs) -> Result<()> {
+    let given = match &args.md5sums {
+        Some(path) => Some(validate::parse_md5sums(&fs::read_to_string(path)?)),
+        None => None,
+    };
+    let mut sums = HashMap::new();
+    let mut sink = JsonlSink::new(create_output(args.output.as_deref())?);
+    let mut invalid = 0;
+    let inputs = expand_inputs(&args.inputs)?;
+    for path in &inputs {
+        let expected = match &given {
+            Some(given) => path
+                .file_name()
+                .and_then(|n| given.get(n.to_string_lossy().as_ref()).cloned()),
+            None => expected_md5(path, &mut sums)?,
+        };
+        let report = validate::validate_file(path, expected.as_deref())?;
+        if !report.valid {
+            invalid += 1;
+        }
+        sink.write(&report)?;
+    }
+    RecordSink::<FileReport>::finish(&mut sink)?;
+    if invalid > 0 {
+        return Err(format!("{} of {} files are invalid", invalid, inputs.len()).into());
+    }
+    Ok(())
+}
@@ -0,0 +1,126 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+/// A matched pair of sources from two catalogs.
+///
+/// `left` and `right` are the positions of the sources in their respective
+/// input catalogs, and `separation` is their angular separation in
+/// arcseconds (after any epoch propagation).
+///
+/// `score` is the likelihood ratio of the match (see `crossmatch::quality`),
+/// `candidates` the number of sources of the right catalog accepted as
+/// matches of the left source (including this one, and any which the match
+/// mode discarded), and `ambiguous` whether any of the others scores nearly
+/// as well as this one.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Match {
+    pub left: usize,
+    pub right: usize,
+    pub separation: f64,
+    pub score: f64,
+    pub candidates: usize,
+    pub ambiguous: bool,
+}
+
+/// Table of matches produced by a cross-match.
+///
+/// Rows are ordered by `left`, and then by increasing separation, so that the
+/// output of different matching algorithms can be compared directly.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct JoinTable {
+    rows: Vec<Match>,
+}
+
+impl JoinTable {
+    pub fn new() -> Self {
+        JoinTable { rows: Vec::new() }
+    }
+
+    /// Create a join table from matches in any order.
+    pub fn from_matches(mut rows: Vec<Match>) -> Self {
+        rows.sort_by(compare_matches);
+        JoinTable { rows }
+    }
+
+    pub fn rows(&self) -> &[Match] {
+        &self.rows
+    }
+
+    pub fn into_rows(self) -> Vec<Match> {
+        self.rows
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// Add the matches found for a single left source.
+    ///
+    /// `left` must be greater than the `left` of every row already in the
+    /// table.
+    pub(crate) fn extend_left(&mut self, mut matches: Vec<Match>) {
+        matches.sort_by(compare_matches);
+        self.rows.extend(matches);
+    }
+
+    pub(crate) fn rows_mut(&mut self) -> &mut [Match] {
+        &mut self.rows
+    }
+
+    /// Remove the row `(right, left)` of a self-match of a catalog when the
+    /// row `(left, right)` of the same pair, with `left < right`, is also in
+    /// the table.
+    pub(crate) fn retain_distinct_pairs(&mut self) {
+        let pairs = self
+            .rows
+            .iter()
+            .map(|m| (m.left, m.right))
+            .collect::<HashSet<_>>();
+        self.rows
+            .retain(|m| m.left < m.right || !pairs.contains(&(m.right, m.left)));
+    }
+
+    /// Keep only the pairs of sources which are each other's nearest match.
+    /// Ties are broken by the position of the source in its catalog.
+    ///
+    /// The table must hold every accepted pair, so this is linear in the
+    /// number of pairs, but that is quadratic in the density of the field.
+    pub(crate) fn retain_mutual(&mut self) {
+        let mut nearest_left = HashMap::<usize, &Match>::new();
+        for m in &self.rows {
+            let nearest = nearest_left.entry(m.right).or_insert(m);
+            if (m.separation, m.left) < (nearest.separation, nearest.left) {
+                *nearest = m;
+            }
+        }
+        let nearest_left = nearest_left
+            .into_iter()
+            .map(|(right, m)| (right, m.left))
+            .collect::<HashMap<_, _>>();
+        // rows are sorted, so the nearest match of each left source is the
+        // first of its rows
+        let mut previous = None;
+        self.rows.retain(|m| {
+            let nearest = previous != Some(m.left);
+            previous = Some(m.left);
+            nearest && nearest_left[&m.right] == m.left
+        });
+    }
+}
+
+/// Order matches by `left`, then separation, then `right`.
+pub(crate) fn compare_matches(a: &Match, b: &Match) -> Ordering {
+    a.left
+        .cmp(&b.left)
+        .then(
+            a.separation
+                .partial_cmp(&b.separation)
+                .unwrap_or(Ordering::Equal),
+        )
+        .then(a.right.cmp(&b.right))
+}
@@ -0,0 +1,766 @@
+use accel2d::quadtree::QuadTree;
+use accel2d::Accel2D;
+use crossmatch::join::{compare_matches, JoinTable, Match};
+use crossmatch::quality::{likelihood, MagnitudePrior, SourceDensity, AMBIGUITY_RATIO};
+use geom::p2::P2;
+use progress::{NoProgress, Tracker};
+use sky::index::sky_bounds;
+use sky::position::{SkyPosition, SkySource, ARCSEC_PER_DEG};
+use std::cmp::Ordering;
+
+/// Which matches to keep for each source of the left catalog.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MatchMode {
+    /// Keep only the nearest accepted match, or with a `MagnitudePrior` the
+    /// one with the highest score (the nearest of those scoring the same).
+    Best,
+    /// Keep every accepted match.
+    All,
+    /// Keep only pairs of sources which are each other's nearest accepted
+    /// match, so that no source is matched twice.
+    ///
+    /// Every accepted pair is kept until all of the left sources have been
+    /// matched, so this takes time and memory in proportion to the number
+    /// of pairs within the radius, which grows with the square of the
+    /// density of a crowded field.
+    Mutual,
+}
+
+/// Configuration of a positional cross-match between two catalogs.
+///
+/// A pair of sources is accepted as a match when their separation is no
+/// larger than `radius`. If an `n_sigma` factor is set, and the positional
+/// uncertainty of at least one of the sources is known, then the pair must
+/// additionally be within `n_sigma` times the combined one-sigma uncertainty
+/// of the two sources. `radius` therefore always acts as the maximum search
+/// radius.
+///
+/// Each match is scored by its likelihood ratio (see `crossmatch::quality`),
+/// taking the combined uncertainty of the pair to be `radius / n_sigma` (or
+/// a third of the radius) when neither uncertainty is known. With a
+/// `MagnitudePrior`, scores are also weighted by how well the magnitudes of
+/// the pair agree.
+///
+/// If an `epoch` is set, both catalogs are propagated to that epoch (using
+/// the proper motions of any sources which have them) before matching.
+///
+/// ```
+/// # use starquad::crossmatch::matcher::{CrossMatch, MatchMode};
+/// # use starquad::sky::position::SkyPosition;
+/// let left = vec![SkyPosition::new(10.0, 10.0), SkyPosition::new(20.0, 20.0)];
+/// let right = vec![SkyPosition::new(20.0, 20.0001), SkyPosition::new(30.0, 30.0)];
+/// let table = CrossMatch::new(1.0).match_catalogs(&left, &right);
+/// assert_eq!(table.len(), 1);
+/// assert_eq!((table.rows()[0].left, table.rows()[0].right), (1, 0));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct CrossMatch {
+    radius: f64,
+    n_sigma: Option<f64>,
+    epoch: Option<f64>,
+    mode: MatchMode,
+    magnitude_prior: Option<MagnitudePrior>,
+}
+
+/// Spatial index over the right-hand catalog of a cross-match.
+///
+/// Positions are stored after propagation to the epoch of the `CrossMatch`
+/// that created the index, so an index should only be used with the
+/// `CrossMatch` it was built by.
+pub struct MatchIndex {
+    tree: QuadTree<f64, IndexEntry>,
+    density: SourceDensity,
+}
+
+impl MatchIndex {
+    /// Index of sources whose positions are already at the matching epoch.
+    /// Their density is not counted, so scores must be scaled by the
+    /// density of the whole catalog (see `CrossMatch::select`).
+    pub(crate) fn from_entries(entries: Vec<IndexEntry>) -> Self {
+        let mut tree = QuadTree::with_bounds(sky_bounds());
+        for entry in entries {
+            tree.push((P2::new(entry.position.ra, entry.position.dec), entry));
+        }
+        MatchIndex {
+            tree,
+            density: SourceDensity::new(),
+        }
+    }
+}
+
+/// A source stored in a `MatchIndex`, or a left source being matched.
+#[derive(Clone, Debug)]
+pub(crate) struct IndexEntry {
+    pub index: usize,
+    pub position: SkyPosition,
+    pub error: Option<f64>,
+    pub magnitude: Option<f64>,
+}
+
+/// Order of the matches of a left source by descending score, then as by
+/// `compare_matches`.
+fn compare_scores(a: &Match, b: &Match) -> Ordering {
+    b.score
+        .partial_cmp(&a.score)
+        .unwrap_or(Ordering::Equal)
+        .then_with(|| compare_matches(a, b))
+}
+
+/// Length of an iterator, if its size hint gives it exactly.
+fn exact_len<I: Iterator>(iter: &I) -> Option<u64> {
+    match iter.size_hint() {
+        (lower, Some(upper)) if lower == upper => Some(lower as u64),
+        _ => None,
+    }
+}
+
+impl CrossMatch {
+    /// Create a cross-match with a maximum match radius in arcseconds.
+    pub fn new(radius: f64) -> Self {
+        CrossMatch {
+            radius,
+            n_sigma: None,
+            epoch: None,
+            mode: MatchMode::Best,
+            magnitude_prior: None,
+        }
+    }
+
+    /// Require matches to be within `n_sigma` times their combined
+    /// positional uncertainty.
+    pub fn with_n_sigma(mut self, n_sigma: f64) -> Self {
+        self.n_sigma = Some(n_sigma);
+        self
+    }
+
+    /// Propagate both catalogs to `epoch` (a Julian year) before matching.
+    pub fn with_epoch(mut self, epoch: f64) -> Self {
+        self.epoch = Some(epoch);
+        self
+    }
+
+    pub fn with_mode(mut self, mode: MatchMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Weight the scores of matches by the agreement of the magnitudes of
+    /// the two sources with a prior.
+    pub fn with_magnitude_prior(mut self, prior: MagnitudePrior) -> Self {
+        self.magnitude_prior = Some(prior);
+        self
+    }
+
+    /// Maximum match radius, in arcseconds.
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    pub fn mode(&self) -> MatchMode {
+        self.mode
+    }
+
+    /// Position of a source at the matching epoch.
+    pub fn position_of<T: SkySource>(&self, source: &T) -> SkyPosition {
+        match self.epoch {
+            Some(epoch) => source.position_at(epoch),
+            None => source.position(),
+        }
+    }
+
+    /// Entry of the source at a position in its catalog.
+    pub(crate) fn entry<T: SkySource>(&self, index: usize, source: &T) -> IndexEntry {
+        IndexEntry {
+            index,
+            position: self.position_of(source),
+            error: source.position_error(),
+            magnitude: source.magnitude(),
+        }
+    }
+
+    /// Largest separation (in arcseconds) at which two sources with the given
+    /// positional uncertainties (in milliarcseconds) are accepted as a match.
+    pub fn match_radius(&self, left_error: Option<f64>, right_error: Option<f64>) -> f64 {
+        match self.n_sigma {
+            None => self.radius,
+            Some(n_sigma) => {
+                let variance = match (left_error, right_error) {
+                    (None, None) => return self.radius,
+                    (Some(e), None) | (None, Some(e)) => e * e,
+                    (Some(l), Some(r)) => l * l + r * r,
+                };
+                let sigma_arcsec = variance.sqrt() / 1000.0;
+                self.radius.min(n_sigma * sigma_arcsec)
+            }
+        }
+    }
+
+    /// Combined one-sigma uncertainty (in arcseconds) of two sources with
+    /// the given positional uncertainties (in milliarcseconds), or the
+    /// radius divided by `n_sigma` (or 3) if neither is known.
+    pub fn sigma(&self, left_error: Option<f64>, right_error: Option<f64>) -> f64 {
+        let variance = left_error.unwrap_or(0.0).powi(2) + right_error.unwrap_or(0.0).powi(2);
+        if variance > 0.0 {
+            variance.sqrt() / 1000.0
+        } else {
+            self.radius / self.n_sigma.unwrap_or(3.0)
+        }
+    }
+
+    /// Build an index over the right-hand catalog.
+    pub fn index<I>(&self, right: I) -> MatchIndex
+    where
+        I: IntoIterator,
+        I::Item: SkySource,
+    {
+        self.index_with_progress(right, &mut Tracker::new(&mut NoProgress))
+    }
+
+    /// Build an index over the right-hand catalog, counting each indexed
+    /// source in an "indexing" stage of a tracker. If the tracker is
+    /// cancelled, the index only holds the sources read so far.
+    pub fn index_with_progress<I>(&self, right: I, tracker: &mut Tracker) -> MatchIndex
+    where
+        I: IntoIterator,
+        I::Item: SkySource,
+    {
+        let right = right.into_iter();
+        tracker.start_stage("indexing", exact_len(&right));
+        let mut tree = QuadTree::with_bounds(sky_bounds());
+        let mut density = SourceDensity::new();
+        for (index, source) in right.enumerate() {
+            let entry = self.entry(index, &source);
+            density.add(&entry.position);
+            tree.push((P2::new(entry.position.ra, entry.position.dec), entry));
+            tracker.record();
+            if tracker.cancelled() {
+                break;
+            }
+        }
+        MatchIndex { tree, density }
+    }
+
+    /// Match a stream of sources against a previously-built index.
+    pub fn match_index<I>(&self, left: I, index: &MatchIndex) -> JoinTable
+    where
+        I: IntoIterator,
+        I::Item: SkySource,
+    {
+        self.match_index_with_progress(left, index, &mut Tracker::new(&mut NoProgress))
+    }
+
+    /// Match a stream of sources against a previously-built index, counting
+    /// each source in a "matching" stage of a tracker. If the tracker is
+    /// cancelled, the table holds the matches of the sources read so far.
+    pub fn match_index_with_progress<I>(
+        &self,
+        left: I,
+        index: &MatchIndex,
+        tracker: &mut Tracker,
+    ) -> JoinTable
+    where
+        I: IntoIterator,
+        I::Item: SkySource,
+    {
+        let mut table = self.match_entries(left, index, false, tracker);
+        self.reduce(&mut table);
+        table
+    }
+
+    /// Matches of a stream of sources in an index, leaving out the pairs of
+    /// a source with itself if the index is of the same catalog.
+    fn match_entries<I>(
+        &self,
+        left: I,
+        index: &MatchIndex,
+        exclude_self: bool,
+        tracker: &mut Tracker,
+    ) -> JoinTable
+    where
+        I: IntoIterator,
+        I::Item: SkySource,
+    {
+        let left = left.into_iter();
+        tracker.start_stage("matching", exact_len(&left));
+        let mut table = JoinTable::new();
+        for (left_index, source) in left.enumerate() {
+            let entry = self.entry(left_index, &source);
+            let mut matches = Vec::new();
+            self.extend_candidates(&entry, index, &mut matches);
+            if exclude_self {
+                matches.retain(|m| m.right != left_index);
+            }
+            table.extend_left(self.select(matches, index.density.density(&entry.position)));
+            tracker.record();
+            if tracker.cancelled() {
+                break;
+            }
+        }
+        table
+    }
+
+    /// Match the sources of a catalog with the other sources of the same
+    /// catalog, to find duplicates or close pairs.
+    ///
+    /// Each source is matched as a left source against the others, so in
+    /// `Best` mode it is paired with its nearest neighbour. A pair found
+    /// from both of its sources (as every pair is in `All` and `Mutual`
+    /// modes) is listed once, with the lower position as `left`.
+    ///
+    /// ```
+    /// # use starquad::crossmatch::matcher::{CrossMatch, MatchMode};
+    /// # use starquad::sky::position::SkyPosition;
+    /// let sources = vec![
+    ///     SkyPosition::new(10.0, 10.0),
+    ///     SkyPosition::new(20.0, 20.0),
+    ///     SkyPosition::new(10.0, 10.0001),
+    /// ];
+    /// let table = CrossMatch::new(1.0).match_self(&sources);
+    /// assert_eq!(table.len(), 1);
+    /// assert_eq!((table.rows()[0].left, table.rows()[0].right), (0, 2));
+    /// ```
+    pub fn match_self<T: SkySource>(&self, sources: &[T]) -> JoinTable {
+        self.match_self_with_progress(sources, &mut Tracker::new(&mut NoProgress))
+    }
+
+    /// Match the sources of a catalog with each other, reporting the progress
+    /// of indexing and matching.
+    pub fn match_self_with_progress<T: SkySource>(
+        &self,
+        sources: &[T],
+        tracker: &mut Tracker,
+    ) -> JoinTable {
+        let index = self.index_with_progress(sources, tracker);
+        if tracker.cancelled() {
+            return JoinTable::new();
+        }
+        let mut table = self.match_entries(sources, &index, true, tracker);
+        self.reduce(&mut table);
+        table.retain_distinct_pairs();
+        table
+    }
+
+    /// Match two catalogs, indexing the right-hand catalog in memory.
+    pub fn match_catalogs<L, R>(&self, left: L, right: R) -> JoinTable
+    where
+        L: IntoIterator,
+        L::Item: SkySource,
+        R: IntoIterator,
+        R::Item: SkySource,
+    {
+        self.match_catalogs_with_progress(left, right, &mut Tracker::new(&mut NoProgress))
+    }
+
+    /// Match two catalogs, reporting the progress of indexing and matching.
+    pub fn match_catalogs_with_progress<L, R>(
+        &self,
+        left: L,
+        right: R,
+        tracker: &mut Tracker,
+    ) -> JoinTable
+    where
+        L: IntoIterator,
+        L::Item: SkySource,
+        R: IntoIterator,
+        R::Item: SkySource,
+    {
+        let index = self.index_with_progress(right, tracker);
+        if tracker.cancelled() {
+            return JoinTable::new();
+        }
+        self.match_index_with_progress(left, &index, tracker)
+    }
+
+    /// Add the accepted matches in an index of a left source.
+    pub(crate) fn extend_candidates(
+        &self,
+        left: &IndexEntry,
+        index: &MatchIndex,
+        matches: &mut Vec<Match>,
+    ) {
+        for rect in left.position.bounding_rects(self.radius / ARCSEC_PER_DEG) {
+            for (_, entry) in index.tree.query_rect(&rect) {
+                if let Some(m) = self.accept(left, entry) {
+                    matches.push(m);
+                }
+            }
+        }
+    }
+
+    /// Check a single candidate pair. The score of an accepted match is the
+    /// likelihood of its separation (weighted by any magnitude prior), which
+    /// `select` divides by the density.
+    pub(crate) fn accept(&self, left: &IndexEntry, right: &IndexEntry) -> Option<Match> {
+        let separation = left.position.separation(&right.position) * ARCSEC_PER_DEG;
+        if separation <= self.match_radius(left.error, right.error) {
+            let weight = match (&self.magnitude_prior, left.magnitude, right.magnitude) {
+                (Some(prior), Some(l), Some(r)) => prior.weight(l, r),
+                _ => 1.0,
+            };
+            Some(Match {
+                left: left.index,
+                right: right.index,
+                separation,
+                score: likelihood(separation, self.sigma(left.error, right.error)) * weight,
+                candidates: 1,
+                ambiguous: false,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Score and flag the accepted matches of a single left source, given
+    /// the density of the right catalog around it (in sources per square
+    /// arcsecond), and reduce them according to the match mode.
+    pub(crate) fn select(&self, mut matches: Vec<Match>, density: f64) -> Vec<Match> {
+        let candidates = matches.len();
+        let ambiguous = (0..candidates)
+            .map(|i| {
+                matches
+                    .iter()
+                    .enumerate()
+                    .any(|(j, other)| j != i && other.score >= matches[i].score * AMBIGUITY_RATIO)
+            })
+            .collect::<Vec<_>>();
+        for (m, ambiguous) in matches.iter_mut().zip(ambiguous) {
+            m.score /= density;
+            m.candidates = candidates;
+            m.ambiguous = ambiguous;
+        }
+        if self.mode == MatchMode::Best {
+            // ties are broken by the position in the right catalog, so that
+            // the result does not depend on the order candidates were found
+            if self.magnitude_prior.is_some() {
+                // a nearer match may be photometrically implausible
+                matches.sort_by(compare_scores);
+            } else {
+                matches.sort_by(compare_matches);
+            }
+            matches.truncate(1);
+        }
+        matches
+    }
+
+    /// Reduce a table of the selected matches of all left sources according
+    /// to the match mode. Mutual matches need the candidates of every left
+    /// source, so `select` keeps them all, and the pairs which are not each
+    /// other's nearest are only removed here.
+    pub(crate) fn reduce(&self, table: &mut JoinTable) {
+        if self.mode == MatchMode::Mutual {
+            table.retain_mutual();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crossmatch::matcher::{CrossMatch, MatchMode};
+    use crossmatch::quality::{likelihood, MagnitudePrior};
+    use quickcheck_macros::quickcheck;
+    use sky::position::{SkyPosition, SkySource, ARCSEC_PER_DEG};
+
+    struct Star {
+        position: SkyPosition,
+        error: Option<f64>,
+        pm: Option<(f64, f64)>,
+        magnitude: Option<f64>,
+    }
+
+    impl SkySource for Star {
+        fn position(&self) -> SkyPosition {
+            self.position
+        }
+
+        fn position_error(&self) -> Option<f64> {
+            self.error
+        }
+
+        fn epoch(&self) -> Option<f64> {
+            Some(2015.5)
+        }
+
+        fn proper_motion(&self) -> Option<(f64, f64)> {
+            self.pm
+        }
+
+        fn magnitude(&self) -> Option<f64> {
+            self.magnitude
+        }
+    }
+
+    fn offset(ra: f64, dec: f64, arcsec: f64) -> SkyPosition {
+        SkyPosition::new(ra, dec + arcsec / ARCSEC_PER_DEG)
+    }
+
+    #[test]
+    fn best_and_all() {
+        let left = vec![SkyPosition::new(0.0, 0.0)];
+        let right = vec![
+            offset(0.0, 0.0, 0.8),
+            offset(0.0, 0.0, 0.3),
+            offset(0.0, 0.0, 1.2),
+        ];
+        let best = CrossMatch::new(1.0).match_catalogs(&left, &right);
+        assert_eq!(best.len(), 1);
+        assert_eq!(best.rows()[0].right, 1);
+        let all = CrossMatch::new(1.0)
+            .with_mode(MatchMode::All)
+            .match_catalogs(&left, &right);
+        let rights = all.rows().iter().map(|m| m.right).collect::<Vec<_>>();
+        assert_eq!(rights, vec![1, 0]);
+    }
+
+    #[test]
+    fn mutual() {
+        let left = vec![
+            SkyPosition::new(0.0, 0.0),
+            offset(0.0, 0.0, 0.5),
+            SkyPosition::new(10.0, 0.0),
+        ];
+        let right = vec![offset(10.0, 0.0, 0.2), offset(0.0, 0.0, 0.4)];
+        let pairs = |mode| {
+            CrossMatch::new(1.0)
+                .with_mode(mode)
+                .match_catalogs(&left, &right)
+                .rows()
+                .iter()
+                .map(|m| (m.left, m.right))
+                .collect::<Vec<_>>()
+        };
+        // the first left source is nearest to the second right source, but
+        // that is nearer still to the second left source
+        assert_eq!(pairs(MatchMode::Best), vec![(0, 1), (1, 1), (2, 0)]);
+        assert_eq!(pairs(MatchMode::Mutual), vec![(1, 1), (2, 0)]);
+    }
+
+    #[test]
+    fn mutual_crowded() {
+        // a grid of sources 0.3 arcsec apart, each with over a hundred
+        // candidates within the radius
+        let jitter = |i: usize| (i * 7919 % 101) as f64 / 101.0 * 0.2;
+        let grid = |shift: usize| {
+            (0..900)
+                .map(|i| {
+                    let ra = (i % 30) as f64 * 0.3 + jitter(i + shift);
+                    let dec = (i / 30) as f64 * 0.3 + jitter(i * 3 + shift);
+                    SkyPosition::new(ra / ARCSEC_PER_DEG, dec / ARCSEC_PER_DEG)
+                })
+                .collect::<Vec<_>>()
+        };
+        let (left, right) = (grid(0), grid(17));
+        let table = CrossMatch::new(2.0)
+            .with_mode(MatchMode::Mutual)
+            .match_catalogs(&left, &right);
+        let separation = |l: usize, r: usize| left[l].separation(&right[r]);
+        let nearest_right = |l| {
+            (0..right.len())
+                .min_by(|&a, &b| separation(l, a).partial_cmp(&separation(l, b)).unwrap())
+                .unwrap()
+        };
+        let nearest_left = |r| {
+            (0..left.len())
+                .min_by(|&a, &b| separation(a, r).partial_cmp(&separation(b, r)).unwrap())
+                .unwrap()
+        };
+        let expected = (0..left.len())
+            .map(|l| (l, nearest_right(l)))
+            .filter(|&(l, r)| nearest_left(r) == l)
+            .collect::<Vec<_>>();
+        let pairs = table
+            .rows()
+            .iter()
+            .map(|m| (m.left, m.right))
+            .collect::<Vec<_>>();
+        assert!(expected.len() > 300);
+        assert_eq!(pairs, expected);
+    }
+
+    #[test]
+    fn self_match() {
+        let sources = vec![
+            SkyPosition::new(12.3, 4.5),
+            offset(12.3, 4.5, 0.3),
+            SkyPosition::new(40.0, 4.5),
+            offset(12.3, 4.5, 1.1),
+        ];
+        let pairs = |mode| {
+            CrossMatch::new(1.0)
+                .with_mode(mode)
+                .match_self(&sources)
+                .rows()
+                .iter()
+                .map(|m| (m.left, m.right, m.candidates))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(pairs(MatchMode::All), vec![(0, 1, 1), (1, 3, 2)]);
+        // the nearest neighbour of the last source is the second, whose own
+        // nearest neighbour is the first
+        assert_eq!(pairs(MatchMode::Best), vec![(0, 1, 1), (3, 1, 1)]);
+        assert_eq!(pairs(MatchMode::Mutual), vec![(0, 1, 1)]);
+    }
+
+    #[test]
+    fn scores() {
+        let left = vec![SkyPosition::new(12.3, 4.5), SkyPosition::new(40.0, 4.5)];
+        let right = vec![
+            offset(12.3, 4.5, 0.3),
+            offset(12.3, 4.5, 0.8),
+            offset(40.0, 4.5, 0.5),
+            offset(40.0, 4.5, 0.6),
+        ];
+        let table = CrossMatch::new(1.0)
+            .with_mode(MatchMode::All)
+            .match_catalogs(&left, &right);
+        let flags = table
+            .rows()
+            .iter()
+            .map(|m| (m.right, m.candidates, m.ambiguous))
+            .collect::<Vec<_>>();
+        // the likelihood of a 0.8 arcsec offset (with a sigma of a third of
+        // the radius) is 12 times lower than that of 0.3 arcsec
+        assert_eq!(
+            flags,
+            vec![(0, 2, false), (1, 2, true), (2, 2, true), (3, 2, true)]
+        );
+        let rows = table.rows();
+        assert!(rows[0].score > rows[1].score * 10.0);
+        // two sources in a pixel of about 0.84 square degrees
+        let density = 2.0 / (0.8393 * 3600.0 * 3600.0);
+        let expected = likelihood(0.3, 1.0 / 3.0) / density;
+        assert!((rows[0].score / expected - 1.0).abs() < 1e-3);
+        let best = CrossMatch::new(1.0).match_catalogs(&left, &right);
+        assert_eq!(best.rows()[0], rows[0]);
+        assert_eq!(best.rows()[1], rows[2]);
+    }
+
+    #[test]
+    fn magnitude_prior() {
+        let star = |position, magnitude| Star {
+            position,
+            error: None,
+            pm: None,
+            magnitude: Some(magnitude),
+        };
+        let left = vec![star(SkyPosition::new(12.3, 4.5), 15.0)];
+        let right = vec![
+            star(offset(12.3, 4.5, 0.3), 9.0),
+            star(offset(12.3, 4.5, 0.6), 14.4),
+        ];
+        let config = CrossMatch::new(1.0).with_mode(MatchMode::All);
+        let table = config.match_catalogs(&left, &right);
+        assert!(table.rows()[0].score > table.rows()[1].score);
+        assert_eq!(
+            CrossMatch::new(1.0).match_catalogs(&left, &right).rows()[0].right,
+            0
+        );
+        // a source six magnitudes brighter than expected is implausible
+        let prior = MagnitudePrior::new(-0.5, 0.3);
+        let table = config
+            .with_magnitude_prior(prior.clone())
+            .match_catalogs(&left, &right);
+        let rows = table.rows();
+        assert_eq!((rows[0].right, rows[1].right), (0, 1));
+        assert!(rows[0].score < rows[1].score * 1e-10);
+        assert!(rows[0].ambiguous);
+        assert!(!rows[1].ambiguous);
+        // so the best match is the further, plausible one
+        let best = CrossMatch::new(1.0)
+            .with_magnitude_prior(prior)
+            .match_catalogs(&left, &right);
+        assert_eq!(best.len(), 1);
+        assert_eq!(best.rows()[0], rows[1]);
+    }
+
+    #[test]
+    fn wraps_ra() {
+        let left = vec![SkyPosition::new(359.9999, 10.0)];
+        let right = vec![SkyPosition::new(0.0001, 10.0)];
+        let table = CrossMatch::new(1.0).match_catalogs(&left, &right);
+        assert_eq!(table.len(), 1);
+        assert!((table.rows()[0].separation - 0.72 * 10f64.to_radians().cos()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn error_aware() {
+        let left = vec![Star {
+            position: SkyPosition::new(50.0, 50.0),
+            error: Some(30.0),
+            pm: None,
+            magnitude: None,
+        }];
+        let right = vec![Star {
+            position: offset(50.0, 50.0, 0.2),
+            error: Some(40.0),
+            pm: None,
+            magnitude: None,
+        }];
+        // combined sigma is 50 mas, so 0.2 arcsec is a 4 sigma offset
+        let table = CrossMatch::new(1.0)
+            .with_n_sigma(5.0)
+            .match_catalogs(&left, &right);
+        assert_eq!(table.len(), 1);
+        let table = CrossMatch::new(1.0)
+            .with_n_sigma(3.0)
+            .match_catalogs(&left, &right);
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn epoch_propagation() {
+        // a high proper motion star, observed 20 years after the Gaia epoch
+        let left = vec![Star {
+            position: SkyPosition::new(120.0, -30.0),
+            error: None,
+            pm: Some((0.0, 1000.0)),
+            magnitude: None,
+        }];
+        let right = vec![offset(120.0, -30.0, 20.0)];
+        assert!(CrossMatch::new(1.0)
+            .match_catalogs(&left, &right)
+            .is_empty());
+        let table = CrossMatch::new(1.0)
+            .with_epoch(2035.5)
+            .match_catalogs(&left, &right);
+        assert_eq!(table.len(), 1);
+        assert!(table.rows()[0].separation < 1e-6);
+    }
+
+    /// Property test: the indexed matcher finds the same pairs as a brute
+    /// force comparison of all pairs.
+    #[quickcheck]
+    fn matches_brute_force(left: Vec<(u16, i16)>, right: Vec<(u16, i16)>) {
+        let to_positions = |coords: Vec<(u16, i16)>| {
+            coords
+                .into_iter()
+                .map(|(ra, dec)| {
+                    // cluster positions in a small region near the pole
+                    SkyPosition::new(
+                        f64::from(ra % 3600) / 10.0,
+                        89.0 + f64::from(dec % 100) / 100.0,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let (left, right) = (to_positions(left), to_positions(right));
+        let radius = 1800.0;
+        let table = CrossMatch::new(radius)
+            .with_mode(MatchMode::All)
+            .match_catalogs(&left, &right);
+        let mut expected = Vec::new();
+        for (i, l) in left.iter().enumerate() {
+            for (j, r) in right.iter().enumerate() {
+                if l.separation(r) * ARCSEC_PER_DEG <= radius {
+                    expected.push((i, j));
+                }
+            }
+        }
+        let mut actual = table
+            .rows()
+            .iter()
+            .map(|m| (m.left, m.right))
+            .collect::<Vec<_>>();
+        actual.sort();
+        assert_eq!(actual, expected);
+    }
+}
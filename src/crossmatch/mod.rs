@@ -0,0 +1,6 @@
+pub mod join;
+pub mod matcher;
+#[cfg(feature = "parallel")]
+pub mod parallel;
+pub mod quality;
+pub mod zones;
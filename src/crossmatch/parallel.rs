@@ -0,0 +1,365 @@
+//! Cross-matching on all cores, shard by shard.
+//!
+//! Both catalogs are partitioned into shards by the HEALPix pixel of each
+//! source (at the matching epoch). Each shard of the left catalog is paired
+//! with the shards of the right catalog whose bounds come within the match
+//! radius of its own, so a source near the edge of its pixel is compared
+//! with the sources across the edge as well, and the pairs are matched as
+//! tasks of rayon's work-stealing pool. The join table is the same as that
+//! of `CrossMatch::match_catalogs`.
+
+use accel2d::bvh::Bvh;
+use crossmatch::join::{compare_matches, JoinTable, Match};
+use crossmatch::matcher::{CrossMatch, IndexEntry, MatchIndex};
+use crossmatch::quality::SourceDensity;
+use geom::p2::P2;
+use geom::rect::Rect;
+use progress::{NoProgress, Tracker};
+use rayon::prelude::*;
+use sky::healpix;
+use sky::position::{SkySource, ARCSEC_PER_DEG};
+use sky::region::Region;
+use std::f64::consts::PI;
+
+/// Highest HEALPix order chosen by `shard_order`, whose pixels are about
+/// half a degree across.
+pub const MAX_SHARD_ORDER: u8 = 7;
+
+/// Number of batches in which the right shards are indexed and the left
+/// shards matched, after each of which progress is reported and
+/// cancellation is checked.
+const BATCHES: usize = 100;
+
+/// HEALPix order of shards at least 16 times as wide as a match radius (in
+/// arcseconds), so that most sources are far from the edges of their
+/// shards, up to `MAX_SHARD_ORDER`.
+pub fn shard_order(radius: f64) -> u8 {
+    let radius = radius / ARCSEC_PER_DEG;
+    (0..=MAX_SHARD_ORDER)
+        .rev()
+        .find(|&order| pixel_size(order) >= 16.0 * radius)
+        .unwrap_or(0)
+}
+
+/// Width of a pixel of an order, as the square root of its area, in
+/// degrees.
+fn pixel_size(order: u8) -> f64 {
+    (4.0 * PI / healpix::n_pixels(order) as f64)
+        .sqrt()
+        .to_degrees()
+}
+
+/// Sources of a catalog in one pixel.
+struct Shard {
+    /// Smallest rectangle containing the positions of the sources.
+    bounds: Rect<f64>,
+    entries: Vec<IndexEntry>,
+}
+
+/// Partition sources into shards of the pixels of an order.
+fn shards<T: SkySource + Sync>(config: &CrossMatch, sources: &[T], order: u8) -> Vec<Shard> {
+    let mut entries = sources
+        .par_iter()
+        .enumerate()
+        .map(|(index, source)| {
+            let entry = config.entry(index, source);
+            (healpix::pixel(order, &entry.position), entry)
+        })
+        .collect::<Vec<_>>();
+    entries.par_sort_unstable_by_key(|(pixel, entry)| (*pixel, entry.index));
+    entries
+        .chunk_by(|a, b| a.0 == b.0)
+        .filter_map(|pixel| {
+            let points = pixel
+                .iter()
+                .map(|(_, e)| P2::new(e.position.ra, e.position.dec))
+                .collect::<Vec<_>>();
+            // sources without positions (NaN) match nothing
+            Some(Shard {
+                bounds: Rect::bounding(&points)?,
+                entries: pixel.iter().map(|(_, e)| e.clone()).collect(),
+            })
+        })
+        .collect()
+}
+
+/// Match two catalogs in parallel, in shards of the pixels of a HEALPix
+/// order (see `shard_order`).
+///
+/// ```
+/// # use starquad::crossmatch::matcher::CrossMatch;
+/// # use starquad::crossmatch::parallel::{match_parallel, shard_order};
+/// # use starquad::sky::position::SkyPosition;
+/// let left = vec![SkyPosition::new(10.0, 10.0), SkyPosition::new(20.0, 20.0)];
+/// let right = vec![SkyPosition::new(20.0, 20.0001), SkyPosition::new(30.0, 30.0)];
+/// let config = CrossMatch::new(1.0);
+/// let table = match_parallel(&config, shard_order(1.0), &left, &right);
+/// assert_eq!(table, config.match_catalogs(&left, &right));
+/// ```
+pub fn match_parallel<L, R>(config: &CrossMatch, order: u8, left: &[L], right: &[R]) -> JoinTable
+where
+    L: SkySource + Sync,
+    R: SkySource + Sync,
+{
+    match_parallel_with_progress(
+        config,
+        order,
+        left,
+        right,
+        &mut Tracker::new(&mut NoProgress),
+    )
+}
+
+/// Match two catalogs in parallel, reporting the progress of indexing and
+/// matching. If the tracker is cancelled, the table holds the matches of
+/// the left sources whose shards were matched.
+pub fn match_parallel_with_progress<L, R>(
+    config: &CrossMatch,
+    order: u8,
+    left: &[L],
+    right: &[R],
+    tracker: &mut Tracker,
+) -> JoinTable
+where
+    L: SkySource + Sync,
+    R: SkySource + Sync,
+{
+    let mut table = match_shards(config, order, left, right, false, tracker);
+    config.reduce(&mut table);
+    table
+}
+
+/// Match the sources of a catalog with each other in parallel, as
+/// `CrossMatch::match_self` does.
+pub fn match_self_parallel<T: SkySource + Sync>(
+    config: &CrossMatch,
+    order: u8,
+    sources: &[T],
+) -> JoinTable {
+    match_self_parallel_with_progress(config, order, sources, &mut Tracker::new(&mut NoProgress))
+}
+
+/// Match the sources of a catalog with each other in parallel, reporting
+/// the progress of indexing and matching.
+pub fn match_self_parallel_with_progress<T: SkySource + Sync>(
+    config: &CrossMatch,
+    order: u8,
+    sources: &[T],
+    tracker: &mut Tracker,
+) -> JoinTable {
+    let mut table = match_shards(config, order, sources, sources, true, tracker);
+    config.reduce(&mut table);
+    table.retain_distinct_pairs();
+    table
+}
+
+/// Matches of the left catalog in the right one, leaving out the pairs of a
+/// source with itself if they are the same catalog.
+fn match_shards<L, R>(
+    config: &CrossMatch,
+    order: u8,
+    left: &[L],
+    right: &[R],
+    exclude_self: bool,
+    tracker: &mut Tracker,
+) -> JoinTable
+where
+    L: SkySource + Sync,
+    R: SkySource + Sync,
+{
+    let order = order.min(healpix::MAX_ORDER);
+    tracker.start_stage("indexing", Some(right.len() as u64));
+    let right_shards = shards(config, right, order);
+    let density = right_shards
+        .iter()
+        .flat_map(|shard| &shard.entries)
+        .map(|entry| &entry.position)
+        .collect::<SourceDensity>();
+    let bounds = Bvh::new(
+        right_shards
+            .iter()
+            .enumerate()
+            .map(|(i, shard)| (shard.bounds.clone(), i))
+            .collect(),
+    );
+    let batch_len = right_shards.len().div_ceil(BATCHES).max(1);
+    let mut right_shards = right_shards.into_iter();
+    let mut indexes = Vec::new();
+    loop {
+        let batch = right_shards.by_ref().take(batch_len).collect::<Vec<_>>();
+        if batch.is_empty() {
+            break;
+        }
+        let sources = batch.iter().map(|shard| shard.entries.len()).sum::<usize>();
+        indexes.par_extend(
+            batch
+                .into_par_iter()
+                .map(|shard| MatchIndex::from_entries(shard.entries)),
+        );
+        for _ in 0..sources {
+            tracker.record();
+        }
+        if tracker.cancelled() {
+            return JoinTable::new();
+        }
+    }
+
+    tracker.start_stage("matching", Some(left.len() as u64));
+    let left_shards = shards(config, left, order);
+    // widened a little, so that rounding never loses a pair of shards
+    // whose sources are separated by exactly the radius
+    let margin = config.radius() / ARCSEC_PER_DEG * (1.0 + 1e-9);
+    let mut rows = Vec::new();
+    for batch in left_shards.chunks(left_shards.len().div_ceil(BATCHES).max(1)) {
+        let matches = batch
+            .par_iter()
+            .map(|shard| {
+                let right = (&bounds, indexes.as_slice(), &density);
+                match_shard(config, shard, right, margin, exclude_self)
+            })
+            .collect::<Vec<_>>();
+        rows.extend(matches.into_iter().flatten());
+        for _ in batch.iter().flat_map(|shard| &shard.entries) {
+            tracker.record();
+        }
+        if tracker.cancelled() {
+            break;
+        }
+    }
+    rows.par_sort_by(compare_matches);
+    JoinTable::from_matches(rows)
+}
+
+/// The bounds of the shards of the right catalog, their indexes and the
+/// density of the catalog.
+type RightShards<'a> = (
+    &'a Bvh<f64, (Rect<f64>, usize)>,
+    &'a [MatchIndex],
+    &'a SourceDensity,
+);
+
+/// Match the sources of a left shard with those of the right shards within
+/// `margin` degrees of it.
+fn match_shard(
+    config: &CrossMatch,
+    shard: &Shard,
+    (bounds, indexes, density): RightShards,
+    margin: f64,
+    exclude_self: bool,
+) -> Vec<Match> {
+    let region = Region::Rect(shard.bounds.clone()).expanded(margin);
+    let mut paired = region
+        .bounding_rects()
+        .iter()
+        .flat_map(|rect| bounds.query_rect(rect))
+        .map(|(_, (_, i))| *i)
+        .collect::<Vec<_>>();
+    paired.sort_unstable();
+    paired.dedup();
+    let mut rows = Vec::new();
+    for entry in &shard.entries {
+        let mut matches = Vec::new();
+        for &i in &paired {
+            config.extend_candidates(entry, &indexes[i], &mut matches);
+        }
+        if exclude_self {
+            matches.retain(|m| m.right != entry.index);
+        }
+        rows.extend(config.select(matches, density.density(&entry.position)));
+    }
+    rows
+}
+
+#[cfg(test)]
+mod test {
+    use crossmatch::matcher::{CrossMatch, MatchMode};
+    use crossmatch::parallel::{
+        match_parallel, match_parallel_with_progress, match_self_parallel, shard_order,
+        MAX_SHARD_ORDER,
+    };
+    use progress::{CancelToken, NoProgress, Tracker};
+    use quickcheck_macros::quickcheck;
+    use sky::position::{SkyPosition, ARCSEC_PER_DEG};
+
+    #[test]
+    fn orders() {
+        assert_eq!(shard_order(1.0), MAX_SHARD_ORDER);
+        assert_eq!(shard_order(3600.0), 1);
+        assert_eq!(shard_order(36000.0), 0);
+    }
+
+    #[test]
+    fn across_shard_edges() {
+        // sources either side of the edges of the pixels of order 3, at
+        // ra = 0 and at the pole
+        let left = vec![
+            SkyPosition::new(359.9999, 10.0),
+            SkyPosition::new(45.0, 89.9999),
+            SkyPosition::new(90.0, 41.8103),
+        ];
+        let right = vec![
+            SkyPosition::new(0.0001, 10.0),
+            SkyPosition::new(225.0, 89.9999),
+            SkyPosition::new(90.0, 41.8104),
+        ];
+        let config = CrossMatch::new(1.0).with_mode(MatchMode::All);
+        let table = match_parallel(&config, 3, &left, &right);
+        let pairs = table
+            .rows()
+            .iter()
+            .map(|m| (m.left, m.right))
+            .collect::<Vec<_>>();
+        assert_eq!(pairs, vec![(0, 0), (1, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn cancelled_while_indexing() {
+        let sources = (0..1000)
+            .map(|i| SkyPosition::new(f64::from(i) * 0.36, 0.0))
+            .collect::<Vec<_>>();
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let mut progress = NoProgress;
+        let mut tracker = Tracker::new(&mut progress).with_cancel(cancel);
+        let config = CrossMatch::new(1.0);
+        let table = match_parallel_with_progress(&config, 3, &sources, &sources, &mut tracker);
+        assert!(table.is_empty());
+        // progress is recorded as the shards are indexed, so indexing stops
+        // after the first batch
+        assert_eq!(tracker.status().stage, "indexing");
+        let records = tracker.status().records;
+        assert!(records > 0 && records < 1000);
+    }
+
+    /// Property test: matching in shards finds the same matches as matching
+    /// with one index, in every mode, between catalogs and within one.
+    #[quickcheck]
+    fn matches_single_index(left: Vec<(u16, i16)>, right: Vec<(u16, i16)>, order: u8) {
+        let to_positions = |coords: Vec<(u16, i16)>| {
+            coords
+                .into_iter()
+                .map(|(ra, dec)| {
+                    // clustered across ra = 0 and near the pole, so that many
+                    // matches straddle the edges of shards
+                    SkyPosition::new(
+                        (f64::from(ra % 200) / 10.0 + 350.0) % 360.0,
+                        80.0 + f64::from(dec % 1000) / 100.0,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let (left, right) = (to_positions(left), to_positions(right));
+        let radius = 0.3 * ARCSEC_PER_DEG;
+        for mode in [MatchMode::All, MatchMode::Best, MatchMode::Mutual] {
+            let config = CrossMatch::new(radius).with_mode(mode);
+            assert_eq!(
+                match_parallel(&config, order % 6, &left, &right),
+                config.match_catalogs(&left, &right)
+            );
+            assert_eq!(
+                match_self_parallel(&config, order % 6, &left),
+                config.match_self(&left)
+            );
+        }
+    }
+}
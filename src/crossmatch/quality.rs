@@ -0,0 +1,147 @@
+//! Likelihood-ratio scores of matches.
+//!
+//! The score of a match is the likelihood ratio of Sutherland & Saunders
+//! (1992): the probability density of the separation of the pair if they
+//! are the same source (a two-dimensional Gaussian of the combined
+//! positional uncertainty), divided by the surface density of the right
+//! catalog around the left source, which is the probability density of
+//! finding an unrelated source there. A score well above one is a
+//! confident match; a score near or below one is no better than chance.
+//!
+//! A `MagnitudePrior` weights the score by how well the magnitude of the
+//! right source agrees with that expected from the magnitude of the left
+//! one, so that a pair which is close on the sky but whose magnitudes are
+//! implausibly different is down-weighted.
+
+use sky::healpix;
+use sky::position::{SkyPosition, ARCSEC_PER_DEG};
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::iter::FromIterator;
+
+/// HEALPix order of the pixels in which the density of the right catalog is
+/// counted, which are about a degree across.
+pub const DENSITY_ORDER: u8 = 6;
+
+/// A match is ambiguous if another candidate of its left source scores at
+/// least this fraction of its own score.
+pub const AMBIGUITY_RATIO: f64 = 0.1;
+
+/// Number of sources of a catalog in each pixel of `DENSITY_ORDER`.
+#[derive(Clone, Debug, Default)]
+pub struct SourceDensity {
+    counts: HashMap<u64, u64>,
+}
+
+impl SourceDensity {
+    pub fn new() -> Self {
+        SourceDensity::default()
+    }
+
+    /// Count a source at a position.
+    pub fn add(&mut self, position: &SkyPosition) {
+        *self
+            .counts
+            .entry(healpix::pixel(DENSITY_ORDER, position))
+            .or_default() += 1;
+    }
+
+    /// Sources per square arcsecond in the pixel of a position. A pixel
+    /// with no sources counts as holding one, so densities are never zero.
+    pub fn density(&self, position: &SkyPosition) -> f64 {
+        let pixel = healpix::pixel(DENSITY_ORDER, position);
+        let count = self.counts.get(&pixel).copied().unwrap_or(0).max(1);
+        let area = 4.0 * PI / healpix::n_pixels(DENSITY_ORDER) as f64;
+        let arcsec_per_radian = ARCSEC_PER_DEG * 180.0 / PI;
+        count as f64 / (area * arcsec_per_radian * arcsec_per_radian)
+    }
+}
+
+impl<'a> FromIterator<&'a SkyPosition> for SourceDensity {
+    fn from_iter<I: IntoIterator<Item = &'a SkyPosition>>(positions: I) -> Self {
+        let mut density = SourceDensity::new();
+        for position in positions {
+            density.add(position);
+        }
+        density
+    }
+}
+
+/// Expected relation between the magnitudes of matched sources: the right
+/// magnitude is expected to be `offset + slope * left` (eg. an external band
+/// against Gaia G), with a Gaussian scatter of `sigma` magnitudes.
+///
+/// ```
+/// # use starquad::crossmatch::quality::MagnitudePrior;
+/// let prior = MagnitudePrior::new(-0.5, 0.2);
+/// assert_eq!(prior.weight(15.0, 14.5), 1.0);
+/// assert!(prior.weight(15.0, 10.0) < 1e-20);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct MagnitudePrior {
+    offset: f64,
+    slope: f64,
+    sigma: f64,
+}
+
+impl MagnitudePrior {
+    /// Prior that the right magnitude is the left magnitude plus `offset`.
+    pub fn new(offset: f64, sigma: f64) -> Self {
+        MagnitudePrior {
+            offset,
+            slope: 1.0,
+            sigma,
+        }
+    }
+
+    pub fn with_slope(mut self, slope: f64) -> Self {
+        self.slope = slope;
+        self
+    }
+
+    /// Weight, between 0 and 1, of a pair with the given left and right
+    /// magnitudes: one if the right magnitude is as expected, falling as a
+    /// Gaussian of the difference.
+    pub fn weight(&self, left: f64, right: f64) -> f64 {
+        let residual = (right - self.offset - self.slope * left) / self.sigma;
+        (-0.5 * residual * residual).exp()
+    }
+}
+
+/// Probability density (per square arcsecond) of a separation (in
+/// arcseconds) between two positions of the same source, whose combined
+/// one-sigma uncertainty is `sigma` arcseconds.
+pub fn likelihood(separation: f64, sigma: f64) -> f64 {
+    if sigma <= 0.0 {
+        return f64::INFINITY;
+    }
+    let variance = sigma * sigma;
+    (-separation * separation / (2.0 * variance)).exp() / (2.0 * PI * variance)
+}
+
+#[cfg(test)]
+mod test {
+    use crossmatch::quality::{likelihood, SourceDensity};
+    use sky::position::SkyPosition;
+
+    #[test]
+    fn densities() {
+        let positions = vec![SkyPosition::new(10.0, 10.0); 1000];
+        let density = positions.iter().collect::<SourceDensity>();
+        // 1000 sources in a pixel of about 0.839 square degrees
+        let expected = 1000.0 / (0.8393 * 3600.0 * 3600.0);
+        let actual = density.density(&SkyPosition::new(10.0, 10.0));
+        assert!((actual / expected - 1.0).abs() < 1e-3);
+        let empty = density.density(&SkyPosition::new(190.0, -10.0));
+        assert!((empty * 1000.0 / actual - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn likelihoods() {
+        let peak = likelihood(0.0, 1.0);
+        assert!((peak - 1.0 / (2.0 * std::f64::consts::PI)).abs() < 1e-12);
+        assert!((likelihood(2.0, 1.0) / peak - (-2.0f64).exp()).abs() < 1e-12);
+        assert!(likelihood(1.0, 0.1) < likelihood(1.0, 1.0));
+        assert_eq!(likelihood(0.0, 0.0), f64::INFINITY);
+    }
+}
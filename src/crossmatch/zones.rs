@@ -0,0 +1,267 @@
+use crossmatch::join::JoinTable;
+use crossmatch::matcher::{CrossMatch, IndexEntry};
+use crossmatch::quality::SourceDensity;
+use sky::position::{SkyPosition, SkySource, ARCSEC_PER_DEG};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Which input of a cross-match an error refers to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Side::Left => "left",
+            Side::Right => "right",
+        })
+    }
+}
+
+/// Error returned when an input to the zones algorithm is not sorted by
+/// declination.
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+#[error("{side} catalog is not sorted by declination at source {index}")]
+pub struct UnsortedError {
+    /// Input which was out of order.
+    pub side: Side,
+    /// Position in the input of the first out-of-order source.
+    pub index: usize,
+}
+
+/// Match two catalogs which are both sorted by increasing declination, using
+/// the zones algorithm.
+///
+/// The sky is divided into declination zones whose height is the match
+/// radius, so that all matches of a source lie in its own zone or one of the
+/// two adjacent zones. The inputs are consumed one zone at a time, so only
+/// three zones of the right catalog (and one zone of the left catalog) are
+/// held in memory at once. Within a zone, sources are sorted by right
+/// ascension and candidates are found by binary search.
+///
+/// The join table produced is identical to that of
+/// [CrossMatch::match_catalogs](::crossmatch::matcher::CrossMatch::match_catalogs).
+/// The scores of matches depend on the density of the whole right catalog,
+/// so it is read to the end, and the scores are scaled once it has been.
+/// The inputs only need to be sorted well enough that their zone numbers
+/// never decrease; if this is violated then an `UnsortedError` is returned.
+/// When the cross-match propagates positions to a different epoch, the
+/// inputs must be sorted by their propagated declinations.
+pub fn match_sorted<L, R>(
+    config: &CrossMatch,
+    left: L,
+    right: R,
+) -> Result<JoinTable, UnsortedError>
+where
+    L: IntoIterator,
+    L::Item: SkySource,
+    R: IntoIterator,
+    R::Item: SkySource,
+{
+    let zones = Zones::new(config.radius() / ARCSEC_PER_DEG);
+    let mut right = RightZones::new(config, zones, right.into_iter());
+    let mut table = JoinTable::new();
+    // position of the left source of each row
+    let mut positions = Vec::new();
+
+    let mut left_zone: Option<i64> = None;
+    for (left_index, source) in left.into_iter().enumerate() {
+        let entry = config.entry(left_index, &source);
+        let zone = zones.zone(entry.position.dec);
+        if left_zone.is_some_and(|z| zone < z) {
+            return Err(UnsortedError {
+                side: Side::Left,
+                index: left_index,
+            });
+        }
+        if left_zone != Some(zone) {
+            right.advance_to(zone)?;
+            left_zone = Some(zone);
+        }
+
+        let mut matches = Vec::new();
+        for candidate in right.candidates(&entry.position, zone) {
+            if let Some(m) = config.accept(&entry, candidate) {
+                matches.push(m);
+            }
+        }
+        let matches = config.select(matches, 1.0);
+        positions.extend(matches.iter().map(|_| entry.position));
+        table.extend_left(matches);
+    }
+
+    while right.next_entry()?.is_some() {}
+    for (row, position) in table.rows_mut().iter_mut().zip(&positions) {
+        row.score /= right.density.density(position);
+    }
+    config.reduce(&mut table);
+    Ok(table)
+}
+
+/// Division of the sky into declination zones.
+#[derive(Clone, Copy, Debug)]
+struct Zones {
+    height: f64,
+}
+
+impl Zones {
+    fn new(radius: f64) -> Self {
+        Zones {
+            height: radius.max(1e-9),
+        }
+    }
+
+    fn zone(&self, dec: f64) -> i64 {
+        ((dec + 90.0) / self.height).floor() as i64
+    }
+}
+
+/// Zones of the right catalog, loaded lazily from its stream.
+struct RightZones<'a, I: Iterator> {
+    config: &'a CrossMatch,
+    zones: Zones,
+    stream: std::iter::Enumerate<I>,
+    /// Next source of the stream, which belongs to a zone not yet loaded.
+    pending: Option<(i64, IndexEntry)>,
+    last_zone: Option<i64>,
+    /// Loaded zones, each sorted by right ascension.
+    loaded: BTreeMap<i64, Vec<IndexEntry>>,
+    /// Density of the sources read so far.
+    density: SourceDensity,
+}
+
+impl<'a, I> RightZones<'a, I>
+where
+    I: Iterator,
+    I::Item: SkySource,
+{
+    fn new(config: &'a CrossMatch, zones: Zones, stream: I) -> Self {
+        RightZones {
+            config,
+            zones,
+            stream: stream.enumerate(),
+            pending: None,
+            last_zone: None,
+            loaded: BTreeMap::new(),
+            density: SourceDensity::new(),
+        }
+    }
+
+    /// Read the next source of the stream along with its zone.
+    fn next_entry(&mut self) -> Result<Option<(i64, IndexEntry)>, UnsortedError> {
+        if let Some(pending) = self.pending.take() {
+            return Ok(Some(pending));
+        }
+        match self.stream.next() {
+            None => Ok(None),
+            Some((index, source)) => {
+                let entry = self.config.entry(index, &source);
+                let zone = self.zones.zone(entry.position.dec);
+                if self.last_zone.is_some_and(|z| zone < z) {
+                    return Err(UnsortedError {
+                        side: Side::Right,
+                        index,
+                    });
+                }
+                self.last_zone = Some(zone);
+                self.density.add(&entry.position);
+                Ok(Some((zone, entry)))
+            }
+        }
+    }
+
+    /// Make zones `zone - 1` to `zone + 1` available, discarding all lower
+    /// zones.
+    fn advance_to(&mut self, zone: i64) -> Result<(), UnsortedError> {
+        while let Some((entry_zone, entry)) = self.next_entry()? {
+            if entry_zone > zone + 1 {
+                self.pending = Some((entry_zone, entry));
+                break;
+            }
+            if entry_zone >= zone - 1 {
+                self.loaded.entry(entry_zone).or_default().push(entry);
+            }
+        }
+        self.loaded = self.loaded.split_off(&(zone - 1));
+        for entries in self.loaded.values_mut() {
+            entries.sort_by(|a, b| a.position.ra.total_cmp(&b.position.ra));
+        }
+        Ok(())
+    }
+
+    /// Entries in the zones adjacent to `zone` which fall within the right
+    /// ascension range of a match centred on `position`.
+    fn candidates<'b>(
+        &'b self,
+        position: &SkyPosition,
+        zone: i64,
+    ) -> impl Iterator<Item = &'b IndexEntry> + 'b {
+        let rects = position.bounding_rects(self.config.radius() / ARCSEC_PER_DEG);
+        self.loaded
+            .range(zone - 1..=zone + 1)
+            .flat_map(move |(_, entries)| {
+                rects.clone().into_iter().flat_map(move |rect| {
+                    let (ra_min, ra_max) = (*rect.x(), rect.x_interval().end());
+                    let first = entries.partition_point(|e| e.position.ra < ra_min);
+                    entries[first..]
+                        .iter()
+                        .take_while(move |e| e.position.ra <= ra_max)
+                })
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crossmatch::matcher::{CrossMatch, MatchMode};
+    use crossmatch::zones::{match_sorted, Side, UnsortedError};
+    use quickcheck_macros::quickcheck;
+    use sky::position::SkyPosition;
+
+    fn sorted_positions(coords: Vec<(u16, i16)>) -> Vec<SkyPosition> {
+        let mut positions = coords
+            .into_iter()
+            .map(|(ra, dec)| {
+                SkyPosition::new(f64::from(ra % 3600) / 10.0, f64::from(dec % 9000) / 100.0)
+            })
+            .collect::<Vec<_>>();
+        positions.sort_by(|a, b| a.dec.total_cmp(&b.dec));
+        positions
+    }
+
+    #[test]
+    fn unsorted() {
+        let sorted = vec![SkyPosition::new(0.0, -10.0), SkyPosition::new(0.0, 10.0)];
+        let unsorted = vec![SkyPosition::new(0.0, 10.0), SkyPosition::new(0.0, -10.0)];
+        let config = CrossMatch::new(1.0);
+        assert_eq!(
+            match_sorted(&config, &unsorted, &sorted),
+            Err(UnsortedError {
+                side: Side::Left,
+                index: 1
+            })
+        );
+        assert_eq!(
+            match_sorted(&config, &sorted, &unsorted),
+            Err(UnsortedError {
+                side: Side::Right,
+                index: 1
+            })
+        );
+    }
+
+    /// Property test: the zones algorithm produces the same join table as the
+    /// in-memory matcher.
+    #[quickcheck]
+    fn matches_in_memory(left: Vec<(u16, i16)>, right: Vec<(u16, i16)>) {
+        let (left, right) = (sorted_positions(left), sorted_positions(right));
+        for mode in [MatchMode::All, MatchMode::Best, MatchMode::Mutual] {
+            let config = CrossMatch::new(7200.0).with_mode(mode);
+            let expected = config.match_catalogs(&left, &right);
+            assert_eq!(match_sorted(&config, &left, &right), Ok(expected));
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use catalog::{fits, generic, votable};
+use crossmatch::zones;
+use gaia::{columnar, diff, filter, projection};
+use sky::{moc, region};
+use std::io;
+use {geom, output, store};
+
+/// Error from any part of the library.
+///
+/// Each module has its own error type, which converts into this one with
+/// `?`, so that code using several modules can return a single type and
+/// still match on the cause.
+///
+/// ```
+/// # use starquad::gaia::filter::{self, Filter};
+/// fn parse(text: &str) -> starquad::Result<Filter> {
+///     Ok(text.parse()?)
+/// }
+/// assert!(matches!(
+///     parse("phot_g_mean_mag <"),
+///     Err(starquad::Error::Filter(filter::Error::Syntax { .. }))
+/// ));
+/// ```
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Geometry which cannot be represented.
+    #[error(transparent)]
+    Geometry(#[from] geom::Error),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// Invalid CSV input.
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    #[error(transparent)]
+    Filter(#[from] filter::Error),
+    #[error(transparent)]
+    Projection(#[from] projection::Error),
+    #[error(transparent)]
+    Columnar(#[from] columnar::Error),
+    /// Cross-match inputs which are not sorted by declination.
+    #[error(transparent)]
+    Unsorted(#[from] zones::UnsortedError),
+    /// Releases which cannot be compared.
+    #[error(transparent)]
+    Diff(#[from] diff::Error),
+    #[error(transparent)]
+    Output(#[from] output::Error),
+    /// Invalid MOC (HEALPix coverage map).
+    #[error(transparent)]
+    Moc(#[from] moc::Error),
+    /// Invalid bounds of a region of the sky.
+    #[error(transparent)]
+    Region(#[from] region::Error),
+    /// Missing or invalid index files.
+    #[error(transparent)]
+    Store(#[from] store::Error),
+    #[error(transparent)]
+    Catalog(#[from] generic::Error),
+    #[error(transparent)]
+    VoTable(#[from] votable::Error),
+    #[error(transparent)]
+    Fits(#[from] fits::Error),
+    #[cfg(feature = "arrow")]
+    #[error(transparent)]
+    Arrow(#[from] ::catalog::arrow::Error),
+    #[cfg(feature = "parquet")]
+    #[error(transparent)]
+    Parquet(#[from] ::catalog::parquet::Error),
+    #[cfg(feature = "sqlite")]
+    #[error(transparent)]
+    Sqlite(#[from] ::catalog::sqlite::Error),
+    #[cfg(feature = "gpu")]
+    #[error(transparent)]
+    Gpu(#[from] ::accel2d::gpu::Error),
+    /// Invalid key or token file of a server.
+    #[cfg(any(feature = "server", feature = "grpc"))]
+    #[error(transparent)]
+    Auth(#[from] ::auth::Error),
+}
+
+/// Result of any part of the library.
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod test {
+    use crossmatch::matcher::CrossMatch;
+    use crossmatch::zones::match_sorted;
+    use error::Error;
+    use geom::rect::Rect;
+    use geom::{self, interval::Interval};
+    use sky::position::SkyPosition;
+    use store::Store;
+
+    fn bounds(x: u8) -> ::Result<Rect<u8>> {
+        let rect = Rect::try_new(x, 0, 10, 10)?;
+        Ok(rect)
+    }
+
+    #[test]
+    fn converts_module_errors() {
+        assert!(bounds(1).is_ok());
+        assert!(matches!(
+            bounds(250),
+            Err(Error::Geometry(geom::Error::InvalidInterval))
+        ));
+        let missing = Store::open("/nonexistent/starquad").map_err(Error::from);
+        assert!(matches!(missing, Err(Error::Store(_))));
+        let left = [SkyPosition::new(0.0, 1.0), SkyPosition::new(0.0, 0.0)];
+        let unsorted = match_sorted(&CrossMatch::new(1.0), left, Vec::<SkyPosition>::new())
+            .map_err(Error::from);
+        assert_eq!(
+            unsorted.unwrap_err().to_string(),
+            "left catalog is not sorted by declination at source 1"
+        );
+        let reversed = Interval::try_enclosing(2, 1).map_err(Error::from);
+        assert_eq!(
+            reversed.unwrap_err().to_string(),
+            "interval minimum is greater than its maximum"
+        );
+    }
+}
@@ -0,0 +1,217 @@
+//! C interface to in-memory sky indexes, built with the `ffi` feature. The
+//! declarations are in `include/starquad.h`, which is generated by
+//! `cbindgen --config cbindgen.toml --output include/starquad.h`.
+//!
+//! An index is an opaque `StarquadIndex`, built from arrays of `ra` and
+//! `dec` and freed by `starquad_index_free`. Queries write the places (in
+//! those arrays) of the positions found to a buffer owned by the caller, and
+//! return the number found, which may be more than fit in the buffer:
+//!
+//! ```c
+//! StarquadIndex *index = starquad_index_new(ra, dec, n);
+//! size_t places[100];
+//! size_t found = starquad_index_query_cone(index, 56.75, 24.12, 1.0, places, 100);
+//! if (found > 100) { /* query again with a larger buffer */ }
+//! starquad_index_free(index);
+//! ```
+
+use accel2d::Accel2D;
+use geom::p2::P2;
+use sky::index::{query_region, sky_bounds, SkyIndex};
+use sky::position::SkyPosition;
+use sky::region::Region;
+use std::mem;
+use std::ptr;
+use std::slice;
+
+/// In-memory index of positions.
+pub struct StarquadIndex {
+    index: SkyIndex<usize>,
+    len: usize,
+}
+
+impl StarquadIndex {
+    /// Write the places of the positions in a region to `out`, in ascending
+    /// order, returning the number found.
+    unsafe fn query(&self, region: &Region, out: *mut usize, capacity: usize) -> usize {
+        let mut places = query_region(&self.index, region)
+            .iter()
+            .map(|(_, i)| *i)
+            .collect::<Vec<_>>();
+        places.sort_unstable();
+        if !out.is_null() {
+            let n = places.len().min(capacity);
+            ptr::copy_nonoverlapping(places.as_ptr(), out, n);
+        }
+        places.len()
+    }
+}
+
+/// Index `len` positions, given by arrays of their `ra` and `dec` in degrees.
+///
+/// Returns null if either array is null (unless `len` is 0).
+///
+/// # Safety
+///
+/// `ra` and `dec` must each point to `len` doubles.
+#[no_mangle]
+pub unsafe extern "C" fn starquad_index_new(
+    ra: *const f64,
+    dec: *const f64,
+    len: usize,
+) -> *mut StarquadIndex {
+    let (ra, dec) = if len == 0 {
+        (&[][..], &[][..])
+    } else if ra.is_null() || dec.is_null() {
+        return ptr::null_mut();
+    } else {
+        (
+            slice::from_raw_parts(ra, len),
+            slice::from_raw_parts(dec, len),
+        )
+    };
+    let items = ra
+        .iter()
+        .zip(dec)
+        .enumerate()
+        .map(|(i, (&ra, &dec))| (P2::new(ra, dec), i))
+        .collect();
+    let mut index = SkyIndex::with_bounds(sky_bounds());
+    index.insert(items);
+    Box::into_raw(Box::new(StarquadIndex { index, len }))
+}
+
+/// Free an index. Freeing null does nothing.
+///
+/// # Safety
+///
+/// `index` must have been returned by `starquad_index_new`, and not already
+/// freed.
+#[no_mangle]
+pub unsafe extern "C" fn starquad_index_free(index: *mut StarquadIndex) {
+    if !index.is_null() {
+        drop(Box::from_raw(index));
+    }
+}
+
+/// Number of positions in an index, or 0 if it is null.
+///
+/// # Safety
+///
+/// `index` must be null or a live index.
+#[no_mangle]
+pub unsafe extern "C" fn starquad_index_len(index: *const StarquadIndex) -> usize {
+    index.as_ref().map_or(0, |index| index.len)
+}
+
+/// Find the positions with `ra` between `ra_min` and `ra_max` and `dec`
+/// between `dec_min` and `dec_max`, writing up to `capacity` of their
+/// places to `out` in ascending order.
+///
+/// Each range runs from the smaller of its bounds to the larger. Ranges of
+/// `ra` are taken modulo 360, so the smaller may be negative for a box
+/// across `ra = 0`, and `dec` is clamped to the poles.
+///
+/// Returns the number of positions found, or 0 if `index` is null or the
+/// bounds are not numbers or lie beyond a pole.
+///
+/// # Safety
+///
+/// `index` must be null or a live index, and `out` must be null or point to
+/// `capacity` writable `size_t`s.
+#[no_mangle]
+pub unsafe extern "C" fn starquad_index_query_rect(
+    index: *const StarquadIndex,
+    ra_min: f64,
+    ra_max: f64,
+    dec_min: f64,
+    dec_max: f64,
+    out: *mut usize,
+    capacity: usize,
+) -> usize {
+    // `Region::sky_box` rejects reversed ranges; a NaN never compares
+    // greater, so it is left for `sky_box` to reject
+    let (mut ra_min, mut ra_max, mut dec_min, mut dec_max) = (ra_min, ra_max, dec_min, dec_max);
+    if ra_min > ra_max {
+        mem::swap(&mut ra_min, &mut ra_max);
+    }
+    if dec_min > dec_max {
+        mem::swap(&mut dec_min, &mut dec_max);
+    }
+    let region = Region::sky_box(ra_min, ra_max, dec_min, dec_max);
+    match (index.as_ref(), region) {
+        (Some(index), Ok(region)) => index.query(&region, out, capacity),
+        _ => 0,
+    }
+}
+
+/// Find the positions within `radius` degrees of `(ra, dec)` (a circle on
+/// the sky), writing up to `capacity` of their places to `out` in ascending
+/// order.
+///
+/// Returns the number of positions found, or 0 if `index` is null.
+///
+/// # Safety
+///
+/// `index` must be null or a live index, and `out` must be null or point to
+/// `capacity` writable `size_t`s.
+#[no_mangle]
+pub unsafe extern "C" fn starquad_index_query_cone(
+    index: *const StarquadIndex,
+    ra: f64,
+    dec: f64,
+    radius: f64,
+    out: *mut usize,
+    capacity: usize,
+) -> usize {
+    match index.as_ref() {
+        Some(index) => {
+            let region = Region::cone(SkyPosition::new(ra, dec), radius);
+            index.query(&region, out, capacity)
+        }
+        None => 0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ffi::{
+        starquad_index_free, starquad_index_len, starquad_index_new, starquad_index_query_cone,
+        starquad_index_query_rect,
+    };
+    use std::ptr;
+
+    #[test]
+    fn index_and_query() {
+        let ra = [10.0, 10.5, 200.0, 359.9];
+        let dec = [0.0, 0.2, -45.0, 0.0];
+        unsafe {
+            let index = starquad_index_new(ra.as_ptr(), dec.as_ptr(), ra.len());
+            assert_eq!(starquad_index_len(index), 4);
+
+            let mut out = [0; 4];
+            let found = starquad_index_query_rect(index, 9.0, 11.0, -1.0, 1.0, out.as_mut_ptr(), 4);
+            assert_eq!(&out[..found], &[0, 1]);
+            // swapped corners
+            let found = starquad_index_query_rect(index, 11.0, 9.0, 1.0, -1.0, out.as_mut_ptr(), 4);
+            assert_eq!(&out[..found], &[0, 1]);
+            // a box across ra = 0, with swapped corners
+            let found =
+                starquad_index_query_rect(index, 10.2, -1.0, 1.0, -1.0, out.as_mut_ptr(), 4);
+            assert_eq!(&out[..found], &[0, 3]);
+            let found =
+                starquad_index_query_rect(index, f64::NAN, 11.0, -1.0, 1.0, out.as_mut_ptr(), 4);
+            assert_eq!(found, 0);
+            // a cone around ra = 0, with too small a buffer
+            let mut one = [usize::MAX; 1];
+            let found = starquad_index_query_cone(index, 0.0, 0.0, 11.0, one.as_mut_ptr(), 1);
+            assert_eq!((found, one), (3, [0]));
+            let found = starquad_index_query_cone(index, 200.0, -45.0, 1.0, ptr::null_mut(), 0);
+            assert_eq!(found, 1);
+
+            starquad_index_free(index);
+            assert!(starquad_index_new(ptr::null(), dec.as_ptr(), 4).is_null());
+            assert_eq!(starquad_index_len(ptr::null()), 0);
+        }
+    }
+}
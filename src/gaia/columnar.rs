@@ -0,0 +1,416 @@
+use accel2d::Accel2D;
+use csv::{Reader, StringRecord};
+use gaia::record::GaiaRecord;
+use gaia::schema::{self, Column, ColumnType};
+use geom::p2::P2;
+use sky::index::{sky_bounds, SkyIndex};
+use sky::position::SkyPosition;
+use std::io::Read;
+
+/// Errors from building a columnar catalog.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// A column name is not a Gaia column.
+    #[error("unknown column: {0}")]
+    UnknownColumn(String),
+    /// A column does not have the type required by an operation.
+    #[error("column {0} has the wrong type")]
+    WrongType(String),
+    /// A value could not be parsed as the type of its column.
+    #[error("invalid value for column {column}: {value:?}")]
+    InvalidValue { column: String, value: String },
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+}
+
+/// Bitmap recording which values of a column are present.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Validity {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl Validity {
+    fn push(&mut self, valid: bool) {
+        if self.len.is_multiple_of(64) {
+            self.words.push(0);
+        }
+        if valid {
+            self.words[self.len / 64] |= 1 << (self.len % 64);
+        }
+        self.len += 1;
+    }
+
+    pub fn is_valid(&self, row: usize) -> bool {
+        self.words[row / 64] & (1 << (row % 64)) != 0
+    }
+}
+
+/// Values of a single column. Missing values are stored as zero (or an
+/// empty string), and are identified by the column's validity bitmap.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ColumnValues {
+    Boolean(Vec<bool>),
+    UnsignedByte(Vec<u8>),
+    Long(Vec<i64>),
+    Double(Vec<f64>),
+    Text(Vec<String>),
+}
+
+impl ColumnValues {
+    fn new(column_type: ColumnType) -> Self {
+        match column_type {
+            ColumnType::Boolean => ColumnValues::Boolean(Vec::new()),
+            ColumnType::UnsignedByte => ColumnValues::UnsignedByte(Vec::new()),
+            ColumnType::Long => ColumnValues::Long(Vec::new()),
+            ColumnType::Double => ColumnValues::Double(Vec::new()),
+            ColumnType::Text => ColumnValues::Text(Vec::new()),
+        }
+    }
+}
+
+/// A typed column of a `ColumnarCatalog`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypedColumn {
+    column: &'static Column,
+    values: ColumnValues,
+    /// Present only for nullable columns.
+    validity: Option<Validity>,
+}
+
+impl TypedColumn {
+    fn new(column: &'static Column) -> Self {
+        TypedColumn {
+            column,
+            values: ColumnValues::new(column.column_type),
+            validity: if column.nullable {
+                Some(Validity::default())
+            } else {
+                None
+            },
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.column.name
+    }
+
+    pub fn values(&self) -> &ColumnValues {
+        &self.values
+    }
+
+    pub fn validity(&self) -> Option<&Validity> {
+        self.validity.as_ref()
+    }
+
+    pub fn is_valid(&self, row: usize) -> bool {
+        self.validity.as_ref().is_none_or(|v| v.is_valid(row))
+    }
+
+    /// Value of a numeric column as a double.
+    pub fn get_f64(&self, row: usize) -> Option<f64> {
+        if !self.is_valid(row) {
+            return None;
+        }
+        match &self.values {
+            ColumnValues::UnsignedByte(values) => Some(f64::from(values[row])),
+            ColumnValues::Long(values) => Some(values[row] as f64),
+            ColumnValues::Double(values) => Some(values[row]),
+            _ => None,
+        }
+    }
+
+    /// Parse the text form of a value, as found in the Gaia CSV files.
+    fn parse(&self, text: &str) -> Result<Cell, Error> {
+        let invalid = || Error::InvalidValue {
+            column: String::from(self.column.name),
+            value: String::from(text),
+        };
+        if text.is_empty() && self.column.column_type != ColumnType::Text {
+            return if self.column.nullable {
+                Ok(Cell::Missing)
+            } else {
+                Err(invalid())
+            };
+        }
+        Ok(match self.column.column_type {
+            ColumnType::Boolean => Cell::Boolean(text.parse().map_err(|_| invalid())?),
+            ColumnType::UnsignedByte => Cell::UnsignedByte(text.parse().map_err(|_| invalid())?),
+            ColumnType::Long => Cell::Long(text.parse().map_err(|_| invalid())?),
+            ColumnType::Double => Cell::Double(text.parse().map_err(|_| invalid())?),
+            ColumnType::Text => Cell::Text(String::from(text)),
+        })
+    }
+
+    fn push(&mut self, cell: Cell) {
+        if let Some(validity) = self.validity.as_mut() {
+            validity.push(cell != Cell::Missing);
+        }
+        match (&mut self.values, cell) {
+            (ColumnValues::Boolean(values), Cell::Boolean(value)) => values.push(value),
+            (ColumnValues::Boolean(values), _) => values.push(false),
+            (ColumnValues::UnsignedByte(values), Cell::UnsignedByte(value)) => values.push(value),
+            (ColumnValues::UnsignedByte(values), _) => values.push(0),
+            (ColumnValues::Long(values), Cell::Long(value)) => values.push(value),
+            (ColumnValues::Long(values), _) => values.push(0),
+            (ColumnValues::Double(values), Cell::Double(value)) => values.push(value),
+            (ColumnValues::Double(values), _) => values.push(0.0),
+            (ColumnValues::Text(values), Cell::Text(value)) => values.push(value),
+            (ColumnValues::Text(values), _) => values.push(String::new()),
+        }
+    }
+}
+
+/// A parsed value, before it is appended to a column.
+#[derive(PartialEq)]
+enum Cell {
+    Missing,
+    Boolean(bool),
+    UnsignedByte(u8),
+    Long(i64),
+    Double(f64),
+    Text(String),
+}
+
+/// In-memory catalog which stores a selection of Gaia columns as typed
+/// columns, rather than as a `Vec<GaiaRecord>`.
+///
+/// The `ra` and `dec` columns are always stored. Only a handful of the ~100
+/// Gaia columns are usually needed, so this takes a fraction of the memory of
+/// the full records, and filters on a column are simple loops over a slice.
+///
+/// ```
+/// # use starquad::gaia::columnar::ColumnarCatalog;
+/// let catalog = ColumnarCatalog::new(&["source_id", "parallax"]).unwrap();
+/// assert_eq!(catalog.column_names(), vec!["source_id", "parallax", "ra", "dec"]);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColumnarCatalog {
+    columns: Vec<TypedColumn>,
+    /// Positions of each stored column in `schema::COLUMNS`.
+    schema_indices: Vec<usize>,
+    len: usize,
+}
+
+impl ColumnarCatalog {
+    /// Create an empty catalog storing the named columns.
+    pub fn new(names: &[&str]) -> Result<Self, Error> {
+        let mut names = names.to_vec();
+        for required in ["ra", "dec"].iter() {
+            if !names.contains(required) {
+                names.push(required);
+            }
+        }
+        let mut columns = Vec::new();
+        let mut schema_indices = Vec::new();
+        for name in names {
+            let index = schema::COLUMNS
+                .iter()
+                .position(|c| c.name == name)
+                .ok_or_else(|| Error::UnknownColumn(String::from(name)))?;
+            columns.push(TypedColumn::new(&schema::COLUMNS[index]));
+            schema_indices.push(index);
+        }
+        Ok(ColumnarCatalog {
+            columns,
+            schema_indices,
+            len: 0,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn column_names(&self) -> Vec<&'static str> {
+        self.columns.iter().map(TypedColumn::name).collect()
+    }
+
+    pub fn column(&self, name: &str) -> Option<&TypedColumn> {
+        self.columns.iter().find(|c| c.name() == name)
+    }
+
+    /// Values of a double column, such as `ra` or `dec` (which are always
+    /// stored), or no values for other columns.
+    fn doubles(&self, name: &str) -> &[f64] {
+        match self.column(name).map(TypedColumn::values) {
+            Some(ColumnValues::Double(values)) => values,
+            _ => &[],
+        }
+    }
+
+    pub fn position(&self, row: usize) -> SkyPosition {
+        SkyPosition::new(self.doubles("ra")[row], self.doubles("dec")[row])
+    }
+
+    /// Append a row given the text fields of all Gaia columns, in the order
+    /// of `schema::COLUMNS`.
+    ///
+    /// If a field is invalid, the catalog is left unchanged.
+    pub fn push_fields(&mut self, fields: &StringRecord) -> Result<(), Error> {
+        let indices = self.schema_indices.clone();
+        self.push_row(fields, &indices)
+    }
+
+    /// Append a row, where `positions` gives the field of each stored column.
+    fn push_row(&mut self, fields: &StringRecord, positions: &[usize]) -> Result<(), Error> {
+        let cells = self
+            .columns
+            .iter()
+            .zip(positions.iter())
+            .map(|(column, &position)| column.parse(fields.get(position).unwrap_or("")))
+            .collect::<Result<Vec<_>, _>>()?;
+        for (column, cell) in self.columns.iter_mut().zip(cells) {
+            column.push(cell);
+        }
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Append records.
+    pub fn extend(&mut self, records: &[GaiaRecord]) -> Result<(), Error> {
+        for fields in schema::string_records(records)? {
+            self.push_fields(&fields)?;
+        }
+        Ok(())
+    }
+
+    /// Append all rows of a Gaia CSV file, without deserializing complete
+    /// records.
+    pub fn read_csv<R: Read>(&mut self, mut reader: Reader<R>) -> Result<(), Error> {
+        let headers = reader.headers()?.clone();
+        let positions = self
+            .columns
+            .iter()
+            .map(|c| {
+                headers
+                    .iter()
+                    .position(|h| h == c.name())
+                    .ok_or_else(|| Error::UnknownColumn(String::from(c.name())))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut record = StringRecord::new();
+        while reader.read_record(&mut record)? {
+            self.push_row(&record, &positions)?;
+        }
+        Ok(())
+    }
+
+    /// Rows whose value of a numeric column satisfies a predicate. Missing
+    /// values never match.
+    pub fn filter<F>(&self, name: &str, predicate: F) -> Result<Vec<usize>, Error>
+    where
+        F: Fn(f64) -> bool,
+    {
+        let column = self
+            .column(name)
+            .ok_or_else(|| Error::UnknownColumn(String::from(name)))?;
+        let rows = match &column.values {
+            ColumnValues::Double(values) => values
+                .iter()
+                .enumerate()
+                .filter(|&(row, &value)| predicate(value) && column.is_valid(row))
+                .map(|(row, _)| row)
+                .collect(),
+            ColumnValues::UnsignedByte(_) | ColumnValues::Long(_) => (0..self.len)
+                .filter(|&row| column.get_f64(row).is_some_and(&predicate))
+                .collect(),
+            _ => return Err(Error::WrongType(String::from(name))),
+        };
+        Ok(rows)
+    }
+
+    /// Build a spatial index whose items are row numbers.
+    pub fn index(&self) -> SkyIndex<usize> {
+        let ra = self.doubles("ra");
+        let dec = self.doubles("dec");
+        let mut index = SkyIndex::with_bounds(sky_bounds());
+        index.insert(
+            (0..self.len)
+                .map(|row| (P2::new(ra[row], dec[row]), row))
+                .collect(),
+        );
+        index
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use accel2d::Accel2D;
+    use csv::Writer;
+    use gaia::columnar::{ColumnValues, ColumnarCatalog, Error};
+    use gaia::reader::csv_reader;
+    use gaia::record::test::sample_record;
+    use gaia::record::GaiaRecord;
+    use geom::rect::Rect;
+
+    fn records() -> Vec<GaiaRecord> {
+        (0..20)
+            .map(|i| {
+                let mut record = sample_record();
+                record.source_id = i;
+                record.ra = i as f64;
+                record.dec = -(i as f64);
+                record.parallax = if i % 2 == 0 { Some(i as f64) } else { None };
+                record
+            })
+            .collect()
+    }
+
+    #[test]
+    fn filter_and_index() {
+        let mut catalog = ColumnarCatalog::new(&["source_id", "parallax"]).unwrap();
+        catalog.extend(&records()).unwrap();
+        assert_eq!(catalog.len(), 20);
+        assert_eq!(
+            catalog.filter("parallax", |p| p >= 10.0).unwrap(),
+            vec![10, 12, 14, 16, 18]
+        );
+        assert_eq!(
+            catalog.filter("source_id", |id| id < 2.0).unwrap(),
+            vec![0, 1]
+        );
+        assert_eq!(catalog.column("parallax").unwrap().get_f64(3), None);
+
+        let index = catalog.index();
+        let mut rows = index
+            .query_rect(&Rect::new(4.5, -10.0, 3.0, 10.0).unwrap())
+            .iter()
+            .map(|(_, row)| *row)
+            .collect::<Vec<_>>();
+        rows.sort();
+        assert_eq!(rows, vec![5, 6, 7]);
+        assert_eq!(catalog.position(5).ra, 5.0);
+    }
+
+    #[test]
+    fn read_csv() {
+        let mut writer = Writer::from_writer(vec![]);
+        for record in records() {
+            writer.serialize(record).unwrap();
+        }
+        let csv = writer.into_inner().unwrap();
+
+        let mut catalog = ColumnarCatalog::new(&["source_id"]).unwrap();
+        catalog.read_csv(csv_reader(csv.as_slice())).unwrap();
+        assert_eq!(catalog.len(), 20);
+        match catalog.column("source_id").unwrap().values() {
+            ColumnValues::Long(ids) => assert_eq!(ids, &(0..20).collect::<Vec<_>>()),
+            _ => panic!("expected a long column"),
+        }
+    }
+
+    #[test]
+    fn errors() {
+        match ColumnarCatalog::new(&["flux"]) {
+            Err(Error::UnknownColumn(name)) => assert_eq!(name, "flux"),
+            _ => panic!("expected an unknown column"),
+        }
+        let catalog = ColumnarCatalog::new(&["designation"]).unwrap();
+        assert!(catalog.filter("designation", |_| true).is_err());
+    }
+}
@@ -0,0 +1,216 @@
+use gaia::record::GaiaRecord;
+use std::collections::HashMap;
+
+/// Criterion used to choose which of several duplicate records to keep.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeepBest {
+    /// Keep the first record encountered.
+    First,
+    /// Keep the record with the largest `visibility_periods_used`.
+    MostVisibilityPeriods,
+    /// Keep the record with the largest `astrometric_n_good_obs_al`.
+    MostGoodObservations,
+    /// Keep the record with the smallest combined `ra_error` and `dec_error`.
+    SmallestPositionError,
+}
+
+impl KeepBest {
+    /// Check if `candidate` should replace `current`. Ties keep `current`.
+    pub fn prefers(&self, candidate: &GaiaRecord, current: &GaiaRecord) -> bool {
+        let position_error = |r: &GaiaRecord| r.ra_error * r.ra_error + r.dec_error * r.dec_error;
+        match self {
+            KeepBest::First => false,
+            KeepBest::MostVisibilityPeriods => {
+                candidate.visibility_periods_used > current.visibility_periods_used
+            }
+            KeepBest::MostGoodObservations => {
+                candidate.astrometric_n_good_obs_al > current.astrometric_n_good_obs_al
+            }
+            KeepBest::SmallestPositionError => position_error(candidate) < position_error(current),
+        }
+    }
+}
+
+/// What to do with records whose `duplicated_source` flag is set.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FlaggedPolicy {
+    /// Treat flagged records like any other record.
+    Keep,
+    /// Remove all flagged records.
+    Drop,
+}
+
+/// Policy for removing duplicate sources.
+///
+/// Two records are duplicates when their `(ra, dec)` positions are exactly
+/// equal; only one of each set of duplicates is kept, chosen using `keep`.
+/// Additionally, records flagged as `duplicated_source` may be dropped
+/// outright.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DuplicatePolicy {
+    pub flagged: FlaggedPolicy,
+    pub keep: KeepBest,
+}
+
+impl DuplicatePolicy {
+    pub fn new(keep: KeepBest) -> Self {
+        DuplicatePolicy {
+            flagged: FlaggedPolicy::Keep,
+            keep,
+        }
+    }
+
+    pub fn with_flagged(mut self, flagged: FlaggedPolicy) -> Self {
+        self.flagged = flagged;
+        self
+    }
+
+    /// Check if a record is removed regardless of any collisions.
+    pub fn drops(&self, record: &GaiaRecord) -> bool {
+        self.flagged == FlaggedPolicy::Drop && record.duplicated_source
+    }
+
+    /// Remove duplicates from a collection of records, wherever they occur.
+    ///
+    /// The kept records remain in the order in which their first duplicate
+    /// was encountered.
+    pub fn dedup_all<I>(&self, records: I) -> Vec<GaiaRecord>
+    where
+        I: IntoIterator<Item = GaiaRecord>,
+    {
+        let mut kept: Vec<GaiaRecord> = Vec::new();
+        let mut by_position: HashMap<(u64, u64), usize> = HashMap::new();
+        for record in records {
+            if self.drops(&record) {
+                continue;
+            }
+            match by_position.get(&position_key(&record)) {
+                Some(&i) => {
+                    if self.keep.prefers(&record, &kept[i]) {
+                        kept[i] = record;
+                    }
+                }
+                None => {
+                    by_position.insert(position_key(&record), kept.len());
+                    kept.push(record);
+                }
+            }
+        }
+        kept
+    }
+}
+
+/// Key used to detect exact position collisions.
+fn position_key(record: &GaiaRecord) -> (u64, u64) {
+    // adding zero maps -0.0 to 0.0
+    ((record.ra + 0.0).to_bits(), (record.dec + 0.0).to_bits())
+}
+
+/// Stream adapter which removes duplicate records.
+///
+/// To run in constant memory, this only detects position collisions between
+/// records which are adjacent in the stream, which finds all collisions when
+/// the stream is sorted by position. Use
+/// [DuplicatePolicy::dedup_all](DuplicatePolicy::dedup_all) (or the
+/// equivalent index-build option) for unsorted records.
+pub struct Dedup<I> {
+    records: I,
+    policy: DuplicatePolicy,
+    current: Option<GaiaRecord>,
+}
+
+impl<I> Dedup<I>
+where
+    I: Iterator<Item = GaiaRecord>,
+{
+    pub fn new(records: I, policy: DuplicatePolicy) -> Self {
+        Dedup {
+            records,
+            policy,
+            current: None,
+        }
+    }
+}
+
+impl<I> Iterator for Dedup<I>
+where
+    I: Iterator<Item = GaiaRecord>,
+{
+    type Item = GaiaRecord;
+
+    fn next(&mut self) -> Option<GaiaRecord> {
+        for record in self.records.by_ref() {
+            if self.policy.drops(&record) {
+                continue;
+            }
+            match self.current.take() {
+                None => self.current = Some(record),
+                Some(current) => {
+                    if position_key(&current) != position_key(&record) {
+                        self.current = Some(record);
+                        return Some(current);
+                    } else if self.policy.keep.prefers(&record, &current) {
+                        self.current = Some(record);
+                    } else {
+                        self.current = Some(current);
+                    }
+                }
+            }
+        }
+        self.current.take()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use gaia::dedup::{Dedup, DuplicatePolicy, FlaggedPolicy, KeepBest};
+    use gaia::record::test::sample_record;
+    use gaia::record::GaiaRecord;
+
+    fn record(source_id: u64, ra: f64, dec: f64, periods: u8, flagged: bool) -> GaiaRecord {
+        let mut record = sample_record();
+        record.source_id = source_id;
+        record.ra = ra;
+        record.dec = dec;
+        record.visibility_periods_used = periods;
+        record.duplicated_source = flagged;
+        record
+    }
+
+    fn ids(records: Vec<GaiaRecord>) -> Vec<u64> {
+        records.iter().map(|r| r.source_id).collect()
+    }
+
+    #[test]
+    fn stream_adjacent_collisions() {
+        let records = vec![
+            record(1, 10.0, 10.0, 5, false),
+            record(2, 10.0, 10.0, 9, true),
+            record(3, 10.0, 10.0, 7, false),
+            record(4, 11.0, 10.0, 5, false),
+        ];
+        let policy = DuplicatePolicy::new(KeepBest::MostVisibilityPeriods);
+        let kept = Dedup::new(records.clone().into_iter(), policy).collect();
+        assert_eq!(ids(kept), vec![2, 4]);
+
+        let policy = policy.with_flagged(FlaggedPolicy::Drop);
+        let kept = Dedup::new(records.clone().into_iter(), policy).collect();
+        assert_eq!(ids(kept), vec![3, 4]);
+
+        let policy = DuplicatePolicy::new(KeepBest::First);
+        let kept = Dedup::new(records.into_iter(), policy).collect();
+        assert_eq!(ids(kept), vec![1, 4]);
+    }
+
+    #[test]
+    fn all_collisions() {
+        let records = vec![
+            record(1, 10.0, 10.0, 5, false),
+            record(2, 11.0, -0.0, 5, false),
+            record(3, 10.0, 10.0, 7, false),
+            record(4, 11.0, 0.0, 6, false),
+        ];
+        let policy = DuplicatePolicy::new(KeepBest::MostVisibilityPeriods);
+        assert_eq!(ids(policy.dedup_all(records)), vec![3, 4]);
+    }
+}
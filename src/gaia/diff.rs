@@ -0,0 +1,378 @@
+use crossmatch::matcher::{CrossMatch, MatchMode};
+use gaia::group::{Aggregate, Summary};
+use gaia::record::GaiaRecord;
+use gaia::schema::{self, ColumnType, COLUMNS};
+use std::collections::HashSet;
+use std::fmt;
+use std::io::Write;
+use std::iter::Peekable;
+
+/// Which of the two releases being compared.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Release {
+    Old,
+    New,
+}
+
+impl fmt::Display for Release {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Release::Old => write!(f, "old"),
+            Release::New => write!(f, "new"),
+        }
+    }
+}
+
+/// Errors from joining releases.
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+pub enum Error {
+    /// A release given to `SourceIdJoin` is not sorted by `source_id`.
+    #[error("{release} release is not sorted by source_id at source {source_id}")]
+    Unsorted { release: Release, source_id: u64 },
+}
+
+/// Records of a source in either or both releases.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pair {
+    pub old: Option<GaiaRecord>,
+    pub new: Option<GaiaRecord>,
+}
+
+/// Full outer join of two releases on `source_id`, by merging them.
+///
+/// Both releases must be sorted by increasing `source_id`, as the Gaia CSV
+/// files are when read in order of name; otherwise sort them first with
+/// `gaia::sort::ExternalSort`.
+pub struct SourceIdJoin<L: Iterator, R: Iterator> {
+    old: Peekable<L>,
+    new: Peekable<R>,
+    last_old: Option<u64>,
+    last_new: Option<u64>,
+}
+
+impl<L, R> SourceIdJoin<L, R>
+where
+    L: Iterator<Item = GaiaRecord>,
+    R: Iterator<Item = GaiaRecord>,
+{
+    pub fn new(old: L, new: R) -> Self {
+        SourceIdJoin {
+            old: old.peekable(),
+            new: new.peekable(),
+            last_old: None,
+            last_new: None,
+        }
+    }
+}
+
+/// Record the id of the next record of a release, which must be greater
+/// than the last.
+fn advance(last: &mut Option<u64>, release: Release, source_id: u64) -> Result<(), Error> {
+    if last.is_some_and(|id| source_id <= id) {
+        return Err(Error::Unsorted { release, source_id });
+    }
+    *last = Some(source_id);
+    Ok(())
+}
+
+impl<L, R> Iterator for SourceIdJoin<L, R>
+where
+    L: Iterator<Item = GaiaRecord>,
+    R: Iterator<Item = GaiaRecord>,
+{
+    type Item = Result<Pair, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let old_id = self.old.peek().map(|r| r.source_id);
+        let new_id = self.new.peek().map(|r| r.source_id);
+        let (take_old, take_new) = match (old_id, new_id) {
+            (None, None) => return None,
+            (Some(o), Some(n)) => (o <= n, n <= o),
+            (old, new) => (old.is_some(), new.is_some()),
+        };
+        if let Some(id) = old_id.filter(|_| take_old) {
+            if let Err(e) = advance(&mut self.last_old, Release::Old, id) {
+                return Some(Err(e));
+            }
+        }
+        if let Some(id) = new_id.filter(|_| take_new) {
+            if let Err(e) = advance(&mut self.last_new, Release::New, id) {
+                return Some(Err(e));
+            }
+        }
+        Some(Ok(Pair {
+            old: self.old.next_if(|_| take_old),
+            new: self.new.next_if(|_| take_new),
+        }))
+    }
+}
+
+/// Differences of one column between the sources of two releases.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColumnDiff {
+    pub name: &'static str,
+    /// Number of sources with a value in both releases.
+    pub compared: u64,
+    /// Number of those sources whose values differ.
+    pub changed: u64,
+    /// Number of sources with a value only in the new release.
+    pub added: u64,
+    /// Number of sources with a value only in the old release.
+    pub removed: u64,
+    delta: Summary,
+}
+
+impl ColumnDiff {
+    fn new(name: &'static str) -> Self {
+        ColumnDiff {
+            name,
+            compared: 0,
+            changed: 0,
+            added: 0,
+            removed: 0,
+            delta: Summary::default(),
+        }
+    }
+
+    /// Statistics of the change (new minus old) of the values of a numeric
+    /// column. Changes of `ra` are wrapped into `[-180, 180)`.
+    pub fn delta(&self) -> &Summary {
+        &self.delta
+    }
+
+    /// Add the text forms of a value in the two releases.
+    fn add(&mut self, column_type: ColumnType, old: &str, new: &str) {
+        match (old.is_empty(), new.is_empty()) {
+            (true, true) => {}
+            (true, false) => self.added += 1,
+            (false, true) => self.removed += 1,
+            (false, false) => self.compare(column_type, old, new),
+        }
+    }
+
+    fn compare(&mut self, column_type: ColumnType, old: &str, new: &str) {
+        self.compared += 1;
+        if old != new {
+            self.changed += 1;
+        }
+        let numeric = match column_type {
+            ColumnType::UnsignedByte | ColumnType::Long | ColumnType::Double => true,
+            ColumnType::Boolean | ColumnType::Text => false,
+        };
+        if let (true, Ok(old), Ok(new)) = (numeric, old.parse::<f64>(), new.parse::<f64>()) {
+            let mut delta = new - old;
+            if self.name == "ra" {
+                delta = (delta + 180.0).rem_euclid(360.0) - 180.0;
+            }
+            self.delta.add(delta);
+        }
+    }
+}
+
+/// Per-column differences between two releases of the catalog, such as DR2
+/// and DR3, for the sources they share.
+///
+/// Sources are paired either on `source_id` (`by_source_id`), or by
+/// position (`by_crossmatch`) where the ids of the releases differ.
+///
+/// ```
+/// # use starquad::gaia::diff::ReleaseDiff;
+/// # use starquad::gaia::record::GaiaRecord;
+/// # fn releases() -> (Vec<GaiaRecord>, Vec<GaiaRecord>) { (Vec::new(), Vec::new()) }
+/// let (dr2, dr3) = releases();
+/// let diff = ReleaseDiff::by_source_id(dr2, dr3)?;
+/// let parallax = diff.column("parallax").unwrap();
+/// println!("{} of {} parallaxes changed", parallax.changed, parallax.compared);
+/// diff.write_csv(std::io::stdout())?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReleaseDiff {
+    /// Number of sources paired between the releases.
+    pub matched: u64,
+    /// Number of sources only in the old release.
+    pub old_only: u64,
+    /// Number of sources only in the new release.
+    pub new_only: u64,
+    columns: Vec<ColumnDiff>,
+}
+
+impl ReleaseDiff {
+    pub fn new() -> Self {
+        ReleaseDiff {
+            matched: 0,
+            old_only: 0,
+            new_only: 0,
+            columns: COLUMNS.iter().map(|c| ColumnDiff::new(c.name)).collect(),
+        }
+    }
+
+    /// Compare two releases sorted by `source_id` (see `SourceIdJoin`).
+    pub fn by_source_id<L, R>(old: L, new: R) -> Result<Self, Error>
+    where
+        L: IntoIterator<Item = GaiaRecord>,
+        R: IntoIterator<Item = GaiaRecord>,
+    {
+        let mut diff = ReleaseDiff::new();
+        for pair in SourceIdJoin::new(old.into_iter(), new.into_iter()) {
+            diff.add(&pair?);
+        }
+        Ok(diff)
+    }
+
+    /// Compare two releases held in memory, pairing each old source with
+    /// its nearest new source within the radius of a cross-match.
+    pub fn by_crossmatch(old: &[GaiaRecord], new: &[GaiaRecord], crossmatch: &CrossMatch) -> Self {
+        let table = crossmatch
+            .clone()
+            .with_mode(MatchMode::Best)
+            .match_catalogs(old, new);
+        let mut diff = ReleaseDiff::new();
+        let mut paired = HashSet::new();
+        for row in table.rows() {
+            diff.add_both(&old[row.left], &new[row.right]);
+            paired.insert(row.right);
+        }
+        diff.old_only = (old.len() - table.len()) as u64;
+        diff.new_only = (new.len() - paired.len()) as u64;
+        diff
+    }
+
+    pub fn add(&mut self, pair: &Pair) {
+        match (&pair.old, &pair.new) {
+            (Some(old), Some(new)) => self.add_both(old, new),
+            (Some(_), None) => self.old_only += 1,
+            (None, Some(_)) => self.new_only += 1,
+            (None, None) => {}
+        }
+    }
+
+    /// Compare the records of a source in the two releases.
+    pub fn add_both(&mut self, old: &GaiaRecord, new: &GaiaRecord) {
+        self.matched += 1;
+        let old = schema::string_record(old);
+        let new = schema::string_record(new);
+        let fields = old.iter().zip(new.iter());
+        for ((diff, column), (o, n)) in self.columns.iter_mut().zip(COLUMNS).zip(fields) {
+            diff.add(column.column_type, o, n);
+        }
+    }
+
+    pub fn columns(&self) -> &[ColumnDiff] {
+        &self.columns
+    }
+
+    pub fn column(&self, name: &str) -> Option<&ColumnDiff> {
+        self.columns.iter().find(|c| c.name == name)
+    }
+
+    /// Write a CSV report with a row for each column. Statistics of the
+    /// changes are empty for text columns and columns without changes.
+    pub fn write_csv<W: Write>(&self, writer: W) -> Result<(), csv::Error> {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.write_record([
+            "column",
+            "unit",
+            "compared",
+            "changed",
+            "added",
+            "removed",
+            "delta_mean",
+            "delta_stddev",
+            "delta_min",
+            "delta_max",
+        ])?;
+        for column in &self.columns {
+            let unit = schema::unit(column.name).map_or("", |u| u.symbol());
+            let mut fields = vec![
+                column.name.to_string(),
+                unit.to_string(),
+                column.compared.to_string(),
+                column.changed.to_string(),
+                column.added.to_string(),
+                column.removed.to_string(),
+            ];
+            // the first value of a summary is its count
+            fields.extend(column.delta.values().iter().skip(1).map(|v| {
+                if v.is_nan() {
+                    String::new()
+                } else {
+                    v.to_string()
+                }
+            }));
+            writer.write_record(fields)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl Default for ReleaseDiff {
+    fn default() -> Self {
+        ReleaseDiff::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crossmatch::matcher::CrossMatch;
+    use gaia::diff::{Error, Release, ReleaseDiff};
+    use gaia::record::test::sample_record;
+    use gaia::record::GaiaRecord;
+
+    fn record(source_id: u64, ra: f64, parallax: Option<f64>) -> GaiaRecord {
+        let mut record = sample_record();
+        record.source_id = source_id;
+        record.ra = ra;
+        record.dec = 10.0;
+        record.parallax = parallax;
+        record
+    }
+
+    #[test]
+    fn by_source_id() {
+        let old = vec![
+            record(1, 359.9999, Some(1.0)),
+            record(2, 20.0, Some(2.0)),
+            record(3, 30.0, None),
+        ];
+        let new = vec![
+            record(2, 20.0, Some(2.5)),
+            record(3, 30.0, Some(3.0)),
+            record(4, 40.0, None),
+        ];
+        let diff = ReleaseDiff::by_source_id(old.clone(), new.clone()).unwrap();
+        assert_eq!((diff.matched, diff.old_only, diff.new_only), (2, 1, 1));
+        let parallax = diff.column("parallax").unwrap();
+        assert_eq!((parallax.compared, parallax.changed), (1, 1));
+        assert_eq!((parallax.added, parallax.removed), (1, 0));
+        assert_eq!(parallax.delta().mean(), Some(0.5));
+        assert_eq!(diff.column("ra").unwrap().changed, 0);
+
+        let mut csv = Vec::new();
+        diff.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.contains("\nparallax,mas,1,1,1,0,0.5,,0.5,0.5\n"));
+
+        let unsorted = vec![record(3, 30.0, None), record(2, 20.0, None)];
+        assert_eq!(
+            ReleaseDiff::by_source_id(unsorted, new),
+            Err(Error::Unsorted {
+                release: Release::Old,
+                source_id: 2
+            })
+        );
+    }
+
+    #[test]
+    fn by_crossmatch() {
+        // new ids, and positions which moved by 0.36 arcsec across ra = 0
+        let old = vec![record(1, 359.99995, Some(1.0)), record(2, 20.0, None)];
+        let new = vec![record(10, 0.00005, Some(1.0)), record(11, 50.0, None)];
+        let diff = ReleaseDiff::by_crossmatch(&old, &new, &CrossMatch::new(1.0));
+        assert_eq!((diff.matched, diff.old_only, diff.new_only), (1, 1, 1));
+        let ra = diff.column("ra").unwrap().delta();
+        assert!((ra.mean().unwrap() - 1e-4).abs() < 1e-9);
+        assert_eq!(diff.column("source_id").unwrap().delta().mean(), Some(9.0));
+    }
+}
@@ -0,0 +1,224 @@
+use csv::{DeserializeRecordsIntoIter, ReaderBuilder, Trim};
+use gaia::reader::decompress;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::iter::Peekable;
+use std::path::Path;
+
+/// Photometric band of an epoch observation.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum Band {
+    G,
+    #[serde(rename = "BP")]
+    Bp,
+    #[serde(rename = "RP")]
+    Rp,
+}
+
+/// A row of the Gaia DR3 epoch photometry datalink product
+/// (`EPOCH_PHOTOMETRY`, in its CSV form): one transit of a source in one
+/// band.
+///
+/// `time` is the barycentric observation time in days (`BJD - 2455197.5`),
+/// and `flux` is in electrons per second. Transits which were rejected have
+/// their flags set, and may have no flux or magnitude.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct EpochObservation {
+    pub source_id: u64,
+    pub transit_id: u64,
+    pub band: Band,
+    pub time: f64,
+    pub mag: Option<f64>,
+    pub flux: Option<f64>,
+    pub flux_error: Option<f64>,
+    #[serde(default)]
+    pub rejected_by_photometry: bool,
+    #[serde(default)]
+    pub rejected_by_variability: bool,
+}
+
+impl EpochObservation {
+    /// Check if the observation has a flux, and was not rejected.
+    pub fn is_valid(&self) -> bool {
+        self.flux.is_some() && !self.rejected_by_photometry && !self.rejected_by_variability
+    }
+}
+
+/// Iterate over the rows of an (uncompressed) epoch photometry CSV stream.
+pub fn records<R: Read>(reader: R) -> DeserializeRecordsIntoIter<R, EpochObservation> {
+    ReaderBuilder::new()
+        .has_headers(true)
+        .trim(Trim::All)
+        .comment(Some(b'#'))
+        .from_reader(reader)
+        .into_deserialize()
+}
+
+/// Iterate over the rows of an epoch photometry CSV file, which is
+/// decompressed if its name ends in `.gz`.
+pub fn open<P: AsRef<Path>>(
+    path: P,
+) -> io::Result<DeserializeRecordsIntoIter<Box<dyn Read>, EpochObservation>> {
+    let path = path.as_ref();
+    Ok(records(decompress(path, File::open(path)?)))
+}
+
+/// Summary statistics of the valid fluxes of a light curve in one band.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FluxStats {
+    pub count: usize,
+    pub mean: f64,
+    /// Sample standard deviation, which is zero for a single observation.
+    pub stddev: f64,
+    /// Half the difference between the largest and smallest fluxes.
+    pub amplitude: f64,
+}
+
+/// All of the epoch observations of one source, in the order they were read.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LightCurve {
+    pub source_id: u64,
+    pub observations: Vec<EpochObservation>,
+}
+
+impl LightCurve {
+    /// Valid observations in a band, as `(time, flux)`.
+    pub fn points(&self, band: Band) -> impl Iterator<Item = (f64, f64)> + '_ {
+        self.observations
+            .iter()
+            .filter(move |o| o.band == band && o.is_valid())
+            .filter_map(|o| o.flux.map(|flux| (o.time, flux)))
+    }
+
+    /// Statistics of the valid fluxes in a band, computed in a single pass,
+    /// or `None` if there are none.
+    pub fn stats(&self, band: Band) -> Option<FluxStats> {
+        // Welford's algorithm
+        let mut count = 0;
+        let mut mean = 0.0;
+        let mut m2 = 0.0;
+        let mut min = f64::INFINITY;
+        let mut max = f64::NEG_INFINITY;
+        for (_, flux) in self.points(band) {
+            count += 1;
+            let delta = flux - mean;
+            mean += delta / count as f64;
+            m2 += delta * (flux - mean);
+            min = min.min(flux);
+            max = max.max(flux);
+        }
+        if count == 0 {
+            return None;
+        }
+        let stddev = if count > 1 {
+            (m2 / (count - 1) as f64).sqrt()
+        } else {
+            0.0
+        };
+        Some(FluxStats {
+            count,
+            mean,
+            stddev,
+            amplitude: (max - min) / 2.0,
+        })
+    }
+}
+
+/// Stream adapter which gathers epoch observations into light curves.
+///
+/// The datalink products list the observations of each source together, so
+/// a light curve ends when the `source_id` changes; a source which appears
+/// again later starts another light curve.
+pub struct LightCurves<I: Iterator> {
+    observations: Peekable<I>,
+}
+
+impl<I> LightCurves<I>
+where
+    I: Iterator<Item = Result<EpochObservation, csv::Error>>,
+{
+    pub fn new(observations: I) -> Self {
+        LightCurves {
+            observations: observations.peekable(),
+        }
+    }
+}
+
+impl<I> Iterator for LightCurves<I>
+where
+    I: Iterator<Item = Result<EpochObservation, csv::Error>>,
+{
+    type Item = Result<LightCurve, csv::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let first = match self.observations.next()? {
+            Ok(observation) => observation,
+            Err(error) => return Some(Err(error)),
+        };
+        let source_id = first.source_id;
+        let mut observations = vec![first];
+        while let Some(Ok(observation)) = self.observations.peek() {
+            if observation.source_id != source_id {
+                break;
+            }
+            if let Some(Ok(observation)) = self.observations.next() {
+                observations.push(observation);
+            }
+        }
+        Some(Ok(LightCurve {
+            source_id,
+            observations,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use gaia::epoch::{records, Band, LightCurves};
+
+    const EPOCH_PHOTOMETRY: &str = "\
+# Gaia DR3 epoch photometry
+source_id,transit_id,band,time,mag,flux,flux_error,rejected_by_photometry,rejected_by_variability
+5,100,G,1700.0,15.0,1000.0,5.0,false,false
+5,100,BP,1700.0,15.3,400.0,8.0,false,false
+5,101,G,1730.0,15.1,1100.0,5.0,false,false
+5,102,G,1760.0,15.2,1300.0,5.0,false,false
+5,103,G,1790.0,,,,true,false
+5,104,G,1820.0,13.0,9000.0,5.0,false,true
+9,200,G,1700.0,17.0,200.0,2.0,false,false
+";
+
+    #[test]
+    fn light_curves() {
+        let curves = LightCurves::new(records(EPOCH_PHOTOMETRY.as_bytes()))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(curves.len(), 2);
+        assert_eq!(curves[0].source_id, 5);
+        assert_eq!(curves[0].observations.len(), 6);
+        let times = curves[0]
+            .points(Band::G)
+            .map(|(t, _)| t)
+            .collect::<Vec<_>>();
+        assert_eq!(times, vec![1700.0, 1730.0, 1760.0]);
+
+        let g = curves[0].stats(Band::G).unwrap();
+        assert_eq!(g.count, 3);
+        assert!((g.mean - 3400.0 / 3.0).abs() < 1e-9);
+        assert!((g.stddev - (70000.0f64 / 3.0).sqrt()).abs() < 1e-9);
+        assert_eq!(g.amplitude, 150.0);
+        assert_eq!(curves[0].stats(Band::Bp).unwrap().stddev, 0.0);
+        assert_eq!(curves[0].stats(Band::Rp), None);
+        assert_eq!(curves[1].stats(Band::G).unwrap().mean, 200.0);
+    }
+
+    #[test]
+    fn invalid_rows() {
+        let text = "source_id,transit_id,band,time,mag,flux,flux_error\n1,1,V,0.0,,,\n";
+        let mut curves = LightCurves::new(records(text.as_bytes()));
+        assert!(curves.next().unwrap().is_err());
+        assert!(curves.next().is_none());
+    }
+}
@@ -0,0 +1,675 @@
+use csv::StringRecord;
+use gaia::record::GaiaRecord;
+use gaia::schema::{self, ColumnType, COLUMNS};
+use gaia::stats::ColumnStats;
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+/// Errors from parsing a filter expression.
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum Error {
+    /// The expression is malformed at a byte offset.
+    #[error("syntax error at offset {position}: {message}")]
+    Syntax { position: usize, message: String },
+    /// A column name is not a Gaia column.
+    #[error("unknown column: {0}")]
+    UnknownColumn(String),
+    /// Two operands cannot be compared, such as a number and a text column.
+    #[error("type error: {0}")]
+    Type(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(String),
+    Text(String),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+/// Comparison operator.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    /// Operator with its operands swapped: `a op b` is `b op.flip() a`.
+    fn flip(self) -> Self {
+        match self {
+            Op::Lt => Op::Gt,
+            Op::Le => Op::Ge,
+            Op::Gt => Op::Lt,
+            Op::Ge => Op::Le,
+            op => op,
+        }
+    }
+
+    fn accepts(self, ordering: Ordering) -> bool {
+        match self {
+            Op::Eq => ordering == Ordering::Equal,
+            Op::Ne => ordering != Ordering::Equal,
+            Op::Lt => ordering == Ordering::Less,
+            Op::Le => ordering != Ordering::Greater,
+            Op::Gt => ordering == Ordering::Greater,
+            Op::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, Error> {
+    let bytes = text.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        let c = bytes[i];
+        let two = &text[i..(i + 2).min(text.len())];
+        let token = if c.is_ascii_whitespace() {
+            i += 1;
+            continue;
+        } else if c == b'(' {
+            i += 1;
+            Token::Open
+        } else if c == b')' {
+            i += 1;
+            Token::Close
+        } else if two == "&&" {
+            i += 2;
+            Token::And
+        } else if two == "||" {
+            i += 2;
+            Token::Or
+        } else if ["==", "!=", "<=", ">="].contains(&two) {
+            i += 2;
+            Token::Op(match two {
+                "==" => Op::Eq,
+                "!=" => Op::Ne,
+                "<=" => Op::Le,
+                _ => Op::Ge,
+            })
+        } else if c == b'<' || c == b'>' || c == b'=' {
+            i += 1;
+            Token::Op(match c {
+                b'<' => Op::Lt,
+                b'>' => Op::Gt,
+                _ => Op::Eq,
+            })
+        } else if c == b'!' {
+            i += 1;
+            Token::Not
+        } else if c == b'\'' || c == b'"' {
+            let end = text[i + 1..].find(c as char).ok_or_else(|| Error::Syntax {
+                position: start,
+                message: String::from("unterminated string"),
+            })?;
+            i += end + 2;
+            Token::Text(String::from(&text[start + 1..i - 1]))
+        } else if c.is_ascii_digit() || c == b'-' || c == b'+' || c == b'.' {
+            i += 1;
+            while i < bytes.len()
+                && (bytes[i].is_ascii_alphanumeric()
+                    || bytes[i] == b'.'
+                    || ((bytes[i] == b'-' || bytes[i] == b'+') && bytes[i - 1] == b'e'))
+            {
+                i += 1;
+            }
+            Token::Number(String::from(&text[start..i]))
+        } else if c.is_ascii_alphabetic() || c == b'_' {
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                i += 1;
+            }
+            match &text[start..i] {
+                "and" | "AND" => Token::And,
+                "or" | "OR" => Token::Or,
+                "not" | "NOT" => Token::Not,
+                word => Token::Ident(String::from(word)),
+            }
+        } else {
+            return Err(Error::Syntax {
+                position: start,
+                message: format!("unexpected character {:?}", c as char),
+            });
+        };
+        tokens.push((start, token));
+    }
+    Ok(tokens)
+}
+
+/// Operand of a comparison.
+#[derive(Clone, Debug, PartialEq)]
+enum Operand {
+    /// Position of a column in `schema::COLUMNS`.
+    Column(usize),
+    Number(f64, Option<i128>),
+    Text(String),
+    Boolean(bool),
+    Null,
+}
+
+impl Operand {
+    fn kind(&self) -> Option<ColumnType> {
+        match self {
+            Operand::Column(i) => Some(match COLUMNS[*i].column_type {
+                ColumnType::UnsignedByte | ColumnType::Long => ColumnType::Double,
+                other => other,
+            }),
+            Operand::Number(..) => Some(ColumnType::Double),
+            Operand::Text(_) => Some(ColumnType::Text),
+            Operand::Boolean(_) => Some(ColumnType::Boolean),
+            Operand::Null => None,
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Operand::Column(i) => String::from(COLUMNS[*i].name),
+            Operand::Number(x, _) => x.to_string(),
+            Operand::Text(s) => format!("{:?}", s),
+            Operand::Boolean(b) => b.to_string(),
+            Operand::Null => String::from("null"),
+        }
+    }
+}
+
+/// Value of an operand for one row. Empty fields are null.
+#[derive(Debug)]
+enum Value<'a> {
+    Number(f64, Option<i128>),
+    Text(&'a str),
+    Boolean(bool),
+    Null,
+}
+
+fn number(text: &str) -> Option<(f64, Option<i128>)> {
+    let x = text.parse::<f64>().ok()?;
+    Some((x, text.parse::<i128>().ok()))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Compare(Operand, Op, Operand),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(_, t)| t)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens.get(self.next).map_or(self.end, |&(p, _)| p)
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, Error> {
+        Err(Error::Syntax {
+            position: self.position(),
+            message: String::from(message),
+        })
+    }
+
+    fn or(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.next += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, Error> {
+        match self.peek() {
+            Some(Token::Not) => {
+                self.next += 1;
+                Ok(Expr::Not(Box::new(self.unary()?)))
+            }
+            Some(Token::Open) => {
+                self.next += 1;
+                let expr = self.or()?;
+                if self.peek() != Some(&Token::Close) {
+                    return self.error("expected ')'");
+                }
+                self.next += 1;
+                Ok(expr)
+            }
+            _ => self.comparison(),
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, Error> {
+        let operand = match self.peek() {
+            Some(Token::Ident(name)) => match name.as_str() {
+                "null" | "NULL" => Operand::Null,
+                "true" => Operand::Boolean(true),
+                "false" => Operand::Boolean(false),
+                name => Operand::Column(
+                    COLUMNS
+                        .iter()
+                        .position(|c| c.name == name)
+                        .ok_or_else(|| Error::UnknownColumn(String::from(name)))?,
+                ),
+            },
+            Some(Token::Number(text)) => match number(text) {
+                Some((x, i)) => Operand::Number(x, i),
+                None => return self.error("invalid number"),
+            },
+            Some(Token::Text(text)) => Operand::Text(text.clone()),
+            _ => return self.error("expected a column or a value"),
+        };
+        self.next += 1;
+        Ok(operand)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, Error> {
+        let left = self.operand()?;
+        let op = match self.peek() {
+            Some(&Token::Op(op)) => op,
+            // a boolean column on its own is true when set
+            _ => match left {
+                Operand::Column(i) if COLUMNS[i].column_type == ColumnType::Boolean => {
+                    return Ok(Expr::Compare(left, Op::Eq, Operand::Boolean(true)));
+                }
+                _ => return self.error("expected a comparison operator"),
+            },
+        };
+        self.next += 1;
+        let right = self.operand()?;
+        let type_error = |message: &str| {
+            Err(Error::Type(format!(
+                "{} {} {}",
+                left.describe(),
+                message,
+                right.describe()
+            )))
+        };
+        match (left.kind(), right.kind()) {
+            (None, _) | (_, None) if op != Op::Eq && op != Op::Ne => {
+                return type_error("cannot be ordered against")
+            }
+            (Some(a), Some(b)) if a != b => return type_error("cannot be compared with"),
+            (Some(ColumnType::Boolean), _) if op != Op::Eq && op != Op::Ne => {
+                return type_error("cannot be ordered against")
+            }
+            _ => (),
+        }
+        Ok(Expr::Compare(left, op, right))
+    }
+}
+
+/// Row filter parsed from an expression such as
+/// `parallax_over_error > 10 && phot_g_mean_mag < 18 && bp_rp != null`.
+///
+/// Comparisons (`==`, `!=`, `<`, `<=`, `>`, `>=`) are between Gaia columns
+/// and numbers, quoted strings, `true`, `false` or `null`, and are combined
+/// with `&&` (or `and`), `||` (or `or`), `!` (or `not`) and parentheses. A
+/// boolean column on its own, such as `duplicated_source`, is true when set.
+///
+/// An empty column is only equal to `null`, so any other comparison with it
+/// is false, as in SQL. Integer columns are compared exactly with integer
+/// literals, so `source_id == 4295806720` is safe.
+///
+/// ```
+/// # use starquad::gaia::filter::Filter;
+/// let filter: Filter = "parallax > 1 and (bp_rp == null or bp_rp < 0.5)".parse().unwrap();
+/// assert_eq!(filter.columns(), vec!["parallax", "bp_rp"]);
+/// assert!("parallax > 'one'".parse::<Filter>().is_err());
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Filter {
+    expr: Expr,
+}
+
+impl FromStr for Filter {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self, Error> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            next: 0,
+            end: text.len(),
+        };
+        let expr = parser.or()?;
+        if parser.next < parser.tokens.len() {
+            return parser.error("unexpected token");
+        }
+        Ok(Filter { expr })
+    }
+}
+
+impl Filter {
+    /// Names of the columns used by the filter, in order of first use.
+    pub fn columns(&self) -> Vec<&'static str> {
+        fn visit(expr: &Expr, columns: &mut Vec<&'static str>) {
+            match expr {
+                Expr::Compare(left, _, right) => {
+                    for operand in [left, right].iter() {
+                        if let Operand::Column(i) = operand {
+                            if !columns.contains(&COLUMNS[*i].name) {
+                                columns.push(COLUMNS[*i].name);
+                            }
+                        }
+                    }
+                }
+                Expr::Not(e) => visit(e, columns),
+                Expr::And(a, b) | Expr::Or(a, b) => {
+                    visit(a, columns);
+                    visit(b, columns);
+                }
+            }
+        }
+        let mut columns = Vec::new();
+        visit(&self.expr, &mut columns);
+        columns
+    }
+
+    /// Evaluate the filter on the text fields of a row, in the order of
+    /// `schema::COLUMNS`. Fields which cannot be parsed as their column type
+    /// are treated as empty.
+    pub fn matches_fields(&self, fields: &StringRecord) -> bool {
+        eval(&self.expr, fields)
+    }
+
+    /// Evaluate the filter on a record.
+    pub fn matches(&self, record: &GaiaRecord) -> bool {
+        self.matches_fields(&schema::string_record(record))
+    }
+
+    /// Filter matching only the record of a source.
+    pub fn source_id(source_id: u64) -> Self {
+        let value = Operand::Number(source_id as f64, Some(i128::from(source_id)));
+        Filter {
+            expr: Expr::Compare(Operand::Column(schema::SOURCE_ID), Op::Eq, value),
+        }
+    }
+
+    /// Whether any row summarized by the statistics of some of its columns
+    /// might match the filter. `false` only when no row can match, so rows
+    /// summarized this way can be skipped without reading them.
+    ///
+    /// Only comparisons of numeric columns with numbers or `null` are
+    /// checked against the statistics; `!` is assumed to match.
+    pub fn may_match(&self, stats: &[ColumnStats]) -> bool {
+        may_match(&self.expr, stats)
+    }
+
+    /// The only values of `source_id` of the rows which can match the
+    /// filter, or `None` if any value could match.
+    ///
+    /// ```
+    /// # use starquad::gaia::filter::Filter;
+    /// let filter: Filter = "(source_id == 7 or source_id == 9) and parallax > 1"
+    ///     .parse()
+    ///     .unwrap();
+    /// assert_eq!(filter.source_ids(), Some(vec![7, 9]));
+    /// assert_eq!("source_id > 7".parse::<Filter>().unwrap().source_ids(), None);
+    /// ```
+    pub fn source_ids(&self) -> Option<Vec<u64>> {
+        source_ids(&self.expr).map(|ids| ids.into_iter().collect())
+    }
+}
+
+/// Largest magnitude below which every integer is an `f64`, so that
+/// statistics of integer columns compare exactly.
+const EXACT_INTEGERS: f64 = 9_007_199_254_740_992.0;
+
+fn may_match(expr: &Expr, stats: &[ColumnStats]) -> bool {
+    match expr {
+        Expr::Compare(left, op, right) => {
+            let (column, op, value) = match (left, right) {
+                (Operand::Column(i), value) => (*i, *op, value),
+                (value, Operand::Column(i)) => (*i, op.flip(), value),
+                _ => return true,
+            };
+            let stats = match stats.iter().find(|s| s.name == COLUMNS[column].name) {
+                Some(stats) => stats,
+                None => return true,
+            };
+            match (value, COLUMNS[column].column_type) {
+                // empty text is not null
+                (Operand::Null, ColumnType::Text) => true,
+                (Operand::Null, _) if op == Op::Eq => stats.nulls > 0,
+                (Operand::Null, _) => stats.count > 0,
+                // an empty value matches no comparison with a number
+                (Operand::Number(..), _) if stats.count == 0 => false,
+                (Operand::Number(x, _), ColumnType::Double)
+                | (Operand::Number(x, _), ColumnType::UnsignedByte)
+                | (Operand::Number(x, _), ColumnType::Long) => match (stats.min, stats.max) {
+                    (Some(min), Some(max))
+                        if COLUMNS[column].column_type != ColumnType::Long
+                            || (min.abs() < EXACT_INTEGERS && max.abs() < EXACT_INTEGERS) =>
+                    {
+                        let x = *x;
+                        match op {
+                            Op::Eq => min <= x && x <= max,
+                            Op::Ne => !(min == x && max == x),
+                            Op::Lt => min < x,
+                            Op::Le => min <= x,
+                            Op::Gt => max > x,
+                            Op::Ge => max >= x,
+                        }
+                    }
+                    _ => true,
+                },
+                _ => true,
+            }
+        }
+        Expr::Not(_) => true,
+        Expr::And(a, b) => may_match(a, stats) && may_match(b, stats),
+        Expr::Or(a, b) => may_match(a, stats) || may_match(b, stats),
+    }
+}
+
+fn source_ids(expr: &Expr) -> Option<BTreeSet<u64>> {
+    match expr {
+        Expr::Compare(left, Op::Eq, right) => {
+            let value = match (left, right) {
+                (Operand::Column(i), value) | (value, Operand::Column(i))
+                    if COLUMNS[*i].name == "source_id" =>
+                {
+                    value
+                }
+                _ => return None,
+            };
+            match value {
+                Operand::Number(_, Some(id)) => Some(u64::try_from(*id).into_iter().collect()),
+                Operand::Number(..) | Operand::Null => Some(BTreeSet::new()),
+                _ => None,
+            }
+        }
+        Expr::Compare(..) | Expr::Not(_) => None,
+        Expr::And(a, b) => match (source_ids(a), source_ids(b)) {
+            (Some(a), Some(b)) => Some(a.intersection(&b).copied().collect()),
+            (a, b) => a.or(b),
+        },
+        Expr::Or(a, b) => {
+            let mut ids = source_ids(a)?;
+            ids.extend(source_ids(b)?);
+            Some(ids)
+        }
+    }
+}
+
+fn value<'a>(operand: &'a Operand, fields: &'a StringRecord) -> Value<'a> {
+    match operand {
+        Operand::Column(i) => {
+            let text = fields.get(*i).unwrap_or("");
+            match COLUMNS[*i].column_type {
+                ColumnType::Text => Value::Text(text),
+                _ if text.is_empty() => Value::Null,
+                ColumnType::Boolean => text.parse().map_or(Value::Null, Value::Boolean),
+                _ => number(text).map_or(Value::Null, |(x, i)| Value::Number(x, i)),
+            }
+        }
+        Operand::Number(x, i) => Value::Number(*x, *i),
+        Operand::Text(s) => Value::Text(s),
+        Operand::Boolean(b) => Value::Boolean(*b),
+        Operand::Null => Value::Null,
+    }
+}
+
+fn eval(expr: &Expr, fields: &StringRecord) -> bool {
+    match expr {
+        Expr::Compare(left, op, right) => {
+            // only comparisons with a literal null can be true for empty values
+            let is_null = *left == Operand::Null || *right == Operand::Null;
+            let ordering = match (value(left, fields), value(right, fields)) {
+                (Value::Null, Value::Null) if is_null => Some(Ordering::Equal),
+                (Value::Null, _) | (_, Value::Null) => return *op == Op::Ne && is_null,
+                (Value::Number(_, Some(a)), Value::Number(_, Some(b))) => Some(a.cmp(&b)),
+                (Value::Number(a, _), Value::Number(b, _)) => a.partial_cmp(&b),
+                (Value::Text(a), Value::Text(b)) => Some(a.cmp(b)),
+                (Value::Boolean(a), Value::Boolean(b)) => Some(a.cmp(&b)),
+                _ => None,
+            };
+            ordering.is_some_and(|o| op.accepts(o))
+        }
+        Expr::Not(e) => !eval(e, fields),
+        Expr::And(a, b) => eval(a, fields) && eval(b, fields),
+        Expr::Or(a, b) => eval(a, fields) || eval(b, fields),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use gaia::filter::{Error, Filter};
+    use gaia::record::test::sample_record;
+    use gaia::stats::CatalogStats;
+
+    fn matches(expr: &str) -> bool {
+        let mut record = sample_record();
+        record.source_id = 4295806720;
+        record.parallax = Some(2.5);
+        record.phot_g_mean_mag = 17.0;
+        record.duplicated_source = true;
+        expr.parse::<Filter>().unwrap().matches(&record)
+    }
+
+    #[test]
+    fn comparisons() {
+        assert!(matches("parallax > 2"));
+        assert!(matches("parallax >= 2.5 && parallax <= 2.5"));
+        assert!(!matches("parallax < 2.5"));
+        assert!(matches("phot_g_mean_mag < 1.8e1"));
+        assert!(matches("source_id == 4295806720"));
+        assert!(!matches("source_id == 4295806721"));
+        assert!(matches("designation == 'Gaia DR2 0'"));
+        assert!(matches("duplicated_source"));
+        assert!(matches("astrometric_primary_flag == false"));
+    }
+
+    #[test]
+    fn nulls() {
+        assert!(matches("bp_rp == null"));
+        assert!(!matches("bp_rp != null"));
+        assert!(matches("parallax != null"));
+        // comparisons with an empty column are false either way
+        assert!(!matches("bp_rp < 1"));
+        assert!(!matches("bp_rp >= 1"));
+        assert!(!matches("bp_rp != 1"));
+    }
+
+    #[test]
+    fn logic() {
+        assert!(matches("parallax > 10 || phot_g_mean_mag < 18"));
+        assert!(!matches("not (parallax > 10 or phot_g_mean_mag < 18)"));
+        assert!(matches("!(bp_rp != null) and parallax > 1"));
+        assert!(matches("parallax > 10 && ra > 1 || duplicated_source"));
+    }
+
+    #[test]
+    fn pruning() {
+        let mut stats = CatalogStats::new();
+        for (g, bp_rp) in [(12.0, Some(0.5)), (15.0, None)] {
+            let mut record = sample_record();
+            record.phot_g_mean_mag = g;
+            record.bp_rp = bp_rp;
+            stats.add(&record);
+        }
+        let may_match = |expr: &str| expr.parse::<Filter>().unwrap().may_match(stats.columns());
+        assert!(may_match("phot_g_mean_mag < 12.5"));
+        assert!(!may_match("phot_g_mean_mag < 12"));
+        assert!(!may_match("16 <= phot_g_mean_mag"));
+        assert!(may_match("phot_g_mean_mag == 15"));
+        assert!(!may_match("phot_g_mean_mag > 20 or bp_rp > 1"));
+        assert!(may_match("phot_g_mean_mag > 20 or bp_rp == null"));
+        assert!(!may_match("parallax != null"));
+        assert!(!may_match("parallax > 1 and bp_rp < 1"));
+        // negations and text columns are not checked
+        assert!(may_match("not (phot_g_mean_mag < 20)"));
+        assert!(may_match("designation == 'x'"));
+        assert!(may_match("designation != null"));
+    }
+
+    #[test]
+    fn source_ids() {
+        let ids = |expr: &str| expr.parse::<Filter>().unwrap().source_ids();
+        assert_eq!(ids("source_id == 4295806720"), Some(vec![4295806720]));
+        assert_eq!(ids("7 == source_id or source_id == 3"), Some(vec![3, 7]));
+        assert_eq!(ids("source_id == 3 and source_id == 7"), Some(vec![]));
+        assert_eq!(ids("source_id == -1 or source_id == 1.5"), Some(vec![]));
+        assert_eq!(ids("source_id == 3 or parallax > 1"), None);
+        assert_eq!(ids("not source_id != 3"), None);
+        assert_eq!(Filter::source_id(3).source_ids(), Some(vec![3]));
+        let mut record = sample_record();
+        record.source_id = 4295806720;
+        assert!(Filter::source_id(4295806720).matches(&record));
+        assert!(!Filter::source_id(4295806721).matches(&record));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            "parallx > 1".parse::<Filter>(),
+            Err(Error::UnknownColumn(String::from("parallx")))
+        );
+        assert!(matches!(
+            "parallax > 'x'".parse::<Filter>(),
+            Err(Error::Type(_))
+        ));
+        assert!(matches!(
+            "bp_rp < null".parse::<Filter>(),
+            Err(Error::Type(_))
+        ));
+        assert!(matches!(
+            "parallax > 1 &&".parse::<Filter>(),
+            Err(Error::Syntax { position: 15, .. })
+        ));
+        assert!(matches!(
+            "(parallax > 1".parse::<Filter>(),
+            Err(Error::Syntax { .. })
+        ));
+        assert!(matches!(
+            "parallax".parse::<Filter>(),
+            Err(Error::Syntax { .. })
+        ));
+    }
+}
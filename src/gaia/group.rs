@@ -0,0 +1,260 @@
+use std::collections::btree_map::{self, BTreeMap};
+use std::fmt::Display;
+use std::io::Write;
+
+/// Summary of the values of a group, built up one value at a time.
+pub trait Aggregate: Default {
+    type Value;
+
+    fn add(&mut self, value: Self::Value);
+
+    /// Names of the columns which `values` gives, for writing tables.
+    fn columns() -> &'static [&'static str];
+
+    fn values(&self) -> Vec<f64>;
+}
+
+/// Number of values in a group.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Count(pub u64);
+
+impl Aggregate for Count {
+    type Value = ();
+
+    fn add(&mut self, _: ()) {
+        self.0 += 1;
+    }
+
+    fn columns() -> &'static [&'static str] {
+        &["count"]
+    }
+
+    fn values(&self) -> Vec<f64> {
+        vec![self.0 as f64]
+    }
+}
+
+/// Count, mean, standard deviation and range of the values in a group,
+/// computed in a single pass.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Summary {
+    pub count: u64,
+    mean: f64,
+    m2: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Summary {
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.mean)
+    }
+
+    /// Sample standard deviation, if there are at least two values.
+    pub fn stddev(&self) -> Option<f64> {
+        (self.count > 1).then(|| (self.m2 / (self.count - 1) as f64).sqrt())
+    }
+
+    pub fn min(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.min)
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.max)
+    }
+}
+
+impl Aggregate for Summary {
+    type Value = f64;
+
+    fn add(&mut self, value: f64) {
+        // Welford's algorithm
+        if self.count == 0 {
+            self.min = value;
+            self.max = value;
+        }
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    fn columns() -> &'static [&'static str] {
+        &["count", "mean", "stddev", "min", "max"]
+    }
+
+    fn values(&self) -> Vec<f64> {
+        let nan = |value: Option<f64>| value.unwrap_or(f64::NAN);
+        vec![
+            self.count as f64,
+            nan(self.mean()),
+            nan(self.stddev()),
+            nan(self.min()),
+            nan(self.max()),
+        ]
+    }
+}
+
+/// Aggregates of groups, by key, in order of key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GroupTable<K, A> {
+    groups: BTreeMap<K, A>,
+}
+
+impl<K: Ord, A: Aggregate> GroupTable<K, A> {
+    pub fn new() -> Self {
+        GroupTable {
+            groups: BTreeMap::new(),
+        }
+    }
+
+    /// Add a value to the group of a key.
+    pub fn add(&mut self, key: K, value: A::Value) {
+        self.groups.entry(key).or_default().add(value);
+    }
+
+    pub fn get(&self, key: &K) -> Option<&A> {
+        self.groups.get(key)
+    }
+
+    pub fn iter(&self) -> btree_map::Iter<'_, K, A> {
+        self.groups.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.groups.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// Write the table as CSV, with a column for the key, named `key_name`,
+    /// followed by the columns of the aggregate. Empty values are written
+    /// as empty fields.
+    pub fn write_csv<W: Write>(&self, writer: W, key_name: &str) -> Result<(), csv::Error>
+    where
+        K: Display,
+    {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.write_field(key_name)?;
+        writer.write_record(A::columns())?;
+        for (key, aggregate) in &self.groups {
+            writer.write_field(key.to_string())?;
+            let fields = aggregate
+                .values()
+                .iter()
+                .map(|v| {
+                    if v.is_nan() {
+                        String::new()
+                    } else {
+                        v.to_string()
+                    }
+                })
+                .collect::<Vec<_>>();
+            writer.write_record(fields)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<K: Ord, A: Aggregate> Default for GroupTable<K, A> {
+    fn default() -> Self {
+        GroupTable::new()
+    }
+}
+
+/// Streaming group-by over any iterator, such as a stream of records.
+///
+/// Each item is given a key and (optionally) a value; items without a value
+/// are skipped. The stream is read once, keeping only an aggregate per key,
+/// so memory is bounded by the number of groups rather than of items.
+///
+/// ```
+/// # use starquad::gaia::group::{Count, GroupBy, Summary};
+/// // mean of the values in each bin of width 10
+/// let values = vec![1.0, 4.0, 12.0, 18.0, f64::NAN];
+/// let table = values.iter().group_by::<_, Summary, _, _>(
+///     |v| (*v / 10.0).floor() as i64,
+///     |v| Some(**v).filter(|v| !v.is_nan()),
+/// );
+/// assert_eq!(table.get(&1).unwrap().mean(), Some(15.0));
+/// assert_eq!(table.len(), 2);
+///
+/// let counts = (0..10).group_by::<_, Count, _, _>(|i| i % 3, |_| Some(()));
+/// assert_eq!(counts.get(&0), Some(&Count(4)));
+/// ```
+pub trait GroupBy: Iterator + Sized {
+    fn group_by<K, A, F, G>(self, mut key: F, mut value: G) -> GroupTable<K, A>
+    where
+        K: Ord,
+        A: Aggregate,
+        F: FnMut(&Self::Item) -> K,
+        G: FnMut(&Self::Item) -> Option<A::Value>,
+    {
+        let mut table = GroupTable::new();
+        for item in self {
+            if let Some(v) = value(&item) {
+                table.add(key(&item), v);
+            }
+        }
+        table
+    }
+}
+
+impl<I: Iterator> GroupBy for I {}
+
+#[cfg(test)]
+mod test {
+    use gaia::group::{Count, GroupBy, Summary};
+    use gaia::record::test::sample_record;
+    use gaia::record::GaiaRecord;
+    use sky::healpix::pixel;
+    use sky::position::SkySource;
+
+    fn record(ra: f64, dec: f64, mag: f64, parallax: Option<f64>) -> GaiaRecord {
+        let mut record = sample_record();
+        record.ra = ra;
+        record.dec = dec;
+        record.phot_g_mean_mag = mag;
+        record.parallax = parallax;
+        record
+    }
+
+    #[test]
+    fn records_by_pixel_and_magnitude() {
+        let records = [
+            record(10.0, 60.0, 12.2, Some(1.0)),
+            record(11.0, 61.0, 12.7, Some(3.0)),
+            record(200.0, -60.0, 12.9, None),
+            record(201.0, -61.0, 15.1, Some(0.5)),
+        ];
+        let counts = records
+            .iter()
+            .group_by::<_, Count, _, _>(|r| pixel(0, &r.position()), |_| Some(()));
+        assert_eq!(
+            counts.iter().map(|(_, c)| c.0).collect::<Vec<_>>(),
+            vec![2, 2]
+        );
+
+        let parallaxes = records
+            .iter()
+            .group_by::<_, Summary, _, _>(|r| r.phot_g_mean_mag.floor() as i64, |r| r.parallax);
+        let bright = parallaxes.get(&12).unwrap();
+        assert_eq!((bright.count, bright.mean()), (2, Some(2.0)));
+        assert_eq!(bright.stddev(), Some(2.0f64.sqrt()));
+        assert_eq!(parallaxes.get(&15).unwrap().stddev(), None);
+
+        let mut csv = Vec::new();
+        parallaxes.write_csv(&mut csv, "g_mag").unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "g_mag,count,mean,stddev,min,max\n\
+             12,2,2,1.4142135623730951,1,3\n\
+             15,1,0.5,,0.5,0.5\n"
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use accel2d::quadtree::{inline_items, InlineQuadTree};
+use gaia::dedup::DuplicatePolicy;
+use gaia::record::GaiaRecord;
+use geom::p2::P2;
+use sky::index::index_sources_inline;
+use sky::quantized::{index_sources_quantized, QuantizedIndex};
+
+/// In-memory index of Gaia records by `(ra, dec)`.
+///
+/// Records are too large to keep inline in the leaves, so they are stored on
+/// the heap and the nodes stay small.
+pub type GaiaIndex = InlineQuadTree<f64, GaiaRecord, { inline_items::<(P2<f64>, GaiaRecord)>() }>;
+
+/// Options controlling how a `GaiaIndex` is built.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IndexBuilder {
+    dedup: Option<DuplicatePolicy>,
+}
+
+impl IndexBuilder {
+    pub fn new() -> Self {
+        IndexBuilder { dedup: None }
+    }
+
+    /// Remove duplicate sources (anywhere in the input) before indexing.
+    pub fn dedup(mut self, policy: DuplicatePolicy) -> Self {
+        self.dedup = Some(policy);
+        self
+    }
+
+    /// Build an index from a collection of records.
+    pub fn build<I>(&self, records: I) -> GaiaIndex
+    where
+        I: IntoIterator<Item = GaiaRecord>,
+    {
+        match &self.dedup {
+            Some(policy) => index_sources_inline(policy.dedup_all(records)),
+            None => index_sources_inline(records),
+        }
+    }
+
+    /// Build an index which stores quantized coordinates, from a collection
+    /// of records.
+    pub fn build_quantized<I>(&self, records: I) -> QuantizedIndex<GaiaRecord>
+    where
+        I: IntoIterator<Item = GaiaRecord>,
+    {
+        match &self.dedup {
+            Some(policy) => index_sources_quantized(policy.dedup_all(records)),
+            None => index_sources_quantized(records),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use accel2d::Accel2D;
+    use gaia::dedup::{DuplicatePolicy, KeepBest};
+    use gaia::index::IndexBuilder;
+    use gaia::record::test::sample_record;
+    use geom::rect::Rect;
+
+    #[test]
+    fn build_with_dedup() {
+        let records = (0..10)
+            .map(|i| {
+                let mut record = sample_record();
+                record.source_id = i;
+                record.ra = 100.0 + (i % 5) as f64;
+                record.dec = -20.0;
+                record.visibility_periods_used = i as u8;
+                record
+            })
+            .collect::<Vec<_>>();
+        let rect = Rect::new(0.0, -90.0, 360.0, 180.0).unwrap();
+
+        let index = IndexBuilder::new().build(records.clone());
+        assert_eq!(index.query_rect(&rect).len(), 10);
+
+        let policy = DuplicatePolicy::new(KeepBest::MostVisibilityPeriods);
+        let builder = IndexBuilder::new().dedup(policy);
+        let mut ids = builder
+            .build(records.clone())
+            .query_rect(&rect)
+            .iter()
+            .map(|(_, r)| r.source_id)
+            .collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(ids, vec![5, 6, 7, 8, 9]);
+
+        let mut ids = builder
+            .build_quantized(records)
+            .query_rect(&rect)
+            .iter()
+            .map(|r| r.source_id)
+            .collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(ids, vec![5, 6, 7, 8, 9]);
+    }
+}
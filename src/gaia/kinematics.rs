@@ -0,0 +1,183 @@
+use accel3d::kdtree::KdTree;
+use gaia::record::GaiaRecord;
+use geom::p3::P3;
+use serde::{Deserialize, Serialize};
+use sky::quantity::KM_S_PER_MAS_YR_KPC;
+
+/// Rotation from ICRS to Galactic Cartesian coordinates (the Hipparcos
+/// definition, as used by Gaia).
+const ICRS_TO_GALACTIC: [[f64; 3]; 3] = [
+    [
+        -0.054_875_560_416_215_4,
+        -0.873_437_090_234_885,
+        -0.483_835_015_548_713_2,
+    ],
+    [
+        0.494_109_427_875_583_7,
+        -0.444_829_629_960_011_2,
+        0.746_982_244_497_219,
+    ],
+    [
+        -0.867_666_149_019_004_7,
+        -0.198_076_373_431_201_5,
+        0.455_983_776_175_066_9,
+    ],
+];
+
+fn rotate(v: [f64; 3]) -> [f64; 3] {
+    let m = ICRS_TO_GALACTIC;
+    [
+        m[0][0] * v[0] + m[0][1] * v[1] + m[0][2] * v[2],
+        m[1][0] * v[0] + m[1][1] * v[1] + m[1][2] * v[2],
+        m[2][0] * v[0] + m[2][1] * v[1] + m[2][2] * v[2],
+    ]
+}
+
+/// Heliocentric position and velocity of a source in Galactic Cartesian
+/// coordinates.
+///
+/// `x` points towards the Galactic centre, `y` in the direction of Galactic
+/// rotation and `z` towards the north Galactic pole. Positions are in
+/// parsecs and velocities (`u`, `v`, `w` along `x`, `y`, `z`) in kilometres
+/// per second. The distance is the inverse of the parallax, so is only
+/// reasonable for sources with precise parallaxes.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PhaseSpace {
+    pub source_id: u64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub u: f64,
+    pub v: f64,
+    pub w: f64,
+}
+
+impl PhaseSpace {
+    /// Phase-space coordinates of a record, or `None` unless it has a
+    /// positive parallax, a proper motion and a radial velocity.
+    pub fn from_record(record: &GaiaRecord) -> Option<Self> {
+        let parallax = record.parallax.filter(|p| *p > 0.0)?;
+        let (pmra, pmdec) = (record.pmra?, record.pmdec?);
+        let radial_velocity = record.radial_velocity?;
+        let kpc = 1.0 / parallax;
+        let (sin_ra, cos_ra) = record.ra.to_radians().sin_cos();
+        let (sin_dec, cos_dec) = record.dec.to_radians().sin_cos();
+        // unit vectors towards the source, and towards east and north
+        let r = [cos_dec * cos_ra, cos_dec * sin_ra, sin_dec];
+        let east = [-sin_ra, cos_ra, 0.0];
+        let north = [-sin_dec * cos_ra, -sin_dec * sin_ra, cos_dec];
+        let (v_east, v_north) = (
+            KM_S_PER_MAS_YR_KPC * kpc * pmra,
+            KM_S_PER_MAS_YR_KPC * kpc * pmdec,
+        );
+        let mut position = [0.0; 3];
+        let mut velocity = [0.0; 3];
+        for i in 0..3 {
+            position[i] = 1000.0 * kpc * r[i];
+            velocity[i] = radial_velocity * r[i] + v_east * east[i] + v_north * north[i];
+        }
+        let [x, y, z] = rotate(position);
+        let [u, v, w] = rotate(velocity);
+        Some(PhaseSpace {
+            source_id: record.source_id,
+            x,
+            y,
+            z,
+            u,
+            v,
+            w,
+        })
+    }
+
+    pub fn position(&self) -> P3<f64> {
+        P3::new(self.x, self.y, self.z)
+    }
+
+    pub fn speed(&self) -> f64 {
+        (self.u * self.u + self.v * self.v + self.w * self.w).sqrt()
+    }
+}
+
+/// Stream adapter which extracts the radial-velocity subsample: the records
+/// with full phase-space coordinates (see `PhaseSpace::from_record`), each
+/// converted to them.
+pub struct RadialVelocitySubsample<I> {
+    records: I,
+}
+
+impl<I> RadialVelocitySubsample<I>
+where
+    I: Iterator<Item = GaiaRecord>,
+{
+    pub fn new(records: I) -> Self {
+        RadialVelocitySubsample { records }
+    }
+
+    /// Index the subsample by Galactic Cartesian position.
+    pub fn index(self) -> KdTree<f64, PhaseSpace> {
+        KdTree::new(self.map(|p| (p.position(), p)).collect())
+    }
+}
+
+impl<I> Iterator for RadialVelocitySubsample<I>
+where
+    I: Iterator<Item = GaiaRecord>,
+{
+    type Item = PhaseSpace;
+
+    fn next(&mut self) -> Option<PhaseSpace> {
+        self.records
+            .by_ref()
+            .find_map(|r| PhaseSpace::from_record(&r))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use gaia::kinematics::{PhaseSpace, RadialVelocitySubsample};
+    use gaia::record::test::sample_record;
+    use gaia::record::GaiaRecord;
+    use geom::p3::P3;
+
+    fn record(ra: f64, dec: f64, parallax: f64, pm: (f64, f64), rv: Option<f64>) -> GaiaRecord {
+        let mut record = sample_record();
+        record.ra = ra;
+        record.dec = dec;
+        record.parallax = Some(parallax);
+        record.pmra = Some(pm.0);
+        record.pmdec = Some(pm.1);
+        record.radial_velocity = rv;
+        record
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    #[test]
+    fn galactic_centre() {
+        // the direction of the Galactic centre, 2 kpc away, receding
+        let p = PhaseSpace::from_record(&record(266.405, -28.936, 0.5, (0.0, 0.0), Some(10.0)));
+        let p = p.unwrap();
+        assert!(close(p.x / 2000.0, 1.0) && p.y.abs() < 2.0 && p.z.abs() < 2.0);
+        assert!(close(p.u, 10.0) && p.v.abs() < 0.01 && p.w.abs() < 0.01);
+        // 1 mas/yr at 1 kpc is 4.74 km/s, in any direction
+        let q = PhaseSpace::from_record(&record(30.0, 40.0, 1.0, (0.6, 0.8), Some(0.0)));
+        assert!(close(q.unwrap().speed(), 4.740_470_446));
+    }
+
+    #[test]
+    fn subsample_index() {
+        let records = vec![
+            record(266.405, -28.936, 1.0, (0.0, 0.0), Some(1.0)),
+            record(266.405, -28.936, 1.0, (0.0, 0.0), None),
+            record(266.405, -28.936, -1.0, (0.0, 0.0), Some(1.0)),
+            record(86.405, 28.936, 0.1, (0.0, 0.0), Some(1.0)),
+        ];
+        let index = RadialVelocitySubsample::new(records.into_iter()).index();
+        assert_eq!(index.len(), 2);
+        let near_centre = index.query_radius(&P3::new(1000.0, 0.0, 0.0), 10.0);
+        assert_eq!(near_centre.len(), 1);
+        assert!(close(near_centre[0].1.u, 1.0));
+    }
+}
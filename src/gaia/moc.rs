@@ -0,0 +1,92 @@
+use gaia::record::GaiaRecord;
+use sky::healpix::{n_pixels, source_id_pixel, GAIA_SOURCE_ID_ORDER};
+use sky::moc::{Coverage, Moc};
+use sky::position::SkySource;
+
+/// Whether a record's position is in a MOC.
+///
+/// The level 12 HEALPix cell in the record's `source_id` decides most
+/// records without computing a pixel from the position: only records in
+/// cells on the edge of the MOC are tested exactly. A source whose position
+/// has crossed into another cell since its `source_id` was assigned is
+/// therefore decided by its `source_id` cell, unless that cell is on the
+/// edge.
+pub fn in_moc(moc: &Moc, record: &GaiaRecord) -> bool {
+    let pixel = source_id_pixel(record.source_id, GAIA_SOURCE_ID_ORDER);
+    if pixel >= n_pixels(GAIA_SOURCE_ID_ORDER) {
+        // not a Gaia source_id
+        return moc.contains(&record.position());
+    }
+    match moc.coverage(GAIA_SOURCE_ID_ORDER, pixel) {
+        Coverage::Full => true,
+        Coverage::Empty => false,
+        Coverage::Partial => moc.contains(&record.position()),
+    }
+}
+
+/// Stream adapter which keeps the records whose positions are in a MOC,
+/// such as "only sources within the SDSS footprint" (see `in_moc`).
+pub struct InMoc<'a, I> {
+    records: I,
+    moc: &'a Moc,
+}
+
+impl<'a, I> InMoc<'a, I>
+where
+    I: Iterator<Item = GaiaRecord>,
+{
+    pub fn new(records: I, moc: &'a Moc) -> Self {
+        InMoc { records, moc }
+    }
+}
+
+impl<'a, I> Iterator for InMoc<'a, I>
+where
+    I: Iterator<Item = GaiaRecord>,
+{
+    type Item = GaiaRecord;
+
+    fn next(&mut self) -> Option<GaiaRecord> {
+        let moc = self.moc;
+        self.records.by_ref().find(|record| in_moc(moc, record))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use gaia::moc::InMoc;
+    use gaia::record::test::sample_record;
+    use gaia::record::GaiaRecord;
+    use sky::healpix::{pixel, GAIA_SOURCE_ID_ORDER};
+    use sky::moc::Moc;
+    use sky::position::SkyPosition;
+
+    fn record(position: &SkyPosition, source_pixel: u64, i: u64) -> GaiaRecord {
+        let mut record = sample_record();
+        record.ra = position.ra;
+        record.dec = position.dec;
+        record.source_id = (source_pixel << 35) | i;
+        record
+    }
+
+    #[test]
+    fn filters_by_source_id_then_position() {
+        let south = SkyPosition::new(10.0, -60.0);
+        let elsewhere = SkyPosition::new(200.0, -60.0);
+        let cell = pixel(GAIA_SOURCE_ID_ORDER, &south);
+        // a northern base pixel, and part of the level 12 cell of `south`
+        let moc = Moc::from_cells(vec![(0, 0), (14, pixel(14, &south))]).unwrap();
+        let records = vec![
+            // decided by the cell in their source_ids alone
+            record(&south, 0, 0),
+            record(&south, pixel(GAIA_SOURCE_ID_ORDER, &elsewhere), 1),
+            // on the edge of the MOC, so decided by position
+            record(&south, cell, 2),
+            record(&elsewhere, cell, 3),
+        ];
+        let kept = InMoc::new(records.into_iter(), &moc)
+            .map(|record| record.source_id & 7)
+            .collect::<Vec<_>>();
+        assert_eq!(kept, vec![0, 2]);
+    }
+}
@@ -0,0 +1,19 @@
+pub mod columnar;
+pub mod dedup;
+pub mod diff;
+pub mod epoch;
+pub mod filter;
+pub mod group;
+pub mod index;
+pub mod kinematics;
+pub mod moc;
+pub mod neighbour;
+pub mod projection;
+pub mod reader;
+pub mod record;
+pub mod sample;
+pub mod schema;
+pub mod sort;
+pub mod stats;
+pub mod validate;
+pub mod writer;
@@ -0,0 +1,186 @@
+use csv::{DeserializeRecordsIntoIter, ReaderBuilder, Trim};
+use gaia::reader::decompress;
+use gaia::record::GaiaRecord;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+
+/// A row of one of the Gaia archive's pre-computed best-neighbour
+/// cross-match tables, such as `tmass_psc_xsc_best_neighbour` or
+/// `panstarrs1_best_neighbour`.
+///
+/// Only the columns which all of these tables share are read; the others
+/// (which differ between external catalogs and data releases) are ignored.
+/// `angular_distance` is in arcseconds.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct BestNeighbour {
+    pub source_id: u64,
+    pub original_ext_source_id: String,
+    pub angular_distance: f64,
+    pub number_of_neighbours: u32,
+    pub number_of_mates: u32,
+}
+
+/// Iterate over the rows of an (uncompressed) best-neighbour CSV stream.
+///
+/// Unlike `gaia_source`, these tables quote some external identifiers, so
+/// quotes are honoured.
+pub fn records<R: Read>(reader: R) -> DeserializeRecordsIntoIter<R, BestNeighbour> {
+    ReaderBuilder::new()
+        .has_headers(true)
+        .trim(Trim::All)
+        .from_reader(reader)
+        .into_deserialize()
+}
+
+/// Iterate over the rows of a best-neighbour CSV file, which is
+/// decompressed if its name ends in `.gz`.
+pub fn open<P: AsRef<Path>>(
+    path: P,
+) -> io::Result<DeserializeRecordsIntoIter<Box<dyn Read>, BestNeighbour>> {
+    let path = path.as_ref();
+    Ok(records(decompress(path, File::open(path)?)))
+}
+
+/// Best neighbours of Gaia sources in one external catalog, by `source_id`.
+///
+/// ```
+/// # use starquad::gaia::neighbour::{self, BestNeighbours};
+/// let table = "source_id,original_ext_source_id,angular_distance,\
+///              number_of_neighbours,number_of_mates\n\
+///              42,\"05325535+0915129\",0.08,1,0\n";
+/// let rows = neighbour::records(table.as_bytes()).collect::<Result<Vec<_>, _>>();
+/// let tmass = BestNeighbours::new(rows.unwrap());
+/// assert_eq!(tmass.get(42).unwrap().original_ext_source_id, "05325535+0915129");
+/// assert!(tmass.get(7).is_none());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct BestNeighbours {
+    by_source_id: HashMap<u64, BestNeighbour>,
+}
+
+impl BestNeighbours {
+    /// Collect best neighbours from the rows of a table. Where a `source_id`
+    /// appears more than once, the closest neighbour is kept.
+    pub fn new<I>(rows: I) -> Self
+    where
+        I: IntoIterator<Item = BestNeighbour>,
+    {
+        let mut by_source_id: HashMap<u64, BestNeighbour> = HashMap::new();
+        for row in rows {
+            match by_source_id.get(&row.source_id) {
+                Some(current) if current.angular_distance <= row.angular_distance => {}
+                _ => {
+                    by_source_id.insert(row.source_id, row);
+                }
+            }
+        }
+        BestNeighbours { by_source_id }
+    }
+
+    /// Read a whole best-neighbour CSV file (see `open`).
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self, csv::Error> {
+        let rows = open(path)?.collect::<Result<Vec<_>, _>>()?;
+        Ok(BestNeighbours::new(rows))
+    }
+
+    pub fn get(&self, source_id: u64) -> Option<&BestNeighbour> {
+        self.by_source_id.get(&source_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_source_id.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_source_id.is_empty()
+    }
+}
+
+/// Stream adapter which pairs each record with its best neighbour in an
+/// external catalog, if it has one, joining on `source_id`.
+pub struct WithNeighbours<'a, I> {
+    records: I,
+    neighbours: &'a BestNeighbours,
+}
+
+impl<'a, I> WithNeighbours<'a, I>
+where
+    I: Iterator<Item = GaiaRecord>,
+{
+    pub fn new(records: I, neighbours: &'a BestNeighbours) -> Self {
+        WithNeighbours {
+            records,
+            neighbours,
+        }
+    }
+}
+
+impl<'a, I> Iterator for WithNeighbours<'a, I>
+where
+    I: Iterator<Item = GaiaRecord>,
+{
+    type Item = (GaiaRecord, Option<&'a BestNeighbour>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = self.records.next()?;
+        let neighbour = self.neighbours.get(record.source_id);
+        Some((record, neighbour))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.records.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use gaia::neighbour::{records, BestNeighbours, WithNeighbours};
+    use gaia::record::test::sample_record;
+    use gaia::record::GaiaRecord;
+
+    const PANSTARRS1: &str = "\
+source_id,clean_panstarrs1_oid,original_ext_source_id,angular_distance,number_of_neighbours,number_of_mates,xm_flag
+1,901,\"108021023349483498\",0.12,1,0,0
+2,902,\"108021023349489999\",0.40,2,0,0
+2,903,\"108021023349481234\",0.05,2,0,0
+";
+
+    fn record(source_id: u64) -> GaiaRecord {
+        let mut record = sample_record();
+        record.source_id = source_id;
+        record
+    }
+
+    #[test]
+    fn joins_on_source_id() {
+        let rows = records(PANSTARRS1.as_bytes())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(rows.len(), 3);
+        let neighbours = BestNeighbours::new(rows);
+        assert_eq!(neighbours.len(), 2);
+        let joined = WithNeighbours::new(
+            vec![record(2), record(3), record(1)].into_iter(),
+            &neighbours,
+        )
+        .map(|(record, neighbour)| {
+            (
+                record.source_id,
+                neighbour.map(|n| n.original_ext_source_id.as_str()),
+            )
+        })
+        .collect::<Vec<_>>();
+        assert_eq!(
+            joined,
+            vec![
+                (2, Some("108021023349481234")),
+                (3, None),
+                (1, Some("108021023349483498")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use csv::StringRecord;
+use gaia::record::GaiaRecord;
+use gaia::schema::{self, Column, ColumnType, COLUMNS};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+/// Errors from creating a projection.
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum Error {
+    /// A column name is not a Gaia column.
+    #[error("unknown column: {0}")]
+    UnknownColumn(String),
+}
+
+/// Selection of the Gaia columns to output for each record.
+///
+/// A projected record serializes as a struct with only the selected fields,
+/// in the order in which they were selected, so it can be written by any of
+/// the `output` sinks.
+///
+/// ```
+/// # use starquad::gaia::projection::Projection;
+/// let projection = Projection::new(&["source_id", "ra", "dec"]).unwrap();
+/// assert_eq!(projection.names(), vec!["source_id", "ra", "dec"]);
+/// assert!(Projection::new(&["rad"]).is_err());
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Projection {
+    /// Positions of the selected columns in `schema::COLUMNS`.
+    columns: Vec<usize>,
+}
+
+impl Projection {
+    pub fn new<S: AsRef<str>>(names: &[S]) -> Result<Self, Error> {
+        let columns = names
+            .iter()
+            .map(|name| {
+                let name = name.as_ref();
+                COLUMNS
+                    .iter()
+                    .position(|c| c.name == name)
+                    .ok_or_else(|| Error::UnknownColumn(String::from(name)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Projection { columns })
+    }
+
+    /// Projection of every column.
+    pub fn all() -> Self {
+        Projection {
+            columns: (0..COLUMNS.len()).collect(),
+        }
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.columns.iter().map(|&i| COLUMNS[i].name).collect()
+    }
+
+    /// Positions of the selected columns in `schema::COLUMNS`.
+    pub fn columns(&self) -> &[usize] {
+        &self.columns
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.columns.iter().any(|&i| COLUMNS[i].name == name)
+    }
+
+    /// Add a column, if it is not already selected.
+    pub fn with_column(mut self, name: &str) -> Result<Self, Error> {
+        if !self.contains(name) {
+            self.columns.extend(Projection::new(&[name])?.columns);
+        }
+        Ok(self)
+    }
+
+    /// Add the `source_id` column, if it is not already selected.
+    pub fn with_source_id(mut self) -> Self {
+        if !self.columns.contains(&schema::SOURCE_ID) {
+            self.columns.push(schema::SOURCE_ID);
+        }
+        self
+    }
+
+    /// Add the `ra` and `dec` columns, if they are not already selected.
+    pub fn with_position(mut self) -> Self {
+        for (i, column) in COLUMNS.iter().enumerate() {
+            if (column.name == "ra" || column.name == "dec") && !self.columns.contains(&i) {
+                self.columns.push(i);
+            }
+        }
+        self
+    }
+
+    /// Copy a record with the columns outside the projection cleared: `None`
+    /// for optional fields, and empty, zero or `false` for the others.
+    pub fn clear_others(&self, record: &GaiaRecord) -> Result<GaiaRecord, csv::Error> {
+        let cleared = schema::string_record(record)
+            .iter()
+            .zip(COLUMNS.iter())
+            .enumerate()
+            .map(|(i, (text, column))| {
+                if self.columns.contains(&i) {
+                    text
+                } else {
+                    column.cleared()
+                }
+            })
+            .collect::<StringRecord>();
+        cleared.deserialize(Some(&schema::headers()))
+    }
+
+    pub fn project(&self, record: &GaiaRecord) -> Projected<'_> {
+        Projected {
+            projection: self,
+            fields: schema::string_record(record),
+        }
+    }
+}
+
+/// The selected columns of a record.
+pub struct Projected<'a> {
+    projection: &'a Projection,
+    fields: StringRecord,
+}
+
+/// Value of a single field, serialized with the type of its column.
+struct Field<'a> {
+    column: &'a Column,
+    text: &'a str,
+}
+
+impl<'a> Serialize for Field<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let text = self.text;
+        let invalid =
+            || serde::ser::Error::custom(format!("invalid {}: {}", self.column.name, text));
+        match self.column.column_type {
+            ColumnType::Text => serializer.serialize_str(text),
+            _ if text.is_empty() => serializer.serialize_none(),
+            ColumnType::Boolean => serializer.serialize_bool(text.parse().map_err(|_| invalid())?),
+            ColumnType::UnsignedByte => {
+                serializer.serialize_u8(text.parse().map_err(|_| invalid())?)
+            }
+            ColumnType::Long => serializer.serialize_u64(text.parse().map_err(|_| invalid())?),
+            ColumnType::Double => serializer.serialize_f64(text.parse().map_err(|_| invalid())?),
+        }
+    }
+}
+
+impl<'a> Serialize for Projected<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let columns = &self.projection.columns;
+        let mut state = serializer.serialize_struct("GaiaRecord", columns.len())?;
+        for &i in columns {
+            let column = &COLUMNS[i];
+            let field = Field {
+                column,
+                text: self.fields.get(i).unwrap_or(""),
+            };
+            state.serialize_field(column.name, &field)?;
+        }
+        state.end()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use gaia::projection::Projection;
+    use gaia::record::test::sample_record;
+
+    #[test]
+    fn json() {
+        let mut record = sample_record();
+        record.source_id = 42;
+        record.parallax = Some(1.5);
+        let projection =
+            Projection::new(&["source_id", "parallax", "bp_rp", "designation"]).unwrap();
+        assert_eq!(
+            serde_json::to_string(&projection.project(&record)).unwrap(),
+            r#"{"source_id":42,"parallax":1.5,"bp_rp":null,"designation":"Gaia DR2 0"}"#
+        );
+    }
+
+    #[test]
+    fn clear_others() {
+        let mut record = sample_record();
+        record.source_id = 42;
+        record.ra = 1.0;
+        record.parallax = Some(1.5);
+        record.pmra = Some(2.5);
+        let projection = Projection::new(&["ra", "pmra"]).unwrap();
+        let cleared = projection.clear_others(&record).unwrap();
+        let mut expected = sample_record();
+        expected.designation = String::new();
+        expected.ref_epoch = String::new();
+        expected.phot_variable_flag = String::new();
+        expected.ra = 1.0;
+        expected.pmra = Some(2.5);
+        assert_eq!(cleared, expected);
+    }
+
+    #[test]
+    fn csv() {
+        let projection = Projection::new(&["dec", "ra"]).unwrap();
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        let mut record = sample_record();
+        record.ra = 10.5;
+        writer.serialize(projection.project(&record)).unwrap();
+        let text = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(text, "dec,ra\n0.0,10.5\n");
+    }
+}
@@ -0,0 +1,68 @@
+use csv::{DeserializeRecordsIntoIter, Reader, ReaderBuilder, Terminator, Trim};
+use flate2::read::GzDecoder;
+use gaia::record::GaiaRecord;
+use progress::{ByteCounter, CountingReader};
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+
+/// Create a CSV reader configured for the Gaia `gaia_source` CSV format.
+pub fn csv_reader<R: Read>(reader: R) -> Reader<R> {
+    ReaderBuilder::new()
+        .delimiter(b',')
+        .has_headers(true)
+        .flexible(false)
+        .trim(Trim::All)
+        .terminator(Terminator::CRLF)
+        .quoting(false)
+        .from_reader(reader)
+}
+
+/// Iterate over the records of an (uncompressed) Gaia CSV stream.
+pub fn records<R: Read>(reader: R) -> DeserializeRecordsIntoIter<R, GaiaRecord> {
+    csv_reader(reader).into_deserialize()
+}
+
+/// Iterate over the records of a gzipped Gaia CSV file, such as
+/// `GaiaSource_*.csv.gz`.
+pub fn open_gz<P: AsRef<Path>>(
+    path: P,
+) -> io::Result<DeserializeRecordsIntoIter<GzDecoder<File>, GaiaRecord>> {
+    let file = File::open(path)?;
+    Ok(records(GzDecoder::new(file)))
+}
+
+/// Open a Gaia CSV file, which is decompressed if its name ends in `.gz`.
+pub fn open_csv<P: AsRef<Path>>(path: P) -> io::Result<Reader<Box<dyn Read>>> {
+    let path = path.as_ref();
+    Ok(csv_reader(decompress(path, File::open(path)?)))
+}
+
+/// Decompress a reader of a file if the file's name ends in `.gz`.
+pub(crate) fn decompress<R: Read + 'static>(path: &Path, reader: R) -> Box<dyn Read> {
+    if path.extension().is_some_and(|e| e == "gz") {
+        Box::new(GzDecoder::new(reader))
+    } else {
+        Box::new(reader)
+    }
+}
+
+/// Iterate over the records of a Gaia CSV file, which is decompressed if its
+/// name ends in `.gz`.
+pub fn open<P: AsRef<Path>>(
+    path: P,
+) -> io::Result<DeserializeRecordsIntoIter<Box<dyn Read>, GaiaRecord>> {
+    Ok(open_csv(path)?.into_deserialize())
+}
+
+/// Like `open`, but also adds the number of (compressed) bytes read from the
+/// file to a counter, for progress reporting.
+pub fn open_counted<P: AsRef<Path>>(
+    path: P,
+    counter: &ByteCounter,
+) -> io::Result<DeserializeRecordsIntoIter<Box<dyn Read>, GaiaRecord>> {
+    let path = path.as_ref();
+    let file = CountingReader::new(File::open(path)?, counter.clone());
+    Ok(csv_reader(decompress(path, file)).into_deserialize())
+}
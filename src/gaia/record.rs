@@ -0,0 +1,280 @@
+use serde::{Deserialize, Serialize};
+use sky::position::{SkyPosition, SkySource};
+use sky::quantity::{Angle, Parallax, ProperMotion};
+
+/// A single row of the Gaia `gaia_source` table.
+///
+/// Field names and types follow the column names of the Gaia DR2 CSV files.
+/// Columns which may be empty in the source files are represented as
+/// `Option`s.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct GaiaRecord {
+    pub solution_id: u64,
+    pub designation: String,
+    pub source_id: u64,
+    pub random_index: u64,
+    pub ref_epoch: String, // TODO: almost always 2015.5
+    pub ra: f64,
+    pub ra_error: f64,
+    pub dec: f64,
+    pub dec_error: f64,
+    pub parallax: Option<f64>,
+    pub parallax_error: Option<f64>,
+    pub parallax_over_error: Option<f64>,
+    pub pmra: Option<f64>,
+    pub pmra_error: Option<f64>,
+    pub pmdec: Option<f64>,
+    pub pmdec_error: Option<f64>,
+    pub ra_dec_corr: f64,
+    pub ra_parallax_corr: Option<f64>,
+    pub ra_pmra_corr: Option<f64>,
+    pub ra_pmdec_corr: Option<f64>,
+    pub dec_parallax_corr: Option<f64>,
+    pub dec_pmra_corr: Option<f64>,
+    pub dec_pmdec_corr: Option<f64>,
+    pub parallax_pmra_corr: Option<f64>,
+    pub parallax_pmdec_corr: Option<f64>,
+    pub pmra_pmdec_corr: Option<f64>,
+    pub astrometric_n_obs_al: u8,
+    pub astrometric_n_obs_ac: u8,
+    pub astrometric_n_good_obs_al: u8,
+    pub astrometric_n_bad_obs_al: u8,
+    pub astrometric_gof_al: f64,
+    pub astrometric_chi2_al: f64,
+    pub astrometric_excess_noise: f64,
+    pub astrometric_excess_noise_sig: f64,
+    pub astrometric_params_solved: u8,
+    pub astrometric_primary_flag: bool,
+    pub astrometric_weight_al: f64,
+    pub astrometric_pseudo_colour: Option<f64>,
+    pub astrometric_pseudo_colour_error: Option<f64>,
+    pub mean_varpi_factor_al: Option<f64>,
+    pub astrometric_matched_observations: u8,
+    pub visibility_periods_used: u8,
+    pub astrometric_sigma5d_max: f64,
+    pub frame_rotator_object_type: u8,
+    pub matched_observations: u8,
+    pub duplicated_source: bool,
+    pub phot_g_n_obs: u8,
+    pub phot_g_mean_flux: f64,
+    pub phot_g_mean_flux_error: f64,
+    pub phot_g_mean_flux_over_error: f64,
+    pub phot_g_mean_mag: f64,
+    pub phot_bp_n_obs: u8,
+    pub phot_bp_mean_flux: Option<f64>,
+    pub phot_bp_mean_flux_error: Option<f64>,
+    pub phot_bp_mean_flux_over_error: Option<f64>,
+    pub phot_bp_mean_mag: Option<f64>,
+    pub phot_rp_n_obs: u8,
+    pub phot_rp_mean_flux: Option<f64>,
+    pub phot_rp_mean_flux_error: Option<f64>,
+    pub phot_rp_mean_flux_over_error: Option<f64>,
+    pub phot_rp_mean_mag: Option<f64>,
+    pub phot_bp_rp_excess_factor: Option<f64>,
+    pub phot_proc_mode: u8,
+    pub bp_rp: Option<f64>,
+    pub bp_g: Option<f64>,
+    pub g_rp: Option<f64>,
+    pub radial_velocity: Option<f64>,
+    pub radial_velocity_error: Option<f64>,
+    pub rv_nb_transits: u8,
+    pub rv_template_teff: Option<f64>,
+    pub rv_template_logg: Option<f64>,
+    pub rv_template_fe_h: Option<f64>,
+    pub phot_variable_flag: String, // TODO: flag
+    pub l: f64,
+    pub b: f64,
+    pub ecl_lon: f64,
+    pub ecl_lat: f64,
+    pub priam_flags: Option<u64>,
+    pub teff_val: Option<f64>,
+    pub teff_percentile_lower: Option<f64>,
+    pub teff_percentile_upper: Option<f64>,
+    pub a_g_val: Option<f64>,
+    pub a_g_percentile_lower: Option<f64>,
+    pub a_g_percentile_upper: Option<f64>,
+    pub e_bp_min_rp_val: Option<f64>,
+    pub e_bp_min_rp_percentile_lower: Option<f64>,
+    pub e_bp_min_rp_percentile_upper: Option<f64>,
+    pub flame_flags: Option<u64>,
+    pub radius_val: Option<f64>,
+    pub radius_percentile_lower: Option<f64>,
+    pub radius_percentile_upper: Option<f64>,
+    pub lum_val: Option<f64>,
+    pub lum_percentile_lower: Option<f64>,
+    pub lum_percentile_upper: Option<f64>,
+}
+
+/// Accessors which carry the units of their columns (see
+/// `schema::COLUMN_META`) in their types.
+impl GaiaRecord {
+    pub fn ra_angle(&self) -> Angle {
+        Angle::from_degrees(self.ra)
+    }
+
+    pub fn dec_angle(&self) -> Angle {
+        Angle::from_degrees(self.dec)
+    }
+
+    pub fn ra_error_angle(&self) -> Angle {
+        Angle::from_mas(self.ra_error)
+    }
+
+    pub fn dec_error_angle(&self) -> Angle {
+        Angle::from_mas(self.dec_error)
+    }
+
+    pub fn plx(&self) -> Option<Parallax> {
+        self.parallax.map(Parallax::new)
+    }
+
+    pub fn plx_error_angle(&self) -> Option<Angle> {
+        self.parallax_error.map(Angle::from_mas)
+    }
+
+    pub fn pm(&self) -> Option<ProperMotion> {
+        Some(ProperMotion::new(self.pmra?, self.pmdec?))
+    }
+
+    /// Standard errors of `pmra` and `pmdec`.
+    pub fn pm_error(&self) -> Option<ProperMotion> {
+        Some(ProperMotion::new(self.pmra_error?, self.pmdec_error?))
+    }
+}
+
+impl SkySource for GaiaRecord {
+    fn position(&self) -> SkyPosition {
+        SkyPosition::new(self.ra, self.dec)
+    }
+
+    /// Quadratic mean of `ra_error` and `dec_error`.
+    fn position_error(&self) -> Option<f64> {
+        Some(((self.ra_error * self.ra_error + self.dec_error * self.dec_error) / 2.0).sqrt())
+    }
+
+    fn epoch(&self) -> Option<f64> {
+        self.ref_epoch.parse().ok()
+    }
+
+    fn proper_motion(&self) -> Option<(f64, f64)> {
+        match (self.pmra, self.pmdec) {
+            (Some(pmra), Some(pmdec)) => Some((pmra, pmdec)),
+            _ => None,
+        }
+    }
+
+    fn id(&self) -> Option<String> {
+        Some(self.source_id.to_string())
+    }
+
+    /// Mean G-band magnitude.
+    fn magnitude(&self) -> Option<f64> {
+        Some(self.phot_g_mean_mag)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use gaia::record::GaiaRecord;
+
+    /// A record with every optional field empty and every other field zero,
+    /// for tests to modify as required.
+    pub fn sample_record() -> GaiaRecord {
+        GaiaRecord {
+            solution_id: 0,
+            designation: String::from("Gaia DR2 0"),
+            source_id: 0,
+            random_index: 0,
+            ref_epoch: String::from("2015.5"),
+            ra: 0.0,
+            ra_error: 0.0,
+            dec: 0.0,
+            dec_error: 0.0,
+            parallax: None,
+            parallax_error: None,
+            parallax_over_error: None,
+            pmra: None,
+            pmra_error: None,
+            pmdec: None,
+            pmdec_error: None,
+            ra_dec_corr: 0.0,
+            ra_parallax_corr: None,
+            ra_pmra_corr: None,
+            ra_pmdec_corr: None,
+            dec_parallax_corr: None,
+            dec_pmra_corr: None,
+            dec_pmdec_corr: None,
+            parallax_pmra_corr: None,
+            parallax_pmdec_corr: None,
+            pmra_pmdec_corr: None,
+            astrometric_n_obs_al: 0,
+            astrometric_n_obs_ac: 0,
+            astrometric_n_good_obs_al: 0,
+            astrometric_n_bad_obs_al: 0,
+            astrometric_gof_al: 0.0,
+            astrometric_chi2_al: 0.0,
+            astrometric_excess_noise: 0.0,
+            astrometric_excess_noise_sig: 0.0,
+            astrometric_params_solved: 0,
+            astrometric_primary_flag: false,
+            astrometric_weight_al: 0.0,
+            astrometric_pseudo_colour: None,
+            astrometric_pseudo_colour_error: None,
+            mean_varpi_factor_al: None,
+            astrometric_matched_observations: 0,
+            visibility_periods_used: 0,
+            astrometric_sigma5d_max: 0.0,
+            frame_rotator_object_type: 0,
+            matched_observations: 0,
+            duplicated_source: false,
+            phot_g_n_obs: 0,
+            phot_g_mean_flux: 0.0,
+            phot_g_mean_flux_error: 0.0,
+            phot_g_mean_flux_over_error: 0.0,
+            phot_g_mean_mag: 0.0,
+            phot_bp_n_obs: 0,
+            phot_bp_mean_flux: None,
+            phot_bp_mean_flux_error: None,
+            phot_bp_mean_flux_over_error: None,
+            phot_bp_mean_mag: None,
+            phot_rp_n_obs: 0,
+            phot_rp_mean_flux: None,
+            phot_rp_mean_flux_error: None,
+            phot_rp_mean_flux_over_error: None,
+            phot_rp_mean_mag: None,
+            phot_bp_rp_excess_factor: None,
+            phot_proc_mode: 0,
+            bp_rp: None,
+            bp_g: None,
+            g_rp: None,
+            radial_velocity: None,
+            radial_velocity_error: None,
+            rv_nb_transits: 0,
+            rv_template_teff: None,
+            rv_template_logg: None,
+            rv_template_fe_h: None,
+            phot_variable_flag: String::new(),
+            l: 0.0,
+            b: 0.0,
+            ecl_lon: 0.0,
+            ecl_lat: 0.0,
+            priam_flags: None,
+            teff_val: None,
+            teff_percentile_lower: None,
+            teff_percentile_upper: None,
+            a_g_val: None,
+            a_g_percentile_lower: None,
+            a_g_percentile_upper: None,
+            e_bp_min_rp_val: None,
+            e_bp_min_rp_percentile_lower: None,
+            e_bp_min_rp_percentile_upper: None,
+            flame_flags: None,
+            radius_val: None,
+            radius_percentile_lower: None,
+            radius_percentile_upper: None,
+            lum_val: None,
+            lum_percentile_lower: None,
+            lum_percentile_upper: None,
+        }
+    }
+}
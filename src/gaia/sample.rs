@@ -0,0 +1,104 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+/// Stream adapter which keeps each item independently with a fixed
+/// probability.
+///
+/// The same seed always selects the same items from the same stream, so a
+/// subsample can be reproduced without storing it.
+pub struct Bernoulli<I> {
+    items: I,
+    fraction: f64,
+    rng: ChaCha8Rng,
+}
+
+impl<I: Iterator> Bernoulli<I> {
+    /// Keep each item with probability `fraction`, which is clamped to
+    /// `[0, 1]`.
+    pub fn new(items: I, fraction: f64, seed: u64) -> Self {
+        Bernoulli {
+            items,
+            fraction: fraction.clamp(0.0, 1.0),
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl<I: Iterator> Iterator for Bernoulli<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        let fraction = self.fraction;
+        let rng = &mut self.rng;
+        self.items.by_ref().find(|_| rng.gen_bool(fraction))
+    }
+}
+
+/// Choose exactly `size` items uniformly at random from a stream (or all of
+/// them, if there are fewer), in a single pass and using memory proportional
+/// to `size`.
+///
+/// The chosen items are returned in the order in which they appeared in the
+/// stream. As with `Bernoulli`, the same seed always chooses the same items.
+pub fn reservoir<I>(items: I, size: usize, seed: u64) -> Vec<I::Item>
+where
+    I: IntoIterator,
+{
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let mut chosen = Vec::with_capacity(size);
+    for (i, item) in items.into_iter().enumerate() {
+        if i < size {
+            chosen.push((i, item));
+        } else {
+            let j = rng.gen_range(0..=i);
+            if j < size {
+                chosen[j] = (i, item);
+            }
+        }
+    }
+    chosen.sort_by_key(|&(i, _)| i);
+    chosen.into_iter().map(|(_, item)| item).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use gaia::sample::{reservoir, Bernoulli};
+
+    #[test]
+    fn bernoulli_fraction() {
+        let kept = Bernoulli::new(0..100_000, 0.1, 42).count();
+        assert!((9_000..11_000).contains(&kept));
+        assert_eq!(Bernoulli::new(0..100, 0.0, 42).count(), 0);
+        assert_eq!(Bernoulli::new(0..100, 1.0, 42).count(), 100);
+    }
+
+    #[test]
+    fn bernoulli_reproducible() {
+        let a = Bernoulli::new(0..1000, 0.5, 7).collect::<Vec<_>>();
+        let b = Bernoulli::new(0..1000, 0.5, 7).collect::<Vec<_>>();
+        let c = Bernoulli::new(0..1000, 0.5, 8).collect::<Vec<_>>();
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn reservoir_size() {
+        let chosen = reservoir(0..1000, 10, 3);
+        assert_eq!(chosen.len(), 10);
+        assert!(chosen.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(chosen, reservoir(0..1000, 10, 3));
+        assert_eq!(reservoir(0..5, 10, 3), vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn reservoir_uniform() {
+        // each item should be chosen in about a tenth of the trials
+        let mut counts = [0; 20];
+        for seed in 0..2000 {
+            for i in reservoir(0..20, 2, seed) {
+                counts[i] += 1;
+            }
+        }
+        assert!(counts.iter().all(|&n| (140..260).contains(&n)));
+    }
+}
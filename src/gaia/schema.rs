@@ -0,0 +1,342 @@
+use csv::{ReaderBuilder, StringRecord, WriterBuilder};
+use gaia::record::GaiaRecord;
+
+/// Storage type of a Gaia catalog column.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColumnType {
+    Boolean,
+    UnsignedByte,
+    Long,
+    Double,
+    Text,
+}
+
+/// Description of a column of the Gaia `gaia_source` table.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Column {
+    pub name: &'static str,
+    pub column_type: ColumnType,
+    /// Whether the column may be empty (an `Option` field of `GaiaRecord`).
+    pub nullable: bool,
+}
+
+const fn col(name: &'static str, column_type: ColumnType, nullable: bool) -> Column {
+    Column {
+        name,
+        column_type,
+        nullable,
+    }
+}
+
+impl Column {
+    /// Text of the column in a record which does not hold it: empty for
+    /// optional and text columns, and `false` or `0` for the others.
+    pub fn cleared(&self) -> &'static str {
+        if self.nullable || self.column_type == ColumnType::Text {
+            ""
+        } else if self.column_type == ColumnType::Boolean {
+            "false"
+        } else {
+            "0"
+        }
+    }
+}
+
+/// Position of `source_id` in `COLUMNS`.
+pub const SOURCE_ID: usize = 2;
+
+/// Columns of `GaiaRecord`, in the order in which they appear in the Gaia CSV
+/// files.
+pub const COLUMNS: &[Column] = &[
+    col("solution_id", ColumnType::Long, false),
+    col("designation", ColumnType::Text, false),
+    col("source_id", ColumnType::Long, false),
+    col("random_index", ColumnType::Long, false),
+    col("ref_epoch", ColumnType::Text, false),
+    col("ra", ColumnType::Double, false),
+    col("ra_error", ColumnType::Double, false),
+    col("dec", ColumnType::Double, false),
+    col("dec_error", ColumnType::Double, false),
+    col("parallax", ColumnType::Double, true),
+    col("parallax_error", ColumnType::Double, true),
+    col("parallax_over_error", ColumnType::Double, true),
+    col("pmra", ColumnType::Double, true),
+    col("pmra_error", ColumnType::Double, true),
+    col("pmdec", ColumnType::Double, true),
+    col("pmdec_error", ColumnType::Double, true),
+    col("ra_dec_corr", ColumnType::Double, false),
+    col("ra_parallax_corr", ColumnType::Double, true),
+    col("ra_pmra_corr", ColumnType::Double, true),
+    col("ra_pmdec_corr", ColumnType::Double, true),
+    col("dec_parallax_corr", ColumnType::Double, true),
+    col("dec_pmra_corr", ColumnType::Double, true),
+    col("dec_pmdec_corr", ColumnType::Double, true),
+    col("parallax_pmra_corr", ColumnType::Double, true),
+    col("parallax_pmdec_corr", ColumnType::Double, true),
+    col("pmra_pmdec_corr", ColumnType::Double, true),
+    col("astrometric_n_obs_al", ColumnType::UnsignedByte, false),
+    col("astrometric_n_obs_ac", ColumnType::UnsignedByte, false),
+    col("astrometric_n_good_obs_al", ColumnType::UnsignedByte, false),
+    col("astrometric_n_bad_obs_al", ColumnType::UnsignedByte, false),
+    col("astrometric_gof_al", ColumnType::Double, false),
+    col("astrometric_chi2_al", ColumnType::Double, false),
+    col("astrometric_excess_noise", ColumnType::Double, false),
+    col("astrometric_excess_noise_sig", ColumnType::Double, false),
+    col("astrometric_params_solved", ColumnType::UnsignedByte, false),
+    col("astrometric_primary_flag", ColumnType::Boolean, false),
+    col("astrometric_weight_al", ColumnType::Double, false),
+    col("astrometric_pseudo_colour", ColumnType::Double, true),
+    col("astrometric_pseudo_colour_error", ColumnType::Double, true),
+    col("mean_varpi_factor_al", ColumnType::Double, true),
+    col(
+        "astrometric_matched_observations",
+        ColumnType::UnsignedByte,
+        false,
+    ),
+    col("visibility_periods_used", ColumnType::UnsignedByte, false),
+    col("astrometric_sigma5d_max", ColumnType::Double, false),
+    col("frame_rotator_object_type", ColumnType::UnsignedByte, false),
+    col("matched_observations", ColumnType::UnsignedByte, false),
+    col("duplicated_source", ColumnType::Boolean, false),
+    col("phot_g_n_obs", ColumnType::UnsignedByte, false),
+    col("phot_g_mean_flux", ColumnType::Double, false),
+    col("phot_g_mean_flux_error", ColumnType::Double, false),
+    col("phot_g_mean_flux_over_error", ColumnType::Double, false),
+    col("phot_g_mean_mag", ColumnType::Double, false),
+    col("phot_bp_n_obs", ColumnType::UnsignedByte, false),
+    col("phot_bp_mean_flux", ColumnType::Double, true),
+    col("phot_bp_mean_flux_error", ColumnType::Double, true),
+    col("phot_bp_mean_flux_over_error", ColumnType::Double, true),
+    col("phot_bp_mean_mag", ColumnType::Double, true),
+    col("phot_rp_n_obs", ColumnType::UnsignedByte, false),
+    col("phot_rp_mean_flux", ColumnType::Double, true),
+    col("phot_rp_mean_flux_error", ColumnType::Double, true),
+    col("phot_rp_mean_flux_over_error", ColumnType::Double, true),
+    col("phot_rp_mean_mag", ColumnType::Double, true),
+    col("phot_bp_rp_excess_factor", ColumnType::Double, true),
+    col("phot_proc_mode", ColumnType::UnsignedByte, false),
+    col("bp_rp", ColumnType::Double, true),
+    col("bp_g", ColumnType::Double, true),
+    col("g_rp", ColumnType::Double, true),
+    col("radial_velocity", ColumnType::Double, true),
+    col("radial_velocity_error", ColumnType::Double, true),
+    col("rv_nb_transits", ColumnType::UnsignedByte, false),
+    col("rv_template_teff", ColumnType::Double, true),
+    col("rv_template_logg", ColumnType::Double, true),
+    col("rv_template_fe_h", ColumnType::Double, true),
+    col("phot_variable_flag", ColumnType::Text, false),
+    col("l", ColumnType::Double, false),
+    col("b", ColumnType::Double, false),
+    col("ecl_lon", ColumnType::Double, false),
+    col("ecl_lat", ColumnType::Double, false),
+    col("priam_flags", ColumnType::Long, true),
+    col("teff_val", ColumnType::Double, true),
+    col("teff_percentile_lower", ColumnType::Double, true),
+    col("teff_percentile_upper", ColumnType::Double, true),
+    col("a_g_val", ColumnType::Double, true),
+    col("a_g_percentile_lower", ColumnType::Double, true),
+    col("a_g_percentile_upper", ColumnType::Double, true),
+    col("e_bp_min_rp_val", ColumnType::Double, true),
+    col("e_bp_min_rp_percentile_lower", ColumnType::Double, true),
+    col("e_bp_min_rp_percentile_upper", ColumnType::Double, true),
+    col("flame_flags", ColumnType::Long, true),
+    col("radius_val", ColumnType::Double, true),
+    col("radius_percentile_lower", ColumnType::Double, true),
+    col("radius_percentile_upper", ColumnType::Double, true),
+    col("lum_val", ColumnType::Double, true),
+    col("lum_percentile_lower", ColumnType::Double, true),
+    col("lum_percentile_upper", ColumnType::Double, true),
+];
+
+/// Physical unit of the values of a column.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Unit {
+    Degree,
+    Milliarcsecond,
+    MilliarcsecondPerYear,
+    /// Weight of an along-scan observation, in `mas**-2`.
+    PerSquareMilliarcsecond,
+    /// Pseudo-colour and parallax factor, in inverse micrometres.
+    PerMicrometre,
+    /// Flux, in photo-electrons per second.
+    ElectronPerSecond,
+    Magnitude,
+    KilometrePerSecond,
+    Kelvin,
+    /// Surface gravity, as the logarithm of `cm.s**-2`.
+    LogCgsGravity,
+    Dex,
+    SolarRadius,
+    SolarLuminosity,
+}
+
+impl Unit {
+    /// Symbol of the unit in the IVOA VOUnits syntax, which VOTable and
+    /// ECSV readers (such as astropy) understand.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Unit::Degree => "deg",
+            Unit::Milliarcsecond => "mas",
+            Unit::MilliarcsecondPerYear => "mas.yr**-1",
+            Unit::PerSquareMilliarcsecond => "mas**-2",
+            Unit::PerMicrometre => "um**-1",
+            Unit::ElectronPerSecond => "electron.s**-1",
+            Unit::Magnitude => "mag",
+            Unit::KilometrePerSecond => "km.s**-1",
+            Unit::Kelvin => "K",
+            Unit::LogCgsGravity => "log(cm.s**-2)",
+            Unit::Dex => "dex",
+            Unit::SolarRadius => "solRad",
+            Unit::SolarLuminosity => "solLum",
+        }
+    }
+}
+
+/// Metadata of a column which has a physical unit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ColumnMeta {
+    pub name: &'static str,
+    pub unit: Unit,
+}
+
+const fn meta(name: &'static str, unit: Unit) -> ColumnMeta {
+    ColumnMeta { name, unit }
+}
+
+/// Units of the columns of `COLUMNS` which have them, in the same order.
+/// Counts, flags, identifiers, correlations and ratios have no unit.
+pub const COLUMN_META: &[ColumnMeta] = &[
+    meta("ra", Unit::Degree),
+    meta("ra_error", Unit::Milliarcsecond),
+    meta("dec", Unit::Degree),
+    meta("dec_error", Unit::Milliarcsecond),
+    meta("parallax", Unit::Milliarcsecond),
+    meta("parallax_error", Unit::Milliarcsecond),
+    meta("pmra", Unit::MilliarcsecondPerYear),
+    meta("pmra_error", Unit::MilliarcsecondPerYear),
+    meta("pmdec", Unit::MilliarcsecondPerYear),
+    meta("pmdec_error", Unit::MilliarcsecondPerYear),
+    meta("astrometric_excess_noise", Unit::Milliarcsecond),
+    meta("astrometric_weight_al", Unit::PerSquareMilliarcsecond),
+    meta("astrometric_pseudo_colour", Unit::PerMicrometre),
+    meta("astrometric_pseudo_colour_error", Unit::PerMicrometre),
+    meta("mean_varpi_factor_al", Unit::PerMicrometre),
+    meta("astrometric_sigma5d_max", Unit::Milliarcsecond),
+    meta("phot_g_mean_flux", Unit::ElectronPerSecond),
+    meta("phot_g_mean_flux_error", Unit::ElectronPerSecond),
+    meta("phot_g_mean_mag", Unit::Magnitude),
+    meta("phot_bp_mean_flux", Unit::ElectronPerSecond),
+    meta("phot_bp_mean_flux_error", Unit::ElectronPerSecond),
+    meta("phot_bp_mean_mag", Unit::Magnitude),
+    meta("phot_rp_mean_flux", Unit::ElectronPerSecond),
+    meta("phot_rp_mean_flux_error", Unit::ElectronPerSecond),
+    meta("phot_rp_mean_mag", Unit::Magnitude),
+    meta("bp_rp", Unit::Magnitude),
+    meta("bp_g", Unit::Magnitude),
+    meta("g_rp", Unit::Magnitude),
+    meta("radial_velocity", Unit::KilometrePerSecond),
+    meta("radial_velocity_error", Unit::KilometrePerSecond),
+    meta("rv_template_teff", Unit::Kelvin),
+    meta("rv_template_logg", Unit::LogCgsGravity),
+    meta("rv_template_fe_h", Unit::Dex),
+    meta("l", Unit::Degree),
+    meta("b", Unit::Degree),
+    meta("ecl_lon", Unit::Degree),
+    meta("ecl_lat", Unit::Degree),
+    meta("teff_val", Unit::Kelvin),
+    meta("teff_percentile_lower", Unit::Kelvin),
+    meta("teff_percentile_upper", Unit::Kelvin),
+    meta("a_g_val", Unit::Magnitude),
+    meta("a_g_percentile_lower", Unit::Magnitude),
+    meta("a_g_percentile_upper", Unit::Magnitude),
+    meta("e_bp_min_rp_val", Unit::Magnitude),
+    meta("e_bp_min_rp_percentile_lower", Unit::Magnitude),
+    meta("e_bp_min_rp_percentile_upper", Unit::Magnitude),
+    meta("radius_val", Unit::SolarRadius),
+    meta("radius_percentile_lower", Unit::SolarRadius),
+    meta("radius_percentile_upper", Unit::SolarRadius),
+    meta("lum_val", Unit::SolarLuminosity),
+    meta("lum_percentile_lower", Unit::SolarLuminosity),
+    meta("lum_percentile_upper", Unit::SolarLuminosity),
+];
+
+/// Unit of a column, or `None` if it has none (or there is no such column).
+///
+/// ```
+/// # use starquad::gaia::schema::{unit, Unit};
+/// assert_eq!(unit("pmra"), Some(Unit::MilliarcsecondPerYear));
+/// assert_eq!(unit("pmra").unwrap().symbol(), "mas.yr**-1");
+/// assert_eq!(unit("source_id"), None);
+/// ```
+pub fn unit(name: &str) -> Option<Unit> {
+    COLUMN_META.iter().find(|m| m.name == name).map(|m| m.unit)
+}
+
+/// Find a column by name.
+pub fn column(name: &str) -> Option<&'static Column> {
+    COLUMNS.iter().find(|c| c.name == name)
+}
+
+/// Names of the columns, as a CSV header record.
+pub fn headers() -> StringRecord {
+    COLUMNS.iter().map(|c| c.name).collect()
+}
+
+/// Convert records to their CSV text fields, in the order of `COLUMNS`.
+/// Empty columns are represented by empty fields.
+pub fn string_records(records: &[GaiaRecord]) -> Result<Vec<StringRecord>, csv::Error> {
+    let mut writer = WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(Vec::new());
+    for record in records {
+        writer.serialize(record)?;
+    }
+    let bytes = writer.into_inner().map_err(|e| e.into_error())?;
+    ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(bytes.as_slice())
+        .into_records()
+        .collect()
+}
+
+/// Convert a record to its CSV text fields, in the order of `COLUMNS`.
+///
+/// Writing a `GaiaRecord` to memory does not fail, but if it did the record
+/// would have no fields, which read as empty columns.
+pub fn string_record(record: &GaiaRecord) -> StringRecord {
+    string_records(std::slice::from_ref(record))
+        .ok()
+        .and_then(|mut fields| fields.pop())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use csv::Writer;
+    use gaia::record::test::sample_record;
+    use gaia::schema::{COLUMNS, COLUMN_META, SOURCE_ID};
+
+    #[test]
+    fn source_id_position() {
+        assert_eq!(COLUMNS[SOURCE_ID].name, "source_id");
+    }
+
+    #[test]
+    fn columns_match_record_fields() {
+        let mut writer = Writer::from_writer(vec![]);
+        writer.serialize(sample_record()).unwrap();
+        let csv = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        let header = csv.lines().next().unwrap();
+        let names = COLUMNS.iter().map(|c| c.name).collect::<Vec<_>>();
+        assert_eq!(header, names.join(","));
+    }
+
+    #[test]
+    fn column_meta_in_column_order() {
+        let places = COLUMN_META
+            .iter()
+            .map(|meta| COLUMNS.iter().position(|c| c.name == meta.name).unwrap())
+            .collect::<Vec<_>>();
+        assert!(places.windows(2).all(|w| w[0] < w[1]));
+    }
+}
@@ -0,0 +1,217 @@
+use gaia::record::GaiaRecord;
+use output::bincode_sink::{BincodeReader, BincodeSink};
+use output::{Error, RecordSink};
+use sky::healpix::{interleave, pixel, MAX_ORDER};
+use sky::position::SkySource;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Order of records sorted by `ExternalSort`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SortKey {
+    SourceId,
+    /// NESTED HEALPix pixel at `MAX_ORDER`, which keeps each pixel's records
+    /// together at every order.
+    Healpix,
+    /// Morton (Z-order) code of `(ra, dec)`, each quantized to 32 bits.
+    Morton,
+}
+
+impl SortKey {
+    pub fn key(self, record: &GaiaRecord) -> u64 {
+        match self {
+            SortKey::SourceId => record.source_id,
+            SortKey::Healpix => pixel(MAX_ORDER, &record.position()),
+            SortKey::Morton => {
+                let scale = f64::from(u32::MAX);
+                let x = (record.ra / 360.0).clamp(0.0, 1.0) * scale;
+                let y = ((record.dec + 90.0) / 180.0).clamp(0.0, 1.0) * scale;
+                interleave(x as u64, y as u64)
+            }
+        }
+    }
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "source_id" | "source-id" => Ok(SortKey::SourceId),
+            "healpix" => Ok(SortKey::Healpix),
+            "morton" => Ok(SortKey::Morton),
+            _ => Err(format!("unknown sort key: {}", name)),
+        }
+    }
+}
+
+/// Sort of a stream of records too large to sort in memory.
+///
+/// Records are gathered into runs of `run_size`, each of which is sorted and
+/// written to a file in a working directory; the runs are then merged, a
+/// record at a time. Memory is bounded by the run size, and then by the
+/// number of runs. Records with equal keys are ordered by `source_id`.
+///
+/// ```no_run
+/// # use starquad::gaia::reader;
+/// # use starquad::gaia::sort::{ExternalSort, SortKey};
+/// let records = reader::open("GaiaSource_000000-003111.csv.gz")?.map_while(Result::ok);
+/// for record in ExternalSort::new(SortKey::Healpix, "/tmp/runs").sort(records)? {
+///     let record = record?;
+///     // ...
+/// }
+/// # Ok::<(), starquad::Error>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct ExternalSort {
+    key: SortKey,
+    run_size: usize,
+    dir: PathBuf,
+}
+
+impl ExternalSort {
+    /// Sort by `key`, writing runs to files in `dir`, which is created if
+    /// it does not exist.
+    pub fn new<P: AsRef<Path>>(key: SortKey, dir: P) -> Self {
+        ExternalSort {
+            key,
+            run_size: 1_000_000,
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Number of records in each run (at least one).
+    pub fn with_run_size(mut self, run_size: usize) -> Self {
+        self.run_size = run_size.max(1);
+        self
+    }
+
+    /// Write the sorted runs of a stream of records, and merge them.
+    pub fn sort<I>(&self, records: I) -> Result<Merge, Error>
+    where
+        I: IntoIterator<Item = GaiaRecord>,
+    {
+        fs::create_dir_all(&self.dir)?;
+        let mut runs = Vec::new();
+        let mut run = Vec::with_capacity(self.run_size);
+        for record in records {
+            run.push(record);
+            if run.len() == self.run_size {
+                runs.push(self.write_run(&mut run, runs.len())?);
+            }
+        }
+        if !run.is_empty() {
+            runs.push(self.write_run(&mut run, runs.len())?);
+        }
+        tracing::debug!(runs = runs.len(), "sorted runs written");
+        Merge::new(self.key, runs)
+    }
+
+    fn write_run(&self, run: &mut Vec<GaiaRecord>, index: usize) -> Result<PathBuf, Error> {
+        let key = self.key;
+        run.sort_by_cached_key(|record| (key.key(record), record.source_id));
+        let path = self.dir.join(format!("run-{:06}.bin", index));
+        let mut sink = BincodeSink::new(File::create(&path)?);
+        sink.write_all(run.iter())?;
+        RecordSink::<GaiaRecord>::finish(&mut sink)?;
+        run.clear();
+        Ok(path)
+    }
+}
+
+type Run = BincodeReader<BufReader<File>, GaiaRecord>;
+
+/// Records of sorted runs, merged in order. The run files are removed when
+/// the merge is dropped.
+pub struct Merge {
+    key: SortKey,
+    paths: Vec<PathBuf>,
+    runs: Vec<Run>,
+    /// Next record of each run which has one, keyed for the merge.
+    heads: BinaryHeap<Reverse<(u64, u64, usize)>>,
+    records: Vec<Option<GaiaRecord>>,
+}
+
+impl Merge {
+    fn new(key: SortKey, paths: Vec<PathBuf>) -> Result<Self, Error> {
+        let runs = paths
+            .iter()
+            .map(|path| Ok(BincodeReader::new(BufReader::new(File::open(path)?))))
+            .collect::<Result<Vec<Run>, Error>>()?;
+        let mut merge = Merge {
+            key,
+            records: vec![None; runs.len()],
+            paths,
+            runs,
+            heads: BinaryHeap::new(),
+        };
+        for run in 0..merge.runs.len() {
+            merge.advance(run)?;
+        }
+        Ok(merge)
+    }
+
+    /// Read the next record of a run into the heap.
+    fn advance(&mut self, run: usize) -> Result<(), Error> {
+        if let Some(record) = self.runs[run].next().transpose()? {
+            let key = (self.key.key(&record), record.source_id, run);
+            self.heads.push(Reverse(key));
+            self.records[run] = Some(record);
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for Merge {
+    type Item = Result<GaiaRecord, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((_, _, run)) = self.heads.pop()?;
+        let record = self.records[run].take()?;
+        Some(self.advance(run).map(|()| record))
+    }
+}
+
+impl Drop for Merge {
+    fn drop(&mut self) {
+        for path in &self.paths {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use gaia::record::test::sample_record;
+    use gaia::sort::{ExternalSort, SortKey};
+    use std::env;
+
+    #[test]
+    fn sorts_across_runs() {
+        let records = (0..100u64).map(|i| {
+            let mut record = sample_record();
+            record.source_id = (i * 37) % 101;
+            record.ra = (i * 7 % 360) as f64;
+            record.dec = (i % 90) as f64 - 45.0;
+            record
+        });
+        let dir = env::temp_dir().join(format!("starquad-sort-{}", std::process::id()));
+        for key in [SortKey::SourceId, SortKey::Healpix, SortKey::Morton] {
+            let sorted = ExternalSort::new(key, &dir)
+                .with_run_size(7)
+                .sort(records.clone())
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            assert_eq!(sorted.len(), 100);
+            assert!(sorted.windows(2).all(|w| key.key(&w[0]) <= key.key(&w[1])));
+        }
+        // the runs are removed once merged
+        assert_eq!(dir.read_dir().unwrap().count(), 0);
+        std::fs::remove_dir(dir).unwrap();
+    }
+}
@@ -0,0 +1,132 @@
+use csv::StringRecord;
+use gaia::record::GaiaRecord;
+use gaia::schema::{self, ColumnType, COLUMNS};
+use serde::{Deserialize, Serialize};
+
+/// Summary statistics of the values of one column.
+///
+/// `min`, `max` and `mean` are only recorded for numeric columns, and are
+/// `None` if the column has no values.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ColumnStats {
+    pub name: String,
+    /// Number of non-empty values.
+    pub count: u64,
+    /// Number of empty values.
+    pub nulls: u64,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    sum: f64,
+}
+
+impl ColumnStats {
+    pub fn new(name: &str) -> Self {
+        ColumnStats {
+            name: String::from(name),
+            count: 0,
+            nulls: 0,
+            min: None,
+            max: None,
+            sum: 0.0,
+        }
+    }
+
+    /// Add the text form of a value.
+    pub fn add(&mut self, text: &str) {
+        if text.is_empty() {
+            self.nulls += 1;
+            return;
+        }
+        self.count += 1;
+        if let Ok(value) = text.parse::<f64>() {
+            self.min = Some(self.min.map_or(value, |m| m.min(value)));
+            self.max = Some(self.max.map_or(value, |m| m.max(value)));
+            self.sum += value;
+        }
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        self.min.map(|_| self.sum / self.count as f64)
+    }
+}
+
+/// Statistics of every column of a stream of Gaia records.
+///
+/// ```
+/// # use starquad::gaia::stats::CatalogStats;
+/// let stats = CatalogStats::new();
+/// assert_eq!(stats.rows(), 0);
+/// assert!(stats.column("parallax").is_some());
+/// ```
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct CatalogStats {
+    columns: Vec<ColumnStats>,
+}
+
+impl CatalogStats {
+    pub fn new() -> Self {
+        CatalogStats {
+            columns: COLUMNS.iter().map(|c| ColumnStats::new(c.name)).collect(),
+        }
+    }
+
+    /// Number of rows added.
+    pub fn rows(&self) -> u64 {
+        self.columns.first().map_or(0, |c| c.count + c.nulls)
+    }
+
+    pub fn columns(&self) -> &[ColumnStats] {
+        &self.columns
+    }
+
+    pub fn column(&self, name: &str) -> Option<&ColumnStats> {
+        self.columns.iter().find(|c| c.name == name)
+    }
+
+    /// Only keep the statistics of some columns.
+    pub fn retain<F>(&mut self, keep: F)
+    where
+        F: FnMut(&ColumnStats) -> bool,
+    {
+        self.columns.retain(keep);
+    }
+
+    /// Add a row given the text fields of all Gaia columns, in the order of
+    /// `schema::COLUMNS`.
+    pub fn add_fields(&mut self, fields: &StringRecord) {
+        for ((stats, column), text) in self.columns.iter_mut().zip(COLUMNS).zip(fields.iter()) {
+            match column.column_type {
+                // values of text and boolean columns are only counted
+                ColumnType::Text | ColumnType::Boolean if !text.is_empty() => stats.count += 1,
+                _ => stats.add(text),
+            }
+        }
+    }
+
+    pub fn add(&mut self, record: &GaiaRecord) {
+        self.add_fields(&schema::string_record(record));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use gaia::record::test::sample_record;
+    use gaia::stats::CatalogStats;
+
+    #[test]
+    fn parallax() {
+        let mut stats = CatalogStats::new();
+        for parallax in [Some(1.0), None, Some(3.0), Some(-1.0)].iter() {
+            let mut record = sample_record();
+            record.parallax = *parallax;
+            stats.add(&record);
+        }
+        assert_eq!(stats.rows(), 4);
+        let parallax = stats.column("parallax").unwrap();
+        assert_eq!((parallax.count, parallax.nulls), (3, 1));
+        assert_eq!((parallax.min, parallax.max), (Some(-1.0), Some(3.0)));
+        assert_eq!(parallax.mean(), Some(1.0));
+        let designation = stats.column("designation").unwrap();
+        assert_eq!((designation.count, designation.min), (4, None));
+    }
+}
@@ -0,0 +1,207 @@
+use csv::StringRecord;
+use flate2::read::GzDecoder;
+use gaia::reader::csv_reader;
+use gaia::record::GaiaRecord;
+use gaia::schema;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::io::Read;
+use std::path::Path;
+
+/// Maximum number of row errors recorded in a report.
+pub const MAX_ERRORS: usize = 10;
+
+/// Name of the checksum file published alongside the Gaia CSV files.
+pub const MD5SUM_FILE: &str = "MD5SUM.txt";
+
+/// Result of checking a downloaded catalog file.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct FileReport {
+    pub path: String,
+    /// Checksum expected from `MD5SUM.txt`, if the file is listed there.
+    pub expected_md5: Option<String>,
+    pub md5: Option<String>,
+    /// Whether `md5` matches `expected_md5`, if both are known.
+    pub md5_ok: Option<bool>,
+    /// Error which stopped the file being read, such as a corrupt gzip
+    /// stream.
+    pub read_error: Option<String>,
+    pub missing_columns: Vec<String>,
+    pub extra_columns: Vec<String>,
+    pub rows: u64,
+    pub bad_rows: u64,
+    /// The first `MAX_ERRORS` row errors, with their line numbers.
+    pub row_errors: Vec<String>,
+    /// Whether no problems were found.
+    pub valid: bool,
+}
+
+impl FileReport {
+    fn md5_matches(&self) -> Option<bool> {
+        match (&self.expected_md5, &self.md5) {
+            (Some(expected), Some(actual)) => Some(expected.eq_ignore_ascii_case(actual)),
+            _ => None,
+        }
+    }
+
+    fn is_valid(&self) -> bool {
+        self.md5_matches() != Some(false)
+            && self.read_error.is_none()
+            && self.missing_columns.is_empty()
+            && self.extra_columns.is_empty()
+            && self.bad_rows == 0
+    }
+}
+
+/// Parse the lines `<md5>  <file name>` of an `MD5SUM.txt` file into a map
+/// from file name to checksum.
+pub fn parse_md5sums(text: &str) -> HashMap<String, String> {
+    text.lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let md5 = parts.next()?;
+            let name = parts.next()?.trim_start_matches('*');
+            Some((String::from(name), md5.to_lowercase()))
+        })
+        .collect()
+}
+
+/// Read the checksums of `MD5SUM.txt` in a directory, if there is one.
+pub fn read_md5sums(dir: &Path) -> io::Result<HashMap<String, String>> {
+    match fs::read_to_string(dir.join(MD5SUM_FILE)) {
+        Ok(text) => Ok(parse_md5sums(&text)),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Reader which computes the MD5 checksum of the bytes read through it.
+struct Md5Reader<R> {
+    reader: R,
+    context: md5::Context,
+}
+
+impl<R: Read> Read for Md5Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.context.consume(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Check a Gaia CSV file (gzipped if its name ends in `.gz`) in a single
+/// pass: its checksum against `expected_md5`, the integrity of its gzip
+/// stream, its header against the Gaia schema, and whether every row can be
+/// parsed as a `GaiaRecord`.
+pub fn validate_file(path: &Path, expected_md5: Option<&str>) -> io::Result<FileReport> {
+    let mut report = FileReport {
+        path: path.display().to_string(),
+        expected_md5: expected_md5.map(str::to_lowercase),
+        ..FileReport::default()
+    };
+    let mut hashed = Md5Reader {
+        reader: File::open(path)?,
+        context: md5::Context::new(),
+    };
+    let result = if path.extension().is_some_and(|e| e == "gz") {
+        check_rows(GzDecoder::new(&mut hashed), &mut report)
+    } else {
+        check_rows(&mut hashed, &mut report)
+    };
+    match result {
+        Ok(()) => {
+            // hash any bytes after the end of the gzip stream
+            io::copy(&mut hashed, &mut io::sink())?;
+            report.md5 = Some(format!("{:x}", hashed.context.compute()));
+        }
+        Err(e) => report.read_error = Some(e.to_string()),
+    }
+    report.md5_ok = report.md5_matches();
+    report.valid = report.is_valid();
+    Ok(report)
+}
+
+fn check_rows<R: Read>(reader: R, report: &mut FileReport) -> Result<(), csv::Error> {
+    let mut reader = csv_reader(reader);
+    let headers = reader.headers()?.clone();
+    let expected = schema::headers();
+    report.missing_columns = expected
+        .iter()
+        .filter(|name| !headers.iter().any(|h| h == *name))
+        .map(String::from)
+        .collect();
+    report.extra_columns = headers
+        .iter()
+        .filter(|name| !expected.iter().any(|e| e == *name))
+        .map(String::from)
+        .collect();
+    let mut record = StringRecord::new();
+    while reader.read_record(&mut record)? {
+        report.rows += 1;
+        if let Err(e) = record.deserialize::<GaiaRecord>(Some(&headers)) {
+            report.bad_rows += 1;
+            if report.row_errors.len() < MAX_ERRORS {
+                let line = record.position().map_or(0, |p| p.line());
+                report.row_errors.push(format!("line {}: {}", line, e));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use gaia::record::test::sample_record;
+    use gaia::validate::{parse_md5sums, validate_file};
+    use gaia::writer::GaiaWriter;
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+
+    fn gzipped_csv() -> Vec<u8> {
+        let mut writer = GaiaWriter::new(Vec::new()).unwrap();
+        writer.write(&sample_record()).unwrap();
+        let mut csv = writer.into_inner().unwrap();
+        // a row with an invalid ra
+        let row = String::from_utf8(csv.clone()).unwrap();
+        let row = row.lines().nth(1).unwrap().replacen(",0.0,", ",x,", 1);
+        csv.extend(row.as_bytes());
+        csv.push(b'\n');
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&csv).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn report() {
+        let bytes = gzipped_csv();
+        let path = env::temp_dir().join(format!("starquad-validate-{}.csv.gz", std::process::id()));
+        fs::write(&path, &bytes).unwrap();
+        let md5 = format!("{:x}", md5::compute(&bytes));
+
+        let report = validate_file(&path, Some(&md5)).unwrap();
+        assert_eq!(report.md5_ok, Some(true));
+        assert_eq!(report.read_error, None);
+        assert!(report.missing_columns.is_empty());
+        assert_eq!((report.rows, report.bad_rows), (2, 1));
+        assert!(report.row_errors[0].starts_with("line 3:"));
+        assert!(!report.valid);
+
+        // truncating the file breaks the gzip stream
+        fs::write(&path, &bytes[..bytes.len() - 10]).unwrap();
+        let report = validate_file(&path, Some(&md5)).unwrap();
+        assert!(report.read_error.is_some());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn md5sums() {
+        let sums = parse_md5sums("0123abcd  GaiaSource_1.csv.gz\nFFFF *GaiaSource_2.csv.gz\n\n");
+        assert_eq!(sums["GaiaSource_1.csv.gz"], "0123abcd");
+        assert_eq!(sums["GaiaSource_2.csv.gz"], "ffff");
+    }
+}
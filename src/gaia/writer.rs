@@ -0,0 +1,124 @@
+use csv::{Writer, WriterBuilder};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use gaia::record::GaiaRecord;
+use gaia::schema;
+use output::{Error, RecordSink};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+/// Writer of Gaia records in the Gaia `gaia_source` CSV format, so that a
+/// filtered subset can be read again with `gaia::reader`.
+///
+/// The header is written when the writer is created, so an empty subset is
+/// still a valid file. Empty (`None`) columns are written as empty fields.
+pub struct GaiaWriter<W: Write> {
+    writer: Writer<W>,
+}
+
+impl<W: Write> GaiaWriter<W> {
+    pub fn new(writer: W) -> Result<Self, Error> {
+        let mut writer = WriterBuilder::new().has_headers(false).from_writer(writer);
+        writer.write_record(&schema::headers())?;
+        Ok(GaiaWriter { writer })
+    }
+
+    pub fn write(&mut self, record: &GaiaRecord) -> Result<(), Error> {
+        self.writer.serialize(record)?;
+        Ok(())
+    }
+
+    /// Flush any buffered records and return the underlying writer.
+    pub fn into_inner(self) -> Result<W, Error> {
+        self.writer
+            .into_inner()
+            .map_err(|e| Error::from(e.into_error()))
+    }
+}
+
+impl<W: Write> RecordSink<GaiaRecord> for GaiaWriter<W> {
+    fn write(&mut self, record: &GaiaRecord) -> Result<(), Error> {
+        GaiaWriter::write(self, record)
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Create a gzipped Gaia CSV file, in the same format as the
+/// `GaiaSource_*.csv.gz` files. The file is complete once the writer has been
+/// finished with `into_inner` and the encoder has been finished.
+pub fn create_gz<P: AsRef<Path>>(path: P) -> Result<GaiaWriter<GzEncoder<File>>, Error> {
+    let file = File::create(path)?;
+    GaiaWriter::new(GzEncoder::new(file, Compression::default()))
+}
+
+/// Write all records to a gzipped Gaia CSV file.
+pub fn write_gz<P, I>(path: P, records: I) -> Result<(), Error>
+where
+    P: AsRef<Path>,
+    I: IntoIterator<Item = GaiaRecord>,
+{
+    let mut writer = create_gz(path)?;
+    for record in records {
+        writer.write(&record)?;
+    }
+    writer.into_inner()?.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use gaia::reader;
+    use gaia::record::test::sample_record;
+    use gaia::record::GaiaRecord;
+    use gaia::schema::COLUMNS;
+    use gaia::writer::GaiaWriter;
+    use quickcheck_macros::quickcheck;
+
+    fn round_trip(records: &[GaiaRecord]) -> (String, Vec<GaiaRecord>) {
+        let mut writer = GaiaWriter::new(Vec::new()).unwrap();
+        for record in records {
+            writer.write(record).unwrap();
+        }
+        let bytes = writer.into_inner().unwrap();
+        let read = reader::records(bytes.as_slice())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        (String::from_utf8(bytes).unwrap(), read)
+    }
+
+    #[test]
+    fn empty() {
+        let (text, read) = round_trip(&[]);
+        let names = COLUMNS.iter().map(|c| c.name).collect::<Vec<_>>();
+        assert_eq!(text, format!("{}\n", names.join(",")));
+        assert!(read.is_empty());
+    }
+
+    #[test]
+    fn nulls_are_empty_fields() {
+        let (text, _) = round_trip(&[sample_record()]);
+        let row = text.lines().nth(1).unwrap();
+        let parallax = COLUMNS.iter().position(|c| c.name == "parallax").unwrap();
+        assert_eq!(row.split(',').nth(parallax), Some(""));
+    }
+
+    /// Property test: records read back from their CSV are unchanged.
+    #[quickcheck]
+    fn records_round_trip(source_id: u64, ra: f64, parallax: Option<f64>, flags: Option<u64>) {
+        let mut record = sample_record();
+        record.source_id = source_id;
+        record.designation = format!("Gaia DR2 {}", source_id);
+        record.ra = ra;
+        record.parallax = parallax;
+        record.priam_flags = flags;
+        record.duplicated_source = source_id.is_multiple_of(2);
+        let records = vec![record.clone(), sample_record(), record];
+        let (_, read) = round_trip(&records);
+        assert_eq!(read, records);
+    }
+}
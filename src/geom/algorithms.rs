@@ -0,0 +1,229 @@
+//! Summaries of sets of points, such as the footprint of a query's results.
+
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use geom::delaunay::{circumradius_2, next_halfedge, Triangulation, NONE};
+use geom::p2::P2;
+use geom::polygon::Polygon;
+use num::traits::float::FloatCore;
+
+/// Twice the signed area of the triangle `o`, `a`, `b`.
+fn cross<S: FloatCore>(o: &P2<S>, a: &P2<S>, b: &P2<S>) -> S {
+    (a.x - o.x) * (b.y - o.y) - (a.y - o.y) * (b.x - o.x)
+}
+
+/// Convex hull of a set of points, counter-clockwise from the point with the
+/// least `x` (and then least `y`), without vertices in the middle of its
+/// edges.
+///
+/// Points with a `NaN` are ignored. The hull of fewer than three distinct
+/// points, or of points on one line, is the distinct points at its ends.
+///
+/// ```
+/// # use starquad::geom::algorithms::convex_hull;
+/// # use starquad::geom::p2::P2;
+/// let points = vec![P2::new(0.0, 0.0), P2::new(1.0, 1.0), P2::new(2.0, 0.0), P2::new(1.0, 3.0)];
+/// let hull = convex_hull(&points);
+/// assert_eq!(hull.vertices(), &[P2::new(0.0, 0.0), P2::new(2.0, 0.0), P2::new(1.0, 3.0)]);
+/// assert_eq!(hull.area(), 3.0);
+/// ```
+pub fn convex_hull<'a, S, I>(points: I) -> Polygon<S>
+where
+    S: FloatCore + 'a,
+    I: IntoIterator<Item = &'a P2<S>>,
+{
+    let mut points = points
+        .into_iter()
+        .filter(|point| !point.x.is_nan() && !point.y.is_nan())
+        .copied()
+        .collect::<Vec<_>>();
+    points.sort_unstable_by(|a, b| {
+        (a.x, a.y)
+            .partial_cmp(&(b.x, b.y))
+            .unwrap_or(Ordering::Equal)
+    });
+    points.dedup();
+    if points.len() < 3 {
+        return Polygon::new(points);
+    }
+
+    // Andrew's monotone chain: the lower hull left to right, then the upper
+    // hull right to left, each dropping points which do not turn left
+    let mut hull: Vec<P2<S>> = Vec::with_capacity(points.len() + 1);
+    for pass in 0..2 {
+        let floor = hull.len();
+        let mut chain = |point: &P2<S>| {
+            while hull.len() >= floor + 2
+                && cross(&hull[hull.len() - 2], &hull[hull.len() - 1], point) <= S::zero()
+            {
+                hull.pop();
+            }
+            hull.push(*point);
+        };
+        if pass == 0 {
+            points.iter().for_each(&mut chain);
+        } else {
+            points.iter().rev().for_each(&mut chain);
+        }
+        // the last point of each chain is the first of the other
+        hull.pop();
+    }
+    if hull.len() == 2 && hull[0] == hull[1] {
+        hull.pop();
+    }
+    Polygon::new(hull)
+}
+
+/// Alpha shape of a set of points with radius `alpha`: the boundary of the
+/// union of the triangles of their Delaunay triangulation whose
+/// circumcircles have radii less than `alpha`.
+///
+/// Unlike the convex hull, this follows concavities and holes in the set
+/// bigger than `alpha`, and separates clusters further apart than twice
+/// `alpha`. As `alpha` grows it becomes the convex hull.
+///
+/// Each boundary is a polygon: counter-clockwise around the shape, and
+/// clockwise around its holes, so the sum of their (signed) areas is the
+/// area of the shape. Points which are in no triangle small enough, and
+/// points which are not finite, are left out.
+///
+/// ```
+/// # use starquad::geom::algorithms::alpha_shape;
+/// # use starquad::geom::p2::P2;
+/// // a grid without its middle point, which leaves a hole of area 2
+/// let mut points = Vec::new();
+/// for i in 0..5 {
+///     for j in 0..5 {
+///         if (i, j) != (2, 2) {
+///             points.push(P2::new(i as f64, j as f64));
+///         }
+///     }
+/// }
+/// let shape = alpha_shape(&points, 0.75);
+/// assert_eq!(shape.len(), 2);
+/// assert_eq!(shape.iter().map(|polygon| polygon.area()).sum::<f64>(), 14.0);
+/// ```
+pub fn alpha_shape<'a, I>(points: I, alpha: f64) -> Vec<Polygon<f64>>
+where
+    I: IntoIterator<Item = &'a P2<f64>>,
+{
+    let triangulation = Triangulation::new(points.into_iter().copied().collect());
+    let points = triangulation.points();
+    let alpha_2 = alpha * alpha;
+    let kept = (0..triangulation.triangle_count())
+        .map(|t| {
+            let [a, b, c] = triangulation.triangle(t);
+            circumradius_2(&points[a], &points[b], &points[c]) < alpha_2
+        })
+        .collect::<Vec<_>>();
+    let on_boundary = |e: usize| {
+        let twin = triangulation.halfedges[e];
+        kept[e / 3] && (twin == NONE || !kept[twin / 3])
+    };
+
+    let mut done = vec![false; triangulation.triangles.len()];
+    let mut polygons = Vec::new();
+    for first in 0..triangulation.triangles.len() {
+        if done[first] || !on_boundary(first) {
+            continue;
+        }
+        let mut vertices = Vec::new();
+        let mut e = first;
+        while !done[e] {
+            done[e] = true;
+            vertices.push(points[triangulation.triangles[e]]);
+            // turn about the end of `e`, through the kept triangles, to the
+            // next edge of the boundary
+            e = next_halfedge(e);
+            while !on_boundary(e) {
+                e = next_halfedge(triangulation.halfedges[e]);
+            }
+        }
+        polygons.push(Polygon::new(vertices));
+    }
+    polygons
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::vec::Vec;
+    use geom::algorithms::{alpha_shape, convex_hull};
+    use geom::p2::P2;
+    use quickcheck_macros::quickcheck;
+
+    /// Property test: every point is inside or on the hull, which turns
+    /// left at every vertex.
+    #[quickcheck]
+    fn f64_convex_hull_contains_points(points: Vec<P2<f64>>) {
+        let points = points
+            .into_iter()
+            .filter(|point| point.x.is_finite() && point.y.is_finite())
+            .map(|point| P2::new(point.x % 1e3, point.y % 1e3))
+            .collect::<Vec<_>>();
+        let hull = convex_hull(&points);
+        let vertices = hull.vertices();
+        if vertices.len() < 3 {
+            return;
+        }
+        for i in 0..vertices.len() {
+            let (a, b) = (&vertices[i], &vertices[(i + 1) % vertices.len()]);
+            let c = &vertices[(i + 2) % vertices.len()];
+            assert!((b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x) > 0.0);
+            for point in points.iter() {
+                let side = (b.x - a.x) * (point.y - a.y) - (b.y - a.y) * (point.x - a.x);
+                assert!(side >= -1e-9, "{:?} outside {:?}", point, hull);
+            }
+        }
+    }
+
+    #[test]
+    fn degenerate_hulls() {
+        assert!(convex_hull(&[] as &[P2<f64>]).is_empty());
+        let same = [P2::new(1.0, 2.0), P2::new(1.0, 2.0)];
+        assert_eq!(convex_hull(&same).vertices(), &[P2::new(1.0, 2.0)]);
+        let line = [P2::new(2.0, 2.0), P2::new(0.0, 0.0), P2::new(1.0, 1.0)];
+        assert_eq!(
+            convex_hull(&line).vertices(),
+            &[P2::new(0.0, 0.0), P2::new(2.0, 2.0)]
+        );
+    }
+
+    /// Property test: with a large enough `alpha`, the alpha shape is the
+    /// convex hull.
+    #[quickcheck]
+    fn f64_alpha_shape_grows_to_convex_hull(points: Vec<(u8, u8)>) {
+        let points = points
+            .into_iter()
+            .map(|(x, y)| P2::new(f64::from(x), f64::from(y)))
+            .collect::<Vec<_>>();
+        let hull = convex_hull(&points).area();
+        let shape = alpha_shape(&points, 1e9);
+        assert!(shape.len() <= 1);
+        let area = shape.iter().map(|polygon| polygon.area()).sum::<f64>();
+        assert!(
+            (area - hull).abs() <= 1e-6 * hull.max(1.0),
+            "{} {}",
+            area,
+            hull
+        );
+    }
+
+    #[test]
+    fn alpha_shape_separates_clusters() {
+        let mut points = Vec::new();
+        for offset in [0.0, 10.0].iter() {
+            for i in 0..5 {
+                for j in 0..5 {
+                    points.push(P2::new(offset + f64::from(i), f64::from(j)));
+                }
+            }
+        }
+        let shape = alpha_shape(&points, 2.0);
+        let areas = shape
+            .iter()
+            .map(|polygon| polygon.area())
+            .collect::<Vec<_>>();
+        assert_eq!(areas, vec![16.0, 16.0]);
+        assert!(alpha_shape(&points, 0.5).is_empty());
+    }
+}
@@ -0,0 +1,806 @@
+//! Delaunay triangulations, and the Voronoi diagrams they are dual to.
+
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use geom::p2::P2;
+use geom::polygon::Polygon;
+use num::traits::float::FloatCore;
+
+/// Marks a half-edge without a twin, on the convex hull.
+pub(crate) const NONE: usize = usize::MAX;
+
+/// Delaunay triangulation of a set of points: the triangulation in which no
+/// point is inside the circumcircle of any triangle.
+///
+/// Its triangles join each point to its neighbours in the Voronoi diagram,
+/// whose cell around a point is the region nearer to it than to any other
+/// point. The area of a point's cell estimates the inverse of the density
+/// of points around it, which is how clusters are found in Voronoi
+/// tessellation methods.
+///
+/// The triangulation is built by sweeping a circle out from a seed triangle
+/// and adding points to the hull in order of their distance from its centre,
+/// flipping edges until every triangle's circumcircle is empty, which takes
+/// about a second for a million points. Points which are not finite, or
+/// which duplicate an earlier point, are left out of it, and there are no
+/// triangles if every point is on one line.
+///
+/// ```
+/// # use starquad::geom::delaunay::Triangulation;
+/// # use starquad::geom::p2::P2;
+/// // a 4 by 4 lattice, with alternate rows shifted along a little
+/// let mut points = Vec::new();
+/// for i in 0..4 {
+///     for j in 0..4 {
+///         points.push(P2::new(i as f64 + 0.1 * (j % 2) as f64, j as f64));
+///     }
+/// }
+/// let triangulation = Triangulation::new(points);
+/// assert_eq!(triangulation.triangle_count(), 20);
+/// assert_eq!(triangulation.neighbors(5), vec![1, 4, 6, 8, 9, 10]);
+/// // the cell of an inner point is bounded, and those on the hull are not
+/// let area = triangulation.voronoi_cell(5).unwrap().area();
+/// assert!((area - 1.0).abs() < 1e-9);
+/// assert_eq!(triangulation.voronoi_cell(0), None);
+/// assert_eq!(triangulation.natural_neighbors(&P2::new(1.5, 1.5)), vec![5, 6, 9, 10]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Triangulation {
+    points: Vec<P2<f64>>,
+    /// Vertex at the start of each half-edge. Half-edge `e` goes from it to
+    /// the next vertex of triangle `e / 3`, whose vertices go
+    /// counter-clockwise.
+    pub(crate) triangles: Vec<usize>,
+    /// Half-edge going the other way in the neighbouring triangle, or
+    /// `NONE` on the hull.
+    pub(crate) halfedges: Vec<usize>,
+    /// Half-edge ending at each vertex, on the hull if the vertex is, or
+    /// `NONE` if the vertex is in no triangle.
+    inedges: Vec<usize>,
+}
+
+/// Where a point is in a triangulation.
+enum Location {
+    /// In the triangle with this index.
+    Inside(usize),
+    /// Outside the hull, beyond the hull half-edge with this index.
+    Outside(usize),
+}
+
+/// Triangle of a triangulation, or the region outside the hull beyond a
+/// hull half-edge, treated as a triangle with a vertex at infinity whose
+/// circumcircle is the half-plane beyond the edge.
+#[derive(Clone, Copy)]
+enum Face {
+    Triangle(usize),
+    Ghost(usize),
+}
+
+/// Next half-edge around the same triangle.
+pub(crate) fn next_halfedge(e: usize) -> usize {
+    if e % 3 == 2 {
+        e - 2
+    } else {
+        e + 1
+    }
+}
+
+/// Previous half-edge around the same triangle.
+pub(crate) fn prev_halfedge(e: usize) -> usize {
+    if e.is_multiple_of(3) {
+        e + 2
+    } else {
+        e - 1
+    }
+}
+
+/// Twice the signed area of a triangle: positive if its vertices go
+/// counter-clockwise.
+fn orient(a: &P2<f64>, b: &P2<f64>, c: &P2<f64>) -> f64 {
+    (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+}
+
+/// Determinant which is positive if `p` is inside the circumcircle of the
+/// counter-clockwise triangle `a`, `b`, `c`, and negative if it is outside.
+fn in_circle_det(a: &P2<f64>, b: &P2<f64>, c: &P2<f64>, p: &P2<f64>) -> f64 {
+    let (dx, dy) = (a.x - p.x, a.y - p.y);
+    let (ex, ey) = (b.x - p.x, b.y - p.y);
+    let (fx, fy) = (c.x - p.x, c.y - p.y);
+    let (ap, bp, cp) = (dx * dx + dy * dy, ex * ex + ey * ey, fx * fx + fy * fy);
+    dx * (ey * cp - bp * fy) - dy * (ex * cp - bp * fx) + ap * (ex * fy - ey * fx)
+}
+
+/// Whether `p` is strictly inside the circumcircle of the counter-clockwise
+/// triangle `a`, `b`, `c`.
+fn in_circle(a: &P2<f64>, b: &P2<f64>, c: &P2<f64>, p: &P2<f64>) -> bool {
+    in_circle_det(a, b, c, p) > 0.0
+}
+
+/// Offset of the circumcentre of a triangle from `a`, which is infinite or
+/// `NaN` if its vertices are on one line.
+fn circumcentre_offset(a: &P2<f64>, b: &P2<f64>, c: &P2<f64>) -> P2<f64> {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let (ex, ey) = (c.x - a.x, c.y - a.y);
+    let (bl, cl) = (dx * dx + dy * dy, ex * ex + ey * ey);
+    let d = 0.5 / (dx * ey - dy * ex);
+    P2::new((ey * bl - dy * cl) * d, (dx * cl - ex * bl) * d)
+}
+
+/// Circumcentre of a triangle.
+fn circumcentre(a: &P2<f64>, b: &P2<f64>, c: &P2<f64>) -> P2<f64> {
+    let offset = circumcentre_offset(a, b, c);
+    P2::new(a.x + offset.x, a.y + offset.y)
+}
+
+/// Square of the circumradius of a triangle, which is infinite or `NaN` if
+/// its vertices are on one line.
+pub(crate) fn circumradius_2(a: &P2<f64>, b: &P2<f64>, c: &P2<f64>) -> f64 {
+    circumcentre_offset(a, b, c).distance_2(&P2::new(0.0, 0.0))
+}
+
+/// Monotonic stand-in for the angle of a vector, in `[0, 1]`.
+fn pseudo_angle(dx: f64, dy: f64) -> f64 {
+    let p = dx / (FloatCore::abs(dx) + FloatCore::abs(dy));
+    if dy > 0.0 {
+        (3.0 - p) / 4.0
+    } else {
+        (1.0 + p) / 4.0
+    }
+}
+
+/// State of the sweep, while the triangulation is built.
+struct Sweep<'a> {
+    points: &'a [P2<f64>],
+    triangles: Vec<usize>,
+    halfedges: Vec<usize>,
+    centre: P2<f64>,
+    /// Hull as a circular list of vertices, with a removed vertex being its
+    /// own next.
+    hull_next: Vec<usize>,
+    hull_prev: Vec<usize>,
+    /// Half-edge of the hull going out of each of its vertices.
+    hull_tri: Vec<usize>,
+    hull_start: usize,
+    /// Vertices of the hull, by the angle at which they are seen from the
+    /// centre, to start the search for the hull edges a point can see.
+    hull_hash: Vec<usize>,
+    edge_stack: Vec<usize>,
+}
+
+impl<'a> Sweep<'a> {
+    fn hash_key(&self, point: &P2<f64>) -> usize {
+        let angle = pseudo_angle(point.x - self.centre.x, point.y - self.centre.y);
+        (angle * self.hull_hash.len() as f64) as usize % self.hull_hash.len()
+    }
+
+    fn link(&mut self, a: usize, b: usize) {
+        self.halfedges[a] = b;
+        if b != NONE {
+            self.halfedges[b] = a;
+        }
+    }
+
+    /// Add a triangle, linking its half-edges to their twins, and return its
+    /// first half-edge.
+    fn add_triangle(&mut self, vertices: [usize; 3], twins: [usize; 3]) -> usize {
+        let t = self.triangles.len();
+        self.triangles.extend_from_slice(&vertices);
+        self.halfedges.extend_from_slice(&[NONE; 3]);
+        for (i, twin) in twins.iter().enumerate() {
+            self.link(t + i, *twin);
+        }
+        t
+    }
+
+    /// Flip edges from half-edge `a` outwards until the triangles on both
+    /// sides of each have empty circumcircles, and return the half-edge
+    /// which ends where `a` started, after the flips.
+    fn legalize(&mut self, mut a: usize) -> usize {
+        //        pl                  pl
+        //       /||\                /  \
+        //    al/ || \bl          al/    \a
+        //     /  ||  \            /      \
+        //    /  a||b  \   flip   /___ar___\
+        //  p0\   ||   /p1   =>  p0\---bl---/p1
+        //     \  ||  /            \      /
+        //    ar\ || /br           b\    /br
+        //       \||/                \  /
+        //        pr                  pr
+        let mut ar;
+        loop {
+            let b = self.halfedges[a];
+            ar = prev_halfedge(a);
+            if b == NONE {
+                match self.edge_stack.pop() {
+                    Some(next) => a = next,
+                    None => break,
+                }
+                continue;
+            }
+            let al = next_halfedge(a);
+            let bl = prev_halfedge(b);
+            let (p0, pr, pl, p1) = (
+                self.triangles[ar],
+                self.triangles[a],
+                self.triangles[al],
+                self.triangles[bl],
+            );
+            let points = self.points;
+            if in_circle(&points[p0], &points[pr], &points[pl], &points[p1]) {
+                self.triangles[a] = p1;
+                self.triangles[b] = p0;
+                let hbl = self.halfedges[bl];
+                if hbl == NONE {
+                    // the flipped edge was on the hull, whose reference to
+                    // it must follow it
+                    let mut e = self.hull_start;
+                    loop {
+                        if self.hull_tri[e] == bl {
+                            self.hull_tri[e] = a;
+                            break;
+                        }
+                        e = self.hull_prev[e];
+                        if e == self.hull_start {
+                            break;
+                        }
+                    }
+                }
+                self.link(a, hbl);
+                let har = self.halfedges[ar];
+                self.link(b, har);
+                self.link(ar, bl);
+                self.edge_stack.push(next_halfedge(b));
+            } else {
+                match self.edge_stack.pop() {
+                    Some(next) => a = next,
+                    None => break,
+                }
+            }
+        }
+        ar
+    }
+
+    /// Add a point outside the hull, or do nothing if it cannot see any of
+    /// the hull's edges (when it is on the hull already).
+    fn add(&mut self, i: usize) {
+        let points = self.points;
+        let point = &points[i];
+        let key = self.hash_key(point);
+        let len = self.hull_hash.len();
+        let mut start = 0;
+        for j in 0..len {
+            start = self.hull_hash[(key + j) % len];
+            if start != NONE && start != self.hull_next[start] {
+                break;
+            }
+        }
+        start = self.hull_prev[start];
+        let mut e = start;
+        loop {
+            let q = self.hull_next[e];
+            if orient(point, &points[e], &points[q]) < 0.0 {
+                break;
+            }
+            e = q;
+            if e == start {
+                return;
+            }
+        }
+
+        let t = self.add_triangle([e, i, self.hull_next[e]], [NONE, NONE, self.hull_tri[e]]);
+        self.hull_tri[i] = self.legalize(t + 2);
+        self.hull_tri[e] = t;
+
+        let mut n = self.hull_next[e];
+        loop {
+            let q = self.hull_next[n];
+            if orient(point, &points[n], &points[q]) >= 0.0 {
+                break;
+            }
+            let t = self.add_triangle([n, i, q], [self.hull_tri[i], NONE, self.hull_tri[n]]);
+            self.hull_tri[i] = self.legalize(t + 2);
+            self.hull_next[n] = n;
+            n = q;
+        }
+        if e == start {
+            loop {
+                let q = self.hull_prev[e];
+                if orient(point, &points[q], &points[e]) >= 0.0 {
+                    break;
+                }
+                let t = self.add_triangle([q, i, e], [NONE, self.hull_tri[e], self.hull_tri[q]]);
+                self.legalize(t + 2);
+                self.hull_tri[q] = t;
+                self.hull_next[e] = e;
+                e = q;
+            }
+        }
+
+        self.hull_start = e;
+        self.hull_prev[i] = e;
+        self.hull_next[e] = i;
+        self.hull_prev[n] = i;
+        self.hull_next[i] = n;
+        let key = self.hash_key(point);
+        self.hull_hash[key] = i;
+        let key = self.hash_key(&points[e]);
+        self.hull_hash[key] = e;
+    }
+}
+
+/// Triangles and half-edges of the Delaunay triangulation of `points`.
+fn triangulate(points: &[P2<f64>]) -> (Vec<usize>, Vec<usize>) {
+    let finite = (0..points.len())
+        .filter(|&i| points[i].x.is_finite() && points[i].y.is_finite())
+        .collect::<Vec<_>>();
+    let empty = (Vec::new(), Vec::new());
+    let (min_x, min_y, max_x, max_y) = finite.iter().fold(
+        (
+            f64::INFINITY,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::NEG_INFINITY,
+        ),
+        |(min_x, min_y, max_x, max_y), &i| {
+            let p = &points[i];
+            (
+                min_x.min(p.x),
+                min_y.min(p.y),
+                max_x.max(p.x),
+                max_y.max(p.y),
+            )
+        },
+    );
+    let middle = P2::new((min_x + max_x) / 2.0, (min_y + max_y) / 2.0);
+    // point nearest to `to`, or to it but not at it if `other`
+    let nearest_to = |to: &P2<f64>, other: bool| {
+        finite
+            .iter()
+            .filter(|i| !other || points[**i] != *to)
+            .min_by(|a, b| {
+                let (a, b) = (points[**a].distance_2(to), points[**b].distance_2(to));
+                a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+            })
+            .copied()
+    };
+
+    // seed triangle, of the point nearest the middle, the point nearest
+    // to that, and the point making the smallest circumcircle with them
+    let (i0, i1) = match nearest_to(&middle, false) {
+        Some(i0) => match nearest_to(&points[i0], true) {
+            Some(i1) => (i0, i1),
+            None => return empty,
+        },
+        None => return empty,
+    };
+    let (p0, p1) = (&points[i0], &points[i1]);
+    let seed = finite
+        .iter()
+        .filter(|&&i| i != i0 && i != i1)
+        .map(|&i| (circumradius_2(p0, p1, &points[i]), i))
+        .filter(|(r, _)| r.is_finite())
+        .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+    let mut i2 = match seed {
+        Some((_, i2)) => i2,
+        None => return empty,
+    };
+    let mut i1 = i1;
+    if orient(&points[i0], &points[i1], &points[i2]) < 0.0 {
+        core::mem::swap(&mut i1, &mut i2);
+    }
+    let centre = circumcentre(&points[i0], &points[i1], &points[i2]);
+
+    let mut order = finite;
+    let distances = (0..points.len())
+        .map(|i| points[i].distance_2(&centre))
+        .collect::<Vec<_>>();
+    order.sort_unstable_by(|a, b| {
+        distances[*a]
+            .partial_cmp(&distances[*b])
+            .unwrap_or(Ordering::Equal)
+    });
+
+    let mut hash_len = 1;
+    while hash_len * hash_len < order.len() {
+        hash_len += 1;
+    }
+    let mut sweep = Sweep {
+        points,
+        triangles: Vec::with_capacity(order.len() * 6),
+        halfedges: Vec::with_capacity(order.len() * 6),
+        centre,
+        hull_next: vec![NONE; points.len()],
+        hull_prev: vec![NONE; points.len()],
+        hull_tri: vec![NONE; points.len()],
+        hull_start: i0,
+        hull_hash: vec![NONE; hash_len],
+        edge_stack: Vec::new(),
+    };
+    sweep.hull_next[i0] = i1;
+    sweep.hull_prev[i2] = i1;
+    sweep.hull_next[i1] = i2;
+    sweep.hull_prev[i0] = i2;
+    sweep.hull_next[i2] = i0;
+    sweep.hull_prev[i1] = i0;
+    for (t, &i) in [i0, i1, i2].iter().enumerate() {
+        sweep.hull_tri[i] = t;
+        let key = sweep.hash_key(&points[i]);
+        sweep.hull_hash[key] = i;
+    }
+    sweep.add_triangle([i0, i1, i2], [NONE; 3]);
+
+    let mut previous: Option<&P2<f64>> = None;
+    for &i in order.iter() {
+        let point = &points[i];
+        if previous == Some(point) {
+            continue;
+        }
+        previous = Some(point);
+        if i != i0 && i != i1 && i != i2 {
+            sweep.add(i);
+        }
+    }
+
+    (sweep.triangles, sweep.halfedges)
+}
+
+impl Triangulation {
+    pub fn new(points: Vec<P2<f64>>) -> Self {
+        let (triangles, halfedges) = triangulate(&points);
+        let mut inedges = vec![NONE; points.len()];
+        for (e, twin) in halfedges.iter().enumerate() {
+            let end = triangles[next_halfedge(e)];
+            if *twin == NONE || inedges[end] == NONE {
+                inedges[end] = e;
+            }
+        }
+        Triangulation {
+            points,
+            triangles,
+            halfedges,
+            inedges,
+        }
+    }
+
+    /// Points triangulated, including any left out of the triangulation.
+    pub fn points(&self) -> &[P2<f64>] {
+        &self.points
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.triangles.len() / 3
+    }
+
+    /// Vertices of a triangle, counter-clockwise.
+    pub fn triangle(&self, t: usize) -> [usize; 3] {
+        [
+            self.triangles[3 * t],
+            self.triangles[3 * t + 1],
+            self.triangles[3 * t + 2],
+        ]
+    }
+
+    /// Half-edges ending at a point, clockwise around it, from the hull
+    /// edge ending at it if it is on the hull. The last of them is followed
+    /// by the hull edge out of the point.
+    fn inedges_around(&self, point: usize) -> impl Iterator<Item = usize> + '_ {
+        let first = self.inedges[point];
+        let mut next = if first == NONE { None } else { Some(first) };
+        core::iter::from_fn(move || {
+            let e = next?;
+            let twin = self.halfedges[next_halfedge(e)];
+            next = if twin == NONE || twin == first {
+                None
+            } else {
+                Some(twin)
+            };
+            Some(e)
+        })
+    }
+
+    /// Whether a point is on the hull (and in the triangulation).
+    fn on_hull(&self, point: usize) -> bool {
+        let e = self.inedges[point];
+        e != NONE && self.halfedges[e] == NONE
+    }
+
+    /// Points joined to a point by an edge of the triangulation, which are
+    /// its neighbours in the Voronoi diagram, in ascending order.
+    pub fn neighbors(&self, point: usize) -> Vec<usize> {
+        let mut neighbors = self
+            .inedges_around(point)
+            .map(|e| self.triangles[e])
+            .collect::<Vec<_>>();
+        if self.on_hull(point) {
+            // the end of the hull edge out of the point
+            if let Some(last) = self.inedges_around(point).last() {
+                neighbors.push(self.triangles[prev_halfedge(last)]);
+            }
+        }
+        neighbors.sort_unstable();
+        neighbors
+    }
+
+    /// Voronoi cell of a point: the region nearer to it than to any other
+    /// point, with vertices at the circumcentres of the triangles around it.
+    ///
+    /// Returns `None` if the cell is unbounded, which it is for points on the
+    /// hull, or if the point is not in the triangulation. Points which
+    /// rounding has put just inside the hull, in a flat triangle, have cells
+    /// with vertices far away or turned inside out, and these are also
+    /// treated as unbounded.
+    pub fn voronoi_cell(&self, point: usize) -> Option<Polygon<f64>> {
+        if self.inedges[point] == NONE || self.on_hull(point) {
+            return None;
+        }
+        let mut vertices = self
+            .inedges_around(point)
+            .map(|e| {
+                let [a, b, c] = self.triangle(e / 3);
+                circumcentre(&self.points[a], &self.points[b], &self.points[c])
+            })
+            .collect::<Vec<_>>();
+        if vertices
+            .iter()
+            .any(|v| !v.x.is_finite() || !v.y.is_finite())
+        {
+            return None;
+        }
+        vertices.reverse();
+        let cell = Polygon::new(vertices);
+        if cell.area() > 0.0 {
+            Some(cell)
+        } else {
+            None
+        }
+    }
+
+    fn orient_halfedge(&self, e: usize, point: &P2<f64>) -> f64 {
+        let (a, b) = (self.triangles[e], self.triangles[next_halfedge(e)]);
+        orient(&self.points[a], &self.points[b], point)
+    }
+
+    /// Triangle containing a point, or the hull edge it is beyond, found by
+    /// walking across the triangles towards it.
+    fn locate(&self, point: &P2<f64>) -> Option<Location> {
+        let crossed = |t: usize| (3 * t..3 * t + 3).find(|e| self.orient_halfedge(*e, point) < 0.0);
+        let count = self.triangle_count();
+        let mut t = 0;
+        for _ in 0..count {
+            match crossed(t) {
+                None => return Some(Location::Inside(t)),
+                Some(e) if self.halfedges[e] == NONE => return Some(Location::Outside(e)),
+                Some(e) => t = self.halfedges[e] / 3,
+            }
+        }
+        // the walk went round in circles, which rounding can make it do
+        (0..count)
+            .find(|t| crossed(*t).is_none())
+            .map(Location::Inside)
+            .or_else(|| {
+                (0..self.halfedges.len())
+                    .find(|e| self.halfedges[*e] == NONE && self.orient_halfedge(*e, point) < 0.0)
+                    .map(Location::Outside)
+            })
+    }
+
+    /// Faces sharing an edge or, for those outside the hull, a vertex at
+    /// infinity with a face.
+    fn adjacent_faces(&self, face: Face) -> [Option<Face>; 3] {
+        match face {
+            Face::Triangle(t) => [0, 1, 2].map(|i| {
+                let e = 3 * t + i;
+                Some(match self.halfedges[e] {
+                    NONE => Face::Ghost(e),
+                    twin => Face::Triangle(twin / 3),
+                })
+            }),
+            Face::Ghost(e) => {
+                let mut next = next_halfedge(e);
+                while self.halfedges[next] != NONE {
+                    next = next_halfedge(self.halfedges[next]);
+                }
+                let mut prev = prev_halfedge(e);
+                while self.halfedges[prev] != NONE {
+                    prev = prev_halfedge(self.halfedges[prev]);
+                }
+                [
+                    Some(Face::Triangle(e / 3)),
+                    Some(Face::Ghost(next)),
+                    Some(Face::Ghost(prev)),
+                ]
+            }
+        }
+    }
+
+    /// Whether a point is strictly inside the circumcircle of a face.
+    fn in_face_circle(&self, face: Face, point: &P2<f64>) -> bool {
+        match face {
+            Face::Triangle(t) => {
+                let [a, b, c] = self.triangle(t);
+                in_circle(&self.points[a], &self.points[b], &self.points[c], point)
+            }
+            Face::Ghost(e) => self.orient_halfedge(e, point) < 0.0,
+        }
+    }
+
+    /// Natural neighbours of a point: the points whose Voronoi cells would
+    /// lose area to it if it were added to the triangulation, in ascending
+    /// order. These are the vertices of the triangles whose circumcircles
+    /// contain it, and the ends of the hull edges it is beyond.
+    ///
+    /// A point already in the triangulation has its Voronoi neighbours as
+    /// its natural neighbours. Points in a triangulation without triangles
+    /// have none.
+    pub fn natural_neighbors(&self, point: &P2<f64>) -> Vec<usize> {
+        let first = match self.locate(point) {
+            Some(Location::Inside(t)) => {
+                if let Some(vertex) = self.triangle(t).iter().find(|v| self.points[**v] == *point) {
+                    return self.neighbors(*vertex);
+                }
+                Face::Triangle(t)
+            }
+            Some(Location::Outside(e)) => Face::Ghost(e),
+            None => return Vec::new(),
+        };
+        let mut seen_triangles = vec![false; self.triangle_count()];
+        let mut seen_ghosts = vec![false; self.halfedges.len()];
+        let mut neighbors = Vec::new();
+        let mut stack = vec![first];
+        while let Some(face) = stack.pop() {
+            let seen = match face {
+                Face::Triangle(t) => &mut seen_triangles[t],
+                Face::Ghost(e) => &mut seen_ghosts[e],
+            };
+            if *seen {
+                continue;
+            }
+            *seen = true;
+            match face {
+                Face::Triangle(t) => neighbors.extend_from_slice(&self.triangle(t)),
+                Face::Ghost(e) => {
+                    neighbors.push(self.triangles[e]);
+                    neighbors.push(self.triangles[next_halfedge(e)]);
+                }
+            }
+            for adjacent in self.adjacent_faces(face).iter().flatten() {
+                if self.in_face_circle(*adjacent, point) {
+                    stack.push(*adjacent);
+                }
+            }
+        }
+        neighbors.sort_unstable();
+        neighbors.dedup();
+        neighbors
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::vec::Vec;
+    use geom::delaunay::{in_circle, in_circle_det, next_halfedge, orient, Triangulation, NONE};
+    use geom::p2::P2;
+    use quickcheck_macros::quickcheck;
+
+    /// Check that a triangulation's half-edges are consistent, that no point
+    /// is in a triangle's circumcircle (up to a tolerance relative to the
+    /// square of its size), that it has as many triangles as any
+    /// triangulation of its points, and that its points' neighbours and
+    /// Voronoi cells follow from its triangles.
+    fn check(points: &[P2<f64>], tolerance: f64) -> Triangulation {
+        let triangulation = Triangulation::new(points.to_vec());
+        let mut distinct = points.to_vec();
+        distinct.sort_by(|a, b| (a.x, a.y).partial_cmp(&(b.x, b.y)).unwrap());
+        distinct.dedup();
+        for (e, twin) in triangulation.halfedges.iter().enumerate() {
+            if *twin != NONE {
+                assert_eq!(triangulation.halfedges[*twin], e);
+                assert_eq!(
+                    triangulation.triangles[next_halfedge(e)],
+                    triangulation.triangles[*twin]
+                );
+            }
+        }
+        for t in 0..triangulation.triangle_count() {
+            let [a, b, c] = triangulation.triangle(t);
+            for point in distinct.iter() {
+                let det = in_circle_det(&points[a], &points[b], &points[c], point);
+                let size = points[a].distance_2(point) + points[b].distance_2(point);
+                assert!(det <= tolerance * size * size);
+            }
+        }
+        if triangulation.triangle_count() > 0 {
+            // Euler's formula, counting hull vertices in the middle of edges
+            let on_hull = triangulation.halfedges.iter().filter(|e| **e == NONE);
+            assert_eq!(
+                triangulation.triangle_count(),
+                2 * distinct.len() - on_hull.count() - 2
+            );
+        }
+
+        for (v, site) in points.iter().enumerate() {
+            let mut neighbors = (0..triangulation.triangle_count())
+                .map(|t| triangulation.triangle(t))
+                .filter(|triangle| triangle.contains(&v))
+                .flat_map(|triangle| triangle.to_vec())
+                .filter(|w| *w != v)
+                .collect::<Vec<_>>();
+            neighbors.sort_unstable();
+            neighbors.dedup();
+            assert_eq!(triangulation.neighbors(v), neighbors);
+
+            if let Some(cell) = triangulation.voronoi_cell(v) {
+                for vertex in cell.vertices() {
+                    let distance_2 = vertex.distance_2(site);
+                    for other in points.iter() {
+                        assert!(distance_2 <= other.distance_2(vertex) * (1.0 + 1e-9) + 1e-9);
+                    }
+                }
+            }
+        }
+        triangulation
+    }
+
+    #[quickcheck]
+    fn grid_triangulations_are_delaunay(points: Vec<(u8, u8)>) {
+        let points = points
+            .into_iter()
+            .map(|(x, y)| P2::new(f64::from(x % 16), f64::from(y % 16)))
+            .collect::<Vec<_>>();
+        check(&points, 0.0);
+    }
+
+    /// Property test: the natural neighbours of a point are the vertices of
+    /// the triangles whose circumcircles contain it, and the ends of the hull
+    /// edges it is beyond.
+    #[quickcheck]
+    fn grid_natural_neighbors_match_brute_force(points: Vec<(u8, u8)>, queries: Vec<(u8, u8)>) {
+        let points = points
+            .into_iter()
+            .map(|(x, y)| P2::new(f64::from(x % 16), f64::from(y % 16)))
+            .collect::<Vec<_>>();
+        let triangulation = Triangulation::new(points.clone());
+        if triangulation.triangle_count() == 0 {
+            return;
+        }
+        for (x, y) in queries {
+            let query = P2::new(f64::from(x % 80) / 4.0 - 2.0, f64::from(y % 80) / 4.0 - 2.0);
+            let mut expected = Vec::new();
+            if let Some(v) =
+                (0..points.len()).find(|v| points[*v] == query && triangulation.inedges[*v] != NONE)
+            {
+                expected = triangulation.neighbors(v);
+            } else {
+                for (e, twin) in triangulation.halfedges.iter().enumerate() {
+                    let [a, b, c] = triangulation.triangle(e / 3);
+                    let (start, end) = (
+                        triangulation.triangles[e],
+                        triangulation.triangles[next_halfedge(e)],
+                    );
+                    if in_circle(&points[a], &points[b], &points[c], &query)
+                        || (*twin == NONE && orient(&points[start], &points[end], &query) < 0.0)
+                    {
+                        expected.extend_from_slice(&[start, end]);
+                    }
+                }
+                expected.sort_unstable();
+                expected.dedup();
+            }
+            assert_eq!(
+                triangulation.natural_neighbors(&query),
+                expected,
+                "{:?}",
+                query
+            );
+        }
+    }
+
+    #[quickcheck]
+    fn f64_triangulations_are_delaunay(points: Vec<(u16, u16)>) {
+        let points = points
+            .into_iter()
+            .map(|(x, y)| P2::new(f64::from(x) / 7.0, f64::from(y) / 3.0))
+            .collect::<Vec<_>>();
+        check(&points, 1e-12);
+    }
+}
@@ -0,0 +1,204 @@
+//! Conversions to and from the [geo-types](https://docs.rs/geo-types) crate
+//! (whose types the `geo` crate re-exports), built with the `geo-types`
+//! feature.
+//!
+//! A `Rect` excludes its upper edges while a `geo_types::Rect` includes them,
+//! so converting to `geo_types` gives the smallest closed rectangle around
+//! the `Rect`, and converting back gives the smallest `Rect` around that:
+//!
+//! ```
+//! # use std::convert::TryFrom;
+//! # use starquad::geom::{p2::P2, rect::Rect};
+//! let rect = Rect::new(1.0, 2.0, 3.0, 4.0).unwrap();
+//! let geo_rect = geo_types::Rect::from(&rect);
+//! assert_eq!(geo_rect.max(), geo_types::coord! { x: 4.0, y: 6.0 });
+//! let back = Rect::try_from(geo_rect).unwrap();
+//! assert!(back.contains(&P2::new(4.0, 6.0)) && !rect.contains(&P2::new(4.0, 6.0)));
+//! ```
+//!
+//! A `geo_types::Polygon` without holes converts to a `Polygon`, which can
+//! be queried with `Accel2D::query_polygon` (or, in `(ra, dec)`, made a
+//! `Region::Polygon`):
+//!
+//! ```
+//! # #[macro_use]
+//! # extern crate geo_types;
+//! # use std::convert::TryFrom;
+//! # use starquad::accel2d::Accel2D;
+//! # use starquad::accel2d::quadtree::QuadTree;
+//! # use starquad::geom::{p2::P2, polygon::Polygon};
+//! let tree = QuadTree::new_from_vec((0..100).map(|i| (P2::new(f64::from(i % 10), f64::from(i / 10)), i)).collect());
+//! let triangle = polygon![(x: 0.0, y: 0.0), (x: 2.5, y: 0.0), (x: 0.0, y: 2.5)];
+//! let found = tree.query_polygon(&Polygon::try_from(&triangle).unwrap());
+//! let mut found = found.iter().map(|(_, i)| *i).collect::<Vec<_>>();
+//! found.sort();
+//! assert_eq!(found, vec![0, 1, 2, 10, 11, 20]);
+//! ```
+
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use geo_types::{Coord, CoordNum, LineString, Point};
+use geom::interval::IntervalDomain;
+use geom::p2::P2;
+use geom::polygon::Polygon;
+use geom::rect::Rect;
+use geom::Error;
+
+impl<T: CoordNum> From<P2<T>> for Coord<T> {
+    fn from(point: P2<T>) -> Self {
+        Coord {
+            x: point.x,
+            y: point.y,
+        }
+    }
+}
+
+impl<T: CoordNum> From<Coord<T>> for P2<T> {
+    fn from(coord: Coord<T>) -> Self {
+        P2::new(coord.x, coord.y)
+    }
+}
+
+impl<T: CoordNum> From<P2<T>> for Point<T> {
+    fn from(point: P2<T>) -> Self {
+        Point::new(point.x, point.y)
+    }
+}
+
+impl<T: CoordNum> From<Point<T>> for P2<T> {
+    fn from(point: Point<T>) -> Self {
+        P2::new(point.x(), point.y())
+    }
+}
+
+/// Smallest closed rectangle which contains a `Rect`.
+impl<S> From<&Rect<S>> for geo_types::Rect<S>
+where
+    S: IntervalDomain + CoordNum,
+{
+    fn from(rect: &Rect<S>) -> Self {
+        geo_types::Rect::new(
+            Coord {
+                x: *rect.x(),
+                y: *rect.y(),
+            },
+            Coord {
+                x: rect.x_interval().end(),
+                y: rect.y_interval().end(),
+            },
+        )
+    }
+}
+
+/// Smallest `Rect` which contains a closed rectangle, or why it cannot be
+/// represented.
+impl<S> TryFrom<geo_types::Rect<S>> for Rect<S>
+where
+    S: IntervalDomain + CoordNum,
+{
+    type Error = Error;
+
+    fn try_from(rect: geo_types::Rect<S>) -> Result<Self, Error> {
+        Rect::try_bounding(&[P2::from(rect.min()), P2::from(rect.max())])
+    }
+}
+
+/// Polygon without holes, whose exterior is closed by repeating the first
+/// vertex.
+impl<T: CoordNum> From<&Polygon<T>> for geo_types::Polygon<T> {
+    fn from(polygon: &Polygon<T>) -> Self {
+        let exterior = polygon
+            .vertices()
+            .iter()
+            .map(|vertex| Coord::from(*vertex))
+            .collect::<LineString<T>>();
+        geo_types::Polygon::new(exterior, Vec::new())
+    }
+}
+
+/// Polygon with the vertices of the exterior, without repeating the first,
+/// or `Error::Holes` if it has interiors.
+impl<T: CoordNum> TryFrom<&geo_types::Polygon<T>> for Polygon<T> {
+    type Error = Error;
+
+    fn try_from(polygon: &geo_types::Polygon<T>) -> Result<Self, Error> {
+        if !polygon.interiors().is_empty() {
+            return Err(Error::Holes);
+        }
+        let mut vertices = polygon
+            .exterior()
+            .coords()
+            .map(|coord| P2::from(*coord))
+            .collect::<Vec<_>>();
+        if vertices.len() > 1 && vertices.first() == vertices.last() {
+            vertices.pop();
+        }
+        Ok(Polygon::new(vertices))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::convert::TryFrom;
+    use geo_types::{coord, point, polygon, Coord, Point};
+    use geom::p2::P2;
+    use geom::polygon::Polygon;
+    use geom::rect::Rect;
+    use geom::Error;
+
+    #[test]
+    fn points() {
+        assert_eq!(Coord::from(P2::new(1, 2)), coord! { x: 1, y: 2 });
+        assert_eq!(P2::from(coord! { x: 1, y: 2 }), P2::new(1, 2));
+        assert_eq!(Point::from(P2::new(1.5, 2.5)), point! { x: 1.5, y: 2.5 });
+        assert_eq!(P2::from(point! { x: 1.5, y: 2.5 }), P2::new(1.5, 2.5));
+    }
+
+    #[test]
+    fn integer_rects() {
+        // integer rects contain their last values, so the closed rectangle
+        // reaches one past them
+        let rect = Rect::new(4, 5, 2, 3).unwrap();
+        let geo_rect = geo_types::Rect::from(&rect);
+        assert_eq!(
+            (geo_rect.min(), geo_rect.max()),
+            (coord! { x: 4, y: 5 }, coord! { x: 6, y: 8 })
+        );
+        assert_eq!(Rect::try_from(geo_rect), Ok(Rect::new(4, 5, 3, 4).unwrap()));
+
+        let unrepresentable =
+            geo_types::Rect::new(coord! { x: 0u8, y: 0 }, coord! { x: 255, y: 1 });
+        assert_eq!(Rect::try_from(unrepresentable), Err(Error::InvalidInterval));
+    }
+
+    #[test]
+    fn polygons() {
+        let triangle = Polygon::new(vec![P2::new(0, 0), P2::new(2, 0), P2::new(0, 1)]);
+        let geo_polygon = geo_types::Polygon::from(&triangle);
+        assert_eq!(
+            geo_polygon.exterior().0,
+            vec![
+                coord! { x: 0, y: 0 },
+                coord! { x: 2, y: 0 },
+                coord! { x: 0, y: 1 },
+                coord! { x: 0, y: 0 }
+            ]
+        );
+        assert!(geo_polygon.interiors().is_empty());
+        assert_eq!(Polygon::try_from(&geo_polygon), Ok(triangle));
+
+        // an exterior which geo-types has closed, and one which is empty
+        let square = polygon![(x: 0, y: 0), (x: 1, y: 0), (x: 1, y: 1), (x: 0, y: 1)];
+        let back = Polygon::try_from(&square).unwrap();
+        assert_eq!(back.len(), 4);
+        assert_eq!(geo_types::Polygon::from(&back), square);
+        let empty = geo_types::Polygon::<f64>::new(geo_types::LineString(vec![]), vec![]);
+        assert!(Polygon::try_from(&empty).unwrap().is_empty());
+
+        let with_hole = polygon!(
+            exterior: [(x: 0, y: 0), (x: 9, y: 0), (x: 9, y: 9), (x: 0, y: 9)],
+            interiors: [[(x: 3, y: 3), (x: 6, y: 3), (x: 6, y: 6)]],
+        );
+        assert_eq!(Polygon::try_from(&with_hole), Err(Error::Holes));
+    }
+}
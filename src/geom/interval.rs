@@ -1,3 +1,6 @@
+use core::cmp::Ordering;
+use geom::Error;
+use num::traits::float::FloatCore;
 use num::{CheckedAdd, CheckedSub, Num};
 
 /// Bounded interval.
@@ -75,6 +78,31 @@ where
         S::new_interval(start, diameter)
     }
 
+    /// Create a new interval, or report why it cannot be created.
+    ///
+    /// ```
+    /// # use starquad::geom::{interval::Interval, Error};
+    /// assert_eq!(Interval::<u8>::try_new(250, 7), Err(Error::InvalidInterval));
+    /// ```
+    pub fn try_new(start: S, diameter: S) -> Result<Interval<S>, Error> {
+        S::new_interval(start, diameter).ok_or(Error::InvalidInterval)
+    }
+
+    /// Create the smallest interval which contains both `min` and `max`, or
+    /// report why it cannot be created.
+    pub fn try_enclosing(min: S, max: S) -> Result<Interval<S>, Error> {
+        if max < min {
+            return Err(Error::Reversed);
+        }
+        S::enclosing_interval(min, max).ok_or(Error::InvalidInterval)
+    }
+
+    /// Create an interval which is known to be valid (for example, a constant
+    /// one) without checking it.
+    pub(crate) fn new_unchecked(start: S, diameter: S) -> Interval<S> {
+        Interval { start, diameter }
+    }
+
     pub fn start(&self) -> &S {
         &self.start
     }
@@ -85,32 +113,73 @@ where
 
     /// Check if an interval contains a value.
     pub fn contains(&self, value: &S) -> bool {
-        if value < &self.start {
-            false
-        } else {
-            let end = self.start.clone() + self.diameter.clone();
-            value < &end
-        }
+        value >= &self.start && S::before_end(self, value)
+    }
+
+    /// Check if two intervals share a value. Empty intervals share no
+    /// values.
+    ///
+    /// ```
+    /// # use starquad::geom::interval::Interval;
+    /// let a = Interval::new(2, 3).unwrap();
+    /// assert!(a.overlaps(&Interval::new(4, 2).unwrap()));
+    /// assert!(!a.overlaps(&Interval::new(5, 2).unwrap()));
+    /// ```
+    pub fn overlaps(&self, other: &Interval<S>) -> bool {
+        self.diameter > S::zero()
+            && other.diameter > S::zero()
+            && S::before_end(self, &other.start)
+            && S::before_end(other, &self.start)
+    }
+
+    /// Compare the ends of two intervals, without computing ends which
+    /// cannot be represented.
+    pub fn cmp_end(&self, other: &Interval<S>) -> Ordering {
+        S::cmp_ends(self, other)
+    }
+
+    /// Return the value at the (exclusive) end of the interval.
+    ///
+    /// For floats this is `start + diameter` rounded, so it may differ from
+    /// the true end, which `contains` uses; see `IntervalDomain::last_value`.
+    pub fn end(&self) -> S {
+        self.start.clone() + self.diameter.clone()
     }
 
-    /// Return the intersection of two intervals if it exists.
+    /// Split an interval into two halves.
+    ///
+    /// The lower half is returned first. If the interval is too small to be
+    /// split into two non-empty halves (for example, an integer interval of
+    /// width 1), then `None` is returned.
+    ///
+    /// ```
+    /// # use starquad::geom::interval::Interval;
+    /// let (lower, upper) = Interval::new(2, 5).unwrap().split().unwrap();
+    /// assert_eq!(lower, Interval::new(2, 2).unwrap());
+    /// assert_eq!(upper, Interval::new(4, 3).unwrap());
+    /// assert_eq!(Interval::new(2, 1).unwrap().split(), None);
+    /// ```
+    pub fn split(&self) -> Option<(Interval<S>, Interval<S>)> {
+        S::split_interval(self)
+    }
+
+    /// Return the intersection of two intervals, or `None` if they share no
+    /// values.
     pub fn intersect(&self, other: &Interval<S>) -> Option<Interval<S>> {
-        if self.start > other.start {
-            other.intersect(self)
-        } else {
-            let self_end = self.start.clone() + self.diameter.clone();
-            if self_end < other.start {
-                None
-            } else {
-                let other_end = other.start.clone() + other.diameter.clone();
-                if self_end < other_end {
-                    let new_diameter = self_end - other.start.clone();
-                    Interval::new(other.start.clone(), new_diameter)
-                } else {
-                    Some(other.clone())
-                }
-            }
-        }
+        S::intersect_intervals(self, other)
+    }
+
+    /// Return the union of two intervals, or `None` if it is not an interval
+    /// (there is a gap between them) or cannot be represented.
+    ///
+    /// ```
+    /// # use starquad::geom::interval::Interval;
+    /// let a = Interval::new(2, 3).unwrap();
+    /// assert_eq!(a.union(&Interval::new(5, 2).unwrap()), Interval::new(2, 5));
+    /// assert_eq!(a.union(&Interval::new(6, 2).unwrap()), None);
+    /// ```
+    pub fn union(&self, other: &Interval<S>) -> Option<Interval<S>> {
+        S::union_intervals(self, other)
     }
 }
 
@@ -125,9 +194,80 @@ pub trait IntervalDomain: Clone + Num + PartialOrd {
     fn new_interval(start: Self, diameter: Self) -> Option<Interval<Self>>
     where
         Self: Sized;
+
+    /// Create the smallest interval which contains both `min` and `max`.
+    ///
+    /// Because intervals exclude their end value, this is not the same as
+    /// `new_interval(min, max - min)`.
+    fn enclosing_interval(min: Self, max: Self) -> Option<Interval<Self>>
+    where
+        Self: Sized;
+
+    /// Check if a value is less than the end of an interval.
+    ///
+    /// The end of an integer interval need not be representable (eg. the
+    /// `u8` interval starting at 255 with a diameter of 1), so integers
+    /// compare with the last value in the interval instead.
+    fn before_end(interval: &Interval<Self>, value: &Self) -> bool
+    where
+        Self: Sized;
+
+    /// Compare the ends of two intervals. Like `before_end`, integers
+    /// compare the last values in the intervals instead.
+    fn cmp_ends(a: &Interval<Self>, b: &Interval<Self>) -> Ordering
+    where
+        Self: Sized;
+
+    /// Largest value in a non-empty interval.
+    ///
+    /// ```
+    /// # use starquad::geom::interval::{Interval, IntervalDomain};
+    /// assert_eq!(u8::last_value(&Interval::new(250, 6).unwrap()), 255);
+    /// let interval = Interval::new(1.0, 2.0).unwrap();
+    /// assert_eq!(f64::last_value(&interval), 3.0_f64.next_down());
+    /// ```
+    fn last_value(interval: &Interval<Self>) -> Self
+    where
+        Self: Sized;
+
+    /// See `Interval::split`.
+    fn split_interval(interval: &Interval<Self>) -> Option<(Interval<Self>, Interval<Self>)>
+    where
+        Self: Sized,
+    {
+        let two = Self::one() + Self::one();
+        let lower_diameter = interval.diameter.clone() / two;
+        let upper_diameter = interval.diameter.clone() - lower_diameter.clone();
+        let mid = interval.start.clone() + lower_diameter.clone();
+        if lower_diameter > Self::zero() && mid > interval.start && Self::before_end(interval, &mid)
+        {
+            Some((
+                Interval {
+                    start: interval.start.clone(),
+                    diameter: lower_diameter,
+                },
+                Interval {
+                    start: mid,
+                    diameter: upper_diameter,
+                },
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// See `Interval::intersect`.
+    fn intersect_intervals(a: &Interval<Self>, b: &Interval<Self>) -> Option<Interval<Self>>
+    where
+        Self: Sized;
+
+    /// See `Interval::union`.
+    fn union_intervals(a: &Interval<Self>, b: &Interval<Self>) -> Option<Interval<Self>>
+    where
+        Self: Sized;
 }
 
-//// Intervals of different types
+// Intervals of different types
 
 /// Create a new `Interval` on an integer-like domain (with checked operations
 /// for addition and subtraction).
@@ -163,22 +303,172 @@ where
     }
 }
 
+/// End of a float interval, as `start + diameter` rounded and the error in
+/// rounding it, so that their sum is exactly the end (the "two-sum" of the
+/// start and diameter).
+///
+/// `start + diameter` alone is off by up to half the spacing of the floats
+/// near the end, so when the diameter is not much larger than that spacing
+/// it may round to the start, or past a value in the interval.
+fn end_parts<S: FloatCore>(interval: &Interval<S>) -> (S, S) {
+    let (start, diameter) = (interval.start, interval.diameter);
+    let end = start + diameter;
+    if !end.is_finite() {
+        return (end, S::zero());
+    }
+    let diameter_part = end - start;
+    let start_part = end - diameter_part;
+    let error = (start - start_part) + (diameter - diameter_part);
+    (end, error)
+}
+
 macro_rules! create_float_interval_ops {
     ($t:ty) => {
         impl IntervalDomain for $t {
             fn new_interval(start: $t, diameter: $t) -> Option<Interval<$t>> {
                 new_float_interval(start, diameter)
             }
+
+            /// Find a diameter for which the exact end is after `max` but
+            /// not after the next value, adjusting an estimate of it by a
+            /// step or two.
+            fn enclosing_interval(min: $t, max: $t) -> Option<Interval<$t>> {
+                if !(min.is_finite() && max.is_finite()) || max < min {
+                    return None;
+                }
+                let mut interval = Interval {
+                    start: min,
+                    diameter: max.next_up() - min,
+                };
+                while !<$t>::before_end(&interval, &max) {
+                    interval.diameter = interval.diameter.next_up();
+                }
+                while <$t>::before_end(&interval, &max.next_up()) {
+                    let smaller = Interval {
+                        start: min,
+                        diameter: interval.diameter.next_down(),
+                    };
+                    if !<$t>::before_end(&smaller, &max) {
+                        break;
+                    }
+                    interval = smaller;
+                }
+                if interval.diameter.is_finite() {
+                    Some(interval)
+                } else {
+                    None
+                }
+            }
+
+            fn before_end(interval: &Interval<$t>, value: &$t) -> bool {
+                let (end, error) = end_parts(interval);
+                *value < end || (*value == end && error > 0.0)
+            }
+
+            fn cmp_ends(a: &Interval<$t>, b: &Interval<$t>) -> Ordering {
+                let (a, b) = (end_parts(a), end_parts(b));
+                a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+            }
+
+            fn last_value(interval: &Interval<$t>) -> $t {
+                let (end, error) = end_parts(interval);
+                if error > 0.0 {
+                    end
+                } else {
+                    end.next_down()
+                }
+            }
+
+            /// Split at the rounded middle, with halves which together
+            /// contain exactly the values of the interval.
+            fn split_interval(interval: &Interval<$t>) -> Option<(Interval<$t>, Interval<$t>)> {
+                let mid = interval.start + interval.diameter / 2.0;
+                if !(mid > interval.start && <$t>::before_end(interval, &mid)) {
+                    return None;
+                }
+                let lower = <$t>::enclosing_interval(interval.start, mid.next_down())?;
+                let upper = <$t>::enclosing_interval(mid, <$t>::last_value(interval))?;
+                Some((lower, upper))
+            }
+
+            fn intersect_intervals(a: &Interval<$t>, b: &Interval<$t>) -> Option<Interval<$t>> {
+                if !(a.diameter > 0.0 && b.diameter > 0.0) {
+                    return None;
+                }
+                let start = a.start.max(b.start);
+                let last = <$t>::last_value(a).min(<$t>::last_value(b));
+                <$t>::enclosing_interval(start, last)
+            }
+
+            fn union_intervals(a: &Interval<$t>, b: &Interval<$t>) -> Option<Interval<$t>> {
+                if a.diameter == 0.0 || b.diameter == 0.0 {
+                    return Some(if a.diameter == 0.0 { b } else { a }.clone());
+                }
+                let (first, second) = if a.start <= b.start { (a, b) } else { (b, a) };
+                let first_last = <$t>::last_value(first);
+                if first_last.next_up() < second.start {
+                    return None;
+                }
+                let last = first_last.max(<$t>::last_value(second));
+                <$t>::enclosing_interval(first.start, last)
+            }
         }
     };
 }
 
+/// Last value in an integer interval, which was checked to be
+/// representable when the interval was created. It is before the start if
+/// the interval is empty.
+fn last<S: Num + Clone>(interval: &Interval<S>) -> S {
+    interval.start.clone() + (interval.diameter.clone() - S::one())
+}
+
 macro_rules! create_int_interval_ops {
     ($t:ty) => {
         impl IntervalDomain for $t {
             fn new_interval(start: $t, diameter: $t) -> Option<Interval<$t>> {
                 new_int_interval(start, diameter)
             }
+
+            fn enclosing_interval(min: $t, max: $t) -> Option<Interval<$t>> {
+                if max < min {
+                    return None;
+                }
+                max.checked_sub(min)
+                    .and_then(|d| d.checked_add(1))
+                    .and_then(|diameter| new_int_interval(min, diameter))
+            }
+
+            fn before_end(interval: &Interval<$t>, value: &$t) -> bool {
+                *value <= last(interval)
+            }
+
+            fn cmp_ends(a: &Interval<$t>, b: &Interval<$t>) -> Ordering {
+                last(a).cmp(&last(b))
+            }
+
+            fn last_value(interval: &Interval<$t>) -> $t {
+                last(interval)
+            }
+
+            fn intersect_intervals(a: &Interval<$t>, b: &Interval<$t>) -> Option<Interval<$t>> {
+                let start = a.start.max(b.start);
+                let last = last(a).min(last(b));
+                <$t>::enclosing_interval(start, last)
+            }
+
+            fn union_intervals(a: &Interval<$t>, b: &Interval<$t>) -> Option<Interval<$t>> {
+                // only signed intervals can be empty
+                if a.diameter == 0 || b.diameter == 0 {
+                    return Some(if a.diameter == 0 { b } else { a }.clone());
+                }
+                let (first, second) = if a.start <= b.start { (a, b) } else { (b, a) };
+                // `second.start - 1` is representable, being at least `first.start`
+                if second.start > first.start && second.start - 1 > last(first) {
+                    return None;
+                }
+                <$t>::enclosing_interval(first.start, last(first).max(last(second)))
+            }
         }
     };
 }
@@ -198,13 +488,15 @@ create_int_interval_ops!(u128);
 create_float_interval_ops!(f32);
 create_float_interval_ops!(f64);
 
-//// Tests
+// Tests
 
 #[cfg(test)]
 pub mod test {
+    use alloc::vec::Vec;
+    use core::convert::TryFrom;
     use geom::interval::{Interval, IntervalDomain};
+    use geom::Error;
     use paste::paste;
-    use quickcheck::{Arbitrary, Gen};
     use quickcheck_macros::quickcheck;
 
     #[test]
@@ -214,6 +506,20 @@ pub mod test {
         assert_eq!(interval.diameter(), &42);
     }
 
+    #[test]
+    fn try_new() {
+        assert_eq!(Interval::<u8>::try_new(250, 7), Err(Error::InvalidInterval));
+        assert_eq!(
+            Interval::<i8>::try_new(-128, -1),
+            Err(Error::InvalidInterval)
+        );
+        assert_eq!(Interval::try_enclosing(2, 1), Err(Error::Reversed));
+        assert_eq!(
+            Interval::try_enclosing(1, 2),
+            Ok(Interval::new(1, 2).unwrap())
+        );
+    }
+
     #[test]
     fn new_normalizes_diameter_int() {
         let interval = Interval::<i8>::new(7, -4).expect("new interval");
@@ -247,8 +553,8 @@ pub mod test {
             interval_b: &Interval<u8>,
             expected: Option<Interval<u8>>,
         ) {
-            let ab = interval_a.intersect(&interval_b);
-            let ba = interval_b.intersect(&interval_a);
+            let ab = interval_a.intersect(interval_b);
+            let ba = interval_b.intersect(interval_a);
             assert_eq!(ab, expected);
             assert_eq!(ba, expected);
         }
@@ -265,6 +571,32 @@ pub mod test {
         intersect_both(&a, &t4, Interval::new(4, 3));
     }
 
+    #[test]
+    fn split_int() {
+        let (lower, upper) = Interval::<u8>::new(250, 5).unwrap().split().unwrap();
+        assert_eq!(lower, Interval::new(250, 2).unwrap());
+        assert_eq!(upper, Interval::new(252, 3).unwrap());
+        assert_eq!(Interval::<u8>::new(255, 1).unwrap().split(), None);
+    }
+
+    #[test]
+    fn enclosing_int() {
+        assert_eq!(u8::enclosing_interval(3, 3), Interval::new(3, 1));
+        assert_eq!(u8::enclosing_interval(3, 7), Interval::new(3, 5));
+        assert_eq!(u8::enclosing_interval(0, 255), None); // width 256
+        assert_eq!(u8::enclosing_interval(7, 3), None);
+    }
+
+    #[test]
+    fn enclosing_float() {
+        let interval = f64::enclosing_interval(1.0, 359.0).unwrap();
+        assert!(interval.contains(&1.0));
+        assert!(interval.contains(&359.0));
+        let point = f64::enclosing_interval(5.0, 5.0).unwrap();
+        assert!(point.contains(&5.0));
+        assert_eq!(f64::enclosing_interval(0.0, f64::NAN), None);
+    }
+
     #[test]
     fn contains_int() {
         let interval = Interval::<u8>::new(2, 2).unwrap();
@@ -272,6 +604,8 @@ pub mod test {
         assert!(interval.contains(&2));
         assert!(interval.contains(&3));
         assert!(!interval.contains(&4));
+        // the end of this interval, 256, is not a u8
+        assert!(Interval::<u8>::new(255, 1).unwrap().contains(&255));
     }
 
     #[test]
@@ -284,64 +618,38 @@ pub mod test {
         assert!(!interval.contains(&4.1));
     }
 
-    macro_rules! create_arbitrary_int_interval {
-        ($t:ty) => {
-            impl Arbitrary for Interval<$t> {
-                fn arbitrary<G>(g: &mut G) -> Self
-                where
-                    G: Gen,
-                {
-                    let mut start: $t;
-                    let mut end: $t;
-                    let diameter: $t;
-                    loop {
-                        start = <$t>::arbitrary(g);
-                        end = <$t>::arbitrary(g);
-                        let opt_diameter = end
-                            .checked_sub(start)
-                            .and_then(|pdiam| pdiam.checked_add(1));
-                        if let Some(d) = opt_diameter {
-                            diameter = d;
-                            break;
-                        }
-                    }
-                    Interval::new(start, diameter).expect("Arbitrary int interval")
-                }
-            }
-        };
-    }
-
-    macro_rules! create_arbitrary_float_interval {
-        ($t:ty) => {
-            impl Arbitrary for Interval<$t> {
-                fn arbitrary<G>(g: &mut G) -> Self
-                where
-                    G: Gen,
-                {
-                    let start = <$t>::arbitrary(g);
-                    let end = <$t>::arbitrary(g);
-                    let diameter = end - start;
-                    Interval::new(start, diameter).expect("Arbitrary float interval")
-                }
-            }
-        };
+    #[test]
+    fn float_ends_at_limits() {
+        // `start + diameter` rounds down to the start
+        let tiny = Interval::new(359.5, 1e-14).unwrap();
+        assert!(tiny.contains(&359.5));
+        assert!(!tiny.contains(&359.5_f64.next_up()));
+        assert_eq!(f64::last_value(&tiny), 359.5);
+        assert_eq!(tiny.split(), None);
+        // ... and to the start of the next value, which is in the interval
+        let eps = f64::EPSILON;
+        let ulps = Interval::new(1.0, 1.4 * eps).unwrap();
+        assert!(ulps.contains(&(1.0 + eps)));
+        assert!(!ulps.contains(&(1.0 + 2.0 * eps)));
+        assert_eq!(
+            ulps.intersect(&Interval::new(1.0 + eps, 1.0).unwrap()),
+            f64::enclosing_interval(1.0 + eps, 1.0 + eps)
+        );
+        // the end rounds to the largest float, or overflows
+        assert!(Interval::new(f64::MAX, 1.0).unwrap().contains(&f64::MAX));
+        assert!(Interval::new(f64::MAX, f64::MAX)
+            .unwrap()
+            .contains(&f64::MAX));
+        assert_eq!(f64::enclosing_interval(0.0, f64::MAX), None);
+        // enclosing intervals hold no more values than they must
+        let point = f64::enclosing_interval(359.5, 359.5).unwrap();
+        assert!(point.contains(&359.5));
+        assert!(!point.contains(&359.5_f64.next_up()));
+        let empty = Interval::new(359.5, 0.0).unwrap();
+        assert!(!empty.contains(&359.5));
+        assert_eq!(empty.intersect(&point), None);
     }
 
-    create_arbitrary_int_interval!(i8);
-    create_arbitrary_int_interval!(i16);
-    create_arbitrary_int_interval!(i32);
-    create_arbitrary_int_interval!(i64);
-    create_arbitrary_int_interval!(i128);
-
-    create_arbitrary_int_interval!(u8);
-    create_arbitrary_int_interval!(u16);
-    create_arbitrary_int_interval!(u32);
-    create_arbitrary_int_interval!(u64);
-    create_arbitrary_int_interval!(u128);
-
-    create_arbitrary_float_interval!(f32);
-    create_arbitrary_float_interval!(f64);
-
     /// Property test for consistency between `contains` and `intersection`.
     ///
     /// If two intervals both contain a value then their intersection must
@@ -355,10 +663,8 @@ pub mod test {
         let opt_intersection = a.intersect(&b);
         if a.contains(&value) && b.contains(&value) {
             assert!(opt_intersection.unwrap().contains(&value));
-        } else {
-            if let Some(intersection) = opt_intersection {
-                assert!(!intersection.contains(&value));
-            }
+        } else if let Some(intersection) = opt_intersection {
+            assert!(!intersection.contains(&value));
         }
     }
 
@@ -389,4 +695,251 @@ pub mod test {
     check_intersection_point_membership!(u128);
     check_intersection_point_membership!(f32);
     check_intersection_point_membership!(f64);
+
+    /// Property test for `split`: every value in the interval belongs to
+    /// exactly one of the two halves.
+    fn split_partitions<S>(interval: Interval<S>, value: S)
+    where
+        S: IntervalDomain,
+    {
+        if let Some((lower, upper)) = interval.split() {
+            assert_eq!(
+                interval.contains(&value),
+                lower.contains(&value) != upper.contains(&value)
+            );
+            assert!(!(lower.contains(&value) && upper.contains(&value)));
+        }
+    }
+
+    #[quickcheck]
+    fn i16_split_partitions(interval: Interval<i16>, value: i16) {
+        split_partitions(interval, value);
+    }
+
+    #[quickcheck]
+    fn u32_split_partitions(interval: Interval<u32>, value: u32) {
+        split_partitions(interval, value);
+    }
+
+    #[quickcheck]
+    fn f64_split_partitions(interval: Interval<f64>, value: f64) {
+        split_partitions(interval, value);
+    }
+
+    /// Set of values of a type of at most 8 bits, as bits indexed from its
+    /// smallest value.
+    type Set = [u128; 2];
+
+    /// Set of the values from `lo` up to but excluding `hi`, given as
+    /// indexes from the smallest value of their type.
+    fn range_set(lo: usize, hi: usize) -> Set {
+        let half = |lo: usize, hi: usize| match hi.saturating_sub(lo) {
+            0 => 0,
+            128 => !0,
+            width => ((1u128 << width) - 1) << lo,
+        };
+        [
+            half(lo.min(128), hi.min(128)),
+            half(lo.max(128) - 128, hi.max(128) - 128),
+        ]
+    }
+
+    fn and(a: Set, b: Set) -> Set {
+        [a[0] & b[0], a[1] & b[1]]
+    }
+
+    fn or(a: Set, b: Set) -> Set {
+        [a[0] | b[0], a[1] | b[1]]
+    }
+
+    /// Whether a set has no gaps between its values.
+    fn contiguous(set: Set) -> bool {
+        let count = (set[0].count_ones() + set[1].count_ones()) as usize;
+        let lo = if set[0] != 0 {
+            set[0].trailing_zeros() as usize
+        } else {
+            128 + set[1].trailing_zeros() as usize
+        };
+        count == 0 || set == range_set(lo, lo + count)
+    }
+
+    /// Exhaustive tests of the intervals of the integer types of 8 bits
+    /// against the sets of values they represent.
+    macro_rules! check_exhaustive {
+        ($t:ty) => {
+            paste! {
+                /// The start and diameter `Interval::new` gives, by the
+                /// checks it makes on them, widened to `i32`.
+                fn [<$t _expected>](start: i32, diameter: i32) -> Option<(i32, i32)> {
+                    let fits = |v: i32| i32::from(<$t>::MIN) <= v && v <= i32::from(<$t>::MAX);
+                    let (start, diameter) = if diameter < 0 {
+                        (start + diameter, -diameter)
+                    } else {
+                        (start, diameter)
+                    };
+                    let valid = fits(start)
+                        && fits(diameter)
+                        && fits(diameter - 1)
+                        && fits(start + diameter - 1);
+                    Some((start, diameter)).filter(|_| valid)
+                }
+
+                fn [<$t _index>](value: i32) -> usize {
+                    (value - i32::from(<$t>::MIN)) as usize
+                }
+
+                /// Values of an interval, from its start and diameter.
+                fn [<$t _set>](interval: &Interval<$t>) -> Set {
+                    let lo = [<$t _index>](i32::from(*interval.start()));
+                    range_set(lo, lo + (i32::from(*interval.diameter()) as usize))
+                }
+
+                /// Values of an interval, by `contains`.
+                fn [<$t _contents>](interval: &Interval<$t>) -> Set {
+                    let mut set = [0; 2];
+                    for value in <$t>::MIN..=<$t>::MAX {
+                        if interval.contains(&value) {
+                            let i = [<$t _index>](i32::from(value));
+                            set[i / 128] |= 1 << (i % 128);
+                        }
+                    }
+                    set
+                }
+
+                #[test]
+                fn [<$t _exhaustive_new_contains_split>]() {
+                    for start in <$t>::MIN..=<$t>::MAX {
+                        for diameter in <$t>::MIN..=<$t>::MAX {
+                            let interval = Interval::new(start, diameter);
+                            let expected = [<$t _expected>](i32::from(start), i32::from(diameter));
+                            let actual = interval
+                                .as_ref()
+                                .map(|i| (i32::from(*i.start()), i32::from(*i.diameter())));
+                            assert_eq!(actual, expected, "new({}, {})", start, diameter);
+                            let interval = match interval {
+                                Some(interval) => interval,
+                                None => continue,
+                            };
+                            let set = [<$t _set>](&interval);
+                            assert_eq!([<$t _contents>](&interval), set, "{:?}", interval);
+
+                            match interval.split() {
+                                Some((lower, upper)) => {
+                                    let (l, u) = ([<$t _set>](&lower), [<$t _set>](&upper));
+                                    assert_eq!(or(l, u), set, "{:?}", interval);
+                                    assert!(l != [0; 2] && u != [0; 2], "{:?}", interval);
+                                    assert_eq!(
+                                        i32::from(*upper.start()),
+                                        i32::from(*lower.start()) + i32::from(*lower.diameter())
+                                    );
+                                }
+                                None => assert!(*interval.diameter() < 2, "{:?}", interval),
+                            }
+                        }
+                    }
+                }
+
+                /// Pairs of the intervals whose first and last values are
+                /// near the ends or the middle of the type, where the checked
+                /// arithmetic matters.
+                #[test]
+                fn [<$t _exhaustive_pairs>]() {
+                    let (min, max) = (i32::from(<$t>::MIN), i32::from(<$t>::MAX));
+                    let mid = (min + max + 1) / 2;
+                    let values = (min..=max)
+                        .filter(|v| v - min <= 8 || (v - mid).abs() <= 8 || max - v <= 8)
+                        .collect::<Vec<_>>();
+                    let mut intervals = Vec::new();
+                    for &first in &values {
+                        for &last in values.iter().filter(|&&last| last + 1 >= first) {
+                            let start = <$t>::try_from(first).unwrap();
+                            let diameter = <$t>::try_from(last - first + 1);
+                            if let Some(interval) = diameter.ok().and_then(|d| Interval::new(start, d)) {
+                                intervals.push(interval);
+                            }
+                        }
+                    }
+
+                    for a in &intervals {
+                        let a_set = [<$t _set>](a);
+                        for b in &intervals {
+                            let b_set = [<$t _set>](b);
+                            let both = and(a_set, b_set);
+                            assert_eq!(a.overlaps(b), both != [0; 2], "{:?} {:?}", a, b);
+                            match a.intersect(b) {
+                                Some(i) => assert_eq!([<$t _set>](&i), both, "{:?} {:?}", a, b),
+                                None => assert_eq!(both, [0; 2], "{:?} {:?}", a, b),
+                            }
+
+                            let either = or(a_set, b_set);
+                            let count = (either[0].count_ones() + either[1].count_ones()) as i32;
+                            let representable = [<$t _expected>](0, count).is_some();
+                            match a.union(b) {
+                                Some(u) => assert_eq!([<$t _set>](&u), either, "{:?} {:?}", a, b),
+                                None => assert!(
+                                    !contiguous(either) || !representable,
+                                    "{:?} {:?}", a, b
+                                ),
+                            }
+
+                            let end = |i: &Interval<$t>| i32::from(*i.start()) + i32::from(*i.diameter());
+                            assert_eq!(a.cmp_end(b), end(a).cmp(&end(b)), "{:?} {:?}", a, b);
+                        }
+                    }
+                }
+            }
+        };
+    }
+
+    check_exhaustive!(u8);
+    check_exhaustive!(i8);
+
+    /// Property test: intervals of a few units in the last place of their
+    /// starts, whose ends cannot be represented, contain the values they
+    /// would with exact arithmetic, and their splits, intersections and
+    /// unions contain the values they should.
+    ///
+    /// Near the start, `value - start` is exact, so the test compares it
+    /// with the diameter.
+    #[quickcheck]
+    fn f64_values_near_unrepresentable_ends(start: f64, a: (u8, u8), b: (i8, u8, u8)) {
+        let start = 256.0 + start.abs();
+        let ulp = start.next_up() - start;
+        let diameter =
+            |(ulps, fraction): (u8, u8)| (f64::from(ulps % 8) + f64::from(fraction) / 256.0) * ulp;
+        let a = Interval::new(start, diameter((a.0, a.1))).unwrap();
+        let b_start = start + f64::from(b.0 % 8) * ulp;
+        let b = Interval::new(b_start, diameter((b.1, b.2))).unwrap();
+        let in_exactly = |interval: &Interval<f64>, value: f64| {
+            *interval.start() <= value && value - interval.start() < *interval.diameter()
+        };
+        let halves = a.split();
+        let (intersection, union) = (a.intersect(&b), a.union(&b));
+        let mut values = start - 10.0 * ulp;
+        while values <= start + 20.0 * ulp {
+            let value = values;
+            values = values.next_up();
+            let (in_a, in_b) = (in_exactly(&a, value), in_exactly(&b, value));
+            assert_eq!(a.contains(&value), in_a, "{:?} {}", a, value);
+            if let Some((lower, upper)) = &halves {
+                assert_eq!(
+                    lower.contains(&value) as u8 + upper.contains(&value) as u8,
+                    in_a as u8
+                );
+            }
+            let in_intersection = intersection.as_ref().is_some_and(|i| i.contains(&value));
+            assert_eq!(in_intersection, in_a && in_b, "{:?} {:?} {}", a, b, value);
+            if let Some(union) = &union {
+                assert_eq!(
+                    union.contains(&value),
+                    in_a || in_b,
+                    "{:?} {:?} {}",
+                    a,
+                    b,
+                    value
+                );
+            }
+        }
+        assert_eq!(a.overlaps(&b), intersection.is_some());
+    }
 }
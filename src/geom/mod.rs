@@ -1,3 +1,27 @@
+pub mod algorithms;
+pub mod delaunay;
+#[cfg(feature = "geo-types")]
+pub mod geo;
 pub mod interval;
 pub mod p2;
-pub mod rect;
\ No newline at end of file
+pub mod p3;
+pub mod polygon;
+pub mod quantize;
+pub mod rect;
+
+/// Errors from constructing geometry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum Error {
+    /// The interval would include values which its type cannot represent.
+    #[error("interval cannot be represented")]
+    InvalidInterval,
+    /// The minimum of an enclosing interval is greater than its maximum.
+    #[error("interval minimum is greater than its maximum")]
+    Reversed,
+    /// There were no comparable points to bound.
+    #[error("no points to bound")]
+    NoPoints,
+    /// A polygon has holes, which `Polygon` cannot represent.
+    #[error("polygon has holes")]
+    Holes,
+}
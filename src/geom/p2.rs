@@ -1,4 +1,6 @@
-#[derive(Debug, Clone, PartialEq)]
+use num::Num;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct P2<S> {
     pub x: S,
     pub y: S,
@@ -8,24 +10,13 @@ impl<S> P2<S> {
     pub fn new(x: S, y: S) -> Self {
         P2 { x, y }
     }
-}
-
-#[cfg(test)]
-mod test {
-    use geom::p2::P2;
-    use quickcheck::{Arbitrary, Gen};
 
-    impl<S> Arbitrary for P2<S>
+    /// Square of the Euclidean distance to another point.
+    pub fn distance_2(&self, other: &P2<S>) -> S
     where
-        S: Arbitrary,
+        S: Num + Copy,
     {
-        fn arbitrary<G>(g: &mut G) -> Self
-        where
-            G: Gen,
-        {
-            let x = S::arbitrary(g);
-            let y = S::arbitrary(g);
-            P2::new(x, y)
-        }
+        let (dx, dy) = (self.x - other.x, self.y - other.y);
+        dx * dx + dy * dy
     }
 }
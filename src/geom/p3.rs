@@ -0,0 +1,35 @@
+use num::Num;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct P3<S> {
+    pub x: S,
+    pub y: S,
+    pub z: S,
+}
+
+impl<S> P3<S> {
+    pub fn new(x: S, y: S, z: S) -> Self {
+        P3 { x, y, z }
+    }
+
+    /// Square of the Euclidean distance to another point.
+    pub fn distance_2(&self, other: &P3<S>) -> S
+    where
+        S: Num + Copy,
+    {
+        let (dx, dy, dz) = (self.x - other.x, self.y - other.y, self.z - other.z);
+        dx * dx + dy * dy + dz * dz
+    }
+
+    /// Coordinate along an axis: 0 for `x`, 1 for `y` and 2 for `z`.
+    pub fn axis(&self, axis: usize) -> S
+    where
+        S: Copy,
+    {
+        match axis {
+            0 => self.x,
+            1 => self.y,
+            _ => self.z,
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use alloc::vec::Vec;
+use geom::p2::P2;
+use num::traits::float::FloatCore;
+
+/// Simple polygon, given by its vertices in order, without repeating the
+/// first vertex at the end.
+///
+/// Polygons built by `geom::algorithms` go counter-clockwise around their
+/// interiors, so their area is positive, except for the holes of alpha
+/// shapes, which go clockwise.
+///
+/// ```
+/// # use starquad::geom::p2::P2;
+/// # use starquad::geom::polygon::Polygon;
+/// let square = Polygon::new(vec![
+///     P2::new(0.0, 0.0),
+///     P2::new(2.0, 0.0),
+///     P2::new(2.0, 2.0),
+///     P2::new(0.0, 2.0),
+/// ]);
+/// assert_eq!(square.area(), 4.0);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polygon<S> {
+    vertices: Vec<P2<S>>,
+}
+
+impl<S> Polygon<S> {
+    pub fn new(vertices: Vec<P2<S>>) -> Self {
+        Polygon { vertices }
+    }
+
+    pub fn vertices(&self) -> &[P2<S>] {
+        &self.vertices
+    }
+
+    pub fn into_vertices(self) -> Vec<P2<S>> {
+        self.vertices
+    }
+
+    pub fn len(&self) -> usize {
+        self.vertices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vertices.is_empty()
+    }
+
+    /// Signed area: positive if the vertices go counter-clockwise, and
+    /// negative if they go clockwise.
+    pub fn area(&self) -> S
+    where
+        S: FloatCore,
+    {
+        let n = self.vertices.len();
+        let twice = (0..n).fold(S::zero(), |sum, i| {
+            let (a, b) = (&self.vertices[i], &self.vertices[(i + 1) % n]);
+            sum + a.x * b.y - b.x * a.y
+        });
+        twice / (S::one() + S::one())
+    }
+
+    /// Whether a point is inside the polygon, by the even-odd rule, so that
+    /// the vertices may go either way around it. Points on its edges may be
+    /// found either inside or outside.
+    ///
+    /// ```
+    /// # use starquad::geom::p2::P2;
+    /// # use starquad::geom::polygon::Polygon;
+    /// let triangle = Polygon::new(vec![P2::new(0.0, 0.0), P2::new(4.0, 0.0), P2::new(0.0, 4.0)]);
+    /// assert!(triangle.contains(&P2::new(1.0, 1.0)));
+    /// assert!(!triangle.contains(&P2::new(3.0, 3.0)));
+    /// ```
+    pub fn contains(&self, point: &P2<S>) -> bool
+    where
+        S: FloatCore,
+    {
+        let n = self.vertices.len();
+        (0..n).fold(false, |inside, i| {
+            let (a, b) = (&self.vertices[i], &self.vertices[(i + 1) % n]);
+            // whether a ray from the point towards +x crosses the edge
+            let crosses = (a.y > point.y) != (b.y > point.y)
+                && point.x < a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x);
+            inside != crosses
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use geom::p2::P2;
+    use geom::polygon::Polygon;
+
+    #[test]
+    fn signed_area() {
+        let mut triangle = vec![P2::new(1.0, 1.0), P2::new(4.0, 1.0), P2::new(1.0, 5.0)];
+        assert_eq!(Polygon::new(triangle.clone()).area(), 6.0);
+        triangle.reverse();
+        assert_eq!(Polygon::new(triangle).area(), -6.0);
+        assert_eq!(Polygon::<f64>::new(vec![]).area(), 0.0);
+    }
+
+    #[test]
+    fn contains_concave() {
+        // a "U" open at the top
+        let u = Polygon::new(vec![
+            P2::new(0.0, 0.0),
+            P2::new(3.0, 0.0),
+            P2::new(3.0, 3.0),
+            P2::new(2.0, 3.0),
+            P2::new(2.0, 1.0),
+            P2::new(1.0, 1.0),
+            P2::new(1.0, 3.0),
+            P2::new(0.0, 3.0),
+        ]);
+        assert!(u.contains(&P2::new(0.5, 2.5)));
+        assert!(u.contains(&P2::new(1.5, 0.5)));
+        assert!(!u.contains(&P2::new(1.5, 2.0)));
+        assert!(!u.contains(&P2::new(-1.0, 0.5)));
+        let mut reversed = u.into_vertices();
+        reversed.reverse();
+        assert!(Polygon::new(reversed).contains(&P2::new(2.5, 2.5)));
+        assert!(!Polygon::<f64>::new(vec![]).contains(&P2::new(0.0, 0.0)));
+    }
+}
@@ -0,0 +1,105 @@
+use geom::interval::{Interval, IntervalDomain};
+use geom::p2::P2;
+use geom::rect::Rect;
+
+/// Number of grid cells along each axis. One less than the number of `u32`
+/// values, so that the whole grid is a valid `Interval<u32>`.
+const CELLS: u32 = u32::MAX;
+
+/// Mapping of a rectangular region of the plane onto a `u32` grid.
+///
+/// Quantization is monotonic along each axis, so a point inside a rectangle
+/// always quantizes into the quantized rectangle (see
+/// [quantize_rect](Quantizer::quantize_rect)). Points outside of the region
+/// are clamped to its edge cells. Quantized coordinates are therefore only
+/// suitable for finding candidates, which must then be refined using their
+/// full-precision coordinates.
+///
+/// ```
+/// # use starquad::geom::p2::P2;
+/// # use starquad::geom::quantize::Quantizer;
+/// # use starquad::geom::rect::Rect;
+/// let quantizer = Quantizer::new(Rect::new(0.0, 0.0, 10.0, 10.0).unwrap());
+/// let q = quantizer.quantize(&P2::new(2.5, 7.5));
+/// let rect = quantizer.quantize_rect(&Rect::new(2.0, 7.0, 1.0, 1.0).unwrap()).unwrap();
+/// assert!(rect.contains(&q));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Quantizer {
+    bounds: Rect<f64>,
+}
+
+impl Quantizer {
+    pub fn new(bounds: Rect<f64>) -> Self {
+        Quantizer { bounds }
+    }
+
+    pub fn bounds(&self) -> &Rect<f64> {
+        &self.bounds
+    }
+
+    /// Region of the grid covering all quantized points.
+    pub fn grid() -> Rect<u32> {
+        let interval = Interval::new_unchecked(0, CELLS);
+        Rect::new_from_intervals(interval.clone(), interval)
+    }
+
+    fn axis(value: f64, start: f64, diameter: f64) -> u32 {
+        let cell = (value - start) / diameter * f64::from(CELLS);
+        // After clamping, truncation by `as` is the floor (which is not in
+        // `core`). NaN converts to 0.
+        cell.clamp(0.0, f64::from(CELLS - 1)) as u32
+    }
+
+    pub fn quantize(&self, point: &P2<f64>) -> P2<u32> {
+        let x = self.bounds.x_interval();
+        let y = self.bounds.y_interval();
+        P2::new(
+            Quantizer::axis(point.x, *x.start(), *x.diameter()),
+            Quantizer::axis(point.y, *y.start(), *y.diameter()),
+        )
+    }
+
+    /// Smallest grid rectangle containing the quantized form of every point
+    /// in `rect`.
+    pub fn quantize_rect(&self, rect: &Rect<f64>) -> Option<Rect<u32>> {
+        let min = self.quantize(&P2::new(*rect.x(), *rect.y()));
+        let max = self.quantize(&P2::new(rect.x_interval().end(), rect.y_interval().end()));
+        Some(Rect::new_from_intervals(
+            u32::enclosing_interval(min.x, max.x)?,
+            u32::enclosing_interval(min.y, max.y)?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::vec::Vec;
+    use geom::p2::P2;
+    use geom::quantize::Quantizer;
+    use geom::rect::Rect;
+    use quickcheck_macros::quickcheck;
+
+    #[test]
+    fn clamps() {
+        let quantizer = Quantizer::new(Rect::new(0.0, 0.0, 1.0, 1.0).unwrap());
+        assert_eq!(
+            quantizer.quantize(&P2::new(-1.0, 2.0)),
+            P2::new(0, u32::MAX - 1)
+        );
+        assert_eq!(quantizer.quantize(&P2::new(f64::NAN, 0.5)).x, 0);
+        assert!(Quantizer::grid().contains(&P2::new(u32::MAX - 1, 0)));
+    }
+
+    /// Property test: points in a rectangle quantize into the quantized
+    /// rectangle.
+    #[quickcheck]
+    fn quantize_rect_contains(points: Vec<P2<f64>>, rect: Rect<f64>) {
+        let quantizer = Quantizer::new(Rect::new(-100.0, -50.0, 200.0, 100.0).unwrap());
+        if let Some(quantized) = quantizer.quantize_rect(&rect) {
+            for point in points.iter().filter(|p| rect.contains(p)) {
+                assert!(quantized.contains(&quantizer.quantize(point)));
+            }
+        }
+    }
+}
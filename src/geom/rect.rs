@@ -1,5 +1,8 @@
+/// Mask written by `Rect::contains_batch`, from the bit-vec crate.
+pub use bit_vec::BitVec;
 use geom::interval::{Interval, IntervalDomain};
 use geom::p2::P2;
+use geom::Error;
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Rect<S> {
@@ -20,6 +23,60 @@ where
         })
     }
 
+    /// Create a new rectangle, or report why it cannot be created.
+    pub fn try_new(x: S, y: S, width: S, height: S) -> Result<Self, Error> {
+        Ok(Rect {
+            x_interval: Interval::try_new(x, width)?,
+            y_interval: Interval::try_new(y, height)?,
+        })
+    }
+
+    /// Create the smallest rectangle that contains all of `points`.
+    ///
+    /// Points with coordinates that are not comparable with themselves (ie.
+    /// `NaN`s) are ignored. Returns `None` if there are no other points, or if
+    /// the rectangle cannot be represented.
+    pub fn bounding<'a, I>(points: I) -> Option<Self>
+    where
+        S: 'a,
+        I: IntoIterator<Item = &'a P2<S>>,
+    {
+        Rect::try_bounding(points).ok()
+    }
+
+    /// Create the smallest rectangle that contains all of `points`, or
+    /// report why it cannot be created.
+    pub fn try_bounding<'a, I>(points: I) -> Result<Self, Error>
+    where
+        S: 'a,
+        I: IntoIterator<Item = &'a P2<S>>,
+    {
+        let mut points = points.into_iter().filter(|point| {
+            point.x.partial_cmp(&point.x).is_some() && point.y.partial_cmp(&point.y).is_some()
+        });
+        let first = points.next().ok_or(Error::NoPoints)?;
+        let (mut min_x, mut max_x) = (first.x.clone(), first.x.clone());
+        let (mut min_y, mut max_y) = (first.y.clone(), first.y.clone());
+        for point in points {
+            if point.x < min_x {
+                min_x = point.x.clone();
+            }
+            if point.x > max_x {
+                max_x = point.x.clone();
+            }
+            if point.y < min_y {
+                min_y = point.y.clone();
+            }
+            if point.y > max_y {
+                max_y = point.y.clone();
+            }
+        }
+        Ok(Rect::new_from_intervals(
+            Interval::try_enclosing(min_x, max_x)?,
+            Interval::try_enclosing(min_y, max_y)?,
+        ))
+    }
+
     pub fn new_from_intervals(x_interval: Interval<S>, y_interval: Interval<S>) -> Self {
         Rect {
             x_interval,
@@ -27,6 +84,14 @@ where
         }
     }
 
+    pub fn x_interval(&self) -> &Interval<S> {
+        &self.x_interval
+    }
+
+    pub fn y_interval(&self) -> &Interval<S> {
+        &self.y_interval
+    }
+
     pub fn x(&self) -> &S {
         self.x_interval.start()
     }
@@ -47,6 +112,54 @@ where
         self.x_interval.contains(&point.x) && self.y_interval.contains(&point.y)
     }
 
+    /// Bits saying which of up to 32 points are in the rectangle, with the
+    /// first point in the lowest bit.
+    ///
+    /// The comparisons are made without branches, so that the loop can be
+    /// vectorized; this is the kernel of `contains_batch` and of the leaf
+    /// scans of `QuadTree`.
+    pub(crate) fn contains_block<'a, I>(&self, points: I) -> u32
+    where
+        S: 'a,
+        I: IntoIterator<Item = &'a P2<S>>,
+    {
+        let (x, y) = (&self.x_interval, &self.y_interval);
+        points
+            .into_iter()
+            .take(32)
+            .enumerate()
+            .fold(0, |bits, (i, point)| {
+                let inside = (&point.x >= x.start())
+                    & S::before_end(x, &point.x)
+                    & (&point.y >= y.start())
+                    & S::before_end(y, &point.y);
+                bits | ((inside as u32) << i)
+            })
+    }
+
+    /// Check which of `points` are in the rectangle, replacing the contents
+    /// of `mask` with one bit per point.
+    ///
+    /// ```
+    /// # use starquad::geom::{p2::P2, rect::{BitVec, Rect}};
+    /// let rect = Rect::new(0.0, 0.0, 1.0, 1.0).unwrap();
+    /// let mut mask = BitVec::new();
+    /// rect.contains_batch(&[P2::new(0.5, 0.5), P2::new(1.0, 0.5)], &mut mask);
+    /// assert_eq!(mask, BitVec::from_fn(2, |i| i == 0));
+    /// ```
+    pub fn contains_batch(&self, points: &[P2<S>], mask: &mut BitVec) {
+        mask.truncate(0);
+        mask.grow(points.len(), false);
+        for (block, chunk) in points.chunks(32).enumerate() {
+            let bits = self.contains_block(chunk);
+            // Safety: bit-vec requires the unused bits of the last block to
+            // be zero, and `bits` has no bits set past the end of `chunk`.
+            unsafe {
+                mask.storage_mut()[block] = bits;
+            }
+        }
+    }
+
     pub fn intersect(&self, other: &Rect<S>) -> Option<Self> {
         self.x_interval
             .intersect(&other.x_interval)
@@ -63,10 +176,13 @@ where
 
 #[cfg(test)]
 mod test {
-    use geom::interval::{Interval, IntervalDomain};
+    use alloc::vec::Vec;
+    use bit_vec::BitVec;
+    use core::convert::TryFrom;
+    use geom::interval::Interval;
     use geom::p2::P2;
     use geom::rect::Rect;
-    use quickcheck::{Arbitrary, Gen};
+    use paste::paste;
     use quickcheck_macros::quickcheck;
 
     #[test]
@@ -85,6 +201,15 @@ mod test {
         assert!(rect.contains(&P2::new(6, 40)));
     }
 
+    #[test]
+    fn bounding() {
+        let points = vec![P2::new(3, 9), P2::new(-2, 4), P2::new(5, 6)];
+        let rect = Rect::bounding(&points).unwrap();
+        assert_eq!(rect, Rect::new(-2, 4, 8, 6).unwrap());
+        assert!(points.iter().all(|point| rect.contains(point)));
+        assert_eq!(Rect::<i32>::bounding(&[]), None);
+    }
+
     #[test]
     fn intersect() {
         let rect_a = Rect::new(2.0, 1.0, 2.0, 4.0).unwrap();
@@ -93,19 +218,29 @@ mod test {
         assert_eq!(rect_a.intersect(&rect_b), Some(expected));
     }
 
-    impl<S> Arbitrary for Rect<S>
-    where
-        Interval<S>: Arbitrary,
-        S: Clone + IntervalDomain,
-    {
-        fn arbitrary<G>(g: &mut G) -> Self
-        where
-            G: Gen,
-        {
-            let x_interval = Interval::<S>::arbitrary(g);
-            let y_interval = Interval::<S>::arbitrary(g);
-            Rect::new_from_intervals(x_interval, y_interval)
-        }
+    #[quickcheck]
+    fn f64_contains_batch(rect: Rect<f64>, points: Vec<P2<f64>>) {
+        let mut mask = BitVec::from_elem(3, true);
+        rect.contains_batch(&points, &mut mask);
+        let expected = points.iter().map(|p| rect.contains(p)).collect::<Vec<_>>();
+        assert_eq!(mask.iter().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn contains_batch_at_type_limits() {
+        let rect = Rect::<u8>::new(254, 0, 2, 1).unwrap();
+        let points = (0..40).map(|i| P2::new(216 + i, 0)).collect::<Vec<_>>();
+        let mut mask = BitVec::new();
+        rect.contains_batch(&points, &mut mask);
+        assert_eq!(mask, BitVec::from_fn(40, |i| i >= 38));
+    }
+
+    #[quickcheck]
+    fn i8_contains_batch(rect: Rect<i8>, points: Vec<P2<i8>>) {
+        let mut mask = BitVec::new();
+        rect.contains_batch(&points, &mut mask);
+        let expected = points.iter().map(|p| rect.contains(p)).collect::<Vec<_>>();
+        assert_eq!(mask.iter().collect::<Vec<_>>(), expected);
     }
 
     #[quickcheck]
@@ -113,10 +248,83 @@ mod test {
         let opt_intersection = a.intersect(&b);
         if a.contains(&point) && b.contains(&point) {
             assert!(opt_intersection.unwrap().contains(&point));
-        } else {
-            if let Some(intersection) = opt_intersection {
-                assert!(!intersection.contains(&point));
-            }
+        } else if let Some(intersection) = opt_intersection {
+            assert!(!intersection.contains(&point));
         }
     }
+
+    /// Exhaustive tests of the rectangles of the integer types of 8 bits
+    /// whose sides start and end at or next to the limits of the type,
+    /// against the points they contain. Containment only changes at the
+    /// sides, so the points at those values and one between them are
+    /// enough to tell the rectangles apart.
+    macro_rules! check_exhaustive {
+        ($t:ty) => {
+            paste! {
+                #[test]
+                fn [<$t _exhaustive>]() {
+                    let (min, max) = (<$t>::MIN, <$t>::MAX);
+                    let ends = [min, min + 1, max - 1, max];
+                    let mut intervals = Vec::new();
+                    for &start in &ends {
+                        for &last in ends.iter().filter(|&&last| last >= start) {
+                            let diameter = i32::from(last) - i32::from(start) + 1;
+                            if let Ok(diameter) = <$t>::try_from(diameter) {
+                                intervals.extend(Interval::new(start, diameter));
+                            }
+                        }
+                    }
+                    assert!(intervals.iter().any(|i| *i.start() == max));
+                    let rects = intervals
+                        .iter()
+                        .flat_map(|x| {
+                            intervals
+                                .iter()
+                                .map(move |y| Rect::new_from_intervals(x.clone(), y.clone()))
+                        })
+                        .collect::<Vec<_>>();
+                    let values = [min, min + 1, min + 2, max / 2, max - 2, max - 1, max];
+                    let points = values
+                        .iter()
+                        .flat_map(|&x| values.iter().map(move |&y| P2::new(x, y)))
+                        .collect::<Vec<_>>();
+                    let inside = |interval: &Interval<$t>, value: $t| {
+                        let (start, value) = (i32::from(*interval.start()), i32::from(value));
+                        start <= value && value < start + i32::from(*interval.diameter())
+                    };
+
+                    let mut mask = BitVec::new();
+                    for rect in &rects {
+                        let expected = points
+                            .iter()
+                            .map(|p| inside(rect.x_interval(), p.x) && inside(rect.y_interval(), p.y))
+                            .collect::<Vec<_>>();
+                        let contains = points.iter().map(|p| rect.contains(p)).collect::<Vec<_>>();
+                        assert_eq!(contains, expected, "{:?}", rect);
+                        rect.contains_batch(&points, &mut mask);
+                        assert_eq!(mask.iter().collect::<Vec<_>>(), expected, "{:?}", rect);
+                    }
+
+                    for a in &rects {
+                        for b in &rects {
+                            let both = points
+                                .iter()
+                                .map(|p| a.contains(p) && b.contains(p))
+                                .collect::<Vec<_>>();
+                            match a.intersect(b) {
+                                Some(rect) => {
+                                    let contains = points.iter().map(|p| rect.contains(p));
+                                    assert_eq!(contains.collect::<Vec<_>>(), both, "{:?} {:?}", a, b);
+                                }
+                                None => assert!(both.iter().all(|b| !b), "{:?} {:?}", a, b),
+                            }
+                        }
+                    }
+                }
+            }
+        };
+    }
+
+    check_exhaustive!(u8);
+    check_exhaustive!(i8);
 }
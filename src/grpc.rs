@@ -0,0 +1,368 @@
+//! gRPC service answering queries of a `Store`.
+//!
+//! The service is defined by `grpc/proto/starquad.proto`. Records are
+//! returned in column-oriented batches, which are much cheaper to encode and
+//! decode than the rows of the HTTP service.
+
+// `tonic::Status` is large, but is the error type of every gRPC method
+#![allow(clippy::result_large_err)]
+
+use auth::{Auth, Credentials, Denied, API_KEY_HEADER, AUTHORIZATION_HEADER};
+use gaia::columnar::{ColumnValues, ColumnarCatalog};
+use gaia::filter::Filter;
+use gaia::projection::Projection;
+use gaia::record::GaiaRecord;
+use sky::position::SkyPosition;
+use sky::region::Region;
+use starquad_grpc::messages::{region, Column, CountResponse, QueryRequest, RecordBatch};
+use starquad_grpc::{StarQuad, StarQuadServer};
+use std::io;
+use std::net::TcpListener;
+use std::sync::Arc;
+use store::{Query, Store};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::service::interceptor::{InterceptedService, Interceptor};
+use tonic::transport::Server;
+use tonic::Status;
+
+/// Number of rows in a batch when a request does not give one.
+pub const DEFAULT_BATCH_SIZE: usize = 8192;
+
+/// Largest number of rows in a batch.
+pub const MAX_BATCH_SIZE: usize = 1 << 20;
+
+/// Number of batches which may be buffered before the query waits for the
+/// client.
+const CHANNEL_CAPACITY: usize = 4;
+
+/// A parsed `QueryRequest`.
+struct Request {
+    region: Region,
+    filter: Option<Filter>,
+    projection: Projection,
+    batch_size: usize,
+}
+
+impl Request {
+    fn parse(request: QueryRequest) -> Result<Self, Status> {
+        let region = match request.region.and_then(|r| r.shape) {
+            Some(region::Shape::Cone(cone)) if cone.radius >= 0.0 => {
+                Region::cone(SkyPosition::new(cone.ra, cone.dec), cone.radius)
+            }
+            Some(region::Shape::Cone(_)) => {
+                return Err(Status::invalid_argument("radius must not be negative"))
+            }
+            Some(region::Shape::Box(b)) => {
+                Region::sky_box(b.ra_min, b.ra_max, b.dec_min, b.dec_max)
+                    .map_err(|e| Status::invalid_argument(e.to_string()))?
+            }
+            None => return Err(Status::invalid_argument("a region is required")),
+        };
+        let filter = if request.filter.trim().is_empty() {
+            None
+        } else {
+            Some(
+                request
+                    .filter
+                    .parse::<Filter>()
+                    .map_err(|e| Status::invalid_argument(format!("invalid filter: {}", e)))?,
+            )
+        };
+        let projection = if request.columns.is_empty() {
+            Projection::all()
+        } else {
+            Projection::new(&request.columns)
+                .map_err(|e| Status::invalid_argument(e.to_string()))?
+        };
+        let batch_size = match request.batch_size as usize {
+            0 => DEFAULT_BATCH_SIZE,
+            size => size.min(MAX_BATCH_SIZE),
+        };
+        Ok(Request {
+            region,
+            filter,
+            projection,
+            batch_size,
+        })
+    }
+}
+
+/// Convert the columns of a catalog to a batch.
+fn record_batch(catalog: &ColumnarCatalog) -> RecordBatch {
+    let columns = catalog
+        .column_names()
+        .into_iter()
+        .filter_map(|name| catalog.column(name))
+        .map(|column| {
+            let mut message = Column {
+                name: String::from(column.name()),
+                ..Column::default()
+            };
+            if column.validity().is_some() {
+                message.validity = (0..catalog.len()).map(|row| column.is_valid(row)).collect();
+            }
+            match column.values() {
+                ColumnValues::Boolean(values) => message.booleans = values.clone(),
+                ColumnValues::UnsignedByte(values) => {
+                    message.longs = values.iter().map(|&v| i64::from(v)).collect()
+                }
+                ColumnValues::Long(values) => message.longs = values.clone(),
+                ColumnValues::Double(values) => message.doubles = values.clone(),
+                ColumnValues::Text(values) => message.strings = values.clone(),
+            }
+            message
+        })
+        .collect();
+    RecordBatch {
+        num_rows: catalog.len() as u64,
+        columns,
+    }
+}
+
+/// Convert records to a batch of the requested columns.
+fn batch(request: &Request, records: &[GaiaRecord]) -> Result<RecordBatch, Status> {
+    let mut catalog = ColumnarCatalog::new(&request.projection.names())
+        .map_err(|e| Status::internal(e.to_string()))?;
+    catalog
+        .extend(records)
+        .map_err(|e| Status::internal(e.to_string()))?;
+    Ok(record_batch(&catalog))
+}
+
+/// Records of the store which match a request.
+fn query(store: &Store, request: &Request) -> Query {
+    let query = store.query(&request.region).select(&request.projection);
+    match &request.filter {
+        Some(filter) => query.matching(filter),
+        None => query,
+    }
+}
+
+/// Send the records of a query in batches.
+fn send_batches(
+    store: &Store,
+    request: &Request,
+    sender: &mpsc::Sender<Result<RecordBatch, Status>>,
+) -> Result<(), Status> {
+    let mut records = Vec::with_capacity(request.batch_size);
+    let mut query = query(store, request).peekable();
+    while let Some(record) = query.next() {
+        records.push(record.map_err(|e| Status::internal(e.to_string()))?);
+        if records.len() == request.batch_size || (query.peek().is_none() && !records.is_empty()) {
+            sender
+                .blocking_send(Ok(batch(request, &records)?))
+                .map_err(|_| Status::cancelled("client disconnected"))?;
+            records.clear();
+        }
+    }
+    Ok(())
+}
+
+/// Implementation of the gRPC service over a store.
+pub struct StoreService {
+    store: Arc<Store>,
+}
+
+impl StoreService {
+    pub fn new(store: Store) -> Self {
+        StoreService {
+            store: Arc::new(store),
+        }
+    }
+}
+
+impl StarQuad for StoreService {
+    type QueryStream = ReceiverStream<Result<RecordBatch, Status>>;
+
+    fn query(&self, request: QueryRequest) -> Result<Self::QueryStream, Status> {
+        let request = Request::parse(request)?;
+        let store = self.store.clone();
+        let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+        tokio::task::spawn_blocking(move || {
+            // an error stops the query, and is sent to the client
+            if let Err(status) = send_batches(&store, &request, &sender) {
+                let _ = sender.blocking_send(Err(status));
+            }
+        });
+        Ok(ReceiverStream::new(receiver))
+    }
+
+    fn count(&self, request: QueryRequest) -> Result<CountResponse, Status> {
+        let request = Request::parse(request)?;
+        let mut count = 0;
+        for record in query(&self.store, &request) {
+            record.map_err(|e| Status::internal(e.to_string()))?;
+            count += 1;
+        }
+        Ok(CountResponse { count })
+    }
+}
+
+/// Interceptor refusing the requests which an `Auth` does not allow.
+#[derive(Clone)]
+struct AuthInterceptor(Option<Arc<Auth>>);
+
+impl Interceptor for AuthInterceptor {
+    fn call(&mut self, request: tonic::Request<()>) -> Result<tonic::Request<()>, Status> {
+        let auth = match &self.0 {
+            Some(auth) => auth,
+            None => return Ok(request),
+        };
+        let metadata = |key: &str| {
+            request
+                .metadata()
+                .get(key)
+                .and_then(|value| value.to_str().ok())
+        };
+        let credentials = Credentials {
+            authorization: metadata(AUTHORIZATION_HEADER),
+            api_key: metadata(API_KEY_HEADER),
+        };
+        match auth.check(&credentials) {
+            Ok(_) => Ok(request),
+            Err(denied @ Denied::RateLimited(_)) => {
+                Err(Status::resource_exhausted(denied.to_string()))
+            }
+            Err(denied) => Err(Status::unauthenticated(denied.to_string())),
+        }
+    }
+}
+
+/// Serve queries of a store on a listening socket, until an error occurs,
+/// refusing the requests which `auth` does not allow.
+pub fn serve(store: Store, auth: Option<Auth>, listener: TcpListener) -> io::Result<()> {
+    let runtime = tokio::runtime::Runtime::new()?;
+    let _guard = runtime.enter();
+    listener.set_nonblocking(true)?;
+    let incoming = TcpListenerStream::new(tokio::net::TcpListener::from_std(listener)?);
+    runtime
+        .block_on(
+            Server::builder()
+                .add_service(InterceptedService::new(
+                    StarQuadServer::new(StoreService::new(store)),
+                    AuthInterceptor(auth.map(Arc::new)),
+                ))
+                .serve_with_incoming(incoming),
+        )
+        .map_err(io::Error::other)
+}
+
+#[cfg(test)]
+mod test {
+    use auth::{ApiKeys, Auth, RateLimit};
+    use gaia::record::test::sample_record;
+    use grpc::serve;
+    use starquad_grpc::messages::{region, Cone, QueryRequest, RaDecBox, Region};
+    use starquad_grpc::StarQuadClient;
+    use std::env;
+    use std::fs;
+    use std::net::TcpListener;
+    use std::thread;
+    use store::builder::StoreBuilder;
+    use store::Store;
+    use tonic::Code;
+
+    #[test]
+    fn queries() {
+        let dir = env::temp_dir().join(format!("starquad-grpc-test-{}", std::process::id()));
+        let mut builder = StoreBuilder::new(&dir).unwrap().with_order(1);
+        for i in 0..100 {
+            let mut record = sample_record();
+            record.source_id = 5000 + i;
+            record.ra = i as f64;
+            record.dec = 10.0;
+            record.parallax = if i % 2 == 0 { Some(1.0) } else { None };
+            builder.push(&record).unwrap();
+        }
+        builder.finish().unwrap();
+        let store = Store::open(&dir).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || serve(store, None, listener));
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let mut client = runtime.block_on(StarQuadClient::connect(address)).unwrap();
+        let request = QueryRequest {
+            region: Some(Region {
+                shape: Some(region::Shape::Box(RaDecBox {
+                    ra_min: 9.5,
+                    ra_max: 39.5,
+                    dec_min: 0.0,
+                    dec_max: 20.0,
+                })),
+            }),
+            filter: String::from("parallax != null"),
+            columns: vec![String::from("source_id"), String::from("parallax")],
+            batch_size: 4,
+        };
+        let count = runtime.block_on(client.count(request.clone())).unwrap();
+        assert_eq!(count.count, 15);
+
+        let mut batches = runtime.block_on(client.query(request)).unwrap();
+        let mut ids = Vec::new();
+        while let Some(batch) = runtime.block_on(batches.message()).unwrap() {
+            assert!(batch.num_rows <= 4);
+            let names = batch
+                .columns
+                .iter()
+                .map(|c| c.name.as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(names, vec!["source_id", "parallax", "ra", "dec"]);
+            assert!(batch.columns[1].validity.iter().all(|&v| v));
+            ids.extend(batch.columns[0].longs.iter().cloned());
+        }
+        ids.sort();
+        assert_eq!(ids, (5010..5040).step_by(2).collect::<Vec<_>>());
+
+        let invalid = QueryRequest {
+            region: Some(Region {
+                shape: Some(region::Shape::Cone(Cone {
+                    ra: 0.0,
+                    dec: 0.0,
+                    radius: -1.0,
+                })),
+            }),
+            ..QueryRequest::default()
+        };
+        let status = runtime.block_on(client.count(invalid)).unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn auth() {
+        let dir = env::temp_dir().join(format!("starquad-grpc-auth-{}", std::process::id()));
+        let mut builder = StoreBuilder::new(&dir).unwrap().with_order(1);
+        builder.push(&sample_record()).unwrap();
+        builder.finish().unwrap();
+        let store = Store::open(&dir).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let auth = Auth::new(ApiKeys::new().with_key("alice", "k1").unwrap())
+            .with_rate_limit(RateLimit::new(0.01, 1).unwrap());
+        thread::spawn(move || serve(store, Some(auth), listener));
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let client = runtime.block_on(StarQuadClient::connect(address)).unwrap();
+        let request = QueryRequest {
+            region: Some(Region {
+                shape: Some(region::Shape::Cone(Cone {
+                    ra: 0.0,
+                    dec: 0.0,
+                    radius: 180.0,
+                })),
+            }),
+            ..QueryRequest::default()
+        };
+        let count = |client: &StarQuadClient| {
+            let mut client = client.clone();
+            runtime.block_on(client.count(request.clone()))
+        };
+        assert_eq!(count(&client).unwrap_err().code(), Code::Unauthenticated);
+        let client = client.with_metadata("x-api-key", "k1").unwrap();
+        assert_eq!(count(&client).unwrap().count, 1);
+        assert_eq!(count(&client).unwrap_err().code(), Code::ResourceExhausted);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,10 +1,154 @@
+//! Spatial indexing and cross-matching of Gaia catalogs.
+//!
+//! - `geom` and `accel2d`: intervals, rectangles, polygons, the quadtree, the
+//!   priority search tree, the range tree and the bounding volume hierarchy,
+//!   and `SharedIndex` for querying them from many threads;
+//! - `accel1d`: the interval tree, for extents of a line;
+//! - `accel3d`: the k-d tree, for points in space, with pluggable distance
+//!   metrics;
+//! - `sky`: positions on the sky, regions, HEALPix and in-memory sky indexes;
+//! - `gaia`: Gaia records and their CSV files, filters and projections;
+//! - `catalog`: reading and writing other catalog formats;
+//! - `crossmatch`: matching the sources of two catalogs;
+//! - `store`: on-disk indexes, built once and queried by region;
+//! - `output`: writing records as CSV, JSON lines or bincode;
+//! - `render`: drawing indexes and their items as SVG or PNG;
+//! - `arbitrary`: `quickcheck` generators for these types, with the
+//!   `arbitrary` feature.
+//!
+//! The most common types are gathered in `prelude`, and every module's
+//! errors convert into `Error`.
+//!
+//! Without the default `std` feature, only `geom`, `accel1d`, `accel2d` and
+//! `accel3d` are built (less `accel3d::metric`), and they need only `core`
+//! and `alloc`. With `std` but without the default `fs` feature, `sky` is
+//! built as well, but nothing which reads or writes files.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(all(feature = "fs", target_arch = "wasm32", target_os = "unknown"))]
+compile_error!("the `fs` feature needs files, which `wasm32-unknown-unknown` does not have");
+
+#[cfg_attr(not(feature = "std"), macro_use)]
+extern crate alloc;
+#[cfg(feature = "arrow")]
+extern crate arrow_array;
+#[cfg(feature = "arrow")]
+extern crate arrow_cast;
+#[cfg(feature = "arrow")]
+extern crate arrow_ipc;
+#[cfg(feature = "arrow")]
+extern crate arrow_schema;
+#[cfg(feature = "server")]
+extern crate axum;
+#[cfg(feature = "fs")]
+extern crate base64;
+#[cfg(feature = "fs")]
+extern crate bincode;
+extern crate bit_vec;
+#[cfg(feature = "server")]
+extern crate bytes;
+// `thiserror` derives refer to `::core`, which in the 2015 edition must be
+// declared at the crate root (`no_std` declares it already).
+#[cfg(feature = "std")]
+extern crate core;
+#[cfg(feature = "fs")]
+extern crate crc32fast;
+#[cfg(feature = "fs")]
+extern crate csv;
+#[cfg(feature = "fs")]
+extern crate flate2;
+#[cfg(feature = "geo-types")]
+extern crate geo_types;
+#[cfg(feature = "fs")]
+extern crate md5;
+#[cfg(feature = "fs")]
+extern crate memmap2;
 extern crate num;
+#[cfg(feature = "python")]
+extern crate numpy;
+#[cfg(feature = "parquet")]
+extern crate parquet;
 #[cfg(test)]
 extern crate paste;
-#[cfg(test)]
+#[cfg(feature = "fs")]
+extern crate png;
+#[cfg(feature = "python")]
+extern crate pyo3;
+#[cfg(feature = "fs")]
+extern crate quick_xml;
+#[cfg(any(test, feature = "arbitrary"))]
 extern crate quickcheck;
 #[cfg(test)]
 extern crate quickcheck_macros;
+#[cfg(feature = "std")]
+extern crate rand;
+#[cfg(feature = "std")]
+extern crate rand_chacha;
+#[cfg(feature = "parallel")]
+extern crate rayon;
+#[cfg(feature = "rstar")]
+extern crate rstar;
+#[cfg(feature = "sqlite")]
+extern crate rusqlite;
+#[cfg(feature = "std")]
+extern crate serde;
+#[cfg(feature = "std")]
+extern crate serde_json;
+extern crate smallvec;
+#[cfg(feature = "grpc")]
+extern crate starquad_grpc;
+extern crate thiserror;
+#[cfg(any(feature = "server", feature = "grpc"))]
+extern crate tokio;
+#[cfg(any(feature = "server", feature = "grpc"))]
+extern crate tokio_stream;
+#[cfg(feature = "grpc")]
+extern crate tonic;
+#[cfg(feature = "fs")]
+extern crate tracing;
+#[cfg(feature = "gpu")]
+extern crate wgpu;
+#[cfg(feature = "zstd")]
+extern crate zstd;
 
+pub mod accel1d;
 pub mod accel2d;
+pub mod accel3d;
+#[cfg(any(test, feature = "arbitrary"))]
+pub mod arbitrary;
+#[cfg(any(feature = "server", feature = "grpc"))]
+pub mod auth;
+#[cfg(feature = "fs")]
+pub mod catalog;
+#[cfg(feature = "fs")]
+pub mod crossmatch;
+#[cfg(feature = "fs")]
+pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "fs")]
+pub mod gaia;
 pub mod geom;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod mst;
+#[cfg(feature = "fs")]
+pub mod output;
+#[cfg(feature = "fs")]
+pub mod prelude;
+#[cfg(feature = "fs")]
+pub mod progress;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "fs")]
+pub mod render;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "std")]
+pub mod sky;
+#[cfg(feature = "fs")]
+pub mod store;
+
+#[cfg(feature = "fs")]
+pub use error::{Error, Result};
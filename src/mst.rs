@@ -0,0 +1,135 @@
+//! Euclidean minimum spanning trees of the items of spatial indexes, by
+//! Borůvka's algorithm, for `QuadTree` in the plane and `KdTree` in space.
+
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+/// Index which can find the shortest edge out of each component of a forest
+/// over its items, which is the step of Borůvka's algorithm.
+pub trait Boruvka {
+    /// Square of the length of an edge.
+    type Length: PartialOrd + Copy;
+
+    /// Number of items, which are named by their places, from 0.
+    fn places(&self) -> usize;
+
+    /// Shortest edge out of each component of the items, by the place
+    /// naming it: its length squared, and the places at its ends, inside
+    /// and outside the component. `component` names the component of each
+    /// place by one of its places.
+    ///
+    /// Edges of the same length are ordered by the places at their ends (as
+    /// `(min, max)`), so that the edges chosen for different components
+    /// never make a cycle. Items which cannot be compared (points with a
+    /// `NaN`) have no edges.
+    fn shortest_edges(&self, component: &[usize]) -> Vec<Option<(Self::Length, usize, usize)>>;
+
+    /// Euclidean minimum spanning tree of the items, as edges between their
+    /// places and the squares of their lengths, shortest first.
+    ///
+    /// Cutting the edges longer than a linking length splits the items into
+    /// the same groups as a friends-of-friends search. Items which cannot be
+    /// compared are left unconnected, so the result is a forest if there are
+    /// any.
+    ///
+    /// Every component of the forest built so far is joined to its nearest
+    /// other component in each round, so there are at most `log2(n)` rounds.
+    ///
+    /// ```
+    /// # use starquad::accel2d::Accel2D;
+    /// # use starquad::accel2d::quadtree::QuadTree;
+    /// # use starquad::accel3d::kdtree::KdTree;
+    /// # use starquad::geom::{p2::P2, p3::P3};
+    /// # use starquad::mst::Boruvka;
+    /// let points = vec![P2::new(0.0, 0.0), P2::new(1.0, 0.0), P2::new(5.0, 0.0), P2::new(1.0, 2.0)];
+    /// let tree = QuadTree::new_from_vec(points.iter().map(|p| (*p, ())).collect());
+    /// let lengths_2 = tree.minimum_spanning_tree().iter().map(|e| e.2).collect::<Vec<_>>();
+    /// assert_eq!(lengths_2, vec![1.0, 4.0, 16.0]);
+    ///
+    /// let tree = KdTree::new(points.iter().map(|p| (P3::new(p.x, 0.0, p.y), ())).collect());
+    /// let lengths_2 = tree.minimum_spanning_tree().iter().map(|e| e.2).collect::<Vec<_>>();
+    /// assert_eq!(lengths_2, vec![1.0, 4.0, 16.0]);
+    /// ```
+    fn minimum_spanning_tree(&self) -> Vec<(usize, usize, Self::Length)> {
+        let len = self.places();
+        let mut sets = DisjointSets::new(len);
+        let mut edges = Vec::new();
+        loop {
+            let component = (0..len).map(|place| sets.find(place)).collect::<Vec<_>>();
+            let joined = edges.len();
+            for (length, a, b) in self.shortest_edges(&component).into_iter().flatten() {
+                if sets.union(a, b) {
+                    edges.push((a, b, length));
+                }
+            }
+            if edges.len() == joined {
+                break;
+            }
+        }
+        edges.sort_by(|a, b| a.2.partial_cmp(&b.2).unwrap_or(Ordering::Equal));
+        edges
+    }
+}
+
+/// Whether the edge from `place` to `other` of length squared `length` is
+/// shorter than `best`, in the order of `Boruvka::shortest_edges`.
+pub(crate) fn shorter<L: PartialOrd>(
+    length: L,
+    place: usize,
+    other: usize,
+    best: &Option<(L, usize, usize)>,
+) -> bool {
+    let ends = (place.min(other), place.max(other));
+    match best {
+        Some((best, a, b)) => {
+            length < *best || (length == *best && ends < ((*a).min(*b), (*a).max(*b)))
+        }
+        None => length.partial_cmp(&length).is_some(),
+    }
+}
+
+/// Components of the items under a node of an index.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Component {
+    Empty,
+    One(usize),
+    Mixed,
+}
+
+impl Component {
+    pub(crate) fn join(self, other: Component) -> Component {
+        match (self, other) {
+            (Component::Empty, label) | (label, Component::Empty) => label,
+            (Component::One(a), Component::One(b)) if a == b => self,
+            _ => Component::Mixed,
+        }
+    }
+}
+
+/// Union-find over places.
+struct DisjointSets {
+    parent: Vec<usize>,
+}
+
+impl DisjointSets {
+    fn new(len: usize) -> Self {
+        DisjointSets {
+            parent: (0..len).collect(),
+        }
+    }
+
+    fn find(&mut self, mut place: usize) -> usize {
+        while self.parent[place] != place {
+            self.parent[place] = self.parent[self.parent[place]];
+            place = self.parent[place];
+        }
+        place
+    }
+
+    /// Join the sets of two places, returning whether they were apart.
+    fn union(&mut self, a: usize, b: usize) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        self.parent[a.max(b)] = a.min(b);
+        a != b
+    }
+}
@@ -0,0 +1,153 @@
+use arrow_ipc::writer::StreamWriter;
+use catalog::arrow::{self, gaia_record_batch, gaia_schema, DEFAULT_BATCH_SIZE};
+use gaia::projection::Projection;
+use gaia::record::GaiaRecord;
+use output::{Error, RecordSink};
+use std::io;
+use std::io::{BufWriter, Write};
+
+/// Sink writing Gaia records as an Arrow IPC stream, which pyarrow, the Java
+/// Arrow libraries and others read without parsing text.
+///
+/// Records are written in record batches of the selected columns, with the
+/// types of `catalog::arrow::gaia_schema`. The stream is only valid once the
+/// sink is finished.
+pub struct ArrowSink<W: Write> {
+    writer: Option<BufWriter<W>>,
+    stream: Option<StreamWriter<BufWriter<W>>>,
+    projection: Projection,
+    batch_size: usize,
+    records: Vec<GaiaRecord>,
+}
+
+impl<W: Write> ArrowSink<W> {
+    pub fn new(writer: W) -> Self {
+        ArrowSink {
+            writer: Some(BufWriter::new(writer)),
+            stream: None,
+            projection: Projection::all(),
+            batch_size: DEFAULT_BATCH_SIZE,
+            records: Vec::new(),
+        }
+    }
+
+    /// Write only the columns of a projection.
+    pub fn with_projection(mut self, projection: Projection) -> Self {
+        self.projection = projection;
+        self
+    }
+
+    /// Write batches of up to `batch_size` records. Smaller batches reach a
+    /// reader sooner, and larger ones are quicker to read.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Stream writer, which writes the schema when it is created.
+    fn stream(&mut self) -> Result<&mut StreamWriter<BufWriter<W>>, Error> {
+        if let Some(writer) = self.writer.take() {
+            let schema = gaia_schema()
+                .project(self.projection.columns())
+                .map_err(arrow::Error::from)?;
+            self.stream = Some(StreamWriter::try_new(writer, &schema).map_err(arrow::Error::from)?);
+        }
+        self.stream
+            .as_mut()
+            .ok_or_else(|| Error::from(io::Error::other("Arrow stream could not be started")))
+    }
+
+    /// Write the buffered records as a record batch.
+    fn write_batch(&mut self) -> Result<(), Error> {
+        if self.records.is_empty() {
+            return Ok(());
+        }
+        let batch = gaia_record_batch(&self.records)?
+            .project(self.projection.columns())
+            .map_err(arrow::Error::from)?;
+        self.records.clear();
+        self.stream()?
+            .write(&batch)
+            .map_err(|e| Error::from(arrow::Error::from(e)))
+    }
+}
+
+impl<W: Write> RecordSink<GaiaRecord> for ArrowSink<W> {
+    fn write(&mut self, record: &GaiaRecord) -> Result<(), Error> {
+        self.records.push(record.clone());
+        if self.records.len() >= self.batch_size {
+            self.write_batch()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.write_batch()?;
+        let stream = self.stream()?;
+        stream.finish().map_err(arrow::Error::from)?;
+        stream.get_mut().flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use arrow_array::{Array, Float64Array};
+    use arrow_ipc::reader::StreamReader;
+    use gaia::projection::Projection;
+    use gaia::record::test::sample_record;
+    use output::arrow_sink::ArrowSink;
+    use output::RecordSink;
+
+    #[test]
+    fn stream_round_trip() {
+        let records = (0..5)
+            .map(|i| {
+                let mut record = sample_record();
+                record.ra = i as f64;
+                record
+            })
+            .collect::<Vec<_>>();
+        let mut output = Vec::new();
+        {
+            let projection = Projection::new(&["source_id", "ra"]).unwrap();
+            let mut sink = ArrowSink::new(&mut output)
+                .with_projection(projection)
+                .with_batch_size(2);
+            sink.write_all(&records).unwrap();
+            sink.finish().unwrap();
+        }
+        let reader = StreamReader::try_new(&output[..], None).unwrap();
+        let names = reader
+            .schema()
+            .fields()
+            .iter()
+            .map(|f| f.name().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["source_id", "ra"]);
+        let batches = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(
+            batches.iter().map(|b| b.num_rows()).collect::<Vec<_>>(),
+            vec![2, 2, 1]
+        );
+        let ra = batches[2]
+            .column(1)
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(ra.value(0), 4.0);
+    }
+
+    #[test]
+    fn empty_stream() {
+        let mut output = Vec::new();
+        {
+            let mut sink = ArrowSink::new(&mut output);
+            sink.finish().unwrap();
+            assert!(sink.finish().is_err());
+        }
+        let reader = StreamReader::try_new(&output[..], None).unwrap();
+        assert_eq!(reader.schema().fields().len(), 94);
+        assert_eq!(reader.count(), 0);
+    }
+}
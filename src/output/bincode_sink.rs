@@ -0,0 +1,106 @@
+use output::{Error, RecordSink};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::convert::TryFrom;
+use std::io;
+use std::io::{BufWriter, Read, Write};
+use std::marker::PhantomData;
+
+/// Sink writing bincode-encoded records, each prefixed by its length in bytes
+/// as a little-endian `u32`.
+///
+/// The length prefixes allow a consumer to split the stream into records
+/// without decoding them. [BincodeReader](BincodeReader) reads the stream
+/// back.
+pub struct BincodeSink<W: Write> {
+    writer: BufWriter<W>,
+}
+
+impl<W: Write> BincodeSink<W> {
+    pub fn new(writer: W) -> Self {
+        BincodeSink {
+            writer: BufWriter::new(writer),
+        }
+    }
+}
+
+impl<W: Write, T: Serialize> RecordSink<T> for BincodeSink<W> {
+    fn write(&mut self, record: &T) -> Result<(), Error> {
+        let bytes = bincode::serialize(record)?;
+        let len = u32::try_from(bytes.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "record too large"))?;
+        self.writer.write_all(&len.to_le_bytes())?;
+        self.writer.write_all(&bytes)?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Reader of records written by a `BincodeSink`.
+pub struct BincodeReader<R, T> {
+    reader: R,
+    record: PhantomData<T>,
+}
+
+impl<R: Read, T: DeserializeOwned> BincodeReader<R, T> {
+    pub fn new(reader: R) -> Self {
+        BincodeReader {
+            reader,
+            record: PhantomData,
+        }
+    }
+
+    fn read_record(&mut self) -> Result<Option<T>, Error> {
+        let mut len = [0u8; 4];
+        match self.reader.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(Error::from(e)),
+        }
+        let mut bytes = vec![0; u32::from_le_bytes(len) as usize];
+        self.reader.read_exact(&mut bytes)?;
+        Ok(Some(bincode::deserialize(&bytes)?))
+    }
+}
+
+impl<R: Read, T: DeserializeOwned> Iterator for BincodeReader<R, T> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use gaia::record::test::sample_record;
+    use gaia::record::GaiaRecord;
+    use output::bincode_sink::{BincodeReader, BincodeSink};
+    use output::RecordSink;
+
+    #[test]
+    fn round_trip() {
+        let records = (0..5)
+            .map(|i| {
+                let mut record = sample_record();
+                record.source_id = i;
+                record.designation = format!("Gaia DR2 {}", i);
+                record
+            })
+            .collect::<Vec<_>>();
+        let mut output = Vec::new();
+        {
+            let mut sink = BincodeSink::new(&mut output);
+            sink.write_all(&records).unwrap();
+            RecordSink::<GaiaRecord>::finish(&mut sink).unwrap();
+        }
+        let read = BincodeReader::<_, GaiaRecord>::new(output.as_slice())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(read, records);
+    }
+}
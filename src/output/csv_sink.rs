@@ -0,0 +1,34 @@
+use csv::Writer;
+use output::{Error, RecordSink};
+use serde::Serialize;
+use std::io::Write;
+
+/// Sink writing records as CSV, with a header taken from the field names of
+/// the first record.
+pub struct CsvSink<W: Write> {
+    writer: Writer<W>,
+}
+
+impl<W: Write> CsvSink<W> {
+    pub fn new(writer: W) -> Self {
+        CsvSink {
+            writer: Writer::from_writer(writer),
+        }
+    }
+
+    pub fn from_csv(writer: Writer<W>) -> Self {
+        CsvSink { writer }
+    }
+}
+
+impl<W: Write, T: Serialize> RecordSink<T> for CsvSink<W> {
+    fn write(&mut self, record: &T) -> Result<(), Error> {
+        self.writer.serialize(record)?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
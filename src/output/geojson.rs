@@ -0,0 +1,308 @@
+//! [GeoJSON](https://datatracker.ietf.org/doc/html/rfc7946) documents of
+//! rectangles, polygons and sky regions, for inspecting footprints and maps
+//! in geographic tools.
+//!
+//! Coordinates are `[ra, dec]` in degrees, as a longitude and latitude.
+//! They are not wrapped into -180 to 180 degrees, so that a shape across
+//! `ra = 0` or `ra = 180` stays in one piece; a ring around a cone has right
+//! ascensions within 180 degrees of its center. Exterior rings go
+//! counter-clockwise, as RFC 7946 recommends.
+
+use geom::polygon::Polygon;
+use geom::rect::Rect;
+use output::Error;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use sky::density::Tile;
+use sky::position::SkyPosition;
+use sky::region::Region;
+use std::f64::consts::PI;
+use std::io::Write;
+use std::iter::FromIterator;
+
+/// Number of vertices of the polygon approximating a cone.
+pub const CONE_VERTICES: usize = 72;
+
+/// Closed ring of `[ra, dec]` positions, whose last position is its first.
+pub type Ring = Vec<[f64; 2]>;
+
+/// GeoJSON geometry.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", content = "coordinates")]
+pub enum Geometry {
+    /// An exterior ring followed by the rings of any holes.
+    Polygon(Vec<Ring>),
+    MultiPolygon(Vec<Vec<Ring>>),
+}
+
+/// GeoJSON feature: a geometry with properties.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type")]
+pub struct Feature {
+    pub geometry: Geometry,
+    pub properties: Map<String, Value>,
+}
+
+/// GeoJSON document of a list of features.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(tag = "type")]
+pub struct FeatureCollection {
+    pub features: Vec<Feature>,
+}
+
+/// Close a ring of vertices by repeating the first.
+fn closed(mut ring: Ring) -> Ring {
+    if let Some(&first) = ring.first() {
+        ring.push(first);
+    }
+    ring
+}
+
+/// Ring of the rectangle from `(x0, y0)` to `(x1, y1)`.
+fn rect_ring(x0: f64, y0: f64, x1: f64, y1: f64) -> Ring {
+    closed(vec![[x0, y0], [x1, y0], [x1, y1], [x0, y1]])
+}
+
+fn ring_of(rect: &Rect<f64>) -> Ring {
+    let (x1, y1) = (rect.x_interval().end(), rect.y_interval().end());
+    rect_ring(*rect.x(), *rect.y(), x1, y1)
+}
+
+impl From<&Rect<f64>> for Geometry {
+    fn from(rect: &Rect<f64>) -> Self {
+        Geometry::Polygon(vec![ring_of(rect)])
+    }
+}
+
+impl From<&Polygon<f64>> for Geometry {
+    fn from(polygon: &Polygon<f64>) -> Self {
+        let ring = polygon.vertices().iter().map(|v| [v.x, v.y]).collect();
+        Geometry::Polygon(vec![closed(ring)])
+    }
+}
+
+impl From<&Region> for Geometry {
+    fn from(region: &Region) -> Self {
+        match region {
+            Region::Rect(rect) => Geometry::from(rect),
+            Region::Cone { center, radius } => Geometry::Polygon(cone(center, *radius)),
+            Region::Rects(rects) => {
+                Geometry::MultiPolygon(rects.iter().map(|r| vec![ring_of(r)]).collect())
+            }
+            Region::Polygon(polygon) => Geometry::from(polygon),
+        }
+    }
+}
+
+/// Rings of a polygon of `CONE_VERTICES` positions on the edge of a cone of
+/// `radius` degrees: its exterior, and any hole. A cone over a pole runs
+/// along the pole's side of the map, 360 degrees wide; a cone over both
+/// poles is the whole map with a hole around the opposite cone.
+///
+/// ```
+/// # use starquad::output::geojson::{cone, CONE_VERTICES};
+/// # use starquad::sky::position::SkyPosition;
+/// let rings = cone(&SkyPosition::new(359.0, 10.0), 2.0);
+/// assert_eq!(rings.len(), 1);
+/// assert_eq!(rings[0].len(), CONE_VERTICES + 1);
+/// assert!(rings[0].iter().all(|[ra, _]| (ra - 359.0).abs() < 2.1));
+/// ```
+pub fn cone(center: &SkyPosition, radius: f64) -> Vec<Ring> {
+    let (west, east) = (center.ra - 180.0, center.ra + 180.0);
+    if radius >= 180.0 {
+        return vec![rect_ring(west, -90.0, east, 90.0)];
+    }
+    // counter-clockwise on the map, from the bearing of the north pole
+    let ring = (0..CONE_VERTICES)
+        .map(|i| {
+            let bearing = -2.0 * PI * i as f64 / CONE_VERTICES as f64;
+            edge(center, radius, bearing)
+        })
+        .collect::<Vec<_>>();
+    let north = center.dec + radius >= 90.0;
+    let south = center.dec - radius <= -90.0;
+    match (north, south) {
+        (false, false) => vec![closed(ring)],
+        (true, false) => vec![over_pole(ring, 0, west, east, 90.0)],
+        (false, true) => vec![over_pole(ring, CONE_VERTICES / 2, east, west, -90.0)],
+        (true, true) => {
+            let opposite = SkyPosition::new(center.ra + 180.0, -center.dec);
+            let mut hole = cone(&opposite, 180.0 - radius).swap_remove(0);
+            hole.reverse();
+            let map = rect_ring(center.ra, -90.0, center.ra + 360.0, 90.0);
+            vec![map, hole]
+        }
+    }
+}
+
+/// Ring of a cone over a pole from the edge of the cone, whose vertex at
+/// `crossing` crosses the meridian opposite its center, along the edge from
+/// `start` to `end` (in right ascension) and back along the pole.
+fn over_pole(ring: Ring, crossing: usize, start: f64, end: f64, pole: f64) -> Ring {
+    let dec = ring[crossing][1];
+    let mut edge = ring;
+    edge.remove(crossing);
+    edge.sort_by(|a, b| a[0].total_cmp(&b[0]));
+    if start > end {
+        edge.reverse();
+    }
+    let mut ring = vec![[start, dec]];
+    ring.extend(edge);
+    ring.extend([[end, dec], [end, pole], [start, pole]]);
+    closed(ring)
+}
+
+/// Position `radius` degrees from `center` at a bearing (in radians, east
+/// of north), with its right ascension within 180 degrees of the center's.
+fn edge(center: &SkyPosition, radius: f64, bearing: f64) -> [f64; 2] {
+    // towards the north and east of the center, which at a pole are
+    // directions along and across its meridian
+    let (sin_ra, cos_ra) = center.ra.to_radians().sin_cos();
+    let (sin_dec, cos_dec) = center.dec.to_radians().sin_cos();
+    let c = [cos_dec * cos_ra, cos_dec * sin_ra, sin_dec];
+    let n = [-sin_dec * cos_ra, -sin_dec * sin_ra, cos_dec];
+    let e = [-sin_ra, cos_ra, 0.0];
+    let (sin_r, cos_r) = radius.to_radians().sin_cos();
+    let (sin_b, cos_b) = bearing.sin_cos();
+    let p = |i: usize| cos_r * c[i] + sin_r * (cos_b * n[i] + sin_b * e[i]);
+    let (x, y, z) = (p(0), p(1), p(2));
+    let offset = (y * cos_ra - x * sin_ra).atan2(x * cos_ra + y * sin_ra);
+    [
+        center.ra + offset.to_degrees(),
+        z.atan2(x.hypot(y)).to_degrees(),
+    ]
+}
+
+impl Feature {
+    /// Feature of a geometry, without properties.
+    pub fn new(geometry: Geometry) -> Self {
+        Feature {
+            geometry,
+            properties: Map::new(),
+        }
+    }
+
+    pub fn with_property<V: Into<Value>>(mut self, name: &str, value: V) -> Self {
+        self.properties.insert(String::from(name), value.into());
+        self
+    }
+}
+
+/// Feature of a tile of an `AdaptiveMap`, with its count and density.
+impl From<&Tile> for Feature {
+    fn from(tile: &Tile) -> Self {
+        let ring = rect_ring(tile.ra_min, tile.dec_min, tile.ra_max, tile.dec_max);
+        Feature::new(Geometry::Polygon(vec![ring]))
+            .with_property("count", tile.count)
+            .with_property("density", tile.density)
+    }
+}
+
+impl FeatureCollection {
+    /// Write the collection as a JSON document.
+    ///
+    /// ```
+    /// # use starquad::geom::rect::Rect;
+    /// # use starquad::output::geojson::{Feature, FeatureCollection, Geometry};
+    /// let rect = Rect::new(10.0, -5.0, 2.0, 1.0).unwrap();
+    /// let features = vec![Feature::new(Geometry::from(&rect)).with_property("name", "field")];
+    /// let mut output = Vec::new();
+    /// features.into_iter().collect::<FeatureCollection>().write(&mut output).unwrap();
+    /// let json = String::from_utf8(output).unwrap();
+    /// assert!(json.starts_with(r#"{"type":"FeatureCollection","features":[{"type":"Feature","#));
+    /// assert!(json.contains(r#""coordinates":[[[10.0,-5.0],[12.0,-5.0],[12.0,-4.0]"#));
+    /// ```
+    pub fn write<W: Write>(&self, writer: W) -> Result<(), Error> {
+        serde_json::to_writer(writer, self)?;
+        Ok(())
+    }
+}
+
+impl FromIterator<Feature> for FeatureCollection {
+    fn from_iter<I: IntoIterator<Item = Feature>>(features: I) -> Self {
+        FeatureCollection {
+            features: features.into_iter().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use geom::p2::P2;
+    use geom::polygon::Polygon;
+    use output::geojson::{cone, Feature, Geometry, CONE_VERTICES};
+    use sky::density::Tile;
+    use sky::position::SkyPosition;
+    use sky::region::Region;
+
+    /// Signed area of a ring, positive if it goes counter-clockwise.
+    fn area(ring: &[[f64; 2]]) -> f64 {
+        Polygon::new(ring.iter().map(|&[x, y]| P2::new(x, y)).collect()).area()
+    }
+
+    #[test]
+    fn regions() {
+        let json = serde_json::to_string(&Geometry::from(
+            &Region::sky_box(350.0, 370.0, 0.0, 1.0).unwrap(),
+        ))
+        .unwrap();
+        assert!(json.starts_with(r#"{"type":"MultiPolygon","coordinates":[[[[350.0,0.0],"#));
+        let triangle = Polygon::new(vec![
+            P2::new(0.0, 0.0),
+            P2::new(1.0, 0.0),
+            P2::new(0.0, 1.0),
+        ]);
+        assert_eq!(
+            Geometry::from(&triangle),
+            Geometry::Polygon(vec![vec![[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [0.0, 0.0]]])
+        );
+    }
+
+    #[test]
+    fn cones() {
+        let center = SkyPosition::new(180.0, 30.0);
+        let rings = cone(&center, 5.0);
+        assert_eq!(rings.len(), 1);
+        assert!(area(&rings[0]) > 0.0);
+        for &[ra, dec] in &rings[0] {
+            let distance = center.separation(&SkyPosition::new(ra, dec));
+            assert!((distance - 5.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn cones_over_poles() {
+        for (center, radius) in [
+            (SkyPosition::new(30.0, 80.0), 20.0),
+            (SkyPosition::new(30.0, 90.0), 20.0),
+            (SkyPosition::new(30.0, -80.0), 20.0),
+        ] {
+            let rings = cone(&center, radius);
+            assert_eq!(rings.len(), 1);
+            let ring = &rings[0];
+            assert_eq!(ring.len(), CONE_VERTICES + 4);
+            assert!(area(ring) > 0.0);
+            assert!(ring.iter().all(|[ra, _]| (ra - 30.0).abs() <= 180.0 + 1e-9));
+        }
+        let rings = cone(&SkyPosition::new(30.0, 10.0), 150.0);
+        assert_eq!(rings.len(), 2);
+        assert_eq!(area(&rings[0]), 360.0 * 180.0);
+        assert!(area(&rings[1]) < 0.0);
+        assert_eq!(cone(&SkyPosition::new(0.0, 0.0), 180.0)[0].len(), 5);
+    }
+
+    #[test]
+    fn tiles() {
+        let tile = Tile {
+            ra_min: 0.0,
+            ra_max: 1.0,
+            dec_min: 2.0,
+            dec_max: 3.0,
+            count: 4,
+            density: 4.0,
+        };
+        let json = serde_json::to_string(&Feature::from(&tile)).unwrap();
+        let expected = r#"{"type":"Feature","geometry":{"type":"Polygon","coordinates":[[[0.0,2.0],[1.0,2.0],[1.0,3.0],[0.0,3.0],[0.0,2.0]]]},"properties":{"count":4,"density":4.0}}"#;
+        assert_eq!(json, expected);
+    }
+}
@@ -0,0 +1,294 @@
+use output::Error;
+use sky::density::Image;
+use std::io::Write;
+use std::str::FromStr;
+
+/// Size of a FITS block; headers and data units are padded to a multiple of
+/// this.
+const FITS_BLOCK_SIZE: usize = 2880;
+
+/// Function mapping values, scaled to `[0, 1]`, to brightness.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stretch {
+    Linear,
+    Sqrt,
+    /// Logarithmic, showing a dynamic range of about 1000.
+    Log,
+    /// Inverse hyperbolic sine, which is linear for faint values and
+    /// logarithmic for bright ones.
+    Asinh,
+}
+
+impl Stretch {
+    pub fn apply(&self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Stretch::Linear => t,
+            Stretch::Sqrt => t.sqrt(),
+            Stretch::Log => (1000.0 * t).ln_1p() / 1000.0f64.ln_1p(),
+            Stretch::Asinh => (10.0 * t).asinh() / 10.0f64.asinh(),
+        }
+    }
+}
+
+impl FromStr for Stretch {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "linear" => Ok(Stretch::Linear),
+            "sqrt" => Ok(Stretch::Sqrt),
+            "log" => Ok(Stretch::Log),
+            "asinh" => Ok(Stretch::Asinh),
+            _ => Err(format!("unknown stretch: {}", name)),
+        }
+    }
+}
+
+/// Map from brightness in `[0, 1]` to colour.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Colormap {
+    Gray,
+    Viridis,
+    Inferno,
+}
+
+const VIRIDIS: [[u8; 3]; 9] = [
+    [68, 1, 84],
+    [71, 44, 122],
+    [59, 81, 139],
+    [44, 113, 142],
+    [33, 144, 141],
+    [39, 173, 129],
+    [92, 200, 99],
+    [170, 220, 50],
+    [253, 231, 37],
+];
+
+const INFERNO: [[u8; 3]; 9] = [
+    [0, 0, 4],
+    [31, 12, 72],
+    [85, 15, 109],
+    [136, 34, 106],
+    [186, 54, 85],
+    [227, 89, 51],
+    [249, 140, 10],
+    [249, 201, 50],
+    [252, 255, 164],
+];
+
+impl Colormap {
+    /// Colour of a brightness, interpolating linearly between evenly-spaced
+    /// stops.
+    pub fn colour(&self, t: f64) -> [u8; 3] {
+        let stops: &[[u8; 3]] = match self {
+            Colormap::Gray => &[[0, 0, 0], [255, 255, 255]],
+            Colormap::Viridis => &VIRIDIS,
+            Colormap::Inferno => &INFERNO,
+        };
+        let position = t.clamp(0.0, 1.0) * (stops.len() - 1) as f64;
+        let i = (position.floor() as usize).min(stops.len() - 2);
+        let f = position - i as f64;
+        let mut colour = [0; 3];
+        for (c, (&a, &b)) in colour.iter_mut().zip(stops[i].iter().zip(&stops[i + 1])) {
+            *c = (f64::from(a) + f * (f64::from(b) - f64::from(a))).round() as u8;
+        }
+        colour
+    }
+}
+
+impl FromStr for Colormap {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "gray" | "grey" => Ok(Colormap::Gray),
+            "viridis" => Ok(Colormap::Viridis),
+            "inferno" => Ok(Colormap::Inferno),
+            _ => Err(format!("unknown colour map: {}", name)),
+        }
+    }
+}
+
+/// Conversion of images to colour.
+///
+/// Values are scaled linearly from `[min, max]` to `[0, 1]`, stretched, and
+/// coloured. By default `min` and `max` are the range of the image. `NaN`
+/// values are transparent.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Render {
+    stretch: Stretch,
+    colormap: Colormap,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+impl Render {
+    pub fn new(stretch: Stretch, colormap: Colormap) -> Self {
+        Render {
+            stretch,
+            colormap,
+            min: None,
+            max: None,
+        }
+    }
+
+    pub fn with_min(mut self, min: f64) -> Self {
+        self.min = Some(min);
+        self
+    }
+
+    pub fn with_max(mut self, max: f64) -> Self {
+        self.max = Some(max);
+        self
+    }
+
+    /// 8-bit RGBA pixels of an image, row by row from the top.
+    pub fn rgba(&self, image: &Image) -> Vec<u8> {
+        let (image_min, image_max) = image.range().unwrap_or((0.0, 1.0));
+        let min = self.min.unwrap_or(image_min);
+        let max = self.max.unwrap_or(image_max);
+        let span = if max > min { max - min } else { 1.0 };
+        let mut pixels = Vec::with_capacity(image.values.len() * 4);
+        for &value in &image.values {
+            if value.is_nan() {
+                pixels.extend_from_slice(&[0, 0, 0, 0]);
+            } else {
+                let t = self.stretch.apply((value - min) / span);
+                pixels.extend_from_slice(&self.colormap.colour(t));
+                pixels.push(255);
+            }
+        }
+        pixels
+    }
+
+    pub fn write_png<W: Write>(&self, image: &Image, writer: W) -> Result<(), Error> {
+        let mut encoder = png::Encoder::new(writer, image.width as u32, image.height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.rgba(image))?;
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+/// Write an image as comma-separated values, one line per row from the top.
+pub fn write_csv<W: Write>(image: &Image, writer: W) -> Result<(), Error> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(writer);
+    for row in image.rows() {
+        writer.write_record(row.iter().map(|v| v.to_string()))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Format a FITS header card in the fixed format: strings start in column
+/// 11, and other values end in column 30.
+fn fits_card(keyword: &str, value: &str) -> String {
+    if value.starts_with('\'') {
+        format!("{:<8}= {}", keyword, value)
+    } else {
+        format!("{:<8}= {:>20}", keyword, value)
+    }
+}
+
+/// Write a FITS file whose primary array holds 64-bit floating-point
+/// `values`, with the length of each axis in `axes` (the first varying
+/// fastest). Additional header cards are given as `(keyword, value)` pairs,
+/// where string values must already be quoted.
+pub fn write_fits<W: Write>(
+    mut writer: W,
+    axes: &[usize],
+    values: &[f64],
+    keywords: &[(&str, String)],
+) -> Result<(), Error> {
+    let mut cards = vec![
+        fits_card("SIMPLE", "T"),
+        fits_card("BITPIX", "-64"),
+        fits_card("NAXIS", &axes.len().to_string()),
+    ];
+    for (i, axis) in axes.iter().enumerate() {
+        cards.push(fits_card(&format!("NAXIS{}", i + 1), &axis.to_string()));
+    }
+    for (keyword, value) in keywords {
+        cards.push(fits_card(keyword, value));
+    }
+    cards.push(String::from("END"));
+    let mut header = cards
+        .iter()
+        .map(|card| format!("{:<80.80}", card))
+        .collect::<String>()
+        .into_bytes();
+    header.resize(
+        header.len().div_ceil(FITS_BLOCK_SIZE) * FITS_BLOCK_SIZE,
+        b' ',
+    );
+    writer.write_all(&header)?;
+
+    let mut data = values
+        .iter()
+        .flat_map(|v| v.to_be_bytes())
+        .collect::<Vec<_>>();
+    data.resize(data.len().div_ceil(FITS_BLOCK_SIZE) * FITS_BLOCK_SIZE, 0);
+    writer.write_all(&data)?;
+    Ok(())
+}
+
+/// Write an image as a two-dimensional FITS primary array. FITS images are
+/// stored from the bottom row up, so the rows are reversed.
+pub fn write_fits_image<W: Write>(
+    image: &Image,
+    writer: W,
+    keywords: &[(&str, String)],
+) -> Result<(), Error> {
+    let values = image.rows().rev().flatten().copied().collect::<Vec<_>>();
+    write_fits(writer, &[image.width, image.height], &values, keywords)
+}
+
+#[cfg(test)]
+mod test {
+    use output::image::{write_fits_image, Colormap, Render, Stretch};
+    use sky::density::Image;
+
+    #[test]
+    fn colours() {
+        assert_eq!(Colormap::Gray.colour(0.5), [128, 128, 128]);
+        assert_eq!(Colormap::Viridis.colour(0.0), [68, 1, 84]);
+        assert_eq!(Colormap::Inferno.colour(1.0), [252, 255, 164]);
+        assert_eq!(Stretch::Log.apply(1.0), 1.0);
+        assert_eq!(Stretch::Asinh.apply(-1.0), 0.0);
+    }
+
+    #[test]
+    fn png() {
+        let mut image = Image::new(3, 2);
+        image.values = vec![0.0, 1.0, 4.0, f64::NAN, 2.0, 4.0];
+        let render = Render::new(Stretch::Sqrt, Colormap::Gray);
+        let pixels = render.rgba(&image);
+        assert_eq!(&pixels[4..8], &[128, 128, 128, 255]);
+        assert_eq!(pixels[15], 0);
+
+        let mut png = Vec::new();
+        render.write_png(&image, &mut png).unwrap();
+        assert_eq!(&png[1..4], b"PNG");
+    }
+
+    #[test]
+    fn fits() {
+        let mut image = Image::new(2, 2);
+        image.values = vec![1.0, 2.0, 3.0, 4.0];
+        let mut fits = Vec::new();
+        write_fits_image(&image, &mut fits, &[("BUNIT", String::from("'count'"))]).unwrap();
+        assert_eq!(fits.len(), 2 * 2880);
+        assert!(fits.starts_with(b"SIMPLE  =                    T"));
+        assert_eq!(
+            &fits[80 * 3..80 * 3 + 30],
+            b"NAXIS1  =                    2"
+        );
+        // the bottom row comes first
+        assert_eq!(&fits[2880..2888], &3.0f64.to_be_bytes());
+    }
+}
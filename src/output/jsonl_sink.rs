@@ -0,0 +1,59 @@
+use output::{Error, RecordSink};
+use serde::Serialize;
+use std::io::{BufWriter, Write};
+
+/// Sink writing records as JSON Lines: one JSON object per line.
+///
+/// Missing values are written as `null`.
+pub struct JsonlSink<W: Write> {
+    writer: BufWriter<W>,
+}
+
+impl<W: Write> JsonlSink<W> {
+    pub fn new(writer: W) -> Self {
+        JsonlSink {
+            writer: BufWriter::new(writer),
+        }
+    }
+}
+
+impl<W: Write, T: Serialize> RecordSink<T> for JsonlSink<W> {
+    fn write(&mut self, record: &T) -> Result<(), Error> {
+        serde_json::to_writer(&mut self.writer, record)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use gaia::record::test::sample_record;
+    use gaia::record::GaiaRecord;
+    use output::jsonl_sink::JsonlSink;
+    use output::RecordSink;
+
+    #[test]
+    fn round_trip() {
+        let mut a = sample_record();
+        a.parallax = Some(1.5);
+        let records = vec![a, sample_record()];
+        let mut output = Vec::new();
+        {
+            let mut sink = JsonlSink::new(&mut output);
+            sink.write_all(&records).unwrap();
+            RecordSink::<GaiaRecord>::finish(&mut sink).unwrap();
+        }
+        let text = String::from_utf8(output).unwrap();
+        assert!(text.lines().nth(1).unwrap().contains("\"parallax\":null"));
+        let read = text
+            .lines()
+            .map(|line| serde_json::from_str::<GaiaRecord>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(read, records);
+    }
+}
@@ -0,0 +1,178 @@
+use gaia::projection::{Projected, Projection};
+use gaia::record::GaiaRecord;
+use serde::Serialize;
+use std::io;
+use std::io::Write;
+use std::str::FromStr;
+
+#[cfg(feature = "arrow")]
+pub mod arrow_sink;
+pub mod bincode_sink;
+pub mod csv_sink;
+pub mod geojson;
+pub mod image;
+pub mod jsonl_sink;
+
+/// Errors from writing records to a sink.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Bincode(#[from] bincode::Error),
+    #[error(transparent)]
+    Png(#[from] png::EncodingError),
+    #[cfg(feature = "arrow")]
+    #[error(transparent)]
+    Arrow(#[from] ::catalog::arrow::Error),
+    /// An output format name was not recognised.
+    #[error("unknown output format: {0}")]
+    UnknownFormat(String),
+    /// The output format can only write Gaia records.
+    #[error("{0:?} output is only supported for Gaia records")]
+    UnsupportedFormat(Format),
+}
+
+/// Destination for a stream of records, such as the results of a query.
+pub trait RecordSink<T> {
+    fn write(&mut self, record: &T) -> Result<(), Error>;
+
+    /// Flush any buffered output. No records may be written afterwards.
+    fn finish(&mut self) -> Result<(), Error>;
+
+    fn write_all<'a, I>(&mut self, records: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = &'a T>,
+        T: 'a,
+        Self: Sized,
+    {
+        for record in records {
+            self.write(record)?;
+        }
+        Ok(())
+    }
+}
+
+/// Output format of a `RecordSink`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// Comma-separated values with a header.
+    Csv,
+    /// One JSON object per line.
+    Jsonl,
+    /// bincode-encoded records, each prefixed by its length.
+    Bincode,
+    /// Arrow IPC stream of record batches (Gaia records only).
+    #[cfg(feature = "arrow")]
+    Arrow,
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self, Error> {
+        match name {
+            "csv" => Ok(Format::Csv),
+            "jsonl" | "ndjson" => Ok(Format::Jsonl),
+            "bincode" => Ok(Format::Bincode),
+            #[cfg(feature = "arrow")]
+            "arrow" | "arrows" => Ok(Format::Arrow),
+            _ => Err(Error::UnknownFormat(String::from(name))),
+        }
+    }
+}
+
+impl Format {
+    /// Create a sink writing records in this format.
+    ///
+    /// ```
+    /// # use starquad::output::Format;
+    /// # use starquad::crossmatch::join::Match;
+    /// let mut output = Vec::new();
+    /// {
+    ///     let format: Format = "jsonl".parse().unwrap();
+    ///     let mut sink = format.sink(&mut output).unwrap();
+    ///     let m = Match {
+    ///         left: 1,
+    ///         right: 2,
+    ///         separation: 0.5,
+    ///         score: 20.0,
+    ///         candidates: 1,
+    ///         ambiguous: false,
+    ///     };
+    ///     sink.write(&m).unwrap();
+    ///     sink.finish().unwrap();
+    /// }
+    /// let line = "{\"left\":1,\"right\":2,\"separation\":0.5,\"score\":20.0,\"candidates\":1,\"ambiguous\":false}\n";
+    /// assert_eq!(output, line.as_bytes());
+    /// ```
+    ///
+    /// Arrow streams have a fixed schema, so can only be written by
+    /// `projected_sink`.
+    pub fn sink<'a, W, T>(&self, writer: W) -> Result<Box<dyn RecordSink<T> + 'a>, Error>
+    where
+        W: Write + 'a,
+        T: Serialize + 'a,
+    {
+        Ok(match self {
+            Format::Csv => Box::new(csv_sink::CsvSink::new(writer)),
+            Format::Jsonl => Box::new(jsonl_sink::JsonlSink::new(writer)),
+            Format::Bincode => Box::new(bincode_sink::BincodeSink::new(writer)),
+            #[cfg(feature = "arrow")]
+            Format::Arrow => return Err(Error::UnsupportedFormat(*self)),
+        })
+    }
+
+    /// Create a sink writing the columns of Gaia records selected by a
+    /// projection (such as the results of a query) in this format.
+    pub fn projected_sink<'a, W>(
+        &self,
+        writer: W,
+        projection: Projection,
+    ) -> Box<dyn RecordSink<GaiaRecord> + 'a>
+    where
+        W: Write + 'a,
+    {
+        match self {
+            Format::Csv => Box::new(ProjectingSink {
+                sink: csv_sink::CsvSink::new(writer),
+                projection,
+            }),
+            Format::Jsonl => Box::new(ProjectingSink {
+                sink: jsonl_sink::JsonlSink::new(writer),
+                projection,
+            }),
+            Format::Bincode => Box::new(ProjectingSink {
+                sink: bincode_sink::BincodeSink::new(writer),
+                projection,
+            }),
+            #[cfg(feature = "arrow")]
+            Format::Arrow => {
+                Box::new(arrow_sink::ArrowSink::new(writer).with_projection(projection))
+            }
+        }
+    }
+}
+
+/// Sink writing the projections of Gaia records to a sink of any records.
+struct ProjectingSink<S> {
+    sink: S,
+    projection: Projection,
+}
+
+impl<S> RecordSink<GaiaRecord> for ProjectingSink<S>
+where
+    S: for<'p> RecordSink<Projected<'p>>,
+{
+    fn write(&mut self, record: &GaiaRecord) -> Result<(), Error> {
+        self.sink.write(&self.projection.project(record))
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        RecordSink::<Projected>::finish(&mut self.sink)
+    }
+}
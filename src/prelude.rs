@@ -0,0 +1,27 @@
+//! The types most programs using the library need, for glob import.
+//!
+//! ```
+//! use starquad::prelude::*;
+//!
+//! let tree = QuadTree::new_from_vec(vec![(P2::new(1.0, 2.0), "a"), (P2::new(5.0, 5.0), "b")]);
+//! let found = tree.query_rect(&Rect::new(0.0, 0.0, 3.0, 3.0).unwrap());
+//! assert_eq!(found, vec![&(P2::new(1.0, 2.0), "a")]);
+//! ```
+
+pub use accel2d::quadtree::QuadTree;
+pub use accel2d::Accel2D;
+pub use error::{Error, Result};
+pub use gaia::filter::Filter;
+pub use gaia::projection::Projection;
+pub use gaia::reader;
+pub use gaia::record::GaiaRecord;
+pub use geom::interval::{Interval, IntervalDomain};
+pub use geom::p2::P2;
+pub use geom::rect::Rect;
+pub use mst::Boruvka;
+pub use output::{Format, RecordSink};
+pub use sky::index::{index_sources, SkyIndex};
+pub use sky::position::{SkyPosition, SkySource};
+pub use sky::region::Region;
+pub use store::builder::StoreBuilder;
+pub use store::Store;
@@ -0,0 +1,311 @@
+use std::io;
+use std::io::Read;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Number of records between checks of whether to report progress.
+const RECORDS_PER_CHECK: u64 = 4096;
+
+/// Minimum time between reports of progress.
+const REPORT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Snapshot of the progress of a long-running operation.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Status {
+    /// Name of the current stage, such as "reading" or "matching".
+    pub stage: String,
+    /// Bytes read from input files.
+    pub bytes: u64,
+    /// Total size of the input files, if known.
+    pub total_bytes: Option<u64>,
+    /// Input files (or shards) completed.
+    pub files: u64,
+    pub total_files: Option<u64>,
+    /// Records processed in the current stage.
+    pub records: u64,
+    pub total_records: Option<u64>,
+    /// Time since the operation started.
+    pub elapsed: Duration,
+}
+
+impl Status {
+    /// Fraction of the work done in the current stage, measured in records
+    /// if the stage has a known number of them and otherwise in bytes read.
+    pub fn fraction(&self) -> Option<f64> {
+        match (self.total_records, self.total_bytes) {
+            (Some(total), _) if total > 0 => Some(self.records as f64 / total as f64),
+            (_, Some(total)) if total > 0 => Some(self.bytes as f64 / total as f64),
+            _ => None,
+        }
+    }
+
+    /// Estimated time remaining, assuming the rate so far continues.
+    pub fn eta(&self) -> Option<Duration> {
+        let fraction = self.fraction()?;
+        if fraction <= 0.0 {
+            return None;
+        }
+        let remaining = self.elapsed.as_secs_f64() * (1.0 - fraction.min(1.0)) / fraction;
+        Some(Duration::from_secs_f64(remaining))
+    }
+}
+
+/// Receiver of progress reports.
+///
+/// Reports are rate-limited by `Tracker`, so implementations may draw to a
+/// terminal on every call.
+pub trait Progress {
+    /// Called at the start of each stage of an operation.
+    fn stage(&mut self, _status: &Status) {}
+    fn update(&mut self, status: &Status);
+    /// Called once when the operation is complete.
+    fn finish(&mut self, _status: &Status) {}
+}
+
+/// Progress which reports nothing.
+pub struct NoProgress;
+
+impl Progress for NoProgress {
+    fn update(&mut self, _status: &Status) {}
+}
+
+/// Shared count of bytes read, updated by `CountingReader`s.
+#[derive(Clone, Debug, Default)]
+pub struct ByteCounter(Arc<AtomicU64>);
+
+impl ByteCounter {
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Flag shared between a long-running operation and whoever may ask it to
+/// stop, such as a Ctrl-C handler.
+///
+/// Operations check the token at safe points (between records) through
+/// their `Tracker`, and stop early leaving their output consistent.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        CancelToken::default()
+    }
+
+    /// Ask the operation to stop.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Reader which adds the number of bytes it reads to a `ByteCounter`.
+pub struct CountingReader<R> {
+    inner: R,
+    counter: ByteCounter,
+}
+
+impl<R: Read> CountingReader<R> {
+    pub fn new(inner: R, counter: ByteCounter) -> Self {
+        CountingReader { inner, counter }
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.counter.0.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+/// Accumulator of the status of an operation, which passes it to a
+/// `Progress` at most every 100 ms, and carries the operation's
+/// `CancelToken`.
+///
+/// ```
+/// # use starquad::progress::{NoProgress, Tracker};
+/// let mut progress = NoProgress;
+/// let mut tracker = Tracker::new(&mut progress);
+/// tracker.start_stage("counting", Some(10));
+/// for _ in 0..10 {
+///     tracker.record();
+/// }
+/// assert_eq!(tracker.status().records, 10);
+/// tracker.finish();
+/// ```
+pub struct Tracker<'a> {
+    progress: &'a mut dyn Progress,
+    status: Status,
+    bytes: ByteCounter,
+    cancel: CancelToken,
+    start: Instant,
+    last_report: Instant,
+}
+
+impl<'a> Tracker<'a> {
+    pub fn new(progress: &'a mut dyn Progress) -> Self {
+        let now = Instant::now();
+        Tracker {
+            progress,
+            status: Status::default(),
+            bytes: ByteCounter::default(),
+            cancel: CancelToken::default(),
+            start: now,
+            last_report: now,
+        }
+    }
+
+    /// Set the total number and size of the input files.
+    pub fn with_files<P: AsRef<Path>>(mut self, paths: &[P]) -> io::Result<Self> {
+        let mut bytes = 0;
+        for path in paths {
+            bytes += path.as_ref().metadata()?.len();
+        }
+        self.status.total_files = Some(paths.len() as u64);
+        self.status.total_bytes = Some(bytes);
+        Ok(self)
+    }
+
+    /// Stop the operation when a token is cancelled.
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Whether the operation has been asked to stop.
+    pub fn cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    /// Counter to be given to the `CountingReader`s of input files.
+    pub fn bytes(&self) -> &ByteCounter {
+        &self.bytes
+    }
+
+    pub fn status(&self) -> &Status {
+        &self.status
+    }
+
+    /// Start a stage of the operation, with the number of records it will
+    /// process if known.
+    pub fn start_stage(&mut self, name: &str, total_records: Option<u64>) {
+        self.status.stage = String::from(name);
+        self.status.records = 0;
+        self.status.total_records = total_records;
+        self.refresh();
+        self.progress.stage(&self.status);
+    }
+
+    /// Count one processed record.
+    pub fn record(&mut self) {
+        self.status.records += 1;
+        if self.status.records.is_multiple_of(RECORDS_PER_CHECK) {
+            self.report();
+        }
+    }
+
+    /// Count a completed input file.
+    pub fn file_done(&mut self) {
+        self.status.files += 1;
+        self.report();
+    }
+
+    /// Pass the status to the `Progress` if it has not been for a while.
+    fn report(&mut self) {
+        let now = Instant::now();
+        if now.duration_since(self.last_report) >= REPORT_INTERVAL {
+            self.last_report = now;
+            self.refresh();
+            self.progress.update(&self.status);
+        }
+    }
+
+    fn refresh(&mut self) {
+        self.status.bytes = self.bytes.get();
+        self.status.elapsed = self.start.elapsed();
+    }
+
+    pub fn finish(mut self) {
+        self.refresh();
+        self.progress.finish(&self.status);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use progress::{CancelToken, CountingReader, NoProgress, Progress, Status, Tracker};
+    use std::io::Read;
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct Log {
+        stages: Vec<String>,
+        last: Option<Status>,
+    }
+
+    impl Progress for Log {
+        fn stage(&mut self, status: &Status) {
+            self.stages.push(status.stage.clone());
+        }
+
+        fn update(&mut self, status: &Status) {
+            self.last = Some(status.clone());
+        }
+
+        fn finish(&mut self, status: &Status) {
+            self.last = Some(status.clone());
+        }
+    }
+
+    #[test]
+    fn tracks_bytes_and_records() {
+        let mut log = Log::default();
+        {
+            let mut tracker = Tracker::new(&mut log);
+            tracker.start_stage("reading", None);
+            let mut reader = CountingReader::new(&[0u8; 100][..], tracker.bytes().clone());
+            let mut buffer = Vec::new();
+            reader.read_to_end(&mut buffer).unwrap();
+            for _ in 0..5 {
+                tracker.record();
+            }
+            tracker.file_done();
+            tracker.start_stage("writing", Some(3));
+            tracker.record();
+            tracker.finish();
+        }
+        assert_eq!(log.stages, vec!["reading", "writing"]);
+        let last = log.last.unwrap();
+        assert_eq!((last.bytes, last.files, last.records), (100, 1, 1));
+        assert_eq!(last.total_records, Some(3));
+    }
+
+    #[test]
+    fn cancel() {
+        let token = CancelToken::new();
+        let mut progress = NoProgress;
+        let tracker = Tracker::new(&mut progress).with_cancel(token.clone());
+        assert!(!tracker.cancelled());
+        token.cancel();
+        assert!(tracker.cancelled());
+    }
+
+    #[test]
+    fn eta() {
+        let status = Status {
+            bytes: 25,
+            total_bytes: Some(100),
+            elapsed: Duration::from_secs(10),
+            ..Status::default()
+        };
+        assert_eq!(status.fraction(), Some(0.25));
+        assert_eq!(status.eta(), Some(Duration::from_secs(30)));
+        assert_eq!(Status::default().eta(), None);
+    }
+}
@@ -0,0 +1,299 @@
+//! Python bindings, built with the `python` feature into the `starquad`
+//! extension module (see `pyproject.toml`).
+//!
+//! ```python
+//! import numpy as np
+//! import starquad
+//!
+//! index = starquad.SkyIndex(ra, dec)        # numpy arrays, in degrees
+//! near = index.cone(56.75, 24.12, 1.0)      # places in ra and dec
+//! places, separations = index.knn(56.75, 24.12, 5)
+//! for record in starquad.read_gaia("GaiaSource_000000-003111.csv.gz"):
+//!     print(record["source_id"], record["phot_g_mean_mag"])
+//! ```
+
+use accel2d::Accel2D;
+#[cfg(feature = "arrow")]
+use arrow_array::ffi::to_ffi;
+#[cfg(feature = "arrow")]
+use arrow_array::{Array, RecordBatch, StructArray};
+#[cfg(feature = "arrow")]
+use catalog::arrow::{self, gaia_record_batch, DEFAULT_BATCH_SIZE};
+use csv::DeserializeRecordsIntoIter;
+use error::Error;
+use gaia::reader;
+use gaia::record::GaiaRecord;
+use gaia::schema::{self, ColumnType, COLUMNS};
+use geom::p2::P2;
+use numpy::{PyArray1, PyReadonlyArray1};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use sky::index::{nearest, query_region, sky_bounds, SkyIndex};
+use sky::position::SkyPosition;
+use sky::region::Region;
+use std::io::Read;
+use std::path::PathBuf;
+
+impl From<Error> for PyErr {
+    fn from(e: Error) -> PyErr {
+        match e {
+            Error::Io(e) => e.into(),
+            e => PyValueError::new_err(e.to_string()),
+        }
+    }
+}
+
+/// In-memory index of positions, whose queries return the places of the
+/// positions found in the arrays the index was built from.
+#[pyclass(name = "SkyIndex", module = "starquad", frozen)]
+pub struct PySkyIndex {
+    index: SkyIndex<usize>,
+    len: usize,
+}
+
+impl PySkyIndex {
+    fn places<'py>(&self, py: Python<'py>, region: &Region) -> Bound<'py, PyArray1<usize>> {
+        let mut places = query_region(&self.index, region)
+            .iter()
+            .map(|(_, i)| *i)
+            .collect::<Vec<_>>();
+        places.sort_unstable();
+        PyArray1::from_vec(py, places)
+    }
+}
+
+#[pymethods]
+impl PySkyIndex {
+    /// Index the positions in arrays of `ra` and `dec`, in degrees.
+    #[new]
+    fn new(
+        py: Python<'_>,
+        ra: PyReadonlyArray1<f64>,
+        dec: PyReadonlyArray1<f64>,
+    ) -> PyResult<Self> {
+        let (ra, dec) = (ra.as_array(), dec.as_array());
+        if ra.len() != dec.len() {
+            return Err(PyValueError::new_err("ra and dec have different lengths"));
+        }
+        let items = ra
+            .iter()
+            .zip(dec.iter())
+            .enumerate()
+            .map(|(i, (&ra, &dec))| (P2::new(ra, dec), i))
+            .collect::<Vec<_>>();
+        let len = items.len();
+        let index = py.detach(|| {
+            let mut index = SkyIndex::with_bounds(sky_bounds());
+            index.insert(items);
+            index
+        });
+        Ok(PySkyIndex { index, len })
+    }
+
+    fn __len__(&self) -> usize {
+        self.len
+    }
+
+    /// Places of the positions within `radius` degrees of `(ra, dec)`, in
+    /// ascending order.
+    fn cone<'py>(
+        &self,
+        py: Python<'py>,
+        ra: f64,
+        dec: f64,
+        radius: f64,
+    ) -> Bound<'py, PyArray1<usize>> {
+        self.places(py, &Region::cone(SkyPosition::new(ra, dec), radius))
+    }
+
+    /// Places of the positions with `ra` in `[ra_min, ra_max]` and `dec` in
+    /// `[dec_min, dec_max]`, in ascending order. Ranges of `ra` are taken
+    /// modulo 360 and `dec` is clamped to the poles (see `Region`).
+    #[pyo3(name = "box")]
+    fn query_box<'py>(
+        &self,
+        py: Python<'py>,
+        ra_min: f64,
+        ra_max: f64,
+        dec_min: f64,
+        dec_max: f64,
+    ) -> PyResult<Bound<'py, PyArray1<usize>>> {
+        let region = Region::sky_box(ra_min, ra_max, dec_min, dec_max).map_err(Error::from)?;
+        Ok(self.places(py, &region))
+    }
+
+    /// Places of the `k` positions nearest to `(ra, dec)`, nearest first,
+    /// and their separations from it in degrees.
+    fn knn<'py>(
+        &self,
+        py: Python<'py>,
+        ra: f64,
+        dec: f64,
+        k: usize,
+    ) -> (Bound<'py, PyArray1<usize>>, Bound<'py, PyArray1<f64>>) {
+        let (separations, places): (Vec<f64>, Vec<usize>) =
+            nearest(&self.index, &SkyPosition::new(ra, dec), k)
+                .into_iter()
+                .unzip();
+        (
+            PyArray1::from_vec(py, places),
+            PyArray1::from_vec(py, separations),
+        )
+    }
+}
+
+/// Convert a record to a dict of its columns, with `None` for empty ones.
+fn record_dict<'py>(py: Python<'py>, record: &GaiaRecord) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    let invalid = |name: &str, text: &str| {
+        PyValueError::new_err(format!("invalid value for {}: {:?}", name, text))
+    };
+    for (column, text) in COLUMNS.iter().zip(schema::string_record(record).iter()) {
+        let name = column.name;
+        match column.column_type {
+            _ if column.nullable && text.is_empty() => dict.set_item(name, py.None())?,
+            ColumnType::Text => dict.set_item(name, text)?,
+            ColumnType::Boolean => dict.set_item(name, text == "true")?,
+            ColumnType::UnsignedByte | ColumnType::Long => {
+                let value = text.parse::<i128>().map_err(|_| invalid(name, text))?;
+                dict.set_item(name, value)?
+            }
+            ColumnType::Double => {
+                let value = text.parse::<f64>().map_err(|_| invalid(name, text))?;
+                dict.set_item(name, value)?
+            }
+        }
+    }
+    Ok(dict)
+}
+
+type Records = DeserializeRecordsIntoIter<Box<dyn Read>, GaiaRecord>;
+
+/// Iterator over the records of a Gaia CSV file, as dicts.
+#[pyclass(name = "GaiaReader", module = "starquad", unsendable)]
+pub struct PyGaiaReader {
+    records: Records,
+}
+
+#[pymethods]
+impl PyGaiaReader {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__<'py>(&mut self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyDict>>> {
+        match self.records.next() {
+            Some(record) => Ok(Some(record_dict(py, &record.map_err(Error::from)?)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Read the records of a Gaia CSV file (which may be gzipped) as dicts.
+#[pyfunction]
+fn read_gaia(path: PathBuf) -> PyResult<PyGaiaReader> {
+    Ok(PyGaiaReader {
+        records: reader::open(path)?,
+    })
+}
+
+/// Convert a record batch to a `pyarrow.RecordBatch`, through the Arrow C
+/// data interface.
+#[cfg(feature = "arrow")]
+fn to_pyarrow<'py>(py: Python<'py>, batch: RecordBatch) -> PyResult<Bound<'py, PyAny>> {
+    let data = StructArray::from(batch).into_data();
+    let (mut array, mut schema) = to_ffi(&data).map_err(|e| Error::from(arrow::Error::from(e)))?;
+    let pyarrow = py.import("pyarrow")?;
+    // pyarrow moves the contents of `array` and `schema`, leaving them empty
+    let array = pyarrow.getattr("Array")?.call_method1(
+        "_import_from_c",
+        (
+            std::ptr::addr_of_mut!(array) as usize,
+            std::ptr::addr_of_mut!(schema) as usize,
+        ),
+    )?;
+    pyarrow
+        .getattr("RecordBatch")?
+        .call_method1("from_struct_array", (array,))
+}
+
+/// Iterator over the records of a Gaia CSV file, as `pyarrow.RecordBatch`es.
+#[cfg(feature = "arrow")]
+#[pyclass(name = "GaiaBatchReader", module = "starquad", unsendable)]
+pub struct PyGaiaBatchReader {
+    records: Records,
+    batch_size: usize,
+}
+
+#[cfg(feature = "arrow")]
+#[pymethods]
+impl PyGaiaBatchReader {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__<'py>(&mut self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyAny>>> {
+        let records = self
+            .records
+            .by_ref()
+            .take(self.batch_size)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(Error::from)?;
+        if records.is_empty() {
+            return Ok(None);
+        }
+        let batch = gaia_record_batch(&records).map_err(Error::from)?;
+        Ok(Some(to_pyarrow(py, batch)?))
+    }
+}
+
+/// Read the records of a Gaia CSV file (which may be gzipped) as
+/// `pyarrow.RecordBatch`es of up to `batch_size` records.
+#[cfg(feature = "arrow")]
+#[pyfunction]
+#[pyo3(signature = (path, batch_size = DEFAULT_BATCH_SIZE))]
+fn read_gaia_batches(path: PathBuf, batch_size: usize) -> PyResult<PyGaiaBatchReader> {
+    Ok(PyGaiaBatchReader {
+        records: reader::open(path)?,
+        batch_size: batch_size.max(1),
+    })
+}
+
+/// The `starquad` Python module.
+#[pymodule]
+pub fn starquad(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PySkyIndex>()?;
+    m.add_class::<PyGaiaReader>()?;
+    m.add_function(wrap_pyfunction!(python::read_gaia, m)?)?;
+    #[cfg(feature = "arrow")]
+    {
+        m.add_class::<PyGaiaBatchReader>()?;
+        m.add_function(wrap_pyfunction!(python::read_gaia_batches, m)?)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use gaia::record::test::sample_record;
+    use pyo3::prelude::*;
+    use python::record_dict;
+
+    #[test]
+    fn records_as_dicts() {
+        Python::initialize();
+        Python::attach(|py| {
+            let mut record = sample_record();
+            record.source_id = 42;
+            record.parallax = Some(1.5);
+            let dict = record_dict(py, &record).unwrap();
+            let get = |name: &str| dict.get_item(name).unwrap().unwrap();
+            assert_eq!(get("source_id").extract::<u64>().unwrap(), 42);
+            assert_eq!(get("parallax").extract::<f64>().unwrap(), 1.5);
+            assert!(get("pmra").is_none());
+            assert!(!get("duplicated_source").extract::<bool>().unwrap());
+            assert_eq!(dict.len(), 94);
+        });
+    }
+}
@@ -0,0 +1,410 @@
+//! Drawing the structure of an index, and the items in it, as SVG or PNG.
+//!
+//! A `Figure` maps a viewport of the plane to an image, with `y` up. The
+//! regions of the index's nodes (see `Accel2D::regions`) are drawn first,
+//! parents before children, and then the items in the viewport. How each is
+//! drawn, or whether it is drawn at all, is chosen by a `Style`.
+//!
+//! ```
+//! # use starquad::accel2d::Accel2D;
+//! # use starquad::accel2d::quadtree::QuadTree;
+//! # use starquad::geom::{p2::P2, rect::Rect};
+//! # use starquad::render::{DefaultStyle, Figure};
+//! let items = (0..100).map(|i| (P2::new(f64::from(i % 10), f64::from(i / 10)), i)).collect();
+//! let tree = QuadTree::new_from_vec(items);
+//! let figure = Figure::new(Rect::new(0.0, 0.0, 10.0, 10.0).unwrap(), 200, 200);
+//! let mut svg = Vec::new();
+//! figure.write_svg(&tree, &DefaultStyle, &mut svg).unwrap();
+//! assert!(String::from_utf8(svg).unwrap().starts_with("<svg"));
+//! ```
+
+use accel2d::Accel2D;
+use geom::interval::IntervalDomain;
+use geom::p2::P2;
+use geom::rect::Rect;
+use num::ToPrimitive;
+use output::Error;
+use std::io::Write;
+
+/// Outline of a node's region: an 8-bit RGBA colour and a width in pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stroke {
+    pub colour: [u8; 4],
+    pub width: f64,
+}
+
+/// Disc drawn at an item's point: an 8-bit RGBA colour and a radius in
+/// pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Marker {
+    pub colour: [u8; 4],
+    pub radius: f64,
+}
+
+/// How a figure is drawn. Returning `None` from `node` or `item` leaves that
+/// node or item out.
+///
+/// Every method has a default, so a style need only override what it
+/// changes; eg. to colour nodes by depth, or items by a property of the item.
+pub trait Style<S, T> {
+    /// Colour filling the figure, if any.
+    fn background(&self) -> Option<[u8; 4]> {
+        Some([255, 255, 255, 255])
+    }
+
+    fn node(&self, _rect: &Rect<S>, _depth: usize) -> Option<Stroke> {
+        Some(Stroke {
+            colour: [128, 128, 128, 255],
+            width: 1.0,
+        })
+    }
+
+    fn item(&self, _item: &(P2<S>, T)) -> Option<Marker> {
+        Some(Marker {
+            colour: [0, 0, 0, 255],
+            radius: 1.5,
+        })
+    }
+}
+
+/// Grey node outlines and black items on white.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DefaultStyle;
+
+impl<S, T> Style<S, T> for DefaultStyle {}
+
+/// Mapping of a viewport of the plane to an image of `width` by `height`
+/// pixels.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Figure<S> {
+    viewport: Rect<S>,
+    width: u32,
+    height: u32,
+}
+
+/// Shape to draw, in pixels from the top left of the figure.
+enum Shape {
+    Outline(f64, f64, f64, f64, Stroke),
+    Disc(f64, f64, Marker),
+}
+
+/// Hexadecimal RGB of a colour, and its opacity.
+fn svg_colour(colour: [u8; 4]) -> (String, f64) {
+    (
+        format!("#{:02x}{:02x}{:02x}", colour[0], colour[1], colour[2]),
+        f64::from(colour[3]) / 255.0,
+    )
+}
+
+impl<S> Figure<S>
+where
+    S: IntervalDomain + ToPrimitive,
+{
+    pub fn new(viewport: Rect<S>, width: u32, height: u32) -> Self {
+        Figure {
+            viewport,
+            width,
+            height,
+        }
+    }
+
+    pub fn viewport(&self) -> &Rect<S> {
+        &self.viewport
+    }
+
+    /// Position of a point in pixels from the top left of the figure.
+    fn pixel(&self, point: &P2<S>) -> (f64, f64) {
+        let f = |s: &S| s.to_f64().unwrap_or(f64::NAN);
+        let (x, y) = (f(self.viewport.x()), f(self.viewport.y()));
+        let (w, h) = (f(self.viewport.width()), f(self.viewport.height()));
+        (
+            (f(&point.x) - x) / w * f64::from(self.width),
+            (1.0 - (f(&point.y) - y) / h) * f64::from(self.height),
+        )
+    }
+
+    /// Shapes to draw for an index, in order.
+    fn shapes<A, St>(&self, index: &A, style: &St) -> Vec<Shape>
+    where
+        A: Accel2D<Scalar = S>,
+        St: Style<S, A::Item>,
+    {
+        let mut shapes = Vec::new();
+        for (rect, depth) in index.regions() {
+            if rect.intersect(&self.viewport).is_none() {
+                continue;
+            }
+            if let Some(stroke) = style.node(&rect, depth) {
+                let (x_interval, y_interval) = (rect.x_interval(), rect.y_interval());
+                let (left, top) =
+                    self.pixel(&P2::new(x_interval.start().clone(), y_interval.end()));
+                let (right, bottom) =
+                    self.pixel(&P2::new(x_interval.end(), y_interval.start().clone()));
+                shapes.push(Shape::Outline(left, top, right, bottom, stroke));
+            }
+        }
+        for item in index.query_rect(&self.viewport) {
+            if let Some(marker) = style.item(item) {
+                let (x, y) = self.pixel(&item.0);
+                shapes.push(Shape::Disc(x, y, marker));
+            }
+        }
+        shapes
+    }
+
+    /// Write the figure as an SVG document.
+    pub fn write_svg<A, St, W>(&self, index: &A, style: &St, mut writer: W) -> Result<(), Error>
+    where
+        A: Accel2D<Scalar = S>,
+        St: Style<S, A::Item>,
+        W: Write,
+    {
+        writeln!(
+            writer,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+            self.width, self.height
+        )?;
+        if let Some(colour) = style.background() {
+            let (fill, opacity) = svg_colour(colour);
+            writeln!(
+                writer,
+                r#"<rect width="100%" height="100%" fill="{}" fill-opacity="{}"/>"#,
+                fill, opacity
+            )?;
+        }
+        for shape in self.shapes(index, style) {
+            match shape {
+                Shape::Outline(left, top, right, bottom, stroke) => {
+                    let (colour, opacity) = svg_colour(stroke.colour);
+                    writeln!(
+                        writer,
+                        r#"<rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="{}" stroke-opacity="{}" stroke-width="{}"/>"#,
+                        left,
+                        top,
+                        right - left,
+                        bottom - top,
+                        colour,
+                        opacity,
+                        stroke.width
+                    )?;
+                }
+                Shape::Disc(x, y, marker) => {
+                    let (colour, opacity) = svg_colour(marker.colour);
+                    writeln!(
+                        writer,
+                        r#"<circle cx="{}" cy="{}" r="{}" fill="{}" fill-opacity="{}"/>"#,
+                        x, y, marker.radius, colour, opacity
+                    )?;
+                }
+            }
+        }
+        writeln!(writer, "</svg>")?;
+        Ok(())
+    }
+
+    /// 8-bit RGBA pixels of the figure, row by row from the top.
+    ///
+    /// Shapes are drawn without anti-aliasing: a pixel is covered if its
+    /// centre is, and a disc always covers the pixel of its centre.
+    pub fn rgba<A, St>(&self, index: &A, style: &St) -> Vec<u8>
+    where
+        A: Accel2D<Scalar = S>,
+        St: Style<S, A::Item>,
+    {
+        let mut raster = Raster {
+            width: self.width as usize,
+            height: self.height as usize,
+            pixels: vec![0; self.width as usize * self.height as usize * 4],
+        };
+        if let Some(colour) = style.background() {
+            raster.fill(
+                0.0,
+                0.0,
+                f64::from(self.width),
+                f64::from(self.height),
+                colour,
+            );
+        }
+        for shape in self.shapes(index, style) {
+            match shape {
+                Shape::Outline(left, top, right, bottom, stroke) => {
+                    let half = stroke.width / 2.0;
+                    let colour = stroke.colour;
+                    // horizontal edges span the corners, which the vertical
+                    // edges then leave out so that no pixel is drawn twice
+                    raster.fill(left - half, top - half, right + half, top + half, colour);
+                    raster.fill(
+                        left - half,
+                        bottom - half,
+                        right + half,
+                        bottom + half,
+                        colour,
+                    );
+                    raster.fill(left - half, top + half, left + half, bottom - half, colour);
+                    raster.fill(
+                        right - half,
+                        top + half,
+                        right + half,
+                        bottom - half,
+                        colour,
+                    );
+                }
+                Shape::Disc(x, y, marker) => raster.disc(x, y, marker.radius, marker.colour),
+            }
+        }
+        raster.pixels
+    }
+
+    pub fn write_png<A, St, W>(&self, index: &A, style: &St, writer: W) -> Result<(), Error>
+    where
+        A: Accel2D<Scalar = S>,
+        St: Style<S, A::Item>,
+        W: Write,
+    {
+        let mut encoder = png::Encoder::new(writer, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.rgba(index, style))?;
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+/// RGBA pixels, onto which colours are composited.
+struct Raster {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Raster {
+    /// Composite a colour over a pixel.
+    fn blend(&mut self, i: usize, j: usize, colour: [u8; 4]) {
+        let pixel = &mut self.pixels[(j * self.width + i) * 4..][..4];
+        let alpha = f64::from(colour[3]) / 255.0;
+        let below = f64::from(pixel[3]) / 255.0 * (1.0 - alpha);
+        let out = alpha + below;
+        if out > 0.0 {
+            for c in 0..3 {
+                let value = f64::from(colour[c]) * alpha + f64::from(pixel[c]) * below;
+                pixel[c] = (value / out).round() as u8;
+            }
+        }
+        pixel[3] = (out * 255.0).round() as u8;
+    }
+
+    /// Range of pixels whose centres are in `[start, end)`, clipped to
+    /// `[0, len)`.
+    fn span(start: f64, end: f64, len: usize) -> (usize, usize) {
+        let clip = |v: f64| (v - 0.5).ceil().clamp(0.0, len as f64) as usize;
+        (clip(start), clip(end))
+    }
+
+    /// Composite a colour over the pixels whose centres are in a rectangle.
+    fn fill(&mut self, left: f64, top: f64, right: f64, bottom: f64, colour: [u8; 4]) {
+        let (i0, i1) = Raster::span(left, right, self.width);
+        let (j0, j1) = Raster::span(top, bottom, self.height);
+        for j in j0..j1 {
+            for i in i0..i1 {
+                self.blend(i, j, colour);
+            }
+        }
+    }
+
+    /// Composite a colour over the pixels whose centres are in a disc, and
+    /// the pixel of its centre.
+    fn disc(&mut self, x: f64, y: f64, radius: f64, colour: [u8; 4]) {
+        let (i0, i1) = Raster::span(x - radius, x + radius + 1.0, self.width);
+        let (j0, j1) = Raster::span(y - radius, y + radius + 1.0, self.height);
+        let (ci, cj) = (x.floor(), y.floor());
+        for j in j0..j1 {
+            for i in i0..i1 {
+                let (dx, dy) = (i as f64 + 0.5 - x, j as f64 + 0.5 - y);
+                if dx * dx + dy * dy <= radius * radius || (i as f64, j as f64) == (ci, cj) {
+                    self.blend(i, j, colour);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use accel2d::quadtree::QuadTree;
+    use accel2d::Accel2D;
+    use geom::p2::P2;
+    use geom::rect::Rect;
+    use render::{DefaultStyle, Figure, Marker, Stroke, Style};
+
+    fn tree() -> QuadTree<f64, usize> {
+        let mut tree =
+            QuadTree::with_bounds(Rect::new(0.0, 0.0, 8.0, 8.0).unwrap()).with_leaf_capacity(1);
+        tree.insert(vec![(P2::new(1.0, 1.0), 0), (P2::new(5.0, 5.0), 1)]);
+        tree
+    }
+
+    #[test]
+    fn svg() {
+        let figure = Figure::new(Rect::new(0.0, 0.0, 8.0, 8.0).unwrap(), 80, 80);
+        let mut svg = Vec::new();
+        figure.write_svg(&tree(), &DefaultStyle, &mut svg).unwrap();
+        let svg = String::from_utf8(svg).unwrap();
+        // the background, the root and its four quadrants
+        assert_eq!(svg.matches("<rect").count(), 6);
+        assert!(svg.contains(
+            r##"<rect x="0" y="0" width="40" height="40" fill="none" stroke="#808080""##
+        ));
+        assert!(svg.contains(r#"<circle cx="10" cy="70" r="1.5""#));
+        assert!(svg.ends_with("</svg>\n"));
+    }
+
+    struct Red;
+
+    impl Style<f64, usize> for Red {
+        fn background(&self) -> Option<[u8; 4]> {
+            None
+        }
+
+        fn node(&self, _rect: &Rect<f64>, depth: usize) -> Option<Stroke> {
+            if depth == 0 {
+                Some(Stroke {
+                    colour: [255, 0, 0, 255],
+                    width: 2.0,
+                })
+            } else {
+                None
+            }
+        }
+
+        fn item(&self, item: &(P2<f64>, usize)) -> Option<Marker> {
+            if item.1 == 1 {
+                Some(Marker {
+                    colour: [0, 0, 255, 128],
+                    radius: 0.0,
+                })
+            } else {
+                None
+            }
+        }
+    }
+
+    #[test]
+    fn png() {
+        let figure = Figure::new(Rect::new(0.0, 0.0, 8.0, 8.0).unwrap(), 8, 8);
+        let pixels = figure.rgba(&tree(), &Red);
+        let pixel = |i: usize, j: usize| &pixels[(j * 8 + i) * 4..][..4];
+        // the outline of the root is on the edge of the figure, half inside
+        assert_eq!(pixel(0, 0), &[255, 0, 0, 255]);
+        assert_eq!(pixel(7, 4), &[255, 0, 0, 255]);
+        assert_eq!(pixel(1, 1), &[0, 0, 0, 0]);
+        // the quadrants and the first item are left out
+        assert_eq!(pixel(4, 4), &[0, 0, 0, 0]);
+        assert_eq!(pixel(1, 6), &[0, 0, 0, 0]);
+        assert_eq!(pixel(5, 3), &[0, 0, 255, 128]);
+
+        let mut png = Vec::new();
+        figure.write_png(&tree(), &Red, &mut png).unwrap();
+        assert_eq!(&png[1..4], b"PNG");
+    }
+}
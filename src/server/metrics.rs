@@ -0,0 +1,188 @@
+//! Audit records and metrics of the queries answered by the service.
+//!
+//! Each query is logged as an `info` event with the target
+//! `starquad::audit`, whose fields are its endpoint, region, filter,
+//! duration, records returned and read, shards read, the limit which
+//! stopped it, whether it was answered from the result cache and any error
+//! (so `--log-json` writes an audit log of JSON lines). Totals over all
+//! queries are served at `/metrics` in the Prometheus text format.
+
+use sky::region::Region;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use store::limits::Truncation;
+use store::QueryStats;
+
+/// Endpoints whose queries are counted, in the order of their counters.
+const ENDPOINTS: &[&str] = &["cone", "box", "scs"];
+
+/// Limits which may stop a query, in the order of their counters.
+const TRUNCATIONS: &[Truncation] = &[Truncation::Results, Truncation::Shards, Truncation::Timeout];
+
+/// Upper bounds of the buckets of the histogram of query durations, in
+/// seconds.
+const DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// A query answered by the service.
+#[derive(Debug)]
+pub struct QueryRecord<'a> {
+    /// Endpoint which answered the query: `cone`, `box` or `scs`.
+    pub endpoint: &'static str,
+    pub region: &'a Region,
+    /// Text of the filter of the query, if it has one.
+    pub filter: Option<&'a str>,
+    pub stats: QueryStats,
+    pub truncated: Option<Truncation>,
+    /// Whether the records came from the result cache.
+    pub cached: bool,
+    /// Error which ended the query, if any.
+    pub error: Option<&'a str>,
+}
+
+/// Counters of the queries answered by the service.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    queries: [AtomicU64; 3],
+    errors: [AtomicU64; 3],
+    truncated: [AtomicU64; 3],
+    cache_hits: AtomicU64,
+    rows: AtomicU64,
+    records_read: AtomicU64,
+    shards: AtomicU64,
+    /// Number of queries in each bucket of `DURATION_BUCKETS`, and beyond
+    /// the last.
+    durations: [AtomicU64; 12],
+    duration_us: AtomicU64,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics::default()
+    }
+
+    /// Log a query, and add it to the totals.
+    pub fn record(&self, query: &QueryRecord) {
+        let stats = &query.stats;
+        let duration = stats.elapsed.as_secs_f64();
+        tracing::info!(
+            target: "starquad::audit",
+            endpoint = query.endpoint,
+            region = ?query.region,
+            filter = query.filter,
+            duration_ms = duration * 1000.0,
+            rows = stats.matched,
+            read = stats.read,
+            shards = stats.shards,
+            truncated = query.truncated.map(|t| t.to_string()),
+            cached = query.cached,
+            error = query.error,
+            "query"
+        );
+        let add = |counter: &AtomicU64, n: u64| counter.fetch_add(n, Ordering::Relaxed);
+        if let Some(i) = ENDPOINTS.iter().position(|&e| e == query.endpoint) {
+            add(&self.queries[i], 1);
+            if query.error.is_some() {
+                add(&self.errors[i], 1);
+            }
+        }
+        if let Some(i) = TRUNCATIONS.iter().position(|&t| query.truncated == Some(t)) {
+            add(&self.truncated[i], 1);
+        }
+        if query.cached {
+            add(&self.cache_hits, 1);
+        }
+        add(&self.rows, stats.matched);
+        add(&self.records_read, stats.read);
+        add(&self.shards, stats.shards as u64);
+        let bucket = DURATION_BUCKETS.partition_point(|&bound| bound < duration);
+        add(&self.durations[bucket], 1);
+        add(&self.duration_us, stats.elapsed.as_micros() as u64);
+    }
+
+    /// The totals, in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for Metrics {
+    fn fmt(&self, text: &mut fmt::Formatter) -> fmt::Result {
+        let get = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let header = |text: &mut fmt::Formatter, name: &str, kind: &str, help: &str| {
+            writeln!(text, "# HELP {} {}", name, help)?;
+            writeln!(text, "# TYPE {} {}", name, kind)
+        };
+        let endpoints = ENDPOINTS.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        let limits = TRUNCATIONS
+            .iter()
+            .map(|t| t.to_string())
+            .collect::<Vec<_>>();
+        for (name, help, label, values, counters) in [
+            (
+                "starquad_queries_total",
+                "Queries answered.",
+                "endpoint",
+                &endpoints,
+                &self.queries,
+            ),
+            (
+                "starquad_query_errors_total",
+                "Queries which ended with an error.",
+                "endpoint",
+                &endpoints,
+                &self.errors,
+            ),
+            (
+                "starquad_query_truncated_total",
+                "Queries stopped by a limit.",
+                "limit",
+                &limits,
+                &self.truncated,
+            ),
+        ] {
+            header(text, name, "counter", help)?;
+            for (value, counter) in values.iter().zip(counters) {
+                writeln!(text, "{}{{{}=\"{}\"}} {}", name, label, value, get(counter))?;
+            }
+        }
+        for (name, help, counter) in [
+            (
+                "starquad_query_cache_hits_total",
+                "Queries answered from the result cache.",
+                &self.cache_hits,
+            ),
+            (
+                "starquad_query_rows_total",
+                "Records returned by queries.",
+                &self.rows,
+            ),
+            (
+                "starquad_query_records_read_total",
+                "Records read from shards by queries.",
+                &self.records_read,
+            ),
+            (
+                "starquad_query_shards_total",
+                "Shards read by queries.",
+                &self.shards,
+            ),
+        ] {
+            header(text, name, "counter", help)?;
+            writeln!(text, "{} {}", name, get(counter))?;
+        }
+        let name = "starquad_query_duration_seconds";
+        header(text, name, "histogram", "Time taken by queries.")?;
+        let mut count = 0;
+        for (bound, counter) in DURATION_BUCKETS.iter().zip(&self.durations) {
+            count += get(counter);
+            writeln!(text, "{}_bucket{{le=\"{}\"}} {}", name, bound, count)?;
+        }
+        count += get(&self.durations[DURATION_BUCKETS.len()]);
+        writeln!(text, "{}_bucket{{le=\"+Inf\"}} {}", name, count)?;
+        let sum = get(&self.duration_us) as f64 / 1e6;
+        writeln!(text, "{}_sum {}", name, sum)?;
+        writeln!(text, "{}_count {}", name, count)
+    }
+}
@@ -0,0 +1,906 @@
+//! HTTP service answering queries of a `Store`.
+//!
+//! The service has the endpoints:
+//!
+//! - `GET /cone?ra=&dec=&radius=`: records within `radius` degrees of
+//!   `(ra, dec)`.
+//! - `GET /box?ra_min=&ra_max=&dec_min=&dec_max=`: records in a range of
+//!   right ascension and declination.
+//! - `GET /manifest`: the manifest of the store, as JSON.
+//! - `GET /scs?RA=&DEC=&SR=`: an IVOA Simple Cone Search (see `scs`).
+//! - `GET /metrics`: totals of the queries answered, in the Prometheus text
+//!   format (see `metrics`).
+//!
+//! The query endpoints also accept `filter` (a filter expression), `columns`
+//! (a comma-separated list of columns) and `format` (`csv`, the default,
+//! `jsonl`, `bincode` or, with the `arrow` feature, `arrow` for an Arrow IPC
+//! stream). Results are streamed as they are read from the
+//! store, so that large result sets are not held in memory.
+//!
+//! Results can also be read a page at a time: with `limit`, a response
+//! holds at most that many records, and if there may be more, the header
+//! `x-next-cursor` holds a continuation token. The same query with `cursor`
+//! set to the token returns the records which follow (see `store::Cursor`).
+//!
+//! A service may limit the records returned, shards read and time taken by
+//! each query (see `store::limits`). A query which reaches a limit returns
+//! the records it found, with the header `x-query-truncated` naming the
+//! limit and `x-next-cursor` the token to continue it. Responses of a
+//! service with limits are buffered, so its limit on records also bounds
+//! the memory each request takes.
+//!
+//! A service with a `ResultCache` answers a query asked again (with its
+//! filter and columns in any equivalent form) from memory, unless it asks
+//! for a page or continues from a cursor (see `store::results`).
+//!
+//! A service given an `Auth` refuses requests without accepted credentials
+//! (`401 Unauthorized`) and, with a rate limit, requests beyond the limit of
+//! their client (`429 Too Many Requests`, with a `Retry-After` header).
+
+use auth::{Auth, Credentials, Denied, API_KEY_HEADER, AUTHORIZATION_HEADER};
+use axum::body::Body;
+use axum::extract::{Query, Request, State};
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use bytes::Bytes;
+use gaia::filter::Filter;
+use gaia::projection::Projection;
+use gaia::record::GaiaRecord;
+use output;
+use output::Format;
+use serde::Deserialize;
+use server::metrics::{Metrics, QueryRecord};
+use sky::position::SkyPosition;
+use sky::region::Region;
+use std::future::{self, Future, IntoFuture, Ready};
+use std::io;
+use std::io::Write;
+use std::net::TcpListener;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll};
+use std::time::Instant;
+use store::limits::{QueryLimits, Truncation};
+use store::results::{QueryKey, QueryResult, ResultCache};
+use store::{Cursor, Query as StoreQuery, QueryStats, Store};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::ReceiverStream;
+
+pub(crate) mod metrics;
+pub(crate) mod scs;
+
+/// Number of chunks of a response which may be buffered before the query
+/// waits for the client.
+const CHANNEL_CAPACITY: usize = 16;
+
+#[derive(Deserialize)]
+struct ConeParams {
+    ra: f64,
+    dec: f64,
+    radius: f64,
+    filter: Option<String>,
+    columns: Option<String>,
+    format: Option<String>,
+    limit: Option<usize>,
+    cursor: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BoxParams {
+    ra_min: f64,
+    ra_max: f64,
+    dec_min: f64,
+    dec_max: f64,
+    filter: Option<String>,
+    columns: Option<String>,
+    format: Option<String>,
+    limit: Option<usize>,
+    cursor: Option<String>,
+}
+
+/// Options controlling which records are returned, and how.
+struct OutputOptions {
+    /// Filter of the records, and its text.
+    filter: Option<(String, Filter)>,
+    projection: Projection,
+    format: Format,
+    /// Number of records of a page, or `None` to return them all.
+    limit: Option<usize>,
+    /// Position after the records of the previous page.
+    cursor: Cursor,
+    limits: QueryLimits,
+}
+
+/// Header of a page of results holding the cursor of the next page.
+pub const NEXT_CURSOR: &str = "x-next-cursor";
+
+/// Header of a response naming the limit which stopped its query.
+pub const TRUNCATED: &str = "x-query-truncated";
+
+/// State shared by the handlers.
+#[derive(Clone)]
+struct Service {
+    store: Arc<Store>,
+    limits: QueryLimits,
+    metrics: Arc<Metrics>,
+    results: Option<Arc<Mutex<ResultCache>>>,
+}
+
+fn bad_request(message: String) -> Response {
+    (StatusCode::BAD_REQUEST, message + "\n").into_response()
+}
+
+impl OutputOptions {
+    fn parse(
+        filter: Option<&str>,
+        columns: Option<&str>,
+        format: Option<&str>,
+        limit: Option<usize>,
+        cursor: Option<&str>,
+        limits: QueryLimits,
+    ) -> Result<Self, String> {
+        let filter = filter
+            .map(|text| match text.parse::<Filter>() {
+                Ok(filter) => Ok((String::from(text), filter)),
+                Err(e) => Err(format!("invalid filter: {}", e)),
+            })
+            .transpose()?;
+        let projection = match columns {
+            Some(columns) => Projection::new(&columns.split(',').collect::<Vec<_>>())
+                .map_err(|e| e.to_string())?,
+            None => Projection::all(),
+        };
+        let format = format
+            .unwrap_or("csv")
+            .parse()
+            .map_err(|e: output::Error| e.to_string())?;
+        let cursor = cursor.map_or(Ok(Cursor::Start), str::parse)?;
+        Ok(OutputOptions {
+            filter,
+            projection,
+            format,
+            limit,
+            cursor,
+            limits,
+        })
+    }
+
+    /// Records of a store in a region which the options select.
+    fn query(&self, store: &Store, region: &Region) -> StoreQuery {
+        let query = store
+            .query(region)
+            .select(&self.projection)
+            .with_limits(self.limits);
+        let query = match &self.filter {
+            Some((_, filter)) => query.matching(filter),
+            None => query,
+        };
+        query.after(self.cursor)
+    }
+
+    /// Whether the records of a query with the options may be answered
+    /// from, and kept in, a result cache: only whole results are kept.
+    fn cacheable(&self) -> bool {
+        self.limit.is_none() && self.cursor == Cursor::Start
+    }
+
+    fn cache_key(&self, region: &Region) -> QueryKey {
+        let filter = self.filter.as_ref().map(|(_, filter)| filter);
+        QueryKey::new(region, filter, &self.projection)
+    }
+
+    /// Add a query of a region with the options to the audit log and the
+    /// metrics of a service.
+    fn record(
+        &self,
+        metrics: &Metrics,
+        endpoint: &'static str,
+        region: &Region,
+        query: &StoreQuery,
+        error: Option<&str>,
+    ) {
+        metrics.record(&QueryRecord {
+            endpoint,
+            region,
+            filter: self.filter.as_ref().map(|(text, _)| text.as_str()),
+            stats: query.stats(),
+            truncated: query.truncated(),
+            cached: false,
+            error,
+        });
+    }
+}
+
+fn lock(cache: &Mutex<ResultCache>) -> MutexGuard<'_, ResultCache> {
+    cache.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Records of a query kept for a result cache, while they fit in it.
+struct Keep {
+    cache: Arc<Mutex<ResultCache>>,
+    key: QueryKey,
+    records: Option<Vec<GaiaRecord>>,
+    max_records: u64,
+}
+
+impl Keep {
+    fn new(cache: Arc<Mutex<ResultCache>>, key: QueryKey) -> Self {
+        let max_records = lock(&cache).max_records();
+        Keep {
+            cache,
+            key,
+            records: Some(Vec::new()),
+            max_records,
+        }
+    }
+
+    fn push(&mut self, record: &GaiaRecord) {
+        if let Some(records) = &mut self.records {
+            if (records.len() as u64) < self.max_records {
+                records.push(record.clone());
+            } else {
+                self.records = None;
+            }
+        }
+    }
+
+    /// Add the records to the cache, unless the query ran out of time, and
+    /// so might return more records if it were run again.
+    fn finish(self, query: &StoreQuery) {
+        let truncated = query.truncated();
+        if let (Some(records), false) = (self.records, truncated == Some(Truncation::Timeout)) {
+            let result = QueryResult {
+                records,
+                truncated: truncated.map(|truncation| (truncation, query.cursor())),
+            };
+            lock(&self.cache).insert(self.key, Arc::new(result));
+        }
+    }
+}
+
+fn content_type(format: Format) -> &'static str {
+    match format {
+        Format::Csv => "text/csv",
+        Format::Jsonl => "application/x-ndjson",
+        Format::Bincode => "application/octet-stream",
+        #[cfg(feature = "arrow")]
+        Format::Arrow => "application/vnd.apache.arrow.stream",
+    }
+}
+
+/// Writer sending its output as chunks of a response body.
+struct ChannelWriter {
+    sender: mpsc::Sender<io::Result<Bytes>>,
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.sender
+            .blocking_send(Ok(Bytes::copy_from_slice(buf)))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "client disconnected"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Write the records of a query to a channel, keeping them for a result
+/// cache with `keep`. An error stops the query, and is sent to abort the
+/// response.
+fn send_query(
+    service: &Service,
+    endpoint: &'static str,
+    region: &Region,
+    options: &OutputOptions,
+    mut keep: Option<Keep>,
+    sender: mpsc::Sender<io::Result<Bytes>>,
+) {
+    let writer = ChannelWriter {
+        sender: sender.clone(),
+    };
+    let mut sink = options
+        .format
+        .projected_sink(writer, options.projection.clone());
+    let mut query = options.query(&service.store, region);
+    let result = query
+        .by_ref()
+        .try_for_each(|record| {
+            let record = record.map_err(|e| e.to_string())?;
+            if let Some(keep) = &mut keep {
+                keep.push(&record);
+            }
+            sink.write(&record).map_err(|e| e.to_string())
+        })
+        .and_then(|()| sink.finish().map_err(|e| e.to_string()));
+    let error = result.err();
+    options.record(&service.metrics, endpoint, region, &query, error.as_deref());
+    match (error, keep) {
+        (Some(message), _) => {
+            let _ = sender.blocking_send(Err(io::Error::other(message)));
+        }
+        (None, Some(keep)) => keep.finish(&query),
+        (None, None) => {}
+    }
+}
+
+/// Respond with a body written by `write` on a blocking thread, so that
+/// reading the store does not hold up other requests.
+fn blocking_response<F>(content_type: &'static str, write: F) -> Response
+where
+    F: FnOnce(mpsc::Sender<io::Result<Bytes>>) + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+    tokio::task::spawn_blocking(move || write(sender));
+    (
+        [(header::CONTENT_TYPE, content_type)],
+        Body::from_stream(ReceiverStream::new(receiver)),
+    )
+        .into_response()
+}
+
+/// Page of the records of a query.
+struct Page {
+    body: Vec<u8>,
+    /// Cursor of the next page, if there may be one.
+    next: Option<Cursor>,
+    /// Limit which stopped the query before the page was full.
+    truncated: Option<Truncation>,
+}
+
+/// Write a page of at most `limit` records of a query, keeping them for a
+/// result cache with `keep`.
+fn write_page(
+    query: &mut StoreQuery,
+    options: &OutputOptions,
+    limit: usize,
+    keep: &mut Option<Keep>,
+) -> Result<Page, String> {
+    let mut body = Vec::new();
+    {
+        let mut sink = options
+            .format
+            .projected_sink(&mut body, options.projection.clone());
+        for record in query.by_ref().take(limit) {
+            let record = record.map_err(|e| e.to_string())?;
+            if let Some(keep) = keep {
+                keep.push(&record);
+            }
+            sink.write(&record).map_err(|e| e.to_string())?;
+        }
+        sink.finish().map_err(|e| e.to_string())?;
+    }
+    let truncated = query.truncated();
+    let cursor = query.cursor();
+    let next = match truncated {
+        Some(_) => Some(cursor),
+        // a limit reached looking for more records leaves a cursor too
+        None => match query.next() {
+            Some(_) => Some(cursor),
+            None => query.truncated().map(|_| query.cursor()),
+        },
+    };
+    Ok(Page {
+        body,
+        next,
+        truncated,
+    })
+}
+
+/// Response of a handler: ready, or built on a blocking thread so that
+/// reading the store does not hold up other requests.
+enum Reply {
+    Ready(Ready<Response>),
+    Blocking(JoinHandle<Response>),
+}
+
+impl From<Response> for Reply {
+    fn from(response: Response) -> Self {
+        Reply::Ready(future::ready(response))
+    }
+}
+
+impl Future for Reply {
+    type Output = Response;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Response> {
+        match self.get_mut() {
+            Reply::Ready(response) => Pin::new(response).poll(cx),
+            Reply::Blocking(handle) => Pin::new(handle).poll(cx).map(|result| {
+                result.unwrap_or_else(|e| {
+                    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+                })
+            }),
+        }
+    }
+}
+
+fn page_response(content_type: &'static str, page: Page) -> Response {
+    let mut response = ([(header::CONTENT_TYPE, content_type)], page.body).into_response();
+    let headers = response.headers_mut();
+    if let Some(cursor) = page.next {
+        match HeaderValue::from_str(&cursor.to_string()) {
+            Ok(value) => headers.insert(NEXT_CURSOR, value),
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        };
+    }
+    if let Some(truncation) = page.truncated {
+        headers.insert(TRUNCATED, HeaderValue::from_static(truncation.as_str()));
+    }
+    response
+}
+
+/// Respond with the records of a query held by the result cache.
+fn cached_response(
+    service: &Service,
+    endpoint: &'static str,
+    region: &Region,
+    options: &OutputOptions,
+    result: &QueryResult,
+) -> Response {
+    let start = Instant::now();
+    let mut body = Vec::new();
+    let written = {
+        let mut sink = options
+            .format
+            .projected_sink(&mut body, options.projection.clone());
+        result
+            .records
+            .iter()
+            .try_for_each(|record| sink.write(record))
+            .and_then(|()| sink.finish())
+            .map_err(|e| e.to_string())
+    };
+    let stats = QueryStats {
+        matched: result.records.len() as u64,
+        elapsed: start.elapsed(),
+        ..QueryStats::default()
+    };
+    service.metrics.record(&QueryRecord {
+        endpoint,
+        region,
+        filter: options.filter.as_ref().map(|(text, _)| text.as_str()),
+        stats,
+        truncated: result.truncated.map(|(truncation, _)| truncation),
+        cached: true,
+        error: written.as_ref().err().map(String::as_str),
+    });
+    match written {
+        Ok(()) => page_response(
+            content_type(options.format),
+            Page {
+                body,
+                next: result.truncated.map(|(_, cursor)| cursor),
+                truncated: result.truncated.map(|(truncation, _)| truncation),
+            },
+        ),
+        Err(message) => (StatusCode::INTERNAL_SERVER_ERROR, message + "\n").into_response(),
+    }
+}
+
+/// Respond with the records of a query, or with a page of them, from the
+/// result cache of the service if it holds them.
+fn query_response(
+    service: Service,
+    endpoint: &'static str,
+    region: Region,
+    options: OutputOptions,
+) -> Reply {
+    let mut keep = None;
+    if let (Some(cache), true) = (&service.results, options.cacheable()) {
+        let key = options.cache_key(&region);
+        let cached = lock(cache).get(&key);
+        if let Some(result) = cached {
+            return Reply::Blocking(tokio::task::spawn_blocking(move || {
+                cached_response(&service, endpoint, &region, &options, &result)
+            }));
+        }
+        keep = Some(Keep::new(cache.clone(), key));
+    }
+    let content_type = content_type(options.format);
+    if options.limit.is_none() && options.limits.is_unlimited() {
+        return blocking_response(content_type, move |sender| {
+            send_query(&service, endpoint, &region, &options, keep, sender)
+        })
+        .into();
+    }
+    let limit = options.limit.unwrap_or(usize::MAX);
+    Reply::Blocking(tokio::task::spawn_blocking(move || {
+        let mut query = options.query(&service.store, &region);
+        let page = write_page(&mut query, &options, limit, &mut keep);
+        let error = page.as_ref().err().map(String::as_str);
+        options.record(&service.metrics, endpoint, &region, &query, error);
+        match page {
+            Ok(page) => {
+                if let Some(keep) = keep {
+                    keep.finish(&query);
+                }
+                page_response(content_type, page)
+            }
+            Err(message) => (StatusCode::INTERNAL_SERVER_ERROR, message + "\n").into_response(),
+        }
+    }))
+}
+
+fn cone(State(service): State<Service>, Query(params): Query<ConeParams>) -> Reply {
+    match OutputOptions::parse(
+        params.filter.as_deref(),
+        params.columns.as_deref(),
+        params.format.as_deref(),
+        params.limit,
+        params.cursor.as_deref(),
+        service.limits,
+    ) {
+        Ok(_) if params.radius.is_nan() || params.radius < 0.0 => {
+            bad_request(String::from("radius must not be negative")).into()
+        }
+        Ok(options) => {
+            let region = Region::cone(SkyPosition::new(params.ra, params.dec), params.radius);
+            query_response(service, "cone", region, options)
+        }
+        Err(message) => bad_request(message).into(),
+    }
+}
+
+fn sky_box(params: &BoxParams) -> Result<Region, String> {
+    Region::sky_box(params.ra_min, params.ra_max, params.dec_min, params.dec_max)
+        .map_err(|e| e.to_string())
+}
+
+fn query_box(State(service): State<Service>, Query(params): Query<BoxParams>) -> Reply {
+    match OutputOptions::parse(
+        params.filter.as_deref(),
+        params.columns.as_deref(),
+        params.format.as_deref(),
+        params.limit,
+        params.cursor.as_deref(),
+        service.limits,
+    )
+    .and_then(|options| Ok((sky_box(&params)?, options)))
+    {
+        Ok((region, options)) => query_response(service, "box", region, options),
+        Err(message) => bad_request(message).into(),
+    }
+}
+
+fn manifest(State(service): State<Service>) -> Ready<Response> {
+    let response = match serde_json::to_vec(service.store.manifest()) {
+        Ok(json) => ([(header::CONTENT_TYPE, "application/json")], json).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    };
+    future::ready(response)
+}
+
+fn metrics(State(service): State<Service>) -> Ready<Response> {
+    let content_type = "text/plain; version=0.0.4";
+    future::ready(
+        (
+            [(header::CONTENT_TYPE, content_type)],
+            service.metrics.render(),
+        )
+            .into_response(),
+    )
+}
+
+/// Response refusing a request.
+fn denied_response(denied: Denied) -> Response {
+    let message = denied.to_string() + "\n";
+    match denied {
+        Denied::Missing | Denied::Invalid => (StatusCode::UNAUTHORIZED, message).into_response(),
+        Denied::RateLimited(wait) => {
+            let seconds = wait.as_secs_f64().ceil().to_string();
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, seconds)],
+                message,
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Pass on the requests which an `Auth` allows, and refuse the others.
+fn authenticate(
+    State(auth): State<Arc<Auth>>,
+    request: Request,
+    next: Next,
+) -> Pin<Box<dyn Future<Output = Response> + Send>> {
+    let header = |name: &str| {
+        request
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    };
+    let credentials = Credentials {
+        authorization: header(AUTHORIZATION_HEADER),
+        api_key: header(API_KEY_HEADER),
+    };
+    match auth.check(&credentials) {
+        Ok(_) => Box::pin(next.run(request)),
+        Err(denied) => Box::pin(future::ready(denied_response(denied))),
+    }
+}
+
+/// Routes of the service, which stops each query at some limits, answers
+/// repeated queries from a result cache, if it is given one, and refuses
+/// the requests which `auth` does not allow.
+pub fn router(
+    store: Store,
+    limits: QueryLimits,
+    results: Option<ResultCache>,
+    auth: Option<Auth>,
+) -> Router {
+    let router = Router::new()
+        .route("/cone", get(cone))
+        .route("/box", get(query_box))
+        .route("/manifest", get(manifest))
+        .route("/scs", get(scs::cone_search))
+        .route("/metrics", get(metrics))
+        .with_state(Service {
+            store: Arc::new(store),
+            limits,
+            metrics: Arc::new(Metrics::new()),
+            results: results.map(|cache| Arc::new(Mutex::new(cache))),
+        });
+    match auth {
+        Some(auth) => router.layer(middleware::from_fn_with_state(Arc::new(auth), authenticate)),
+        None => router,
+    }
+}
+
+/// Serve queries of a store on a listening socket, until an error occurs.
+pub fn serve(
+    store: Store,
+    limits: QueryLimits,
+    results: Option<ResultCache>,
+    auth: Option<Auth>,
+    listener: TcpListener,
+) -> io::Result<()> {
+    let runtime = tokio::runtime::Runtime::new()?;
+    let _guard = runtime.enter();
+    listener.set_nonblocking(true)?;
+    let listener = tokio::net::TcpListener::from_std(listener)?;
+    runtime.block_on(axum::serve(listener, router(store, limits, results, auth)).into_future())
+}
+
+#[cfg(test)]
+mod test {
+    use auth::{Auth, BearerTokens, RateLimit};
+    use gaia::record::test::sample_record;
+    use server::serve;
+    use std::env;
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use store::builder::StoreBuilder;
+    use store::limits::QueryLimits;
+    use store::results::ResultCache;
+    use store::Store;
+
+    fn get(address: &str, path: &str) -> String {
+        get_with_headers(address, path, "")
+    }
+
+    fn get_with_headers(address: &str, path: &str, headers: &str) -> String {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(
+            stream,
+            "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n{}\r\n",
+            path, headers
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn queries() {
+        let dir = env::temp_dir().join(format!("starquad-server-test-{}", std::process::id()));
+        let mut builder = StoreBuilder::new(&dir).unwrap().with_order(1);
+        for i in 0..100 {
+            let mut record = sample_record();
+            record.source_id = 5000 + i;
+            record.ra = i as f64;
+            record.dec = 10.0;
+            builder.push(&record).unwrap();
+        }
+        builder.finish().unwrap();
+        let store = Store::open(&dir).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || serve(store, QueryLimits::new(), None, None, listener));
+
+        let response = get(&address, "/cone?ra=20&dec=10&radius=0.5&columns=source_id");
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.contains("text/csv"));
+        assert!(response.contains("source_id\n5020\n"));
+
+        let response = get(
+            &address,
+            "/box?ra_min=30.5&ra_max=32.5&dec_min=0&dec_max=20&format=jsonl&columns=source_id,ra",
+        );
+        assert!(response.contains("{\"source_id\":5031,\"ra\":31.0}\n"));
+        assert!(response.contains("{\"source_id\":5032,\"ra\":32.0}\n"));
+
+        let response = get(
+            &address,
+            "/cone?ra=20&dec=10&radius=1&filter=parallax%20%3E",
+        );
+        assert!(response.starts_with("HTTP/1.1 400"));
+        let response = get(&address, "/cone?ra=20&dec=10");
+        assert!(response.starts_with("HTTP/1.1 400"));
+
+        let response = get(&address, "/scs?RA=20&DEC=10&SR=0.5&VERB=1");
+        assert!(response.contains("x-votable"));
+        assert!(response.contains(r#"ucd="pos.eq.ra;meta.main""#));
+        assert!(response.contains("<TR><TD>5020</TD><TD>20</TD><TD>10</TD></TR>"));
+        let response = get(&address, "/scs?RA=20&DEC=10");
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(
+            response.contains(r#"<INFO name="QUERY_STATUS" value="ERROR">missing parameter SR"#)
+        );
+
+        // pages of a box, followed by their cursors
+        let mut ids = Vec::new();
+        let mut path = String::from("/box?ra_min=0&ra_max=99&dec_min=0&dec_max=20&limit=30");
+        loop {
+            let response = get(&address, &format!("{}&columns=source_id", path));
+            assert!(response.starts_with("HTTP/1.1 200"));
+            let (head, body) = response.split_once("\r\n\r\n").unwrap();
+            let page = body.lines().skip(1).collect::<Vec<_>>();
+            assert!(page.len() <= 30);
+            ids.extend(page.iter().map(|id| id.parse::<u64>().unwrap()));
+            match head.lines().find_map(|h| h.strip_prefix("x-next-cursor: ")) {
+                Some(cursor) => {
+                    path = format!(
+                        "/box?ra_min=0&ra_max=99&dec_min=0&dec_max=20&limit=30&cursor={}",
+                        cursor
+                    )
+                }
+                None => break,
+            }
+        }
+        ids.sort_unstable();
+        assert_eq!(ids, (5000..5100).collect::<Vec<_>>());
+        let response = get(&address, "/cone?ra=20&dec=10&radius=1&cursor=x");
+        assert!(response.starts_with("HTTP/1.1 400"));
+
+        let response = get(&address, "/manifest");
+        assert!(response.contains("\"order\":1"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn limits() {
+        let dir = env::temp_dir().join(format!("starquad-server-limits-{}", std::process::id()));
+        let mut builder = StoreBuilder::new(&dir).unwrap().with_order(1);
+        for i in 0..100 {
+            let mut record = sample_record();
+            record.source_id = 5000 + i;
+            record.ra = i as f64;
+            record.dec = 10.0;
+            builder.push(&record).unwrap();
+        }
+        builder.finish().unwrap();
+        let store = Store::open(&dir).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let limits = QueryLimits::new().with_max_results(40);
+        thread::spawn(move || serve(store, limits, None, None, listener));
+
+        // a query stops at the limit, and its cursor continues it
+        let path = "/box?ra_min=0&ra_max=99&dec_min=0&dec_max=20&columns=source_id";
+        let response = get(&address, path);
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert_eq!(body.lines().count(), 41);
+        assert!(head.contains("x-query-truncated: results"));
+        let cursor = head
+            .lines()
+            .find_map(|h| h.strip_prefix("x-next-cursor: "))
+            .unwrap();
+        let response = get(&address, &format!("{}&limit=10&cursor={}", path, cursor));
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert_eq!(body.lines().count(), 11);
+        assert!(!head.contains("x-query-truncated"));
+        assert!(head.contains("x-next-cursor"));
+
+        let response = get(&address, "/scs?RA=50&DEC=10&SR=90&VERB=1");
+        assert_eq!(response.matches("<TR>").count(), 40);
+        assert!(response.contains(r#"<INFO name="QUERY_STATUS" value="OVERFLOW">"#));
+        let response = get(&address, "/scs?RA=50&DEC=10&SR=0.5&VERB=1");
+        assert!(!response.contains("OVERFLOW"));
+
+        // every query is counted, with the limits which stopped them
+        let metrics = get(&address, "/metrics");
+        for line in [
+            "starquad_queries_total{endpoint=\"cone\"} 0",
+            "starquad_queries_total{endpoint=\"box\"} 2",
+            "starquad_queries_total{endpoint=\"scs\"} 2",
+            "starquad_query_truncated_total{limit=\"results\"} 2",
+            "starquad_query_duration_seconds_bucket{le=\"+Inf\"} 4",
+            "starquad_query_duration_seconds_count 4",
+        ] {
+            assert!(metrics.lines().any(|l| l == line), "{} missing", line);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn auth() {
+        let dir = env::temp_dir().join(format!("starquad-server-auth-{}", std::process::id()));
+        let mut builder = StoreBuilder::new(&dir).unwrap().with_order(1);
+        builder.push(&sample_record()).unwrap();
+        builder.finish().unwrap();
+        let store = Store::open(&dir).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let auth = Auth::new(BearerTokens::new().with_token("alice", "t1").unwrap())
+            .with_rate_limit(RateLimit::new(0.01, 2).unwrap());
+        thread::spawn(move || serve(store, QueryLimits::new(), None, Some(auth), listener));
+
+        let status = |headers: &str| {
+            let response = get_with_headers(&address, "/manifest", headers);
+            String::from(response.lines().next().unwrap())
+        };
+        assert_eq!(status(""), "HTTP/1.1 401 Unauthorized");
+        assert_eq!(
+            status("Authorization: Bearer t2\r\n"),
+            "HTTP/1.1 401 Unauthorized"
+        );
+        // the client may make a burst of two requests
+        for _ in 0..2 {
+            assert_eq!(status("Authorization: Bearer t1\r\n"), "HTTP/1.1 200 OK");
+        }
+        let response = get_with_headers(&address, "/manifest", "Authorization: Bearer t1\r\n");
+        assert!(response.starts_with("HTTP/1.1 429 Too Many Requests"));
+        assert!(response.contains("retry-after: 100"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn result_cache() {
+        let dir = env::temp_dir().join(format!("starquad-server-results-{}", std::process::id()));
+        let mut builder = StoreBuilder::new(&dir).unwrap().with_order(1);
+        for i in 0..100 {
+            let mut record = sample_record();
+            record.source_id = 5000 + i;
+            record.ra = i as f64;
+            record.dec = 10.0;
+            builder.push(&record).unwrap();
+        }
+        builder.finish().unwrap();
+        let store = Store::open(&dir).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let limits = QueryLimits::new().with_max_results(40);
+        let results = ResultCache::new(1000);
+        thread::spawn(move || serve(store, limits, Some(results), None, listener));
+
+        // a repeated query, written differently, is answered from the cache
+        let path = "/box?ra_min=0&ra_max=99&dec_min=0&dec_max=20&columns=source_id,ra";
+        let first = get(&address, &format!("{}&filter=ra%20%3E%2010", path));
+        let again = get(
+            &address,
+            &format!("{}&filter=ra%20%3E%2010&format=jsonl", path),
+        );
+        let again_csv = get(&address, &format!("{}&filter=ra%3E10", path));
+        let body = |response: &str| String::from(response.split_once("\r\n\r\n").unwrap().1);
+        assert_eq!(body(&first), body(&again_csv));
+        assert_eq!(body(&again).lines().count(), 40);
+        assert!(again.contains("x-query-truncated: results"));
+        // pages are not
+        get(&address, &format!("{}&limit=10", path));
+        let metrics = get(&address, "/metrics");
+        assert!(metrics
+            .lines()
+            .any(|l| l == "starquad_query_cache_hits_total 2"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
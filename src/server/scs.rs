@@ -0,0 +1,202 @@
+//! IVOA Simple Cone Search (version 1.03).
+//!
+//! A search is a `GET` request with the parameters `RA` and `DEC` (the
+//! center, in degrees) and `SR` (the search radius, in degrees), and
+//! optionally `VERB`, which selects the columns returned:
+//!
+//! - `VERB=1`: `source_id`, `ra` and `dec`;
+//! - `VERB=2` (the default): the main astrometric and photometric columns;
+//! - `VERB=3`: all columns.
+//!
+//! Results are returned as a VOTable, in which `source_id`, `ra` and `dec`
+//! have the UCDs required by the standard. Errors (including invalid
+//! parameters) are reported with status 200 as a VOTable containing an
+//! `INFO` element named `QUERY_STATUS` with the value `ERROR`, and a search
+//! stopped by a limit of the service returns the rows it found followed by
+//! one with the value `OVERFLOW`.
+
+use axum::extract::{Query, State};
+use axum::response::Response;
+use bytes::Bytes;
+use catalog::votable::{self, VoTable};
+use server::metrics::QueryRecord;
+use server::{blocking_response, ChannelWriter, Service};
+use sky::position::SkyPosition;
+use sky::region::Region;
+use std::collections::HashMap;
+use std::future::{self, Ready};
+use std::io;
+use std::io::Write;
+use store::limits::Truncation;
+use tokio::sync::mpsc;
+
+/// Content type of VOTable responses.
+const CONTENT_TYPE: &str = "text/xml;content=x-votable";
+
+/// Columns returned with `VERB=2`.
+const DEFAULT_COLUMNS: &[&str] = &[
+    "source_id",
+    "ref_epoch",
+    "ra",
+    "ra_error",
+    "dec",
+    "dec_error",
+    "parallax",
+    "parallax_error",
+    "pmra",
+    "pmra_error",
+    "pmdec",
+    "pmdec_error",
+    "phot_g_mean_mag",
+    "phot_bp_mean_mag",
+    "phot_rp_mean_mag",
+];
+
+/// Parameters of a cone search.
+#[derive(Clone, Debug, PartialEq)]
+struct Search {
+    center: SkyPosition,
+    radius: f64,
+    verbosity: u8,
+}
+
+/// Parse the parameters of a search. Parameter names are matched without
+/// regard to case.
+fn parse(params: &HashMap<String, String>) -> Result<Search, String> {
+    let get = |name: &str| {
+        params
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.trim())
+    };
+    let number = |name: &str| -> Result<f64, String> {
+        let value = get(name).ok_or_else(|| format!("missing parameter {}", name))?;
+        value
+            .parse::<f64>()
+            .ok()
+            .filter(|v| v.is_finite())
+            .ok_or_else(|| format!("invalid value for {}: {:?}", name, value))
+    };
+    let center = SkyPosition::new(number("RA")?, number("DEC")?);
+    if !(-90.0..=90.0).contains(&center.dec) {
+        return Err(String::from("DEC must be between -90 and 90"));
+    }
+    let radius = number("SR")?;
+    if radius < 0.0 {
+        return Err(String::from("SR must not be negative"));
+    }
+    let verbosity = match get("VERB") {
+        None | Some("") => 2,
+        Some(verb) => verb
+            .parse::<u8>()
+            .ok()
+            .filter(|v| (1..=3).contains(v))
+            .ok_or_else(|| format!("invalid value for VERB: {:?}", verb))?,
+    };
+    Ok(Search {
+        center,
+        radius,
+        verbosity,
+    })
+}
+
+/// Run a search, returning the VOTable of its results and the limit which
+/// stopped it, if any.
+fn search(service: &Service, search: &Search) -> Result<(VoTable, Option<Truncation>), String> {
+    let region = Region::cone(search.center, search.radius);
+    let mut query = service.store.query(&region).with_limits(service.limits);
+    let records = query
+        .by_ref()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string());
+    service.metrics.record(&QueryRecord {
+        endpoint: "scs",
+        region: &region,
+        filter: None,
+        stats: query.stats(),
+        truncated: query.truncated(),
+        cached: false,
+        error: records.as_ref().err().map(String::as_str),
+    });
+    let mut table = VoTable::from_gaia_records(&records?).map_err(|e| e.to_string())?;
+    match search.verbosity {
+        1 => table.retain_fields(|f| ["source_id", "ra", "dec"].contains(&f.name.as_str())),
+        2 => table.retain_fields(|f| DEFAULT_COLUMNS.contains(&f.name.as_str())),
+        _ => {}
+    }
+    Ok((table, query.truncated()))
+}
+
+/// Write the results of a search, or an error document.
+fn send_search(
+    service: &Service,
+    request: Result<Search, String>,
+    sender: mpsc::Sender<io::Result<Bytes>>,
+) {
+    let mut document = Vec::new();
+    let written = match request.and_then(|s| search(service, &s)) {
+        Ok((table, None)) => table.write(&mut document),
+        Ok((table, Some(truncation))) => {
+            let message = format!("query stopped at its {} limit", truncation);
+            table.write_overflow(&mut document, &message)
+        }
+        Err(message) => votable::write_error(&mut document, &message),
+    };
+    if written.is_ok() {
+        // a failure to send means the client has gone
+        let _ = ChannelWriter { sender }.write_all(&document);
+    }
+}
+
+pub(super) fn cone_search(
+    State(service): State<Service>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Ready<Response> {
+    let request = parse(&params);
+    future::ready(blocking_response(CONTENT_TYPE, move |sender| {
+        send_search(&service, request, sender)
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use server::scs::{parse, Search};
+    use sky::position::SkyPosition;
+    use std::collections::HashMap;
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (String::from(*k), String::from(*v)))
+            .collect()
+    }
+
+    #[test]
+    fn parameters() {
+        assert_eq!(
+            parse(&params(&[("RA", "10.5"), ("dec", "-20"), ("SR", "0.1")])),
+            Ok(Search {
+                center: SkyPosition::new(10.5, -20.0),
+                radius: 0.1,
+                verbosity: 2,
+            })
+        );
+        let verbose = parse(&params(&[
+            ("RA", "0"),
+            ("DEC", "0"),
+            ("SR", "0"),
+            ("VERB", "3"),
+        ]));
+        assert_eq!(verbose.map(|s| s.verbosity), Ok(3));
+        assert!(parse(&params(&[("RA", "0"), ("DEC", "0")])).is_err());
+        assert!(parse(&params(&[("RA", "0"), ("DEC", "91"), ("SR", "1")])).is_err());
+        assert!(parse(&params(&[("RA", "x"), ("DEC", "0"), ("SR", "1")])).is_err());
+        assert!(parse(&params(&[
+            ("RA", "0"),
+            ("DEC", "0"),
+            ("SR", "1"),
+            ("VERB", "4")
+        ]))
+        .is_err());
+    }
+}
@@ -0,0 +1,356 @@
+use accel2d::quadtree::QuadTree;
+use accel2d::Accel2D;
+use geom::p2::P2;
+use geom::rect::Rect;
+use serde::Serialize;
+use sky::healpix;
+use sky::position::SkyPosition;
+use sky::region::Region;
+use std::slice;
+
+/// Two-dimensional array of values, stored row by row from the top.
+///
+/// Pixels which lie outside a projection are `NaN`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub values: Vec<f64>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Self {
+        Image {
+            width,
+            height,
+            values: vec![0.0; width * height],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> f64 {
+        self.values[y * self.width + x]
+    }
+
+    pub fn rows(&self) -> slice::Chunks<'_, f64> {
+        self.values.chunks(self.width.max(1))
+    }
+
+    /// Smallest and largest values which are not `NaN`.
+    pub fn range(&self) -> Option<(f64, f64)> {
+        self.values
+            .iter()
+            .filter(|v| !v.is_nan())
+            .fold(None, |range, &v| match range {
+                None => Some((v, v)),
+                Some((min, max)) => Some((v.min(min), v.max(max))),
+            })
+    }
+}
+
+/// Counts of sources in the pixels of a HEALPix map, in the NESTED
+/// numbering scheme.
+///
+/// ```
+/// # use starquad::sky::density::HealpixMap;
+/// # use starquad::sky::position::SkyPosition;
+/// let mut map = HealpixMap::new(0);
+/// map.add(&SkyPosition::new(0.0, 0.0));
+/// assert_eq!(map.counts()[4], 1);
+/// assert_eq!(map.total(), 1);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct HealpixMap {
+    order: u8,
+    counts: Vec<u64>,
+}
+
+impl HealpixMap {
+    /// # Panics
+    ///
+    /// Panics if `order` is greater than `healpix::MAX_ORDER`, or the map
+    /// would not fit in memory.
+    pub fn new(order: u8) -> Self {
+        assert!(
+            order <= healpix::MAX_ORDER,
+            "HEALPix order {} is too large",
+            order
+        );
+        HealpixMap {
+            order,
+            counts: vec![0; healpix::n_pixels(order) as usize],
+        }
+    }
+
+    pub fn order(&self) -> u8 {
+        self.order
+    }
+
+    pub fn add(&mut self, position: &SkyPosition) {
+        self.counts[healpix::pixel(self.order, position) as usize] += 1;
+    }
+
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Render the map as an all-sky image in the plate carrée projection,
+    /// `width` pixels wide and half as high. Right ascension increases to the
+    /// left from 0 at the right edge, and north is up.
+    pub fn image(&self, width: usize) -> Image {
+        let height = (width / 2).max(1);
+        let mut image = Image::new(width, height);
+        for y in 0..height {
+            let dec = 90.0 - (y as f64 + 0.5) * 180.0 / height as f64;
+            for x in 0..width {
+                let ra = 360.0 - (x as f64 + 0.5) * 360.0 / width as f64;
+                let pixel = healpix::pixel(self.order, &SkyPosition::new(ra, dec));
+                image.values[y * width + x] = self.counts[pixel as usize] as f64;
+            }
+        }
+        image
+    }
+}
+
+/// Counts of sources in a grid on the tangent plane at a position (the
+/// gnomonic projection).
+///
+/// The grid has square pixels of `scale` degrees, with north up and east to
+/// the left, and is centred on `center`.
+///
+/// ```
+/// # use starquad::sky::density::TangentGrid;
+/// # use starquad::sky::position::SkyPosition;
+/// let mut grid = TangentGrid::new(SkyPosition::new(10.0, 20.0), 0.1, 4, 4);
+/// grid.add(&SkyPosition::new(10.0, 20.05));
+/// grid.add(&SkyPosition::new(190.0, -20.0));
+/// assert_eq!(grid.image().get(2, 1), 1.0);
+/// assert_eq!(grid.total(), 1);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct TangentGrid {
+    center: SkyPosition,
+    scale: f64,
+    image: Image,
+}
+
+impl TangentGrid {
+    pub fn new(center: SkyPosition, scale: f64, width: usize, height: usize) -> Self {
+        TangentGrid {
+            center,
+            scale,
+            image: Image::new(width, height),
+        }
+    }
+
+    pub fn center(&self) -> SkyPosition {
+        self.center
+    }
+
+    /// Size of a pixel, in degrees.
+    pub fn scale(&self) -> f64 {
+        self.scale
+    }
+
+    /// Standard coordinates `(xi, eta)` of a position, in degrees, or `None`
+    /// if it is not in the hemisphere around the center.
+    pub fn project(&self, position: &SkyPosition) -> Option<(f64, f64)> {
+        let (sin_d0, cos_d0) = self.center.dec.to_radians().sin_cos();
+        let (sin_d, cos_d) = position.dec.to_radians().sin_cos();
+        let (sin_dra, cos_dra) = (position.ra - self.center.ra).to_radians().sin_cos();
+        let cos_c = sin_d0 * sin_d + cos_d0 * cos_d * cos_dra;
+        if cos_c <= 0.0 {
+            return None;
+        }
+        let xi = cos_d * sin_dra / cos_c;
+        let eta = (cos_d0 * sin_d - sin_d0 * cos_d * cos_dra) / cos_c;
+        Some((xi.to_degrees(), eta.to_degrees()))
+    }
+
+    /// Count a position, if it lies within the grid.
+    pub fn add(&mut self, position: &SkyPosition) {
+        if let Some((xi, eta)) = self.project(position) {
+            let x = (self.image.width as f64 / 2.0 - xi / self.scale).floor();
+            let y = (self.image.height as f64 / 2.0 - eta / self.scale).floor();
+            if x >= 0.0
+                && y >= 0.0
+                && (x as usize) < self.image.width
+                && (y as usize) < self.image.height
+            {
+                self.image.values[y as usize * self.image.width + x as usize] += 1.0;
+            }
+        }
+    }
+
+    /// Half the diagonal of the grid, in degrees; every position in the grid
+    /// is within this distance of the center.
+    pub fn radius(&self) -> f64 {
+        let half_width = self.image.width as f64 * self.scale / 2.0;
+        let half_height = self.image.height as f64 * self.scale / 2.0;
+        half_width
+            .to_radians()
+            .hypot(half_height.to_radians())
+            .atan()
+            .to_degrees()
+    }
+
+    pub fn total(&self) -> u64 {
+        self.image.values.iter().sum::<f64>() as u64
+    }
+
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+}
+
+/// Counts of sources in the leaves of a quadtree over a rectangle of
+/// `(ra, dec)`: an adaptive map, whose tiles are small where sources are
+/// dense and large over empty regions.
+///
+/// ```
+/// # use starquad::geom::rect::Rect;
+/// # use starquad::sky::density::AdaptiveMap;
+/// # use starquad::sky::position::SkyPosition;
+/// let mut map = AdaptiveMap::new(Rect::new(10.0, 0.0, 2.0, 2.0).unwrap(), 1);
+/// map.add(&SkyPosition::new(10.5, 0.5));
+/// map.add(&SkyPosition::new(11.5, 0.5));
+/// map.add(&SkyPosition::new(50.0, 0.5));
+/// let tiles = map.tiles();
+/// assert_eq!(tiles.len(), 4);
+/// assert_eq!(tiles.iter().map(|t| t.count).sum::<u64>(), 2);
+/// assert_eq!(map.total(), 2);
+/// ```
+pub struct AdaptiveMap {
+    tree: QuadTree<f64, ()>,
+}
+
+/// A tile of an `AdaptiveMap`, in degrees.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Tile {
+    pub ra_min: f64,
+    pub ra_max: f64,
+    pub dec_min: f64,
+    pub dec_max: f64,
+    pub count: u64,
+    /// Sources per square degree.
+    pub density: f64,
+}
+
+impl AdaptiveMap {
+    /// Map of a rectangle, whose tiles are split once they hold more than
+    /// `leaf_capacity` sources.
+    pub fn new(bounds: Rect<f64>, leaf_capacity: usize) -> Self {
+        AdaptiveMap {
+            tree: QuadTree::with_bounds(bounds).with_leaf_capacity(leaf_capacity),
+        }
+    }
+
+    /// Count a position, if it lies within the bounds of the map.
+    pub fn add(&mut self, position: &SkyPosition) {
+        let point = P2::new(position.ra, position.dec);
+        if self.tree.bounds().is_some_and(|b| b.contains(&point)) {
+            self.tree.push((point, ()));
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.tree.len() as u64
+    }
+
+    /// The tiles of the map, ordered by declination and then right
+    /// ascension.
+    pub fn tiles(&self) -> Vec<Tile> {
+        let mut tiles = self
+            .tree
+            .leaves()
+            .into_iter()
+            .map(|(rect, items)| {
+                let count = items.len() as u64;
+                let (ra_min, dec_min) = (*rect.x(), *rect.y());
+                Tile {
+                    ra_min,
+                    ra_max: ra_min + rect.width(),
+                    dec_min,
+                    dec_max: dec_min + rect.height(),
+                    count,
+                    density: count as f64 / Region::Rect(rect).area(),
+                }
+            })
+            .collect::<Vec<_>>();
+        tiles.sort_by(|a, b| {
+            a.dec_min
+                .total_cmp(&b.dec_min)
+                .then(a.ra_min.total_cmp(&b.ra_min))
+        });
+        tiles
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use geom::rect::Rect;
+    use sky::density::{AdaptiveMap, HealpixMap, TangentGrid};
+    use sky::position::SkyPosition;
+
+    #[test]
+    fn healpix_image() {
+        let mut map = HealpixMap::new(1);
+        map.add(&SkyPosition::new(45.0, 89.0));
+        map.add(&SkyPosition::new(46.0, 88.0));
+        let image = map.image(64);
+        assert_eq!((image.width, image.height), (64, 32));
+        assert_eq!(image.range(), Some((0.0, 2.0)));
+        // ra = 45 is three-quarters of the way across, near the top
+        assert_eq!(image.get(56, 0), 2.0);
+        assert_eq!(image.get(8, 0), 0.0);
+    }
+
+    #[test]
+    fn tangent_grid() {
+        let center = SkyPosition::new(0.0, 0.0);
+        let mut grid = TangentGrid::new(center, 1.0, 10, 6);
+        // east of the center is to the left
+        grid.add(&SkyPosition::new(2.5, 0.5));
+        grid.add(&SkyPosition::new(357.5, -0.5));
+        grid.add(&SkyPosition::new(0.0, 10.0));
+        assert_eq!(grid.image().get(2, 2), 1.0);
+        assert_eq!(grid.image().get(7, 3), 1.0);
+        assert_eq!(grid.total(), 2);
+        assert!((grid.radius() - 5.0f64.hypot(3.0)).abs() < 0.2);
+    }
+
+    #[test]
+    fn adaptive_tiles() {
+        // a cluster in one corner of an otherwise sparse square
+        let mut map = AdaptiveMap::new(Rect::new(0.0, -4.0, 8.0, 8.0).unwrap(), 4);
+        for i in 0..64 {
+            map.add(&SkyPosition::new(
+                0.01 * (i % 8) as f64,
+                -3.99 + 0.01 * (i / 8) as f64,
+            ));
+        }
+        map.add(&SkyPosition::new(6.0, 3.0));
+        let tiles = map.tiles();
+        assert_eq!(tiles.iter().map(|t| t.count).sum::<u64>(), 65);
+        // tiles cover the square without overlapping
+        let area = tiles
+            .iter()
+            .map(|t| (t.ra_max - t.ra_min) * (t.dec_max - t.dec_min))
+            .sum::<f64>();
+        assert!((area - 64.0).abs() < 1e-9);
+        let smallest = tiles
+            .iter()
+            .min_by(|a, b| (a.ra_max - a.ra_min).total_cmp(&(b.ra_max - b.ra_min)))
+            .unwrap();
+        assert!(smallest.ra_max < 0.1 && smallest.dec_min < -3.9);
+        let sparse = tiles
+            .iter()
+            .find(|t| t.ra_min <= 6.0 && t.ra_max > 6.0 && t.dec_max > 3.0);
+        assert_eq!(sparse.unwrap().count, 1);
+        assert_eq!(sparse.unwrap().ra_max - sparse.unwrap().ra_min, 4.0);
+        assert!(smallest.density > 1000.0 * sparse.unwrap().density);
+    }
+}
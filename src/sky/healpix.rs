@@ -0,0 +1,194 @@
+use sky::position::{normalize_ra, SkyPosition};
+
+/// Maximum supported HEALPix order (depth); pixel numbers then fit in a
+/// `u64`.
+pub const MAX_ORDER: u8 = 29;
+
+/// HEALPix order whose pixel number is encoded in Gaia `source_id`s.
+pub const GAIA_SOURCE_ID_ORDER: u8 = 12;
+
+/// Number of pixels at an order.
+pub fn n_pixels(order: u8) -> u64 {
+    12 << (2 * u64::from(order))
+}
+
+/// Interleave the bits of `x` (in even positions) and `y` (in odd
+/// positions).
+pub(crate) fn interleave(x: u64, y: u64) -> u64 {
+    fn spread(mut v: u64) -> u64 {
+        v &= 0xffff_ffff;
+        v = (v | (v << 16)) & 0x0000_ffff_0000_ffff;
+        v = (v | (v << 8)) & 0x00ff_00ff_00ff_00ff;
+        v = (v | (v << 4)) & 0x0f0f_0f0f_0f0f_0f0f;
+        v = (v | (v << 2)) & 0x3333_3333_3333_3333;
+        (v | (v << 1)) & 0x5555_5555_5555_5555
+    }
+    spread(x) | (spread(y) << 1)
+}
+
+/// Pixel containing a position, in the NESTED numbering scheme.
+///
+/// ```
+/// # use starquad::sky::healpix::pixel;
+/// # use starquad::sky::position::SkyPosition;
+/// assert_eq!(pixel(0, &SkyPosition::new(0.0, 0.0)), 4);
+/// assert_eq!(pixel(0, &SkyPosition::new(0.0, -90.0)), 8);
+/// ```
+///
+/// # Panics
+///
+/// Panics if `order` is greater than `MAX_ORDER`.
+pub fn pixel(order: u8, position: &SkyPosition) -> u64 {
+    assert!(order <= MAX_ORDER, "HEALPix order {} is too large", order);
+    let nside = 1i64 << order;
+    let z = position.dec.to_radians().sin();
+    let za = z.abs();
+    let tt = (position.ra / 90.0).rem_euclid(4.0);
+
+    let (face, ix, iy) = if za <= 2.0 / 3.0 {
+        // equatorial region
+        let temp1 = nside as f64 * (0.5 + tt);
+        let temp2 = nside as f64 * (z * 0.75);
+        let jp = (temp1 - temp2) as i64;
+        let jm = (temp1 + temp2) as i64;
+        let ifp = jp >> order;
+        let ifm = jm >> order;
+        let face = if ifp == ifm {
+            ifp | 4
+        } else if ifp < ifm {
+            ifp
+        } else {
+            ifm + 8
+        };
+        (face, jm & (nside - 1), nside - (jp & (nside - 1)) - 1)
+    } else {
+        // polar caps
+        let ntt = (tt as i64).min(3);
+        let tp = tt - ntt as f64;
+        let tmp = nside as f64 * (3.0 * (1.0 - za)).sqrt();
+        let jp = ((tp * tmp) as i64).min(nside - 1);
+        let jm = (((1.0 - tp) * tmp) as i64).min(nside - 1);
+        if z >= 0.0 {
+            (ntt, nside - jm - 1, nside - jp - 1)
+        } else {
+            (ntt + 8, jp, jm)
+        }
+    };
+    ((face as u64) << (2 * u64::from(order))) + interleave(ix as u64, iy as u64)
+}
+
+/// Inverse of `interleave`.
+fn deinterleave(v: u64) -> (u64, u64) {
+    fn compact(mut v: u64) -> u64 {
+        v &= 0x5555_5555_5555_5555;
+        v = (v | (v >> 1)) & 0x3333_3333_3333_3333;
+        v = (v | (v >> 2)) & 0x0f0f_0f0f_0f0f_0f0f;
+        v = (v | (v >> 4)) & 0x00ff_00ff_00ff_00ff;
+        v = (v | (v >> 8)) & 0x0000_ffff_0000_ffff;
+        (v | (v >> 16)) & 0xffff_ffff
+    }
+    (compact(v), compact(v >> 1))
+}
+
+/// Position within a pixel, in the NESTED numbering scheme.
+///
+/// `dx` and `dy` (each in `[0, 1]`) are the fractional position across the
+/// pixel, so `(0.5, 0.5)` is its centre. The projection is equal-area, so
+/// uniformly distributed offsets give positions uniformly distributed over
+/// the pixel.
+///
+/// ```
+/// # use starquad::sky::healpix::{pixel, position_in};
+/// let centre = position_in(0, 4, 0.5, 0.5);
+/// assert!(centre.ra.abs() < 1e-12 && centre.dec.abs() < 1e-12);
+/// assert_eq!(pixel(8, &position_in(8, 12345, 0.5, 0.5)), 12345);
+/// ```
+///
+/// # Panics
+///
+/// Panics if `order` is greater than `MAX_ORDER`.
+pub fn position_in(order: u8, pixel: u64, dx: f64, dy: f64) -> SkyPosition {
+    assert!(order <= MAX_ORDER, "HEALPix order {} is too large", order);
+    const JRLL: [i64; 12] = [2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4];
+    const JPLL: [i64; 12] = [1, 3, 5, 7, 0, 2, 4, 6, 1, 3, 5, 7];
+    let face = (pixel >> (2 * u64::from(order))) as usize;
+    let (ix, iy) = deinterleave(pixel & ((1 << (2 * u64::from(order))) - 1));
+    let nside = (1u64 << order) as f64;
+    let x = (ix as f64 + dx) / nside;
+    let y = (iy as f64 + dy) / nside;
+    let jr = JRLL[face] as f64 - x - y;
+    let (z, nr) = if jr < 1.0 {
+        (1.0 - jr * jr / 3.0, jr)
+    } else if jr > 3.0 {
+        let nr = 4.0 - jr;
+        (nr * nr / 3.0 - 1.0, nr)
+    } else {
+        ((2.0 - jr) * 2.0 / 3.0, 1.0)
+    };
+    let tmp = (JPLL[face] as f64 * nr + x - y).rem_euclid(8.0);
+    let phi = if nr < 1e-15 { 0.0 } else { 45.0 * tmp / nr };
+    SkyPosition::new(normalize_ra(phi), z.clamp(-1.0, 1.0).asin().to_degrees())
+}
+
+/// Pixel at a lower order containing a pixel.
+pub fn parent(pixel: u64, order: u8, parent_order: u8) -> u64 {
+    debug_assert!(parent_order <= order);
+    pixel >> (2 * u64::from(order - parent_order))
+}
+
+/// Pixel (at an order of at most 12) containing a Gaia source, decoded from
+/// its `source_id`.
+pub fn source_id_pixel(source_id: u64, order: u8) -> u64 {
+    parent(source_id >> 35, GAIA_SOURCE_ID_ORDER, order)
+}
+
+#[cfg(test)]
+mod test {
+    use quickcheck_macros::quickcheck;
+    use sky::healpix::{n_pixels, parent, pixel, position_in, source_id_pixel};
+    use sky::position::SkyPosition;
+
+    #[test]
+    fn base_pixels() {
+        assert_eq!(pixel(0, &SkyPosition::new(0.0, 90.0)), 0);
+        assert_eq!(pixel(0, &SkyPosition::new(45.0, 60.0)), 0);
+        assert_eq!(pixel(0, &SkyPosition::new(135.0, 60.0)), 1);
+        assert_eq!(pixel(0, &SkyPosition::new(90.0, 0.0)), 5);
+        assert_eq!(pixel(0, &SkyPosition::new(315.0, -60.0)), 11);
+        assert_eq!(pixel(1, &SkyPosition::new(0.0, 20.0)), 19);
+        assert_eq!(pixel(1, &SkyPosition::new(0.0, -20.0)), 16);
+    }
+
+    #[test]
+    fn gaia_source_id() {
+        let pixel = 12345u64;
+        let source_id = (pixel << 35) | 987;
+        assert_eq!(source_id_pixel(source_id, 12), pixel);
+        assert_eq!(source_id_pixel(source_id, 10), pixel >> 4);
+    }
+
+    /// Property test: pixels are nested, so the pixel at one order contains
+    /// the pixel at the next order.
+    #[quickcheck]
+    fn nested(ra: u32, dec: i32, order: u8) {
+        let order = order % 20 + 1;
+        let position = SkyPosition::new(
+            f64::from(ra % 3_600_000) / 1e4,
+            f64::from(dec % 900_000) / 1e4,
+        );
+        let child = pixel(order, &position);
+        assert!(child < n_pixels(order));
+        assert_eq!(parent(child, order, order - 1), pixel(order - 1, &position));
+    }
+
+    /// Property test: positions within a pixel are in the pixel.
+    #[quickcheck]
+    fn position_in_pixel(any_pixel: u64, order: u8, dx: u16, dy: u16) {
+        let order = order % 30;
+        let p = any_pixel % n_pixels(order);
+        // stay clear of the edges, where rounding may cross into a neighbour
+        let offset = |d: u16| 0.01 + 0.98 * f64::from(d) / f64::from(u16::MAX);
+        let position = position_in(order, p, offset(dx), offset(dy));
+        assert_eq!(pixel(order, &position), p);
+    }
+}
@@ -0,0 +1,137 @@
+use accel2d::quadtree::{InlineQuadTree, QuadTree};
+use accel2d::Accel2D;
+use geom::interval::Interval;
+use geom::p2::P2;
+use geom::rect::Rect;
+use sky::position::{SkyPosition, SkySource};
+use sky::region::Region;
+
+/// In-memory index of sources by `(ra, dec)`, in degrees.
+pub type SkyIndex<T> = QuadTree<f64, T>;
+
+/// Region of `(ra, dec)` covered by sky indexes.
+///
+/// This includes both `ra = 360` and `dec = 90`, so that no valid position
+/// falls outside of it.
+pub fn sky_bounds() -> Rect<f64> {
+    // The diameters are the smallest for which `start + diameter` exceeds
+    // the maximum, as `f64::enclosing_interval` would find.
+    let ra = Interval::new_unchecked(0.0, 360.0_f64.next_up());
+    let dec = Interval::new_unchecked(-90.0, 180.0_f64.next_up());
+    Rect::new_from_intervals(ra, dec)
+}
+
+/// Index a collection of sources by their positions.
+pub fn index_sources<I>(sources: I) -> SkyIndex<I::Item>
+where
+    I: IntoIterator,
+    I::Item: SkySource + Clone,
+{
+    index_sources_inline(sources)
+}
+
+/// Index a collection of sources by their positions, keeping up to `INLINE`
+/// of them inline in each leaf.
+pub fn index_sources_inline<I, const INLINE: usize>(
+    sources: I,
+) -> InlineQuadTree<f64, I::Item, INLINE>
+where
+    I: IntoIterator,
+    I::Item: SkySource + Clone,
+{
+    let mut index = InlineQuadTree::with_bounds(sky_bounds());
+    index.insert(
+        sources
+            .into_iter()
+            .map(|source| {
+                let position = source.position();
+                (P2::new(position.ra, position.dec), source)
+            })
+            .collect(),
+    );
+    index
+}
+
+/// Items of an index whose positions lie in a region.
+pub fn query_region<'a, T: Clone, const INLINE: usize>(
+    index: &'a InlineQuadTree<f64, T, INLINE>,
+    region: &Region,
+) -> Vec<&'a (P2<f64>, T)> {
+    region
+        .bounding_rects()
+        .iter()
+        .flat_map(|rect| index.query_rect(rect))
+        .filter(|(p, _)| region.contains(&SkyPosition::new(p.x, p.y)))
+        .collect()
+}
+
+/// The `k` items of an index nearest to a position, nearest first, with
+/// their separations from it in degrees.
+///
+/// This searches cones of growing radius until one holds `k` items, so it is
+/// fastest when the items are spread evenly.
+pub fn nearest<'a, T: Clone, const INLINE: usize>(
+    index: &'a InlineQuadTree<f64, T, INLINE>,
+    position: &SkyPosition,
+    k: usize,
+) -> Vec<(f64, &'a T)> {
+    let mut radius = 1.0 / 60.0;
+    loop {
+        let found = query_region(index, &Region::cone(*position, radius));
+        // every item outside the cone is further away than those inside it
+        if found.len() >= k || radius >= 180.0 {
+            let mut found = found
+                .into_iter()
+                .map(|(p, item)| (position.separation(&SkyPosition::new(p.x, p.y)), item))
+                .collect::<Vec<_>>();
+            found.sort_by(|a, b| a.0.total_cmp(&b.0));
+            found.truncate(k);
+            return found;
+        }
+        radius *= 4.0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use geom::p2::P2;
+    use geom::polygon::Polygon;
+    use sky::index::{index_sources, nearest, query_region};
+    use sky::position::SkyPosition;
+    use sky::region::Region;
+
+    #[test]
+    fn region_and_nearest() {
+        let positions = (0..36)
+            .map(|i| SkyPosition::new(i as f64 * 10.0, 0.0))
+            .collect::<Vec<_>>();
+        let index = index_sources(positions);
+        let cone = Region::cone(SkyPosition::new(0.0, 0.0), 15.0);
+        let mut found = query_region(&index, &cone)
+            .iter()
+            .map(|(_, p)| p.ra)
+            .collect::<Vec<_>>();
+        found.sort_by(f64::total_cmp);
+        assert_eq!(found, vec![0.0, 10.0, 350.0]);
+
+        let wedge = Region::Polygon(Polygon::new(vec![
+            P2::new(5.0, -1.0),
+            P2::new(45.0, -1.0),
+            P2::new(5.0, 1.0),
+        ]));
+        let mut found = query_region(&index, &wedge)
+            .iter()
+            .map(|(_, p)| p.ra)
+            .collect::<Vec<_>>();
+        found.sort_by(f64::total_cmp);
+        // the wedge crosses the equator from ra 5 to ra 25
+        assert_eq!(found, vec![10.0, 20.0]);
+
+        let near = nearest(&index, &SkyPosition::new(101.0, 1.0), 3);
+        let ras = near.iter().map(|(_, p)| p.ra).collect::<Vec<_>>();
+        assert_eq!(ras, vec![100.0, 110.0, 90.0]);
+        assert!(near.windows(2).all(|w| w[0].0 <= w[1].0));
+        assert_eq!(nearest(&index, &SkyPosition::new(0.0, 90.0), 100).len(), 36);
+        assert!(nearest(&index, &SkyPosition::new(0.0, 0.0), 0).is_empty());
+    }
+}
@@ -0,0 +1,305 @@
+use sky::healpix::{n_pixels, pixel, MAX_ORDER};
+use sky::position::SkyPosition;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+/// Errors from building or reading a MOC.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The text of a MOC is malformed.
+    #[error("invalid MOC: {0}")]
+    Syntax(String),
+    /// A cell's order is too large, or its pixel is not in its order.
+    #[error("no HEALPix cell {pixel} at order {order}")]
+    InvalidCell { order: u8, pixel: u64 },
+    /// A MOC covers nothing, where it must cover something.
+    #[error("empty MOC")]
+    Empty,
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+/// How much of a HEALPix cell a MOC covers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Coverage {
+    Full,
+    Partial,
+    Empty,
+}
+
+/// Multi-Order Coverage map: a region of the sky made of HEALPix cells of
+/// any order (in the NESTED scheme), such as the footprint of a survey.
+///
+/// Cells are kept as sorted, disjoint ranges of pixels at `MAX_ORDER`, so
+/// the same region always has the same `Moc` however its cells were given.
+/// MOCs are read from the ASCII and JSON serializations of the IVOA MOC
+/// standard, and written in the ASCII one.
+///
+/// ```
+/// # use starquad::sky::moc::Moc;
+/// # use starquad::sky::position::SkyPosition;
+/// // the northern base pixels, with one of them split into its quarters
+/// let moc: Moc = "0/0-2 1/12-15".parse().unwrap();
+/// assert_eq!(moc.to_string(), "0/0-3");
+/// assert_eq!(moc.sky_fraction(), 1.0 / 3.0);
+/// assert!(moc.contains(&SkyPosition::new(10.0, 60.0)));
+/// assert!(!moc.contains(&SkyPosition::new(10.0, -60.0)));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Moc {
+    ranges: Vec<Range<u64>>,
+}
+
+/// Pixels at `MAX_ORDER` in a cell.
+fn cell_range(order: u8, pixel: u64) -> Range<u64> {
+    let shift = 2 * u32::from(MAX_ORDER - order);
+    (pixel << shift)..((pixel + 1) << shift)
+}
+
+impl Moc {
+    /// MOC of some cells, given as `(order, pixel)`.
+    pub fn from_cells<I>(cells: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = (u8, u64)>,
+    {
+        let mut ranges = Vec::new();
+        for (order, pixel) in cells {
+            if order > MAX_ORDER || pixel >= n_pixels(order) {
+                return Err(Error::InvalidCell { order, pixel });
+            }
+            ranges.push(cell_range(order, pixel));
+        }
+        Ok(Moc::from_ranges(ranges))
+    }
+
+    /// MOC of ranges of pixels at `MAX_ORDER`, which are sorted and merged.
+    fn from_ranges(mut ranges: Vec<Range<u64>>) -> Self {
+        ranges.sort_unstable_by_key(|range| range.start);
+        let mut merged: Vec<Range<u64>> = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+        Moc { ranges: merged }
+    }
+
+    /// Read a MOC in the JSON serialization, which maps each order (as a
+    /// string) to a list of pixels, such as `{"1": [12, 13], "2": [56]}`.
+    pub fn from_json(text: &str) -> Result<Self, Error> {
+        let orders: BTreeMap<String, Vec<u64>> = serde_json::from_str(text)?;
+        let mut cells = Vec::new();
+        for (order, pixels) in orders {
+            let order = order
+                .parse::<u8>()
+                .map_err(|_| Error::Syntax(format!("invalid order: {:?}", order)))?;
+            cells.extend(pixels.into_iter().map(|pixel| (order, pixel)));
+        }
+        Moc::from_cells(cells)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Sorted, disjoint and non-adjacent ranges of the pixels at `MAX_ORDER`
+    /// which the MOC covers.
+    pub fn ranges(&self) -> &[Range<u64>] {
+        &self.ranges
+    }
+
+    /// Fraction of the sky which the MOC covers.
+    pub fn sky_fraction(&self) -> f64 {
+        let covered: u64 = self
+            .ranges
+            .iter()
+            .map(|range| range.end - range.start)
+            .sum();
+        covered as f64 / n_pixels(MAX_ORDER) as f64
+    }
+
+    pub fn contains(&self, position: &SkyPosition) -> bool {
+        let pixel = pixel(MAX_ORDER, position);
+        let i = self.ranges.partition_point(|range| range.end <= pixel);
+        self.ranges.get(i).is_some_and(|range| range.start <= pixel)
+    }
+
+    /// How much of a cell the MOC covers.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `order` is greater than `MAX_ORDER`.
+    pub fn coverage(&self, order: u8, pixel: u64) -> Coverage {
+        assert!(order <= MAX_ORDER, "HEALPix order {} is too large", order);
+        let cell = cell_range(order, pixel);
+        let i = self.ranges.partition_point(|range| range.end <= cell.start);
+        match self.ranges.get(i) {
+            Some(range) if range.start <= cell.start && range.end >= cell.end => Coverage::Full,
+            Some(range) if range.start < cell.end => Coverage::Partial,
+            _ => Coverage::Empty,
+        }
+    }
+
+    /// Fewest cells which make up the MOC, as `(order, pixel)`, by order and
+    /// then pixel.
+    pub fn cells(&self) -> Vec<(u8, u64)> {
+        let mut cells = Vec::new();
+        for range in self.ranges.iter() {
+            let mut start = range.start;
+            while start < range.end {
+                // the largest cell which starts at `start` and fits
+                let mut order = start.trailing_zeros().min(2 * u32::from(MAX_ORDER)) / 2;
+                while start + (1 << (2 * order)) > range.end {
+                    order -= 1;
+                }
+                cells.push((MAX_ORDER - order as u8, start >> (2 * order)));
+                start += 1 << (2 * order);
+            }
+        }
+        cells.sort_unstable();
+        cells
+    }
+}
+
+/// The ASCII serialization: each order followed by `/` and its pixels, with
+/// runs of pixels written as ranges, such as `1/12-13 2/56`.
+impl fmt::Display for Moc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let cells = self.cells();
+        let mut previous_order = None;
+        let mut i = 0;
+        while i < cells.len() {
+            let (order, first) = cells[i];
+            let mut last = first;
+            while cells.get(i + 1) == Some(&(order, last + 1)) {
+                last += 1;
+                i += 1;
+            }
+            i += 1;
+            if previous_order.is_some() {
+                write!(f, " ")?;
+            }
+            if previous_order != Some(order) {
+                write!(f, "{}/", order)?;
+                previous_order = Some(order);
+            }
+            if last == first {
+                write!(f, "{}", first)?;
+            } else {
+                write!(f, "{}-{}", first, last)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Read the ASCII serialization, in which pixels and ranges of pixels may
+/// be separated by spaces or commas, and an order may have no pixels.
+impl FromStr for Moc {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self, Error> {
+        let invalid = |token: &str| Error::Syntax(format!("unexpected {:?}", token));
+        let mut order = None;
+        let mut cells = Vec::new();
+        for token in text.split(|c: char| c.is_whitespace() || c == ',') {
+            let pixels = match token.split_once('/') {
+                Some((o, pixels)) => {
+                    order = Some(o.parse::<u8>().map_err(|_| invalid(token))?);
+                    pixels
+                }
+                None => token,
+            };
+            if pixels.is_empty() {
+                continue;
+            }
+            let order = order.ok_or_else(|| invalid(token))?;
+            let (first, last) = pixels.split_once('-').unwrap_or((pixels, pixels));
+            let first = first.parse::<u64>().map_err(|_| invalid(token))?;
+            let last = last.parse::<u64>().map_err(|_| invalid(token))?;
+            if last < first {
+                return Err(invalid(token));
+            }
+            for pixel in [first, last].iter() {
+                if order > MAX_ORDER || *pixel >= n_pixels(order) {
+                    return Err(Error::InvalidCell {
+                        order,
+                        pixel: *pixel,
+                    });
+                }
+            }
+            cells.push(cell_range(order, first).start..cell_range(order, last).end);
+        }
+        Ok(Moc::from_ranges(cells))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use quickcheck_macros::quickcheck;
+    use sky::healpix::{parent, pixel};
+    use sky::moc::{Coverage, Error, Moc};
+    use sky::position::SkyPosition;
+
+    #[test]
+    fn ascii() {
+        let moc: Moc = "1/1 2 4 2/12-14, 21 23 25 8/".parse().unwrap();
+        assert_eq!(moc.to_string(), "1/1-2 4 2/12-14 21 23 25");
+        assert_eq!(moc.to_string().parse::<Moc>().unwrap(), moc);
+        assert_eq!("".parse::<Moc>().unwrap(), Moc::default());
+        assert!(matches!("3".parse::<Moc>(), Err(Error::Syntax(_))));
+        assert!(matches!("0/5-3".parse::<Moc>(), Err(Error::Syntax(_))));
+        assert!(matches!(
+            "0/12".parse::<Moc>(),
+            Err(Error::InvalidCell {
+                order: 0,
+                pixel: 12
+            })
+        ));
+    }
+
+    #[test]
+    fn json() {
+        let moc = Moc::from_json(r#"{"1": [4, 5, 6, 7], "2": [32]}"#).unwrap();
+        assert_eq!(moc.cells(), vec![(0, 1), (2, 32)]);
+        assert!(matches!(Moc::from_json("[1]"), Err(Error::Json(_))));
+    }
+
+    #[test]
+    fn coverage() {
+        let moc: Moc = "1/5 3/0".parse().unwrap();
+        assert_eq!(moc.coverage(1, 5), Coverage::Full);
+        assert_eq!(moc.coverage(4, 5 * 64 + 7), Coverage::Full);
+        assert_eq!(moc.coverage(0, 1), Coverage::Partial);
+        assert_eq!(moc.coverage(1, 0), Coverage::Partial);
+        assert_eq!(moc.coverage(3, 1), Coverage::Empty);
+        assert_eq!(moc.coverage(0, 11), Coverage::Empty);
+    }
+
+    /// Property test: a MOC contains a position exactly when one of its
+    /// cells contains the position's pixel.
+    #[quickcheck]
+    fn contains_cells(cells: Vec<(u8, u32)>, ra: u32, dec: i32) {
+        let cells = cells
+            .into_iter()
+            .map(|(order, pixel)| {
+                let order = order % 8;
+                (order, u64::from(pixel) % (12 << (2 * order)))
+            })
+            .collect::<Vec<_>>();
+        let moc = Moc::from_cells(cells.iter().copied()).unwrap();
+        assert_eq!(Moc::from_cells(moc.cells()).unwrap(), moc);
+        let position = SkyPosition::new(
+            f64::from(ra % 3_600_000) / 1e4,
+            f64::from(dec % 900_000) / 1e4,
+        );
+        let deepest = pixel(7, &position);
+        let expected = cells
+            .iter()
+            .any(|(order, pixel)| parent(deepest, 7, *order) == *pixel);
+        assert_eq!(moc.contains(&position), expected);
+    }
+}
@@ -0,0 +1,10 @@
+pub mod density;
+pub mod healpix;
+pub mod index;
+pub mod moc;
+pub mod neighbours;
+pub mod position;
+pub mod quantity;
+pub mod quantized;
+pub mod random;
+pub mod region;
@@ -0,0 +1,216 @@
+//! Distribution of the separations of sources from their nearest
+//! neighbours, as a clustering diagnostic.
+//!
+//! For sources scattered at random (a Poisson field) with a density of `n`
+//! per square arcsecond, the probability that the nearest neighbour of a
+//! source is further than `r` arcseconds is `exp(-pi n r^2)`, and the mean
+//! separation is `1 / (2 sqrt(n))`. Clustered sources have more close
+//! neighbours than this, and a Clark-Evans ratio (the observed mean over
+//! the expected) below one; evenly spaced sources have a ratio above one.
+//!
+//! Only the given sources are searched, so sources near the edge of the
+//! region may have their true nearest neighbour outside it, which biases
+//! the separations slightly upward for small regions.
+
+use serde::Serialize;
+use sky::index::{index_sources, nearest};
+use sky::position::{SkyPosition, SkySource, ARCSEC_PER_DEG};
+use sky::region::Region;
+use std::f64::consts::PI;
+
+/// Most bins in a histogram. Separations beyond the last bin are counted
+/// in it.
+pub const MAX_BINS: usize = 1000;
+
+/// A bin of the histogram of nearest-neighbour separations.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Bin {
+    /// Smallest separation of the bin, in arcseconds.
+    pub start: f64,
+    /// Separation (in arcseconds) at which the next bin starts, or infinity
+    /// for a last bin which holds all larger separations.
+    pub end: f64,
+    pub count: u64,
+    /// Count expected of a Poisson field of the same density.
+    pub expected: f64,
+}
+
+/// Statistics of the nearest-neighbour separations of sources in a region.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct NeighbourStats {
+    pub sources: usize,
+    /// Sources per square arcsecond.
+    pub density: f64,
+    /// Smallest separation, in arcseconds.
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub median: f64,
+    /// Mean separation of a Poisson field of the same density.
+    pub expected_mean: f64,
+    /// Median separation of a Poisson field of the same density.
+    pub expected_median: f64,
+    pub histogram: Vec<Bin>,
+}
+
+impl NeighbourStats {
+    /// Statistics of sources in a region, with a histogram of bins
+    /// `bin_width` arcseconds wide (default: a quarter of the expected mean
+    /// separation). `None` if there are fewer than two sources.
+    ///
+    /// ```
+    /// # use starquad::sky::neighbours::NeighbourStats;
+    /// # use starquad::sky::position::SkyPosition;
+    /// # use starquad::sky::region::Region;
+    /// // a grid of sources a tenth of a degree apart
+    /// let positions = (0..100)
+    ///     .map(|i| {
+    ///         let (ra, dec) = ((i % 10) as f64 / 10.0, (i / 10) as f64 / 10.0);
+    ///         SkyPosition::new(0.05 + ra, -0.5 + dec)
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// let region = Region::sky_box(0.0, 1.0, -0.55, 0.45).unwrap();
+    /// let stats = NeighbourStats::new(&positions, &region, None).unwrap();
+    /// assert!((stats.median - 360.0).abs() < 0.1);
+    /// // evenly spaced sources are further apart than random ones
+    /// assert!(stats.clark_evans() > 1.9);
+    /// ```
+    pub fn new(positions: &[SkyPosition], region: &Region, bin_width: Option<f64>) -> Option<Self> {
+        if positions.len() < 2 {
+            return None;
+        }
+        let mut separations = nearest_separations(positions);
+        separations.sort_by(f64::total_cmp);
+        let n = separations.len();
+        let area = region.area() * ARCSEC_PER_DEG * ARCSEC_PER_DEG;
+        let density = n as f64 / area;
+        let expected_mean = 0.5 / density.sqrt();
+        let bin_width = bin_width.unwrap_or(expected_mean / 4.0);
+        let max = separations[n - 1];
+        let bins = ((max / bin_width).floor() as usize + 1).min(MAX_BINS);
+        // fraction of a Poisson field whose nearest neighbour is further
+        // than a separation
+        let beyond = |r: f64| (-PI * density * r * r).exp();
+        let histogram = (0..bins)
+            .map(|i| {
+                let start = i as f64 * bin_width;
+                let end = if i + 1 == bins {
+                    f64::INFINITY
+                } else {
+                    start + bin_width
+                };
+                let first = separations.partition_point(|&s| s < start);
+                let last = separations.partition_point(|&s| s < end);
+                Bin {
+                    start,
+                    end,
+                    count: (last - first) as u64,
+                    expected: n as f64 * (beyond(start) - beyond(end)),
+                }
+            })
+            .collect();
+        Some(NeighbourStats {
+            sources: n,
+            density,
+            min: separations[0],
+            max,
+            mean: separations.iter().sum::<f64>() / n as f64,
+            median: if n.is_multiple_of(2) {
+                (separations[n / 2 - 1] + separations[n / 2]) / 2.0
+            } else {
+                separations[n / 2]
+            },
+            expected_mean,
+            expected_median: (2f64.ln() / (PI * density)).sqrt(),
+            histogram,
+        })
+    }
+
+    /// Clark-Evans ratio of the observed to the expected mean separation.
+    pub fn clark_evans(&self) -> f64 {
+        self.mean / self.expected_mean
+    }
+}
+
+/// Separation of each source from its nearest neighbour among the others,
+/// in arcseconds, in the order of the sources. A source with no neighbour
+/// has an infinite separation.
+pub fn nearest_separations(positions: &[SkyPosition]) -> Vec<f64> {
+    let index = index_sources(positions.iter().enumerate().map(|(i, p)| Indexed(i, *p)));
+    positions
+        .iter()
+        .enumerate()
+        .map(|(i, position)| {
+            nearest(&index, position, 2)
+                .into_iter()
+                .find(|(_, source)| source.0 != i)
+                .map_or(f64::INFINITY, |(separation, _)| separation * ARCSEC_PER_DEG)
+        })
+        .collect()
+}
+
+/// A position, with its place in a slice.
+#[derive(Clone)]
+struct Indexed(usize, SkyPosition);
+
+impl SkySource for Indexed {
+    fn position(&self) -> SkyPosition {
+        self.1
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+    use sky::neighbours::{nearest_separations, NeighbourStats};
+    use sky::position::SkyPosition;
+    use sky::region::Region;
+
+    #[test]
+    fn separations() {
+        let positions = vec![
+            SkyPosition::new(10.0, 0.0),
+            SkyPosition::new(10.0, 0.001),
+            SkyPosition::new(10.0, 0.0),
+            SkyPosition::new(20.0, 0.0),
+        ];
+        let separations = nearest_separations(&positions);
+        assert_eq!(separations[0], 0.0);
+        assert!((separations[1] - 3.6).abs() < 1e-9);
+        assert_eq!(separations[2], 0.0);
+        assert!((separations[3] - 36000.0).abs() < 1e-6);
+        assert_eq!(nearest_separations(&positions[..1]), vec![f64::INFINITY]);
+    }
+
+    #[test]
+    fn poisson_field() {
+        let region = Region::sky_box(10.0, 12.0, -1.0, 1.0).unwrap();
+        // uniform on the sphere, so sin(dec) is uniform
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let sin_dec = 1f64.to_radians().sin();
+        let positions = (0..20000)
+            .map(|_| {
+                let dec = rng.gen_range(-sin_dec..sin_dec).asin().to_degrees();
+                SkyPosition::new(rng.gen_range(10.0..12.0), dec)
+            })
+            .collect::<Vec<_>>();
+        let stats = NeighbourStats::new(&positions, &region, None).unwrap();
+        assert_eq!(stats.sources, 20000);
+        assert!((stats.clark_evans() - 1.0).abs() < 0.05);
+        assert!((stats.median / stats.expected_median - 1.0).abs() < 0.05);
+        let counted = stats.histogram.iter().map(|b| b.count).sum::<u64>();
+        assert_eq!(counted, 20000);
+        let expected = stats.histogram.iter().map(|b| b.expected).sum::<f64>();
+        assert!((expected - 20000.0).abs() < 1e-6);
+        for bin in &stats.histogram[..8] {
+            assert!((bin.count as f64 - bin.expected).abs() < 5.0 * bin.expected.sqrt() + 5.0);
+        }
+    }
+
+    #[test]
+    fn too_few() {
+        let region = Region::cone(SkyPosition::new(0.0, 0.0), 1.0);
+        assert!(NeighbourStats::new(&[SkyPosition::new(0.0, 0.0)], &region, None).is_none());
+    }
+}
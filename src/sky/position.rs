@@ -0,0 +1,333 @@
+use geom::interval::IntervalDomain;
+use geom::p3::P3;
+use geom::rect::Rect;
+
+/// Number of milliarcseconds in a degree.
+pub const MAS_PER_DEG: f64 = 3_600_000.0;
+
+/// Number of arcseconds in a degree.
+pub const ARCSEC_PER_DEG: f64 = 3_600.0;
+
+/// Position on the celestial sphere.
+///
+/// Right ascension (`ra`) and declination (`dec`) are both in degrees.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SkyPosition {
+    pub ra: f64,
+    pub dec: f64,
+}
+
+impl SkyPosition {
+    pub fn new(ra: f64, dec: f64) -> Self {
+        SkyPosition { ra, dec }
+    }
+
+    /// Angular separation between two positions, in degrees.
+    ///
+    /// This uses the Vincenty formula, which is well-conditioned for both
+    /// very small and very large separations.
+    ///
+    /// ```
+    /// # use starquad::sky::position::SkyPosition;
+    /// let a = SkyPosition::new(10.0, 89.0);
+    /// let b = SkyPosition::new(190.0, 89.0);
+    /// assert!((a.separation(&b) - 2.0).abs() < 1e-12);
+    /// ```
+    pub fn separation(&self, other: &SkyPosition) -> f64 {
+        let (sin_d1, cos_d1) = self.dec.to_radians().sin_cos();
+        let (sin_d2, cos_d2) = other.dec.to_radians().sin_cos();
+        let (sin_dra, cos_dra) = (other.ra - self.ra).to_radians().sin_cos();
+        let num1 = cos_d2 * sin_dra;
+        let num2 = cos_d1 * sin_d2 - sin_d1 * cos_d2 * cos_dra;
+        let denom = sin_d1 * sin_d2 + cos_d1 * cos_d2 * cos_dra;
+        num1.hypot(num2).atan2(denom).to_degrees()
+    }
+
+    /// Position as a point on the unit sphere, with `x` towards `(0, 0)`
+    /// and `z` towards the north pole, for indexing in a
+    /// `accel3d::kdtree::KdTree` with the `GreatCircle` metric.
+    pub fn unit_vector(&self) -> P3<f64> {
+        let (sin_ra, cos_ra) = self.ra.to_radians().sin_cos();
+        let (sin_dec, cos_dec) = self.dec.to_radians().sin_cos();
+        P3::new(cos_dec * cos_ra, cos_dec * sin_ra, sin_dec)
+    }
+
+    /// Propagate a position along its proper motion.
+    ///
+    /// `pmra` is the proper motion in right ascension (including the
+    /// `cos(dec)` factor) and `pmdec` the proper motion in declination, both
+    /// in milliarcseconds per year. `years` is the time interval to propagate
+    /// over. The propagation is linear in the tangent plane, which is accurate
+    /// for the small displacements involved over decades.
+    pub fn propagate(&self, pmra: f64, pmdec: f64, years: f64) -> SkyPosition {
+        let (sin_ra, cos_ra) = self.ra.to_radians().sin_cos();
+        let (sin_dec, cos_dec) = self.dec.to_radians().sin_cos();
+        // unit vectors towards the position, and towards east and north
+        let r = [cos_dec * cos_ra, cos_dec * sin_ra, sin_dec];
+        let east = [-sin_ra, cos_ra, 0.0];
+        let north = [-sin_dec * cos_ra, -sin_dec * sin_ra, cos_dec];
+        let xi = (pmra * years / MAS_PER_DEG).to_radians();
+        let eta = (pmdec * years / MAS_PER_DEG).to_radians();
+        let v = [
+            r[0] + xi * east[0] + eta * north[0],
+            r[1] + xi * east[1] + eta * north[1],
+            r[2] + xi * east[2] + eta * north[2],
+        ];
+        let ra = v[1].atan2(v[0]).to_degrees();
+        let dec = v[2].atan2(v[0].hypot(v[1])).to_degrees();
+        SkyPosition::new(normalize_ra(ra), dec)
+    }
+
+    /// Rectangles in `(ra, dec)` which together contain every position within
+    /// `radius` degrees of this one.
+    ///
+    /// A circle which straddles `ra = 0` is covered by two rectangles, and one
+    /// which contains a pole is covered by a rectangle spanning all right
+    /// ascensions.
+    pub fn bounding_rects(&self, radius: f64) -> Vec<Rect<f64>> {
+        let dec_min = (self.dec - radius).max(-90.0);
+        let dec_max = (self.dec + radius).min(90.0);
+        let half_width = if dec_min <= -90.0 || dec_max >= 90.0 {
+            180.0
+        } else {
+            let ratio = radius.to_radians().sin() / self.dec.to_radians().cos();
+            if ratio >= 1.0 {
+                180.0
+            } else {
+                ratio.asin().to_degrees()
+            }
+        };
+        let rect = |ra_min: f64, ra_max: f64| {
+            f64::enclosing_interval(ra_min, ra_max).and_then(|x_interval| {
+                f64::enclosing_interval(dec_min, dec_max)
+                    .map(|y_interval| Rect::new_from_intervals(x_interval, y_interval))
+            })
+        };
+        let ra = normalize_ra(self.ra);
+        let ranges = if half_width >= 180.0 {
+            vec![(0.0, 360.0)]
+        } else if ra - half_width < 0.0 {
+            vec![(0.0, ra + half_width), (ra - half_width + 360.0, 360.0)]
+        } else if ra + half_width >= 360.0 {
+            vec![(ra - half_width, 360.0), (0.0, ra + half_width - 360.0)]
+        } else {
+            vec![(ra - half_width, ra + half_width)]
+        };
+        ranges
+            .into_iter()
+            .filter_map(|(ra_min, ra_max)| rect(ra_min, ra_max))
+            .collect()
+    }
+}
+
+/// Wrap a right ascension into the range `[0, 360)` degrees.
+pub fn normalize_ra(ra: f64) -> f64 {
+    let wrapped = ra.rem_euclid(360.0);
+    // rem_euclid can round up to exactly 360.0 for tiny negative inputs
+    if wrapped >= 360.0 {
+        0.0
+    } else {
+        wrapped
+    }
+}
+
+/// A catalog source which has a position on the sky.
+///
+/// Beyond the position, the remaining methods provide optional information
+/// which is used (when present) to propagate positions between epochs and to
+/// scale match radii by positional uncertainty.
+pub trait SkySource {
+    /// Position at the source's reference epoch.
+    fn position(&self) -> SkyPosition;
+
+    /// One-sigma positional uncertainty, in milliarcseconds.
+    fn position_error(&self) -> Option<f64> {
+        None
+    }
+
+    /// Reference epoch of `position`, as a Julian year (eg. `2015.5`).
+    fn epoch(&self) -> Option<f64> {
+        None
+    }
+
+    /// Proper motion `(pmra, pmdec)` in milliarcseconds per year, where
+    /// `pmra` includes the `cos(dec)` factor.
+    fn proper_motion(&self) -> Option<(f64, f64)> {
+        None
+    }
+
+    /// Identifier of the source within its catalog.
+    fn id(&self) -> Option<String> {
+        None
+    }
+
+    /// Magnitude of the source, in the catalog's primary band.
+    fn magnitude(&self) -> Option<f64> {
+        None
+    }
+
+    /// Position propagated to `epoch`.
+    ///
+    /// If the source lacks either a proper motion or a reference epoch then
+    /// its position is returned unchanged.
+    fn position_at(&self, epoch: f64) -> SkyPosition {
+        match (self.proper_motion(), self.epoch()) {
+            (Some((pmra, pmdec)), Some(source_epoch)) => {
+                self.position().propagate(pmra, pmdec, epoch - source_epoch)
+            }
+            _ => self.position(),
+        }
+    }
+}
+
+impl SkySource for SkyPosition {
+    fn position(&self) -> SkyPosition {
+        *self
+    }
+}
+
+impl<T: SkySource + ?Sized> SkySource for &T {
+    fn position(&self) -> SkyPosition {
+        (**self).position()
+    }
+
+    fn position_error(&self) -> Option<f64> {
+        (**self).position_error()
+    }
+
+    fn epoch(&self) -> Option<f64> {
+        (**self).epoch()
+    }
+
+    fn proper_motion(&self) -> Option<(f64, f64)> {
+        (**self).proper_motion()
+    }
+
+    fn id(&self) -> Option<String> {
+        (**self).id()
+    }
+
+    fn magnitude(&self) -> Option<f64> {
+        (**self).magnitude()
+    }
+}
+
+impl<T: SkySource + ?Sized> SkySource for Box<T> {
+    fn position(&self) -> SkyPosition {
+        (**self).position()
+    }
+
+    fn position_error(&self) -> Option<f64> {
+        (**self).position_error()
+    }
+
+    fn epoch(&self) -> Option<f64> {
+        (**self).epoch()
+    }
+
+    fn proper_motion(&self) -> Option<(f64, f64)> {
+        (**self).proper_motion()
+    }
+
+    fn id(&self) -> Option<String> {
+        (**self).id()
+    }
+
+    fn magnitude(&self) -> Option<f64> {
+        (**self).magnitude()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use geom::p2::P2;
+    use quickcheck_macros::quickcheck;
+    use sky::position::{normalize_ra, SkyPosition, MAS_PER_DEG};
+
+    #[test]
+    fn separation_simple() {
+        let a = SkyPosition::new(0.0, 0.0);
+        assert!((a.separation(&SkyPosition::new(90.0, 0.0)) - 90.0).abs() < 1e-12);
+        assert!((a.separation(&SkyPosition::new(359.0, 0.0)) - 1.0).abs() < 1e-12);
+        assert!((a.separation(&SkyPosition::new(123.0, 90.0)) - 90.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn separation_small() {
+        let a = SkyPosition::new(45.0, 60.0);
+        let b = SkyPosition::new(45.0, 60.0 + 1.0 / MAS_PER_DEG);
+        let mas = a.separation(&b) * MAS_PER_DEG;
+        assert!((mas - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn propagate_dec() {
+        let a = SkyPosition::new(10.0, 20.0);
+        let b = a.propagate(0.0, 1000.0, 36.0); // 36 arcsec north
+        assert!((b.ra - 10.0).abs() < 1e-12);
+        assert!((b.dec - 20.01).abs() < 1e-9);
+    }
+
+    #[test]
+    fn propagate_across_pole() {
+        let a = SkyPosition::new(0.0, 90.0 - 1e-3);
+        let b = a.propagate(0.0, 2.0 * MAS_PER_DEG * 1e-3, 1.0);
+        assert!((b.ra - 180.0).abs() < 1e-6);
+        assert!((b.dec - (90.0 - 1e-3)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn normalize_ra_range() {
+        assert_eq!(normalize_ra(360.0), 0.0);
+        assert_eq!(normalize_ra(-90.0), 270.0);
+        assert_eq!(normalize_ra(-1e-20), 0.0);
+    }
+
+    #[test]
+    fn bounding_rects_wrap() {
+        let rects = SkyPosition::new(359.5, 0.0).bounding_rects(1.0);
+        assert_eq!(rects.len(), 2);
+        let covered = |ra: f64, dec: f64| rects.iter().any(|r| r.contains(&P2::new(ra, dec)));
+        assert!(covered(0.4, 0.0));
+        assert!(covered(358.6, 0.9));
+        assert!(!covered(1.6, 0.0));
+    }
+
+    #[test]
+    fn bounding_rects_pole() {
+        let rects = SkyPosition::new(100.0, 89.5).bounding_rects(1.0);
+        assert_eq!(rects.len(), 1);
+        assert!(rects[0].contains(&P2::new(280.0, 89.5)));
+        assert!(rects[0].contains(&P2::new(0.0, 90.0)));
+    }
+
+    /// Property test: positions within the radius are inside one of the
+    /// bounding rectangles.
+    #[quickcheck]
+    fn bounding_rects_cover(ra: u16, dec: i8, dra: i8, ddec: i8) {
+        let center = SkyPosition::new(f64::from(ra % 360), f64::from(dec % 90));
+        let other = SkyPosition::new(
+            normalize_ra(center.ra + f64::from(dra) / 10.0),
+            (center.dec + f64::from(ddec) / 100.0).clamp(-90.0, 90.0),
+        );
+        let radius = 2.0;
+        if center.separation(&other) <= radius {
+            let rects = center.bounding_rects(radius);
+            assert!(rects
+                .iter()
+                .any(|r| r.contains(&P2::new(other.ra, other.dec))));
+        }
+    }
+
+    /// Property test: proper motion displaces a position by the total proper
+    /// motion multiplied by the time interval (for small displacements).
+    #[quickcheck]
+    fn propagate_distance(ra: u16, dec: i8, pmra: i16, pmdec: i16) {
+        let a = SkyPosition::new(f64::from(ra % 360), f64::from(dec % 89));
+        let (pmra, pmdec) = (f64::from(pmra), f64::from(pmdec));
+        let b = a.propagate(pmra, pmdec, 10.0);
+        let expected = 10.0 * pmra.hypot(pmdec);
+        let actual = a.separation(&b) * MAS_PER_DEG;
+        assert!((actual - expected).abs() < 1e-3 * expected.max(1.0));
+    }
+}
@@ -0,0 +1,239 @@
+use sky::position::{ARCSEC_PER_DEG, MAS_PER_DEG};
+use std::ops::{Add, Mul, Neg, Sub};
+
+/// Kilometres per second of one milliarcsecond per year at one kiloparsec.
+pub const KM_S_PER_MAS_YR_KPC: f64 = 4.740_470_446;
+
+/// An angle, such as a coordinate, a separation or a positional error.
+///
+/// Catalog columns give angles in degrees or milliarcseconds; keeping them
+/// as `Angle`s, rather than `f64`s, means the unit is only chosen when the
+/// value is read.
+///
+/// ```
+/// # use starquad::sky::quantity::Angle;
+/// let error = Angle::from_mas(36.0);
+/// assert!((error.arcsec() - 0.036).abs() < 1e-15);
+/// assert!(error < Angle::from_degrees(1e-3));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct Angle(f64);
+
+impl Angle {
+    pub fn from_degrees(degrees: f64) -> Self {
+        Angle(degrees)
+    }
+
+    pub fn from_arcsec(arcsec: f64) -> Self {
+        Angle(arcsec / ARCSEC_PER_DEG)
+    }
+
+    pub fn from_mas(mas: f64) -> Self {
+        Angle(mas / MAS_PER_DEG)
+    }
+
+    pub fn from_radians(radians: f64) -> Self {
+        Angle(radians.to_degrees())
+    }
+
+    pub fn degrees(self) -> f64 {
+        self.0
+    }
+
+    pub fn arcsec(self) -> f64 {
+        self.0 * ARCSEC_PER_DEG
+    }
+
+    pub fn mas(self) -> f64 {
+        self.0 * MAS_PER_DEG
+    }
+
+    pub fn radians(self) -> f64 {
+        self.0.to_radians()
+    }
+}
+
+/// Proper motion, in milliarcseconds per year.
+///
+/// `pmra` is the motion in right ascension including the `cos(dec)` factor
+/// (so it is a true angular rate), and `pmdec` the motion in declination,
+/// as in the Gaia `pmra` and `pmdec` columns.
+///
+/// ```
+/// # use starquad::sky::quantity::{Parallax, ProperMotion};
+/// let pm = ProperMotion::new(3.0, -4.0);
+/// assert_eq!(pm.total(), 5.0);
+/// // 5 mas/yr at 2 kpc
+/// let v = pm.tangential_velocity_at(Parallax::new(0.5)).unwrap();
+/// assert!((v - 47.40470446).abs() < 1e-9);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ProperMotion {
+    pub pmra: f64,
+    pub pmdec: f64,
+}
+
+impl ProperMotion {
+    pub fn new(pmra: f64, pmdec: f64) -> Self {
+        ProperMotion { pmra, pmdec }
+    }
+
+    /// Total proper motion, in milliarcseconds per year.
+    pub fn total(&self) -> f64 {
+        self.pmra.hypot(self.pmdec)
+    }
+
+    /// Standard error of `total`, from the standard errors of `pmra` and
+    /// `pmdec` (as a `ProperMotion`) and the correlation between them (the
+    /// Gaia `pmra_pmdec_corr` column).
+    ///
+    /// The error of a zero proper motion is the larger of the two errors.
+    pub fn total_error(&self, error: &ProperMotion, correlation: f64) -> f64 {
+        let total = self.total();
+        if total == 0.0 {
+            return error.pmra.max(error.pmdec);
+        }
+        let variance = self.pmra * self.pmra * error.pmra * error.pmra
+            + self.pmdec * self.pmdec * error.pmdec * error.pmdec
+            + 2.0 * self.pmra * self.pmdec * correlation * error.pmra * error.pmdec;
+        variance.max(0.0).sqrt() / total
+    }
+
+    /// Position angle of the motion, east of north, in degrees in
+    /// `[0, 360)`.
+    pub fn position_angle(&self) -> f64 {
+        self.pmra.atan2(self.pmdec).to_degrees().rem_euclid(360.0)
+    }
+
+    /// Tangential velocity, in kilometres per second, of a source at a
+    /// distance in parsecs.
+    pub fn tangential_velocity(&self, distance_pc: f64) -> f64 {
+        KM_S_PER_MAS_YR_KPC * self.total() * distance_pc / 1000.0
+    }
+
+    /// Tangential velocity of a source with a parallax, or `None` if the
+    /// parallax is not positive.
+    pub fn tangential_velocity_at(&self, parallax: Parallax) -> Option<f64> {
+        Some(self.tangential_velocity(parallax.distance_pc()?))
+    }
+
+    /// Angular displacement over an interval, in years, as `(east, north)`.
+    pub fn displacement(&self, years: f64) -> (Angle, Angle) {
+        (
+            Angle::from_mas(self.pmra * years),
+            Angle::from_mas(self.pmdec * years),
+        )
+    }
+}
+
+impl Add for ProperMotion {
+    type Output = ProperMotion;
+
+    fn add(self, other: ProperMotion) -> ProperMotion {
+        ProperMotion::new(self.pmra + other.pmra, self.pmdec + other.pmdec)
+    }
+}
+
+impl Sub for ProperMotion {
+    type Output = ProperMotion;
+
+    fn sub(self, other: ProperMotion) -> ProperMotion {
+        ProperMotion::new(self.pmra - other.pmra, self.pmdec - other.pmdec)
+    }
+}
+
+impl Mul<f64> for ProperMotion {
+    type Output = ProperMotion;
+
+    fn mul(self, scale: f64) -> ProperMotion {
+        ProperMotion::new(self.pmra * scale, self.pmdec * scale)
+    }
+}
+
+impl Neg for ProperMotion {
+    type Output = ProperMotion;
+
+    fn neg(self) -> ProperMotion {
+        ProperMotion::new(-self.pmra, -self.pmdec)
+    }
+}
+
+/// Parallax, in milliarcseconds.
+///
+/// Gaia parallaxes may be zero or negative for distant or faint sources, so
+/// the distance is only given for positive parallaxes, and naively
+/// inverting a parallax with a large relative error gives a biased
+/// distance.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct Parallax(f64);
+
+impl Parallax {
+    pub fn new(mas: f64) -> Self {
+        Parallax(mas)
+    }
+
+    pub fn mas(self) -> f64 {
+        self.0
+    }
+
+    pub fn angle(self) -> Angle {
+        Angle::from_mas(self.0)
+    }
+
+    /// Distance in parsecs, as the inverse of the parallax, or `None` if
+    /// the parallax is not positive.
+    pub fn distance_pc(self) -> Option<f64> {
+        if self.0 > 0.0 {
+            Some(1000.0 / self.0)
+        } else {
+            None
+        }
+    }
+
+    /// Standard error of `distance_pc`, to first order, from the standard
+    /// error of the parallax in milliarcseconds.
+    pub fn distance_error_pc(self, error: f64) -> Option<f64> {
+        self.distance_pc().map(|d| d * error / self.0)
+    }
+
+    /// Ratio of the parallax to its standard error (the Gaia
+    /// `parallax_over_error` column).
+    pub fn over_error(self, error: f64) -> f64 {
+        self.0 / error
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use sky::quantity::{Parallax, ProperMotion};
+
+    #[test]
+    fn proper_motion_arithmetic() {
+        let a = ProperMotion::new(1.0, 2.0);
+        let b = ProperMotion::new(0.5, -1.0);
+        assert_eq!(a + b, ProperMotion::new(1.5, 1.0));
+        assert_eq!(a - b, ProperMotion::new(0.5, 3.0));
+        assert_eq!(-a * 2.0, ProperMotion::new(-2.0, -4.0));
+        assert_eq!(ProperMotion::new(1.0, 0.0).position_angle(), 90.0);
+        assert_eq!(ProperMotion::new(0.0, -1.0).position_angle(), 180.0);
+        let (east, north) = a.displacement(3600.0);
+        assert!((east.arcsec() - 3.6).abs() < 1e-12 && (north.arcsec() - 7.2).abs() < 1e-12);
+    }
+
+    #[test]
+    fn error_propagation() {
+        let error = ProperMotion::new(0.3, 0.4);
+        // along an axis, the error is that axis's error
+        assert!((ProperMotion::new(0.0, 10.0).total_error(&error, 0.5) - 0.4).abs() < 1e-12);
+        // uncorrelated, at 45 degrees: sqrt((0.09 + 0.16) / 2)
+        let pm = ProperMotion::new(1.0, 1.0);
+        assert!((pm.total_error(&error, 0.0) - 0.125f64.sqrt()).abs() < 1e-12);
+        assert_eq!(ProperMotion::default().total_error(&error, 0.0), 0.4);
+
+        let parallax = Parallax::new(2.0);
+        assert_eq!(parallax.distance_pc(), Some(500.0));
+        assert_eq!(parallax.distance_error_pc(0.1), Some(25.0));
+        assert_eq!(parallax.over_error(0.1), 20.0);
+        assert_eq!(Parallax::new(-0.1).distance_pc(), None);
+    }
+}
@@ -0,0 +1,130 @@
+use accel2d::quadtree::QuadTree;
+use accel2d::Accel2D;
+use geom::p2::P2;
+use geom::quantize::Quantizer;
+use geom::rect::Rect;
+use sky::index::sky_bounds;
+use sky::position::SkySource;
+
+/// Index of sources by `(ra, dec)` quantized to `u32` fixed-point.
+///
+/// The tree holds `u32` coordinates, which take half of the space of `f64`
+/// coordinates. Queries find candidates in the quantized tree and then refine
+/// them exactly, using the full-precision position of each source.
+pub struct QuantizedIndex<T> {
+    quantizer: Quantizer,
+    tree: QuadTree<u32, T>,
+}
+
+impl<T: SkySource + Clone> QuantizedIndex<T> {
+    /// Create an empty index covering the whole sky.
+    pub fn new() -> Self {
+        QuantizedIndex::with_quantizer(Quantizer::new(sky_bounds()))
+    }
+
+    /// Create an empty index, quantizing coordinates over a region of the
+    /// sky. Sources outside of the region are still found by queries, but
+    /// less efficiently.
+    pub fn with_quantizer(quantizer: Quantizer) -> Self {
+        QuantizedIndex {
+            quantizer,
+            tree: QuadTree::with_bounds(Quantizer::grid()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    pub fn push(&mut self, source: T) {
+        let position = source.position();
+        let point = self.quantizer.quantize(&P2::new(position.ra, position.dec));
+        self.tree.push((point, source));
+    }
+
+    /// Find the sources whose positions lie within a rectangle of
+    /// `(ra, dec)`.
+    pub fn query_rect(&self, rect: &Rect<f64>) -> Vec<&T> {
+        let quantized = match self.quantizer.quantize_rect(rect) {
+            Some(quantized) => quantized,
+            None => return Vec::new(),
+        };
+        self.tree
+            .query_rect(&quantized)
+            .into_iter()
+            .map(|(_, source)| source)
+            .filter(|source| {
+                let position = source.position();
+                rect.contains(&P2::new(position.ra, position.dec))
+            })
+            .collect()
+    }
+}
+
+impl<T: SkySource + Clone> Default for QuantizedIndex<T> {
+    fn default() -> Self {
+        QuantizedIndex::new()
+    }
+}
+
+/// Index a collection of sources by their quantized positions.
+pub fn index_sources_quantized<I>(sources: I) -> QuantizedIndex<I::Item>
+where
+    I: IntoIterator,
+    I::Item: SkySource + Clone,
+{
+    let mut index = QuantizedIndex::new();
+    for source in sources {
+        index.push(source);
+    }
+    index
+}
+
+#[cfg(test)]
+mod test {
+    use geom::p2::P2;
+    use geom::rect::Rect;
+    use quickcheck_macros::quickcheck;
+    use sky::position::SkyPosition;
+    use sky::quantized::index_sources_quantized;
+
+    /// Property test: queries of a quantized index find exactly the sources
+    /// within the query rectangle.
+    #[quickcheck]
+    fn query_rect_is_exact(coords: Vec<(u32, i32)>, rect: (u16, i16, u16, u16)) {
+        let positions = coords
+            .iter()
+            .map(|&(ra, dec)| {
+                SkyPosition::new(
+                    f64::from(ra % 3_600_000) / 1e4,
+                    f64::from(dec % 900_000) / 1e4,
+                )
+            })
+            .collect::<Vec<_>>();
+        let rect = Rect::new(
+            f64::from(rect.0 % 3600) / 10.0,
+            f64::from(rect.1 % 900) / 10.0,
+            f64::from(rect.2) / 100.0,
+            f64::from(rect.3) / 100.0,
+        )
+        .unwrap();
+        let index = index_sources_quantized(positions.iter().cloned());
+        let mut found = index
+            .query_rect(&rect)
+            .iter()
+            .map(|p| (p.ra, p.dec))
+            .collect::<Vec<_>>();
+        let mut expected = positions
+            .iter()
+            .filter(|p| rect.contains(&P2::new(p.ra, p.dec)))
+            .map(|p| (p.ra, p.dec))
+            .collect::<Vec<_>>();
+        found.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(found, expected);
+    }
+}
@@ -0,0 +1,193 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use sky::healpix::{pixel, position_in, MAX_ORDER};
+use sky::moc::{self, Moc};
+use sky::position::{SkyPosition, SkySource};
+
+/// A source of a random catalog.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RandomSource {
+    pub position: SkyPosition,
+    pub magnitude: Option<f64>,
+}
+
+impl SkySource for RandomSource {
+    fn position(&self) -> SkyPosition {
+        self.position
+    }
+
+    fn magnitude(&self) -> Option<f64> {
+        self.magnitude
+    }
+}
+
+/// Endless stream of sources placed uniformly at random over the area of a
+/// MOC, with magnitudes drawn from the distribution of a sample's: the "R"
+/// catalog for correlation function estimators and completeness studies.
+///
+/// Magnitudes are drawn from the sample's empirical distribution, with
+/// linear interpolation between its order statistics, so (unlike
+/// resampling) they need not repeat values of the sample. Without any
+/// magnitudes, the sources have none.
+///
+/// As with `gaia::sample::Bernoulli`, the same seed always gives the same
+/// catalog.
+///
+/// ```
+/// # use starquad::sky::moc::Moc;
+/// # use starquad::sky::random::RandomCatalog;
+/// let footprint: Moc = "3/100-120".parse().unwrap();
+/// let randoms = RandomCatalog::new(footprint.clone(), 42)
+///     .unwrap()
+///     .with_magnitudes(vec![18.0, 19.0, 20.0])
+///     .take(100)
+///     .collect::<Vec<_>>();
+/// assert!(randoms.iter().all(|r| footprint.contains(&r.position)));
+/// assert!(randoms.iter().all(|r| (18.0..=20.0).contains(&r.magnitude.unwrap())));
+/// ```
+pub struct RandomCatalog {
+    moc: Moc,
+    /// Number of pixels at `MAX_ORDER` before the end of each range.
+    cumulative: Vec<u64>,
+    /// Number of pixels at `MAX_ORDER` in the MOC, which is not 0.
+    total: u64,
+    magnitudes: Vec<f64>,
+    rng: ChaCha8Rng,
+}
+
+impl RandomCatalog {
+    /// Random sources over a MOC, which must not be empty.
+    pub fn new(moc: Moc, seed: u64) -> Result<Self, moc::Error> {
+        let cumulative = moc
+            .ranges()
+            .iter()
+            .scan(0, |total, range| {
+                *total += range.end - range.start;
+                Some(*total)
+            })
+            .collect::<Vec<_>>();
+        let total = match cumulative.last() {
+            Some(&total) if total > 0 => total,
+            _ => return Err(moc::Error::Empty),
+        };
+        Ok(RandomCatalog {
+            moc,
+            cumulative,
+            total,
+            magnitudes: Vec::new(),
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        })
+    }
+
+    /// Random catalog matching a sample: covering the cells at `order` which
+    /// contain any of its sources, with the distribution of their
+    /// magnitudes. There must be at least one source.
+    pub fn matching<I, S>(sources: I, order: u8, seed: u64) -> Result<Self, moc::Error>
+    where
+        I: IntoIterator<Item = S>,
+        S: SkySource,
+    {
+        if order > MAX_ORDER {
+            return Err(moc::Error::InvalidCell { order, pixel: 0 });
+        }
+        let mut cells = Vec::new();
+        let mut magnitudes = Vec::new();
+        for source in sources {
+            cells.push((order, pixel(order, &source.position())));
+            magnitudes.extend(source.magnitude());
+        }
+        let moc = Moc::from_cells(cells)?;
+        Ok(RandomCatalog::new(moc, seed)?.with_magnitudes(magnitudes))
+    }
+
+    /// Draw magnitudes from the distribution of these. NaNs are ignored.
+    pub fn with_magnitudes<I>(mut self, magnitudes: I) -> Self
+    where
+        I: IntoIterator<Item = f64>,
+    {
+        self.magnitudes = magnitudes.into_iter().filter(|m| !m.is_nan()).collect();
+        self.magnitudes.sort_unstable_by(f64::total_cmp);
+        self
+    }
+
+    pub fn moc(&self) -> &Moc {
+        &self.moc
+    }
+
+    fn random_position(&mut self) -> SkyPosition {
+        let n = self.rng.gen_range(0..self.total);
+        let i = self.cumulative.partition_point(|&end| end <= n);
+        let range = &self.moc.ranges()[i];
+        let before = if i == 0 { 0 } else { self.cumulative[i - 1] };
+        let pixel = range.start + (n - before);
+        position_in(MAX_ORDER, pixel, self.rng.gen(), self.rng.gen())
+    }
+
+    fn random_magnitude(&mut self) -> Option<f64> {
+        let last = self.magnitudes.len().checked_sub(1)?;
+        if last == 0 {
+            return Some(self.magnitudes[0]);
+        }
+        let u = self.rng.gen_range(0.0..last as f64);
+        let i = (u as usize).min(last - 1);
+        let t = u - i as f64;
+        Some(self.magnitudes[i] + t * (self.magnitudes[i + 1] - self.magnitudes[i]))
+    }
+}
+
+impl Iterator for RandomCatalog {
+    type Item = RandomSource;
+
+    fn next(&mut self) -> Option<RandomSource> {
+        Some(RandomSource {
+            position: self.random_position(),
+            magnitude: self.random_magnitude(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use sky::healpix::pixel;
+    use sky::moc::Moc;
+    use sky::position::SkyPosition;
+    use sky::random::{RandomCatalog, RandomSource};
+
+    #[test]
+    fn uniform_over_moc() {
+        // two base pixels: the second has a quarter of it missing
+        let moc: Moc = "0/0 1/16-18".parse().unwrap();
+        let randoms = RandomCatalog::new(moc.clone(), 1)
+            .unwrap()
+            .take(70_000)
+            .collect::<Vec<_>>();
+        assert!(randoms.iter().all(|r| moc.contains(&r.position)));
+        assert!(randoms.iter().all(|r| r.magnitude.is_none()));
+        let in_first = randoms
+            .iter()
+            .filter(|r| pixel(0, &r.position) == 0)
+            .count();
+        // areas in the ratio 4:3
+        assert!((39_000..41_000).contains(&in_first));
+    }
+
+    #[test]
+    fn matching_sample() {
+        let sample = (0..1000)
+            .map(|i| RandomSource {
+                position: SkyPosition::new(40.0 + f64::from(i % 10) * 0.01, 10.0),
+                magnitude: Some(if i < 900 { 15.0 } else { 20.0 }),
+            })
+            .collect::<Vec<_>>();
+        let catalog = RandomCatalog::matching(sample.iter(), 8, 7).unwrap();
+        assert!(catalog.moc().sky_fraction() < 1e-4);
+        let randoms = catalog.take(10_000).collect::<Vec<_>>();
+        let bright = randoms
+            .iter()
+            .filter(|r| r.magnitude.unwrap() < 17.5)
+            .count();
+        assert!((8_800..9_200).contains(&bright));
+        let again = RandomCatalog::matching(sample.iter(), 8, 7).unwrap().next();
+        assert_eq!(again, randoms.first().copied());
+    }
+}
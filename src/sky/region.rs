@@ -0,0 +1,421 @@
+use geom::p2::P2;
+use geom::polygon::Polygon;
+use geom::rect::Rect;
+use sky::index::sky_bounds;
+use sky::position::{normalize_ra, SkyPosition};
+use std::f64::consts::PI;
+
+/// Errors from describing a region.
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum Error {
+    /// A bound or size is not a finite number.
+    #[error("bounds must be finite numbers")]
+    NotFinite,
+    /// The minimum of a range is greater than its maximum, or a size is
+    /// negative.
+    #[error("the minimum of a range must not be greater than its maximum")]
+    Reversed,
+    /// A range of declination lies wholly beyond a pole.
+    #[error("declinations from {0} to {1} are beyond a pole")]
+    BeyondPole(f64, f64),
+}
+
+/// Region of the sky searched by a query.
+///
+/// # Boxes in right ascension and declination
+///
+/// A rectangle of `(ra, dec)` is a very different shape near the poles than
+/// near the equator, and bounds outside the sphere have no meaning, so boxes
+/// given as ranges of right ascension and declination (by the CLI, the
+/// services and the bindings) are made regions by `Region::sky_box`:
+///
+/// - declinations are clamped to `[-90, 90]`, and a range wholly beyond a
+///   pole is an error;
+/// - right ascensions are taken modulo 360, so that `350..370` (or
+///   `-10..10`) is a box across `ra = 0`, and a range of 360 or more spans
+///   all right ascensions;
+/// - a box spanning all right ascensions which reaches a pole is the cap
+///   around that pole, a `Cone` centred on it;
+/// - a box with a smaller range of right ascension which reaches a pole is
+///   the wedge of the sphere between its meridians, and sources at the pole
+///   itself are found only if their right ascension is in the range.
+///
+/// `Region::sky_box_around` instead gives a box its size on the sky: it is
+/// at least as wide as asked along every parallel it spans, and becomes the
+/// cap around a pole when it reaches one.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Region {
+    /// Rectangle of `(ra, dec)`, in degrees.
+    Rect(Rect<f64>),
+    /// All positions within `radius` degrees of `center`.
+    Cone { center: SkyPosition, radius: f64 },
+    /// Positions in any of several rectangles of `(ra, dec)`, such as the
+    /// two sides of a box across `ra = 0`.
+    Rects(Vec<Rect<f64>>),
+    /// Polygon of `(ra, dec)`, in degrees, whose edges are straight in
+    /// `(ra, dec)` rather than great circles. It does not wrap around
+    /// `ra = 0`.
+    Polygon(Polygon<f64>),
+}
+
+/// Rectangle of `(ra, dec)` including both ends of each range.
+fn closed_rect(ra_min: f64, ra_max: f64, dec_min: f64, dec_max: f64) -> Result<Rect<f64>, Error> {
+    Rect::bounding(&[P2::new(ra_min, dec_min), P2::new(ra_max, dec_max)]).ok_or(Error::NotFinite)
+}
+
+/// Area of a polygon of `(ra, dec)` on the sky, in square degrees.
+///
+/// By Green's theorem this is the integral of `-sin(dec) d(ra)` around the
+/// polygon, which along an edge straight in `(ra, dec)` has a closed form.
+fn polygon_area(polygon: &Polygon<f64>) -> f64 {
+    let vertices = polygon.vertices();
+    let n = vertices.len();
+    let integral = (0..n).fold(0.0, |sum, i| {
+        let (a, b) = (&vertices[i], &vertices[(i + 1) % n]);
+        let (ra_0, ra_1) = (a.x.to_radians(), b.x.to_radians());
+        let (dec_0, dec_1) = (a.y.to_radians(), b.y.to_radians());
+        let mean_sin = if dec_0 == dec_1 {
+            dec_0.sin()
+        } else {
+            (dec_0.cos() - dec_1.cos()) / (dec_1 - dec_0)
+        };
+        sum - (ra_1 - ra_0) * mean_sin
+    });
+    integral.abs() * (180.0 / PI).powi(2)
+}
+
+impl Region {
+    pub fn cone(center: SkyPosition, radius: f64) -> Self {
+        Region::Cone { center, radius }
+    }
+
+    /// Region of the positions with `ra` from `ra_min` to `ra_max` and `dec`
+    /// from `dec_min` to `dec_max`, in degrees and including the ends, by
+    /// the policy described on `Region`.
+    ///
+    /// ```
+    /// # use starquad::sky::position::SkyPosition;
+    /// # use starquad::sky::region::Region;
+    /// let across_zero = Region::sky_box(350.0, 370.0, -5.0, 5.0).unwrap();
+    /// assert!(across_zero.contains(&SkyPosition::new(5.0, 0.0)));
+    /// assert!(across_zero.contains(&SkyPosition::new(355.0, 0.0)));
+    /// let cap = Region::sky_box(0.0, 360.0, 80.0, 95.0).unwrap();
+    /// assert_eq!(cap, Region::cone(SkyPosition::new(0.0, 90.0), 10.0));
+    /// assert!(Region::sky_box(0.0, 10.0, 91.0, 95.0).is_err());
+    /// ```
+    pub fn sky_box(ra_min: f64, ra_max: f64, dec_min: f64, dec_max: f64) -> Result<Self, Error> {
+        if ![ra_min, ra_max, dec_min, dec_max]
+            .iter()
+            .all(|b| b.is_finite())
+        {
+            return Err(Error::NotFinite);
+        }
+        if ra_min > ra_max || dec_min > dec_max {
+            return Err(Error::Reversed);
+        }
+        if dec_min > 90.0 || dec_max < -90.0 {
+            return Err(Error::BeyondPole(dec_min, dec_max));
+        }
+        let (dec_min, dec_max) = (dec_min.max(-90.0), dec_max.min(90.0));
+        if ra_max - ra_min >= 360.0 {
+            return Ok(match (dec_min <= -90.0, dec_max >= 90.0) {
+                (true, true) => Region::Rect(sky_bounds()),
+                (false, true) => Region::cone(SkyPosition::new(0.0, 90.0), 90.0 - dec_min),
+                (true, false) => Region::cone(SkyPosition::new(0.0, -90.0), dec_max + 90.0),
+                (false, false) => Region::Rect(closed_rect(0.0, 360.0, dec_min, dec_max)?),
+            });
+        }
+        let start = normalize_ra(ra_min);
+        let end = start + (ra_max - ra_min);
+        Ok(if end <= 360.0 {
+            Region::Rect(closed_rect(start, end, dec_min, dec_max)?)
+        } else {
+            Region::Rects(vec![
+                closed_rect(start, 360.0, dec_min, dec_max)?,
+                closed_rect(0.0, end - 360.0, dec_min, dec_max)?,
+            ])
+        })
+    }
+
+    /// Region of a box `width` by `height` degrees on the sky, centred on a
+    /// position, by the policy described on `Region`.
+    ///
+    /// The range of right ascension is widened by `1 / cos(dec)` at the
+    /// declination of the box furthest from the equator, so the box is at
+    /// least `width` wide along each parallel.
+    ///
+    /// ```
+    /// # use starquad::sky::position::SkyPosition;
+    /// # use starquad::sky::region::Region;
+    /// let near_pole = Region::sky_box_around(&SkyPosition::new(30.0, 59.0), 1.0, 2.0).unwrap();
+    /// // at dec 60, a degree on the sky is two degrees of ra
+    /// assert!(near_pole.contains(&SkyPosition::new(29.1, 60.0)));
+    /// let polar = Region::sky_box_around(&SkyPosition::new(30.0, 89.5), 1.0, 2.0).unwrap();
+    /// assert_eq!(polar, Region::cone(SkyPosition::new(0.0, 90.0), 1.5));
+    /// ```
+    pub fn sky_box_around(center: &SkyPosition, width: f64, height: f64) -> Result<Self, Error> {
+        if !(width.is_finite() && height.is_finite()) {
+            return Err(Error::NotFinite);
+        }
+        if width < 0.0 || height < 0.0 {
+            return Err(Error::Reversed);
+        }
+        let dec_min = center.dec - height / 2.0;
+        let dec_max = center.dec + height / 2.0;
+        let cos_dec = dec_min
+            .abs()
+            .max(dec_max.abs())
+            .min(90.0)
+            .to_radians()
+            .cos();
+        let half_width = (width / 2.0 / cos_dec).min(180.0);
+        if dec_max < 90.0 && dec_min > -90.0 {
+            Region::sky_box(
+                center.ra - half_width,
+                center.ra + half_width,
+                dec_min,
+                dec_max,
+            )
+        } else if center.dec >= 0.0 {
+            Region::sky_box(0.0, 360.0, dec_min, 90.0)
+        } else {
+            Region::sky_box(0.0, 360.0, -90.0, dec_max)
+        }
+    }
+
+    pub fn contains(&self, position: &SkyPosition) -> bool {
+        match self {
+            Region::Rect(rect) => rect.contains(&P2::new(position.ra, position.dec)),
+            Region::Cone { center, radius } => center.separation(position) <= *radius,
+            Region::Rects(rects) => {
+                let point = P2::new(position.ra, position.dec);
+                rects.iter().any(|rect| rect.contains(&point))
+            }
+            Region::Polygon(polygon) => polygon.contains(&P2::new(position.ra, position.dec)),
+        }
+    }
+
+    /// Rectangles in `(ra, dec)` which together contain the region.
+    pub fn bounding_rects(&self) -> Vec<Rect<f64>> {
+        match self {
+            Region::Rect(rect) => vec![rect.clone()],
+            Region::Cone { center, radius } => center.bounding_rects(*radius),
+            Region::Rects(rects) => rects.clone(),
+            Region::Polygon(polygon) => Rect::bounding(polygon.vertices()).into_iter().collect(),
+        }
+    }
+
+    /// Region containing every position within `margin` degrees of this
+    /// one.
+    ///
+    /// A rectangle is widened in right ascension by enough to cover the
+    /// margin at its declination furthest from the equator, and made a
+    /// region by `Region::sky_box`, so it may wrap around `ra = 0` or become
+    /// the cap around a pole. A polygon is expanded as the rectangle bounding
+    /// it. A negative or `NaN` margin is taken as 0, and a rectangle whose
+    /// expanded bounds are not finite becomes the whole sky.
+    pub fn expanded(&self, margin: f64) -> Region {
+        let margin = margin.max(0.0);
+        match self {
+            Region::Cone { center, radius } => Region::cone(*center, radius + margin),
+            Region::Rects(_) | Region::Polygon(_) => Region::Rects(
+                self.bounding_rects()
+                    .iter()
+                    .flat_map(|rect| Region::Rect(rect.clone()).expanded(margin).bounding_rects())
+                    .collect(),
+            ),
+            Region::Rect(rect) => {
+                let (ra, dec) = (*rect.x(), *rect.y());
+                let dec_min = (dec - margin).max(-90.0);
+                let dec_max = (dec + rect.height() + margin).min(90.0);
+                let cos_dec = dec_min.abs().max(dec_max.abs()).to_radians().cos();
+                let ra_margin = if cos_dec > 0.0 {
+                    margin / cos_dec
+                } else {
+                    180.0
+                };
+                Region::sky_box(
+                    ra - ra_margin,
+                    ra + rect.width() + ra_margin,
+                    dec_min,
+                    dec_max,
+                )
+                .unwrap_or_else(|_| Region::Rect(sky_bounds()))
+            }
+        }
+    }
+
+    /// Area of the region on the sky, in square degrees. The rectangles of
+    /// `Region::Rects` are assumed not to overlap.
+    pub fn area(&self) -> f64 {
+        let rect_area = |rect: &Rect<f64>| {
+            let width = rect.width().min(360.0).to_radians();
+            let dec_min = rect.y().max(-90.0).to_radians();
+            let dec_max = (rect.y() + rect.height()).min(90.0).to_radians();
+            width * (dec_max.sin() - dec_min.sin()) * (180.0 / PI).powi(2)
+        };
+        match self {
+            Region::Rect(rect) => rect_area(rect),
+            Region::Cone { radius, .. } => {
+                2.0 * PI * (1.0 - radius.min(180.0).to_radians().cos()) * (180.0 / PI).powi(2)
+            }
+            Region::Rects(rects) => rects.iter().map(rect_area).sum(),
+            Region::Polygon(polygon) => polygon_area(polygon),
+        }
+    }
+
+    /// Whether the region may contain positions within a rectangle.
+    pub fn intersects(&self, rect: &Rect<f64>) -> bool {
+        self.bounding_rects()
+            .iter()
+            .any(|r| r.intersect(rect).is_some())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use geom::p2::P2;
+    use geom::polygon::Polygon;
+    use geom::rect::Rect;
+    use sky::position::SkyPosition;
+    use sky::region::{Error, Region};
+
+    #[test]
+    fn sky_box() {
+        // declinations are clamped to the sphere
+        let clamped = Region::sky_box(10.0, 20.0, 80.0, 95.0).unwrap();
+        assert!(clamped.contains(&SkyPosition::new(15.0, 90.0)));
+        assert!(!clamped.contains(&SkyPosition::new(25.0, 90.0)));
+        assert_eq!(
+            Region::sky_box(10.0, 20.0, -95.0, -91.0),
+            Err(Error::BeyondPole(-95.0, -91.0))
+        );
+
+        // right ascensions wrap around ra = 0
+        let wrapped = Region::sky_box(-10.0, 10.0, -1.0, 1.0).unwrap();
+        assert_eq!(wrapped, Region::sky_box(350.0, 370.0, -1.0, 1.0).unwrap());
+        assert!(wrapped.contains(&SkyPosition::new(359.0, 1.0)));
+        assert!(wrapped.contains(&SkyPosition::new(0.0, -1.0)));
+        assert!(wrapped.contains(&SkyPosition::new(10.0, 0.0)));
+        assert!(!wrapped.contains(&SkyPosition::new(10.1, 0.0)));
+        assert!(!wrapped.contains(&SkyPosition::new(180.0, 0.0)));
+        assert_eq!(wrapped.bounding_rects().len(), 2);
+        let shifted = Region::sky_box(370.0, 380.0, 0.0, 1.0).unwrap();
+        assert_eq!(shifted, Region::sky_box(10.0, 20.0, 0.0, 1.0).unwrap());
+
+        // a box spanning all right ascensions reaches round the sphere
+        let band = Region::sky_box(-100.0, 300.0, -1.0, 1.0).unwrap();
+        assert!(band.contains(&SkyPosition::new(359.9, 0.0)));
+        let south = Region::sky_box(0.0, 360.0, -100.0, -80.0).unwrap();
+        assert_eq!(south, Region::cone(SkyPosition::new(0.0, -90.0), 10.0));
+        assert!(south.contains(&SkyPosition::new(123.0, -80.1)));
+        assert!(Region::sky_box(0.0, 360.0, -90.0, 90.0)
+            .unwrap()
+            .contains(&SkyPosition::new(359.99, 90.0)));
+
+        assert_eq!(Region::sky_box(20.0, 10.0, 0.0, 1.0), Err(Error::Reversed));
+        assert_eq!(
+            Region::sky_box(0.0, f64::NAN, 0.0, 1.0),
+            Err(Error::NotFinite)
+        );
+    }
+
+    #[test]
+    fn sky_box_around() {
+        // on the equator, a degree on the sky is a degree of ra
+        let equator = Region::sky_box_around(&SkyPosition::new(0.0, 0.0), 2.0, 2.0).unwrap();
+        assert!(equator.contains(&SkyPosition::new(359.0, 1.0)));
+        assert!(!equator.contains(&SkyPosition::new(358.9, 0.0)));
+
+        // a box across the south pole is its cap
+        let polar = Region::sky_box_around(&SkyPosition::new(200.0, -89.0), 1.0, 4.0).unwrap();
+        assert_eq!(polar, Region::cone(SkyPosition::new(0.0, -90.0), 3.0));
+
+        // a box too wide for its parallel spans all right ascensions
+        let wide = Region::sky_box_around(&SkyPosition::new(90.0, 80.0), 70.0, 2.0).unwrap();
+        assert_eq!(wide, Region::sky_box(0.0, 360.0, 79.0, 81.0).unwrap());
+
+        assert_eq!(
+            Region::sky_box_around(&SkyPosition::new(0.0, 0.0), -1.0, 1.0),
+            Err(Error::Reversed)
+        );
+    }
+
+    #[test]
+    fn rects_expanded() {
+        let wrapped = Region::sky_box(359.0, 361.0, 0.0, 1.0).unwrap();
+        let wider = wrapped.expanded(0.5);
+        assert!(wider.contains(&SkyPosition::new(1.4, 1.4)));
+        assert!(wider.contains(&SkyPosition::new(358.6, -0.4)));
+        assert!(!wider.contains(&SkyPosition::new(180.0, 0.0)));
+    }
+
+    #[test]
+    fn cone_across_ra_zero() {
+        let cone = Region::cone(SkyPosition::new(359.5, 0.0), 1.0);
+        assert!(cone.contains(&SkyPosition::new(0.4, 0.0)));
+        assert!(!cone.contains(&SkyPosition::new(0.4, 0.95)));
+        assert!(cone.intersects(&Rect::new(0.0, -1.0, 0.2, 0.2).unwrap()));
+        assert!(!cone.intersects(&Rect::new(180.0, -1.0, 1.0, 1.0).unwrap()));
+    }
+
+    #[test]
+    fn areas() {
+        let sphere = 4.0 * std::f64::consts::PI * (180.0 / std::f64::consts::PI).powi(2);
+        let all = Region::sky_box(0.0, 360.0, -90.0, 90.0).unwrap();
+        assert!((all.area() / sphere - 1.0).abs() < 1e-9);
+        let hemisphere = Region::cone(SkyPosition::new(10.0, 20.0), 90.0);
+        assert!((hemisphere.area() / sphere - 0.5).abs() < 1e-9);
+        // a degree square on the equator, across ra = 0
+        let wrapped = Region::sky_box(359.5, 360.5, -0.5, 0.5).unwrap();
+        assert!((wrapped.area() - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn expanded() {
+        let rect = Region::Rect(Rect::new(10.0, 50.0, 10.0, 10.0).unwrap());
+        let wider = rect.expanded(1.0);
+        // at dec 60, a degree on the sky is two degrees of ra
+        assert!(wider.contains(&SkyPosition::new(8.1, 60.9)));
+        assert!(!wider.contains(&SkyPosition::new(7.9, 55.0)));
+        assert!(!wider.contains(&SkyPosition::new(15.0, 61.1)));
+        let wrapped = Region::Rect(Rect::new(0.5, 0.0, 1.0, 1.0).unwrap()).expanded(1.0);
+        assert!(wrapped.contains(&SkyPosition::new(359.9, 0.5)));
+        let cone = Region::cone(SkyPosition::new(0.0, 0.0), 1.0).expanded(0.5);
+        assert!(cone.contains(&SkyPosition::new(1.4, 0.0)));
+    }
+
+    #[test]
+    fn polygons() {
+        let triangle = Region::Polygon(Polygon::new(vec![
+            P2::new(0.0, 0.0),
+            P2::new(10.0, 0.0),
+            P2::new(0.0, 10.0),
+        ]));
+        assert!(triangle.contains(&SkyPosition::new(2.0, 2.0)));
+        assert!(!triangle.contains(&SkyPosition::new(6.0, 6.0)));
+        assert_eq!(
+            triangle.bounding_rects(),
+            vec![Rect::bounding(&[P2::new(0.0, 0.0), P2::new(10.0, 10.0)]).unwrap()]
+        );
+        assert!(triangle
+            .expanded(1.0)
+            .contains(&SkyPosition::new(359.5, 10.5)));
+
+        // the integral of cos(dec) over the triangle is 1 - cos(10 degrees)
+        let expected = (1.0 - 10f64.to_radians().cos()) * (180.0 / std::f64::consts::PI).powi(2);
+        assert!((triangle.area() - expected).abs() < 1e-9);
+        let square = [(10.0, 20.0), (30.0, 20.0), (30.0, 60.0), (10.0, 60.0)];
+        let mut vertices = square
+            .iter()
+            .map(|&(ra, dec)| P2::new(ra, dec))
+            .collect::<Vec<_>>();
+        vertices.reverse();
+        let rect = Region::Rect(Rect::new(10.0, 20.0, 20.0, 40.0).unwrap());
+        assert!((Region::Polygon(Polygon::new(vertices)).area() - rect.area()).abs() < 1e-9);
+        assert_eq!(Region::Polygon(Polygon::new(vec![])).area(), 0.0);
+        assert!(Region::Polygon(Polygon::new(vec![]))
+            .bounding_rects()
+            .is_empty());
+    }
+}
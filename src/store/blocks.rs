@@ -0,0 +1,276 @@
+//! Shards of zstd-compressed blocks of records (`ShardFormat::Zstd`).
+//!
+//! Records are written as in a bincode shard, each prefixed by its length
+//! as a little-endian `u32`, in blocks of `BLOCK_RECORDS` records which are
+//! compressed separately. A table of the blocks follows them: the byte
+//! offset of each block and the position in the shard of its first record,
+//! as little-endian `u64`s. The file ends with the byte offset of the table
+//! and `MAGIC`.
+//!
+//! A query reads the blocks in order, decompressing one at a time, and a
+//! lookup by position decompresses only the block holding the record.
+
+use gaia::record::GaiaRecord;
+use output;
+use output::bincode_sink::BincodeReader;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use store::file::u64_at;
+use store::Error;
+
+/// Number of records in each block but the last of a shard.
+pub const BLOCK_RECORDS: usize = 256;
+
+/// Last bytes of a shard of blocks.
+const MAGIC: &[u8; 8] = b"SQZBLK01";
+
+/// Bytes of each entry of the block table.
+const ENTRY_LEN: u64 = 16;
+
+fn invalid(message: &str) -> Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string()).into()
+}
+
+/// Writer of a shard of blocks.
+pub(crate) struct BlockWriter {
+    writer: BufWriter<File>,
+    /// Records of the block being filled, uncompressed.
+    block: Vec<u8>,
+    in_block: usize,
+    /// Records written, including those of the block being filled.
+    records: u64,
+    /// Bytes written.
+    offset: u64,
+    /// Offset and first record of each block written.
+    table: Vec<(u64, u64)>,
+}
+
+impl BlockWriter {
+    pub(crate) fn new(file: File) -> Self {
+        BlockWriter {
+            writer: BufWriter::new(file),
+            block: Vec::new(),
+            in_block: 0,
+            records: 0,
+            offset: 0,
+            table: Vec::new(),
+        }
+    }
+
+    pub(crate) fn write(&mut self, record: &GaiaRecord) -> Result<(), Error> {
+        let bytes = bincode::serialize(record).map_err(output::Error::from)?;
+        let len = u32::try_from(bytes.len()).map_err(|_| invalid("record too large"))?;
+        self.block.extend_from_slice(&len.to_le_bytes());
+        self.block.extend_from_slice(&bytes);
+        self.in_block += 1;
+        self.records += 1;
+        if self.in_block == BLOCK_RECORDS {
+            self.flush_block()?;
+        }
+        Ok(())
+    }
+
+    fn flush_block(&mut self) -> Result<(), Error> {
+        let compressed = zstd::bulk::compress(&self.block, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+        self.writer.write_all(&compressed)?;
+        self.table
+            .push((self.offset, self.records - self.in_block as u64));
+        self.offset += compressed.len() as u64;
+        self.block.clear();
+        self.in_block = 0;
+        Ok(())
+    }
+
+    /// Write the last block and the block table.
+    pub(crate) fn finish(mut self) -> Result<(), Error> {
+        if self.in_block > 0 {
+            self.flush_block()?;
+        }
+        for (offset, first) in &self.table {
+            self.writer.write_all(&offset.to_le_bytes())?;
+            self.writer.write_all(&first.to_le_bytes())?;
+        }
+        self.writer.write_all(&self.offset.to_le_bytes())?;
+        self.writer.write_all(MAGIC)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Block table of a shard: the offset and first record of each block, and
+/// the offset of the end of the last block.
+struct BlockTable {
+    blocks: Vec<(u64, u64)>,
+    end: u64,
+}
+
+impl BlockTable {
+    fn read<R: Read + Seek>(reader: &mut R) -> Result<Self, Error> {
+        let len = reader.seek(SeekFrom::End(0))?;
+        if len < 16 {
+            return Err(invalid("shard too short for a block table"));
+        }
+        let mut trailer = [0; 16];
+        reader.seek(SeekFrom::Start(len - 16))?;
+        reader.read_exact(&mut trailer)?;
+        if &trailer[8..] != MAGIC {
+            return Err(invalid("not a shard of zstd blocks"));
+        }
+        let end = u64_at(&trailer, 0);
+        let table_len = (len - 16)
+            .checked_sub(end)
+            .filter(|n| n % ENTRY_LEN == 0)
+            .ok_or_else(|| invalid("invalid block table"))?;
+        let mut bytes = vec![0; table_len as usize];
+        reader.seek(SeekFrom::Start(end))?;
+        reader.read_exact(&mut bytes)?;
+        let blocks = bytes
+            .chunks_exact(ENTRY_LEN as usize)
+            .map(|entry| (u64_at(entry, 0), u64_at(entry, 1)))
+            .collect();
+        Ok(BlockTable { blocks, end })
+    }
+
+    /// Decompressed records of a block.
+    fn read_block<R: Read + Seek>(
+        &self,
+        reader: &mut R,
+        block: usize,
+    ) -> Result<BincodeReader<Cursor<Vec<u8>>, GaiaRecord>, Error> {
+        let start = self.blocks[block].0;
+        let end = self.blocks.get(block + 1).map_or(self.end, |b| b.0);
+        let len = end
+            .checked_sub(start)
+            .ok_or_else(|| invalid("invalid block table"))?;
+        let mut compressed = vec![0; len as usize];
+        reader.seek(SeekFrom::Start(start))?;
+        reader.read_exact(&mut compressed)?;
+        let bytes = zstd::stream::decode_all(compressed.as_slice())?;
+        Ok(BincodeReader::new(Cursor::new(bytes)))
+    }
+}
+
+/// Reader of the records of a shard of blocks, in order.
+pub(crate) struct BlockReader {
+    reader: BufReader<File>,
+    table: BlockTable,
+    next: usize,
+    block: Option<BincodeReader<Cursor<Vec<u8>>, GaiaRecord>>,
+}
+
+impl BlockReader {
+    pub(crate) fn new(file: File) -> Result<Self, Error> {
+        let mut reader = BufReader::new(file);
+        let table = BlockTable::read(&mut reader)?;
+        Ok(BlockReader {
+            reader,
+            table,
+            next: 0,
+            block: None,
+        })
+    }
+}
+
+impl Iterator for BlockReader {
+    type Item = Result<GaiaRecord, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(record) = self.block.as_mut().and_then(Iterator::next) {
+                return Some(record.map_err(Error::from));
+            }
+            if self.next == self.table.blocks.len() {
+                return None;
+            }
+            match self.table.read_block(&mut self.reader, self.next) {
+                Ok(block) => {
+                    self.block = Some(block);
+                    self.next += 1;
+                }
+                Err(e) => {
+                    self.block = None;
+                    self.next = self.table.blocks.len();
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+/// Record at a position of a shard of blocks, decompressing only its
+/// block.
+pub(crate) fn read_record(path: &Path, position: u64) -> Result<Option<GaiaRecord>, Error> {
+    let mut file = File::open(path)?;
+    let table = BlockTable::read(&mut file)?;
+    let block = match table
+        .blocks
+        .partition_point(|&(_, first)| first <= position)
+    {
+        0 => return Ok(None),
+        n => n - 1,
+    };
+    let skip = position - table.blocks[block].1;
+    table
+        .read_block(&mut file, block)?
+        .nth(skip as usize)
+        .transpose()
+        .map_err(Error::from)
+}
+
+#[cfg(test)]
+mod test {
+    use gaia::record::test::sample_record;
+    use gaia::record::GaiaRecord;
+    use std::env;
+    use std::fs::{self, File};
+    use store::blocks::{read_record, BlockReader, BlockWriter, BLOCK_RECORDS};
+
+    #[test]
+    fn blocks_round_trip() {
+        let path = env::temp_dir().join(format!("starquad-blocks-{}.bin.zst", std::process::id()));
+        let records = (0..2 * BLOCK_RECORDS as u64 + 10)
+            .map(|i| {
+                let mut record = sample_record();
+                record.source_id = i;
+                record.ra = i as f64 / 10.0;
+                record
+            })
+            .collect::<Vec<GaiaRecord>>();
+        let mut writer = BlockWriter::new(File::create(&path).unwrap());
+        for record in &records {
+            writer.write(record).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let read = BlockReader::new(File::open(&path).unwrap())
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(read, records);
+        // the first, last and a middle record of a block
+        for position in [0, 255, 256, 300, 521] {
+            let record = read_record(&path, position).unwrap();
+            assert_eq!(record.as_ref(), records.get(position as usize));
+        }
+        assert_eq!(read_record(&path, 522).unwrap(), None);
+        // compressed, repeated records take much less than their bincode
+        let raw = records
+            .iter()
+            .map(|r| 4 + bincode::serialized_size(r).unwrap())
+            .sum::<u64>();
+        assert!(fs::metadata(&path).unwrap().len() * 4 < raw);
+
+        // a shard without blocks
+        BlockWriter::new(File::create(&path).unwrap())
+            .finish()
+            .unwrap();
+        let mut empty = BlockReader::new(File::open(&path).unwrap()).unwrap();
+        assert!(empty.next().is_none());
+        assert_eq!(read_record(&path, 0).unwrap(), None);
+        fs::write(&path, b"not a shard").unwrap();
+        assert!(BlockReader::new(File::open(&path).unwrap()).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,122 @@
+//! Bloom filters of the `source_id`s of shards.
+//!
+//! A store built with `StoreBuilder::with_bloom_filters` has a file beside
+//! each shard (`shard-{pixel}.bloom`) holding a Bloom filter of the sources
+//! in the shard, with a false positive rate of about 1%. Lookups without a
+//! source index, and queries filtered on `source_id`, read only the shards
+//! whose filters might hold the sources.
+//!
+//! The file holds the number of hashes as a little-endian `u32`, followed
+//! by the bits of the filter as little-endian `u64` words.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use store::file::u64_at;
+use store::Error;
+
+/// Bits of a filter per source.
+const BITS_PER_SOURCE: u64 = 10;
+
+/// Number of hashes, which minimizes the false positive rate for
+/// `BITS_PER_SOURCE` bits per source.
+const HASHES: u32 = 7;
+
+/// Bloom filter of `source_id`s.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BloomFilter {
+    hashes: u32,
+    words: Vec<u64>,
+}
+
+/// The splitmix64 finalizer, which spreads the bits of consecutive ids.
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+impl BloomFilter {
+    /// Empty filter sized for a number of sources.
+    pub fn new(sources: u64) -> Self {
+        let words = (sources * BITS_PER_SOURCE).div_ceil(64).max(1);
+        BloomFilter {
+            hashes: HASHES,
+            words: vec![0; words as usize],
+        }
+    }
+
+    /// Positions of the bits of a source, by double hashing.
+    fn bits(&self, source_id: u64) -> impl Iterator<Item = (usize, u64)> {
+        let len = self.words.len() as u64 * 64;
+        let h1 = mix(source_id);
+        let h2 = mix(h1) | 1;
+        (0..u64::from(self.hashes)).map(move |i| {
+            let bit = h1.wrapping_add(i.wrapping_mul(h2)) % len;
+            ((bit / 64) as usize, 1 << (bit % 64))
+        })
+    }
+
+    pub fn insert(&mut self, source_id: u64) {
+        for (word, mask) in self.bits(source_id) {
+            self.words[word] |= mask;
+        }
+    }
+
+    /// Whether the filter might hold a source: `false` only if it does not.
+    pub fn contains(&self, source_id: u64) -> bool {
+        self.bits(source_id)
+            .all(|(word, mask)| self.words[word] & mask != 0)
+    }
+
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        let bytes = fs::read(path)?;
+        if bytes.len() < 12 || (bytes.len() - 4) % 8 != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid Bloom filter").into());
+        }
+        let hashes = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        let words = (0..(bytes.len() - 4) / 8)
+            .map(|i| u64_at(&bytes[4..], i))
+            .collect();
+        Ok(BloomFilter { hashes, words })
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(&self.hashes.to_le_bytes())?;
+        for word in &self.words {
+            writer.write_all(&word.to_le_bytes())?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+    use store::bloom::BloomFilter;
+
+    #[test]
+    fn false_positives() {
+        let mut filter = BloomFilter::new(10_000);
+        for id in 0..10_000 {
+            filter.insert(id * 1000);
+        }
+        assert!((0..10_000).all(|id| filter.contains(id * 1000)));
+        let false_positives = (0..10_000)
+            .filter(|id| filter.contains(id * 1000 + 1))
+            .count();
+        assert!(false_positives < 200, "{} false positives", false_positives);
+
+        let path = env::temp_dir().join(format!("starquad-bloom-{}", std::process::id()));
+        filter.write(&path).unwrap();
+        assert_eq!(BloomFilter::read(&path).unwrap(), filter);
+        fs::write(&path, b"short").unwrap();
+        assert!(BloomFilter::read(&path).is_err());
+        fs::remove_file(&path).unwrap();
+        assert!(!BloomFilter::new(0).contains(1));
+    }
+}
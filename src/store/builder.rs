@@ -0,0 +1,388 @@
+#[cfg(feature = "parquet")]
+use catalog::arrow::DEFAULT_BATCH_SIZE;
+#[cfg(feature = "parquet")]
+use catalog::parquet::ParquetWriter;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use gaia::projection::Projection;
+use gaia::record::GaiaRecord;
+use gaia::schema::{self, ColumnType};
+use gaia::stats::CatalogStats;
+use gaia::writer::GaiaWriter;
+use output;
+use output::bincode_sink::BincodeSink;
+use output::RecordSink;
+use sky::healpix;
+use sky::position::SkyPosition;
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+#[cfg(feature = "zstd")]
+use store::blocks::BlockWriter;
+use store::bloom::BloomFilter;
+use store::lookup::{SourceEntry, SourceIndexWriter};
+use store::manifest::{self, Checkpoint, Manifest, ShardFormat, ShardInfo, FORMAT_VERSION};
+use store::{read_shard, Error};
+
+/// Default HEALPix order of the shards of a store, which gives 768 shards.
+pub const DEFAULT_ORDER: u8 = 3;
+
+/// Columns of which each shard records the statistics by default, for
+/// queries filtered on magnitude or parallax.
+pub const DEFAULT_ZONE_MAPS: &[&str] = &["phot_g_mean_mag", "parallax"];
+
+/// Open shard file.
+pub(crate) enum ShardWriter {
+    /// Bincode sink, and the byte offset of the next record.
+    Bincode(BincodeSink<File>, u64),
+    Csv(Box<GaiaWriter<GzEncoder<File>>>),
+    #[cfg(feature = "parquet")]
+    Parquet(Box<ParquetWriter<File>>, Vec<GaiaRecord>),
+    #[cfg(feature = "zstd")]
+    Zstd(BlockWriter),
+}
+
+impl ShardWriter {
+    /// Open a bincode shard to append records to it.
+    fn append(path: &Path) -> Result<Self, Error> {
+        let file = OpenOptions::new().append(true).open(path)?;
+        let offset = file.metadata()?.len();
+        Ok(ShardWriter::Bincode(BincodeSink::new(file), offset))
+    }
+
+    pub(crate) fn create(path: &Path, format: ShardFormat) -> Result<Self, Error> {
+        let file = File::create(path)?;
+        Ok(match format {
+            ShardFormat::Bincode => ShardWriter::Bincode(BincodeSink::new(file), 0),
+            ShardFormat::Csv => ShardWriter::Csv(Box::new(GaiaWriter::new(GzEncoder::new(
+                file,
+                Compression::default(),
+            ))?)),
+            #[cfg(feature = "parquet")]
+            ShardFormat::Parquet => {
+                ShardWriter::Parquet(Box::new(ParquetWriter::new(file)?), Vec::new())
+            }
+            #[cfg(feature = "zstd")]
+            ShardFormat::Zstd => ShardWriter::Zstd(BlockWriter::new(file)),
+        })
+    }
+
+    /// Write a record, returning its byte offset in the shard if the shard
+    /// can be read from an offset.
+    pub(crate) fn write(&mut self, record: &GaiaRecord) -> Result<Option<u64>, Error> {
+        match self {
+            ShardWriter::Bincode(sink, offset) => {
+                sink.write(record)?;
+                let start = *offset;
+                let size = bincode::serialized_size(record).map_err(output::Error::from)?;
+                // each record is prefixed by its length as a `u32`
+                *offset += 4 + size;
+                return Ok(Some(start));
+            }
+            ShardWriter::Csv(writer) => writer.write(record)?,
+            #[cfg(feature = "parquet")]
+            ShardWriter::Parquet(writer, buffer) => {
+                buffer.push(record.clone());
+                if buffer.len() >= DEFAULT_BATCH_SIZE {
+                    writer.write(buffer)?;
+                    buffer.clear();
+                }
+            }
+            #[cfg(feature = "zstd")]
+            ShardWriter::Zstd(writer) => writer.write(record)?,
+        }
+        Ok(None)
+    }
+
+    pub(crate) fn finish(self) -> Result<(), Error> {
+        match self {
+            ShardWriter::Bincode(mut sink, _) => RecordSink::<GaiaRecord>::finish(&mut sink)?,
+            ShardWriter::Csv(writer) => {
+                writer.into_inner()?.finish()?;
+            }
+            #[cfg(feature = "parquet")]
+            ShardWriter::Parquet(mut writer, buffer) => {
+                writer.write(&buffer)?;
+                writer.close()?;
+            }
+            #[cfg(feature = "zstd")]
+            ShardWriter::Zstd(writer) => writer.finish()?,
+        }
+        Ok(())
+    }
+}
+
+/// Writer of a store directory from a stream of records.
+///
+/// Each record is appended to the shard of the HEALPix pixel containing it,
+/// so the records need not be sorted. One file is open for each shard that
+/// has been written to, so high orders may exceed the limit on open files.
+///
+/// With `with_source_index`, the builder also writes a `SourceIndex` of the
+/// records by `source_id`, for `Store::lookup`.
+///
+/// Each shard records the statistics of the columns of `with_zone_maps`
+/// (by default `DEFAULT_ZONE_MAPS`), and with `with_bloom_filters` has a
+/// Bloom filter of its sources, so filtered queries (see `Query::matching`)
+/// skip the shards which cannot match.
+pub struct StoreBuilder {
+    dir: PathBuf,
+    version: u32,
+    requires: Vec<String>,
+    order: u8,
+    format: ShardFormat,
+    columns: Option<Projection>,
+    stats: CatalogStats,
+    shards: BTreeMap<u64, (ShardInfo, ShardWriter)>,
+    source_index: Option<SourceIndexWriter>,
+    zone_maps: Vec<String>,
+    bloom_filters: bool,
+}
+
+impl StoreBuilder {
+    /// Create a builder writing to `dir`, which is created if necessary.
+    pub fn new<P: AsRef<Path>>(dir: P) -> Result<Self, Error> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        Ok(StoreBuilder {
+            dir,
+            version: FORMAT_VERSION,
+            requires: Vec::new(),
+            order: DEFAULT_ORDER,
+            format: ShardFormat::default(),
+            columns: None,
+            stats: CatalogStats::new(),
+            shards: BTreeMap::new(),
+            source_index: None,
+            zone_maps: DEFAULT_ZONE_MAPS.iter().map(|&c| String::from(c)).collect(),
+            bloom_filters: false,
+        })
+    }
+
+    pub fn with_order(mut self, order: u8) -> Self {
+        self.order = order.min(healpix::MAX_ORDER);
+        self
+    }
+
+    /// Write the store in an earlier version of the format (see
+    /// `manifest::FORMAT_VERSION`), for older readers.
+    pub fn with_version(mut self, version: u32) -> Result<Self, Error> {
+        manifest::check_version(version)?;
+        self.version = version;
+        Ok(self)
+    }
+
+    pub fn with_format(mut self, format: ShardFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Only store the columns of a projection, clearing the others. The `ra`
+    /// and `dec` columns are always stored.
+    pub fn with_columns(mut self, columns: Projection) -> Self {
+        self.columns = Some(columns.with_position());
+        self
+    }
+
+    /// Also write an index of the records by `source_id`.
+    pub fn with_source_index(mut self) -> Result<Self, Error> {
+        self.source_index = Some(SourceIndexWriter::open(&self.dir, false)?);
+        Ok(self)
+    }
+
+    /// Record the statistics of other numeric columns in each shard, or of
+    /// none.
+    pub fn with_zone_maps(mut self, columns: &[&str]) -> Result<Self, Error> {
+        for &name in columns {
+            match schema::column(name).map(|c| c.column_type) {
+                Some(ColumnType::Double)
+                | Some(ColumnType::UnsignedByte)
+                | Some(ColumnType::Long) => {}
+                Some(_) => return Err(Error::ZoneMap(format!("{} is not numeric", name))),
+                None => return Err(Error::ZoneMap(format!("unknown column {}", name))),
+            }
+        }
+        self.zone_maps = columns.iter().map(|&c| String::from(c)).collect();
+        Ok(self)
+    }
+
+    /// Also write a Bloom filter of the sources of each shard.
+    pub fn with_bloom_filters(mut self) -> Self {
+        self.bloom_filters = true;
+        self
+    }
+
+    pub fn push(&mut self, record: &GaiaRecord) -> Result<(), Error> {
+        let pixel = healpix::pixel(self.order, &SkyPosition::new(record.ra, record.dec));
+        let (info, writer) = match self.shards.entry(pixel) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                if let (1, Some(capability)) = (self.version, self.format.capability()) {
+                    return Err(Error::UnsupportedFormat(format!(
+                        "version 1 cannot require {}",
+                        capability
+                    )));
+                }
+                let info = ShardInfo::new(pixel).with_zones(&self.zone_maps);
+                let path = self.dir.join(info.file_name(self.format));
+                let writer = ShardWriter::create(&path, self.format)?;
+                entry.insert((info, writer))
+            }
+        };
+        let cleared;
+        let stored = match &self.columns {
+            Some(columns) => {
+                cleared = columns.clear_others(record)?;
+                &cleared
+            }
+            None => record,
+        };
+        let offset = writer.write(stored)?;
+        if let Some(index) = self.source_index.as_mut() {
+            index.push(SourceEntry {
+                source_id: record.source_id,
+                pixel,
+                offset: offset.unwrap_or(info.records),
+            })?;
+        }
+        info.add_record(stored);
+        self.stats.add(record);
+        Ok(())
+    }
+
+    /// Continue a build which was stopped by `finish_with_checkpoint`,
+    /// returning the builder and the position in its inputs from which to
+    /// continue.
+    ///
+    /// Only bincode shards can be appended to, so only builds of bincode
+    /// stores can be resumed.
+    pub fn resume<P: AsRef<Path>>(dir: P) -> Result<(Self, Checkpoint), Error> {
+        let dir = dir.as_ref().to_path_buf();
+        let manifest = Manifest::read(&dir)?;
+        let checkpoint = manifest
+            .checkpoint
+            .ok_or_else(|| Error::NotResumable(String::from("the store is complete")))?;
+        if manifest.format != ShardFormat::Bincode {
+            return Err(Error::NotResumable(format!(
+                "{:?} shards cannot be appended to",
+                manifest.format
+            )));
+        }
+        let columns = match manifest.columns {
+            Some(names) => Some(
+                Projection::new(&names)
+                    .map_err(|e| Error::NotResumable(format!("invalid columns: {}", e)))?,
+            ),
+            None => None,
+        };
+        let mut shards = BTreeMap::new();
+        for info in manifest.shards {
+            let writer = ShardWriter::append(&dir.join(info.file_name(manifest.format)))?;
+            shards.insert(info.pixel, (info, writer));
+        }
+        let source_index = if manifest.source_index {
+            Some(SourceIndexWriter::open(&dir, true)?)
+        } else {
+            None
+        };
+        let builder = StoreBuilder {
+            source_index,
+            zone_maps: manifest.zone_maps,
+            bloom_filters: manifest.bloom_filters,
+            dir,
+            version: manifest.version,
+            requires: manifest.requires,
+            order: manifest.order,
+            format: manifest.format,
+            columns,
+            stats: manifest.stats,
+            shards,
+        };
+        Ok((builder, checkpoint))
+    }
+
+    /// Flush all shards and write the manifest.
+    pub fn finish(self) -> Result<Manifest, Error> {
+        self.write(None)
+    }
+
+    /// Flush all shards and write the manifest of an incomplete store, which
+    /// can be queried as it is or completed with `resume`.
+    pub fn finish_with_checkpoint(self, checkpoint: Checkpoint) -> Result<Manifest, Error> {
+        self.write(Some(checkpoint))
+    }
+
+    fn write(self, checkpoint: Option<Checkpoint>) -> Result<Manifest, Error> {
+        let _span = tracing::info_span!("finish", dir = %self.dir.display()).entered();
+        let start = Instant::now();
+        let mut shards = Vec::new();
+        for (_, (info, writer)) in self.shards {
+            let shard_start = Instant::now();
+            writer.finish()?;
+            tracing::trace!(
+                pixel = info.pixel,
+                records = info.records,
+                elapsed_ms = shard_start.elapsed().as_secs_f64() * 1000.0,
+                "shard written"
+            );
+            if self.bloom_filters {
+                write_bloom_filter(&self.dir, &info, self.format)?;
+            }
+            shards.push(info);
+        }
+        let source_index = self.source_index.is_some();
+        if let Some(index) = self.source_index {
+            match checkpoint {
+                Some(_) => index.checkpoint()?,
+                None => index.finish()?,
+            }
+        }
+        let mut stats = self.stats;
+        // a resumed build adds to the statistics of all columns
+        if let (Some(columns), None) = (&self.columns, &checkpoint) {
+            stats.retain(|s| columns.contains(&s.name));
+        }
+        let mut requires = self.requires;
+        if let Some(capability) = self.format.capability() {
+            if !requires.iter().any(|c| c == capability) {
+                requires.push(String::from(capability));
+            }
+        }
+        let manifest = Manifest {
+            version: self.version,
+            requires,
+            order: self.order,
+            format: self.format,
+            columns: self
+                .columns
+                .map(|c| c.names().into_iter().map(String::from).collect()),
+            stats,
+            shards,
+            source_index,
+            zone_maps: self.zone_maps,
+            bloom_filters: self.bloom_filters,
+            checkpoint,
+        };
+        manifest.write(&self.dir)?;
+        tracing::info!(
+            shards = manifest.shards.len(),
+            records = manifest.records(),
+            complete = manifest.checkpoint.is_none(),
+            elapsed_ms = start.elapsed().as_secs_f64() * 1000.0,
+            "index written"
+        );
+        Ok(manifest)
+    }
+}
+
+/// Write the Bloom filter of the sources of a shard, once the shard is
+/// written.
+fn write_bloom_filter(dir: &Path, info: &ShardInfo, format: ShardFormat) -> Result<(), Error> {
+    let mut filter = BloomFilter::new(info.records);
+    for record in read_shard(&dir.join(info.file_name(format)), format)? {
+        filter.insert(record?.source_id);
+    }
+    filter.write(&dir.join(info.bloom_file_name()))
+}
@@ -0,0 +1,437 @@
+use gaia::record::GaiaRecord;
+use sky::position::SkySource;
+use sky::region::Region;
+use std::collections::HashMap;
+use std::time::Instant;
+use std::vec;
+use store::manifest::ShardInfo;
+use store::{read_shard, Error, Records, Store};
+
+/// Default maximum number of records held by a `CachedStore`.
+pub const DEFAULT_CAPACITY: u64 = 10_000_000;
+
+/// Store which keeps the records of the shards it has read in memory, so
+/// that repeated queries of nearby regions do not read the same shards again.
+///
+/// When the cache holds more than its capacity in records, the least recently
+/// used shards are dropped. The shards needed by the current query are always
+/// kept, so a single large query may exceed the capacity.
+pub struct CachedStore {
+    store: Store,
+    capacity: u64,
+    shards: HashMap<u64, CachedShard>,
+    records: u64,
+    clock: u64,
+}
+
+struct CachedShard {
+    records: Vec<GaiaRecord>,
+    last_used: u64,
+}
+
+/// Shard touched by a query, as reported by `CachedStore::explain`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShardPlan {
+    pub shard: ShardInfo,
+    /// Whether the records of the shard are in memory.
+    pub cached: bool,
+}
+
+impl CachedStore {
+    pub fn new(store: Store) -> Self {
+        CachedStore {
+            store,
+            capacity: DEFAULT_CAPACITY,
+            shards: HashMap::new(),
+            records: 0,
+            clock: 0,
+        }
+    }
+
+    pub fn with_capacity(mut self, capacity: u64) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    pub fn store(&self) -> &Store {
+        &self.store
+    }
+
+    /// Number of shards in memory.
+    pub fn cached_shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Number of records in memory.
+    pub fn cached_records(&self) -> u64 {
+        self.records
+    }
+
+    /// Drop all shards from memory.
+    pub fn clear(&mut self) {
+        self.shards.clear();
+        self.records = 0;
+    }
+
+    /// Shards which a query of a region would read, without reading them.
+    pub fn explain(&self, region: &Region) -> Vec<ShardPlan> {
+        self.store
+            .shards(region)
+            .into_iter()
+            .map(|shard| ShardPlan {
+                shard: shard.clone(),
+                cached: self.shards.contains_key(&shard.pixel),
+            })
+            .collect()
+    }
+
+    /// Records whose positions lie in a region, reading the shards which are
+    /// not already in memory, with the changes in the log of the store.
+    /// Every shard read is kept until the query is done, so results which
+    /// may not fit in memory should be read with `stream`.
+    pub fn query(&mut self, region: &Region) -> Result<Vec<&GaiaRecord>, Error> {
+        let shards = self
+            .store
+            .shards(region)
+            .into_iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        self.clock += 1;
+        for shard in &shards {
+            if let Some(cached) = self.shards.get_mut(&shard.pixel) {
+                cached.last_used = self.clock;
+                continue;
+            }
+            let path = self
+                .store
+                .dir
+                .join(shard.file_name(self.store.manifest.format));
+            let start = Instant::now();
+            let records =
+                read_shard(&path, self.store.manifest.format)?.collect::<Result<Vec<_>, _>>()?;
+            tracing::debug!(
+                pixel = shard.pixel,
+                records = records.len(),
+                elapsed_ms = start.elapsed().as_secs_f64() * 1000.0,
+                "shard cached"
+            );
+            self.records += records.len() as u64;
+            self.shards.insert(
+                shard.pixel,
+                CachedShard {
+                    records,
+                    last_used: self.clock,
+                },
+            );
+        }
+        self.evict();
+        let cached = &self.shards;
+        let pending = &self.store.pending;
+        Ok(shards
+            .iter()
+            .filter_map(move |shard| cached.get(&shard.pixel))
+            .flat_map(|shard| &shard.records)
+            .filter(|record| !pending.changed(record.source_id))
+            .chain(pending.records())
+            .filter(|record| region.contains(&record.position()))
+            .collect())
+    }
+
+    /// Stream of the records whose positions lie in a region, which reads
+    /// each shard as it is reached rather than collecting the results.
+    ///
+    /// Shards are kept in memory as they are read only if they fit within
+    /// the capacity, after dropping the least recently used shards which
+    /// the stream has not read, and the others are read from their files
+    /// without being kept. Unlike `query`, a stream holds at most the
+    /// capacity of the cache in memory, however many records it returns.
+    pub fn stream(&mut self, region: &Region) -> CachedQuery<'_> {
+        let shards = self
+            .store
+            .shards(region)
+            .into_iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        self.clock += 1;
+        CachedQuery {
+            cache: self,
+            region: region.clone(),
+            shards: shards.into_iter(),
+            current: None,
+            added: None,
+        }
+    }
+
+    /// Drop least recently used shards, other than those used by the current
+    /// query, until the cache is within its capacity.
+    fn evict(&mut self) {
+        self.make_room(0);
+    }
+
+    /// Drop least recently used shards, other than those used by the
+    /// current query, until a number of records more fits within the
+    /// capacity. Returns whether they fit.
+    fn make_room(&mut self, records: u64) -> bool {
+        if self.records + records <= self.capacity {
+            return true;
+        }
+        let mut unused = self
+            .shards
+            .iter()
+            .filter(|(_, s)| s.last_used < self.clock)
+            .map(|(&pixel, s)| (s.last_used, pixel))
+            .collect::<Vec<_>>();
+        unused.sort();
+        for (_, pixel) in unused {
+            if self.records + records <= self.capacity {
+                break;
+            }
+            if let Some(shard) = self.shards.remove(&pixel) {
+                self.records -= shard.records.len() as u64;
+            }
+        }
+        self.records + records <= self.capacity
+    }
+}
+
+/// Shard being read by a `CachedQuery`.
+enum Scan {
+    /// A shard in memory, and the position of its next record.
+    Cached { pixel: u64, next: usize },
+    /// A shard read from its file, and its records so far if it is to be
+    /// kept in memory.
+    File {
+        pixel: u64,
+        records: Records,
+        kept: Option<Vec<GaiaRecord>>,
+        start: Instant,
+    },
+}
+
+/// Iterator over the records of a `CachedStore` in a region (see
+/// `CachedStore::stream`).
+///
+/// The records of the shards which are not replaced or removed by the log
+/// of the store come first, followed by those pushed to the log.
+pub struct CachedQuery<'a> {
+    cache: &'a mut CachedStore,
+    region: Region,
+    shards: vec::IntoIter<ShardInfo>,
+    current: Option<Scan>,
+    /// Records of the log in the region, once the shards have been read.
+    added: Option<vec::IntoIter<GaiaRecord>>,
+}
+
+impl<'a> CachedQuery<'a> {
+    /// Start reading a shard, from memory if it is cached.
+    fn open(&mut self, shard: &ShardInfo) -> Result<Scan, Error> {
+        let cache = &mut *self.cache;
+        if let Some(cached) = cache.shards.get_mut(&shard.pixel) {
+            cached.last_used = cache.clock;
+            return Ok(Scan::Cached {
+                pixel: shard.pixel,
+                next: 0,
+            });
+        }
+        let format = cache.store.manifest.format;
+        let records = read_shard(&cache.store.dir.join(shard.file_name(format)), format)?;
+        let kept = if cache.make_room(shard.records) {
+            Some(Vec::with_capacity(shard.records as usize))
+        } else {
+            None
+        };
+        Ok(Scan::File {
+            pixel: shard.pixel,
+            records,
+            kept,
+            start: Instant::now(),
+        })
+    }
+}
+
+impl<'a> Iterator for CachedQuery<'a> {
+    type Item = Result<GaiaRecord, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let cache = &mut *self.cache;
+            let pending = &cache.store.pending;
+            let region = &self.region;
+            let wanted = |record: &GaiaRecord| {
+                !pending.changed(record.source_id) && region.contains(&record.position())
+            };
+            match self.current.as_mut() {
+                Some(Scan::Cached { pixel, next }) => {
+                    let records = &cache.shards[pixel].records;
+                    while let Some(record) = records.get(*next) {
+                        *next += 1;
+                        if wanted(record) {
+                            return Some(Ok(record.clone()));
+                        }
+                    }
+                    self.current = None;
+                }
+                Some(Scan::File {
+                    pixel,
+                    records,
+                    kept,
+                    start,
+                }) => {
+                    for record in records.by_ref() {
+                        let record = match record {
+                            Ok(record) => record,
+                            Err(e) => {
+                                self.current = None;
+                                return Some(Err(e));
+                            }
+                        };
+                        if let Some(kept) = kept.as_mut() {
+                            kept.push(record.clone());
+                        }
+                        if wanted(&record) {
+                            return Some(Ok(record));
+                        }
+                    }
+                    if let Some(records) = kept.take() {
+                        tracing::debug!(
+                            pixel = *pixel,
+                            records = records.len(),
+                            elapsed_ms = start.elapsed().as_secs_f64() * 1000.0,
+                            "shard cached"
+                        );
+                        cache.records += records.len() as u64;
+                        let last_used = cache.clock;
+                        cache
+                            .shards
+                            .insert(*pixel, CachedShard { records, last_used });
+                    }
+                    self.current = None;
+                }
+                None => match self.shards.next() {
+                    Some(shard) => match self.open(&shard) {
+                        Ok(scan) => self.current = Some(scan),
+                        Err(e) => return Some(Err(e)),
+                    },
+                    None => {
+                        let added = self.added.get_or_insert_with(|| {
+                            pending
+                                .records()
+                                .filter(|record| region.contains(&record.position()))
+                                .cloned()
+                                .collect::<Vec<_>>()
+                                .into_iter()
+                        });
+                        return added.next().map(Ok);
+                    }
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use gaia::record::test::sample_record;
+    use sky::position::SkyPosition;
+    use sky::region::Region;
+    use std::env;
+    use std::fs;
+    use store::builder::StoreBuilder;
+    use store::cache::CachedStore;
+    use store::Store;
+
+    #[test]
+    fn queries_use_cache() {
+        let dir = env::temp_dir().join(format!("starquad-cache-test-{}", std::process::id()));
+        let mut builder = StoreBuilder::new(&dir).unwrap().with_order(1);
+        for i in 0..360 {
+            let mut record = sample_record();
+            record.source_id = i;
+            record.ra = i as f64;
+            record.dec = 0.0;
+            builder.push(&record).unwrap();
+        }
+        builder.finish().unwrap();
+
+        let mut store = CachedStore::new(Store::open(&dir).unwrap()).with_capacity(1);
+        let first = Region::cone(SkyPosition::new(10.0, 0.0), 2.5);
+        assert!(store.explain(&first).iter().all(|p| !p.cached));
+        let ids = store
+            .query(&first)
+            .unwrap()
+            .iter()
+            .map(|r| r.source_id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, (8..13).collect::<Vec<_>>());
+        // the shards of the current query are kept beyond the capacity
+        assert!(store.explain(&first).iter().all(|p| p.cached));
+        assert!(store.cached_records() > 1);
+
+        // and dropped by the next query
+        let second = Region::cone(SkyPosition::new(200.0, 0.0), 1.0);
+        assert_eq!(store.query(&second).unwrap().len(), 3);
+        assert!(store.explain(&first).iter().all(|p| !p.cached));
+
+        // the files are no longer needed once the shards are in memory
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(store.query(&second).unwrap().len(), 3);
+        assert!(store.query(&first).is_err());
+        store.clear();
+        assert_eq!(store.cached_shards(), 0);
+    }
+
+    #[test]
+    fn streams_within_capacity() {
+        let dir = env::temp_dir().join(format!("starquad-cache-stream-{}", std::process::id()));
+        let mut builder = StoreBuilder::new(&dir).unwrap().with_order(1);
+        for i in 0..360 {
+            let mut record = sample_record();
+            record.source_id = i;
+            record.ra = i as f64;
+            record.dec = 0.0;
+            builder.push(&record).unwrap();
+        }
+        builder.finish().unwrap();
+        let mut store = Store::open(&dir).unwrap();
+        let mut pushed = sample_record();
+        pushed.source_id = 1000;
+        pushed.ra = 100.5;
+        store.push(&pushed).unwrap();
+        store.remove(100).unwrap();
+
+        let all = Region::sky_box(0.0, 360.0, -1.0, 1.0).unwrap();
+        let ids = |store: &mut CachedStore, region: &Region| {
+            store
+                .stream(region)
+                .map(|r| r.unwrap().source_id)
+                .collect::<Vec<_>>()
+        };
+        // nothing is kept by a stream larger than the cache
+        let mut small = CachedStore::new(Store::open(&dir).unwrap()).with_capacity(100);
+        let streamed = ids(&mut small, &all);
+        let mut expected = (0..360).filter(|&i| i != 100).collect::<Vec<_>>();
+        expected.push(1000);
+        assert_eq!(sorted(streamed.clone()), sorted(expected));
+        assert!(small.cached_records() <= 100);
+        let queried = small
+            .query(&all)
+            .unwrap()
+            .iter()
+            .map(|r| r.source_id)
+            .collect::<Vec<_>>();
+        assert_eq!(queried, streamed);
+
+        // and a stream which fits is then read from memory
+        let mut large = CachedStore::new(Store::open(&dir).unwrap());
+        let cone = Region::cone(SkyPosition::new(100.0, 0.0), 2.5);
+        assert_eq!(ids(&mut large, &cone), vec![98, 99, 101, 102, 1000]);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(ids(&mut large, &cone), vec![98, 99, 101, 102, 1000]);
+        assert!(large.stream(&all).any(|r| r.is_err()));
+    }
+
+    fn sorted(mut ids: Vec<u64>) -> Vec<u64> {
+        ids.sort();
+        ids
+    }
+}
@@ -0,0 +1,885 @@
+//! Single-file catalogs, for sharing a subset of the catalog as one file.
+//!
+//! A starquad file holds the selected columns of a set of records, an index
+//! of them by HEALPix pixel, their statistics and a description, and is
+//! queried in place through a memory map by `StarFile::open`, without a
+//! server or an index directory.
+//!
+//! The file starts with a header of the magic bytes `STARQUAD`, the format
+//! version and the offset and length of the metadata, which is JSON at the
+//! end of the file. Between them are the sections the metadata points to,
+//! each aligned to 8 bytes:
+//!
+//! - the cells: for each non-empty pixel, in order, its number, first row,
+//!   number of rows and the bounds of their positions, as three `u64` and
+//!   four `f64`;
+//! - for each column, its values: `f64`, `i64` or `u8` (booleans as `0` or
+//!   `1`), or for text columns the UTF-8 text of all rows and the `u64`
+//!   offsets of each row's text in it, followed by a final offset;
+//! - for each nullable column, a bitmap of the rows holding a value, as
+//!   `u64` words.
+//!
+//! Numbers are little-endian, and the rows are sorted by pixel, so that
+//! each cell's rows are contiguous.
+
+use csv::StringRecord;
+use gaia::columnar::{ColumnValues, ColumnarCatalog, TypedColumn};
+use gaia::filter::Filter;
+use gaia::projection::Projection;
+use gaia::record::GaiaRecord;
+use gaia::schema::{self, Column, ColumnType};
+use gaia::stats::CatalogStats;
+use geom::p2::P2;
+use geom::rect::Rect;
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use sky::healpix;
+use sky::position::SkyPosition;
+use sky::region::Region;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use store::builder::DEFAULT_ORDER;
+use store::Error;
+
+/// First bytes of a starquad file.
+pub const MAGIC: &[u8; 8] = b"STARQUAD";
+
+/// Version of the file format written by this release.
+pub const FILE_VERSION: u32 = 1;
+
+/// Length of the header: the magic bytes, the version, a reserved word and
+/// the offset and length of the metadata.
+const HEADER_LEN: usize = 32;
+
+/// Length of a cell of the index.
+const CELL_LEN: usize = 56;
+
+/// Extent of a section of a file, in bytes.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+struct Section {
+    offset: u64,
+    len: u64,
+}
+
+/// Sections holding a column.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+struct ColumnSections {
+    name: String,
+    values: Section,
+    /// Offsets of the text of each row, for text columns.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    offsets: Option<Section>,
+    /// Rows holding a value, for nullable columns.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    validity: Option<Section>,
+}
+
+/// Description of a starquad file.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Metadata {
+    pub version: u32,
+    pub rows: u64,
+    /// HEALPix order of the cells.
+    pub order: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Statistics of the stored columns.
+    pub stats: CatalogStats,
+    cells: Section,
+    columns: Vec<ColumnSections>,
+}
+
+impl Metadata {
+    /// Names of the stored columns, in the order they were selected.
+    pub fn columns(&self) -> Vec<&str> {
+        self.columns.iter().map(|c| c.name.as_str()).collect()
+    }
+}
+
+/// Rows of a file in one HEALPix pixel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cell {
+    pub pixel: u64,
+    pub start: u64,
+    pub rows: u64,
+    pub min_ra: f64,
+    pub max_ra: f64,
+    pub min_dec: f64,
+    pub max_dec: f64,
+}
+
+impl Cell {
+    /// Smallest rectangle containing the positions of the cell's rows.
+    pub fn bounds(&self) -> Option<Rect<f64>> {
+        Rect::bounding(&[
+            P2::new(self.min_ra, self.min_dec),
+            P2::new(self.max_ra, self.max_dec),
+        ])
+    }
+
+    fn rows(&self) -> Range<u64> {
+        self.start..self.start + self.rows
+    }
+
+    fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        for word in [self.pixel, self.start, self.rows] {
+            out.write_all(&word.to_le_bytes())?;
+        }
+        for value in [self.min_ra, self.max_ra, self.min_dec, self.max_dec] {
+            out.write_all(&value.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn read(bytes: &[u8]) -> Self {
+        let word = |i: usize| u64_at(bytes, i);
+        let value = |i: usize| f64::from_bits(u64_at(bytes, i));
+        Cell {
+            pixel: word(0),
+            start: word(1),
+            rows: word(2),
+            min_ra: value(3),
+            max_ra: value(4),
+            min_dec: value(5),
+            max_dec: value(6),
+        }
+    }
+}
+
+/// The `i`th little-endian `u64` of `bytes`, which must hold it.
+pub(crate) fn u64_at(bytes: &[u8], i: usize) -> u64 {
+    let mut word = [0; 8];
+    word.copy_from_slice(&bytes[8 * i..8 * i + 8]);
+    u64::from_le_bytes(word)
+}
+
+/// Writer of a starquad file from a stream of records.
+///
+/// The records are held in memory, as typed columns, until `finish` sorts
+/// them by pixel and writes the file, so a file is limited to the records
+/// which fit in memory.
+///
+/// ```
+/// # use starquad::gaia::projection::Projection;
+/// # use starquad::sky::position::SkyPosition;
+/// # use starquad::sky::region::Region;
+/// # use starquad::store::file::{StarFile, StarFileBuilder};
+/// # let path = std::env::temp_dir().join("starquad-file-doctest.starquad");
+/// # let records: Vec<starquad::gaia::record::GaiaRecord> = Vec::new();
+/// let mut builder = StarFileBuilder::new(&path)
+///     .with_columns(Projection::new(&["source_id", "phot_g_mean_mag"]).unwrap())
+///     .with_description("Bright stars of the Pleiades");
+/// for record in &records {
+///     builder.push(record).unwrap();
+/// }
+/// builder.finish().unwrap();
+///
+/// let file = StarFile::open(&path).unwrap();
+/// let region = Region::cone(SkyPosition::new(56.75, 24.12), 1.0);
+/// let found = file.query(&region).collect::<Result<Vec<_>, _>>().unwrap();
+/// # assert!(found.is_empty());
+/// ```
+pub struct StarFileBuilder {
+    path: PathBuf,
+    order: u8,
+    columns: Projection,
+    description: Option<String>,
+    catalog: Option<ColumnarCatalog>,
+    stats: CatalogStats,
+}
+
+impl StarFileBuilder {
+    /// Create a builder writing to `path`, storing every column.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        StarFileBuilder {
+            path: path.as_ref().to_path_buf(),
+            order: DEFAULT_ORDER,
+            columns: Projection::all(),
+            description: None,
+            catalog: None,
+            stats: CatalogStats::new(),
+        }
+    }
+
+    pub fn with_order(mut self, order: u8) -> Self {
+        self.order = order.min(healpix::MAX_ORDER);
+        self
+    }
+
+    /// Only store the columns of a projection. The `ra` and `dec` columns
+    /// are always stored. Records pushed before are dropped.
+    pub fn with_columns(mut self, columns: Projection) -> Self {
+        self.columns = columns.with_position();
+        self.catalog = None;
+        self.stats = CatalogStats::new();
+        self
+    }
+
+    pub fn with_description(mut self, description: &str) -> Self {
+        self.description = Some(String::from(description));
+        self
+    }
+
+    /// Number of records pushed.
+    pub fn len(&self) -> usize {
+        self.catalog.as_ref().map_or(0, ColumnarCatalog::len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn push(&mut self, record: &GaiaRecord) -> Result<(), Error> {
+        self.push_fields(&schema::string_record(record))
+    }
+
+    /// Add a record given the text fields of all Gaia columns, in the order
+    /// of `schema::COLUMNS`.
+    pub fn push_fields(&mut self, fields: &StringRecord) -> Result<(), Error> {
+        let catalog = match &mut self.catalog {
+            Some(catalog) => catalog,
+            None => self
+                .catalog
+                .insert(ColumnarCatalog::new(&self.columns.names())?),
+        };
+        catalog.push_fields(fields)?;
+        self.stats.add_fields(fields);
+        Ok(())
+    }
+
+    /// Write the file, returning its metadata.
+    pub fn finish(mut self) -> Result<Metadata, Error> {
+        let catalog = match self.catalog.take() {
+            Some(catalog) => catalog,
+            None => ColumnarCatalog::new(&self.columns.names())?,
+        };
+        let mut rows = (0..catalog.len())
+            .map(|row| (healpix::pixel(self.order, &catalog.position(row)), row))
+            .collect::<Vec<_>>();
+        rows.sort_unstable();
+        let sorted = rows.iter().map(|&(_, row)| row).collect::<Vec<_>>();
+
+        let mut out = Output::new(BufWriter::new(File::create(&self.path)?));
+        out.write_all(&[0; HEADER_LEN])?;
+        let cell_start = out.offset;
+        for cell in cells(&catalog, &rows) {
+            cell.write(&mut out)?;
+        }
+        let cells = out.section(cell_start)?;
+        let columns = catalog
+            .column_names()
+            .iter()
+            .filter_map(|&name| catalog.column(name))
+            .map(|column| write_column(&mut out, column, &sorted))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut stats = self.stats;
+        stats.retain(|s| columns.iter().any(|c| c.name == s.name));
+        let metadata = Metadata {
+            version: FILE_VERSION,
+            rows: catalog.len() as u64,
+            order: self.order,
+            description: self.description,
+            stats,
+            cells,
+            columns,
+        };
+        let start = out.offset;
+        serde_json::to_writer(&mut out, &metadata).map_err(io::Error::from)?;
+        let json = out.section(start)?;
+
+        let mut file = out.inner;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(MAGIC)?;
+        file.write_all(&FILE_VERSION.to_le_bytes())?;
+        file.write_all(&0u32.to_le_bytes())?;
+        file.write_all(&json.offset.to_le_bytes())?;
+        file.write_all(&json.len.to_le_bytes())?;
+        file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        Ok(metadata)
+    }
+}
+
+/// Cells of rows sorted by pixel, given as `(pixel, row)`.
+fn cells(catalog: &ColumnarCatalog, rows: &[(u64, usize)]) -> Vec<Cell> {
+    let mut cells: Vec<Cell> = Vec::new();
+    for (i, &(pixel, row)) in rows.iter().enumerate() {
+        let position = catalog.position(row);
+        match cells.last_mut() {
+            Some(cell) if cell.pixel == pixel => {
+                cell.rows += 1;
+                cell.min_ra = cell.min_ra.min(position.ra);
+                cell.max_ra = cell.max_ra.max(position.ra);
+                cell.min_dec = cell.min_dec.min(position.dec);
+                cell.max_dec = cell.max_dec.max(position.dec);
+            }
+            _ => cells.push(Cell {
+                pixel,
+                start: i as u64,
+                rows: 1,
+                min_ra: position.ra,
+                max_ra: position.ra,
+                min_dec: position.dec,
+                max_dec: position.dec,
+            }),
+        }
+    }
+    cells
+}
+
+/// Writer counting the bytes written, so that sections can record their
+/// offsets.
+struct Output<W> {
+    inner: W,
+    offset: u64,
+}
+
+impl<W: Write> Output<W> {
+    fn new(inner: W) -> Self {
+        Output { inner, offset: 0 }
+    }
+
+    /// The section from `start` to the current offset, after which the
+    /// output is padded to a multiple of 8 bytes.
+    fn section(&mut self, start: u64) -> io::Result<Section> {
+        let section = Section {
+            offset: start,
+            len: self.offset - start,
+        };
+        let padding = (8 - self.offset % 8) % 8;
+        self.write_all(&[0; 8][..padding as usize])?;
+        Ok(section)
+    }
+}
+
+impl<W: Write> Write for Output<W> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(bytes)?;
+        self.offset += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Write the values of a column, in the order of `rows`.
+fn write_column<W: Write>(
+    out: &mut Output<W>,
+    column: &TypedColumn,
+    rows: &[usize],
+) -> Result<ColumnSections, Error> {
+    let start = out.offset;
+    let mut offsets = None;
+    match column.values() {
+        ColumnValues::Boolean(values) => {
+            let bytes = rows.iter().map(|&row| u8::from(values[row]));
+            out.write_all(&bytes.collect::<Vec<_>>())?;
+        }
+        ColumnValues::UnsignedByte(values) => {
+            out.write_all(&rows.iter().map(|&row| values[row]).collect::<Vec<_>>())?;
+        }
+        ColumnValues::Long(values) => {
+            for &row in rows {
+                out.write_all(&values[row].to_le_bytes())?;
+            }
+        }
+        ColumnValues::Double(values) => {
+            for &row in rows {
+                out.write_all(&values[row].to_le_bytes())?;
+            }
+        }
+        ColumnValues::Text(values) => {
+            let mut ends = vec![0u64];
+            for &row in rows {
+                out.write_all(values[row].as_bytes())?;
+                ends.push(out.offset - start);
+            }
+            let text = out.section(start)?;
+            let start = out.offset;
+            for end in ends {
+                out.write_all(&end.to_le_bytes())?;
+            }
+            offsets = Some((text, out.section(start)?));
+        }
+    }
+    let (values, offsets) = match offsets {
+        Some((text, offsets)) => (text, Some(offsets)),
+        None => (out.section(start)?, None),
+    };
+    let validity = match column.validity() {
+        Some(validity) => {
+            let start = out.offset;
+            let mut words = vec![0u64; rows.len().div_ceil(64)];
+            for (i, &row) in rows.iter().enumerate() {
+                if validity.is_valid(row) {
+                    words[i / 64] |= 1 << (i % 64);
+                }
+            }
+            for word in words {
+                out.write_all(&word.to_le_bytes())?;
+            }
+            Some(out.section(start)?)
+        }
+        None => None,
+    };
+    Ok(ColumnSections {
+        name: String::from(column.name()),
+        values,
+        offsets,
+        validity,
+    })
+}
+
+/// A stored column, with the bytes of its sections in the file.
+struct StoredColumn {
+    column: &'static Column,
+    values: Range<usize>,
+    offsets: Option<Range<usize>>,
+    validity: Option<Range<usize>>,
+}
+
+/// A starquad file, opened for queries.
+///
+/// The file is memory-mapped, so opening it reads only its metadata and
+/// index, and queries read only the pages of the rows in the cells they
+/// touch. It must not be modified while it is open.
+///
+/// Records returned by queries hold the stored columns of the file; the
+/// other columns are cleared (see `schema::Column::cleared`).
+pub struct StarFile {
+    map: Mmap,
+    metadata: Metadata,
+    cells: Vec<Cell>,
+    columns: Vec<StoredColumn>,
+    /// Positions of the columns of `schema::COLUMNS` in `columns`.
+    schema: Vec<Option<usize>>,
+    /// Positions of `ra` and `dec` in `columns`.
+    ra: usize,
+    dec: usize,
+}
+
+impl StarFile {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = File::open(path)?;
+        // Safety: the map is only valid while the file is unchanged, which
+        // is a documented requirement of `StarFile`; every section is
+        // checked to lie within the map before it is read.
+        let map = unsafe { Mmap::map(&file)? };
+        let invalid = |reason: &str| Error::InvalidFile(String::from(reason));
+        if map.len() < HEADER_LEN || &map[..8] != MAGIC {
+            return Err(invalid("not a starquad file"));
+        }
+        let version = u32::from_le_bytes([map[8], map[9], map[10], map[11]]);
+        if version > FILE_VERSION {
+            return Err(Error::UnsupportedFormat(format!(
+                "file version {} (this release reads up to {})",
+                version, FILE_VERSION
+            )));
+        }
+        let json = Section {
+            offset: u64_at(&map, 2),
+            len: u64_at(&map, 3),
+        };
+        let metadata: Metadata = serde_json::from_slice(&map[bytes(&map, json)?])
+            .map_err(|e| invalid(&format!("invalid metadata: {}", e)))?;
+        let rows = metadata.rows;
+
+        let cell_bytes = &map[bytes(&map, metadata.cells)?];
+        if cell_bytes.len() % CELL_LEN != 0 {
+            return Err(invalid("truncated index"));
+        }
+        let cells = cell_bytes
+            .chunks(CELL_LEN)
+            .map(Cell::read)
+            .collect::<Vec<_>>();
+        if cells
+            .iter()
+            .any(|c| c.start.checked_add(c.rows).is_none_or(|end| end > rows))
+        {
+            return Err(invalid("index refers to missing rows"));
+        }
+
+        let mut columns = Vec::new();
+        let mut schema = vec![None; schema::COLUMNS.len()];
+        for sections in &metadata.columns {
+            let index = schema::COLUMNS
+                .iter()
+                .position(|c| c.name == sections.name)
+                .ok_or_else(|| invalid(&format!("unknown column {}", sections.name)))?;
+            let column = &schema::COLUMNS[index];
+            let width = match column.column_type {
+                ColumnType::Boolean | ColumnType::UnsignedByte => Some(1),
+                ColumnType::Long | ColumnType::Double => Some(8),
+                ColumnType::Text => None,
+            };
+            let values = bytes(&map, sections.values)?;
+            let offsets = sections.offsets.map(|s| bytes(&map, s)).transpose()?;
+            let validity = sections.validity.map(|s| bytes(&map, s)).transpose()?;
+            let expected = |range: &Option<Range<usize>>, len: u64| {
+                range.as_ref().map(|r| r.len() as u64) == Some(len)
+            };
+            let valid = match width {
+                Some(width) => values.len() as u64 == width * rows && offsets.is_none(),
+                None => expected(&offsets, 8 * (rows + 1)),
+            } && (!column.nullable || expected(&validity, 8 * rows.div_ceil(64)));
+            if !valid || schema[index].is_some() {
+                return Err(invalid(&format!("invalid column {}", column.name)));
+            }
+            schema[index] = Some(columns.len());
+            columns.push(StoredColumn {
+                column,
+                values,
+                offsets,
+                validity: validity.filter(|_| column.nullable),
+            });
+        }
+        let stored = |name: &str| {
+            let index = schema::COLUMNS.iter().position(|c| c.name == name);
+            index
+                .and_then(|i| schema[i])
+                .ok_or_else(|| invalid("positions are not stored"))
+        };
+        let (ra, dec) = (stored("ra")?, stored("dec")?);
+        Ok(StarFile {
+            map,
+            metadata,
+            cells,
+            columns,
+            schema,
+            ra,
+            dec,
+        })
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Statistics of the stored columns.
+    pub fn stats(&self) -> &CatalogStats {
+        &self.metadata.stats
+    }
+
+    pub fn cells(&self) -> &[Cell] {
+        &self.cells
+    }
+
+    /// Number of records.
+    pub fn len(&self) -> u64 {
+        self.metadata.rows
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.metadata.rows == 0
+    }
+
+    /// Whether a column is stored.
+    pub fn contains(&self, name: &str) -> bool {
+        self.schema
+            .iter()
+            .zip(schema::COLUMNS)
+            .any(|(stored, c)| stored.is_some() && c.name == name)
+    }
+
+    /// Records in a region.
+    pub fn query(&self, region: &Region) -> FileQuery<'_> {
+        let cells = self
+            .cells
+            .iter()
+            .filter(|cell| cell.bounds().is_some_and(|b| region.intersects(&b)))
+            .map(Cell::rows)
+            .collect();
+        FileQuery::new(self, cells, Some(region.clone()))
+    }
+
+    /// Every record, in the order of the cells.
+    pub fn records(&self) -> FileQuery<'_> {
+        FileQuery::new(self, self.cells.iter().map(Cell::rows).collect(), None)
+    }
+
+    fn double(&self, column: &StoredColumn, row: usize) -> f64 {
+        f64::from_bits(u64_at(&self.map[column.values.clone()], row))
+    }
+
+    fn position(&self, row: usize) -> SkyPosition {
+        SkyPosition::new(
+            self.double(&self.columns[self.ra], row),
+            self.double(&self.columns[self.dec], row),
+        )
+    }
+
+    /// Text fields of all Gaia columns of a row, with the columns which are
+    /// not stored cleared.
+    fn fields(&self, row: usize) -> Result<StringRecord, Error> {
+        let mut fields = StringRecord::new();
+        for (column, stored) in schema::COLUMNS.iter().zip(&self.schema) {
+            match stored {
+                Some(i) => fields.push_field(&self.text(&self.columns[*i], row)?),
+                None => fields.push_field(column.cleared()),
+            }
+        }
+        Ok(fields)
+    }
+
+    fn text(&self, column: &StoredColumn, row: usize) -> Result<String, Error> {
+        if let Some(validity) = &column.validity {
+            if u64_at(&self.map[validity.clone()], row / 64) & (1 << (row % 64)) == 0 {
+                return Ok(String::new());
+            }
+        }
+        let values = &self.map[column.values.clone()];
+        Ok(match column.column.column_type {
+            ColumnType::Boolean => (values[row] != 0).to_string(),
+            ColumnType::UnsignedByte => values[row].to_string(),
+            ColumnType::Long => (u64_at(values, row) as i64).to_string(),
+            ColumnType::Double => self.double(column, row).to_string(),
+            ColumnType::Text => {
+                let offsets = &self.map[column.offsets.clone().unwrap_or_default()];
+                let (start, end) = (u64_at(offsets, row), u64_at(offsets, row + 1));
+                values
+                    .get(start as usize..end as usize)
+                    .and_then(|text| std::str::from_utf8(text).ok())
+                    .map(String::from)
+                    .ok_or_else(|| {
+                        Error::InvalidFile(format!("invalid text in column {}", column.column.name))
+                    })?
+            }
+        })
+    }
+}
+
+/// Bytes of a section, if it lies within `map`.
+fn bytes(map: &[u8], section: Section) -> Result<Range<usize>, Error> {
+    let end = section.offset.checked_add(section.len);
+    match end {
+        Some(end) if end <= map.len() as u64 => Ok(section.offset as usize..end as usize),
+        _ => Err(Error::InvalidFile(String::from(
+            "section beyond the end of the file",
+        ))),
+    }
+}
+
+/// Iterator over the records of a `StarFile` in a region.
+pub struct FileQuery<'a> {
+    file: &'a StarFile,
+    /// Rows of the cells still to be read, in reverse order.
+    cells: Vec<Range<u64>>,
+    rows: Range<u64>,
+    region: Option<Region>,
+    filter: Option<Filter>,
+    headers: StringRecord,
+}
+
+impl<'a> FileQuery<'a> {
+    fn new(file: &'a StarFile, mut cells: Vec<Range<u64>>, region: Option<Region>) -> Self {
+        cells.reverse();
+        FileQuery {
+            file,
+            cells,
+            rows: 0..0,
+            region,
+            filter: None,
+            headers: schema::headers(),
+        }
+    }
+
+    /// Only return the records matching a filter, whose columns must be
+    /// stored in the file.
+    pub fn matching(mut self, filter: &Filter) -> Result<Self, Error> {
+        if let Some(name) = filter
+            .columns()
+            .into_iter()
+            .find(|c| !self.file.contains(c))
+        {
+            return Err(Error::MissingColumn(String::from(name)));
+        }
+        if !filter.may_match(self.file.stats().columns()) {
+            self.cells.clear();
+        }
+        self.filter = Some(filter.clone());
+        Ok(self)
+    }
+}
+
+impl Iterator for FileQuery<'_> {
+    type Item = Result<GaiaRecord, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let row = match self.rows.next() {
+                Some(row) => row as usize,
+                None => {
+                    self.rows = self.cells.pop()?;
+                    continue;
+                }
+            };
+            if let Some(region) = &self.region {
+                if !region.contains(&self.file.position(row)) {
+                    continue;
+                }
+            }
+            let fields = match self.file.fields(row) {
+                Ok(fields) => fields,
+                Err(e) => return Some(Err(e)),
+            };
+            if self
+                .filter
+                .as_ref()
+                .is_some_and(|f| !f.matches_fields(&fields))
+            {
+                continue;
+            }
+            return Some(fields.deserialize(Some(&self.headers)).map_err(Error::from));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use gaia::filter::Filter;
+    use gaia::projection::Projection;
+    use gaia::record::test::sample_record;
+    use gaia::record::GaiaRecord;
+    use sky::position::SkyPosition;
+    use sky::region::Region;
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use store::file::{StarFile, StarFileBuilder};
+    use store::Error;
+
+    fn path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!(
+            "starquad-file-test-{}-{}.starquad",
+            name,
+            std::process::id()
+        ))
+    }
+
+    fn records() -> Vec<GaiaRecord> {
+        (0..200)
+            .map(|i| {
+                let mut record = sample_record();
+                record.source_id = i;
+                record.designation = format!("Gaia DR2 {}", i);
+                record.ra = (i as f64 * 7.3) % 360.0;
+                record.dec = (i as f64) * 0.85 - 85.0;
+                record.parallax = if i % 3 == 0 {
+                    None
+                } else {
+                    Some(i as f64 / 10.0)
+                };
+                record.phot_g_mean_mag = 10.0 + (i % 10) as f64;
+                record.duplicated_source = i % 2 == 0;
+                record
+            })
+            .collect()
+    }
+
+    fn by_id(mut records: Vec<GaiaRecord>) -> Vec<GaiaRecord> {
+        records.sort_by_key(|r| r.source_id);
+        records
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = path("round-trip");
+        let mut builder = StarFileBuilder::new(&path)
+            .with_order(2)
+            .with_description("test records");
+        for record in &records() {
+            builder.push(record).unwrap();
+        }
+        let metadata = builder.finish().unwrap();
+        assert_eq!(metadata.rows, 200);
+
+        let file = StarFile::open(&path).unwrap();
+        assert_eq!(file.metadata(), &metadata);
+        assert_eq!(file.len(), 200);
+        assert_eq!(file.metadata().description.as_deref(), Some("test records"));
+        assert!(file.cells().len() > 1);
+        assert!(file.cells().windows(2).all(|w| w[0].pixel < w[1].pixel));
+        let read = file.records().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(by_id(read), records());
+        assert_eq!(file.stats().column("parallax").unwrap().nulls, 67);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn queries() {
+        let path = path("queries");
+        let columns = Projection::new(&["source_id", "parallax"]).unwrap();
+        let mut builder = StarFileBuilder::new(&path)
+            .with_order(3)
+            .with_columns(columns);
+        for record in &records() {
+            builder.push(record).unwrap();
+        }
+        builder.finish().unwrap();
+        let file = StarFile::open(&path).unwrap();
+        assert_eq!(
+            file.metadata().columns(),
+            vec!["source_id", "parallax", "ra", "dec"]
+        );
+        assert!(file.stats().column("phot_g_mean_mag").is_none());
+
+        let region = Region::cone(SkyPosition::new(40.0, 0.0), 25.0);
+        let filter = "parallax > 5".parse::<Filter>().unwrap();
+        let found = file
+            .query(&region)
+            .matching(&filter)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let expected = records()
+            .into_iter()
+            .filter(|r| region.contains(&SkyPosition::new(r.ra, r.dec)) && filter.matches(r))
+            .map(|r| r.source_id)
+            .collect::<Vec<_>>();
+        assert!(!expected.is_empty());
+        let found = by_id(found);
+        assert_eq!(
+            found.iter().map(|r| r.source_id).collect::<Vec<_>>(),
+            expected
+        );
+        // columns which are not stored are cleared
+        assert!(found
+            .iter()
+            .all(|r| r.phot_g_mean_mag == 0.0 && r.designation.is_empty()));
+
+        let unstored = "phot_g_mean_mag < 12".parse::<Filter>().unwrap();
+        assert!(matches!(
+            file.query(&region).matching(&unstored),
+            Err(Error::MissingColumn(_))
+        ));
+        let none = "parallax > 100".parse::<Filter>().unwrap();
+        assert_eq!(file.records().matching(&none).unwrap().count(), 0);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn invalid_files() {
+        let path = path("invalid");
+        fs::write(&path, "source_id,ra,dec\n").unwrap();
+        assert!(matches!(StarFile::open(&path), Err(Error::InvalidFile(_))));
+
+        let mut builder = StarFileBuilder::new(&path);
+        builder.push(&sample_record()).unwrap();
+        builder.finish().unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+        assert!(matches!(StarFile::open(&path), Err(Error::InvalidFile(_))));
+        let mut newer = bytes.clone();
+        newer[8] = 2;
+        fs::write(&path, newer).unwrap();
+        assert!(matches!(
+            StarFile::open(&path),
+            Err(Error::UnsupportedFormat(_))
+        ));
+        fs::remove_file(&path).unwrap();
+    }
+}
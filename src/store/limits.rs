@@ -0,0 +1,97 @@
+//! Limits on the work done by a query.
+//!
+//! A query with limits (see `Query::with_limits`) stops at the first limit
+//! it reaches, as if it had no more records, and `Query::truncated` then
+//! tells which limit stopped it. Its cursor continues it from there, so the
+//! rest of its records can be read by later queries.
+
+use std::fmt;
+use std::time::Duration;
+
+/// Limits on the records returned, shards read and time taken by a query.
+/// The default has no limits.
+///
+/// ```
+/// # use starquad::store::limits::QueryLimits;
+/// # use std::time::Duration;
+/// let limits = QueryLimits::new()
+///     .with_max_results(100_000)
+///     .with_timeout(Duration::from_secs(30));
+/// assert!(!limits.is_unlimited());
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct QueryLimits {
+    max_results: Option<u64>,
+    max_shards: Option<usize>,
+    timeout: Option<Duration>,
+}
+
+impl QueryLimits {
+    pub fn new() -> Self {
+        QueryLimits::default()
+    }
+
+    /// Return at most this many records.
+    pub fn with_max_results(mut self, max_results: u64) -> Self {
+        self.max_results = Some(max_results);
+        self
+    }
+
+    /// Read at most this many shards.
+    pub fn with_max_shards(mut self, max_shards: usize) -> Self {
+        self.max_shards = Some(max_shards);
+        self
+    }
+
+    /// Stop reading shards once the query has taken this long. The time is
+    /// checked before each record is read, so a query overruns it by at
+    /// most the time to read one record (or to open one shard).
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn max_results(&self) -> Option<u64> {
+        self.max_results
+    }
+
+    pub fn max_shards(&self) -> Option<usize> {
+        self.max_shards
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        *self == QueryLimits::default()
+    }
+}
+
+/// Limit which stopped a query before it returned all of its records.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Truncation {
+    /// The query returned its maximum number of records, and had more.
+    Results,
+    /// The query read its maximum number of shards, and had more to read.
+    Shards,
+    /// The query ran out of time.
+    Timeout,
+}
+
+impl Truncation {
+    /// Name of the limit, as written by `Display`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Truncation::Results => "results",
+            Truncation::Shards => "shards",
+            Truncation::Timeout => "timeout",
+        }
+    }
+}
+
+impl fmt::Display for Truncation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
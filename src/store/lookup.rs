@@ -0,0 +1,277 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, BinaryHeap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use store::file::u64_at;
+use store::temp_path;
+
+/// Name of the source index file in a store directory.
+pub const SOURCE_INDEX_FILE: &str = "source_ids.idx";
+
+/// Name of the file of unsorted entries written while a store is built.
+const UNSORTED_FILE: &str = "source_ids.unsorted";
+
+/// Number of entries sorted in memory at a time (96 MiB).
+const RUN_ENTRIES: usize = 1 << 22;
+
+/// Where the record of a source is stored.
+///
+/// `offset` is the byte offset of the record in a bincode shard, or the
+/// position of the record in the shard for other formats, which cannot be
+/// read from an offset.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SourceEntry {
+    pub source_id: u64,
+    pub pixel: u64,
+    pub offset: u64,
+}
+
+impl SourceEntry {
+    const SIZE: usize = 24;
+
+    fn to_bytes(self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[0..8].copy_from_slice(&self.source_id.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.pixel.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.offset.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8; Self::SIZE]) -> Self {
+        SourceEntry {
+            source_id: u64_at(bytes, 0),
+            pixel: u64_at(bytes, 1),
+            offset: u64_at(bytes, 2),
+        }
+    }
+
+    fn read<R: Read>(reader: &mut R) -> io::Result<Option<Self>> {
+        let mut bytes = [0; Self::SIZE];
+        match reader.read_exact(&mut bytes) {
+            Ok(()) => Ok(Some(SourceEntry::from_bytes(&bytes))),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Sidecar index of a store from `source_id` to the shard and offset of the
+/// record, for fetching single records without a scan.
+///
+/// The index is a file of fixed-size entries sorted by `source_id`, which is
+/// binary searched on disk, so it is never read into memory: a lookup in an
+/// index of the whole of Gaia DR2 reads about 31 entries.
+pub struct SourceIndex {
+    file: File,
+    len: u64,
+}
+
+impl SourceIndex {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len() / SourceEntry::SIZE as u64;
+        Ok(SourceIndex { file, len })
+    }
+
+    fn entry(&mut self, i: u64) -> io::Result<SourceEntry> {
+        self.file
+            .seek(SeekFrom::Start(i * SourceEntry::SIZE as u64))?;
+        SourceEntry::read(&mut self.file)?.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::UnexpectedEof, "source index is truncated")
+        })
+    }
+
+    /// Entry of a source, if it is in the index.
+    pub fn find(&mut self, source_id: u64) -> io::Result<Option<SourceEntry>> {
+        let (mut lo, mut hi) = (0, self.len);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            let entry = self.entry(mid)?;
+            if entry.source_id < source_id {
+                lo = mid + 1;
+            } else if entry.source_id > source_id {
+                hi = mid;
+            } else {
+                return Ok(Some(entry));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Writer of the entries of a source index as a store is built, in the
+/// order its records arrive.
+pub(crate) struct SourceIndexWriter {
+    dir: PathBuf,
+    unsorted: BufWriter<File>,
+}
+
+impl SourceIndexWriter {
+    /// Start an index in a store directory, or continue the index of a
+    /// resumed build.
+    pub(crate) fn open(dir: &Path, resume: bool) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(resume)
+            .write(true)
+            .truncate(!resume)
+            .open(dir.join(UNSORTED_FILE))?;
+        Ok(SourceIndexWriter {
+            dir: dir.to_path_buf(),
+            unsorted: BufWriter::new(file),
+        })
+    }
+
+    pub(crate) fn push(&mut self, entry: SourceEntry) -> io::Result<()> {
+        self.unsorted.write_all(&entry.to_bytes())
+    }
+
+    /// Flush the entries so far, to be continued by a resumed build.
+    pub(crate) fn checkpoint(mut self) -> io::Result<()> {
+        self.unsorted.flush()
+    }
+
+    /// Sort the entries into the index file, with an external merge sort.
+    pub(crate) fn finish(self) -> io::Result<()> {
+        self.sort(RUN_ENTRIES)
+    }
+
+    fn sort(mut self, run_entries: usize) -> io::Result<()> {
+        self.unsorted.flush()?;
+        let unsorted_path = self.dir.join(UNSORTED_FILE);
+        let mut unsorted = BufReader::new(File::open(&unsorted_path)?);
+        let mut runs = Vec::new();
+        let mut run = Vec::with_capacity(run_entries);
+        loop {
+            let entry = SourceEntry::read(&mut unsorted)?;
+            if let Some(entry) = entry {
+                run.push(entry);
+            }
+            if run.len() == run_entries || (entry.is_none() && !run.is_empty()) {
+                run.sort_unstable();
+                let path = self.dir.join(format!("source_ids.run-{}", runs.len()));
+                write_entries(&path, &run)?;
+                runs.push(path);
+                run.clear();
+            }
+            if entry.is_none() {
+                break;
+            }
+        }
+        let sorted = self.dir.join(SOURCE_INDEX_FILE);
+        if runs.len() == 1 {
+            fs::rename(&runs[0], &sorted)?;
+        } else {
+            merge_runs(&runs, &sorted)?;
+            for path in &runs {
+                fs::remove_file(path)?;
+            }
+        }
+        fs::remove_file(unsorted_path)
+    }
+}
+
+/// Write a new version of the index of a store beside it, in which the
+/// entries of some shards are replaced by new entries, sorted by
+/// `source_id`.
+pub(crate) fn rewrite(
+    dir: &Path,
+    pixels: &BTreeSet<u64>,
+    entries: &[SourceEntry],
+) -> io::Result<()> {
+    let path = dir.join(SOURCE_INDEX_FILE);
+    let mut old = BufReader::new(File::open(&path)?);
+    let mut writer = BufWriter::new(File::create(temp_path(&path))?);
+    let mut new = entries.iter().peekable();
+    while let Some(entry) = SourceEntry::read(&mut old)? {
+        if pixels.contains(&entry.pixel) {
+            continue;
+        }
+        while let Some(next) = new.next_if(|next| **next < entry) {
+            writer.write_all(&next.to_bytes())?;
+        }
+        writer.write_all(&entry.to_bytes())?;
+    }
+    for next in new {
+        writer.write_all(&next.to_bytes())?;
+    }
+    writer.flush()?;
+    writer.get_ref().sync_all()
+}
+
+fn write_entries(path: &Path, entries: &[SourceEntry]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    for entry in entries {
+        writer.write_all(&entry.to_bytes())?;
+    }
+    writer.flush()
+}
+
+fn merge_runs(runs: &[PathBuf], path: &Path) -> io::Result<()> {
+    let mut readers = runs
+        .iter()
+        .map(|path| Ok(BufReader::new(File::open(path)?)))
+        .collect::<io::Result<Vec<_>>>()?;
+    let mut heads = BinaryHeap::new();
+    for (i, reader) in readers.iter_mut().enumerate() {
+        if let Some(entry) = SourceEntry::read(reader)? {
+            heads.push(Reverse((entry, i)));
+        }
+    }
+    let mut writer = BufWriter::new(File::create(path)?);
+    while let Some(Reverse((entry, i))) = heads.pop() {
+        writer.write_all(&entry.to_bytes())?;
+        if let Some(next) = SourceEntry::read(&mut readers[i])? {
+            heads.push(Reverse((next, i)));
+        }
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+    use std::io;
+    use store::lookup::{SourceEntry, SourceIndex, SourceIndexWriter, SOURCE_INDEX_FILE};
+
+    #[test]
+    fn write_and_find() {
+        let dir = env::temp_dir().join(format!("starquad-lookup-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut writer = SourceIndexWriter::open(&dir, false).unwrap();
+        for i in 0..500 {
+            let source_id = (i * 7919) % 1000;
+            writer
+                .push(SourceEntry {
+                    source_id,
+                    pixel: source_id % 12,
+                    offset: i,
+                })
+                .unwrap();
+        }
+        writer.sort(64).unwrap();
+
+        let mut index = SourceIndex::open(dir.join(SOURCE_INDEX_FILE)).unwrap();
+        assert_eq!(index.len, 500);
+        for i in 0..500 {
+            let source_id = (i * 7919) % 1000;
+            let entry = index.find(source_id).unwrap().unwrap();
+            assert_eq!((entry.pixel, entry.offset), (source_id % 12, i));
+        }
+        assert_eq!(index.find(1001).unwrap(), None);
+        // only the index is left
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        // a file truncated after it is opened is an error, not a panic
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .open(dir.join(SOURCE_INDEX_FILE))
+            .unwrap();
+        file.set_len(SourceEntry::SIZE as u64 * 10).unwrap();
+        let error = index.find(999).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
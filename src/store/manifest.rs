@@ -0,0 +1,325 @@
+use gaia::record::GaiaRecord;
+use gaia::schema::{self, COLUMNS};
+use gaia::stats::{CatalogStats, ColumnStats};
+use geom::p2::P2;
+use geom::rect::Rect;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use store::{temp_path, Error};
+
+/// Name of the manifest file in a store directory.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Newest version of the store format, which is written by default.
+///
+/// - Version 1, of stores written before versions were recorded: the
+///   manifest, shards of bincode records (in the order of the Gaia DR2
+///   columns), gzipped CSV or Parquet, and the source index.
+/// - Version 2 adds the `version` and `requires` fields of the manifest,
+///   and the change log of `store::wal`. Shards of zstd blocks are
+///   version 2 stores which require `ZSTD_BLOCKS`.
+///
+/// Every version from 1 is read. A later version or an unknown capability
+/// in `requires` is refused rather than misread, and a store can be
+/// written (and rewritten, with `Store::set_version`) in an earlier version
+/// for older readers.
+pub const FORMAT_VERSION: u32 = 2;
+
+/// Capability of a store whose change log may hold changes, which readers
+/// must apply to its shards.
+pub const CHANGE_LOG: &str = "change_log";
+
+/// Capability of a store whose shards are zstd-compressed blocks
+/// (`ShardFormat::Zstd`).
+pub const ZSTD_BLOCKS: &str = "zstd_blocks";
+
+/// Capabilities which readers of this release support.
+#[cfg(not(feature = "zstd"))]
+pub const CAPABILITIES: &[&str] = &[CHANGE_LOG];
+/// Capabilities which readers of this release support.
+#[cfg(feature = "zstd")]
+pub const CAPABILITIES: &[&str] = &[CHANGE_LOG, ZSTD_BLOCKS];
+
+/// File format of the shards of a store.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShardFormat {
+    /// Length-prefixed bincode records: the fastest to read.
+    #[default]
+    Bincode,
+    /// Gzipped Gaia CSV: portable, and compact when columns are cleared.
+    Csv,
+    /// Parquet with zstd compression: the most compact.
+    #[cfg(feature = "parquet")]
+    Parquet,
+    /// Length-prefixed bincode records in zstd-compressed blocks, of which
+    /// a lookup decompresses only one (see `store::blocks`).
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl ShardFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ShardFormat::Bincode => "bin",
+            ShardFormat::Csv => "csv.gz",
+            #[cfg(feature = "parquet")]
+            ShardFormat::Parquet => "parquet",
+            #[cfg(feature = "zstd")]
+            ShardFormat::Zstd => "bin.zst",
+        }
+    }
+
+    /// Whether shards in the format are read a column at a time, so that
+    /// queries selecting columns decode only those (see `Query::select`).
+    pub fn is_columnar(self) -> bool {
+        match self {
+            #[cfg(feature = "parquet")]
+            ShardFormat::Parquet => true,
+            _ => false,
+        }
+    }
+
+    /// Capability which readers of shards in the format need, beyond those
+    /// of the version of their store.
+    pub fn capability(self) -> Option<&'static str> {
+        match self {
+            #[cfg(feature = "zstd")]
+            ShardFormat::Zstd => Some(ZSTD_BLOCKS),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for ShardFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name {
+            "bincode" => Ok(ShardFormat::Bincode),
+            "csv" => Ok(ShardFormat::Csv),
+            #[cfg(feature = "parquet")]
+            "parquet" => Ok(ShardFormat::Parquet),
+            #[cfg(feature = "zstd")]
+            "zstd" => Ok(ShardFormat::Zstd),
+            _ => Err(format!("unknown shard format: {}", name)),
+        }
+    }
+}
+
+/// Description of a store, written as JSON alongside its shards.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Manifest {
+    /// Version of the store format (see `FORMAT_VERSION`), which is left
+    /// out of manifests of version 1.
+    #[serde(default = "version_1", skip_serializing_if = "is_version_1")]
+    pub version: u32,
+    /// Capabilities beyond those of its version which a reader needs to
+    /// read the store correctly.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requires: Vec<String>,
+    /// HEALPix order of the shards.
+    pub order: u8,
+    #[serde(default)]
+    pub format: ShardFormat,
+    /// Columns stored in the shards, or `None` if all columns are stored.
+    /// Other columns are cleared (see `Projection::clear_others`).
+    #[serde(default)]
+    pub columns: Option<Vec<String>>,
+    /// Statistics of the stored columns.
+    #[serde(default)]
+    pub stats: CatalogStats,
+    /// Shards, in order of increasing pixel.
+    pub shards: Vec<ShardInfo>,
+    /// Whether the store has a `SourceIndex` (see `store::lookup`).
+    #[serde(default)]
+    pub source_index: bool,
+    /// Columns of which each shard records the statistics (`ShardInfo`'s
+    /// `zones`), for queries to skip shards which cannot match a filter.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub zone_maps: Vec<String>,
+    /// Whether each shard has a Bloom filter of its sources (see
+    /// `store::bloom`).
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub bloom_filters: bool,
+    /// Where an interrupted build stopped, or `None` if the store is
+    /// complete.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checkpoint: Option<Checkpoint>,
+}
+
+/// Position in the inputs of an interrupted build, from which
+/// `StoreBuilder::resume` continues it.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Checkpoint {
+    /// Input files of the build, in order.
+    pub inputs: Vec<PathBuf>,
+    /// Number of inputs which were read completely.
+    pub files: usize,
+    /// Number of records of the next input which were read.
+    pub records: u64,
+}
+
+/// Description of a single shard, which holds the records of one HEALPix
+/// pixel.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ShardInfo {
+    pub pixel: u64,
+    pub records: u64,
+    pub min_ra: f64,
+    pub max_ra: f64,
+    pub min_dec: f64,
+    pub max_dec: f64,
+    /// Statistics of the columns of the manifest's `zone_maps` over the
+    /// records of the shard.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub zones: Vec<ColumnStats>,
+}
+
+impl ShardInfo {
+    pub fn new(pixel: u64) -> Self {
+        ShardInfo {
+            pixel,
+            records: 0,
+            min_ra: f64::INFINITY,
+            max_ra: f64::NEG_INFINITY,
+            min_dec: f64::INFINITY,
+            max_dec: f64::NEG_INFINITY,
+            zones: Vec::new(),
+        }
+    }
+
+    /// Also record the statistics of some columns.
+    pub fn with_zones(mut self, columns: &[String]) -> Self {
+        self.zones = columns.iter().map(|c| ColumnStats::new(c)).collect();
+        self
+    }
+
+    /// Name of the shard file, relative to the store directory.
+    pub fn file_name(&self, format: ShardFormat) -> String {
+        format!("shard-{}.{}", self.pixel, format.extension())
+    }
+
+    /// Name of the file of the Bloom filter of the shard, relative to the
+    /// store directory.
+    pub fn bloom_file_name(&self) -> String {
+        format!("shard-{}.bloom", self.pixel)
+    }
+
+    /// Record a position added to the shard.
+    pub fn add(&mut self, ra: f64, dec: f64) {
+        self.records += 1;
+        self.min_ra = self.min_ra.min(ra);
+        self.max_ra = self.max_ra.max(ra);
+        self.min_dec = self.min_dec.min(dec);
+        self.max_dec = self.max_dec.max(dec);
+    }
+
+    /// Record a record added to the shard.
+    pub fn add_record(&mut self, record: &GaiaRecord) {
+        self.add(record.ra, record.dec);
+        if self.zones.is_empty() {
+            return;
+        }
+        let fields = schema::string_record(record);
+        for zone in &mut self.zones {
+            if let Some(i) = COLUMNS.iter().position(|c| c.name == zone.name) {
+                zone.add(fields.get(i).unwrap_or(""));
+            }
+        }
+    }
+
+    /// Smallest rectangle containing the positions of all records in the
+    /// shard, or `None` if it is empty.
+    pub fn bounds(&self) -> Option<Rect<f64>> {
+        Rect::bounding(&[
+            P2::new(self.min_ra, self.min_dec),
+            P2::new(self.max_ra, self.max_dec),
+        ])
+        .filter(|_| self.records > 0)
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+fn version_1() -> u32 {
+    1
+}
+
+fn is_version_1(version: &u32) -> bool {
+    *version == 1
+}
+
+/// Check that a version of the store format can be read and written.
+pub fn check_version(version: u32) -> Result<(), Error> {
+    if (1..=FORMAT_VERSION).contains(&version) {
+        Ok(())
+    } else {
+        Err(Error::UnsupportedFormat(format!(
+            "version {} (this release supports versions 1 to {})",
+            version, FORMAT_VERSION
+        )))
+    }
+}
+
+impl Manifest {
+    /// Read the manifest of a store, if this release can read the store.
+    ///
+    /// The version and capabilities are checked before the rest of the
+    /// manifest, whose fields may have changed in later versions.
+    pub fn read(dir: &Path) -> Result<Self, Error> {
+        let file = File::open(dir.join(MANIFEST_FILE))?;
+        let value: serde_json::Value = serde_json::from_reader(BufReader::new(file))?;
+        let version = match value.get("version") {
+            None => 1,
+            Some(version) => version
+                .as_u64()
+                .and_then(|v| u32::try_from(v).ok())
+                .ok_or_else(|| Error::UnsupportedFormat(format!("version {}", version)))?,
+        };
+        check_version(version)?;
+        let manifest: Manifest = serde_json::from_value(value)?;
+        let unknown = manifest
+            .requires
+            .iter()
+            .filter(|c| !CAPABILITIES.contains(&c.as_str()))
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        if !unknown.is_empty() {
+            return Err(Error::UnsupportedFormat(format!(
+                "requires {}",
+                unknown.join(", ")
+            )));
+        }
+        Ok(manifest)
+    }
+
+    /// Write the manifest beside the old one and rename it over it, so a
+    /// crash leaves one or the other.
+    pub fn write(&self, dir: &Path) -> Result<(), Error> {
+        let path = dir.join(MANIFEST_FILE);
+        let temp = temp_path(&path);
+        let mut writer = BufWriter::new(File::create(&temp)?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        fs::rename(temp, path)?;
+        Ok(())
+    }
+
+    /// Whether the store requires a capability of its readers.
+    pub fn requires(&self, capability: &str) -> bool {
+        self.requires.iter().any(|c| c == capability)
+    }
+
+    /// Total number of records in all shards.
+    pub fn records(&self) -> u64 {
+        self.shards.iter().map(|s| s.records).sum()
+    }
+}
@@ -0,0 +1,1394 @@
+#[cfg(feature = "parquet")]
+use catalog::parquet::{self, ParquetReader, RowFilter};
+use flate2::read::GzDecoder;
+use gaia::columnar;
+use gaia::filter::Filter;
+use gaia::projection::{self, Projection};
+use gaia::reader;
+use gaia::record::GaiaRecord;
+use geom::rect::Rect;
+use output;
+use output::bincode_sink::BincodeReader;
+use sky::position::{SkySource, MAS_PER_DEG};
+use sky::region::Region;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::vec;
+use tracing::Span;
+
+#[cfg(feature = "zstd")]
+pub(crate) mod blocks;
+pub(crate) mod bloom;
+pub mod builder;
+pub mod cache;
+pub mod file;
+pub mod limits;
+pub(crate) mod lookup;
+pub mod manifest;
+pub mod results;
+pub mod wal;
+
+use store::bloom::BloomFilter;
+use store::limits::{QueryLimits, Truncation};
+use store::lookup::{SourceIndex, SOURCE_INDEX_FILE};
+use store::manifest::{Manifest, ShardFormat, ShardInfo, CHANGE_LOG};
+use store::wal::{Pending, WriteAheadLog};
+
+/// Reference epochs of the first and last Gaia releases (DR1 and DR3), as
+/// Julian years. The positions of a store are assumed to be at epochs in
+/// this range.
+const REF_EPOCHS: (f64, f64) = (2015.0, 2016.0);
+
+/// Proper motion of Barnard's star, the largest known, in milliarcseconds
+/// per year: the bound used for a store without statistics of its proper
+/// motions.
+const MAX_PROPER_MOTION: f64 = 10_400.0;
+
+/// Errors from building or reading a store.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("invalid manifest: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Output(#[from] output::Error),
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    #[error(transparent)]
+    Projection(#[from] projection::Error),
+    #[error(transparent)]
+    Columnar(#[from] columnar::Error),
+    #[cfg(feature = "parquet")]
+    #[error(transparent)]
+    Parquet(#[from] parquet::Error),
+    /// A build cannot be resumed from a store.
+    #[error("cannot resume build: {0}")]
+    NotResumable(String),
+    /// The store was written in a version of the format, or with a
+    /// capability, which this release does not support.
+    #[error("unsupported store format: {0}")]
+    UnsupportedFormat(String),
+    /// A column cannot be summarized in zone maps.
+    #[error("invalid zone map: {0}")]
+    ZoneMap(String),
+    /// A store cannot be compacted while its build is incomplete.
+    #[error("cannot compact an incomplete store; resume its build first")]
+    Incomplete,
+    /// A starquad file is damaged, or is not a starquad file.
+    #[error("invalid starquad file: {0}")]
+    InvalidFile(String),
+    /// A query of a starquad file needs a column it does not store.
+    #[error("column {0} is not stored in the file")]
+    MissingColumn(String),
+}
+
+/// On-disk index of Gaia records, partitioned into one shard per HEALPix
+/// pixel.
+///
+/// A store is a directory holding a `manifest.json` and a file of records for
+/// each non-empty shard, in one of the formats of `ShardFormat`. The manifest
+/// records the bounds of each shard, so a query only reads the shards which
+/// might contain matching records.
+///
+/// Records can be pushed to and removed from a store after it is built,
+/// through a log of changes which queries read alongside the shards, and
+/// which `compact` folds into them (see `store::wal`).
+///
+/// Queries take `&self` and open their own shard files, and changes take
+/// `&mut self`, so a `Store` is `Send` and `Sync` and the servers share one
+/// between their requests in an `Arc`. Indexes in memory which are added
+/// to while they are queried can be shared with
+/// `accel2d::shared::SharedIndex`.
+///
+/// ```
+/// # use starquad::geom::rect::Rect;
+/// # use starquad::store::builder::StoreBuilder;
+/// # use starquad::store::Store;
+/// # let dir = std::env::temp_dir().join("starquad-store-doctest");
+/// # let records: Vec<starquad::gaia::record::GaiaRecord> = Vec::new();
+/// let mut builder = StoreBuilder::new(&dir).unwrap().with_order(2);
+/// for record in &records {
+///     builder.push(record).unwrap();
+/// }
+/// builder.finish().unwrap();
+///
+/// let store = Store::open(&dir).unwrap();
+/// let rect = Rect::new(10.0, -5.0, 1.0, 1.0).unwrap();
+/// let found = store.query_rect(&rect).collect::<Result<Vec<_>, _>>().unwrap();
+/// # assert!(found.is_empty());
+/// ```
+pub struct Store {
+    dir: PathBuf,
+    manifest: Manifest,
+    pending: Arc<Pending>,
+    /// Log of changes, once the store has been changed.
+    wal: Option<WriteAheadLog>,
+}
+
+impl Store {
+    /// Open a store, with the changes in its log.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, Error> {
+        let dir = dir.as_ref().to_path_buf();
+        let manifest = Manifest::read(&dir)?;
+        let pending = Arc::new(wal::read(&dir)?);
+        Ok(Store {
+            dir,
+            manifest,
+            pending,
+            wal: None,
+        })
+    }
+
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    /// Rewrite the manifest of the store in another version of the format
+    /// (see `manifest::FORMAT_VERSION`), for readers of that version.
+    ///
+    /// A store with changes in its log must be compacted before it is
+    /// written in version 1, which has no log, and a store of zstd blocks
+    /// cannot be written in version 1.
+    pub fn set_version(&mut self, version: u32) -> Result<(), Error> {
+        manifest::check_version(version)?;
+        if version < 2 {
+            if !self.pending.is_empty() {
+                return Err(Error::UnsupportedFormat(String::from(
+                    "version 1 has no change log; compact the store first",
+                )));
+            }
+            if let Some(capability) = self.manifest.requires.iter().find(|c| *c != CHANGE_LOG) {
+                return Err(Error::UnsupportedFormat(format!(
+                    "version 1 cannot require {}",
+                    capability
+                )));
+            }
+            self.manifest.requires.clear();
+        }
+        self.manifest.version = version;
+        self.manifest.write(&self.dir)
+    }
+
+    /// Shards which might contain records in a region.
+    pub fn shards(&self, region: &Region) -> Vec<&ShardInfo> {
+        self.manifest
+            .shards
+            .iter()
+            .filter(|s| s.bounds().is_some_and(|b| region.intersects(&b)))
+            .collect()
+    }
+
+    /// Records whose positions lie in a region.
+    pub fn query(&self, region: &Region) -> Query {
+        let shards = self.shards(region).into_iter().cloned().collect::<Vec<_>>();
+        let span = tracing::info_span!("query", region = ?region, shards = shards.len());
+        Query::new(self, shards, Some(region.clone()), span)
+    }
+
+    /// Records whose positions, propagated to an epoch (a Julian year), lie
+    /// in a region.
+    ///
+    /// The shards read are those which might contain records in the region
+    /// expanded by the furthest any source in the store could have moved;
+    /// records without proper motions are not propagated.
+    pub fn query_at(&self, region: &Region, epoch: f64) -> Query {
+        let years = (epoch - REF_EPOCHS.0)
+            .abs()
+            .max((epoch - REF_EPOCHS.1).abs());
+        let margin = self.max_proper_motion() * years / MAS_PER_DEG;
+        let shards = self
+            .shards(&region.expanded(margin))
+            .into_iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        let span = tracing::info_span!(
+            "query",
+            region = ?region,
+            epoch,
+            margin_deg = margin,
+            shards = shards.len()
+        );
+        let mut query = Query::new(self, shards, Some(region.clone()), span);
+        query.epoch = Some(epoch);
+        query
+    }
+
+    /// Largest total proper motion of any record, bounded from the
+    /// statistics of `pmra` and `pmdec`, in milliarcseconds per year.
+    pub fn max_proper_motion(&self) -> f64 {
+        let largest = |name: &str| {
+            let stats = self.manifest.stats.column(name)?;
+            Some(match (stats.min, stats.max) {
+                (Some(min), Some(max)) => min.abs().max(max.abs()),
+                _ => 0.0,
+            })
+        };
+        match (largest("pmra"), largest("pmdec")) {
+            (Some(pmra), Some(pmdec)) => pmra.hypot(pmdec),
+            _ => MAX_PROPER_MOTION,
+        }
+    }
+
+    /// Records whose `(ra, dec)` lie in a rectangle, in degrees.
+    pub fn query_rect(&self, rect: &Rect<f64>) -> Query {
+        self.query(&Region::Rect(rect.clone()))
+    }
+
+    /// All records of the store, in shard order.
+    pub fn scan(&self) -> Query {
+        let shards = self.manifest.shards.clone();
+        let span = tracing::info_span!("scan", shards = shards.len());
+        Query::new(self, shards, None, span)
+    }
+
+    /// Record of a source, if it is in the store.
+    ///
+    /// Stores built with a source index (see
+    /// `StoreBuilder::with_source_index`) find the record from its entry in
+    /// the index, reading only that record of a bincode shard. Other stores
+    /// are scanned.
+    pub fn lookup(&self, source_id: u64) -> Result<Option<GaiaRecord>, Error> {
+        if let Some(change) = self.pending.get(source_id) {
+            return Ok(change.cloned());
+        }
+        if !self.manifest.source_index {
+            tracing::warn!(source_id, "no source index; scanning the store");
+            return self
+                .scan()
+                .matching(&Filter::source_id(source_id))
+                .find(|r| r.as_ref().map_or(true, |r| r.source_id == source_id))
+                .transpose();
+        }
+        let mut index = SourceIndex::open(self.dir.join(SOURCE_INDEX_FILE))?;
+        let entry = match index.find(source_id)? {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        let path = self
+            .dir
+            .join(ShardInfo::new(entry.pixel).file_name(self.manifest.format));
+        let found = match self.manifest.format {
+            ShardFormat::Bincode => {
+                let mut file = File::open(&path)?;
+                file.seek(SeekFrom::Start(entry.offset))?;
+                BincodeReader::new(BufReader::new(file))
+                    .next()
+                    .transpose()?
+            }
+            #[cfg(feature = "zstd")]
+            ShardFormat::Zstd => blocks::read_record(&path, entry.offset)?,
+            _ => read_shard(&path, self.manifest.format)?
+                .nth(entry.offset as usize)
+                .transpose()?,
+        };
+        if found.as_ref().is_some_and(|r| r.source_id == source_id) {
+            return Ok(found);
+        }
+        // a compaction stopped between rewriting the shard and the index
+        tracing::warn!(source_id, "stale source index; scanning the shard");
+        read_shard(&path, self.manifest.format)?
+            .find(|r| r.as_ref().map_or(true, |r| r.source_id == source_id))
+            .transpose()
+    }
+}
+
+/// Path of the new version of a file, written beside it before it replaces
+/// it.
+pub(crate) fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+type Records = Box<dyn Iterator<Item = Result<GaiaRecord, Error>>>;
+
+/// Open a shard file.
+fn read_shard(path: &Path, format: ShardFormat) -> Result<Records, Error> {
+    let file = File::open(path)?;
+    Ok(match format {
+        ShardFormat::Bincode => {
+            Box::new(BincodeReader::new(BufReader::new(file)).map(|r| r.map_err(Error::from)))
+        }
+        ShardFormat::Csv => Box::new(
+            reader::records(GzDecoder::new(BufReader::new(file))).map(|r| r.map_err(Error::from)),
+        ),
+        #[cfg(feature = "parquet")]
+        ShardFormat::Parquet => Box::new(ParquetReader::new(file)?.map(|r| r.map_err(Error::from))),
+        #[cfg(feature = "zstd")]
+        ShardFormat::Zstd => Box::new(blocks::BlockReader::new(file)?),
+    })
+}
+
+/// Open a shard file, to read the columns of a projection. Records of
+/// columnar shards hold only those columns, and records of other shards
+/// hold them all.
+#[cfg_attr(not(feature = "parquet"), allow(unused_variables))]
+fn read_columns(
+    path: &Path,
+    format: ShardFormat,
+    projection: &Projection,
+) -> Result<Records, Error> {
+    match format {
+        #[cfg(feature = "parquet")]
+        ShardFormat::Parquet => {
+            let reader = ParquetReader::with_projection(
+                File::open(path)?,
+                RowFilter::new(),
+                projection.clone(),
+            )?;
+            Ok(Box::new(reader.map(|r| r.map_err(Error::from))))
+        }
+        _ => read_shard(path, format),
+    }
+}
+
+/// Shard being read by a query.
+struct ShardScan {
+    pixel: u64,
+    path: PathBuf,
+    records: Records,
+    start: Instant,
+    read: u64,
+    /// Number of records read by an earlier page of the query.
+    skip: u64,
+    matched: u64,
+}
+
+/// Position in the records of a query, from which a query of the same
+/// store with the same arguments continues (see `Query::after`).
+///
+/// Its text form, the continuation token of the servers, is `start`,
+/// `PIXEL-READ` or `log-MATCHED`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cursor {
+    /// Before the first record.
+    Start,
+    /// After a number of the records of the shard of a pixel, counting
+    /// those which do not match.
+    Shard { pixel: u64, read: u64 },
+    /// After a number of the records of the log which match.
+    Log(u64),
+}
+
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Cursor::Start => write!(f, "start"),
+            Cursor::Shard { pixel, read } => write!(f, "{}-{}", pixel, read),
+            Cursor::Log(matched) => write!(f, "log-{}", matched),
+        }
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid cursor: {}", text);
+        if text == "start" {
+            return Ok(Cursor::Start);
+        }
+        let (first, second) = text.split_once('-').ok_or_else(invalid)?;
+        let second = second.parse().map_err(|_| invalid())?;
+        if first == "log" {
+            return Ok(Cursor::Log(second));
+        }
+        Ok(Cursor::Shard {
+            pixel: first.parse().map_err(|_| invalid())?,
+            read: second,
+        })
+    }
+}
+
+/// Work done by a query so far (see `Query::stats`).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct QueryStats {
+    /// Number of shards opened.
+    pub shards: usize,
+    /// Number of records read from the shards, whether or not they matched.
+    pub read: u64,
+    /// Number of records returned.
+    pub matched: u64,
+    /// Time since the query was created.
+    pub elapsed: Duration,
+}
+
+/// Iterator over the records of a store which match a query.
+///
+/// The records of the shards which are not replaced or removed by the log
+/// of the store come first, followed by those pushed to the log.
+///
+/// Results can be read a page at a time: `cursor` gives the position after
+/// the records returned so far, and `after` continues another query with
+/// the same arguments from it, reading at most the rest of one shard again.
+/// Pages of a store which is changed between them may miss or repeat
+/// records.
+///
+/// The time taken to read each shard is logged as a `debug` event of the
+/// query's span, and the totals as an `info` event when the query is done.
+pub struct Query {
+    dir: PathBuf,
+    format: ShardFormat,
+    bloom_filters: bool,
+    shards: vec::IntoIter<ShardInfo>,
+    current: Option<ShardScan>,
+    region: Option<Region>,
+    /// Filter of the records, which the shards read might match.
+    filter: Option<Filter>,
+    /// Columns to return, or `None` for all of them.
+    columns: Option<Projection>,
+    /// Epoch to which positions are propagated before testing them.
+    epoch: Option<f64>,
+    /// Changes in the log of the store when the query started.
+    pending: Arc<Pending>,
+    /// Records of the log in the region, once the shards have been read.
+    added: Option<vec::IntoIter<GaiaRecord>>,
+    /// Position after the records returned so far.
+    cursor: Cursor,
+    /// Position from which the query continues an earlier one.
+    from: Cursor,
+    limits: QueryLimits,
+    /// Number of shards opened.
+    shards_read: usize,
+    /// Number of records read from the shards.
+    records_read: u64,
+    /// Limit which stopped the query, if one has.
+    truncated: Option<Truncation>,
+    span: Span,
+    start: Instant,
+    matched: u64,
+    done: bool,
+}
+
+impl Query {
+    fn new(store: &Store, shards: Vec<ShardInfo>, region: Option<Region>, span: Span) -> Self {
+        Query {
+            dir: store.dir.clone(),
+            format: store.manifest.format,
+            bloom_filters: store.manifest.bloom_filters,
+            filter: None,
+            columns: None,
+            pending: store.pending.clone(),
+            added: None,
+            cursor: Cursor::Start,
+            from: Cursor::Start,
+            shards: shards.into_iter(),
+            current: None,
+            region,
+            epoch: None,
+            limits: QueryLimits::default(),
+            shards_read: 0,
+            records_read: 0,
+            truncated: None,
+            span,
+            start: Instant::now(),
+            matched: 0,
+            done: false,
+        }
+    }
+
+    /// Only the records which match a filter, skipping the shards which
+    /// cannot: those whose zone maps rule the filter out, and those whose
+    /// Bloom filters hold none of the sources it requires.
+    pub fn matching(mut self, filter: &Filter) -> Self {
+        let sources = filter.source_ids();
+        let read = self.shards.len();
+        let (dir, bloom_filters) = (&self.dir, self.bloom_filters);
+        let shards = self
+            .shards
+            .by_ref()
+            .filter(|shard| filter.may_match(&shard.zones))
+            .filter(|shard| match (&sources, bloom_filters) {
+                (Some(sources), true) => may_hold_any(dir, shard, sources),
+                _ => true,
+            })
+            .collect::<Vec<_>>();
+        self.span.in_scope(|| {
+            tracing::debug!(
+                filter = ?filter,
+                shards = shards.len(),
+                skipped = read - shards.len(),
+                "shards pruned"
+            )
+        });
+        self.shards = shards.into_iter();
+        self.filter = Some(filter.clone());
+        self
+    }
+
+    /// Only the columns of a projection, which are all that is read of
+    /// columnar (Parquet) shards, along with the columns the query tests.
+    /// The other columns of records read from those shards are cleared (as
+    /// by `Projection::clear_others`); records read from other shards, or
+    /// from the log, are returned whole.
+    pub fn select(mut self, projection: &Projection) -> Self {
+        self.columns = Some(projection.clone());
+        self
+    }
+
+    /// Columns to read of each shard: those selected, and those the query
+    /// tests.
+    fn read_columns(&self) -> Result<Option<Projection>, Error> {
+        let mut columns = match &self.columns {
+            Some(columns) => columns.clone().with_position(),
+            None => return Ok(None),
+        };
+        let mut tested = vec!["source_id"];
+        if self.epoch.is_some() {
+            tested.extend(&["pmra", "pmdec", "ref_epoch"]);
+        }
+        if let Some(filter) = &self.filter {
+            tested.extend(filter.columns());
+        }
+        for name in tested {
+            columns = columns.with_column(name)?;
+        }
+        Ok(Some(columns))
+    }
+
+    /// Stop at the first of some limits which the query reaches (see
+    /// `store::limits`).
+    pub fn with_limits(mut self, limits: QueryLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Limit which stopped the query before it returned all of its records,
+    /// if any. The query may be continued from its `cursor`.
+    pub fn truncated(&self) -> Option<Truncation> {
+        self.truncated
+    }
+
+    /// Stop the query at a limit, with a cursor from which to continue it.
+    fn truncate(
+        &mut self,
+        truncation: Truncation,
+        cursor: Cursor,
+    ) -> Option<Result<GaiaRecord, Error>> {
+        self.truncated = Some(truncation);
+        self.cursor = cursor;
+        self.current = None;
+        self.done = true;
+        self.span.in_scope(|| {
+            tracing::info!(
+                matched = self.matched,
+                shards = self.shards_read,
+                elapsed_ms = self.start.elapsed().as_secs_f64() * 1000.0,
+                limit = %truncation,
+                "query truncated"
+            )
+        });
+        None
+    }
+
+    pub fn stats(&self) -> QueryStats {
+        QueryStats {
+            shards: self.shards_read,
+            read: self.records_read,
+            matched: self.matched,
+            elapsed: self.start.elapsed(),
+        }
+    }
+
+    /// Position after the records returned so far.
+    pub fn cursor(&self) -> Cursor {
+        self.cursor
+    }
+
+    /// Only the records after a cursor of another query of the store with
+    /// the same arguments.
+    pub fn after(mut self, cursor: Cursor) -> Self {
+        let shards = match cursor {
+            Cursor::Start => return self,
+            Cursor::Shard { pixel, .. } => {
+                self.shards.by_ref().filter(|s| s.pixel >= pixel).collect()
+            }
+            Cursor::Log(_) => Vec::new(),
+        };
+        self.shards = shards.into_iter();
+        self.cursor = cursor;
+        self.from = cursor;
+        self
+    }
+}
+
+/// Whether the Bloom filter of a shard might hold any of some sources. A
+/// filter which cannot be read holds them all.
+fn may_hold_any(dir: &Path, shard: &ShardInfo, sources: &[u64]) -> bool {
+    let path = dir.join(shard.bloom_file_name());
+    match BloomFilter::read(&path) {
+        Ok(filter) => sources.iter().any(|&source_id| filter.contains(source_id)),
+        Err(e) => {
+            tracing::warn!(path = %path.display(), error = %e, "cannot read Bloom filter");
+            true
+        }
+    }
+}
+
+/// Whether a record is in the region of a query, at its epoch, and matches
+/// its filter.
+fn matches(
+    region: &Option<Region>,
+    epoch: Option<f64>,
+    filter: &Option<Filter>,
+    record: &GaiaRecord,
+) -> bool {
+    let position = match epoch {
+        Some(epoch) => record.position_at(epoch),
+        None => record.position(),
+    };
+    region.as_ref().is_none_or(|r| r.contains(&position))
+        && filter.as_ref().is_none_or(|f| f.matches(record))
+}
+
+impl Iterator for Query {
+    type Item = Result<GaiaRecord, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.truncated.is_some() {
+            return None;
+        }
+        let max_results = self.limits.max_results().unwrap_or(u64::MAX);
+        let deadline = self.limits.timeout().map(|timeout| self.start + timeout);
+        let timed_out = || deadline.is_some_and(|deadline| Instant::now() >= deadline);
+        loop {
+            if let Some(shard) = self.current.as_mut() {
+                loop {
+                    if timed_out() {
+                        let cursor = Cursor::Shard {
+                            pixel: shard.pixel,
+                            read: shard.read,
+                        };
+                        return self.truncate(Truncation::Timeout, cursor);
+                    }
+                    let record = match shard.records.next() {
+                        Some(record) => record,
+                        None => break,
+                    };
+                    shard.read += 1;
+                    self.records_read += 1;
+                    match record {
+                        Ok(_) if shard.read <= shard.skip => {}
+                        Ok(record) if self.pending.changed(record.source_id) => {}
+                        Ok(record) => {
+                            if !matches(&self.region, self.epoch, &self.filter, &record) {
+                                continue;
+                            }
+                            if self.matched == max_results {
+                                let cursor = Cursor::Shard {
+                                    pixel: shard.pixel,
+                                    read: shard.read - 1,
+                                };
+                                return self.truncate(Truncation::Results, cursor);
+                            }
+                            shard.matched += 1;
+                            self.matched += 1;
+                            self.cursor = Cursor::Shard {
+                                pixel: shard.pixel,
+                                read: shard.read,
+                            };
+                            return Some(Ok(record));
+                        }
+                        Err(e) => return Some(Err(e)),
+                    }
+                }
+                self.span.in_scope(|| {
+                    tracing::debug!(
+                        shard = %shard.path.display(),
+                        read = shard.read,
+                        matched = shard.matched,
+                        elapsed_ms = shard.start.elapsed().as_secs_f64() * 1000.0,
+                        "shard scanned"
+                    )
+                });
+                self.current = None;
+            }
+            let shard = match self.shards.next() {
+                Some(shard) => shard,
+                None => {
+                    let (region, epoch, filter) = (&self.region, self.epoch, &self.filter);
+                    let pending = &self.pending;
+                    let skip = match self.from {
+                        Cursor::Log(matched) => matched as usize,
+                        _ => 0,
+                    };
+                    let added = self.added.get_or_insert_with(|| {
+                        pending
+                            .records()
+                            .filter(|record| matches(region, epoch, filter, record))
+                            .skip(skip)
+                            .cloned()
+                            .collect::<Vec<_>>()
+                            .into_iter()
+                    });
+                    if let Some(record) = added.next() {
+                        let matched = match self.cursor {
+                            Cursor::Log(matched) => matched,
+                            _ => 0,
+                        };
+                        if self.matched == max_results {
+                            return self.truncate(Truncation::Results, Cursor::Log(matched));
+                        }
+                        self.matched += 1;
+                        self.cursor = Cursor::Log(matched + 1);
+                        return Some(Ok(record));
+                    }
+                    if !self.done {
+                        self.done = true;
+                        self.span.in_scope(|| {
+                            tracing::info!(
+                                matched = self.matched,
+                                elapsed_ms = self.start.elapsed().as_secs_f64() * 1000.0,
+                                "query done"
+                            )
+                        });
+                    }
+                    return None;
+                }
+            };
+            let path = self.dir.join(shard.file_name(self.format));
+            let skip = match self.from {
+                Cursor::Shard { pixel, read } if pixel == shard.pixel => read,
+                _ => 0,
+            };
+            let truncation = if Some(self.shards_read) == self.limits.max_shards() {
+                Some(Truncation::Shards)
+            } else if timed_out() {
+                Some(Truncation::Timeout)
+            } else {
+                None
+            };
+            if let Some(truncation) = truncation {
+                let cursor = Cursor::Shard {
+                    pixel: shard.pixel,
+                    read: skip,
+                };
+                return self.truncate(truncation, cursor);
+            }
+            self.shards_read += 1;
+            let records = match self.read_columns() {
+                Ok(Some(columns)) => read_columns(&path, self.format, &columns),
+                Ok(None) => read_shard(&path, self.format),
+                Err(e) => Err(e),
+            };
+            match records {
+                Ok(records) => {
+                    self.current = Some(ShardScan {
+                        pixel: shard.pixel,
+                        path,
+                        records,
+                        start: Instant::now(),
+                        read: 0,
+                        skip,
+                        matched: 0,
+                    })
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use gaia::filter::Filter;
+    use gaia::projection::Projection;
+    use gaia::record::test::sample_record;
+    use geom::rect::Rect;
+    use sky::position::SkyPosition;
+    use sky::region::Region;
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::time::Duration;
+    use store::builder::StoreBuilder;
+    use store::limits::{QueryLimits, Truncation};
+    use store::manifest::{
+        Checkpoint, ShardFormat, ShardInfo, CHANGE_LOG, FORMAT_VERSION, MANIFEST_FILE,
+    };
+    use store::{Cursor, Error, Query, Store};
+
+    #[test]
+    fn build_and_query() {
+        let dir = env::temp_dir().join(format!("starquad-store-test-{}", std::process::id()));
+        let mut builder = StoreBuilder::new(&dir).unwrap().with_order(1);
+        for i in 0..360 {
+            let mut record = sample_record();
+            record.source_id = i;
+            record.ra = i as f64;
+            record.dec = (i as f64) / 4.0 - 45.0;
+            builder.push(&record).unwrap();
+        }
+        let manifest = builder.finish().unwrap();
+        assert_eq!(manifest.records(), 360);
+        assert!(manifest.shards.len() > 1);
+
+        let store = Store::open(&dir).unwrap();
+        assert_eq!(store.manifest(), &manifest);
+        let rect = Rect::new(100.0, -90.0, 10.0, 180.0).unwrap();
+        let mut ids = store
+            .query_rect(&rect)
+            .map(|r| r.unwrap().source_id)
+            .collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(ids, (100..110).collect::<Vec<_>>());
+        let cone = Region::cone(SkyPosition::new(200.0, 5.0), 1.0);
+        let found = store
+            .query(&cone)
+            .map(|r| r.unwrap().source_id)
+            .collect::<Vec<_>>();
+        assert_eq!(found, vec![200]);
+        assert_eq!(store.scan().count(), 360);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn resume() {
+        let dir = env::temp_dir().join(format!("starquad-store-resume-{}", std::process::id()));
+        let record = |i: u64| {
+            let mut record = sample_record();
+            record.source_id = i;
+            record.ra = i as f64;
+            record.dec = 0.0;
+            record
+        };
+        let mut builder = StoreBuilder::new(&dir).unwrap().with_order(1);
+        for i in 0..100 {
+            builder.push(&record(i)).unwrap();
+        }
+        let checkpoint = Checkpoint {
+            inputs: vec![PathBuf::from("a.csv"), PathBuf::from("b.csv")],
+            files: 1,
+            records: 50,
+        };
+        builder.finish_with_checkpoint(checkpoint.clone()).unwrap();
+        // an interrupted build can be queried
+        assert_eq!(Store::open(&dir).unwrap().scan().count(), 100);
+
+        let (mut builder, resumed) = StoreBuilder::resume(&dir).unwrap();
+        assert_eq!(resumed, checkpoint);
+        for i in 100..360 {
+            builder.push(&record(i)).unwrap();
+        }
+        let manifest = builder.finish().unwrap();
+        assert_eq!(manifest.checkpoint, None);
+        assert_eq!(manifest.order, 1);
+        assert_eq!(manifest.stats.rows(), 360);
+        let mut ids = Store::open(&dir)
+            .unwrap()
+            .scan()
+            .map(|r| r.unwrap().source_id)
+            .collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(ids, (0..360).collect::<Vec<_>>());
+        assert!(StoreBuilder::resume(&dir).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn query_at_epoch() {
+        let dir = env::temp_dir().join(format!("starquad-store-epoch-{}", std::process::id()));
+        let mut builder = StoreBuilder::new(&dir).unwrap().with_order(3);
+        let mut fast = sample_record();
+        fast.source_id = 1;
+        fast.ra = 45.0;
+        fast.dec = 0.0;
+        // a degree east every century
+        fast.pmra = Some(36_000.0);
+        fast.pmdec = Some(0.0);
+        let mut slow = fast.clone();
+        slow.source_id = 2;
+        slow.ra = 46.0;
+        slow.pmra = None;
+        builder.push(&fast).unwrap();
+        builder.push(&slow).unwrap();
+        builder.finish().unwrap();
+
+        let store = Store::open(&dir).unwrap();
+        assert_eq!(store.max_proper_motion(), 36_000.0);
+        let ids = |query: Query| query.map(|r| r.unwrap().source_id).collect::<Vec<_>>();
+        let cone = Region::cone(SkyPosition::new(46.0, 0.0), 0.1);
+        assert_eq!(ids(store.query(&cone)), vec![2]);
+        let mut found = ids(store.query_at(&cone, 2115.5));
+        found.sort();
+        assert_eq!(found, vec![1, 2]);
+        let start = Region::cone(SkyPosition::new(45.0, 0.0), 0.1);
+        assert_eq!(ids(store.query_at(&start, 2115.5)), Vec::<u64>::new());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Formats of the shards of stores built by the tests.
+    fn formats() -> Vec<ShardFormat> {
+        #[allow(unused_mut)]
+        let mut formats = vec![ShardFormat::Bincode, ShardFormat::Csv];
+        #[cfg(feature = "zstd")]
+        formats.push(ShardFormat::Zstd);
+        formats
+    }
+
+    #[test]
+    fn lookup() {
+        let record = |i: u64| {
+            let mut record = sample_record();
+            record.source_id = 1000 + i;
+            record.ra = i as f64;
+            record.dec = (i % 90) as f64;
+            record
+        };
+        for format in formats() {
+            let dir = env::temp_dir().join(format!(
+                "starquad-store-lookup-{:?}-{}",
+                format,
+                std::process::id()
+            ));
+            let mut builder = StoreBuilder::new(&dir)
+                .unwrap()
+                .with_order(1)
+                .with_format(format)
+                .with_source_index()
+                .unwrap();
+            for i in 0..100 {
+                builder.push(&record(i)).unwrap();
+            }
+            if format == ShardFormat::Bincode {
+                // entries and offsets continue across a resumed build
+                builder
+                    .finish_with_checkpoint(Checkpoint::default())
+                    .unwrap();
+                builder = StoreBuilder::resume(&dir).unwrap().0;
+            }
+            for i in 100..360 {
+                builder.push(&record(i)).unwrap();
+            }
+            assert!(builder.finish().unwrap().source_index);
+
+            let store = Store::open(&dir).unwrap();
+            for i in [0, 99, 100, 255, 359] {
+                assert_eq!(store.lookup(1000 + i).unwrap(), Some(record(i)));
+            }
+            assert_eq!(store.lookup(999).unwrap(), None);
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn changes_and_compaction() {
+        let record = |i: u64, ra: f64| {
+            let mut record = sample_record();
+            record.source_id = i;
+            record.ra = ra;
+            record.dec = 0.0;
+            record
+        };
+        for format in formats() {
+            let dir = env::temp_dir().join(format!(
+                "starquad-store-wal-{:?}-{}",
+                format,
+                std::process::id()
+            ));
+            let mut builder = StoreBuilder::new(&dir)
+                .unwrap()
+                .with_order(1)
+                .with_format(format);
+            if format == ShardFormat::Bincode {
+                builder = builder.with_source_index().unwrap();
+            }
+            for i in 0..360 {
+                builder.push(&record(i, i as f64)).unwrap();
+            }
+            builder.finish().unwrap();
+
+            let mut store = Store::open(&dir).unwrap();
+            // a new source, a source moved to another shard, and one removed
+            store.push(&record(1000, 10.5)).unwrap();
+            store.push(&record(20, 200.5)).unwrap();
+            store.remove(30).unwrap();
+            assert_eq!(store.pending().len(), 3);
+
+            let ids = |store: &Store, ra: f64| {
+                let region = Region::cone(SkyPosition::new(ra, 0.0), 1.1);
+                let mut ids = store
+                    .query(&region)
+                    .map(|r| r.unwrap().source_id)
+                    .collect::<Vec<_>>();
+                ids.sort();
+                ids
+            };
+            let check = |store: &Store| {
+                assert_eq!(ids(store, 10.0), vec![9, 10, 11, 1000]);
+                assert_eq!(ids(store, 20.0), vec![19, 21]);
+                assert_eq!(ids(store, 30.0), vec![29, 31]);
+                assert_eq!(ids(store, 200.0), vec![20, 199, 200, 201]);
+                assert_eq!(store.scan().count(), 360);
+                assert_eq!(store.lookup(20).unwrap(), Some(record(20, 200.5)));
+                assert_eq!(store.lookup(30).unwrap(), None);
+                assert_eq!(store.lookup(31).unwrap(), Some(record(31, 31.0)));
+            };
+            check(&store);
+            // the changes are read back from the log
+            let mut store = Store::open(&dir).unwrap();
+            check(&store);
+
+            let manifest = store.compact().unwrap();
+            assert!(store.pending().is_empty());
+            assert_eq!(manifest.records(), 360);
+            assert_eq!(Store::open(&dir).unwrap().manifest(), &manifest);
+            check(&store);
+            check(&Store::open(&dir).unwrap());
+            assert!(fs::read_dir(&dir).unwrap().all(|f| !f
+                .unwrap()
+                .path()
+                .to_string_lossy()
+                .ends_with(".tmp")));
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn zone_maps_and_bloom_filters() {
+        let record = |i: u64, ra: f64, g: f64| {
+            let mut record = sample_record();
+            record.source_id = i;
+            record.ra = ra;
+            record.dec = 0.0;
+            record.phot_g_mean_mag = g;
+            record
+        };
+        let dir = env::temp_dir().join(format!("starquad-store-zones-{}", std::process::id()));
+        let mut builder = StoreBuilder::new(&dir)
+            .unwrap()
+            .with_order(1)
+            .with_bloom_filters();
+        for i in 0..360 {
+            let g = if i < 180 { 10.0 } else { 20.0 };
+            builder.push(&record(i, i as f64, g)).unwrap();
+        }
+        let manifest = builder.finish().unwrap();
+        let faint = |shard: &ShardInfo| shard.zones[0].max == Some(20.0);
+        let faint_shards = manifest.shards.iter().filter(|s| faint(s)).count();
+        assert!(faint_shards > 0 && faint_shards < manifest.shards.len());
+
+        let mut store = Store::open(&dir).unwrap();
+        let filter = "phot_g_mean_mag > 15".parse::<Filter>().unwrap();
+        let check = |store: &Store, faint_shards: usize, records: usize| {
+            let query = store.scan().matching(&filter);
+            assert_eq!(query.shards.len(), faint_shards);
+            assert_eq!(query.count(), records);
+            let query = store.scan().matching(&Filter::source_id(300));
+            assert!(query.shards.len() < 3);
+            assert_eq!(
+                query.map(|r| r.unwrap().source_id).collect::<Vec<_>>(),
+                vec![300]
+            );
+            assert_eq!(store.lookup(300).unwrap(), Some(record(300, 300.0, 20.0)));
+        };
+        check(&store, faint_shards, 180);
+
+        // pushed records are matched before compaction, and summarized by it
+        store.push(&record(1000, 10.5, 20.0)).unwrap();
+        check(&store, faint_shards, 181);
+        let manifest = store.compact().unwrap();
+        assert_eq!(
+            manifest.shards.iter().filter(|s| faint(s)).count(),
+            faint_shards + 1
+        );
+        check(&store, faint_shards + 1, 181);
+        assert_eq!(store.lookup(1000).unwrap(), Some(record(1000, 10.5, 20.0)));
+        assert!(StoreBuilder::new(&dir)
+            .unwrap()
+            .with_zone_maps(&["designation"])
+            .is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn pages() {
+        let dir = env::temp_dir().join(format!("starquad-store-pages-{}", std::process::id()));
+        let mut builder = StoreBuilder::new(&dir).unwrap().with_order(1);
+        for i in 0..100 {
+            let mut record = sample_record();
+            record.source_id = i;
+            record.ra = i as f64 * 3.6;
+            record.parallax = Some((i % 10) as f64);
+            builder.push(&record).unwrap();
+        }
+        builder.finish().unwrap();
+        let mut store = Store::open(&dir).unwrap();
+        for i in [7, 100, 101] {
+            let mut record = sample_record();
+            record.source_id = i;
+            record.ra = 10.0;
+            record.parallax = Some(5.0);
+            store.push(&record).unwrap();
+        }
+
+        let filter = "parallax > 4".parse::<Filter>().unwrap();
+        let query = || store.scan().matching(&filter);
+        let all = query().map(|r| r.unwrap().source_id).collect::<Vec<_>>();
+        assert_eq!(all.len(), 52);
+        let mut paged = Vec::new();
+        let mut cursor = Cursor::Start;
+        loop {
+            let mut page = query().after(cursor);
+            let ids = page
+                .by_ref()
+                .take(7)
+                .map(|r| r.unwrap().source_id)
+                .collect::<Vec<_>>();
+            if ids.is_empty() {
+                break;
+            }
+            paged.extend(ids);
+            cursor = page.cursor().to_string().parse().unwrap();
+        }
+        assert_eq!(paged, all);
+        assert!(matches!(cursor, Cursor::Log(3)));
+        assert_eq!("17-4".parse(), Ok(Cursor::Shard { pixel: 17, read: 4 }));
+        assert!("17".parse::<Cursor>().is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn selected_columns() {
+        #[allow(unused_mut)]
+        let mut formats = formats();
+        #[cfg(feature = "parquet")]
+        formats.push(ShardFormat::Parquet);
+        let region = Region::sky_box(0.0, 360.0, -10.0, 10.0).unwrap();
+        let filter = "parallax > 4".parse::<Filter>().unwrap();
+        let projection = Projection::new(&["phot_g_mean_mag"]).unwrap();
+        for format in formats {
+            let dir = env::temp_dir().join(format!(
+                "starquad-store-select-{:?}-{}",
+                format,
+                std::process::id()
+            ));
+            let mut builder = StoreBuilder::new(&dir)
+                .unwrap()
+                .with_order(1)
+                .with_format(format);
+            for i in 0..100 {
+                let mut record = sample_record();
+                record.source_id = i;
+                record.ra = i as f64 * 3.6;
+                record.parallax = Some((i % 10) as f64);
+                record.pmra = Some(1.0);
+                record.pmdec = Some(1.0);
+                record.phot_g_mean_mag = 10.0 + i as f64;
+                builder.push(&record).unwrap();
+            }
+            builder.finish().unwrap();
+            let store = Store::open(&dir).unwrap();
+            let records = store
+                .query_at(&region, 2016.0)
+                .select(&projection)
+                .matching(&filter)
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            assert_eq!(records.len(), 50);
+            for record in &records {
+                // the selected columns, and those the query tests, are read
+                assert_eq!(record.phot_g_mean_mag, 10.0 + record.source_id as f64);
+                assert_eq!(record.ra, record.source_id as f64 * 3.6);
+                assert!(record.parallax > Some(4.0));
+                assert_eq!(record.pmra, Some(1.0));
+                assert_eq!(record.ref_epoch, "2015.5");
+                assert_eq!(record.designation.is_empty(), format.is_columnar());
+            }
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn limits() {
+        let dir = env::temp_dir().join(format!("starquad-store-limits-{}", std::process::id()));
+        let mut builder = StoreBuilder::new(&dir).unwrap().with_order(1);
+        for i in 0..100 {
+            let mut record = sample_record();
+            record.source_id = i;
+            record.ra = i as f64 * 3.6;
+            builder.push(&record).unwrap();
+        }
+        builder.finish().unwrap();
+        let mut store = Store::open(&dir).unwrap();
+        for i in [100, 101, 102] {
+            let mut record = sample_record();
+            record.source_id = i;
+            store.push(&record).unwrap();
+        }
+        let ids = |query: &mut Query| query.map(|r| r.unwrap().source_id).collect::<Vec<_>>();
+        let mut all = store.scan();
+        let all_ids = ids(&mut all);
+        assert_eq!(all_ids.len(), 103);
+        assert_eq!(all.truncated(), None);
+        let shards = store.manifest().shards.len();
+        let stats = all.stats();
+        assert_eq!(
+            (stats.shards, stats.read, stats.matched),
+            (shards, 100, 103)
+        );
+        for (limits, truncation, queries) in [
+            (
+                QueryLimits::new().with_max_results(10),
+                Truncation::Results,
+                11,
+            ),
+            (
+                QueryLimits::new().with_max_shards(3),
+                Truncation::Shards,
+                shards.div_ceil(3),
+            ),
+        ] {
+            // each query stops at the limit, and the next continues it
+            let mut read = Vec::new();
+            let mut cursor = Cursor::Start;
+            for i in 0..queries {
+                let mut query = store.scan().with_limits(limits).after(cursor);
+                read.extend(ids(&mut query));
+                let last = i + 1 == queries;
+                assert_eq!(query.truncated(), Some(truncation).filter(|_| !last));
+                cursor = query.cursor();
+            }
+            assert_eq!(read, all_ids);
+        }
+
+        let mut query = store
+            .scan()
+            .with_limits(QueryLimits::new().with_timeout(Duration::ZERO));
+        assert!(ids(&mut query).is_empty());
+        assert_eq!(query.truncated(), Some(Truncation::Timeout));
+        assert_eq!(ids(&mut store.scan().after(query.cursor())), all_ids);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn format_versions() {
+        let dir = env::temp_dir().join(format!("starquad-store-version-{}", std::process::id()));
+        let mut builder = StoreBuilder::new(&dir)
+            .unwrap()
+            .with_version(1)
+            .unwrap()
+            .with_order(1);
+        builder.push(&sample_record()).unwrap();
+        let manifest = builder.finish().unwrap();
+        assert_eq!((manifest.version, manifest.requires.len()), (1, 0));
+        let path = dir.join(MANIFEST_FILE);
+        // as written before versions were recorded
+        assert!(!fs::read_to_string(&path).unwrap().contains("version"));
+
+        let mut store = Store::open(&dir).unwrap();
+        assert_eq!(store.manifest(), &manifest);
+        assert!(matches!(store.remove(1), Err(Error::UnsupportedFormat(_))));
+        store.set_version(FORMAT_VERSION).unwrap();
+        store.remove(1).unwrap();
+        assert!(Store::open(&dir).unwrap().manifest().requires(CHANGE_LOG));
+        assert!(matches!(
+            store.set_version(1),
+            Err(Error::UnsupportedFormat(_))
+        ));
+        store.compact().unwrap();
+        assert!(Store::open(&dir).unwrap().manifest().requires.is_empty());
+        store.set_version(1).unwrap();
+        assert_eq!(Store::open(&dir).unwrap().manifest().version, 1);
+        assert!(StoreBuilder::new(&dir).unwrap().with_version(0).is_err());
+
+        // later versions and unknown capabilities are refused
+        let json = fs::read_to_string(&path).unwrap();
+        let newer = json.replacen('{', "{\"version\": 99, \"shards\": \"changed\",", 1);
+        fs::write(&path, newer).unwrap();
+        assert!(matches!(
+            Store::open(&dir),
+            Err(Error::UnsupportedFormat(_))
+        ));
+        let unknown = json.replacen('{', "{\"version\": 2, \"requires\": [\"teleport\"],", 1);
+        fs::write(&path, unknown).unwrap();
+        match Store::open(&dir) {
+            Err(Error::UnsupportedFormat(message)) => assert!(message.contains("teleport")),
+            _ => panic!("unknown capability accepted"),
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd_shards_require_capability() {
+        use store::manifest::ZSTD_BLOCKS;
+
+        let dir = env::temp_dir().join(format!("starquad-store-zstd-{}", std::process::id()));
+        let builder = || {
+            StoreBuilder::new(&dir)
+                .unwrap()
+                .with_order(1)
+                .with_format(ShardFormat::Zstd)
+        };
+        let mut old = builder().with_version(1).unwrap();
+        assert!(matches!(
+            old.push(&sample_record()),
+            Err(Error::UnsupportedFormat(_))
+        ));
+        let mut builder = builder();
+        builder.push(&sample_record()).unwrap();
+        let manifest = builder.finish().unwrap();
+        assert_eq!(manifest.requires, vec![String::from(ZSTD_BLOCKS)]);
+
+        let mut store = Store::open(&dir).unwrap();
+        let position = SkyPosition::new(sample_record().ra, sample_record().dec);
+        assert_eq!(store.query(&Region::cone(position, 1.0)).count(), 1);
+        store.remove(sample_record().source_id).unwrap();
+        store.compact().unwrap();
+        assert_eq!(store.manifest().requires, vec![String::from(ZSTD_BLOCKS)]);
+        assert!(matches!(
+            store.set_version(1),
+            Err(Error::UnsupportedFormat(_))
+        ));
+        assert_eq!(
+            Store::open(&dir).unwrap().manifest().version,
+            FORMAT_VERSION
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn csv_shards_with_columns() {
+        let dir = env::temp_dir().join(format!("starquad-store-csv-{}", std::process::id()));
+        let mut builder = StoreBuilder::new(&dir)
+            .unwrap()
+            .with_format(ShardFormat::Csv)
+            .with_columns(Projection::new(&["source_id"]).unwrap());
+        let mut record = sample_record();
+        record.source_id = 7;
+        record.ra = 20.0;
+        record.dec = 30.0;
+        record.parallax = Some(1.0);
+        builder.push(&record).unwrap();
+        let manifest = builder.finish().unwrap();
+        assert_eq!(
+            manifest.columns,
+            Some(vec![
+                String::from("source_id"),
+                String::from("ra"),
+                String::from("dec")
+            ])
+        );
+
+        let found = Store::open(&dir)
+            .unwrap()
+            .scan()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            (found[0].source_id, found[0].ra, found[0].dec),
+            (7, 20.0, 30.0)
+        );
+        assert_eq!(found[0].parallax, None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,277 @@
+//! Cache of the results of recent queries.
+//!
+//! Interactive users repeat nearly identical queries as they pan around a
+//! field. A `ResultCache` keeps the records of recent queries in memory,
+//! keyed by the canonical form of each query (`QueryKey`), so that a query
+//! asked again is answered without reading any shards. The least recently
+//! used results are dropped to keep the cache within a number of records,
+//! and results may also expire after a time, so that a cache of a store
+//! which is being updated is not stale for long.
+
+use gaia::filter::Filter;
+use gaia::projection::Projection;
+use gaia::record::GaiaRecord;
+use sky::region::Region;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use store::limits::Truncation;
+use store::Cursor;
+
+/// Default maximum number of records held by a `ResultCache`.
+pub const DEFAULT_MAX_RECORDS: u64 = 1_000_000;
+
+/// Canonical form of a query, from its region, filter and selected columns
+/// as parsed, so that queries written differently but asking for the same
+/// records share a key: filters differing only in spacing or the case of
+/// keywords, boxes across `ra = 0` with equivalent bounds, or the same
+/// columns in another order.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct QueryKey(String);
+
+impl QueryKey {
+    pub fn new(region: &Region, filter: Option<&Filter>, projection: &Projection) -> Self {
+        let mut columns = projection.columns().to_vec();
+        columns.sort_unstable();
+        QueryKey(format!("{:?} {:?} {:?}", region, filter, columns))
+    }
+}
+
+/// Records returned by a query.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueryResult {
+    pub records: Vec<GaiaRecord>,
+    /// Limit which stopped the query, and the cursor to continue it.
+    pub truncated: Option<(Truncation, Cursor)>,
+}
+
+struct Entry {
+    result: Arc<QueryResult>,
+    added: Instant,
+    last_used: u64,
+}
+
+/// Results of recent queries, bounded in records held and, optionally, in
+/// the time each is kept.
+pub struct ResultCache {
+    max_records: u64,
+    ttl: Option<Duration>,
+    entries: HashMap<QueryKey, Entry>,
+    records: u64,
+    clock: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl Default for ResultCache {
+    fn default() -> Self {
+        ResultCache::new(DEFAULT_MAX_RECORDS)
+    }
+}
+
+impl ResultCache {
+    /// Cache holding at most `max_records` records in all.
+    pub fn new(max_records: u64) -> Self {
+        ResultCache {
+            max_records,
+            ttl: None,
+            entries: HashMap::new(),
+            records: 0,
+            clock: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Drop results once they are this old.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn max_records(&self) -> u64 {
+        self.max_records
+    }
+
+    pub fn ttl(&self) -> Option<Duration> {
+        self.ttl
+    }
+
+    /// Number of results held.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Number of records held.
+    pub fn cached_records(&self) -> u64 {
+        self.records
+    }
+
+    /// Number of lookups which found a result.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Number of lookups which found no result.
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.records = 0;
+    }
+
+    /// Result of a query, if it is held and has not expired.
+    pub fn get(&mut self, key: &QueryKey) -> Option<Arc<QueryResult>> {
+        let expired = match (self.entries.get(key), self.ttl) {
+            (Some(entry), Some(ttl)) => entry.added.elapsed() >= ttl,
+            _ => false,
+        };
+        if expired {
+            self.remove(key);
+        }
+        self.clock += 1;
+        match self.entries.get_mut(key) {
+            Some(entry) => {
+                entry.last_used = self.clock;
+                self.hits += 1;
+                Some(entry.result.clone())
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Keep the result of a query, dropping the least recently used results
+    /// to make room for it. Returns whether it was kept: a result of more
+    /// than `max_records` records is not.
+    pub fn insert(&mut self, key: QueryKey, result: Arc<QueryResult>) -> bool {
+        let records = result.records.len() as u64;
+        if records > self.max_records {
+            return false;
+        }
+        self.remove(&key);
+        if self.records + records > self.max_records {
+            let mut entries = self
+                .entries
+                .iter()
+                .map(|(key, entry)| (entry.last_used, key.clone()))
+                .collect::<Vec<_>>();
+            entries.sort_unstable_by_key(|(last_used, _)| *last_used);
+            for (_, key) in entries {
+                if self.records + records <= self.max_records {
+                    break;
+                }
+                self.remove(&key);
+            }
+        }
+        self.clock += 1;
+        self.records += records;
+        self.entries.insert(
+            key,
+            Entry {
+                result,
+                added: Instant::now(),
+                last_used: self.clock,
+            },
+        );
+        true
+    }
+
+    fn remove(&mut self, key: &QueryKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.records -= entry.result.records.len() as u64;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use gaia::filter::Filter;
+    use gaia::projection::Projection;
+    use gaia::record::test::sample_record;
+    use sky::position::SkyPosition;
+    use sky::region::Region;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use store::results::{QueryKey, QueryResult, ResultCache};
+
+    fn result(records: usize) -> Arc<QueryResult> {
+        Arc::new(QueryResult {
+            records: vec![sample_record(); records],
+            truncated: None,
+        })
+    }
+
+    fn key(ra: f64) -> QueryKey {
+        let region = Region::cone(SkyPosition::new(ra, 0.0), 1.0);
+        QueryKey::new(&region, None, &Projection::all())
+    }
+
+    #[test]
+    fn keys() {
+        let filter = |text: &str| text.parse::<Filter>().unwrap();
+        let projection = |names: &[&str]| Projection::new(names).unwrap();
+        let region = Region::sky_box(350.0, 370.0, -5.0, 5.0).unwrap();
+        let same = Region::sky_box(-10.0, 10.0, -5.0, 5.0).unwrap();
+        assert_eq!(
+            QueryKey::new(
+                &region,
+                Some(&filter("parallax > 1 and phot_g_mean_mag < 15")),
+                &projection(&["ra", "dec"])
+            ),
+            QueryKey::new(
+                &same,
+                Some(&filter("parallax>1 AND phot_g_mean_mag<15")),
+                &projection(&["dec", "ra"])
+            )
+        );
+        let other = Region::sky_box(-10.0, 11.0, -5.0, 5.0).unwrap();
+        assert_ne!(
+            QueryKey::new(&region, None, &Projection::all()),
+            QueryKey::new(&other, None, &Projection::all())
+        );
+        assert_ne!(
+            QueryKey::new(&region, None, &Projection::all()),
+            QueryKey::new(&region, Some(&filter("parallax > 1")), &Projection::all())
+        );
+    }
+
+    #[test]
+    fn least_recently_used() {
+        let mut cache = ResultCache::new(10);
+        assert!(cache.insert(key(1.0), result(4)));
+        assert!(cache.insert(key(2.0), result(4)));
+        assert_eq!(cache.get(&key(1.0)).unwrap().records.len(), 4);
+        // the least recently used result makes room for the next
+        assert!(cache.insert(key(3.0), result(4)));
+        assert!(cache.get(&key(2.0)).is_none());
+        assert!(cache.get(&key(1.0)).is_some());
+        assert_eq!((cache.len(), cache.cached_records()), (2, 8));
+        assert_eq!((cache.hits(), cache.misses()), (2, 1));
+        assert!(!cache.insert(key(4.0), result(11)));
+        assert!(cache.insert(key(1.0), result(10)));
+        assert_eq!((cache.len(), cache.cached_records()), (1, 10));
+        cache.clear();
+        assert!(cache.is_empty());
+        assert_eq!(cache.cached_records(), 0);
+    }
+
+    #[test]
+    fn expiry() {
+        let mut cache = ResultCache::new(10).with_ttl(Duration::ZERO);
+        cache.insert(key(1.0), result(1));
+        assert!(cache.get(&key(1.0)).is_none());
+        assert_eq!(cache.cached_records(), 0);
+        let mut cache = ResultCache::new(10).with_ttl(Duration::from_secs(3600));
+        cache.insert(key(1.0), result(1));
+        assert!(cache.get(&key(1.0)).is_some());
+    }
+}
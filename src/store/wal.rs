@@ -0,0 +1,464 @@
+//! Write-ahead log of changes to a store.
+//!
+//! A store is built once, but catalog supplements add, correct and retract
+//! sources a few at a time. Rather than rebuilding the store for each,
+//! changes are appended to a log in the store directory (`changes.wal`),
+//! which queries of the store read alongside its shards. Compaction later
+//! rewrites just the shards the changes touch, and empties the log.
+//!
+//! Each entry of the log is a batch of changes, written as its length and
+//! CRC-32 as little-endian `u32`s followed by the bincode-encoded changes,
+//! and synced to disk before it is acknowledged. An entry cut short by a
+//! crash fails its checksum, and it and anything after it are ignored.
+
+use gaia::projection::Projection;
+use gaia::record::GaiaRecord;
+use output;
+use serde::{Deserialize, Serialize};
+use sky::healpix;
+use sky::position::SkySource;
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+use store::bloom::BloomFilter;
+use store::builder::ShardWriter;
+use store::lookup::{self, SourceEntry, SourceIndex, SOURCE_INDEX_FILE};
+use store::manifest::{Manifest, ShardInfo, CHANGE_LOG};
+use store::{read_shard, temp_path, Error, Store};
+
+/// Name of the log file in a store directory.
+pub const WAL_FILE: &str = "changes.wal";
+
+/// Change to the records of a store.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Change {
+    /// Add a record, replacing any record of the same source.
+    Push(Box<GaiaRecord>),
+    /// Remove the record of a source, if there is one.
+    Remove(u64),
+}
+
+/// Changes of a log which are not yet in the shards of its store, by
+/// source: the latest record pushed for the source, or `None` if it was
+/// removed since.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Pending {
+    sources: BTreeMap<u64, Option<GaiaRecord>>,
+}
+
+impl Pending {
+    pub fn apply(&mut self, change: Change) {
+        match change {
+            Change::Push(record) => self.sources.insert(record.source_id, Some(*record)),
+            Change::Remove(source_id) => self.sources.insert(source_id, None),
+        };
+    }
+
+    /// Number of sources changed.
+    pub fn len(&self) -> usize {
+        self.sources.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+
+    /// Whether the record of a source in the shards is replaced or removed.
+    pub fn changed(&self, source_id: u64) -> bool {
+        self.sources.contains_key(&source_id)
+    }
+
+    /// Change to a source: `Some(Some(record))` if it was pushed,
+    /// `Some(None)` if it was removed, and `None` if it is unchanged.
+    pub fn get(&self, source_id: u64) -> Option<Option<&GaiaRecord>> {
+        self.sources.get(&source_id).map(Option::as_ref)
+    }
+
+    /// Records pushed and not since removed, in order of `source_id`.
+    pub fn records(&self) -> impl Iterator<Item = &GaiaRecord> {
+        self.sources.values().flatten()
+    }
+}
+
+/// Log of changes to a store, open for appending.
+#[derive(Debug)]
+pub struct WriteAheadLog {
+    file: File,
+}
+
+impl WriteAheadLog {
+    /// Open the log of a store directory, creating it if need be, and read
+    /// the changes in it. A torn entry at the end, left by a crash while it
+    /// was written, is cut off.
+    pub fn open(dir: &Path) -> Result<(Self, Pending), Error> {
+        let path = dir.join(WAL_FILE);
+        let (pending, len) = read_entries(&path)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(&path)?;
+        if file.metadata()?.len() > len {
+            tracing::warn!(path = %path.display(), len, "cutting off a torn log entry");
+            file.set_len(len)?;
+            file.sync_data()?;
+        }
+        let mut wal = WriteAheadLog { file };
+        wal.seek_end()?;
+        Ok((wal, pending))
+    }
+
+    fn seek_end(&mut self) -> io::Result<()> {
+        io::Seek::seek(&mut self.file, io::SeekFrom::End(0)).map(|_| ())
+    }
+
+    /// Append a batch of changes as one entry, and sync it to disk: once
+    /// this returns, the changes survive a crash.
+    pub fn append(&mut self, changes: &[Change]) -> Result<(), Error> {
+        let bytes = bincode::serialize(changes).map_err(output::Error::from)?;
+        let len = u32::try_from(bytes.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "batch too large"))?;
+        let mut entry = Vec::with_capacity(bytes.len() + 8);
+        entry.extend_from_slice(&len.to_le_bytes());
+        entry.extend_from_slice(&crc32fast::hash(&bytes).to_le_bytes());
+        entry.extend_from_slice(&bytes);
+        self.file.write_all(&entry)?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    /// Empty the log, once its changes are in the shards.
+    pub fn clear(&mut self) -> Result<(), Error> {
+        self.file.set_len(0)?;
+        self.file.sync_data()?;
+        self.seek_end()?;
+        Ok(())
+    }
+}
+
+/// Changes in the log of a store directory, which are none if it has no
+/// log.
+pub fn read(dir: &Path) -> Result<Pending, Error> {
+    Ok(read_entries(&dir.join(WAL_FILE))?.0)
+}
+
+/// Changes in a log file, and its length up to the end of the last whole
+/// entry.
+fn read_entries(path: &Path) -> Result<(Pending, u64), Error> {
+    let mut pending = Pending::default();
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok((pending, 0)),
+        Err(e) => return Err(e.into()),
+    };
+    let file_len = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+    let mut len = 0;
+    loop {
+        let mut header = [0; 8];
+        if reader.read_exact(&mut header).is_err() {
+            break;
+        }
+        let size = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let crc = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        if len + 8 + u64::from(size) > file_len {
+            break;
+        }
+        let mut bytes = vec![0; size as usize];
+        if reader.read_exact(&mut bytes).is_err() || crc32fast::hash(&bytes) != crc {
+            break;
+        }
+        let changes: Vec<Change> = bincode::deserialize(&bytes).map_err(output::Error::from)?;
+        for change in changes {
+            pending.apply(change);
+        }
+        len += 8 + u64::from(size);
+    }
+    Ok((pending, len))
+}
+
+impl Store {
+    /// Changes in the log of the store which are not yet in its shards.
+    pub fn pending(&self) -> &Pending {
+        &self.pending
+    }
+
+    /// Add a record through the log, replacing any record of the same
+    /// source.
+    pub fn push(&mut self, record: &GaiaRecord) -> Result<(), Error> {
+        self.apply(vec![Change::Push(Box::new(record.clone()))])
+    }
+
+    /// Remove the record of a source through the log.
+    pub fn remove(&mut self, source_id: u64) -> Result<(), Error> {
+        self.apply(vec![Change::Remove(source_id)])
+    }
+
+    /// Append a batch of changes to the log, with a single sync, and apply
+    /// them to later queries of the store.
+    ///
+    /// Only the columns stored by the store are kept of the records pushed.
+    /// Queries already running see the store as it was when they started.
+    /// Only one process should change a store at a time.
+    pub fn apply(&mut self, mut changes: Vec<Change>) -> Result<(), Error> {
+        if self.manifest.version < 2 {
+            return Err(Error::UnsupportedFormat(String::from(
+                "version 1 has no change log; see `Store::set_version`",
+            )));
+        }
+        if !self.manifest.requires(CHANGE_LOG) {
+            // readers which cannot apply the log must not read the shards
+            self.manifest.requires.push(String::from(CHANGE_LOG));
+            self.manifest.write(&self.dir)?;
+        }
+        if let Some(names) = &self.manifest.columns {
+            let projection = Projection::new(names)?;
+            for change in &mut changes {
+                if let Change::Push(record) = change {
+                    **record = projection.clear_others(record)?;
+                }
+            }
+        }
+        let wal = match self.wal.take() {
+            Some(wal) => wal,
+            None => {
+                let (wal, pending) = WriteAheadLog::open(&self.dir)?;
+                self.pending = Arc::new(pending);
+                wal
+            }
+        };
+        self.wal.insert(wal).append(&changes)?;
+        let pending = Arc::make_mut(&mut self.pending);
+        for change in changes {
+            pending.apply(change);
+        }
+        Ok(())
+    }
+
+    /// Rewrite the shards holding the changes in the log, with the source
+    /// index if there is one, and empty the log.
+    ///
+    /// Only the shards which gain or lose records are rewritten, but
+    /// without a source index every shard is read to find the records
+    /// replaced or removed. The new files are written alongside the old and
+    /// renamed over them, and the log is emptied last, so a crash during
+    /// compaction leaves a store which reads the same. The statistics of the
+    /// store still count the records removed.
+    pub fn compact(&mut self) -> Result<Manifest, Error> {
+        if self.manifest.checkpoint.is_some() {
+            return Err(Error::Incomplete);
+        }
+        let (mut wal, pending) = match self.wal.take() {
+            Some(wal) => (wal, (*self.pending).clone()),
+            None => WriteAheadLog::open(&self.dir)?,
+        };
+        if pending.is_empty() {
+            self.wal = Some(wal);
+            self.release_change_log()?;
+            return Ok(self.manifest.clone());
+        }
+        let _span = tracing::info_span!("compact", dir = %self.dir.display()).entered();
+        let start = Instant::now();
+        let order = self.manifest.order;
+        let format = self.manifest.format;
+
+        // the shards which records are pushed to, and which hold the
+        // records replaced or removed
+        let mut pushed = BTreeMap::<u64, Vec<&GaiaRecord>>::new();
+        for record in pending.records() {
+            let pixel = healpix::pixel(order, &record.position());
+            pushed.entry(pixel).or_default().push(record);
+        }
+        let mut pixels = pushed.keys().copied().collect::<BTreeSet<_>>();
+        if self.manifest.source_index {
+            let mut index = SourceIndex::open(self.dir.join(SOURCE_INDEX_FILE))?;
+            for &source_id in pending.sources.keys() {
+                pixels.extend(index.find(source_id)?.map(|entry| entry.pixel));
+            }
+        } else {
+            pixels.extend(self.manifest.shards.iter().map(|s| s.pixel));
+        }
+
+        let mut shards = self
+            .manifest
+            .shards
+            .iter()
+            .map(|s| (s.pixel, s.clone()))
+            .collect::<BTreeMap<_, _>>();
+        let mut rewritten = BTreeSet::new();
+        let mut entries = Vec::new();
+        for pixel in pixels {
+            let info = ShardInfo::new(pixel);
+            let path = self.dir.join(info.file_name(format));
+            let mut records = Vec::new();
+            let mut dropped = false;
+            if shards.contains_key(&pixel) {
+                for record in read_shard(&path, format)? {
+                    let record = record?;
+                    if pending.changed(record.source_id) {
+                        dropped = true;
+                    } else {
+                        records.push(record);
+                    }
+                }
+            }
+            let added = pushed.remove(&pixel).unwrap_or_default();
+            if !dropped && added.is_empty() {
+                continue;
+            }
+            let mut info = info.with_zones(&self.manifest.zone_maps);
+            let temp = temp_path(&path);
+            let mut writer = ShardWriter::create(&temp, format)?;
+            let mut bloom = self
+                .manifest
+                .bloom_filters
+                .then(|| BloomFilter::new((records.len() + added.len()) as u64));
+            for record in records.iter().chain(added) {
+                let offset = writer.write(record)?;
+                entries.push(SourceEntry {
+                    source_id: record.source_id,
+                    pixel,
+                    offset: offset.unwrap_or(info.records),
+                });
+                info.add_record(record);
+                if let Some(bloom) = bloom.as_mut() {
+                    bloom.insert(record.source_id);
+                }
+            }
+            writer.finish()?;
+            File::open(&temp)?.sync_all()?;
+            if let Some(bloom) = bloom {
+                let temp = temp_path(&self.dir.join(info.bloom_file_name()));
+                bloom.write(&temp)?;
+                File::open(&temp)?.sync_all()?;
+            }
+            tracing::debug!(pixel, records = info.records, "shard rewritten");
+            rewritten.insert(pixel);
+            shards.insert(pixel, info);
+        }
+        if self.manifest.source_index {
+            entries.sort_unstable();
+            lookup::rewrite(&self.dir, &rewritten, &entries)?;
+        }
+
+        // replace the shards, then the index and the manifest, and only then
+        // forget the changes
+        let mut manifest = self.manifest.clone();
+        for record in pending.records() {
+            manifest.stats.add(record);
+        }
+        for &pixel in &rewritten {
+            let info = ShardInfo::new(pixel);
+            let path = self.dir.join(info.file_name(format));
+            if shards[&pixel].records > 0 {
+                fs::rename(temp_path(&path), &path)?;
+                if self.manifest.bloom_filters {
+                    let path = self.dir.join(info.bloom_file_name());
+                    fs::rename(temp_path(&path), &path)?;
+                }
+            }
+        }
+        if self.manifest.source_index {
+            let path = self.dir.join(SOURCE_INDEX_FILE);
+            fs::rename(temp_path(&path), &path)?;
+        }
+        manifest.shards = shards.into_values().filter(|s| s.records > 0).collect();
+        manifest.write(&self.dir)?;
+        wal.clear()?;
+        for &pixel in &rewritten {
+            let path = self.dir.join(ShardInfo::new(pixel).file_name(format));
+            if !manifest.shards.iter().any(|s| s.pixel == pixel) {
+                fs::remove_file(temp_path(&path))?;
+                let bloom = self.dir.join(ShardInfo::new(pixel).bloom_file_name());
+                for path in [path, bloom.clone(), temp_path(&bloom)] {
+                    if path.exists() {
+                        fs::remove_file(&path)?;
+                    }
+                }
+            }
+        }
+        tracing::info!(
+            changes = pending.len(),
+            shards = rewritten.len(),
+            records = manifest.records(),
+            elapsed_ms = start.elapsed().as_secs_f64() * 1000.0,
+            "store compacted"
+        );
+        self.manifest = manifest;
+        self.pending = Arc::new(Pending::default());
+        self.wal = Some(wal);
+        self.release_change_log()?;
+        Ok(self.manifest.clone())
+    }
+
+    /// Stop requiring readers to apply the log, once it is empty.
+    fn release_change_log(&mut self) -> Result<(), Error> {
+        if self.manifest.requires(CHANGE_LOG) {
+            self.manifest.requires.retain(|c| c != CHANGE_LOG);
+            self.manifest.write(&self.dir)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use gaia::record::test::sample_record;
+    use gaia::record::GaiaRecord;
+    use std::env;
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use store::wal::{self, Change, WriteAheadLog, WAL_FILE};
+
+    fn record(source_id: u64) -> GaiaRecord {
+        let mut record = sample_record();
+        record.source_id = source_id;
+        record
+    }
+
+    #[test]
+    fn append_and_recover() {
+        let dir = env::temp_dir().join(format!("starquad-wal-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (mut log, pending) = WriteAheadLog::open(&dir).unwrap();
+        assert!(pending.is_empty());
+        log.append(&[
+            Change::Push(Box::new(record(1))),
+            Change::Push(Box::new(record(2))),
+        ])
+        .unwrap();
+        log.append(&[Change::Remove(1), Change::Push(Box::new(record(3)))])
+            .unwrap();
+        drop(log);
+
+        let pending = wal::read(&dir).unwrap();
+        assert_eq!(pending.len(), 3);
+        assert_eq!(pending.get(1), Some(None));
+        assert_eq!(pending.get(2), Some(Some(&record(2))));
+        assert_eq!(pending.get(4), None);
+        let ids = pending.records().map(|r| r.source_id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![2, 3]);
+
+        // a crash part of the way through an entry
+        let path = dir.join(WAL_FILE);
+        let whole = fs::metadata(&path).unwrap().len();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[200, 0, 0, 0, 1, 2, 3, 4, 5]).unwrap();
+        drop(file);
+        assert_eq!(wal::read(&dir).unwrap(), pending);
+        let (mut log, recovered) = WriteAheadLog::open(&dir).unwrap();
+        assert_eq!(recovered, pending);
+        assert_eq!(fs::metadata(&path).unwrap().len(), whole);
+        log.append(&[Change::Remove(2)]).unwrap();
+        assert_eq!(wal::read(&dir).unwrap().records().count(), 1);
+
+        log.clear().unwrap();
+        assert!(wal::read(&dir).unwrap().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,186 @@
+//! End-to-end tests of the ingestion path, from a gzipped Gaia CSV file to
+//! exported query results, against expected outputs in `tests/data`.
+//!
+//! `tests/data/GaiaSource_sample.csv.gz` holds 300 synthetic sources in the
+//! Gaia DR2 format: 200 around the Pleiades (60 of them members of the
+//! cluster) and 100 in a field across `ra = 0`. To update the expected
+//! outputs after an intended change, run the tests with `STARQUAD_BLESS=1`
+//! and review the differences.
+
+extern crate starquad;
+
+use starquad::gaia::filter::Filter;
+use starquad::gaia::index::IndexBuilder;
+use starquad::gaia::projection::Projection;
+use starquad::gaia::reader;
+use starquad::gaia::record::GaiaRecord;
+use starquad::output::Format;
+use starquad::sky::index::query_region;
+use starquad::sky::position::SkyPosition;
+use starquad::sky::region::Region;
+use starquad::store::builder::StoreBuilder;
+use starquad::store::Store;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+fn data_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data")
+        .join(name)
+}
+
+fn sample() -> Vec<GaiaRecord> {
+    reader::open_gz(data_path("GaiaSource_sample.csv.gz"))
+        .expect("open sample")
+        .collect::<Result<_, _>>()
+        .expect("read sample")
+}
+
+fn pleiades() -> Region {
+    Region::cone(SkyPosition::new(56.75, 24.12), 1.0)
+}
+
+/// Records sorted by `source_id`, so outputs do not depend on the order in
+/// which indexes return them.
+fn sorted<'a, I: IntoIterator<Item = &'a GaiaRecord>>(records: I) -> Vec<&'a GaiaRecord> {
+    let mut records = records.into_iter().collect::<Vec<_>>();
+    records.sort_by_key(|r| r.source_id);
+    records
+}
+
+/// Write the selected columns of records in a format.
+fn export(records: &[&GaiaRecord], format: Format, columns: &[&str]) -> String {
+    let projection = Projection::new(columns).expect("projection");
+    let mut output = Vec::new();
+    {
+        let mut sink = format.projected_sink(&mut output, projection);
+        for record in records {
+            sink.write(record).expect("write record");
+        }
+        sink.finish().expect("finish output");
+    }
+    String::from_utf8(output).expect("UTF-8 output")
+}
+
+/// Compare an output with its expected contents, or replace them when
+/// `STARQUAD_BLESS` is set.
+fn check_expected(name: &str, actual: &str) {
+    let path = data_path("expected").join(name);
+    if env::var_os("STARQUAD_BLESS").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected =
+        fs::read_to_string(&path).unwrap_or_else(|e| panic!("reading {}: {}", path.display(), e));
+    assert!(
+        actual == expected,
+        "{} differs from the output:\n{}",
+        path.display(),
+        actual
+    );
+}
+
+#[test]
+fn read_sample() {
+    let records = sample();
+    assert_eq!(records.len(), 300);
+    assert!(records.windows(2).all(|w| w[0].source_id < w[1].source_id));
+    assert!(records
+        .iter()
+        .all(|r| r.designation == format!("Gaia DR2 {}", r.source_id)));
+    let two_parameter = records.iter().filter(|r| r.parallax.is_none()).count();
+    assert_eq!(
+        two_parameter,
+        records
+            .iter()
+            .filter(|r| r.astrometric_params_solved == 3)
+            .count()
+    );
+    assert!(two_parameter > 0);
+}
+
+#[test]
+fn filter_to_csv() {
+    let filter: Filter = "parallax > 6 and pmdec < -40 and phot_g_mean_mag < 12"
+        .parse()
+        .unwrap();
+    let records = sample();
+    let members = sorted(records.iter().filter(|r| filter.matches(r)));
+    let columns = [
+        "source_id",
+        "ra",
+        "dec",
+        "parallax",
+        "pmra",
+        "pmdec",
+        "phot_g_mean_mag",
+    ];
+    check_expected(
+        "pleiades_bright.csv",
+        &export(&members, Format::Csv, &columns),
+    );
+}
+
+#[test]
+fn index_query_to_jsonl() {
+    let index = IndexBuilder::new().build(sample());
+    let found = sorted(
+        query_region(&index, &pleiades())
+            .into_iter()
+            .map(|(_, r)| r),
+    );
+    let columns = [
+        "source_id",
+        "ra",
+        "dec",
+        "parallax",
+        "bp_rp",
+        "radial_velocity",
+    ];
+    check_expected(
+        "pleiades_cone.jsonl",
+        &export(&found, Format::Jsonl, &columns),
+    );
+}
+
+#[test]
+fn store_query_to_csv() {
+    let dir = env::temp_dir().join(format!("starquad-ingest-test-{}", std::process::id()));
+    let records = sample();
+    let mut builder = StoreBuilder::new(&dir).unwrap().with_order(3);
+    for record in &records {
+        builder.push(record).unwrap();
+    }
+    builder.finish().unwrap();
+    let store = Store::open(&dir).unwrap();
+
+    // a cone across `ra = 0`, read from the shards on both sides
+    let region = Region::cone(SkyPosition::new(0.0, 0.0), 0.5);
+    let found = store.query(&region).collect::<Result<Vec<_>, _>>().unwrap();
+    let columns = [
+        "source_id",
+        "ra",
+        "dec",
+        "phot_g_mean_mag",
+        "duplicated_source",
+    ];
+    check_expected(
+        "ra_zero_cone.csv",
+        &export(&sorted(&found), Format::Csv, &columns),
+    );
+
+    // the store finds the same records as an index in memory
+    let index = IndexBuilder::new().build(records);
+    let stored = store
+        .query(&pleiades())
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let indexed = query_region(&index, &pleiades())
+        .into_iter()
+        .map(|(_, r)| r);
+    assert_eq!(sorted(&stored), sorted(indexed));
+    fs::remove_dir_all(&dir).unwrap();
+}
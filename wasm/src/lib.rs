@@ -0,0 +1,140 @@
+//! JavaScript bindings for browser-based sky viewers, built for
+//! `wasm32-unknown-unknown` with `wasm-pack build wasm`.
+//!
+//! A `PositionIndex` is built from arrays of `ra` and `dec` (or from the
+//! bytes of such an array, as fetched from a server), and its queries return
+//! the places in those arrays of the sources found. Nothing here reads files,
+//! which a browser cannot.
+
+use starquad::accel2d::quadtree::QuadTree;
+use starquad::accel2d::Accel2D;
+use starquad::geom::p2::P2;
+use starquad::sky::index::{query_region, sky_bounds, SkyIndex};
+use starquad::sky::position::SkyPosition;
+use starquad::sky::region::Region;
+use wasm_bindgen::prelude::*;
+
+/// Size of a position in `PositionIndex::from_bytes`: two `f64`s.
+const POSITION_BYTES: usize = 16;
+
+/// In-memory index of the positions of sources, which are identified by
+/// their place in the arrays the index was built from.
+#[wasm_bindgen]
+pub struct PositionIndex {
+    index: SkyIndex<u32>,
+    len: usize,
+}
+
+impl PositionIndex {
+    fn build(positions: impl Iterator<Item = (f64, f64)>) -> Self {
+        let items = positions
+            .zip(0..)
+            .map(|((ra, dec), i)| (P2::new(ra, dec), i))
+            .collect::<Vec<_>>();
+        let len = items.len();
+        let mut index = QuadTree::with_bounds(sky_bounds());
+        index.insert(items);
+        PositionIndex { index, len }
+    }
+
+    /// Places of the sources in a region, in ascending order.
+    fn query(&self, region: &Region) -> Vec<u32> {
+        let mut found = query_region(&self.index, region)
+            .iter()
+            .map(|(_, i)| *i)
+            .collect::<Vec<_>>();
+        found.sort_unstable();
+        found
+    }
+}
+
+#[wasm_bindgen]
+impl PositionIndex {
+    /// Index sources from arrays of their `ra` and `dec`, in degrees.
+    #[wasm_bindgen(constructor)]
+    pub fn new(ra: &[f64], dec: &[f64]) -> Result<PositionIndex, JsError> {
+        if ra.len() != dec.len() {
+            return Err(JsError::new("ra and dec have different lengths"));
+        }
+        Ok(PositionIndex::build(
+            ra.iter().copied().zip(dec.iter().copied()),
+        ))
+    }
+
+    /// Index sources from the bytes of an array of little-endian `f64`s,
+    /// holding the `ra` and then the `dec` of each source.
+    #[wasm_bindgen(js_name = fromBytes)]
+    pub fn from_bytes(bytes: &[u8]) -> Result<PositionIndex, JsError> {
+        if !bytes.len().is_multiple_of(POSITION_BYTES) {
+            return Err(JsError::new("length is not a multiple of 16 bytes"));
+        }
+        let value = |b: &[u8]| f64::from_le_bytes(b.try_into().unwrap_or_default());
+        Ok(PositionIndex::build(
+            bytes
+                .chunks_exact(POSITION_BYTES)
+                .map(|p| (value(&p[..8]), value(&p[8..]))),
+        ))
+    }
+
+    /// Number of sources in the index.
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.len
+    }
+
+    /// Sources with `ra` in `[ra_min, ra_max]` and `dec` in
+    /// `[dec_min, dec_max]`, in degrees, given in either order. Ranges of
+    /// `ra` are taken modulo 360 and `dec` is clamped to the poles.
+    #[wasm_bindgen(js_name = queryBox)]
+    pub fn query_box(&self, ra_min: f64, ra_max: f64, dec_min: f64, dec_max: f64) -> Vec<u32> {
+        let (ra_min, ra_max) = if ra_min > ra_max {
+            (ra_max, ra_min)
+        } else {
+            (ra_min, ra_max)
+        };
+        let (dec_min, dec_max) = if dec_min > dec_max {
+            (dec_max, dec_min)
+        } else {
+            (dec_min, dec_max)
+        };
+        match Region::sky_box(ra_min, ra_max, dec_min, dec_max) {
+            Ok(region) => self.query(&region),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Sources within `radius` degrees of `(ra, dec)`.
+    #[wasm_bindgen(js_name = queryCone)]
+    pub fn query_cone(&self, ra: f64, dec: f64, radius: f64) -> Vec<u32> {
+        self.query(&Region::cone(SkyPosition::new(ra, dec), radius))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PositionIndex;
+
+    #[test]
+    fn queries() {
+        let ra = [10.0, 10.5, 200.0, 359.9];
+        let dec = [0.0, 0.2, -45.0, 0.0];
+        let index = PositionIndex::new(&ra, &dec).unwrap();
+        assert_eq!(index.length(), 4);
+        assert_eq!(index.query_box(9.0, 11.0, -1.0, 1.0), vec![0, 1]);
+        assert_eq!(index.query_box(11.0, 9.0, 1.0, -1.0), vec![0, 1]);
+        // boxes across ra = 0 wrap
+        assert_eq!(index.query_box(-1.0, 1.0, -1.0, 1.0), vec![3]);
+        assert_eq!(index.query_cone(10.0, 0.0, 0.3), vec![0]);
+        // cones around ra = 0 wrap
+        assert_eq!(index.query_cone(0.0, 0.0, 0.5), vec![3]);
+
+        let bytes = ra
+            .iter()
+            .zip(&dec)
+            .flat_map(|(ra, dec)| [ra.to_le_bytes(), dec.to_le_bytes()])
+            .flatten()
+            .collect::<Vec<u8>>();
+        let from_bytes = PositionIndex::from_bytes(&bytes).unwrap();
+        assert_eq!(from_bytes.query_cone(200.0, -45.0, 1.0), vec![2]);
+    }
+}